@@ -5,10 +5,10 @@ use jomini::{
 };
 use std::collections::HashMap;
 
-const METADATA_BIN: &'static [u8] = include_bytes!("../tests/fixtures/meta.bin");
-const METADATA_TXT: &'static [u8] = include_bytes!("../tests/fixtures/meta.txt");
-const CK3_BIN: &'static [u8] = include_bytes!("../tests/fixtures/ck3-header.bin");
-const CK3_TXT: &'static [u8] = include_bytes!("../tests/fixtures/ck3-header.txt");
+const METADATA_BIN: &[u8] = include_bytes!("../tests/fixtures/meta.bin");
+const METADATA_TXT: &[u8] = include_bytes!("../tests/fixtures/meta.txt");
+const CK3_BIN: &[u8] = include_bytes!("../tests/fixtures/ck3-header.bin");
+const CK3_TXT: &[u8] = include_bytes!("../tests/fixtures/ck3-header.txt");
 
 pub fn is_ascii_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("is_ascii");
@@ -43,8 +43,8 @@ pub fn windows1252_benchmark(c: &mut Criterion) {
 pub fn utf8_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("utf8");
     for size in [2, 4, 8, 16, 32, 64, 128, 256, 512].iter() {
-        let mut ascii_str = String::with_capacity(*size as usize);
-        let mut utf8_str = String::with_capacity(*size / 2 as usize);
+        let mut ascii_str = String::with_capacity(*size);
+        let mut utf8_str = String::with_capacity(*size / 2);
         for _ in 0..*size {
             ascii_str.push('a');
         }
@@ -58,10 +58,10 @@ pub fn utf8_benchmark(c: &mut Criterion) {
         group.bench_with_input(
             BenchmarkId::new("ascii-to-utf8", size),
             size,
-            |b, &_size| b.iter(|| Utf8Encoding::decode(&data)),
+            |b, &_size| b.iter(|| Utf8Encoding::decode(data)),
         );
         group.bench_with_input(BenchmarkId::new("utf8-to-utf8", size), size, |b, &_size| {
-            b.iter(|| Utf8Encoding::decode(&data2))
+            b.iter(|| Utf8Encoding::decode(data2))
         });
     }
     group.finish();
@@ -99,13 +99,52 @@ pub fn binary_deserialize_benchmark(c: &mut Criterion) {
     group.bench_function("binary", |b| {
         b.iter(|| {
             let _res: Meta = BinaryDeserializer::eu4_builder()
-                .from_slice(&data[..], &map)
+                .from_slice(data, &map)
+                .unwrap();
+        })
+    });
+    group.bench_function("binary-ondemand", |b| {
+        b.iter(|| {
+            let _res: Meta = BinaryDeserializer::eu4_builder()
+                .from_slice_ondemand(data, &map)
                 .unwrap();
         })
     });
     group.finish();
 }
 
+pub fn export_benchmark(c: &mut Criterion) {
+    #[cfg(feature = "export")]
+    {
+        use jomini::export::{self, ExportFormat};
+
+        let data = &METADATA_TXT["EU4txt".len()..];
+        let tape = TextTape::from_slice(data).unwrap();
+        let mut group = c.benchmark_group("export");
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_function("msgpack", |b| {
+            b.iter(|| export::text(&tape, Windows1252Encoding::new(), ExportFormat::MessagePack))
+        });
+        group.bench_function("cbor", |b| {
+            b.iter(|| export::text(&tape, Windows1252Encoding::new(), ExportFormat::Cbor))
+        });
+        group.bench_function("json-transcode", |b| {
+            b.iter(|| {
+                let builder = TextDeserializer::builder();
+                let mut deserializer = builder.deserializer(tape.windows1252_reader());
+                let mut out = Vec::with_capacity(data.len());
+                let mut serializer = serde_json::Serializer::new(&mut out);
+                serde_transcode::transcode(&mut deserializer, &mut serializer).unwrap();
+                out
+            })
+        });
+        group.finish();
+    }
+
+    #[cfg(not(feature = "export"))]
+    let _ = c;
+}
+
 pub fn text_deserialize_benchmark(c: &mut Criterion) {
     #[derive(serde::Deserialize, PartialEq, Eq, Debug)]
     struct Meta {
@@ -117,7 +156,34 @@ pub fn text_deserialize_benchmark(c: &mut Criterion) {
     group.throughput(Throughput::Bytes(data.len() as u64));
     group.bench_function("text", |b| {
         b.iter(|| {
-            let _res: Meta = TextDeserializer::from_windows1252_slice(&data[..]).unwrap();
+            let _res: Meta = TextDeserializer::from_windows1252_slice(data).unwrap();
+        })
+    });
+    group.finish();
+}
+
+pub fn history_deserialize_benchmark(c: &mut Criterion) {
+    #[derive(serde::Deserialize, PartialEq, Eq, Debug)]
+    struct Event {
+        owner: Option<String>,
+        controller: Option<String>,
+    }
+
+    let mut data = Vec::new();
+    for year in 1444..1821 {
+        let entry = format!(
+            "{0}.1.1={{ owner=ENG }} {0}.6.1={{ controller=FRA }} ",
+            year
+        );
+        data.extend_from_slice(entry.as_bytes());
+    }
+
+    let mut group = c.benchmark_group("deserialize");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("ordered-history", |b| {
+        b.iter(|| {
+            let res: Vec<(Date, Event)> = TextDeserializer::from_windows1252_slice(&data).unwrap();
+            black_box(res)
         })
     });
     group.finish();
@@ -131,18 +197,28 @@ pub fn binary_parse_benchmark(c: &mut Criterion) {
         let mut tape = BinaryTape::default();
         b.iter(move || {
             BinaryTape::eu4_parser()
-                .parse_slice_into_tape(&data[..], &mut tape)
+                .parse_slice_into_tape(data, &mut tape)
                 .unwrap();
         })
     });
 
-    let data = &CK3_BIN[..];
+    group.bench_function(BenchmarkId::new("binary-visit", "eu4"), |b| {
+        b.iter(move || {
+            let mut count = 0;
+            BinaryTape::eu4_parser()
+                .parse_with(data, |_| count += 1)
+                .unwrap();
+            count
+        })
+    });
+
+    let data = CK3_BIN;
     group.throughput(Throughput::Bytes(data.len() as u64));
     group.bench_function(BenchmarkId::new("binary", "ck3"), |b| {
         let mut tape = BinaryTape::default();
         b.iter(move || {
             BinaryTape::eu4_parser()
-                .parse_slice_into_tape(&data[..], &mut tape)
+                .parse_slice_into_tape(data, &mut tape)
                 .unwrap();
         })
     });
@@ -158,18 +234,36 @@ pub fn text_parse_benchmark(c: &mut Criterion) {
         let mut tape = TextTape::default();
         b.iter(|| {
             TextTape::parser()
-                .parse_slice_into_tape(&data[..], &mut tape)
+                .parse_slice_into_tape(data, &mut tape)
+                .unwrap();
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("text-exact-capacity", "eu4"), |b| {
+        let mut tape = TextTape::default();
+        b.iter(|| {
+            TextTape::parser()
+                .exact_capacity(true)
+                .parse_slice_into_tape(data, &mut tape)
                 .unwrap();
         })
     });
 
-    let data = &CK3_TXT[..];
+    group.bench_function(BenchmarkId::new("text-segmented", "eu4"), |b| {
+        b.iter(|| {
+            TextTape::parser()
+                .parse_slice_segmented(data, 1 << 20)
+                .unwrap()
+        })
+    });
+
+    let data = CK3_TXT;
     group.throughput(Throughput::Bytes(data.len() as u64));
     group.bench_function(BenchmarkId::new("text", "ck3"), |b| {
         let mut tape = TextTape::default();
         b.iter(|| {
             TextTape::parser()
-                .parse_slice_into_tape(&data[..], &mut tape)
+                .parse_slice_into_tape(data, &mut tape)
                 .unwrap();
         })
     });
@@ -200,6 +294,8 @@ criterion_group!(
     text_parse_benchmark,
     binary_deserialize_benchmark,
     text_deserialize_benchmark,
+    export_benchmark,
+    history_deserialize_benchmark,
     to_u64_benchmark,
     to_f64_benchmark,
     date_benchmark,
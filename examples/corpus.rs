@@ -0,0 +1,19 @@
+use jomini::bench::{self, Corpus};
+use std::error;
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let dir = std::env::args().nth(1).ok_or("expected corpus directory")?;
+    let corpus = Corpus::from_dir(dir)?;
+    let iterations = 5;
+
+    let text = bench::text_parse(&corpus, iterations)?;
+    println!("text tape: {:.2} MB/s", text.megabytes_per_second());
+
+    let eu4 = bench::binary_parse(&corpus, jomini::Eu4Flavor::new(), iterations)?;
+    println!("binary tape (eu4): {:.2} MB/s", eu4.megabytes_per_second());
+
+    let ck3 = bench::binary_parse(&corpus, jomini::Ck3Flavor::new(), iterations)?;
+    println!("binary tape (ck3): {:.2} MB/s", ck3.megabytes_per_second());
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use std::error;
+use std::io::{self, Read};
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    let tape = jomini::TextTape::from_slice(&data)?;
+    let builder = jomini::TextDeserializer::builder();
+    let mut deserializer = builder.deserializer(tape.windows1252_reader());
+
+    let stdout = io::stdout();
+    let mut serializer = serde_json::Serializer::new(stdout.lock());
+    serde_transcode::transcode(&mut deserializer, &mut serializer)?;
+    println!();
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use std::env;
+use std::error;
+use std::io::{self, Read, Write};
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    let selector = jomini::Selector::parse(args.get(1).map_or(".", String::as_str))?;
+
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    let tape = jomini::TextTape::from_slice(&data)?;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for value in selector.execute_text(&tape, jomini::Windows1252Encoding::new()) {
+        serde_json::to_writer(&mut out, &value)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
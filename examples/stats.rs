@@ -0,0 +1,20 @@
+use std::error;
+use std::io::{self, Read};
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    let tape = jomini::TextTape::from_slice(&data)?;
+    let stats = jomini::TapeStats::from(&tape);
+    println!("objects: {}", stats.objects());
+    println!("hidden objects: {}", stats.hidden_objects());
+    println!("arrays: {}", stats.arrays());
+    println!("scalars: {}", stats.scalars());
+    println!("max depth: {}", stats.max_depth());
+    println!("most duplicated keys:");
+    for (key, count) in stats.duplicate_keys().iter().take(10) {
+        println!("  {}: {}", String::from_utf8_lossy(key), count);
+    }
+
+    Ok(())
+}
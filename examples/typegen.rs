@@ -0,0 +1,15 @@
+use std::error;
+use std::fs;
+
+/// Prints Rust types that model the text documents given as arguments
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let mut schema = jomini::Schema::new();
+    for path in std::env::args().skip(1) {
+        let data = fs::read(&path)?;
+        let tape = jomini::TextTape::from_slice(&data)?;
+        schema.add_text(&tape);
+    }
+
+    schema.write_rust("Root", std::io::stdout())?;
+    Ok(())
+}
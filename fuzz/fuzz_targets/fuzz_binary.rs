@@ -44,7 +44,7 @@ fuzz_target!(|data: &[u8]| {
 
     let _: Result<Meta, _> = jomini::BinaryTape::from_eu4(&data).and_then(|tape| {
         let tokens = tape.tokens();
-        for (i, token) in tokens.iter().enumerate() {
+        for token in tokens.iter() {
             match token {
                 jomini::BinaryToken::Array(ind) |
                 jomini::BinaryToken::Object(ind) |
@@ -52,17 +52,11 @@ fuzz_target!(|data: &[u8]| {
                 jomini::BinaryToken::End(ind) if *ind == 0 => {
                     panic!("zero ind encountered");
                 }
-                jomini::BinaryToken::Array(ind) | jomini::BinaryToken::Object(ind) | jomini::BinaryToken::HiddenObject(ind) => {
-                    match tokens[*ind] {
-                        jomini::BinaryToken::End(ind2) => {
-                            assert_eq!(ind2, i)
-                        }
-                        _ => panic!("expected end")
-                    }
-                }
                 _ => {}
             }
         }
+
+        tape.validate().unwrap();
         jomini::BinaryDeserializer::eu4_builder().from_tape(&tape, &hash)
     });
 });
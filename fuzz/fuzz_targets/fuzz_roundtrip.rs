@@ -0,0 +1,13 @@
+#![no_main]
+use jomini::document::Document;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|doc: Document| {
+    let text = doc.to_text();
+    let tape = jomini::TextTape::from_slice(&text).unwrap();
+    assert_eq!(Document::from_text_tape(&tape).unwrap(), doc);
+
+    let binary = doc.to_binary();
+    let tape = jomini::BinaryTape::from_eu4(&binary).unwrap();
+    assert_eq!(Document::from_binary_tape(&tape).unwrap(), doc);
+});
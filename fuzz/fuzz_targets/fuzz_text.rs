@@ -62,7 +62,7 @@ fuzz_target!(|data: &[u8]| {
     let _: Result<Meta, _> = jomini::TextTape::from_slice(&data)
         .and_then(|tape| {
             let tokens = tape.tokens();
-            for (i, token) in tokens.iter().enumerate() {
+            for token in tokens.iter() {
                 match token {
                     TextToken::Array(ind) |
                     TextToken::Object(ind) |
@@ -70,18 +70,12 @@ fuzz_target!(|data: &[u8]| {
                     TextToken::End(ind) if *ind == 0 => {
                         panic!("zero ind encountered");
                     }
-                    TextToken::Array(ind) | TextToken::Object(ind) | TextToken::HiddenObject(ind) => {
-                        match tokens[*ind] {
-                            TextToken::End(ind2) => {
-                                assert_eq!(ind2, i)
-                            }
-                            _ => panic!("expected end")
-                        }
-                    }
                     _ => {}
                 }
             }
 
+            tape.validate().unwrap();
+
             iterate_object(tape.windows1252_reader());
             jomini::TextDeserializer::from_windows1252_tape(&tape)
         });
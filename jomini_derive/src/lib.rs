@@ -1,6 +1,10 @@
 use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, DeriveInput, Field, Ident, Lit, Meta, NestedMeta, Type};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, DeriveInput, Field, GenericParam, Ident, Lifetime,
+    LifetimeDef, Lit, Meta, NestedMeta, Type, WherePredicate,
+};
 
 fn is_duplicated(f: &Field) -> bool {
     f.attrs
@@ -20,6 +24,23 @@ fn is_duplicated(f: &Field) -> bool {
         .any(|_| true)
 }
 
+fn is_rest(f: &Field) -> bool {
+    f.attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("jomini"))
+        .map(|attr| attr.parse_meta().unwrap())
+        .filter_map(|meta| match meta {
+            Meta::List(x) => Some(x),
+            _ => None,
+        })
+        .flat_map(|x| x.nested)
+        .filter_map(|x| match x {
+            NestedMeta::Meta(m) => Some(m.path().clone()),
+            _ => None,
+        })
+        .any(|p| p.is_ident("rest"))
+}
+
 enum DefaultFallback {
     Path(Ident),
     Yes,
@@ -94,8 +115,8 @@ fn can_deserialize_with(f: &Field) -> Option<Ident> {
     })
 }
 
-fn alias(f: &Field) -> Option<String> {
-    f.attrs
+fn alias(attrs: &[Attribute]) -> Option<String> {
+    attrs
         .iter()
         .filter(|attr| attr.path.is_ident("jomini"))
         .map(|attr| attr.parse_meta().unwrap())
@@ -120,6 +141,247 @@ fn alias(f: &Field) -> Option<String> {
         .next()
 }
 
+fn alias_path(f: &Field) -> Option<String> {
+    f.attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("jomini"))
+        .map(|attr| attr.parse_meta().unwrap())
+        .filter_map(|meta| match meta {
+            Meta::List(x) => Some(x),
+            _ => None,
+        })
+        .flat_map(|x| x.nested)
+        .filter_map(|x| match x {
+            NestedMeta::Meta(m) => Some(m),
+            _ => None,
+        })
+        .find(|m| m.path().is_ident("alias_path"))
+        .map(|meta| match meta {
+            Meta::NameValue(mnv) => match mnv.lit {
+                Lit::Str(lit) => lit.value(),
+                _ => panic!("expected alias_path to be a string"),
+            },
+            _ => panic!("expected name value for alias_path"),
+        })
+}
+
+/// Fields that aren't collected by the rest field or lifted from a nested object
+fn is_regular(f: &Field) -> bool {
+    !is_rest(f) && alias_path(f).is_none()
+}
+
+/// A key along the alias paths of the struct's fields. Fields that share the start of their
+/// paths share nodes so that each nested object is only deserialized once.
+struct PathNode<'a> {
+    key: String,
+    field: Option<&'a Field>,
+    children: Vec<PathNode<'a>>,
+}
+
+impl<'a> PathNode<'a> {
+    fn insert(nodes: &mut Vec<PathNode<'a>>, keys: &[&str], field: &'a Field) {
+        let idx = match nodes.iter().position(|x| x.key == keys[0]) {
+            Some(idx) => idx,
+            None => {
+                nodes.push(PathNode {
+                    key: String::from(keys[0]),
+                    field: None,
+                    children: Vec::new(),
+                });
+                nodes.len() - 1
+            }
+        };
+
+        let node = &mut nodes[idx];
+        if keys.len() == 1 {
+            node.field = Some(field);
+        } else {
+            Self::insert(&mut node.children, &keys[1..], field);
+        }
+
+        if node.field.is_some() && !node.children.is_empty() {
+            panic!("alias_path {} is both a value and an object", keys[0]);
+        }
+    }
+
+    /// Returns the type that the node deserializes into, generating a struct for the nested
+    /// object if the node isn't a field
+    fn ty(&self, items: &mut Vec<TokenStream2>, generics: &DeGenerics) -> TokenStream2 {
+        if let Some(field) = self.field {
+            let ty = &field.ty;
+            return quote! { #ty };
+        }
+
+        let ident = format_ident!("__PathNode{}", items.len());
+        items.push(TokenStream2::new());
+        let idx = items.len() - 1;
+
+        let children: Vec<_> = (0..self.children.len())
+            .map(|i| format_ident!("__child{}", i))
+            .collect();
+        let types: Vec<_> = self
+            .children
+            .iter()
+            .map(|x| x.ty(items, generics))
+            .collect();
+        let keys = self.children.iter().map(|x| &x.key);
+        let expecting = format!("object with {}", self.key);
+        let DeGenerics {
+            impl_generics,
+            helper_ty,
+            where_clause,
+            marker,
+            ..
+        } = generics;
+
+        items[idx] = quote! {
+            #[allow(non_camel_case_types)]
+            struct #ident #impl_generics #where_clause {
+                #(#children: ::std::option::Option<#types>,)*
+                __marker: #marker,
+            }
+
+            impl #impl_generics ::serde::Deserialize<'de> for #ident #helper_ty #where_clause {
+                fn deserialize<__D>(__deserializer: __D) -> ::std::result::Result<Self, __D::Error>
+                where __D: ::serde::Deserializer<'de> {
+                    struct __Visitor #impl_generics #where_clause {
+                        __marker: #marker,
+                    }
+
+                    impl #impl_generics ::serde::de::Visitor<'de> for __Visitor #helper_ty #where_clause {
+                        type Value = #ident #helper_ty;
+
+                        fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                            write!(formatter, #expecting)
+                        }
+
+                        fn visit_map<__A>(
+                            self,
+                            mut __map: __A,
+                        ) -> ::std::result::Result<Self::Value, __A::Error>
+                        where
+                            __A: ::serde::de::MapAccess<'de>,
+                        {
+                            #(let mut #children = None;)*
+                            while let Some(__key) = ::serde::de::MapAccess::next_key::<::std::string::String>(&mut __map)? {
+                                match __key.as_str() {
+                                    #(#keys if #children.is_none() => {
+                                        #children = Some(::serde::de::MapAccess::next_value::<#types>(&mut __map)?);
+                                    })*
+                                    _ => { ::serde::de::MapAccess::next_value::<::serde::de::IgnoredAny>(&mut __map)?; }
+                                }
+                            }
+                            Ok(#ident {
+                                #(#children,)*
+                                __marker: ::std::marker::PhantomData,
+                            })
+                        }
+                    }
+
+                    let __visitor = __Visitor {
+                        __marker: ::std::marker::PhantomData,
+                    };
+                    ::serde::de::Deserializer::deserialize_map(__deserializer, __visitor)
+                }
+            }
+        };
+
+        quote! { #ident #helper_ty }
+    }
+}
+
+fn encoding(attrs: &[Attribute]) -> Option<Ident> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("jomini"))
+        .map(|attr| attr.parse_meta().unwrap())
+        .filter_map(|meta| match meta {
+            Meta::List(x) => Some(x),
+            _ => None,
+        })
+        .flat_map(|x| x.nested)
+        .filter_map(|x| match x {
+            NestedMeta::Meta(m) => Some(m),
+            _ => None,
+        })
+        .find(|m| m.path().is_ident("encoding"))
+        .map(|meta| match meta {
+            Meta::NameValue(mnv) => match mnv.lit {
+                Lit::Str(lit) if lit.value() == "utf8" => format_ident!("Utf8Encoding"),
+                Lit::Str(lit) if lit.value() == "windows1252" => {
+                    format_ident!("Windows1252Encoding")
+                }
+                _ => panic!("expected encoding to be \"utf8\" or \"windows1252\""),
+            },
+            _ => panic!("expected name value for encoding"),
+        })
+}
+
+fn tag(attrs: &[Attribute]) -> Option<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("jomini"))
+        .map(|attr| attr.parse_meta().unwrap())
+        .filter_map(|meta| match meta {
+            Meta::List(x) => Some(x),
+            _ => None,
+        })
+        .flat_map(|x| x.nested)
+        .filter_map(|x| match x {
+            NestedMeta::Meta(m) => Some(m),
+            _ => None,
+        })
+        .find(|m| m.path().is_ident("tag"))
+        .map(|meta| match meta {
+            Meta::NameValue(mnv) => match mnv.lit {
+                Lit::Str(lit) => lit.value(),
+                _ => panic!("expected tag to be a string"),
+            },
+            _ => panic!("expected name value for tag"),
+        })
+}
+
+fn is_positional(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("jomini"))
+        .map(|attr| attr.parse_meta().unwrap())
+        .filter_map(|meta| match meta {
+            Meta::List(x) => Some(x),
+            _ => None,
+        })
+        .flat_map(|x| x.nested)
+        .filter_map(|x| match x {
+            NestedMeta::Meta(m) => Some(m.path().clone()),
+            _ => None,
+        })
+        .any(|p| p.is_ident("positional"))
+}
+
+fn take(f: &Field) -> Option<usize> {
+    f.attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("jomini"))
+        .map(|attr| attr.parse_meta().unwrap())
+        .filter_map(|meta| match meta {
+            Meta::List(x) => Some(x),
+            _ => None,
+        })
+        .flat_map(|x| x.nested)
+        .filter_map(|x| match x {
+            NestedMeta::Meta(m) => Some(m),
+            _ => None,
+        })
+        .find(|m| m.path().is_ident("take"))
+        .map(|meta| match meta {
+            Meta::NameValue(mnv) => match mnv.lit {
+                Lit::Int(lit) => lit.base10_parse().unwrap(),
+                _ => panic!("expected take to be an integer"),
+            },
+            _ => panic!("expected name value for take"),
+        })
+}
+
 fn ungroup(mut ty: &Type) -> &Type {
     while let Type::Group(group) = ty {
         ty = &group.elem;
@@ -127,6 +389,54 @@ fn ungroup(mut ty: &Type) -> &Type {
     ty
 }
 
+/// The generics of the derived struct extended with the `'de` lifetime of the deserializer
+struct DeGenerics {
+    /// The struct's parameters and `'de`, which outlives the struct's lifetimes: `<'de: 'a, 'a, T>`
+    impl_generics: TokenStream2,
+
+    /// The parameters of the generated helper types: `<'de, 'a, T>`
+    helper_ty: TokenStream2,
+
+    /// The struct's parameters: `<'a, T>`
+    ty: TokenStream2,
+
+    /// The struct's where clause with each type parameter bound by `Deserialize<'de>`
+    where_clause: TokenStream2,
+
+    /// The type of a field that makes a helper type use every parameter
+    marker: TokenStream2,
+}
+
+impl DeGenerics {
+    fn new(ident: &Ident, generics: &syn::Generics) -> Self {
+        let mut de = generics.clone();
+        let mut de_lifetime = LifetimeDef::new(Lifetime::new("'de", Span::call_site()));
+        de_lifetime
+            .bounds
+            .extend(generics.lifetimes().map(|x| x.lifetime.clone()));
+        de.params.insert(0, GenericParam::Lifetime(de_lifetime));
+
+        let bounds: Vec<WherePredicate> = generics
+            .type_params()
+            .map(|x| {
+                let ident = &x.ident;
+                parse_quote!(#ident: ::serde::Deserialize<'de>)
+            })
+            .collect();
+        de.make_where_clause().predicates.extend(bounds);
+
+        let (impl_generics, helper_ty, where_clause) = de.split_for_impl();
+        let (_, ty, _) = generics.split_for_impl();
+        DeGenerics {
+            impl_generics: quote! { #impl_generics },
+            helper_ty: quote! { #helper_ty },
+            ty: quote! { #ty },
+            where_clause: quote! { #where_clause },
+            marker: quote! { ::std::marker::PhantomData<(&'de (), #ident #ty)> },
+        }
+    }
+}
+
 /// Creates a serde compatible `Deserialize` implementation
 ///
 /// ```rust
@@ -173,6 +483,38 @@ fn ungroup(mut ty: &Type) -> &Type {
 /// - `#[jomini(default = "...")]`
 /// - `#[jomini(deserialize_with = "...")]`
 ///
+/// A field decorated with `#[jomini(alias_path = "parent/child")]` is lifted out of nested
+/// objects, so that a deeply nested value doesn't require a struct for each object along the
+/// way. Fields can share the start of their paths.
+///
+/// A single map field (eg: `HashMap<String, V>` or `BTreeMap<String, V>`) can be decorated with
+/// `#[jomini(rest)]` to collect every key that doesn't match another field along with its value,
+/// so that a partially modeled file can be deserialized without losing data. The values are
+/// deserialized as `V`, so a self describing type should be used to capture any value.
+///
+/// Tuple structs, and named structs with the `#[jomini(positional)]` container attribute, are
+/// deserialized from an array where each field takes the next value in order, so positional data
+/// like `unit_template = { 5 3 2 }` can be given names. A field decorated with
+/// `#[jomini(take = N)]` takes the next `N` values as a sequence of its own (eg: into a
+/// `Vec<T>` or `[T; N]`). Missing trailing values fall back to the field's default if it has one
+/// and extra values are ignored.
+///
+/// Enums decorated with `#[jomini(tag = "type")]` are internally tagged: the `type` field of the
+/// object names the variant and the remaining fields are deserialized as the variant's fields,
+/// with the same field attributes as a struct. Variants match their name, or the
+/// `#[jomini(alias = "...")]` of the variant, and can be unit variants. The text and binary tape
+/// deserializers find the tag anywhere in the object, while other deserializers require the tag
+/// to be the first field.
+///
+/// Structs can be generic over types and lifetimes. Each type parameter is required to implement
+/// `Deserialize`, and fields can borrow from the data (eg: `&'a str`) when the deserializer is
+/// able to lend it out, as is the case for UTF-8 text without escapes.
+///
+/// A struct can also declare the encoding of its text data with
+/// `#[jomini(encoding = "utf8")]` or `#[jomini(encoding = "windows1252")]`, which implements
+/// `jomini::TextEncoded` so that `TextDeserializer::from_slice` picks the encoding without the
+/// caller naming it. This attribute requires the `jomini` crate to be a dependency.
+///
 /// ## The Why
 ///
 /// Serde's `Deserialize` implementation will raise an error if a field occurs more than once in
@@ -211,29 +553,180 @@ fn ungroup(mut ty: &Type) -> &Type {
 pub fn derive(input: TokenStream) -> TokenStream {
     let dinput = parse_macro_input!(input as DeriveInput);
     let struct_ident = dinput.ident;
+    let generics = DeGenerics::new(&struct_ident, &dinput.generics);
+    let (impl_generics, ty_generics, where_clause) = dinput.generics.split_for_impl();
+    let text_encoding = encoding(&dinput.attrs).map(|encoding| {
+        quote! {
+            impl #impl_generics ::jomini::TextEncoded for #struct_ident #ty_generics #where_clause {
+                type Encoding = ::jomini::#encoding;
+            }
+        }
+    });
 
-    let syn_struct = match dinput.data {
-        syn::Data::Struct(x) => x,
+    let output = match dinput.data {
+        syn::Data::Struct(x) => match x.fields {
+            syn::Fields::Named(x) if is_positional(&dinput.attrs) => {
+                derive_positional(&struct_ident, &generics, &x.named, true)
+            }
+            syn::Fields::Unnamed(x) => {
+                derive_positional(&struct_ident, &generics, &x.unnamed, false)
+            }
+            syn::Fields::Named(x) => {
+                derive_struct(&struct_ident, &struct_ident.to_string(), &generics, &x)
+            }
+            _ => panic!("Expected named fields"),
+        },
+        syn::Data::Enum(x) => match tag(&dinput.attrs) {
+            Some(tag) if dinput.generics.params.is_empty() => {
+                derive_tagged(&struct_ident, &tag, &x)
+            }
+            Some(_) => panic!("expected tagged enum to not be generic"),
+            None => panic!("expected enum to have a tag attribute"),
+        },
         _ => panic!("Expected struct"),
     };
 
-    let named_fields = match syn_struct.fields {
-        syn::Fields::Named(x) => x,
-        _ => panic!("Expected named fields"),
+    let output = quote! {
+        #output
+        #text_encoding
+    };
+    output.into()
+}
+
+/// Creates a `Deserialize` implementation for a struct with named fields
+fn derive_struct(
+    struct_ident: &Ident,
+    struct_name: &str,
+    generics: &DeGenerics,
+    named_fields: &syn::FieldsNamed,
+) -> TokenStream2 {
+    let rest_fields: Vec<_> = named_fields.named.iter().filter(|f| is_rest(f)).collect();
+    if rest_fields.len() > 1 {
+        panic!("expected at most one rest field");
+    }
+
+    let rest_field = rest_fields.first().map(|f| {
+        let name = &f.ident;
+        let path = match ungroup(&f.ty) {
+            syn::Type::Path(ty) => &ty.path,
+            _ => panic!("expected rest field to be a map"),
+        };
+        let args = match path.segments.last().map(|x| &x.arguments) {
+            Some(syn::PathArguments::AngleBracketed(bracketed)) if bracketed.args.len() == 2 => {
+                &bracketed.args
+            }
+            _ => panic!("expected rest field to be a map with a key and value type"),
+        };
+
+        let key = &args[0];
+        let value = &args[1];
+        quote! {
+            __Field::__other(__key) => {
+                (#name).insert(
+                    <#key as ::std::convert::From<::std::string::String>>::from(__key),
+                    serde::de::MapAccess::next_value::<#value>(&mut __map)?,
+                );
+            }
+        }
+    });
+
+    let (other_variant, other_match) = if rest_field.is_some() {
+        (
+            quote! { __other(::std::string::String) },
+            quote! { _ => Ok(__Field::__other(::std::string::String::from(__value))) },
+        )
+    } else {
+        (quote! { __ignore }, quote! { _ => Ok(__Field::__ignore) })
     };
 
-    let builder_init = named_fields.named.iter().map(|f| {
+    let unmatched_arm = rest_field.unwrap_or_else(|| {
+        quote! {
+            _ => { ::serde::de::MapAccess::next_value::<::serde::de::IgnoredAny>(&mut __map)?; }
+        }
+    });
+
+    let mut path_roots = Vec::new();
+    for f in named_fields.named.iter() {
+        if let Some(path) = alias_path(f) {
+            let keys: Vec<_> = path.split('/').collect();
+            PathNode::insert(&mut path_roots, &keys, f);
+        }
+    }
+
+    let mut path_items = Vec::new();
+    let path_vars: Vec<_> = (0..path_roots.len())
+        .map(|i| format_ident!("__path{}", i))
+        .collect();
+    let path_types: Vec<_> = path_roots
+        .iter()
+        .map(|x| x.ty(&mut path_items, generics))
+        .collect();
+    let path_keys: Vec<_> = path_roots.iter().map(|x| &x.key).collect();
+
+    let path_extract = named_fields.named.iter().filter_map(|f| {
+        let path = alias_path(f)?;
         let name = &f.ident;
-        let x = &f.ty;
-        if !is_duplicated(f) {
-            let field_name_opt = format_ident!("{}_opt", name.as_ref().unwrap());
-            quote! { let mut #field_name_opt : ::std::option::Option<#x> = None }
-        } else {
-            quote! { let mut #name : #x = Default::default() }
+        let mut keys = path.split('/');
+        let first = keys.next().unwrap();
+        let root = path_roots.iter().position(|x| x.key == first).unwrap();
+        let var = &path_vars[root];
+        let mut node = &path_roots[root];
+        let mut children = Vec::new();
+        for key in keys {
+            let idx = node.children.iter().position(|x| x.key == key).unwrap();
+            children.push(format_ident!("__child{}", idx));
+            node = &node.children[idx];
         }
+
+        // Fields can share nodes, so values are taken out of them
+        let value = match children.split_last() {
+            None => quote! { #var.take() },
+            Some((last, parents)) => quote! {
+                #var.as_mut()
+                    #(.and_then(|__x| __x.#parents.as_mut()))*
+                    .and_then(|__x| __x.#last.take())
+            },
+        };
+        Some(match can_default(f) {
+            DefaultFallback::Yes => quote! {
+                let #name = #value.unwrap_or_default();
+            },
+            DefaultFallback::Path(lit) => quote! {
+                let #name = #value.unwrap_or_else(#lit);
+            },
+            DefaultFallback::No => quote! {
+                let #name = #value
+                    .ok_or_else(|| <__A::Error as ::serde::de::Error>::missing_field(#path))?;
+            },
+        })
     });
 
-    let builder_fields = named_fields.named.iter().map(|f| {
+    let builder_init = named_fields
+        .named
+        .iter()
+        .filter(|f| alias_path(f).is_none())
+        .map(|f| {
+            let name = &f.ident;
+            let x = &f.ty;
+            if is_rest(f) {
+                quote! { let mut #name : #x = Default::default() }
+            } else if !is_duplicated(f) {
+                let field_name_opt = format_ident!("{}_opt", name.as_ref().unwrap());
+                quote! { let mut #field_name_opt : ::std::option::Option<#x> = None }
+            } else {
+                quote! { let mut #name : #x = Default::default() }
+            }
+        });
+
+    let DeGenerics {
+        impl_generics,
+        helper_ty,
+        ty,
+        where_clause,
+        marker,
+    } = generics;
+
+    let builder_fields = named_fields.named.iter().filter(|f| is_regular(f)).map(|f| {
         let name = &f.ident;
         let x = &f.ty;
         let name_str = name
@@ -248,21 +741,25 @@ pub fn derive(input: TokenStream) -> TokenStream {
             let des = if let Some(ident) = can_deserialize_with(f) {
                 let fncall = quote! { #ident(__deserializer) };
                 quote! {{
-                    struct __DeserializeWith {
+                    struct __DeserializeWith #impl_generics #where_clause {
                         value: #x,
+                        __marker: #marker,
                     }
-                    impl<'de> ::serde::Deserialize<'de> for __DeserializeWith {
+                    impl #impl_generics ::serde::Deserialize<'de> for __DeserializeWith #helper_ty #where_clause {
                         fn deserialize<__D>(
                             __deserializer: __D,
                         ) -> ::std::result::Result<Self, __D::Error>
                         where
                             __D: ::serde::Deserializer<'de>,
                         {
-                            Ok(__DeserializeWith { value: #fncall? })
+                            Ok(__DeserializeWith {
+                                value: #fncall?,
+                                __marker: ::std::marker::PhantomData,
+                            })
                         }
                     }
                     ::serde::de::MapAccess::next_value::<
-                        __DeserializeWith,
+                        __DeserializeWith #helper_ty,
                     >(&mut __map).map(|x| x.value)
                 }}
             } else {
@@ -297,7 +794,7 @@ pub fn derive(input: TokenStream) -> TokenStream {
         }
     });
 
-    let field_extract =  named_fields.named.iter().filter(|x| !is_duplicated(x)).map(|f| {
+    let field_extract =  named_fields.named.iter().filter(|x| !is_duplicated(x) && is_regular(x)).map(|f| {
         let name = &f.ident;
         let field_name_opt = format_ident!("{}_opt", name.as_ref().unwrap());
         let name_str = name
@@ -324,30 +821,38 @@ pub fn derive(input: TokenStream) -> TokenStream {
         quote! { #name }
     });
 
-    let field_enums = named_fields.named.iter().map(|f| {
-        let name = &f.ident;
-        quote! { #name }
-    });
+    let field_enums = named_fields
+        .named
+        .iter()
+        .filter(|f| is_regular(f))
+        .map(|f| {
+            let name = &f.ident;
+            quote! { #name }
+        });
 
-    let field_enum_match = named_fields.named.iter().map(|f| {
-        let name = &f.ident;
-        let name_str = name
-            .as_ref()
-            .map(|x| x.to_string())
-            .unwrap_or_else(|| String::from("unknown"));
-        let match_arm = alias(f).unwrap_or_else(|| name_str.to_string());
-        let field_ident = quote! { __Field::#name };
-        quote! {
-            #match_arm => Ok(#field_ident)
-        }
-    });
+    let field_enum_match = named_fields
+        .named
+        .iter()
+        .filter(|f| is_regular(f))
+        .map(|f| {
+            let name = &f.ident;
+            let name_str = name
+                .as_ref()
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| String::from("unknown"));
+            let match_arm = alias(&f.attrs).unwrap_or_else(|| name_str.to_string());
+            let field_ident = quote! { __Field::#name };
+            quote! {
+                #match_arm => Ok(#field_ident)
+            }
+        });
 
-    let expecting = format!("struct {}", struct_ident.to_string());
-    let struct_ident_str = struct_ident.to_string();
+    let expecting = format!("struct {}", struct_name);
 
     let field_names: Vec<_> = named_fields
         .named
         .iter()
+        .filter(|f| is_regular(f))
         .map(|field| {
             field
                 .ident
@@ -357,16 +862,19 @@ pub fn derive(input: TokenStream) -> TokenStream {
         })
         .collect();
 
-    let output = quote! {
-        impl<'de> ::serde::Deserialize<'de> for #struct_ident {
+    quote! {
+        impl #impl_generics ::serde::Deserialize<'de> for #struct_ident #ty #where_clause {
             fn deserialize<__D>(__deserializer: __D) -> ::std::result::Result<Self, __D::Error>
             where __D: ::serde::Deserializer<'de> {
                 #[allow(non_camel_case_types)]
                 enum __Field {
-                    #(#field_enums),* ,
-                    __ignore,
+                    #(#field_enums,)*
+                    #(#path_vars,)*
+                    #other_variant,
                 };
 
+                #(#path_items)*
+
                 struct __FieldVisitor;
                 impl<'de> ::serde::de::Visitor<'de> for __FieldVisitor {
                     type Value = __Field;
@@ -384,8 +892,9 @@ pub fn derive(input: TokenStream) -> TokenStream {
                         __E: ::serde::de::Error,
                     {
                         match __value {
-                            #(#field_enum_match),* ,
-                            _ => Ok(__Field::__ignore),
+                            #(#field_enum_match,)*
+                            #(#path_keys => Ok(__Field::#path_vars),)*
+                            #other_match,
                         }
                     }
                 }
@@ -402,10 +911,12 @@ pub fn derive(input: TokenStream) -> TokenStream {
                     }
                 }
 
-                struct __Visitor;
+                struct __Visitor #impl_generics #where_clause {
+                    __marker: #marker,
+                }
 
-                impl<'de> ::serde::de::Visitor<'de> for __Visitor {
-                    type Value = #struct_ident;
+                impl #impl_generics ::serde::de::Visitor<'de> for __Visitor #helper_ty #where_clause {
+                    type Value = #struct_ident #ty;
 
                     fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
                         write!(formatter, #expecting)
@@ -419,16 +930,22 @@ pub fn derive(input: TokenStream) -> TokenStream {
                     where
                         __A: ::serde::de::MapAccess<'de>,
                     {
-                        #(#builder_init);* ;
+                        #(#builder_init;)*
+                        #(let mut #path_vars: ::std::option::Option<#path_types> = None;)*
 
                         while let Some(__key) = ::serde::de::MapAccess::next_key::<__Field>(&mut __map)? {
                             match __key {
                                 #(#builder_fields),*
-                                _ => { ::serde::de::MapAccess::next_value::<::serde::de::IgnoredAny>(&mut __map)?; }
+                                #(__Field::#path_vars => match #path_vars {
+                                    None => #path_vars = Some(::serde::de::MapAccess::next_value::<#path_types>(&mut __map)?),
+                                    _ => { ::serde::de::MapAccess::next_value::<::serde::de::IgnoredAny>(&mut __map)?; }
+                                })*
+                                #unmatched_arm
                             }
                         }
 
-                        #(#field_extract);* ;
+                        #(#field_extract;)*
+                        #(#path_extract)*
 
                         Ok(#struct_ident {
                             #(#field_constructor),*
@@ -439,12 +956,276 @@ pub fn derive(input: TokenStream) -> TokenStream {
                 const FIELDS: &'static [&'static str] = &[ #(#field_names),* ];
                 ::serde::de::Deserializer::deserialize_struct(
                     __deserializer,
-                    #struct_ident_str,
+                    #struct_name,
                     FIELDS,
-                    __Visitor
+                    __Visitor {
+                        __marker: ::std::marker::PhantomData,
+                    },
                 )
             }
         }
+    }
+}
+
+/// Creates a `Deserialize` implementation for a struct whose fields are read in order from an
+/// array
+fn derive_positional(
+    struct_ident: &Ident,
+    generics: &DeGenerics,
+    fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>,
+    named: bool,
+) -> TokenStream2 {
+    let mut len = 0usize;
+    let mut reads = Vec::new();
+    let mut idents = Vec::new();
+    for (i, f) in fields.iter().enumerate() {
+        let ident = format_ident!("__field{}", i);
+        let x = &f.ty;
+        let read = match take(f) {
+            Some(n) => {
+                len += n;
+                quote! {
+                    let #ident = <#x as ::serde::Deserialize>::deserialize(__Take {
+                        seq: &mut __seq,
+                        remaining: #n,
+                    })?;
+                }
+            }
+            None => {
+                let position = len;
+                len += 1;
+                let fallback = match can_default(f) {
+                    DefaultFallback::Yes => quote! { .unwrap_or_default() },
+                    DefaultFallback::Path(lit) => quote! { .unwrap_or_else(#lit) },
+                    DefaultFallback::No => quote! {
+                        .ok_or_else(|| <__A::Error as ::serde::de::Error>::invalid_length(#position, &self))?
+                    },
+                };
+                quote! {
+                    let #ident = ::serde::de::SeqAccess::next_element::<#x>(&mut __seq)? #fallback;
+                }
+            }
+        };
+        reads.push(read);
+        idents.push(ident);
+    }
+
+    let constructor = if named {
+        let names = fields.iter().map(|f| &f.ident);
+        quote! { #struct_ident { #(#names: #idents),* } }
+    } else {
+        quote! { #struct_ident ( #(#idents),* ) }
     };
-    output.into()
+
+    let take_helper = if fields.iter().any(|f| take(f).is_some()) {
+        quote! {
+            /// Presents the next elements of a sequence as a sequence of their own
+            struct __Take<'a, __A> {
+                seq: &'a mut __A,
+                remaining: usize,
+            }
+
+            impl<'de, 'a, __A> ::serde::de::SeqAccess<'de> for __Take<'a, __A>
+            where __A: ::serde::de::SeqAccess<'de> {
+                type Error = __A::Error;
+
+                fn next_element_seed<__T>(
+                    &mut self,
+                    seed: __T,
+                ) -> ::std::result::Result<::std::option::Option<__T::Value>, Self::Error>
+                where __T: ::serde::de::DeserializeSeed<'de> {
+                    if self.remaining == 0 {
+                        return Ok(None);
+                    }
+                    self.remaining -= 1;
+                    self.seq.next_element_seed(seed)
+                }
+
+                fn size_hint(&self) -> ::std::option::Option<usize> {
+                    Some(self.remaining)
+                }
+            }
+
+            impl<'de, 'a, __A> ::serde::Deserializer<'de> for __Take<'a, __A>
+            where __A: ::serde::de::SeqAccess<'de> {
+                type Error = __A::Error;
+
+                fn deserialize_any<__V>(
+                    mut self,
+                    visitor: __V,
+                ) -> ::std::result::Result<__V::Value, Self::Error>
+                where __V: ::serde::de::Visitor<'de> {
+                    let value = visitor.visit_seq(&mut self)?;
+                    while self.remaining > 0 {
+                        self.remaining -= 1;
+                        if self.seq.next_element::<::serde::de::IgnoredAny>()?.is_none() {
+                            break;
+                        }
+                    }
+                    Ok(value)
+                }
+
+                ::serde::forward_to_deserialize_any! {
+                    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+                    bytes byte_buf option unit unit_struct newtype_struct seq tuple
+                    tuple_struct map struct enum identifier ignored_any
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let expecting = format!("positional struct {}", struct_ident);
+    let DeGenerics {
+        impl_generics,
+        helper_ty,
+        ty,
+        where_clause,
+        marker,
+    } = generics;
+    quote! {
+        impl #impl_generics ::serde::Deserialize<'de> for #struct_ident #ty #where_clause {
+            fn deserialize<__D>(__deserializer: __D) -> ::std::result::Result<Self, __D::Error>
+            where __D: ::serde::Deserializer<'de> {
+                #take_helper
+
+                struct __Visitor #impl_generics #where_clause {
+                    __marker: #marker,
+                }
+
+                impl #impl_generics ::serde::de::Visitor<'de> for __Visitor #helper_ty #where_clause {
+                    type Value = #struct_ident #ty;
+
+                    fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        write!(formatter, #expecting)
+                    }
+
+                    fn visit_seq<__A>(
+                        self,
+                        mut __seq: __A,
+                    ) -> ::std::result::Result<Self::Value, __A::Error>
+                    where
+                        __A: ::serde::de::SeqAccess<'de>,
+                    {
+                        #(#reads)*
+                        while ::serde::de::SeqAccess::next_element::<::serde::de::IgnoredAny>(&mut __seq)?.is_some() {}
+                        Ok(#constructor)
+                    }
+                }
+
+                let __visitor = __Visitor {
+                    __marker: ::std::marker::PhantomData,
+                };
+                ::serde::de::Deserializer::deserialize_tuple(__deserializer, #len, __visitor)
+            }
+        }
+    }
+}
+
+/// Creates a `Deserialize` implementation for an internally tagged enum: an object whose `tag`
+/// field names the variant and whose other fields belong to the variant
+fn derive_tagged(enum_ident: &Ident, tag: &str, data: &syn::DataEnum) -> TokenStream2 {
+    let mut items = Vec::new();
+    let mut arms = Vec::new();
+    let mut variant_names = Vec::new();
+    for (i, variant) in data.variants.iter().enumerate() {
+        let name = &variant.ident;
+        let name_str = alias(&variant.attrs).unwrap_or_else(|| name.to_string());
+        let arm = match &variant.fields {
+            syn::Fields::Unit => quote! {
+                #name_str => {
+                    while ::serde::de::MapAccess::next_entry::<
+                        ::serde::de::IgnoredAny,
+                        ::serde::de::IgnoredAny,
+                    >(&mut __map)?.is_some() {}
+                    Ok(#enum_ident::#name)
+                }
+            },
+            syn::Fields::Named(fields) => {
+                // The variant's fields are deserialized as a struct of their own from the rest
+                // of the object
+                let helper = format_ident!("__Variant{}", i);
+                let helper_name = format!("{}::{}", enum_ident, name);
+                let generics = DeGenerics::new(&helper, &syn::Generics::default());
+                let names: Vec<_> = fields.named.iter().map(|f| &f.ident).collect();
+                let types = fields.named.iter().map(|f| &f.ty);
+                items.push(quote! {
+                    #[allow(non_camel_case_types)]
+                    struct #helper {
+                        #(#names: #types,)*
+                    }
+                });
+                items.push(derive_struct(&helper, &helper_name, &generics, fields));
+                quote! {
+                    #name_str => {
+                        let #helper { #(#names),* } = <#helper as ::serde::Deserialize>::deserialize(
+                            ::serde::de::value::MapAccessDeserializer::new(__map),
+                        )?;
+                        Ok(#enum_ident::#name { #(#names),* })
+                    }
+                }
+            }
+            syn::Fields::Unnamed(_) => panic!("expected tagged enum variants to have named fields"),
+        };
+        arms.push(arm);
+        variant_names.push(name_str);
+    }
+
+    let expecting = format!("enum {}", enum_ident);
+    let out_of_order = format!("expected `{}` to be the first field", tag);
+    quote! {
+        impl<'de> ::serde::Deserialize<'de> for #enum_ident {
+            fn deserialize<__D>(__deserializer: __D) -> ::std::result::Result<Self, __D::Error>
+            where __D: ::serde::Deserializer<'de> {
+                #(#items)*
+
+                struct __Visitor;
+
+                impl<'de> ::serde::de::Visitor<'de> for __Visitor {
+                    type Value = #enum_ident;
+
+                    fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        write!(formatter, #expecting)
+                    }
+
+                    fn visit_map<__A>(
+                        self,
+                        mut __map: __A,
+                    ) -> ::std::result::Result<Self::Value, __A::Error>
+                    where
+                        __A: ::serde::de::MapAccess<'de>,
+                    {
+                        match ::serde::de::MapAccess::next_key::<::std::string::String>(&mut __map)? {
+                            Some(__key) if __key == #tag => {}
+                            Some(_) => {
+                                return Err(<__A::Error as ::serde::de::Error>::custom(#out_of_order));
+                            }
+                            None => {
+                                return Err(<__A::Error as ::serde::de::Error>::missing_field(#tag));
+                            }
+                        }
+
+                        let __variant = ::serde::de::MapAccess::next_value::<::std::string::String>(&mut __map)?;
+                        match __variant.as_str() {
+                            #(#arms)*
+                            _ => Err(<__A::Error as ::serde::de::Error>::unknown_variant(&__variant, VARIANTS)),
+                        }
+                    }
+                }
+
+                const VARIANTS: &[&str] = &[ #(#variant_names),* ];
+
+                // jomini's deserializers recognize this name and move the tag to the front of
+                // the object, other deserializers must be given data where the tag is first
+                const TAG: &[&str] = &[ #tag ];
+                ::serde::de::Deserializer::deserialize_struct(
+                    __deserializer,
+                    "$jomini::tagged",
+                    TAG,
+                    __Visitor,
+                )
+            }
+        }
+    }
 }
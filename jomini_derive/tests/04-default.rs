@@ -23,7 +23,7 @@ fn test_default() {
         }"#;
 
     let m: Model = serde_json::from_str(data).unwrap();
-    assert_eq!(m.human, true);
+    assert!(m.human);
     assert_eq!(m.first, 0);
     assert_eq!(m.fourth, 2);
     assert_eq!(m.core, vec!["AAA".to_string(), "BBB".to_string()]);
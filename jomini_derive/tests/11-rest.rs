@@ -0,0 +1,51 @@
+use jomini_derive::JominiDeserialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(JominiDeserialize)]
+pub struct Model {
+    human: bool,
+    #[jomini(alias = "forth")]
+    fourth: u16,
+    #[jomini(rest)]
+    rest: HashMap<String, serde_json::Value>,
+}
+
+#[derive(JominiDeserialize)]
+pub struct Strings {
+    #[jomini(duplicated)]
+    core: Vec<u32>,
+    #[jomini(rest)]
+    other: BTreeMap<String, String>,
+}
+
+#[test]
+fn test_rest() {
+    let data = r#"
+        {
+            "human": true,
+            "gold": 10.5,
+            "forth": 2,
+            "ideas": { "a": [1, 2] }
+        }"#;
+
+    let m: Model = serde_json::from_str(data).unwrap();
+    assert!(m.human);
+    assert_eq!(m.fourth, 2);
+    assert_eq!(m.rest.len(), 2);
+    assert_eq!(m.rest["gold"], serde_json::json!(10.5));
+    assert_eq!(m.rest["ideas"], serde_json::json!({ "a": [1, 2] }));
+}
+
+#[test]
+fn test_rest_with_duplicated() {
+    let data = r#"{ "core": 1, "a": "b", "core": 2, "c": "d" }"#;
+    let m: Strings = serde_json::from_str(data).unwrap();
+    assert_eq!(m.core, vec![1, 2]);
+    assert_eq!(
+        m.other.into_iter().collect::<Vec<_>>(),
+        vec![
+            (String::from("a"), String::from("b")),
+            (String::from("c"), String::from("d")),
+        ]
+    );
+}
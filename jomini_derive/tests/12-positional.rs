@@ -0,0 +1,43 @@
+use jomini_derive::JominiDeserialize;
+
+#[derive(JominiDeserialize, Debug, PartialEq)]
+#[jomini(positional)]
+pub struct Template {
+    infantry: u8,
+    #[jomini(take = 2)]
+    support: Vec<u8>,
+    artillery: u8,
+    #[jomini(default)]
+    extra: u8,
+}
+
+#[derive(JominiDeserialize, Debug, PartialEq)]
+pub struct Pair(u8, Option<String>);
+
+#[test]
+fn test_positional() {
+    let m: Template = serde_json::from_str("[5, 3, 2, 1]").unwrap();
+    assert_eq!(
+        m,
+        Template {
+            infantry: 5,
+            support: vec![3, 2],
+            artillery: 1,
+            extra: 0,
+        }
+    );
+
+    let m: Template = serde_json::from_str("[5, 3, 2, 1, 4, 9]").unwrap();
+    assert_eq!(m.extra, 4);
+
+    assert!(serde_json::from_str::<Template>("[5, 3, 2]").is_err());
+}
+
+#[test]
+fn test_tuple_struct() {
+    let m: Pair = serde_json::from_str("[1]").unwrap();
+    assert_eq!(m, Pair(1, None));
+
+    let m: Pair = serde_json::from_str(r#"[1, "a"]"#).unwrap();
+    assert_eq!(m, Pair(1, Some(String::from("a"))));
+}
@@ -0,0 +1,46 @@
+use jomini_derive::JominiDeserialize;
+
+#[derive(JominiDeserialize, Debug, PartialEq)]
+pub struct Model {
+    name: String,
+    #[jomini(alias_path = "meta/version/major")]
+    major: u16,
+    #[jomini(alias_path = "meta/version/minor")]
+    minor: Option<u16>,
+    #[jomini(alias_path = "meta/player")]
+    player: String,
+    #[jomini(alias_path = "meta/missing/value", default)]
+    missing: u16,
+}
+
+#[test]
+fn test_alias_path() {
+    let data = r#"
+        {
+            "meta": {
+                "other": { "major": 7 },
+                "version": { "major": 1, "patch": 3 },
+                "player": "ENG"
+            },
+            "name": "a"
+        }"#;
+
+    let m: Model = serde_json::from_str(data).unwrap();
+    assert_eq!(
+        m,
+        Model {
+            name: String::from("a"),
+            major: 1,
+            minor: None,
+            player: String::from("ENG"),
+            missing: 0,
+        }
+    );
+}
+
+#[test]
+fn test_alias_path_missing() {
+    let data = r#"{ "name": "a", "meta": { "player": "ENG" } }"#;
+    let err = serde_json::from_str::<Model>(data).unwrap_err();
+    assert!(err.to_string().contains("meta/version/major"));
+}
@@ -0,0 +1,61 @@
+use jomini_derive::JominiDeserialize;
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+use std::fmt::Debug;
+
+#[derive(JominiDeserialize, Debug, PartialEq)]
+pub struct Model<'a, T>
+where
+    T: Debug,
+{
+    name: &'a str,
+    title: Cow<'a, str>,
+    value: T,
+    #[jomini(duplicated)]
+    core: Vec<T>,
+    #[jomini(alias_path = "meta/extra")]
+    extra: Option<T>,
+    #[jomini(deserialize_with = "deserialize_wrapped")]
+    wrapped: Vec<T>,
+}
+
+#[derive(JominiDeserialize, Debug, PartialEq)]
+pub struct Pair<T>(T, T);
+
+fn deserialize_wrapped<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(|x| vec![x])
+}
+
+#[test]
+fn test_generics() {
+    let data = r#"
+        {
+            "name": "a",
+            "title": "b",
+            "value": 1,
+            "core": 10,
+            "meta": { "extra": 3 },
+            "wrapped": 4,
+            "core": 20
+        }"#;
+
+    let m: Model<u16> = serde_json::from_str(data).unwrap();
+    assert_eq!(
+        m,
+        Model {
+            name: "a",
+            title: Cow::Borrowed("b"),
+            value: 1,
+            core: vec![10, 20],
+            extra: Some(3),
+            wrapped: vec![4],
+        }
+    );
+
+    let pair: Pair<String> = serde_json::from_str(r#"["a", "b"]"#).unwrap();
+    assert_eq!(pair, Pair(String::from("a"), String::from("b")));
+}
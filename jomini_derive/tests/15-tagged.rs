@@ -0,0 +1,47 @@
+use jomini_derive::JominiDeserialize;
+
+#[derive(JominiDeserialize, Debug, PartialEq)]
+#[jomini(tag = "type")]
+pub enum Ai {
+    #[jomini(alias = "general")]
+    General {
+        aggression: u8,
+        #[jomini(duplicated)]
+        target: Vec<String>,
+    },
+    #[jomini(alias = "defensive")]
+    Defensive,
+}
+
+#[test]
+fn test_tagged() {
+    let data = r#"{ "type": "general", "target": "ENG", "aggression": 3, "target": "FRA" }"#;
+    let ai: Ai = serde_json::from_str(data).unwrap();
+    assert_eq!(
+        ai,
+        Ai::General {
+            aggression: 3,
+            target: vec![String::from("ENG"), String::from("FRA")],
+        }
+    );
+
+    let data = r#"{ "type": "defensive", "extra": { "a": 1 } }"#;
+    let ai: Ai = serde_json::from_str(data).unwrap();
+    assert_eq!(ai, Ai::Defensive);
+}
+
+#[test]
+fn test_tagged_errors() {
+    let data = r#"{ "aggression": 3, "type": "general" }"#;
+    let err = serde_json::from_str::<Ai>(data).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("expected `type` to be the first field"));
+
+    let err = serde_json::from_str::<Ai>("{}").unwrap_err();
+    assert!(err.to_string().contains("missing field `type`"));
+
+    let data = r#"{ "type": "general" }"#;
+    let err = serde_json::from_str::<Ai>(data).unwrap_err();
+    assert!(err.to_string().contains("missing field `aggression`"));
+}
@@ -0,0 +1,178 @@
+use crate::{BinaryEvent, BinaryFlavor, BinaryPushParser, Error, TextEvent, TextPushParser};
+use std::collections::VecDeque;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const CHUNK_SIZE: usize = 32 * 1024;
+
+/// Reads text events from an `AsyncRead` source, so that a save can be
+/// processed as it is downloaded instead of buffered in memory first.
+///
+/// ```
+/// use jomini::{AsyncTextEventReader, TextEvent};
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let data = &b"player=\"ENG\""[..];
+/// let mut reader = AsyncTextEventReader::new(data);
+/// let mut events = Vec::new();
+/// while let Some(event) = reader.next_event().await? {
+///     events.push(event);
+/// }
+///
+/// assert_eq!(events[2], TextEvent::Quoted(b"ENG".to_vec()));
+/// # Ok::<(), jomini::Error>(())
+/// # }).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AsyncTextEventReader<R> {
+    reader: R,
+    parser: TextPushParser,
+    events: VecDeque<TextEvent>,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R> AsyncTextEventReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Create a reader of text events
+    pub fn new(reader: R) -> Self {
+        AsyncTextEventReader {
+            reader,
+            parser: TextPushParser::new(),
+            events: VecDeque::new(),
+            buf: vec![0; CHUNK_SIZE],
+            done: false,
+        }
+    }
+
+    /// Return the next event or None when the data has been exhausted
+    pub async fn next_event(&mut self) -> Result<Option<TextEvent>, Error> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            if self.done {
+                return Ok(None);
+            }
+
+            let read = self.reader.read(&mut self.buf).await?;
+            if read == 0 {
+                self.done = true;
+                self.parser.finish_into(&mut self.events)?;
+            } else {
+                self.parser.feed_into(&self.buf[..read], &mut self.events)?;
+            }
+        }
+    }
+}
+
+/// Reads binary events from an `AsyncRead` source, so that a save can be
+/// processed as it is downloaded instead of buffered in memory first.
+///
+/// ```
+/// use jomini::{AsyncBinaryEventReader, BinaryEvent, Eu4Flavor};
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let data = &[0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x59, 0x00, 0x00, 0x00][..];
+/// let mut reader = AsyncBinaryEventReader::new(data, Eu4Flavor::new());
+/// let mut events = Vec::new();
+/// while let Some(event) = reader.next_event().await? {
+///     events.push(event);
+/// }
+///
+/// assert_eq!(
+///     events,
+///     vec![BinaryEvent::Token(0x2d82), BinaryEvent::Equal, BinaryEvent::I32(89)]
+/// );
+/// # Ok::<(), jomini::Error>(())
+/// # }).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AsyncBinaryEventReader<R, F> {
+    reader: R,
+    parser: BinaryPushParser<F>,
+    events: VecDeque<BinaryEvent>,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R, F> AsyncBinaryEventReader<R, F>
+where
+    R: AsyncRead + Unpin,
+    F: BinaryFlavor,
+{
+    /// Create a reader of binary events with the given flavor
+    pub fn new(reader: R, flavor: F) -> Self {
+        AsyncBinaryEventReader {
+            reader,
+            parser: BinaryPushParser::new(flavor),
+            events: VecDeque::new(),
+            buf: vec![0; CHUNK_SIZE],
+            done: false,
+        }
+    }
+
+    /// Return the next event or None when the data has been exhausted
+    pub async fn next_event(&mut self) -> Result<Option<BinaryEvent>, Error> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            if self.done {
+                return Ok(None);
+            }
+
+            let read = self.reader.read(&mut self.buf).await?;
+            if read == 0 {
+                self.done = true;
+                self.parser.check_finished()?;
+            } else {
+                self.parser.feed_into(&self.buf[..read], &mut self.events);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Eu4Flavor;
+
+    #[tokio::test]
+    async fn test_async_text_events_across_reads() {
+        let data = &b"abc={ 1 2 }"[..];
+        let reader = (&data[..2]).chain(&data[2..7]).chain(&data[7..]);
+        let mut reader = AsyncTextEventReader::new(reader);
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event().await.unwrap() {
+            events.push(event);
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                TextEvent::Scalar(b"abc".to_vec()),
+                TextEvent::Equal,
+                TextEvent::Open,
+                TextEvent::Scalar(b"1".to_vec()),
+                TextEvent::Scalar(b"2".to_vec()),
+                TextEvent::Close,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_binary_events_truncated() {
+        let data = &[0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x59][..];
+        let mut reader = AsyncBinaryEventReader::new(data, Eu4Flavor::new());
+        assert_eq!(
+            reader.next_event().await.unwrap(),
+            Some(BinaryEvent::Token(0x2d82))
+        );
+        assert_eq!(reader.next_event().await.unwrap(), Some(BinaryEvent::Equal));
+        assert!(reader.next_event().await.unwrap_err().is_eof());
+    }
+}
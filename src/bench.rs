@@ -0,0 +1,323 @@
+//! Helpers for measuring parsing throughput against real world files
+//!
+//! The synthetic fixtures in this repo are small and only exercise so much of
+//! the parser. To catch regressions in the hot paths, point the harness at a
+//! directory of save files (the corpus). Files are not distributed with jomini
+//! and should be supplied by the user, eg: through the `JOMINI_CORPUS`
+//! environment variable.
+//!
+//! ```no_run
+//! use jomini::bench::{self, Corpus};
+//!
+//! if let Some(corpus) = Corpus::from_env("JOMINI_CORPUS")? {
+//!     let text = bench::text_parse(&corpus, 5)?;
+//!     println!("text: {:.2} MB/s", text.megabytes_per_second());
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use crate::{BinaryFlavor, BinaryTape, Error, NormalizedTape, TextTape, TokenResolver};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A single file of a corpus
+#[derive(Debug, Clone)]
+pub struct CorpusFile {
+    path: PathBuf,
+    data: Vec<u8>,
+}
+
+impl CorpusFile {
+    /// Create a corpus file from data already in memory
+    pub fn new<P: Into<PathBuf>>(path: P, data: Vec<u8>) -> Self {
+        CorpusFile {
+            path: path.into(),
+            data,
+        }
+    }
+
+    /// Where the file was loaded from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The raw contents of the file
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The contents of the file with a leading header (eg: `EU4txt` or
+    /// `EU4bin`) removed
+    pub fn body(&self) -> &[u8] {
+        let is_header = self.data.len() >= 6
+            && self.data[..3].iter().all(|x| x.is_ascii_alphanumeric())
+            && (&self.data[3..6] == b"txt" || &self.data[3..6] == b"bin");
+
+        if is_header {
+            &self.data[6..]
+        } else {
+            &self.data
+        }
+    }
+
+    /// Returns true if the file's header denotes the binary format
+    pub fn is_binary(&self) -> bool {
+        self.data.get(3..6) == Some(&b"bin"[..])
+    }
+}
+
+/// A collection of files to measure against
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    files: Vec<CorpusFile>,
+}
+
+impl Corpus {
+    /// Create a corpus from the given files
+    pub fn new(files: Vec<CorpusFile>) -> Self {
+        Corpus { files }
+    }
+
+    /// Load every file in the directory (not recursive), ordered by path
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> io::Result<Corpus> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+
+        paths.sort();
+        let files = paths
+            .into_iter()
+            .map(|path| std::fs::read(&path).map(|data| CorpusFile::new(path, data)))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Corpus { files })
+    }
+
+    /// Load the corpus from the directory named by the environment variable.
+    /// Returns `None` when the variable is not set.
+    pub fn from_env(var: &str) -> io::Result<Option<Corpus>> {
+        match std::env::var_os(var) {
+            Some(dir) => Corpus::from_dir(dir).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The files of the corpus
+    pub fn files(&self) -> &[CorpusFile] {
+        &self.files
+    }
+
+    /// A corpus of only the plaintext files
+    pub fn text(&self) -> Corpus {
+        Corpus::new(
+            self.files
+                .iter()
+                .filter(|x| !x.is_binary())
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// A corpus of only the binary files
+    pub fn binary(&self) -> Corpus {
+        Corpus::new(
+            self.files
+                .iter()
+                .filter(|x| x.is_binary())
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Total number of bytes in the bodies of the files
+    pub fn len(&self) -> usize {
+        self.files.iter().map(|x| x.body().len()).sum()
+    }
+
+    /// Returns true if the corpus does not contain any files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// The result of running a workload against a corpus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    bytes: u64,
+    iterations: u32,
+    elapsed: Duration,
+}
+
+impl Measurement {
+    /// Total number of bytes processed across all iterations
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of times the corpus was processed
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// Total time spent processing
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Throughput in megabytes (10^6 bytes) per second
+    pub fn megabytes_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.bytes as f64 / 1_000_000.0 / secs
+        }
+    }
+}
+
+/// Run the workload on the body of every file in the corpus for the given
+/// number of iterations. This is the building block for measuring any
+/// workload, eg: deserializing into a downstream crate's types.
+pub fn measure<'a, F>(corpus: &'a Corpus, iterations: u32, mut f: F) -> Result<Measurement, Error>
+where
+    F: FnMut(&'a [u8]) -> Result<(), Error>,
+{
+    let start = Instant::now();
+    for _ in 0..iterations {
+        for file in corpus.files() {
+            f(file.body())?;
+        }
+    }
+
+    Ok(Measurement {
+        bytes: corpus.len() as u64 * u64::from(iterations),
+        iterations,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Measure the throughput of parsing the plaintext files into a tape
+pub fn text_parse(corpus: &Corpus, iterations: u32) -> Result<Measurement, Error> {
+    let corpus = corpus.text();
+    let mut tape = TextTape::new();
+    measure(&corpus, iterations, |data| {
+        TextTape::parser().parse_slice_into_tape(data, &mut tape)
+    })
+}
+
+/// Measure the throughput of parsing the binary files into a tape
+pub fn binary_parse<F>(corpus: &Corpus, flavor: F, iterations: u32) -> Result<Measurement, Error>
+where
+    F: BinaryFlavor,
+{
+    let corpus = corpus.binary();
+    let mut tape = BinaryTape::new();
+    measure(&corpus, iterations, |data| {
+        BinaryTape::parser_flavor(&flavor).parse_slice_into_tape(data, &mut tape)
+    })
+}
+
+/// Measure the throughput of normalizing (melting) the binary files into the
+/// plaintext format. The measurement includes parsing the files into a tape.
+pub fn binary_normalize<F, RES>(
+    corpus: &Corpus,
+    flavor: F,
+    resolver: &RES,
+    iterations: u32,
+) -> Result<Measurement, Error>
+where
+    F: BinaryFlavor,
+    RES: TokenResolver,
+{
+    let corpus = corpus.binary();
+    let mut tape = BinaryTape::new();
+    let normalizer = NormalizedTape::builder_flavor(&flavor);
+    measure(&corpus, iterations, |data| {
+        BinaryTape::parser_flavor(&flavor).parse_slice_into_tape(data, &mut tape)?;
+        normalizer.normalize(&tape, resolver).map(|_| ())
+    })
+}
+
+/// Measure the throughput of deserializing the plaintext files
+#[cfg(feature = "derive")]
+pub fn text_deserialize<T>(corpus: &Corpus, iterations: u32) -> Result<Measurement, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let corpus = corpus.text();
+    measure(&corpus, iterations, |data| {
+        crate::TextDeserializer::from_windows1252_slice::<T>(data).map(|_| ())
+    })
+}
+
+/// Measure the throughput of deserializing the binary files
+#[cfg(feature = "derive")]
+pub fn binary_deserialize<T, F, RES>(
+    corpus: &Corpus,
+    flavor: F,
+    resolver: &RES,
+    iterations: u32,
+) -> Result<Measurement, Error>
+where
+    T: serde::de::DeserializeOwned,
+    F: BinaryFlavor,
+    RES: TokenResolver,
+{
+    let corpus = corpus.binary();
+    let deserializer = crate::BinaryDeserializer::builder_flavor(flavor);
+    measure(&corpus, iterations, |data| {
+        deserializer.from_slice::<_, T>(data, resolver).map(|_| ())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Eu4Flavor;
+    use std::collections::HashMap;
+
+    fn corpus() -> Corpus {
+        Corpus::new(vec![
+            CorpusFile::new("a.txt", b"EU4txt\nfoo=bar".to_vec()),
+            CorpusFile::new(
+                "b.bin",
+                b"EU4bin\x82\x2d\x01\x00\x0f\x00\x03\x00\x45\x4e\x47".to_vec(),
+            ),
+            CorpusFile::new("c.txt", b"a={b=c}".to_vec()),
+        ])
+    }
+
+    #[test]
+    fn test_corpus_bodies() {
+        let corpus = corpus();
+        assert_eq!(corpus.files()[0].body(), b"\nfoo=bar");
+        assert_eq!(corpus.files()[2].body(), b"a={b=c}");
+        assert_eq!(corpus.text().files().len(), 2);
+        assert_eq!(corpus.binary().files().len(), 1);
+        assert_eq!(corpus.len(), 8 + 11 + 7);
+    }
+
+    #[test]
+    fn test_measure() {
+        let corpus = corpus();
+        let text = text_parse(&corpus, 3).unwrap();
+        assert_eq!(text.bytes(), 3 * 15);
+        assert_eq!(text.iterations(), 3);
+
+        let binary = binary_parse(&corpus, Eu4Flavor::new(), 2).unwrap();
+        assert_eq!(binary.bytes(), 2 * 11);
+
+        let resolver: HashMap<u16, &str> = HashMap::new();
+        let normalized = binary_normalize(&corpus, Eu4Flavor::new(), &resolver, 2).unwrap();
+        assert_eq!(normalized.bytes(), 2 * 11);
+    }
+
+    #[test]
+    fn test_measure_error() {
+        let corpus = Corpus::new(vec![CorpusFile::new("a.txt", b"a={".to_vec())]);
+        assert!(text_parse(&corpus, 1).is_err());
+    }
+}
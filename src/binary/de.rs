@@ -1,10 +1,20 @@
+use super::ondemand::{OndemandDeserializer, OndemandRootDeserializer};
 use super::tape::{array_len, object_len};
+#[cfg(feature = "metrics")]
+use crate::observer::{Observer, ParseObserver};
+use crate::trace::span;
 use crate::{
-    de::ColorSequence, BinaryFlavor, BinaryTape, BinaryToken, Ck3Flavor, DeserializeError,
-    DeserializeErrorKind, Encoding, Error, Eu4Flavor, FailedResolveStrategy, TokenResolver,
+    de::{
+        duplicate_key_error, tagged_struct, visit_integer, visit_rgb, DuplicatedValues,
+        IntegerType, KeyValuePair,
+    },
+    BinaryFlavor, BinaryTape, BinaryToken, Ck3Flavor, DeserializeError, DeserializeErrorKind,
+    Diagnostic, Diagnostics, DuplicateKeyStrategy, Error, Eu4Flavor, FailedResolveStrategy,
+    IntegerOverflow, RgbRepresentation, ScalarError, TokenResolver,
 };
 use serde::de::{self, Deserialize, DeserializeSeed, MapAccess, SeqAccess, Visitor};
 use std::borrow::Cow;
+use std::collections::HashMap;
 
 /// A structure to deserialize binary data into Rust values.
 ///
@@ -60,11 +70,41 @@ use std::borrow::Cow;
 /// let c: StructC = deserializer.from_tape(&tape, &map)?;
 /// assert_eq!(b, StructB { field1: "ENG".to_string() });
 /// assert_eq!(c, StructC { field2: "ENH".to_string() });
+///
+/// // the tape can be inspected before being deserialized
+/// assert_eq!(tape.tokens().len(), 4);
+/// let a: StructA = BinaryDeserializer::from_eu4_tape(&tape, &map)?;
+/// assert_eq!(a.b, b);
 /// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
 pub struct BinaryDeserializer;
 
 impl BinaryDeserializer {
+    /// Create a builder that is the single entry point for binary
+    /// deserialization: pick the flavor, attach a resolver, and deserialize.
+    /// The flavor defaults to EU4 until [`flavor`](BinaryDeserializerBuilder::flavor)
+    /// is set.
+    ///
+    /// ```
+    /// use jomini::{BinaryDeserializer, Ck3Flavor, FailedResolveStrategy};
+    /// use std::collections::HashMap;
+    ///
+    /// let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47];
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "field1");
+    ///
+    /// let actual: HashMap<String, String> = BinaryDeserializer::builder()
+    ///     .flavor(Ck3Flavor::new())
+    ///     .on_failed_resolve(FailedResolveStrategy::Error)
+    ///     .resolver(&map)
+    ///     .deserialize_slice(&data[..])?;
+    /// assert_eq!(actual["field1"], "ENG");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn builder() -> BinaryDeserializerBuilder<Eu4Flavor> {
+        Self::eu4_builder()
+    }
+
     /// Create a builder to custom binary deserialization
     pub fn eu4_builder() -> BinaryDeserializerBuilder<Eu4Flavor> {
         BinaryDeserializerBuilder::with_flavor(Eu4Flavor::new())
@@ -75,7 +115,30 @@ impl BinaryDeserializer {
         BinaryDeserializerBuilder::with_flavor(Ck3Flavor::new())
     }
 
-    /// A customized builder for a certain flavor of binary data
+    /// A customized builder for a certain flavor of binary data.
+    ///
+    /// The flavor can be a trait object to select the game at runtime
+    ///
+    /// ```
+    /// use jomini::{BinaryDeserializer, BinaryFlavor, Ck3Flavor, Eu4Flavor};
+    /// use std::collections::HashMap;
+    ///
+    /// fn flavor(game: &str) -> Box<dyn BinaryFlavor> {
+    ///     match game {
+    ///         "eu4" => Box::new(Eu4Flavor::new()),
+    ///         _ => Box::new(Ck3Flavor::new()),
+    ///     }
+    /// }
+    ///
+    /// let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47];
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "field1");
+    ///
+    /// let builder = BinaryDeserializer::builder_flavor(flavor("eu4"));
+    /// let actual: HashMap<String, String> = builder.from_slice(&data[..], &map)?;
+    /// assert_eq!(actual["field1"], "ENG");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     pub fn builder_flavor<F>(flavor: F) -> BinaryDeserializerBuilder<F>
     where
         F: BinaryFlavor,
@@ -83,7 +146,53 @@ impl BinaryDeserializer {
         BinaryDeserializerBuilder::with_flavor(flavor)
     }
 
-    /// Convenience method for parsing and deserializing binary data in a single step
+    /// Convenience method for parsing and deserializing binary data of the
+    /// given flavor in a single step. Generic code can accept the flavor as a
+    /// value instead of choosing between the differently named methods.
+    ///
+    /// ```
+    /// use jomini::{BinaryDeserializer, Eu4Flavor};
+    /// use std::collections::HashMap;
+    ///
+    /// let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47];
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "field1");
+    ///
+    /// let actual: HashMap<String, String> =
+    ///     BinaryDeserializer::from_slice_with(&data[..], Eu4Flavor::new(), &map)?;
+    /// assert_eq!(actual["field1"], "ENG");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_slice_with<'a, 'res: 'a, F, RES, T>(
+        data: &'a [u8],
+        flavor: F,
+        resolver: &'res RES,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        F: BinaryFlavor,
+        RES: TokenResolver,
+    {
+        Self::builder_flavor(flavor).from_slice(data, resolver)
+    }
+
+    /// Convenience method for deserializing an already parsed binary tape of
+    /// the given flavor
+    pub fn from_tape_with<'a, 'res: 'a, F, RES, T>(
+        tape: &BinaryTape<'a>,
+        flavor: F,
+        resolver: &'res RES,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        F: BinaryFlavor,
+        RES: TokenResolver,
+    {
+        Self::builder_flavor(flavor).from_tape(tape, resolver)
+    }
+
+    /// Convenience method for parsing and deserializing binary data in a single step.
+    /// The resolver can be any `TokenResolver`, like a `HashMap` or a `TokenTable`.
     pub fn from_eu4<'a, 'res: 'a, RES, T>(data: &'a [u8], resolver: &'res RES) -> Result<T, Error>
     where
         T: Deserialize<'a>,
@@ -92,7 +201,8 @@ impl BinaryDeserializer {
         Self::eu4_builder().from_slice(data, resolver)
     }
 
-    /// Convenience method for parsing and deserializing binary data in a single step
+    /// Convenience method for parsing and deserializing binary data in a single step.
+    /// The resolver can be any `TokenResolver`, like a `HashMap` or a `TokenTable`.
     pub fn from_ck3<'a, 'b, 'res: 'a, RES, T>(
         data: &'a [u8],
         resolver: &'res RES,
@@ -103,12 +213,45 @@ impl BinaryDeserializer {
     {
         Self::ck3_builder().from_slice(data, resolver)
     }
+
+    /// Convenience method for deserializing an already parsed eu4 binary tape
+    pub fn from_eu4_tape<'a, 'res: 'a, RES, T>(
+        tape: &BinaryTape<'a>,
+        resolver: &'res RES,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        RES: TokenResolver,
+    {
+        Self::eu4_builder().from_tape(tape, resolver)
+    }
+
+    /// Convenience method for deserializing an already parsed ck3 binary tape
+    pub fn from_ck3_tape<'a, 'res: 'a, RES, T>(
+        tape: &BinaryTape<'a>,
+        resolver: &'res RES,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        RES: TokenResolver,
+    {
+        Self::ck3_builder().from_tape(tape, resolver)
+    }
 }
 
 /// Build a tweaked binary deserializer
 #[derive(Debug)]
 pub struct BinaryDeserializerBuilder<F> {
     failed_resolve_strategy: FailedResolveStrategy,
+    duplicate_key_strategy: DuplicateKeyStrategy,
+    diagnostics: Option<Diagnostics>,
+    rgb_representation: RgbRepresentation,
+    dates_as_text: bool,
+    empty_block_as_none: bool,
+    integer_overflow: IntegerOverflow,
+    lossy: bool,
+    #[cfg(feature = "metrics")]
+    observer: Option<Observer>,
     flavor: F,
 }
 
@@ -120,16 +263,182 @@ where
     pub fn with_flavor(flavor: F) -> Self {
         BinaryDeserializerBuilder {
             failed_resolve_strategy: FailedResolveStrategy::Ignore,
+            duplicate_key_strategy: DuplicateKeyStrategy::Preserve,
+            diagnostics: None,
+            rgb_representation: RgbRepresentation::Header,
+            dates_as_text: false,
+            empty_block_as_none: false,
+            integer_overflow: IntegerOverflow::Error,
+            lossy: false,
+            #[cfg(feature = "metrics")]
+            observer: None,
+            flavor,
+        }
+    }
+
+    /// Replace the flavor of binary data that will be deserialized, keeping
+    /// the rest of the configuration
+    pub fn flavor<F2>(self, flavor: F2) -> BinaryDeserializerBuilder<F2>
+    where
+        F2: BinaryFlavor,
+    {
+        BinaryDeserializerBuilder {
+            failed_resolve_strategy: self.failed_resolve_strategy,
+            duplicate_key_strategy: self.duplicate_key_strategy,
+            diagnostics: self.diagnostics,
+            rgb_representation: self.rgb_representation,
+            dates_as_text: self.dates_as_text,
+            empty_block_as_none: self.empty_block_as_none,
+            integer_overflow: self.integer_overflow,
+            lossy: self.lossy,
+            #[cfg(feature = "metrics")]
+            observer: self.observer,
             flavor,
         }
     }
 
+    /// Attach the token resolver so that data can be deserialized without
+    /// passing the resolver to each call
+    pub fn resolver<'b, 'res, RES>(
+        &'b self,
+        resolver: &'res RES,
+    ) -> ResolvedBinaryDeserializer<'b, 'res, F, RES>
+    where
+        RES: TokenResolver,
+    {
+        ResolvedBinaryDeserializer {
+            builder: self,
+            resolver,
+        }
+    }
+
     /// Set the behavior when a unknown token is encountered
     pub fn on_failed_resolve(&mut self, strategy: FailedResolveStrategy) -> &mut Self {
         self.failed_resolve_strategy = strategy;
         self
     }
 
+    /// Set the behavior when a key occurs multiple times within an object
+    pub fn on_duplicate_key(&mut self, strategy: DuplicateKeyStrategy) -> &mut Self {
+        self.duplicate_key_strategy = strategy;
+        self
+    }
+
+    /// Record recoverable problems (unknown tokens that were not treated as
+    /// an error, strings that could not be decoded cleanly, and strings that
+    /// were truncated by a lossy parse) into the given diagnostics
+    pub fn diagnostics(&mut self, diagnostics: &Diagnostics) -> &mut Self {
+        self.diagnostics = Some(diagnostics.clone());
+        self
+    }
+
+    /// Report the bytes parsed, tokens produced, and unknown tokens
+    /// encountered to the observer. See `ParseObserver`.
+    #[cfg(feature = "metrics")]
+    pub fn observer(&mut self, observer: &'static dyn ParseObserver) -> &mut Self {
+        self.observer = Some(Observer(observer));
+        self
+    }
+
+    /// Set how rgb tokens are presented to the deserialized type. By
+    /// default, colors are a sequence of the `rgb` header and the channels
+    /// to mirror the text format.
+    ///
+    /// ```
+    /// use jomini::{BinaryDeserializer, RgbRepresentation};
+    /// use serde::Deserialize;
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Color {
+    ///     r: u8,
+    ///     g: u8,
+    ///     b: u8,
+    /// }
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct MyStruct {
+    ///     color: Color,
+    /// }
+    ///
+    /// let data = [
+    ///     0x82, 0x2d, 0x01, 0x00, 0x43, 0x02, 0x03, 0x00, 0x14, 0x00, 0x6e, 0x00, 0x00, 0x00,
+    ///     0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x04, 0x00,
+    /// ];
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "color");
+    ///
+    /// let actual: MyStruct = BinaryDeserializer::eu4_builder()
+    ///     .rgb_representation(RgbRepresentation::Object)
+    ///     .from_slice(&data, &map)?;
+    /// assert_eq!(actual.color, Color { r: 110, g: 27, b: 27 });
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn rgb_representation(&mut self, representation: RgbRepresentation) -> &mut Self {
+        self.rgb_representation = representation;
+        self
+    }
+
+    /// Present binary dates to string fields in their textual `Y.M.D` form.
+    ///
+    /// Dates are integers in binary data but strings in text, so a `String`
+    /// field that holds a date fails to deserialize from one of the formats.
+    /// When enabled, an integer that the flavor decodes as a date is given
+    /// as text whenever a string is requested. Integer fields are
+    /// unaffected, and a `Date` field accepts either format regardless.
+    ///
+    /// ```
+    /// use jomini::{BinaryDeserializer, TextDeserializer};
+    /// use serde::Deserialize;
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Meta {
+    ///     date: String,
+    /// }
+    ///
+    /// let data = [0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x10, 0x77, 0x5d, 0x03];
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "date");
+    ///
+    /// let binary: Meta = BinaryDeserializer::eu4_builder()
+    ///     .dates_as_text(true)
+    ///     .from_slice(&data[..], &map)?;
+    /// let text: Meta = TextDeserializer::from_windows1252_slice(b"date=1444.11.11")?;
+    /// assert_eq!(binary, text);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn dates_as_text(&mut self, enabled: bool) -> &mut Self {
+        self.dates_as_text = enabled;
+        self
+    }
+
+    /// Deserialize an empty block (`field = {}`) into an `Option` as `None`
+    /// instead of `Some` of an empty value. Defaults to false. See
+    /// `TextDeserializerBuilder::empty_block_as_none` for how fields map onto
+    /// sequences and optional values, which is the same for binary data.
+    pub fn empty_block_as_none(&mut self, enabled: bool) -> &mut Self {
+        self.empty_block_as_none = enabled;
+        self
+    }
+
+    /// Set the behavior when an integer doesn't fit in the requested type
+    /// (eg: a `u32` token deserialized into a `u8`). Defaults to an error.
+    /// See `TextDeserializerBuilder::integer_overflow`, which behaves the
+    /// same for text data.
+    pub fn integer_overflow(&mut self, overflow: IntegerOverflow) -> &mut Self {
+        self.integer_overflow = overflow;
+        self
+    }
+
+    /// Parse slices with `BinaryTapeParser::truncate_strings` so that a
+    /// corrupted string length doesn't fail the whole file. Truncated strings
+    /// are recorded in the diagnostics.
+    pub fn lossy(&mut self, lossy: bool) -> &mut Self {
+        self.lossy = lossy;
+        self
+    }
+
     /// Convenience method for parsing and deserializing binary data in a single step
     pub fn from_slice<'a, 'b, 'res: 'a, RES, T>(
         &'b self,
@@ -140,8 +449,83 @@ where
         T: Deserialize<'a>,
         RES: TokenResolver,
     {
-        let tape = BinaryTape::parser_flavor(&self.flavor).parse_slice(data)?;
-        Ok(self.from_tape(&tape, resolver)?)
+        let mut parser = BinaryTape::parser_flavor(&self.flavor).truncate_strings(self.lossy);
+        if let Some(diagnostics) = &self.diagnostics {
+            parser = parser.diagnostics(diagnostics);
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(Observer(observer)) = self.observer {
+            parser = parser.observer(observer);
+        }
+
+        let tape = parser.parse_slice(data)?;
+        self.from_tape(&tape, resolver)
+    }
+
+    /// Deserialize binary data without first parsing it into a tape.
+    ///
+    /// The data is walked with a cursor and values are fed to serde as they
+    /// are encountered, so memory usage does not grow with the number of
+    /// tokens in the input. Values ignored by the deserialized type still
+    /// need to be scanned to find where they end, which a tape can skip over
+    /// in constant time, so prefer `from_tape` when the same data is
+    /// deserialized multiple times.
+    ///
+    /// Duplicate key strategies other than `Preserve` need to see all the
+    /// fields of an object up front, so the data is parsed into a tape
+    /// instead when one is configured, as it is for lossy parsing. For the
+    /// same reason, the tag of a derived internally tagged enum must be the
+    /// first field of its object.
+    ///
+    /// ```
+    /// use jomini::BinaryDeserializer;
+    /// use serde::Deserialize;
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct MyStruct {
+    ///     field1: String,
+    /// }
+    ///
+    /// let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47];
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "field1");
+    ///
+    /// let actual: MyStruct = BinaryDeserializer::eu4_builder().from_slice_ondemand(&data, &map)?;
+    /// assert_eq!(actual, MyStruct { field1: "ENG".to_string() });
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_slice_ondemand<'a, 'b, 'res: 'a, RES, T>(
+        &'b self,
+        data: &'a [u8],
+        resolver: &'res RES,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        RES: TokenResolver,
+    {
+        if self.duplicate_key_strategy != DuplicateKeyStrategy::Preserve || self.lossy {
+            return self.from_slice(data, resolver);
+        }
+
+        let config = BinaryConfig {
+            resolver,
+            failed_resolve_strategy: self.failed_resolve_strategy,
+            duplicate_key_strategy: self.duplicate_key_strategy,
+            diagnostics: self.diagnostics.clone(),
+            rgb: self.rgb_representation,
+            dates_as_text: self.dates_as_text,
+            empty_block_as_none: self.empty_block_as_none,
+            integer_overflow: self.integer_overflow,
+            encoding: &self.flavor,
+            #[cfg(feature = "metrics")]
+            observer: self.observer,
+        };
+
+        let _span = span!("deserialize_binary_ondemand", bytes = data.len());
+        let mut deserializer = OndemandDeserializer::new(data, &config);
+        T::deserialize(OndemandRootDeserializer::new(&mut deserializer))
     }
 
     /// Deserialize the given binary tape
@@ -157,21 +541,208 @@ where
         let config = BinaryConfig {
             resolver,
             failed_resolve_strategy: self.failed_resolve_strategy,
+            duplicate_key_strategy: self.duplicate_key_strategy,
+            diagnostics: self.diagnostics.clone(),
+            rgb: self.rgb_representation,
+            dates_as_text: self.dates_as_text,
+            empty_block_as_none: self.empty_block_as_none,
+            integer_overflow: self.integer_overflow,
             encoding: &self.flavor,
+            #[cfg(feature = "metrics")]
+            observer: self.observer,
         };
 
+        let _span = span!("deserialize_binary", tokens = tape.tokens().len());
         let mut deserializer = RootDeserializer {
             tokens: tape.tokens(),
             config: &config,
         };
         Ok(T::deserialize(&mut deserializer)?)
     }
+
+    /// Create a serde deserializer of the tape, so that it can be used with
+    /// crates that drive a deserializer directly, like `serde_transcode` to
+    /// convert the data into another format without an intermediate value.
+    ///
+    /// ```
+    /// use jomini::{BinaryDeserializer, BinaryTape};
+    /// use serde::Deserialize;
+    /// use std::collections::HashMap;
+    ///
+    /// let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47];
+    /// let tape = BinaryTape::from_eu4(&data[..])?;
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "player");
+    ///
+    /// let builder = BinaryDeserializer::eu4_builder();
+    /// let mut deserializer = builder.deserializer(&tape, &map);
+    /// let data = HashMap::<String, String>::deserialize(&mut deserializer)?;
+    /// assert_eq!(data.get("player").map(String::as_str), Some("ENG"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn deserializer<'a, 'b, 'res: 'a, RES>(
+        &'b self,
+        tape: &'b BinaryTape<'a>,
+        resolver: &'res RES,
+    ) -> BinaryTapeDeserializer<'b, 'a, 'res, RES, &'b F>
+    where
+        RES: TokenResolver,
+    {
+        BinaryTapeDeserializer {
+            tokens: tape.tokens(),
+            config: BinaryConfig {
+                resolver,
+                failed_resolve_strategy: self.failed_resolve_strategy,
+                duplicate_key_strategy: self.duplicate_key_strategy,
+                diagnostics: self.diagnostics.clone(),
+                rgb: self.rgb_representation,
+                dates_as_text: self.dates_as_text,
+                empty_block_as_none: self.empty_block_as_none,
+                integer_overflow: self.integer_overflow,
+                encoding: &self.flavor,
+                #[cfg(feature = "metrics")]
+                observer: self.observer,
+            },
+        }
+    }
 }
 
-struct BinaryConfig<'res, RES, E> {
+/// A binary deserializer with its flavor and token resolver decided. See
+/// `BinaryDeserializer::builder`.
+pub struct ResolvedBinaryDeserializer<'b, 'res, F, RES> {
+    builder: &'b BinaryDeserializerBuilder<F>,
     resolver: &'res RES,
-    failed_resolve_strategy: FailedResolveStrategy,
-    encoding: E,
+}
+
+impl<'b, 'res, F, RES> std::fmt::Debug for ResolvedBinaryDeserializer<'b, 'res, F, RES>
+where
+    F: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResolvedBinaryDeserializer")
+            .field("builder", &self.builder)
+            .finish()
+    }
+}
+
+impl<'b, 'res, F, RES> ResolvedBinaryDeserializer<'b, 'res, F, RES>
+where
+    F: BinaryFlavor,
+    RES: TokenResolver,
+{
+    /// Parse and deserialize binary data in a single step
+    pub fn deserialize_slice<'a, T>(&self, data: &'a [u8]) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        'res: 'a,
+    {
+        self.builder.from_slice(data, self.resolver)
+    }
+
+    /// Deserialize binary data without first parsing it into a tape. See
+    /// `BinaryDeserializerBuilder::from_slice_ondemand`.
+    pub fn deserialize_slice_ondemand<'a, T>(&self, data: &'a [u8]) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        'res: 'a,
+    {
+        self.builder.from_slice_ondemand(data, self.resolver)
+    }
+
+    /// Deserialize an already parsed binary tape
+    pub fn deserialize_tape<'a, T>(&self, tape: &BinaryTape<'a>) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        'res: 'a,
+    {
+        self.builder.from_tape(tape, self.resolver)
+    }
+}
+
+/// A serde deserializer of a binary tape. See
+/// `BinaryDeserializerBuilder::deserializer`.
+pub struct BinaryTapeDeserializer<'b, 'de, 'res, RES, E> {
+    tokens: &'b [BinaryToken<'de>],
+    config: BinaryConfig<'res, RES, E>,
+}
+
+impl<'b, 'de, 'res, RES, E> std::fmt::Debug for BinaryTapeDeserializer<'b, 'de, 'res, RES, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BinaryTapeDeserializer")
+            .field("tokens", &self.tokens.len())
+            .finish()
+    }
+}
+
+impl<'b, 'de, 'r, 'res: 'de, RES: TokenResolver, E: BinaryFlavor> de::Deserializer<'de>
+    for &'r mut BinaryTapeDeserializer<'b, 'de, 'res, RES, E>
+{
+    type Error = DeserializeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut root = RootDeserializer {
+            tokens: self.tokens,
+            config: &self.config,
+        };
+        de::Deserializer::deserialize_map(&mut root, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut root = RootDeserializer {
+            tokens: self.tokens,
+            config: &self.config,
+        };
+        de::Deserializer::deserialize_struct(&mut root, name, fields, visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut root = RootDeserializer {
+            tokens: self.tokens,
+            config: &self.config,
+        };
+        de::Deserializer::deserialize_seq(&mut root, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct tuple
+        tuple_struct enum ignored_any identifier
+    }
+}
+
+pub(super) struct BinaryConfig<'res, RES, E> {
+    pub(super) resolver: &'res RES,
+    pub(super) failed_resolve_strategy: FailedResolveStrategy,
+    pub(super) duplicate_key_strategy: DuplicateKeyStrategy,
+    pub(super) diagnostics: Option<Diagnostics>,
+    pub(super) rgb: RgbRepresentation,
+    pub(super) dates_as_text: bool,
+    pub(super) empty_block_as_none: bool,
+    pub(super) integer_overflow: IntegerOverflow,
+    pub(super) encoding: E,
+    #[cfg(feature = "metrics")]
+    pub(super) observer: Option<Observer>,
 }
 
 struct RootDeserializer<'b, 'a: 'b, 'res: 'a, RES, E> {
@@ -179,7 +750,7 @@ struct RootDeserializer<'b, 'a: 'b, 'res: 'a, RES, E> {
     config: &'b BinaryConfig<'res, RES, E>,
 }
 
-impl<'b, 'de, 'r, 'res, RES: TokenResolver, E: Encoding> de::Deserializer<'de>
+impl<'b, 'de, 'r, 'res, RES: TokenResolver, E: BinaryFlavor> de::Deserializer<'de>
     for &'r mut RootDeserializer<'b, 'de, 'res, RES, E>
 {
     type Error = DeserializeError;
@@ -209,19 +780,32 @@ impl<'b, 'de, 'r, 'res, RES: TokenResolver, E: Encoding> de::Deserializer<'de>
 
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
-        _fields: &'static [&'static str],
+        name: &'static str,
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        let map = BinaryMap::new(self.config, self.tokens, 0, self.tokens.len());
+        visitor.visit_map(map.with_tag(tagged_struct(name, fields)))
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(BinaryEntries {
+            config: self.config,
+            tokens: self.tokens,
+            tape_idx: 0,
+            end_idx: self.tokens.len(),
+        })
     }
 
     serde::forward_to_deserialize_any! {
         bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        bytes byte_buf option unit unit_struct newtype_struct tuple
         tuple_struct enum ignored_any identifier
     }
 }
@@ -232,6 +816,17 @@ struct BinaryMap<'c, 'a: 'c, 'de: 'a, 'res: 'de, RES: 'a, E> {
     tape_idx: usize,
     end_idx: usize,
     value_ind: usize,
+
+    // When keys are deduplicated, the key indices of each distinct key in
+    // order of first occurrence
+    fields: Option<std::vec::IntoIter<Vec<usize>>>,
+
+    // The value indices of a key with collected values
+    duplicates: Vec<usize>,
+
+    // The key index of the tag of a tagged struct, which is visited first
+    tag_idx: Option<usize>,
+    tag_pending: bool,
 }
 
 impl<'c, 'a, 'de, 'res: 'de, RES, E> BinaryMap<'c, 'a, 'de, 'res, RES, E> {
@@ -241,17 +836,154 @@ impl<'c, 'a, 'de, 'res: 'de, RES, E> BinaryMap<'c, 'a, 'de, 'res, RES, E> {
         tape_idx: usize,
         end_idx: usize,
     ) -> Self {
+        let fields = if config.duplicate_key_strategy != DuplicateKeyStrategy::Preserve {
+            Some(group_keys(tokens, tape_idx, end_idx).into_iter())
+        } else {
+            None
+        };
+
         BinaryMap {
             config,
             tokens,
             tape_idx,
             end_idx,
             value_ind: 0,
+            fields,
+            duplicates: Vec::new(),
+            tag_idx: None,
+            tag_pending: false,
+        }
+    }
+}
+
+impl<'c, 'a, 'de, 'res: 'de, RES: TokenResolver, E: BinaryFlavor>
+    BinaryMap<'c, 'a, 'de, 'res, RES, E>
+{
+    /// Visit the first field with the tag as its key before the other fields
+    fn with_tag(mut self, tag: Option<&str>) -> Self {
+        let tag = match tag {
+            Some(x) => x,
+            None => return self,
+        };
+
+        let mut idx = self.tape_idx;
+        while idx < self.end_idx {
+            if key_is(&self.tokens[idx], self.config, tag) {
+                self.tag_idx = Some(idx);
+                self.tag_pending = true;
+                break;
+            }
+
+            idx = match self.tokens[idx + 1] {
+                BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => x,
+                _ => idx + 1,
+            } + 1;
+        }
+
+        self
+    }
+}
+
+/// The identity of a key for the purposes of detecting duplicates
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum KeyId<'a> {
+    Text(&'a [u8]),
+    Number(u8, u64),
+}
+
+fn key_id<'a>(token: &BinaryToken<'a>, idx: usize) -> KeyId<'a> {
+    match *token {
+        BinaryToken::Text(x) | BinaryToken::Unquoted(x) => KeyId::Text(x.view_data()),
+        BinaryToken::Token(x) => KeyId::Number(0, u64::from(x)),
+        BinaryToken::U32(x) => KeyId::Number(1, u64::from(x)),
+        BinaryToken::U64(x) => KeyId::Number(2, x),
+        BinaryToken::I32(x) => KeyId::Number(3, x as u64),
+        BinaryToken::Bool(x) => KeyId::Number(4, u64::from(x)),
+        BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => KeyId::Number(5, u64::from(x.to_bits())),
+
+        // not a valid key, so it is never considered a duplicate
+        _ => KeyId::Number(6, idx as u64),
+    }
+}
+
+/// Group the key indices of an object by key in order of first occurrence
+fn group_keys(tokens: &[BinaryToken], mut tape_idx: usize, end_idx: usize) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut seen = HashMap::new();
+    while tape_idx < end_idx {
+        let value_ind = tape_idx + 1;
+        let next_key = match tokens.get(value_ind) {
+            Some(BinaryToken::Array(x))
+            | Some(BinaryToken::Object(x))
+            | Some(BinaryToken::HiddenObject(x)) => *x,
+            _ => value_ind,
+        };
+
+        let group = *seen
+            .entry(key_id(&tokens[tape_idx], tape_idx))
+            .or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+        groups[group].push(tape_idx);
+        tape_idx = next_key + 1;
+    }
+
+    groups
+}
+
+fn is_scalar(token: &BinaryToken) -> bool {
+    !matches!(
+        token,
+        BinaryToken::Array(_)
+            | BinaryToken::Object(_)
+            | BinaryToken::HiddenObject(_)
+            | BinaryToken::End(_)
+            | BinaryToken::Rgb(_)
+    )
+}
+
+pub(super) fn key_name<RES: TokenResolver, E: BinaryFlavor>(
+    token: &BinaryToken,
+    config: &BinaryConfig<RES, E>,
+) -> String {
+    match *token {
+        BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+            config.encoding.decode(x.view_data()).into_owned()
+        }
+        BinaryToken::Token(x) => match config.resolver.resolve(x) {
+            Some(name) => name.into_owned(),
+            None => format!("0x{:x}", x),
+        },
+        BinaryToken::U32(x) => x.to_string(),
+        BinaryToken::U64(x) => x.to_string(),
+        BinaryToken::I32(x) => x.to_string(),
+        BinaryToken::Bool(x) => x.to_string(),
+        BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => x.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Returns true if the key is the given name. Unlike comparing against
+/// `key_name`, resolved tokens and text keys are compared without allocating.
+fn key_is<RES: TokenResolver, E: BinaryFlavor>(
+    token: &BinaryToken,
+    config: &BinaryConfig<RES, E>,
+    name: &str,
+) -> bool {
+    match *token {
+        BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+            config.encoding.decode(x.view_data()) == name
         }
+        BinaryToken::Token(x) => match config.resolver.resolve(x) {
+            Some(resolved) => resolved == name,
+            None => key_name(token, config) == name,
+        },
+        _ => key_name(token, config) == name,
     }
 }
 
-impl<'c, 'de, 'a, 'res: 'de, RES: TokenResolver, E: Encoding> MapAccess<'de>
+impl<'c, 'de, 'a, 'res: 'de, RES: TokenResolver, E: BinaryFlavor> MapAccess<'de>
     for BinaryMap<'c, 'a, 'de, 'res, RES, E>
 {
     type Error = DeserializeError;
@@ -260,7 +992,50 @@ impl<'c, 'de, 'a, 'res: 'de, RES: TokenResolver, E: Encoding> MapAccess<'de>
     where
         K: DeserializeSeed<'de>,
     {
-        if self.tape_idx < self.end_idx {
+        if let (true, Some(key_idx)) = (self.tag_pending, self.tag_idx) {
+            self.tag_pending = false;
+            self.value_ind = key_idx + 1;
+            return seed
+                .deserialize(KeyDeserializer {
+                    tape_idx: key_idx,
+                    tokens: self.tokens,
+                    config: self.config,
+                })
+                .map(Some);
+        }
+
+        if let Some(fields) = self.fields.as_mut() {
+            let tag_idx = self.tag_idx;
+            let keys = match fields.find(|x| Some(x[0]) != tag_idx) {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            let key_idx = keys[0];
+            match self.config.duplicate_key_strategy {
+                DuplicateKeyStrategy::Error if keys.len() > 1 => {
+                    let name = key_name(&self.tokens[key_idx], self.config);
+                    return Err(duplicate_key_error(&name));
+                }
+                DuplicateKeyStrategy::Collect
+                    if keys.len() > 1 || is_scalar(&self.tokens[key_idx + 1]) =>
+                {
+                    self.duplicates = keys.iter().map(|x| x + 1).collect();
+                }
+                DuplicateKeyStrategy::TakeFirst => self.value_ind = key_idx + 1,
+                _ => self.value_ind = keys[keys.len() - 1] + 1,
+            }
+
+            return seed
+                .deserialize(KeyDeserializer {
+                    tape_idx: key_idx,
+                    tokens: self.tokens,
+                    config: self.config,
+                })
+                .map(Some);
+        }
+
+        while self.tape_idx < self.end_idx {
             let current_idx = self.tape_idx;
 
             self.value_ind = self.tape_idx + 1;
@@ -270,30 +1045,105 @@ impl<'c, 'de, 'a, 'res: 'de, RES: TokenResolver, E: Encoding> MapAccess<'de>
             };
 
             self.tape_idx = next_key + 1;
-            seed.deserialize(KeyDeserializer {
-                tape_idx: current_idx,
-                tokens: self.tokens,
-                config: self.config,
-            })
-            .map(Some)
-        } else {
-            Ok(None)
+            if Some(current_idx) == self.tag_idx {
+                continue;
+            }
+
+            return seed
+                .deserialize(KeyDeserializer {
+                    tape_idx: current_idx,
+                    tokens: self.tokens,
+                    config: self.config,
+                })
+                .map(Some);
         }
+
+        Ok(None)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
     where
         V: DeserializeSeed<'de>,
     {
+        if !self.duplicates.is_empty() {
+            let (tokens, config) = (self.tokens, self.config);
+            let values = self
+                .duplicates
+                .drain(..)
+                .map(|value_ind| ValueDeserializer {
+                    value_ind,
+                    tokens,
+                    config,
+                })
+                .collect::<Vec<_>>();
+            let key_idx = values[0].value_ind - 1;
+            return seed
+                .deserialize(DuplicatedValues::new(values))
+                .map_err(|e| e.within(&key_name(&tokens[key_idx], config)));
+        }
+
         seed.deserialize(ValueDeserializer {
             value_ind: self.value_ind,
-            tokens: &self.tokens,
+            tokens: self.tokens,
             config: self.config,
         })
+        .map_err(|e| e.within(&key_name(&self.tokens[self.value_ind - 1], self.config)))
     }
 
     fn size_hint(&self) -> Option<usize> {
-        Some(object_len(&self.tokens, self.tape_idx))
+        match &self.fields {
+            Some(fields) => Some(fields.len()),
+            None => Some(object_len(self.tokens, self.tape_idx)),
+        }
+    }
+}
+
+/// Deserializes the fields of an object as a sequence of key value pairs in
+/// the order they appear. Every occurrence of a duplicate key is kept.
+struct BinaryEntries<'c, 'a: 'c, 'de: 'a, 'res: 'de, RES: 'a, E> {
+    config: &'a BinaryConfig<'res, RES, E>,
+    tokens: &'c [BinaryToken<'de>],
+    tape_idx: usize,
+    end_idx: usize,
+}
+
+impl<'c, 'de, 'a, 'res: 'de, RES: TokenResolver, E: BinaryFlavor> SeqAccess<'de>
+    for BinaryEntries<'c, 'a, 'de, 'res, RES, E>
+{
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.tape_idx >= self.end_idx {
+            return Ok(None);
+        }
+
+        let key_idx = self.tape_idx;
+        let value_ind = key_idx + 1;
+        let next_key = match self.tokens[value_ind] {
+            BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => x,
+            _ => value_ind,
+        };
+
+        self.tape_idx = next_key + 1;
+        let key = KeyDeserializer {
+            tape_idx: key_idx,
+            tokens: self.tokens,
+            config: self.config,
+        };
+        let value = ValueDeserializer {
+            value_ind,
+            tokens: self.tokens,
+            config: self.config,
+        };
+        seed.deserialize(&mut KeyValuePair::new(key, value))
+            .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(object_len(self.tokens, self.tape_idx))
     }
 }
 
@@ -303,13 +1153,36 @@ struct KeyDeserializer<'b, 'de: 'b, 'res: 'de, RES, E> {
     tape_idx: usize,
 }
 
-fn visit_key<'c, 'b: 'c, 'de: 'b, 'res: 'de, RES: TokenResolver, E: Encoding, V: Visitor<'de>>(
+fn visit_key<
+    'c,
+    'b: 'c,
+    'de: 'b,
+    'res: 'de,
+    RES: TokenResolver,
+    E: BinaryFlavor,
+    V: Visitor<'de>,
+>(
     tape_idx: usize,
     tokens: &'b [BinaryToken<'de>],
     config: &'b BinaryConfig<'res, RES, E>,
     visitor: V,
 ) -> Result<V::Value, DeserializeError> {
-    match tokens[tape_idx] {
+    visit_token(&tokens[tape_idx], config, visitor)
+}
+
+pub(super) fn visit_token<
+    'b,
+    'de: 'b,
+    'res: 'de,
+    RES: TokenResolver,
+    E: BinaryFlavor,
+    V: Visitor<'de>,
+>(
+    token: &BinaryToken<'de>,
+    config: &'b BinaryConfig<'res, RES, E>,
+    visitor: V,
+) -> Result<V::Value, DeserializeError> {
+    match *token {
         BinaryToken::Object(_)
         | BinaryToken::Array(_)
         | BinaryToken::HiddenObject(_)
@@ -321,28 +1194,207 @@ fn visit_key<'c, 'b: 'c, 'de: 'b, 'res: 'de, RES: TokenResolver, E: Encoding, V:
         BinaryToken::U32(x) => visitor.visit_u32(x),
         BinaryToken::U64(x) => visitor.visit_u64(x),
         BinaryToken::I32(x) => visitor.visit_i32(x),
-        BinaryToken::Text(x) => match config.encoding.decode(x.view_data()) {
-            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
-            Cow::Owned(s) => visitor.visit_string(s),
-        },
+        BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+            match config.encoding.decode(x.view_data()) {
+                Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+                Cow::Owned(s) => {
+                    if let Some(diagnostics) = &config.diagnostics {
+                        diagnostics.check_decode(x.view_data(), &s);
+                    }
+                    visitor.visit_string(s)
+                }
+            }
+        }
         BinaryToken::F32_1(x) => visitor.visit_f32(x),
         BinaryToken::F32_2(x) => visitor.visit_f32(x),
         BinaryToken::Token(s) => match config.resolver.resolve(s) {
-            Some(id) => visitor.visit_borrowed_str(id),
-            None => match config.failed_resolve_strategy {
-                FailedResolveStrategy::Error => Err(DeserializeError {
-                    kind: DeserializeErrorKind::UnknownToken { token_id: s },
-                }),
-                FailedResolveStrategy::Stringify => visitor.visit_string(format!("0x{:x}", s)),
-                FailedResolveStrategy::Ignore => {
-                    visitor.visit_borrowed_str("__internal_identifier_ignore")
-                }
-            },
+            Some(Cow::Borrowed(id)) => visitor.visit_borrowed_str(id),
+            Some(Cow::Owned(id)) => visitor.visit_string(id),
+            None => visit_unknown_token(s, config, visitor),
         },
     }
 }
 
-impl<'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'de>
+#[cold]
+fn visit_unknown_token<'b, 'de: 'b, 'res: 'de, RES, E: BinaryFlavor, V: Visitor<'de>>(
+    token_id: u16,
+    config: &'b BinaryConfig<'res, RES, E>,
+    visitor: V,
+) -> Result<V::Value, DeserializeError> {
+    if config.failed_resolve_strategy != FailedResolveStrategy::Error {
+        if let Some(diagnostics) = &config.diagnostics {
+            diagnostics.push(Diagnostic::UnknownToken { token_id });
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Some(Observer(observer)) = config.observer {
+        observer.unknown_tokens(1);
+    }
+
+    match config.failed_resolve_strategy {
+        FailedResolveStrategy::Error => Err(DeserializeError {
+            kind: DeserializeErrorKind::UnknownToken { token_id },
+        }),
+        FailedResolveStrategy::Stringify => visitor.visit_string(format!("0x{:x}", token_id)),
+        FailedResolveStrategy::Ignore => visitor.visit_borrowed_str("__internal_identifier_ignore"),
+    }
+}
+
+/// Visit a scalar that is requested as a string. When dates are presented as
+/// text, integers that the flavor decodes as a date are formatted as `Y.M.D`.
+pub(super) fn visit_str_token<
+    'b,
+    'de: 'b,
+    'res: 'de,
+    RES: TokenResolver,
+    E: BinaryFlavor,
+    V: Visitor<'de>,
+>(
+    token: &BinaryToken<'de>,
+    config: &'b BinaryConfig<'res, RES, E>,
+    visitor: V,
+) -> Result<V::Value, DeserializeError> {
+    match *token {
+        BinaryToken::I32(x) if config.dates_as_text => match config.encoding.visit_date(x) {
+            Some(date) => visitor.visit_string(date.game_fmt()),
+            None => visitor.visit_i32(x),
+        },
+
+        // Match the text format, where `yes` and `no` are also strings
+        BinaryToken::Bool(x) => visitor.visit_borrowed_str(if x { "yes" } else { "no" }),
+        _ => visit_token(token, config, visitor),
+    }
+}
+
+/// The type that a key is requested as
+#[derive(Debug, Clone, Copy)]
+pub(super) enum KeyType {
+    Bool,
+    Integer(IntegerType),
+    Signed128,
+    Unsigned128,
+    Float,
+}
+
+/// Visit a key that is requested as the given type. Keys that are encoded as
+/// text (eg: `"-5"`) are parsed into the type so that maps can be keyed by
+/// numbers regardless of how the key was written.
+pub(super) fn visit_typed_key<'b, 'de: 'b, 'res: 'de, RES, E, V, Er>(
+    token: &BinaryToken<'de>,
+    config: &'b BinaryConfig<'res, RES, E>,
+    ty: KeyType,
+    visitor: V,
+) -> Result<V::Value, Er>
+where
+    RES: TokenResolver,
+    E: BinaryFlavor,
+    V: Visitor<'de>,
+    Er: de::Error + From<DeserializeError>,
+{
+    let scalar = match (token, ty) {
+        (_, KeyType::Integer(ty)) => {
+            return visit_integer_token(token, config, ty, visitor).map_err(Er::from)
+        }
+        (BinaryToken::Text(x) | BinaryToken::Unquoted(x), _) => *x,
+        _ => return visit_token(token, config, visitor).map_err(Er::from),
+    };
+
+    let err = |e: ScalarError| Er::from(DeserializeError::from(e));
+    match ty {
+        KeyType::Bool => visitor.visit_bool(scalar.to_bool().map_err(err)?),
+        KeyType::Integer(_) => unreachable!(),
+        KeyType::Signed128 => visitor.visit_i128(scalar.to_i128().map_err(err)?),
+        KeyType::Unsigned128 => visitor.visit_u128(scalar.to_u128().map_err(err)?),
+        KeyType::Float => visitor.visit_f64(scalar.to_f64().map_err(err)?),
+    }
+}
+
+/// Visit an integer token (or text that contains an integer) as the integer
+/// type, narrowing it according to the integer overflow policy. Other tokens
+/// are visited as is.
+pub(super) fn visit_integer_token<'b, 'de: 'b, 'res: 'de, RES, E, V>(
+    token: &BinaryToken<'de>,
+    config: &'b BinaryConfig<'res, RES, E>,
+    ty: IntegerType,
+    visitor: V,
+) -> Result<V::Value, DeserializeError>
+where
+    RES: TokenResolver,
+    E: BinaryFlavor,
+    V: Visitor<'de>,
+{
+    let value = match *token {
+        BinaryToken::I32(x) => i128::from(x),
+        BinaryToken::U32(x) => i128::from(x),
+        BinaryToken::U64(x) => i128::from(x),
+        BinaryToken::Text(x) | BinaryToken::Unquoted(x) => x.to_i128()?,
+        _ => return visit_token(token, config, visitor),
+    };
+
+    visit_integer(value, ty, config.integer_overflow, visitor)
+}
+
+/// Implements the deserialization of keys as primitives in terms of
+/// `visit_typed_key`
+macro_rules! typed_key_methods {
+    ($self:ident => $token:expr) => {
+        typed_key_methods!(@impl $self => $token;
+            deserialize_bool: KeyType::Bool,
+            deserialize_i8: KeyType::Integer(IntegerType::I8),
+            deserialize_i16: KeyType::Integer(IntegerType::I16),
+            deserialize_i32: KeyType::Integer(IntegerType::I32),
+            deserialize_i64: KeyType::Integer(IntegerType::I64),
+            deserialize_u8: KeyType::Integer(IntegerType::U8),
+            deserialize_u16: KeyType::Integer(IntegerType::U16),
+            deserialize_u32: KeyType::Integer(IntegerType::U32),
+            deserialize_u64: KeyType::Integer(IntegerType::U64),
+            deserialize_i128: KeyType::Signed128,
+            deserialize_u128: KeyType::Unsigned128,
+            deserialize_f32: KeyType::Float,
+            deserialize_f64: KeyType::Float,
+        );
+    };
+    (@impl $self:ident => $token:expr; $($method:ident: $ty:expr,)*) => {
+        $(
+            fn $method<V>($self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                visit_typed_key($token, $self.config, $ty, visitor)
+            }
+        )*
+    };
+}
+
+pub(super) use typed_key_methods;
+
+/// Implements the deserialization of values as integers in terms of a
+/// `deserialize_integer` method
+macro_rules! integer_methods {
+    () => {
+        integer_methods!(@impl
+            deserialize_i8: I8 deserialize_i16: I16
+            deserialize_i32: I32 deserialize_i64: I64
+            deserialize_u8: U8 deserialize_u16: U16
+            deserialize_u32: U32 deserialize_u64: U64
+        );
+    };
+    (@impl $($method:ident: $ty:ident)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.deserialize_integer(IntegerType::$ty, visitor)
+            }
+        )*
+    };
+}
+
+pub(super) use integer_methods;
+
+impl<'b, 'de, 'res: 'de, RES: TokenResolver, E: BinaryFlavor> de::Deserializer<'de>
     for KeyDeserializer<'b, 'de, 'res, RES, E>
 {
     type Error = DeserializeError;
@@ -354,8 +1406,24 @@ impl<'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'de>
         visit_key(self.tape_idx, self.tokens, self.config, visitor)
     }
 
+    typed_key_methods!(self => &self.tokens[self.tape_idx]);
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visit_str_token(&self.tokens[self.tape_idx], self.config, visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
     serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        char
         bytes byte_buf option unit unit_struct newtype_struct seq tuple
         tuple_struct map enum ignored_any identifier struct
     }
@@ -367,7 +1435,25 @@ struct ValueDeserializer<'c, 'b: 'c, 'de: 'b, 'res: 'de, RES, E> {
     tokens: &'c [BinaryToken<'de>],
 }
 
-impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'de>
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: BinaryFlavor>
+    ValueDeserializer<'c, 'b, 'de, 'res, RES, E>
+{
+    fn deserialize_integer<V>(
+        self,
+        ty: IntegerType,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.tokens[self.value_ind] {
+            token if is_scalar(token) => visit_integer_token(token, self.config, ty, visitor),
+            _ => de::Deserializer::deserialize_any(self, visitor),
+        }
+    }
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: BinaryFlavor> de::Deserializer<'de>
     for ValueDeserializer<'c, 'b, 'de, 'res, RES, E>
 {
     type Error = DeserializeError;
@@ -385,9 +1471,9 @@ impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'
                 idx: idx + 1,
                 end_idx: *x,
             }),
-            BinaryToken::Rgb(x) => visitor.visit_seq(ColorSequence::new(*x)),
+            BinaryToken::Rgb(x) => visit_rgb(*x, self.config.rgb, visitor),
             BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => {
-                visitor.visit_map(BinaryMap::new(&self.config, self.tokens, idx + 1, *x))
+                visitor.visit_map(BinaryMap::new(self.config, self.tokens, idx + 1, *x))
             }
             BinaryToken::End(_x) => Err(DeserializeError {
                 kind: DeserializeErrorKind::Unsupported(String::from(
@@ -411,7 +1497,15 @@ impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'
                 idx: idx + 1,
                 end_idx: *x,
             }),
-            BinaryToken::Rgb(x) => visitor.visit_seq(ColorSequence::new(*x)),
+            BinaryToken::Rgb(x) => visit_rgb(*x, self.config.rgb, visitor),
+            BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => {
+                visitor.visit_seq(BinaryEntries {
+                    config: self.config,
+                    tokens: self.tokens,
+                    tape_idx: idx + 1,
+                    end_idx: *x,
+                })
+            }
             _ => Err(DeserializeError {
                 kind: DeserializeErrorKind::Unsupported(String::from(
                     "encountered non-array when trying to deserialize array",
@@ -420,18 +1514,47 @@ impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'
         }
     }
 
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.tokens[self.value_ind] {
+            token if is_scalar(token) => visit_str_token(token, self.config, visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_some(self)
+        let idx = self.value_ind;
+        match self.tokens[idx] {
+            BinaryToken::Array(end) | BinaryToken::Object(end) | BinaryToken::HiddenObject(end)
+                if self.config.empty_block_as_none && end == idx + 1 =>
+            {
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        // A color deserialized as a tuple of its channels skips the header
+        match &self.tokens[self.value_ind] {
+            BinaryToken::Rgb(x) if len != 2 => visit_rgb(*x, RgbRepresentation::Array, visitor),
+            _ => self.deserialize_seq(visitor),
+        }
     }
 
     fn deserialize_tuple_struct<V>(
@@ -466,14 +1589,21 @@ impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'
 
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
-        _fields: &'static [&'static str],
+        name: &'static str,
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        let idx = self.value_ind;
+        match (&self.tokens[idx], tagged_struct(name, fields)) {
+            (BinaryToken::Object(x) | BinaryToken::HiddenObject(x), Some(tag)) => {
+                let map = BinaryMap::new(self.config, self.tokens, idx + 1, *x);
+                visitor.visit_map(map.with_tag(Some(tag)))
+            }
+            _ => self.deserialize_map(visitor),
+        }
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -483,13 +1613,14 @@ impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'
         let idx = self.value_ind;
         match &self.tokens[idx] {
             BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => {
-                visitor.visit_map(BinaryMap::new(&self.config, self.tokens, idx + 1, *x))
+                visitor.visit_map(BinaryMap::new(self.config, self.tokens, idx + 1, *x))
             }
 
             // An array is supported if it is empty
             BinaryToken::Array(x) => {
-                visitor.visit_map(BinaryMap::new(&self.config, self.tokens, idx + 1, *x))
+                visitor.visit_map(BinaryMap::new(self.config, self.tokens, idx + 1, *x))
             }
+            BinaryToken::Rgb(x) => visit_rgb(*x, self.config.rgb, visitor),
             _ => Err(DeserializeError {
                 kind: DeserializeErrorKind::Unsupported(String::from(
                     "encountered unexpected token when trying to deserialize map",
@@ -498,8 +1629,10 @@ impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'
         }
     }
 
+    integer_methods!();
+
     serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bool i128 u128 f32 f64 char
         bytes byte_buf unit unit_struct
         enum identifier
     }
@@ -513,7 +1646,25 @@ struct BinarySequence<'b, 'de: 'b, 'res: 'de, RES, E> {
     end_idx: usize,
 }
 
-impl<'b, 'de, 'r, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'de>
+impl<'b, 'de, 'res: 'de, RES: TokenResolver, E: BinaryFlavor>
+    BinarySequence<'b, 'de, 'res, RES, E>
+{
+    fn deserialize_integer<V>(
+        &mut self,
+        ty: IntegerType,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.tokens[self.de_idx] {
+            token if is_scalar(token) => visit_integer_token(token, self.config, ty, visitor),
+            _ => de::Deserializer::deserialize_any(self, visitor),
+        }
+    }
+}
+
+impl<'b, 'de, 'r, 'res: 'de, RES: TokenResolver, E: BinaryFlavor> de::Deserializer<'de>
     for &'r mut BinarySequence<'b, 'de, 'res, RES, E>
 {
     type Error = DeserializeError;
@@ -542,14 +1693,34 @@ impl<'b, 'de, 'r, 'res: 'de, RES: TokenResolver, E: Encoding> de::Deserializer<'
         }
     }
 
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match (&self.tokens[self.de_idx], tagged_struct(name, fields)) {
+            (BinaryToken::Object(x) | BinaryToken::HiddenObject(x), Some(tag)) => {
+                let map = BinaryMap::new(self.config, self.tokens, self.de_idx + 1, *x);
+                visitor.visit_map(map.with_tag(Some(tag)))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    integer_methods!();
+
     serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bool i128 u128 f32 f64 char str string
         bytes byte_buf option unit unit_struct newtype_struct tuple
-        tuple_struct map enum ignored_any identifier struct seq
+        tuple_struct map enum ignored_any identifier seq
     }
 }
 
-impl<'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> SeqAccess<'de>
+impl<'b, 'de, 'res: 'de, RES: TokenResolver, E: BinaryFlavor> SeqAccess<'de>
     for BinarySequence<'b, 'de, 'res, RES, E>
 {
     type Error = DeserializeError;
@@ -573,13 +1744,15 @@ impl<'b, 'de, 'res: 'de, RES: TokenResolver, E: Encoding> SeqAccess<'de>
     }
 
     fn size_hint(&self) -> Option<usize> {
-        Some(array_len(&self.tokens, self.idx))
+        Some(array_len(self.tokens, self.idx))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ErrorKind;
+    use crate::TextDeserializer;
     use jomini_derive::JominiDeserialize;
     use serde::{de::Deserializer, Deserialize};
     use std::collections::HashMap;
@@ -1129,6 +2302,139 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_duplicate_key_strategies() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x14, 0x00, 0x59, 0x00, 0x00, 0x00, 0x82, 0x2d, 0x01, 0x00,
+            0x14, 0x00, 0x5a, 0x00, 0x00, 0x00, 0xe3, 0x28, 0x01, 0x00, 0x0c, 0x00, 0x0b, 0x00,
+            0x00, 0x00,
+        ];
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct Single {
+            field1: u32,
+            second: i32,
+        }
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct Collected {
+            field1: Vec<u32>,
+            second: Vec<i32>,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "field1");
+        map.insert(0x28e3, "second");
+
+        let mut builder = BinaryDeserializer::eu4_builder();
+        builder.on_duplicate_key(DuplicateKeyStrategy::TakeFirst);
+        let actual: Single = builder.from_slice(&data[..], &map).unwrap();
+        assert_eq!(actual.field1, 89);
+
+        builder.on_duplicate_key(DuplicateKeyStrategy::TakeLast);
+        let actual: Single = builder.from_slice(&data[..], &map).unwrap();
+        assert_eq!(actual.field1, 90);
+        let actual: Single = builder.from_slice_ondemand(&data[..], &map).unwrap();
+        assert_eq!(actual.field1, 90);
+
+        builder.on_duplicate_key(DuplicateKeyStrategy::Error);
+        let err = builder
+            .from_slice::<_, Single>(&data[..], &map)
+            .unwrap_err();
+        assert!(err.to_string().contains("duplicate field `field1`"));
+
+        builder.on_duplicate_key(DuplicateKeyStrategy::Collect);
+        let actual: Collected = builder.from_slice(&data[..], &map).unwrap();
+        assert_eq!(
+            actual,
+            Collected {
+                field1: vec![89, 90],
+                second: vec![11],
+            }
+        );
+    }
+
+    #[test]
+    fn test_deserialize_typed_keys() {
+        use crate::common::Date;
+
+        // "1"={ "-2"=3 } 56379360=4 (1436.1.1) "1445.1.1"=5
+        let data = [
+            0x0f, 0x00, 0x01, 0x00, 0x31, 0x01, 0x00, 0x03, 0x00, 0x0f, 0x00, 0x02, 0x00, 0x2d,
+            0x32, 0x01, 0x00, 0x0c, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x0c, 0x00, 0xe0,
+            0x47, 0x5c, 0x03, 0x01, 0x00, 0x0c, 0x00, 0x04, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x08,
+            0x00, 0x31, 0x34, 0x34, 0x35, 0x2e, 0x31, 0x2e, 0x31, 0x01, 0x00, 0x0c, 0x00, 0x05,
+            0x00, 0x00, 0x00,
+        ];
+
+        let map: HashMap<u16, String> = HashMap::new();
+        let actual: HashMap<u32, HashMap<i32, u32>> = from_slice(&data[..25], &map).unwrap();
+        assert_eq!(actual[&1][&-2], 3);
+
+        let actual: HashMap<u128, HashMap<i128, u32>> = from_slice(&data[..25], &map).unwrap();
+        assert_eq!(actual[&1][&-2], 3);
+
+        let actual: HashMap<Date, u32> = from_slice(&data[25..], &map).unwrap();
+        let date = Date::parse_from_str("1436.1.1").unwrap();
+        assert_eq!(actual[&date], 4);
+        let date = Date::parse_from_str("1445.1.1").unwrap();
+        assert_eq!(actual[&date], 5);
+
+        let actual: HashMap<u32, HashMap<i32, u32>> = BinaryDeserializer::eu4_builder()
+            .from_slice_ondemand(&data[..25], &map)
+            .unwrap();
+        assert_eq!(actual[&1][&-2], 3);
+
+        let actual: HashMap<Date, u32> = BinaryDeserializer::eu4_builder()
+            .from_slice_ondemand(&data[25..], &map)
+            .unwrap();
+        assert_eq!(actual[&date], 5);
+    }
+
+    #[test]
+    fn test_deserialize_ordered_entries() {
+        // field1={ b=1 a=2 b=3 } second=4
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0f, 0x00, 0x01, 0x00, 0x62, 0x01, 0x00, 0x0c,
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x01, 0x00, 0x61, 0x01, 0x00, 0x0c, 0x00,
+            0x02, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x01, 0x00, 0x62, 0x01, 0x00, 0x0c, 0x00, 0x03,
+            0x00, 0x00, 0x00, 0x04, 0x00, 0xe3, 0x28, 0x01, 0x00, 0x0c, 0x00, 0x04, 0x00, 0x00,
+            0x00,
+        ];
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct MyStruct {
+            field1: Vec<(String, i32)>,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "field1");
+        map.insert(0x28e3, "second");
+
+        let expected = vec![
+            (String::from("b"), 1),
+            (String::from("a"), 2),
+            (String::from("b"), 3),
+        ];
+
+        let actual: MyStruct = from_slice(&data[..], &map).unwrap();
+        assert_eq!(actual.field1, expected);
+
+        let actual: MyStruct = BinaryDeserializer::eu4_builder()
+            .from_slice_ondemand(&data[..], &map)
+            .unwrap();
+        assert_eq!(actual.field1, expected);
+
+        let root: Vec<(&str, serde::de::IgnoredAny)> = from_slice(&data[..], &map).unwrap();
+        let keys: Vec<_> = root.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec!["field1", "second"]);
+
+        let root: Vec<(&str,)> = BinaryDeserializer::eu4_builder()
+            .from_slice_ondemand(&data[..], &map)
+            .unwrap();
+        assert_eq!(root, vec![("field1",), ("second",)]);
+    }
+
     #[test]
     fn test_error_unresolved_field() {
         let data = [
@@ -1144,7 +2450,12 @@ mod tests {
         let actual: Result<MyStruct, _> = BinaryDeserializer::eu4_builder()
             .on_failed_resolve(FailedResolveStrategy::Error)
             .from_slice(&data[..], &map);
-        assert!(actual.is_err());
+        let err = actual.unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::UnknownToken { token_id: 0x2d82 }
+        ));
+        assert_eq!(err.token_id(), Some(0x2d82));
     }
 
     #[test]
@@ -1163,6 +2474,114 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_owned_token_resolver() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47,
+        ];
+
+        struct OwnedResolver;
+
+        impl TokenResolver for OwnedResolver {
+            fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+                Some(Cow::Owned(format!("field_{:x}", token)))
+            }
+        }
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct MyStruct {
+            field_2d82: String,
+        }
+
+        let expected = MyStruct {
+            field_2d82: String::from("ENG"),
+        };
+
+        let actual: MyStruct = from_slice(&data[..], &OwnedResolver).unwrap();
+        assert_eq!(actual, expected);
+
+        let actual: MyStruct = BinaryDeserializer::eu4_builder()
+            .from_slice_ondemand(&data[..], &OwnedResolver)
+            .unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_lossy_truncated_string() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x83, 0x2d, 0x01, 0x00,
+            0x0f, 0x00, 0xff, 0x00, 0x45, 0x4e, 0x47,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            id: i32,
+            name: String,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "id");
+        map.insert(0x2d83, "name");
+
+        assert!(from_slice::<_, MyStruct>(&data[..], &map).is_err());
+
+        let diagnostics = Diagnostics::new();
+        let mut builder = BinaryDeserializer::eu4_builder();
+        builder.lossy(true).diagnostics(&diagnostics);
+
+        let expected = MyStruct {
+            id: 1,
+            name: String::from("ENG"),
+        };
+        let actual: MyStruct = builder.from_slice(&data[..], &map).unwrap();
+        assert_eq!(actual, expected);
+
+        let actual: MyStruct = builder.from_slice_ondemand(&data[..], &map).unwrap();
+        assert_eq!(actual, expected);
+
+        let report = diagnostics.take();
+        assert_eq!(report.len(), 2);
+        assert!(matches!(
+            report[0],
+            Diagnostic::TruncatedString {
+                offset: 16,
+                length: 255,
+                available: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unresolved_field_diagnostics() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x83, 0x2d, 0x01,
+            0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00,
+            0x03, 0x00, 0x45, 0x4e, 0x47,
+        ];
+
+        let map: HashMap<u16, String> = HashMap::new();
+        let diagnostics = Diagnostics::new();
+        let mut builder = BinaryDeserializer::eu4_builder();
+        builder
+            .on_failed_resolve(FailedResolveStrategy::Stringify)
+            .diagnostics(&diagnostics);
+
+        let _: HashMap<String, &str> = builder.from_slice(&data[..], &map).unwrap();
+        let report = diagnostics.take();
+        assert_eq!(report.len(), 2);
+        assert!(matches!(
+            report[0],
+            Diagnostic::UnknownToken { token_id: 0x2d82 }
+        ));
+        assert!(matches!(
+            report[1],
+            Diagnostic::UnknownToken { token_id: 0x2d83 }
+        ));
+
+        let _: HashMap<String, &str> = builder.from_slice_ondemand(&data[..], &map).unwrap();
+        assert_eq!(diagnostics.len(), 2);
+    }
+
     #[test]
     fn test_optional_field() {
         let data = [
@@ -1431,7 +2850,7 @@ mod tests {
         let mut map = HashMap::new();
         map.insert(0x337f, "campaign_id");
 
-        let actual: Meta = from_slice(&data[..], &map).unwrap();
+        let actual: Meta = from_slice(data, &map).unwrap();
         assert_eq!(
             actual,
             Meta {
@@ -1508,4 +2927,228 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_dyn_flavor() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x83, 0x2d, 0x01,
+            0x00, 0x0d, 0x00, 0x00, 0x00, 0x80, 0x3f,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "field1");
+        map.insert(0x2d83, "field2");
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct MyStruct {
+            field1: String,
+            field2: f32,
+        }
+
+        let flavors: Vec<(Box<dyn BinaryFlavor>, f32)> = vec![
+            (Box::new(Eu4Flavor::new()), 1_065_353.2),
+            (Box::new(Ck3Flavor::new()), 1.0),
+        ];
+
+        for (flavor, expected) in flavors {
+            let flavor: &dyn BinaryFlavor = flavor.as_ref();
+            let builder = BinaryDeserializer::builder_flavor(flavor);
+            let actual: MyStruct = builder.from_slice(&data[..], &map).unwrap();
+            assert_eq!(actual.field1, "ENG");
+            assert_eq!(actual.field2, expected);
+
+            let actual: MyStruct = builder.from_slice_ondemand(&data[..], &map).unwrap();
+            assert_eq!(actual.field2, expected);
+        }
+    }
+
+    #[test]
+    fn test_bool_as_string() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x00,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Flags {
+            human: String,
+            ai: bool,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, String::from("human"));
+        map.insert(0x2d83, String::from("ai"));
+
+        let expected = Flags {
+            human: String::from("yes"),
+            ai: false,
+        };
+        let actual: Flags = from_slice(&data[..], &map).unwrap();
+        assert_eq!(actual, expected);
+
+        let actual: Flags = BinaryDeserializer::eu4_builder()
+            .from_slice_ondemand(&data[..], &map)
+            .unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_dates_as_text() {
+        // date=1444.11.11 id=56379360 history={ 1436.1.1=5 }
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x10, 0x77, 0x5d, 0x03, 0x83, 0x2d, 0x01, 0x00,
+            0x0c, 0x00, 0xe0, 0x47, 0x5c, 0x03, 0x84, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00,
+            0xe0, 0x47, 0x5c, 0x03, 0x01, 0x00, 0x0c, 0x00, 0x05, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "date");
+        map.insert(0x2d83, "id");
+        map.insert(0x2d84, "history");
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct MyStruct {
+            date: String,
+            id: i32,
+            history: HashMap<String, i32>,
+        }
+
+        let mut builder = BinaryDeserializer::eu4_builder();
+        assert!(builder.from_slice::<_, MyStruct>(&data[..], &map).is_err());
+
+        builder.dates_as_text(true);
+        let expected = MyStruct {
+            date: String::from("1444.11.11"),
+            id: 56379360,
+            history: vec![(String::from("1436.1.1"), 5)].into_iter().collect(),
+        };
+
+        let actual: MyStruct = builder.from_slice(&data[..], &map).unwrap();
+        assert_eq!(actual, expected);
+
+        let actual: MyStruct = builder.from_slice_ondemand(&data[..], &map).unwrap();
+        assert_eq!(actual, expected);
+
+        let actual: MyStruct = TextDeserializer::from_windows1252_slice(
+            b"date=1444.11.11 id=56379360 history={ 1436.1.1=5 }",
+        )
+        .unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_unified_builder() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x83, 0x2d, 0x01,
+            0x00, 0x0d, 0x00, 0x00, 0x00, 0x80, 0x3f,
+        ];
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct MyStruct {
+            field1: String,
+            field2: f32,
+        }
+
+        let resolver = |token: u16| match token {
+            0x2d82 => Some("field1"),
+            0x2d83 => Some("field2"),
+            _ => None,
+        };
+        let resolver = resolver as fn(u16) -> Option<&'static str>;
+
+        let actual: MyStruct = BinaryDeserializer::builder()
+            .resolver(&resolver)
+            .deserialize_slice(&data[..])
+            .unwrap();
+        assert_eq!(actual.field2, 1_065_353.2);
+
+        let mut builder = BinaryDeserializer::builder().flavor(Ck3Flavor::new());
+        builder.on_failed_resolve(FailedResolveStrategy::Error);
+        let deserializer = builder.resolver(&resolver);
+        let actual: MyStruct = deserializer.deserialize_slice(&data[..]).unwrap();
+        assert_eq!(actual.field2, 1.0);
+
+        let actual: MyStruct = deserializer.deserialize_slice_ondemand(&data[..]).unwrap();
+        assert_eq!(actual.field1, "ENG");
+
+        let tape = BinaryTape::from_ck3(&data[..]).unwrap();
+        let actual: MyStruct = deserializer.deserialize_tape(&tape).unwrap();
+        assert_eq!(actual.field2, 1.0);
+
+        let actual: MyStruct = BinaryDeserializer::from_ck3(&data[..], &resolver).unwrap();
+        assert_eq!(actual.field2, 1.0);
+    }
+
+    #[test]
+    fn test_rgb_representation() {
+        let data = [
+            0x3a, 0x05, 0x01, 0x00, 0x43, 0x02, 0x03, 0x00, 0x14, 0x00, 0x6e, 0x00, 0x00, 0x00,
+            0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x053a, "color");
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Channels {
+            r: u8,
+            g: u8,
+            b: u8,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct MyStruct<T> {
+            color: T,
+        }
+
+        fn check<T>(data: &[u8], map: &HashMap<u16, &str>, rgb: RgbRepresentation, expected: T)
+        where
+            T: for<'de> Deserialize<'de> + PartialEq + fmt::Debug,
+        {
+            let mut builder = BinaryDeserializer::eu4_builder();
+            builder.rgb_representation(rgb);
+            let actual: MyStruct<T> = builder.from_slice(data, map).unwrap();
+            assert_eq!(actual.color, expected);
+            let actual: MyStruct<T> = builder.from_slice_ondemand(data, map).unwrap();
+            assert_eq!(actual.color, expected);
+        }
+
+        check(&data, &map, RgbRepresentation::Array, [110u8, 27, 27]);
+        check(
+            &data,
+            &map,
+            RgbRepresentation::Array,
+            (110u32, 27u32, 27u32),
+        );
+        check(
+            &data,
+            &map,
+            RgbRepresentation::Object,
+            Channels {
+                r: 110,
+                g: 27,
+                b: 27,
+            },
+        );
+        check(&data, &map, RgbRepresentation::Hex, String::from("#6e1b1b"));
+        check(
+            &data,
+            &map,
+            RgbRepresentation::Header,
+            (String::from("rgb"), [110u8, 27, 27]),
+        );
+
+        // Types that want three channels receive them regardless of representation
+        check(
+            &data,
+            &map,
+            RgbRepresentation::Header,
+            crate::Rgb {
+                r: 110,
+                g: 27,
+                b: 27,
+            },
+        );
+        check(&data, &map, RgbRepresentation::Header, [110u8, 27, 27]);
+        check(&data, &map, RgbRepresentation::Hex, (110u32, 27u32, 27u32));
+    }
 }
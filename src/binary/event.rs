@@ -0,0 +1,272 @@
+use super::tape::{BOOL, END, EQUAL, F32_1, F32_2, I32, OPEN, RGB, STRING_1, STRING_2, U32, U64};
+use crate::util::{le_i32, le_u16, le_u32, le_u64};
+use crate::{BinaryFlavor, Error, Rgb, Scalar};
+
+/// A lexical element of the binary format that is produced when data is read
+/// incrementally instead of parsed into a tape all at once.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryEvent {
+    /// An open token
+    Open,
+
+    /// An end token
+    Close,
+
+    /// An equal token that separates a key and value
+    Equal,
+
+    /// A binary boolean
+    Bool(bool),
+
+    /// A binary unsigned 32bit integer
+    U32(u32),
+
+    /// A binary unsigned 64bit integer
+    U64(u64),
+
+    /// A binary signed 32bit integer
+    I32(i32),
+
+    /// A binary encoded string that was quoted in the text format
+    Text(Vec<u8>),
+
+    /// The first binary encoding for representing a rational number
+    F32_1(f32),
+
+    /// The second binary encoding for representing a rational number
+    F32_2(f32),
+
+    /// A 16bit token that can be resolved to a field name
+    Token(u16),
+
+    /// An encoded rgb value
+    Rgb(Rgb),
+
+    /// A binary encoded string that was unquoted in the text format
+    Unquoted(Vec<u8>),
+}
+
+/// A lexical element of the binary format that borrows from the data. These
+/// are given to the callback of `BinaryTapeParser::parse_with`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryLexeme<'a> {
+    /// An open token
+    Open,
+
+    /// An end token
+    Close,
+
+    /// An equal token that separates a key and value
+    Equal,
+
+    /// A binary boolean
+    Bool(bool),
+
+    /// A binary unsigned 32bit integer
+    U32(u32),
+
+    /// A binary unsigned 64bit integer
+    U64(u64),
+
+    /// A binary signed 32bit integer
+    I32(i32),
+
+    /// A binary encoded string that was quoted in the text format
+    Text(Scalar<'a>),
+
+    /// The first binary encoding for representing a rational number
+    F32_1(f32),
+
+    /// The second binary encoding for representing a rational number
+    F32_2(f32),
+
+    /// A 16bit token that can be resolved to a field name
+    Token(u16),
+
+    /// An encoded rgb value
+    Rgb(Rgb),
+
+    /// A binary encoded string that was unquoted in the text format
+    Unquoted(Scalar<'a>),
+}
+
+impl<'a> From<BinaryLexeme<'a>> for BinaryEvent {
+    fn from(lexeme: BinaryLexeme<'a>) -> Self {
+        match lexeme {
+            BinaryLexeme::Open => BinaryEvent::Open,
+            BinaryLexeme::Close => BinaryEvent::Close,
+            BinaryLexeme::Equal => BinaryEvent::Equal,
+            BinaryLexeme::Bool(x) => BinaryEvent::Bool(x),
+            BinaryLexeme::U32(x) => BinaryEvent::U32(x),
+            BinaryLexeme::U64(x) => BinaryEvent::U64(x),
+            BinaryLexeme::I32(x) => BinaryEvent::I32(x),
+            BinaryLexeme::Text(x) => BinaryEvent::Text(x.view_data().to_vec()),
+            BinaryLexeme::F32_1(x) => BinaryEvent::F32_1(x),
+            BinaryLexeme::F32_2(x) => BinaryEvent::F32_2(x),
+            BinaryLexeme::Token(x) => BinaryEvent::Token(x),
+            BinaryLexeme::Rgb(x) => BinaryEvent::Rgb(x),
+            BinaryLexeme::Unquoted(x) => BinaryEvent::Unquoted(x.view_data().to_vec()),
+        }
+    }
+}
+
+/// An incremental parser of the binary format that is driven by pushing
+/// chunks of data into it. See `TextPushParser`.
+///
+/// ```
+/// use jomini::{BinaryEvent, BinaryPushParser, Eu4Flavor};
+///
+/// let mut parser = BinaryPushParser::new(Eu4Flavor::new());
+/// let events = parser.feed(&[0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x59]);
+/// assert_eq!(events, vec![BinaryEvent::Token(0x2d82), BinaryEvent::Equal]);
+///
+/// let events = parser.feed(&[0x00, 0x00, 0x00]);
+/// assert_eq!(events, vec![BinaryEvent::I32(89)]);
+/// parser.finish()?;
+/// # Ok::<(), jomini::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct BinaryPushParser<F> {
+    buf: Vec<u8>,
+    flavor: F,
+}
+
+impl<F> BinaryPushParser<F>
+where
+    F: BinaryFlavor,
+{
+    /// Create a binary push parser with the given flavor
+    pub fn new(flavor: F) -> Self {
+        BinaryPushParser {
+            buf: Vec::new(),
+            flavor,
+        }
+    }
+
+    /// Append a chunk of data and return the events that it completes
+    pub fn feed(&mut self, data: &[u8]) -> Vec<BinaryEvent> {
+        let mut events = Vec::new();
+        self.feed_into(data, &mut events);
+        events
+    }
+
+    /// Signal that there is no more data, which is an error if the data
+    /// ended partway through an event
+    pub fn finish(self) -> Result<(), Error> {
+        self.check_finished()
+    }
+
+    /// The number of bytes held while waiting for the rest of an event
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(crate) fn feed_into<E>(&mut self, data: &[u8], events: &mut E)
+    where
+        E: Extend<BinaryEvent>,
+    {
+        self.buf.extend_from_slice(data);
+        let mut pos = 0;
+        while let Some((len, lexeme)) = next_lexeme(&self.buf[pos..], &self.flavor) {
+            pos += len;
+            events.extend(Some(BinaryEvent::from(lexeme)));
+        }
+
+        self.buf.drain(..pos);
+    }
+
+    pub(crate) fn check_finished(&self) -> Result<(), Error> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::eof("the rest of a binary token"))
+        }
+    }
+}
+
+/// Returns the number of bytes that make up the next lexeme or None if the
+/// lexeme isn't complete
+pub(crate) fn next_lexeme<'a, F: BinaryFlavor>(
+    d: &'a [u8],
+    flavor: &F,
+) -> Option<(usize, BinaryLexeme<'a>)> {
+    let token_id = le_u16(d.get(..2)?);
+    let d = &d[2..];
+    let (len, lexeme) = match token_id {
+        OPEN => (0, BinaryLexeme::Open),
+        END => (0, BinaryLexeme::Close),
+        EQUAL => (0, BinaryLexeme::Equal),
+        U32 => (4, BinaryLexeme::U32(le_u32(d.get(..4)?))),
+        U64 => (8, BinaryLexeme::U64(le_u64(d.get(..8)?))),
+        I32 => (4, BinaryLexeme::I32(le_i32(d.get(..4)?))),
+        BOOL => (1, BinaryLexeme::Bool(*d.first()? != 0)),
+        STRING_1 => {
+            let text_len = usize::from(le_u16(d.get(..2)?));
+            let text = d.get(2..2 + text_len)?;
+            (2 + text_len, BinaryLexeme::Text(Scalar::new(text)))
+        }
+        STRING_2 => {
+            let text_len = usize::from(le_u16(d.get(..2)?));
+            let text = d.get(2..2 + text_len)?;
+            (2 + text_len, BinaryLexeme::Unquoted(Scalar::new(text)))
+        }
+        F32_1 => (4, BinaryLexeme::F32_1(flavor.visit_f32_1(d.get(..4)?))),
+        F32_2 => (8, BinaryLexeme::F32_2(flavor.visit_f32_2(d.get(..8)?))),
+        RGB => {
+            // u16 `{` + (u16 + u32) * 3 + u16 `}`
+            let x = d.get(..22)?;
+            let rgb = Rgb {
+                r: le_u32(&x[4..]),
+                g: le_u32(&x[10..]),
+                b: le_u32(&x[16..]),
+            };
+            (22, BinaryLexeme::Rgb(rgb))
+        }
+        x => (0, BinaryLexeme::Token(x)),
+    };
+
+    Some((2 + len, lexeme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Eu4Flavor;
+
+    #[test]
+    fn test_binary_events() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x0c,
+            0x00, 0x59, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x01, 0x04, 0x00,
+        ];
+
+        let expected = vec![
+            BinaryEvent::Token(0x2d82),
+            BinaryEvent::Equal,
+            BinaryEvent::Open,
+            BinaryEvent::Text(b"ENG".to_vec()),
+            BinaryEvent::I32(89),
+            BinaryEvent::Bool(true),
+            BinaryEvent::Close,
+        ];
+
+        for chunk_size in 1..=data.len() {
+            let mut parser = BinaryPushParser::new(Eu4Flavor::new());
+            let mut events = Vec::new();
+            for chunk in data.chunks(chunk_size) {
+                events.extend(parser.feed(chunk));
+            }
+            parser.finish().unwrap();
+            assert_eq!(events, expected);
+        }
+    }
+
+    #[test]
+    fn test_binary_events_incomplete() {
+        let mut parser = BinaryPushParser::new(Eu4Flavor::new());
+        let events = parser.feed(&[0x82, 0x2d, 0x0c, 0x00, 0x59]);
+        assert_eq!(events, vec![BinaryEvent::Token(0x2d82)]);
+        assert_eq!(parser.pending(), 3);
+        assert!(parser.finish().unwrap_err().is_eof());
+    }
+}
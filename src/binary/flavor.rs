@@ -1,15 +1,49 @@
+use crate::common::Date;
 use crate::{util::le_f32, util::le_i32, Encoding, Utf8Encoding, Windows1252Encoding};
 
 /// Trait customizing decoding values from binary data
-pub trait BinaryFlavor: Sized + Encoding {
+///
+/// The trait is object safe. Applications that support multiple games can
+/// choose a flavor at runtime and pass a `&dyn BinaryFlavor` or
+/// `Box<dyn BinaryFlavor>` where a flavor is expected, so that parsers and
+/// deserializers are compiled once instead of once per game.
+pub trait BinaryFlavor: Encoding {
     /// Decode a f32 from 4 bytes of data
     fn visit_f32_1(&self, data: &[u8]) -> f32;
 
     /// Decode a f32 from 8 bytes of data
     fn visit_f32_2(&self, data: &[u8]) -> f32;
+
+    /// The number of decimal places the game writes in plaintext for floats
+    /// that are encoded in 4 bytes
+    fn f32_1_decimals(&self) -> usize {
+        3
+    }
+
+    /// The number of decimal places the game writes in plaintext for floats
+    /// that are encoded in 8 bytes
+    fn f32_2_decimals(&self) -> usize {
+        5
+    }
+
+    /// Whether the game quotes a binary string when it is written in
+    /// plaintext. Strings that can't be written without quotes are always
+    /// quoted.
+    fn quote_string(&self, is_key: bool) -> bool {
+        !is_key
+    }
+
+    /// Decode a date from a binary integer, returning `None` if the integer
+    /// does not represent a date. Games differ in the epoch of their binary
+    /// dates and in the earliest year that they use, which flavors express
+    /// through [`Date::from_binary_epoch`]. By default, the integer must pass
+    /// [`Date::from_binary_heuristic`].
+    fn visit_date(&self, data: i32) -> Option<Date> {
+        Date::from_binary_heuristic(data)
+    }
 }
 
-impl<T: BinaryFlavor> BinaryFlavor for &'_ T {
+impl<T: BinaryFlavor + ?Sized> BinaryFlavor for &'_ T {
     fn visit_f32_1(&self, data: &[u8]) -> f32 {
         (**self).visit_f32_1(data)
     }
@@ -17,6 +51,48 @@ impl<T: BinaryFlavor> BinaryFlavor for &'_ T {
     fn visit_f32_2(&self, data: &[u8]) -> f32 {
         (**self).visit_f32_2(data)
     }
+
+    fn f32_1_decimals(&self) -> usize {
+        (**self).f32_1_decimals()
+    }
+
+    fn f32_2_decimals(&self) -> usize {
+        (**self).f32_2_decimals()
+    }
+
+    fn quote_string(&self, is_key: bool) -> bool {
+        (**self).quote_string(is_key)
+    }
+
+    fn visit_date(&self, data: i32) -> Option<Date> {
+        (**self).visit_date(data)
+    }
+}
+
+impl<T: BinaryFlavor + ?Sized> BinaryFlavor for Box<T> {
+    fn visit_f32_1(&self, data: &[u8]) -> f32 {
+        (**self).visit_f32_1(data)
+    }
+
+    fn visit_f32_2(&self, data: &[u8]) -> f32 {
+        (**self).visit_f32_2(data)
+    }
+
+    fn f32_1_decimals(&self) -> usize {
+        (**self).f32_1_decimals()
+    }
+
+    fn f32_2_decimals(&self) -> usize {
+        (**self).f32_2_decimals()
+    }
+
+    fn quote_string(&self, is_key: bool) -> bool {
+        (**self).quote_string(is_key)
+    }
+
+    fn visit_date(&self, data: i32) -> Option<Date> {
+        (**self).visit_date(data)
+    }
 }
 
 /// The eu4 binary flavor
@@ -75,4 +151,42 @@ impl BinaryFlavor for Ck3Flavor {
     fn visit_f32_2(&self, data: &[u8]) -> f32 {
         (le_i32(data) as f32) / 1000.0
     }
+
+    fn f32_1_decimals(&self) -> usize {
+        5
+    }
+
+    fn f32_2_decimals(&self) -> usize {
+        3
+    }
+
+    fn visit_date(&self, data: i32) -> Option<Date> {
+        // CK3 has dates well before year 0 (eg: the birth dates of
+        // historical characters), so the full range of years is accepted
+        if data % 24 != 0 {
+            return None;
+        }
+
+        Date::from_binary_epoch(data, 5000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visit_date_range() {
+        let date = Date::new(-500, 3, 1).unwrap();
+        let data = date.to_binary().unwrap();
+        assert_eq!(Eu4Flavor::new().visit_date(data), None);
+        assert_eq!(Ck3Flavor::new().visit_date(data), Some(date));
+
+        let date = Date::new(1444, 11, 11).unwrap();
+        let data = date.to_binary().unwrap();
+        assert_eq!(Eu4Flavor::new().visit_date(data), Some(date));
+        assert_eq!(Ck3Flavor::new().visit_date(data), Some(date));
+        assert_eq!(Eu4Flavor::new().visit_date(data + 1), None);
+        assert_eq!(Ck3Flavor::new().visit_date(data + 1), None);
+    }
 }
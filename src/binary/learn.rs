@@ -0,0 +1,325 @@
+use crate::{BinaryFlavor, BinaryTape, Error, Scalar, TextTape, TextToken, TokenResolver};
+use std::collections::HashMap;
+
+/// A proposed name for an unknown token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenCandidate {
+    /// The token id
+    pub id: u16,
+
+    /// The most likely name of the token
+    pub name: String,
+
+    /// The number of times the token lined up with this name
+    pub votes: usize,
+
+    /// The number of times the token lined up with any name. When this is
+    /// greater than `votes`, the token also lined up with other names.
+    pub sightings: usize,
+}
+
+/// Proposes names for unknown tokens by lining up a binary save with the
+/// same save written in plaintext.
+///
+/// The binary tape is melted with the known tokens, and its fields are
+/// matched to the fields of the plaintext at the same position in the same
+/// object. Fields with known keys anchor the alignment, so where an unknown
+/// token sits in the binary data, the key (or value) of the plaintext field at
+/// that position is recorded as a vote for the token's name. Feeding more
+/// save pairs to the learner increases the confidence of the candidates.
+///
+/// ```
+/// use jomini::{BinaryTape, Eu4Flavor, TextTape, TokenLearner};
+/// use std::collections::HashMap;
+///
+/// // owner=1 0x2d83=2 0x2d84=0x2d85
+/// let data = [
+///     0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00,
+///     0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00,
+///     0x84, 0x2d, 0x01, 0x00, 0x85, 0x2d,
+/// ];
+/// let binary = BinaryTape::from_eu4(&data[..])?;
+/// let text = TextTape::from_slice(b"owner=1 core=2 religion=catholic")?;
+///
+/// let mut map = HashMap::new();
+/// map.insert(0x2d82, "owner");
+///
+/// let mut learner = TokenLearner::new();
+/// learner.learn(&binary, &map, Eu4Flavor::new(), &text)?;
+/// let proposed: Vec<_> = learner
+///     .candidates()
+///     .into_iter()
+///     .map(|x| (x.id, x.name))
+///     .collect();
+/// assert_eq!(
+///     proposed,
+///     vec![
+///         (0x2d83, String::from("core")),
+///         (0x2d84, String::from("religion")),
+///         (0x2d85, String::from("catholic")),
+///     ]
+/// );
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TokenLearner {
+    votes: HashMap<u16, HashMap<String, usize>>,
+}
+
+impl TokenLearner {
+    /// A learner that hasn't seen any data
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Line up the binary tape with its plaintext counterpart and record the
+    /// names of the tokens that the resolver doesn't know
+    pub fn learn<RES, F>(
+        &mut self,
+        binary: &BinaryTape,
+        resolver: &RES,
+        flavor: F,
+        text: &TextTape,
+    ) -> Result<(), Error>
+    where
+        RES: TokenResolver,
+        F: BinaryFlavor,
+    {
+        let melted = binary.normalize(resolver, flavor);
+        let melted = melted.tape()?;
+        let mut aligner = Aligner {
+            melted: melted.tokens(),
+            text: text.tokens(),
+            votes: &mut self.votes,
+        };
+
+        aligner.object((0, aligner.melted.len()), (0, aligner.text.len()));
+        Ok(())
+    }
+
+    /// The most likely name of each token seen so far, ordered by the most
+    /// votes first (ties are ordered by id)
+    pub fn candidates(&self) -> Vec<TokenCandidate> {
+        let mut result: Vec<_> = self
+            .votes
+            .iter()
+            .filter_map(|(&id, names)| {
+                let (name, &votes) = names
+                    .iter()
+                    .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))?;
+                Some(TokenCandidate {
+                    id,
+                    name: name.clone(),
+                    votes,
+                    sightings: names.values().sum(),
+                })
+            })
+            .collect();
+
+        result.sort_unstable_by(|a, b| b.votes.cmp(&a.votes).then(a.id.cmp(&b.id)));
+        result
+    }
+}
+
+/// Returns the id of an unknown token that the melter wrote as hex
+fn unknown_token(scalar: Scalar) -> Option<u16> {
+    let hex = scalar.view_data().strip_prefix(b"0x")?;
+    let hex = std::str::from_utf8(hex).ok()?;
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Returns true if the scalar looks like an identifier that a token could
+/// be named
+fn is_name(scalar: Scalar) -> bool {
+    match scalar.view_data() {
+        [first, rest @ ..] => {
+            (first.is_ascii_alphabetic() || *first == b'_')
+                && rest.iter().all(|x| x.is_ascii_alphanumeric() || *x == b'_')
+        }
+        [] => false,
+    }
+}
+
+struct Aligner<'a, 'm, 't> {
+    melted: &'a [TextToken<'m>],
+    text: &'a [TextToken<'t>],
+    votes: &'a mut HashMap<u16, HashMap<String, usize>>,
+}
+
+impl<'a, 'm, 't> Aligner<'a, 'm, 't> {
+    fn vote(&mut self, melted: Scalar, text: Scalar) {
+        if let Some(id) = unknown_token(melted) {
+            if is_name(text) {
+                let name = text.to_string();
+                *self.votes.entry(id).or_default().entry(name).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn value(&mut self, melted: usize, text: usize) {
+        match (&self.melted[melted], &self.text[text]) {
+            (TextToken::Scalar(x), TextToken::Scalar(y)) => self.vote(*x, *y),
+            (TextToken::Header(x), TextToken::Header(y)) => {
+                self.vote(*x, *y);
+                self.value(melted + 1, text + 1);
+            }
+            (
+                TextToken::Object(x) | TextToken::HiddenObject(x),
+                TextToken::Object(y) | TextToken::HiddenObject(y),
+            ) => self.object((melted + 1, *x), (text + 1, *y)),
+            (TextToken::Array(x), TextToken::Array(y)) => {
+                let melted = elements(self.melted, melted + 1, *x);
+                let text = elements(self.text, text + 1, *y);
+                for (m, t) in melted.into_iter().zip(text) {
+                    self.value(m, t);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn object(&mut self, melted: (usize, usize), text: (usize, usize)) {
+        let melted = fields(self.melted, melted.0, melted.1);
+        let text = fields(self.text, text.0, text.1);
+
+        let mut j = 0;
+        for (key, value) in melted {
+            if j >= text.len() {
+                break;
+            }
+
+            if unknown_token(key).is_some() {
+                self.vote(key, text[j].0);
+            } else {
+                // A known key anchors the alignment: skip over plaintext
+                // fields that are missing from the binary data
+                let found = text[j..]
+                    .iter()
+                    .position(|x| x.0.view_data() == key.view_data());
+                match found {
+                    Some(offset) => j += offset,
+                    None => continue,
+                }
+            }
+
+            self.value(value, text[j].1);
+            j += 1;
+        }
+    }
+}
+
+fn next_idx(tokens: &[TextToken], idx: usize) -> usize {
+    match tokens[idx] {
+        TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+        TextToken::Header(_) => next_idx(tokens, idx + 1),
+        _ => idx + 1,
+    }
+}
+
+/// The indices of the values of an array
+fn elements(tokens: &[TextToken], mut idx: usize, end: usize) -> Vec<usize> {
+    let mut result = Vec::new();
+    while idx < end {
+        result.push(idx);
+        idx = next_idx(tokens, idx);
+    }
+    result
+}
+
+/// The keys and value indices of an object
+fn fields<'t>(tokens: &[TextToken<'t>], mut idx: usize, end: usize) -> Vec<(Scalar<'t>, usize)> {
+    let mut result = Vec::new();
+    while idx < end {
+        let key = match tokens[idx] {
+            TextToken::Scalar(x) => x,
+            _ => {
+                idx = next_idx(tokens, idx);
+                continue;
+            }
+        };
+
+        let mut value_idx = idx + 1;
+        if let Some(TextToken::Operator(_)) = tokens.get(value_idx) {
+            value_idx += 1;
+        }
+
+        if value_idx >= end {
+            break;
+        }
+
+        result.push((key, value_idx));
+        idx = next_idx(tokens, value_idx);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Eu4Flavor;
+
+    #[test]
+    fn test_learn_skips_fields_missing_from_binary() {
+        // a={ 0x2d83=1 b=2 0x2d84={ 0x2d83=3 } } 0x2d85={ 0x2d86 0x2d86 }
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x87, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x84, 0x2d,
+            0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x03, 0x00, 0x00, 0x00,
+            0x04, 0x00, 0x04, 0x00, 0x85, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x86, 0x2d, 0x86, 0x2d,
+            0x04, 0x00,
+        ];
+        let binary = BinaryTape::from_eu4(&data[..]).unwrap();
+        let text = TextTape::from_slice(
+            b"a={ x=1 extra=yes b=2 y={ x=3 } } z={ france france } trailing=1",
+        )
+        .unwrap();
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d87, "b");
+
+        let mut learner = TokenLearner::new();
+        learner
+            .learn(&binary, &map, Eu4Flavor::new(), &text)
+            .unwrap();
+        learner
+            .learn(&binary, &map, Eu4Flavor::new(), &text)
+            .unwrap();
+
+        let candidates = learner.candidates();
+        let found: Vec<_> = candidates
+            .iter()
+            .map(|x| (x.id, x.name.as_str(), x.votes, x.sightings))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0x2d83, "x", 4, 4),
+                (0x2d86, "france", 4, 4),
+                (0x2d84, "y", 2, 2),
+                (0x2d85, "z", 2, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_votes() {
+        // 0x2d83=1 0x2d83=2
+        let data = [
+            0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x83, 0x2d, 0x01, 0x00,
+            0x0c, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ];
+        let binary = BinaryTape::from_eu4(&data[..]).unwrap();
+        let text = TextTape::from_slice(b"core=1 owner=2").unwrap();
+        let map: HashMap<u16, &str> = HashMap::new();
+
+        let mut learner = TokenLearner::new();
+        learner
+            .learn(&binary, &map, Eu4Flavor::new(), &text)
+            .unwrap();
+        let candidates = learner.candidates();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].name, "core");
+        assert_eq!((candidates[0].votes, candidates[0].sightings), (1, 2));
+    }
+}
@@ -1,11 +1,25 @@
 #[cfg(feature = "derive")]
 mod de;
+mod event;
 mod flavor;
+mod learn;
+mod normalize;
+#[cfg(feature = "derive")]
+mod ondemand;
 mod resolver;
 mod tape;
+mod tokens;
 
 #[cfg(feature = "derive")]
-pub use self::de::{BinaryDeserializer, BinaryDeserializerBuilder};
+pub use self::de::{
+    BinaryDeserializer, BinaryDeserializerBuilder, BinaryTapeDeserializer,
+    ResolvedBinaryDeserializer,
+};
+pub use self::event::{BinaryEvent, BinaryLexeme, BinaryPushParser};
 pub use self::flavor::{BinaryFlavor, Ck3Flavor, Eu4Flavor};
+pub use self::learn::{TokenCandidate, TokenLearner};
+pub use self::normalize::{NormalizedTape, NormalizerBuilder};
 pub use self::resolver::{FailedResolveStrategy, TokenResolver};
+pub(crate) use self::tape::extend_tape;
 pub use self::tape::{BinaryTape, BinaryTapeParser, BinaryToken};
+pub use self::tokens::TokenTable;
@@ -0,0 +1,691 @@
+use crate::common::FixedFloat;
+#[cfg(feature = "metrics")]
+use crate::observer::{Observer, ParseObserver};
+use crate::trace::span;
+use crate::{
+    BinaryFlavor, BinaryTape, BinaryToken, Error, ErrorKind, FailedResolveStrategy, TextTape,
+    TokenResolver,
+};
+use std::io::Write;
+
+/// A binary tape that has been converted into the plaintext format so that it
+/// can be processed with the same logic as text data (diffing, querying,
+/// converting to other formats, etc).
+///
+/// Tokens are resolved to their names, strings are decoded with the given
+/// flavor and stored as utf-8, and numbers are written out in their decimal
+/// form. Tokens that can't be resolved are written as their hex id
+/// (eg: `0x2d82`).
+///
+/// The flavor decides how values are formatted so that the output matches
+/// what the game writes: floats keep the game's number of decimal places
+/// (`1.000`) and strings are quoted according to the flavor's policy, which
+/// keeps a quoted number like `"10"` distinct from the number `10`.
+///
+/// ```
+/// use jomini::{BinaryTape, Eu4Flavor};
+/// use std::collections::HashMap;
+///
+/// let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47];
+/// let mut map = HashMap::new();
+/// map.insert(0x2d82, "field1");
+///
+/// let normalized = BinaryTape::from_eu4(&data)?.normalize(&map, Eu4Flavor::new());
+/// assert_eq!(normalized.as_bytes(), b"field1=\"ENG\"\n");
+///
+/// let tape = normalized.tape()?;
+/// let (key, _op, value) = tape.utf8_reader().next_field().unwrap();
+/// assert_eq!(key.read_str(), "field1");
+/// assert_eq!(value.read_string()?, "ENG");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedTape {
+    data: Vec<u8>,
+}
+
+impl NormalizedTape {
+    /// The utf-8 plaintext that the binary tape was converted into
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Parse the plaintext into a tape
+    pub fn tape(&self) -> Result<TextTape<'_>, Error> {
+        TextTape::from_slice(&self.data)
+    }
+
+    /// Create a builder to tweak how a binary tape is normalized
+    pub fn builder_flavor<F>(flavor: F) -> NormalizerBuilder<F>
+    where
+        F: BinaryFlavor,
+    {
+        NormalizerBuilder::with_flavor(flavor)
+    }
+}
+
+/// Build a tweaked normalizer of binary tapes
+///
+/// By default, tokens that can't be resolved are written as their hex id.
+/// When updating a token file, it's helpful to have them stand out instead,
+/// so they can be annotated with a comment that names the unknown token:
+///
+/// ```
+/// use jomini::{BinaryTape, Eu4Flavor, FailedResolveStrategy, NormalizedTape};
+/// use std::collections::HashMap;
+///
+/// let data = [0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00,
+///             0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00];
+/// let mut map = HashMap::new();
+/// map.insert(0x2d82, "a");
+///
+/// let tape = BinaryTape::from_eu4(&data)?;
+/// let normalized = NormalizedTape::builder_flavor(Eu4Flavor::new())
+///     .annotate_unknown_tokens(true)
+///     .normalize(&tape, &map)?;
+/// assert_eq!(normalized.as_bytes(), b"a=1\n0x2d83=2 # unknown 0x2d83\n");
+///
+/// let normalized = NormalizedTape::builder_flavor(Eu4Flavor::new())
+///     .on_failed_resolve(FailedResolveStrategy::Ignore)
+///     .annotate_unknown_tokens(true)
+///     .normalize(&tape, &map)?;
+/// assert_eq!(normalized.as_bytes(), b"a=1\n# unknown 0x2d83\n");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct NormalizerBuilder<F> {
+    flavor: F,
+    failed_resolve_strategy: FailedResolveStrategy,
+    annotate_unknown_tokens: bool,
+    rakaly_compat: bool,
+    #[cfg(feature = "metrics")]
+    observer: Option<Observer>,
+}
+
+impl<F> NormalizerBuilder<F>
+where
+    F: BinaryFlavor,
+{
+    /// Create a new builder instance
+    pub fn with_flavor(flavor: F) -> Self {
+        NormalizerBuilder {
+            flavor,
+            failed_resolve_strategy: FailedResolveStrategy::Stringify,
+            annotate_unknown_tokens: false,
+            rakaly_compat: false,
+            #[cfg(feature = "metrics")]
+            observer: None,
+        }
+    }
+
+    /// Set the behavior when a token can't be resolved. Ignoring a token
+    /// drops the field (or array value) that it is a part of.
+    pub fn on_failed_resolve(&mut self, strategy: FailedResolveStrategy) -> &mut Self {
+        self.failed_resolve_strategy = strategy;
+        self
+    }
+
+    /// Write a `# unknown 0x1234` comment after each field or value that
+    /// contains a token that couldn't be resolved
+    pub fn annotate_unknown_tokens(&mut self, enabled: bool) -> &mut Self {
+        self.annotate_unknown_tokens = enabled;
+        self
+    }
+
+    /// Write the same output as the Rakaly melter so that pipelines that
+    /// diff melted saves can switch over without spurious changes. Each field
+    /// is written on its own line and indented with tabs, arrays that only
+    /// contain scalars stay on a single line, empty blocks are written as
+    /// `{` and `}` on separate lines, and unknown tokens are written as
+    /// `__unknown_0x2d82`. Floats keep the flavor's decimal places.
+    ///
+    /// ```
+    /// use jomini::{BinaryTape, Eu4Flavor, NormalizedTape};
+    /// use std::collections::HashMap;
+    ///
+    /// let data = [
+    ///     0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0d, 0x00,
+    ///     0xe8, 0x03, 0x00, 0x00, 0x84, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00,
+    ///     0x01, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+    ///     0x04, 0x00,
+    /// ];
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d82, "country");
+    /// map.insert(0x2d83, "gold");
+    ///
+    /// let tape = BinaryTape::from_eu4(&data)?;
+    /// let normalized = NormalizedTape::builder_flavor(Eu4Flavor::new())
+    ///     .rakaly_compat(true)
+    ///     .normalize(&tape, &map)?;
+    /// assert_eq!(
+    ///     normalized.as_bytes(),
+    ///     b"country={\n\tgold=1.000\n\t__unknown_0x2d84={ 1 2 }\n}\n"
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn rakaly_compat(&mut self, enabled: bool) -> &mut Self {
+        self.rakaly_compat = enabled;
+        self
+    }
+
+    /// Report the number of unknown tokens of each conversion to the
+    /// observer. See `ParseObserver`.
+    #[cfg(feature = "metrics")]
+    pub fn observer(&mut self, observer: &'static dyn ParseObserver) -> &mut Self {
+        self.observer = Some(Observer(observer));
+        self
+    }
+
+    /// Convert the binary tape into the plaintext format
+    pub fn normalize<RES>(&self, tape: &BinaryTape, resolver: &RES) -> Result<NormalizedTape, Error>
+    where
+        RES: TokenResolver,
+    {
+        if self.failed_resolve_strategy == FailedResolveStrategy::Error {
+            for token in tape.tokens() {
+                match *token {
+                    BinaryToken::Token(x) if resolver.resolve(x).is_none() => {
+                        return Err(Error::new(ErrorKind::UnknownToken { token_id: x }))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let normalizer = Normalizer {
+            tokens: tape.tokens(),
+            resolver,
+            flavor: &self.flavor,
+            ignore_unknown: self.failed_resolve_strategy == FailedResolveStrategy::Ignore,
+            annotate_unknown: self.annotate_unknown_tokens,
+            rakaly_compat: self.rakaly_compat,
+            depth: 0,
+            unknown: Vec::new(),
+            unknown_count: 0,
+            out: Vec::with_capacity(tape.tokens().len() * 8),
+        };
+
+        let (normalized, unknown_count) = normalizer.normalize();
+
+        #[cfg(feature = "metrics")]
+        if let Some(Observer(observer)) = self.observer {
+            observer.unknown_tokens(unknown_count);
+        }
+
+        #[cfg(not(feature = "metrics"))]
+        let _ = unknown_count;
+
+        Ok(normalized)
+    }
+}
+
+impl<'a> BinaryTape<'a> {
+    /// Convert the binary tape into the plaintext format. See `NormalizedTape`.
+    pub fn normalize<RES, F>(&self, resolver: &RES, flavor: F) -> NormalizedTape
+    where
+        RES: TokenResolver,
+        F: BinaryFlavor,
+    {
+        let normalizer = Normalizer {
+            tokens: self.tokens(),
+            resolver,
+            flavor,
+            ignore_unknown: false,
+            annotate_unknown: false,
+            rakaly_compat: false,
+            depth: 0,
+            unknown: Vec::new(),
+            unknown_count: 0,
+            out: Vec::with_capacity(self.tokens().len() * 8),
+        };
+
+        normalizer.normalize().0
+    }
+}
+
+struct Normalizer<'a, 'b, 'res, RES, F> {
+    tokens: &'b [BinaryToken<'a>],
+    resolver: &'res RES,
+    flavor: F,
+    ignore_unknown: bool,
+    annotate_unknown: bool,
+    rakaly_compat: bool,
+
+    /// The number of objects and arrays that are open
+    depth: usize,
+
+    /// Unknown tokens that are waiting to be annotated
+    unknown: Vec<u16>,
+
+    /// The number of unknown tokens that have been seen
+    unknown_count: usize,
+    out: Vec<u8>,
+}
+
+/// Returns true if the string can't be written in plaintext without quotes
+fn needs_quotes(s: &str) -> bool {
+    s.is_empty()
+        || s.bytes().any(|b| {
+            b.is_ascii_whitespace() || matches!(b, b'"' | b'=' | b'{' | b'}' | b'#' | b'<' | b'>')
+        })
+}
+
+impl<'a, 'b, 'res, RES, F> Normalizer<'a, 'b, 'res, RES, F>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    /// Returns the normalized tape and the number of unknown tokens
+    fn normalize(mut self) -> (NormalizedTape, usize) {
+        let span = span!("melt", tokens = self.tokens.len(); bytes);
+        let mut idx = 0;
+        while idx < self.tokens.len() {
+            if let Some(next) = self.skip_ignored_field(idx) {
+                idx = next;
+                self.write_annotation();
+                continue;
+            }
+
+            idx = self.write_field(idx);
+            if !self.write_annotation() {
+                self.out.push(b'\n');
+            }
+        }
+
+        span.record("bytes", self.out.len());
+        (NormalizedTape { data: self.out }, self.unknown_count)
+    }
+
+    fn write_fields(&mut self, mut idx: usize, end: usize) {
+        while idx < end {
+            if let Some(next) = self.skip_ignored_field(idx) {
+                idx = next;
+            } else {
+                self.begin_item();
+                idx = self.write_field(idx);
+            }
+
+            self.write_annotation();
+        }
+    }
+
+    /// Writes the values of an array and returns if they were written on
+    /// their own lines
+    fn write_values(&mut self, mut idx: usize, end: usize) -> bool {
+        let multiline = self.rakaly_compat
+            && (idx == end
+                || self.tokens[idx..end].iter().any(|x| {
+                    matches!(
+                        x,
+                        BinaryToken::Array(_)
+                            | BinaryToken::Object(_)
+                            | BinaryToken::HiddenObject(_)
+                    )
+                }));
+
+        while idx < end {
+            if let Some(token) = self.ignored_token(idx) {
+                self.unknown.push(token);
+                idx = self.next_idx(idx);
+            } else {
+                // the fields of a hidden object are already separated
+                if !matches!(self.tokens[idx], BinaryToken::HiddenObject(_)) {
+                    if multiline {
+                        self.begin_line();
+                    } else {
+                        self.out.push(b' ');
+                    }
+                }
+                idx = self.write_value(idx);
+            }
+
+            self.write_annotation();
+        }
+
+        multiline
+    }
+
+    /// Separates a field from what was written before it
+    fn begin_item(&mut self) {
+        if self.rakaly_compat {
+            self.begin_line();
+        } else {
+            self.out.push(b' ');
+        }
+    }
+
+    /// Starts a new line that is indented to the current depth
+    fn begin_line(&mut self) {
+        if !matches!(self.out.last(), None | Some(b'\n')) {
+            self.out.push(b'\n');
+        }
+
+        for _ in 0..self.depth {
+            self.out.push(b'\t');
+        }
+    }
+
+    /// Writes the closing brace of an object or array
+    fn end_block(&mut self, multiline: bool) {
+        self.depth -= 1;
+        if multiline {
+            self.begin_line();
+            self.out.push(b'}');
+        } else {
+            self.out.extend_from_slice(b" }");
+        }
+    }
+
+    /// Writes the field at the given index and returns the index of the next
+    /// field
+    fn write_field(&mut self, idx: usize) -> usize {
+        self.write_scalar(idx, true);
+        self.out.push(b'=');
+        self.write_value(idx + 1)
+    }
+
+    /// Returns the index of the next field if the key or value of the field
+    /// at the given index is left out of the output
+    fn skip_ignored_field(&mut self, idx: usize) -> Option<usize> {
+        let key = self.ignored_token(idx);
+        let value = self.ignored_token(idx + 1);
+        if key.is_none() && value.is_none() {
+            return None;
+        }
+
+        self.unknown.extend(key);
+        self.unknown.extend(value);
+        Some(self.next_idx(idx + 1))
+    }
+
+    /// Returns the token id at the given index if it can't be resolved and
+    /// should be left out of the output
+    fn ignored_token(&self, idx: usize) -> Option<u16> {
+        match self.tokens.get(idx) {
+            Some(BinaryToken::Token(x)) if self.ignore_unknown => {
+                self.resolver.resolve(*x).map_or(Some(*x), |_| None)
+            }
+            _ => None,
+        }
+    }
+
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens.get(idx) {
+            Some(BinaryToken::Array(end))
+            | Some(BinaryToken::Object(end))
+            | Some(BinaryToken::HiddenObject(end)) => end + 1,
+            _ => idx + 1,
+        }
+    }
+
+    /// Writes a comment for the pending unknown tokens, which ends the line,
+    /// and returns if one was written
+    fn write_annotation(&mut self) -> bool {
+        if self.unknown.is_empty() {
+            return false;
+        }
+
+        self.unknown_count += self.unknown.len();
+
+        if !self.annotate_unknown {
+            self.unknown.clear();
+            return false;
+        }
+
+        if !matches!(self.out.last(), None | Some(b'\n')) {
+            self.out.push(b' ');
+        }
+
+        self.out.extend_from_slice(b"# unknown");
+        for token in self.unknown.drain(..) {
+            let _ = write!(self.out, " 0x{:x}", token);
+        }
+        self.out.push(b'\n');
+        true
+    }
+
+    /// Writes the value at the given index and returns the index of the next
+    /// token
+    fn write_value(&mut self, idx: usize) -> usize {
+        match self.tokens.get(idx) {
+            Some(BinaryToken::Array(end)) => {
+                self.out.push(b'{');
+                self.depth += 1;
+                let multiline = self.write_values(idx + 1, *end);
+                self.end_block(multiline);
+                end + 1
+            }
+            Some(BinaryToken::Object(end)) => {
+                self.out.push(b'{');
+                self.depth += 1;
+                self.write_fields(idx + 1, *end);
+                self.end_block(self.rakaly_compat);
+                end + 1
+            }
+
+            // The fields of a hidden object are written inline so that the
+            // text parser recreates the hidden object
+            Some(BinaryToken::HiddenObject(end)) => {
+                self.write_fields(idx + 1, *end);
+                end + 1
+            }
+            Some(BinaryToken::Rgb(color)) => {
+                let _ = write!(self.out, "rgb {{ {} {} {} }}", color.r, color.g, color.b);
+                idx + 1
+            }
+            Some(_) => {
+                self.write_scalar(idx, false);
+                idx + 1
+            }
+            None => idx + 1,
+        }
+    }
+
+    fn write_scalar(&mut self, idx: usize, is_key: bool) {
+        let _ = match self.tokens[idx] {
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                let decoded = self.flavor.decode(x.view_data());
+                if self.flavor.quote_string(is_key) || needs_quotes(&decoded) {
+                    self.out.push(b'"');
+                    for &b in decoded.as_bytes() {
+                        if b == b'"' {
+                            self.out.push(b'\\');
+                        }
+                        self.out.push(b);
+                    }
+                    self.out.push(b'"');
+                } else {
+                    self.out.extend_from_slice(decoded.as_bytes());
+                }
+                Ok(())
+            }
+            BinaryToken::Token(x) => match self.resolver.resolve(x) {
+                Some(name) => self.out.write_all(name.as_bytes()),
+                None => {
+                    self.unknown.push(x);
+                    if self.rakaly_compat {
+                        write!(self.out, "__unknown_0x{:x}", x)
+                    } else {
+                        write!(self.out, "0x{:x}", x)
+                    }
+                }
+            },
+            BinaryToken::Bool(x) => self.out.write_all(if x { b"yes" } else { b"no" }),
+            BinaryToken::U32(x) => write!(self.out, "{}", x),
+            BinaryToken::U64(x) => write!(self.out, "{}", x),
+            BinaryToken::I32(x) => write!(self.out, "{}", x),
+            BinaryToken::F32_1(x) => write!(
+                self.out,
+                "{}",
+                FixedFloat::new(x, self.flavor.f32_1_decimals())
+            ),
+            BinaryToken::F32_2(x) => write!(
+                self.out,
+                "{}",
+                FixedFloat::new(x, self.flavor.f32_2_decimals())
+            ),
+            BinaryToken::Array(_)
+            | BinaryToken::Object(_)
+            | BinaryToken::HiddenObject(_)
+            | BinaryToken::End(_)
+            | BinaryToken::Rgb(_) => Ok(()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Eu4Flavor, TextToken};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_normalize_hidden_object() {
+        let data = [
+            0x6f, 0x34, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+            0xaa, 0xaa, 0x01, 0x00, 0x03, 0x00, 0xbb, 0xbb, 0x01, 0x00, 0xcc, 0xcc, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x346f, "levels");
+        map.insert(0xaaaa, "foo");
+        map.insert(0xbbbb, "bar");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let normalized = tape.normalize(&map, Eu4Flavor::new());
+        assert_eq!(
+            normalized.as_bytes(),
+            &b"levels={ 10 0=2 1=2 }\nfoo={ bar=0xcccc }\n"[..]
+        );
+
+        let text_tape = normalized.tape().unwrap();
+        assert!(matches!(text_tape.tokens()[3], TextToken::HiddenObject(_)));
+    }
+
+    #[test]
+    fn test_normalize_flavor_formatting() {
+        let data = [
+            0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x03, 0x00, 0x82, 0x2d, 0x01, 0x00, 0x0f,
+            0x00, 0x02, 0x00, 0x31, 0x30, 0x83, 0x2d, 0x01, 0x00, 0x0d, 0x00, 0xe8, 0x03, 0x00,
+            0x00, 0x84, 0x2d, 0x01, 0x00, 0x67, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "id");
+        map.insert(0x2d83, "gold");
+        map.insert(0x2d84, "ratio");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let normalized = tape.normalize(&map, Eu4Flavor::new());
+        assert_eq!(
+            normalized.as_bytes(),
+            &b"ENG={ id=\"10\" gold=1.000 ratio=1.00000 }\n"[..]
+        );
+    }
+
+    #[test]
+    fn test_normalize_escapes_quotes() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x61, 0x22, 0x62, 0x83, 0x2d, 0x01,
+            0x00, 0x03, 0x00, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "name");
+        map.insert(0x2d83, "empty");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let normalized = tape.normalize(&map, Eu4Flavor::new());
+        assert_eq!(normalized.as_bytes(), &b"name=\"a\\\"b\"\nempty={ }\n"[..]);
+
+        let text_tape = normalized.tape().unwrap();
+        let mut reader = text_tape.utf8_reader();
+        let (_key, _op, value) = reader.next_field().unwrap();
+        assert_eq!(value.read_string().unwrap(), "a\"b");
+    }
+
+    #[test]
+    fn test_normalize_rakaly_compat() {
+        // levels={ 10 0=2 } foo={ bar={ } list={ { a=1 } { a=2 } } }
+        let data = [
+            0x6f, 0x34, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+            0xaa, 0xaa, 0x01, 0x00, 0x03, 0x00, 0xbb, 0xbb, 0x01, 0x00, 0x03, 0x00, 0x04, 0x00,
+            0xdd, 0xdd, 0x01, 0x00, 0x03, 0x00, 0x03, 0x00, 0xee, 0xee, 0x01, 0x00, 0x0c, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x03, 0x00, 0xee, 0xee, 0x01, 0x00, 0x0c, 0x00,
+            0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x04, 0x00, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x346f, "levels");
+        map.insert(0xaaaa, "foo");
+        map.insert(0xbbbb, "bar");
+        map.insert(0xdddd, "list");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let normalized = NormalizedTape::builder_flavor(Eu4Flavor::new())
+            .rakaly_compat(true)
+            .normalize(&tape, &map)
+            .unwrap();
+        assert_eq!(
+            std::str::from_utf8(normalized.as_bytes()).unwrap(),
+            "levels={\n\t10\n\t0=2\n}\nfoo={\n\tbar={\n\t}\n\tlist={\n\t\t{\n\t\t\t__unknown_0xeeee=1\n\t\t}\n\t\t{\n\t\t\t__unknown_0xeeee=2\n\t\t}\n\t}\n}\n"
+        );
+
+        let compact = tape.normalize(&map, Eu4Flavor::new());
+        let compact = compact.tape().unwrap();
+        assert_eq!(
+            normalized.tape().unwrap().tokens().len(),
+            compact.tokens().len()
+        );
+    }
+
+    #[test]
+    fn test_normalize_unknown_tokens() {
+        // foo={ 0xbbbb=1 bar={ 0xcccc } } 0xdddd=0xeeee
+        let data = [
+            0xaa, 0xaa, 0x01, 0x00, 0x03, 0x00, 0xbb, 0xbb, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x01, 0x01, 0x01, 0x00, 0x03, 0x00, 0xcc, 0xcc, 0x04, 0x00, 0x04, 0x00,
+            0xdd, 0xdd, 0x01, 0x00, 0xee, 0xee,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0xaaaa, "foo");
+        map.insert(0x0101, "bar");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let mut builder = NormalizedTape::builder_flavor(Eu4Flavor::new());
+        let normalized = builder.normalize(&tape, &map).unwrap();
+        assert_eq!(normalized, tape.normalize(&map, Eu4Flavor::new()));
+        assert_eq!(
+            normalized.as_bytes(),
+            &b"foo={ 0xbbbb=1 bar={ 0xcccc } }\n0xdddd=0xeeee\n"[..]
+        );
+
+        builder.annotate_unknown_tokens(true);
+        let normalized = builder.normalize(&tape, &map).unwrap();
+        assert_eq!(
+            normalized.as_bytes(),
+            &b"foo={ 0xbbbb=1 # unknown 0xbbbb\n bar={ 0xcccc # unknown 0xcccc\n } }\n0xdddd=0xeeee # unknown 0xdddd 0xeeee\n"[..]
+        );
+        assert_eq!(normalized.tape().unwrap().tokens().len(), 11);
+
+        builder.on_failed_resolve(FailedResolveStrategy::Ignore);
+        let normalized = builder.normalize(&tape, &map).unwrap();
+        assert_eq!(
+            normalized.as_bytes(),
+            &b"foo={ # unknown 0xbbbb\n bar={ # unknown 0xcccc\n } }\n# unknown 0xdddd 0xeeee\n"[..]
+        );
+
+        builder.annotate_unknown_tokens(false);
+        let normalized = builder.normalize(&tape, &map).unwrap();
+        assert_eq!(normalized.as_bytes(), &b"foo={ bar={ } }\n"[..]);
+
+        builder.on_failed_resolve(FailedResolveStrategy::Error);
+        let err = builder.normalize(&tape, &map).unwrap_err();
+        assert_eq!(err.token_id(), Some(0xbbbb));
+    }
+}
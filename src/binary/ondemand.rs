@@ -0,0 +1,938 @@
+use super::de::{
+    integer_methods, key_name, typed_key_methods, visit_integer_token, visit_str_token,
+    visit_token, visit_typed_key, BinaryConfig, KeyType,
+};
+use super::tape::{BOOL, END, EQUAL, F32_1, F32_2, I32, OPEN, RGB, STRING_1, STRING_2, U32, U64};
+use crate::{
+    de::{visit_rgb, IntegerType, KeyValuePair},
+    util::{le_i32, le_u16, le_u32, le_u64},
+    BinaryFlavor, BinaryToken, DeserializeError, DeserializeErrorKind, Error, ErrorKind, Rgb,
+    RgbRepresentation, Scalar, TokenResolver,
+};
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+
+/// Deserializes binary data by walking the data with a cursor instead of
+/// first materializing a `BinaryTape`.
+///
+/// The tape based deserializer knows upfront where an object or array ends,
+/// while here the same information is discovered by peeking ahead. Lookahead
+/// is limited to a single scalar, so the only costs over the tape are the
+/// occasional double decode of a scalar and the scanning of ignored values.
+pub(super) struct OndemandDeserializer<'b, 'de, 'res: 'de, RES, F> {
+    data: &'de [u8],
+    original_length: usize,
+    config: &'b BinaryConfig<'res, RES, F>,
+}
+
+impl<'b, 'de, 'res: 'de, RES, F> OndemandDeserializer<'b, 'de, 'res, RES, F>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    fn deserialize_integer<V>(&mut self, ty: IntegerType, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_id() {
+            None | Some(OPEN) | Some(RGB) => de::Deserializer::deserialize_any(self, visitor),
+            Some(_) => {
+                let id = self.next_id()?;
+                let token = self.read_scalar(id)?;
+                Ok(visit_integer_token(&token, self.config, ty, visitor)?)
+            }
+        }
+    }
+
+    pub(super) fn new(data: &'de [u8], config: &'b BinaryConfig<'res, RES, F>) -> Self {
+        OndemandDeserializer {
+            data,
+            original_length: data.len(),
+            config,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.original_length - self.data.len()
+    }
+
+    fn syntax_error(&self, msg: &str) -> Error {
+        Error::new(ErrorKind::InvalidSyntax {
+            msg: String::from(msg),
+            offset: self.offset(),
+        })
+    }
+
+    #[inline]
+    fn peek_id(&self) -> Option<u16> {
+        self.data.get(..2).map(le_u16)
+    }
+
+    #[inline]
+    fn next_id(&mut self) -> Result<u16, Error> {
+        let id = self.peek_id().ok_or_else(|| Error::eof("a token id"))?;
+        self.data = &self.data[2..];
+        Ok(id)
+    }
+
+    #[inline]
+    fn take(&mut self, len: usize) -> Result<&'de [u8], Error> {
+        if self.data.len() >= len {
+            let (head, rest) = self.data.split_at(len);
+            self.data = rest;
+            Ok(head)
+        } else {
+            Err(Error::eof("scalar data"))
+        }
+    }
+
+    /// Reads the payload of the scalar that the given token id introduces
+    fn read_scalar(&mut self, token_id: u16) -> Result<BinaryToken<'de>, Error> {
+        let token = match token_id {
+            U32 => BinaryToken::U32(le_u32(self.take(4)?)),
+            U64 => BinaryToken::U64(le_u64(self.take(8)?)),
+            I32 => BinaryToken::I32(le_i32(self.take(4)?)),
+            BOOL => BinaryToken::Bool(self.take(1)?[0] != 0),
+            STRING_1 => {
+                let text_len = usize::from(le_u16(self.take(2)?));
+                BinaryToken::Text(Scalar::new(self.take(text_len)?))
+            }
+            STRING_2 => {
+                let text_len = usize::from(le_u16(self.take(2)?));
+                BinaryToken::Unquoted(Scalar::new(self.take(text_len)?))
+            }
+            F32_1 => BinaryToken::F32_1(self.config.encoding.visit_f32_1(self.take(4)?)),
+            F32_2 => BinaryToken::F32_2(self.config.encoding.visit_f32_2(self.take(8)?)),
+
+            // u16 `{` + (u16 + u32) * 3 + u16 `}`
+            RGB => {
+                let x = self.take(22)?;
+                BinaryToken::Rgb(Rgb {
+                    r: le_u32(&x[4..]),
+                    g: le_u32(&x[10..]),
+                    b: le_u32(&x[16..]),
+                })
+            }
+            OPEN | END | EQUAL => return Err(self.syntax_error("expected a scalar")),
+            x => BinaryToken::Token(x),
+        };
+
+        Ok(token)
+    }
+
+    /// Returns the token id that follows the upcoming scalar without
+    /// advancing the cursor. If the next token is not a scalar or if the
+    /// data ends after the scalar then `None` is returned.
+    fn peek_after_scalar(&mut self) -> Result<Option<u16>, Error> {
+        let checkpoint = self.data;
+        let result = match self.peek_id() {
+            None | Some(OPEN) | Some(END) | Some(EQUAL) => None,
+            Some(_) => {
+                let id = self.next_id()?;
+                self.read_scalar(id)?;
+                self.peek_id()
+            }
+        };
+
+        self.data = checkpoint;
+        Ok(result)
+    }
+
+    /// Advances the cursor past the next value, whether it is a scalar or a
+    /// container
+    fn skip_value(&mut self) -> Result<(), Error> {
+        match self.next_id()? {
+            OPEN => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.next_id()? {
+                        OPEN => depth += 1,
+                        END => depth -= 1,
+                        EQUAL => {}
+                        x => {
+                            self.read_scalar(x)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            x => self.read_scalar(x).map(|_| ()),
+        }
+    }
+
+    fn expect_open(&mut self) -> Result<(), Error> {
+        if self.next_id()? == OPEN {
+            Ok(())
+        } else {
+            Err(self.syntax_error("expected an open token"))
+        }
+    }
+
+    fn visit_map<V>(&mut self, visitor: V, kind: MapKind) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut map = OndemandMap {
+            de: self,
+            kind,
+            key: None,
+        };
+        let value = visitor.visit_map(&mut map)?;
+        map.finish()?;
+        Ok(value)
+    }
+
+    fn visit_entries<V>(&mut self, visitor: V, kind: MapKind) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut entries = OndemandEntries {
+            map: OndemandMap {
+                de: self,
+                kind,
+                key: None,
+            },
+        };
+        let value = visitor.visit_seq(&mut entries)?;
+        entries.map.finish()?;
+        Ok(value)
+    }
+
+    /// Without a tape, whether an open token starts an object or an array is
+    /// decided by what follows the first scalar
+    fn is_object_start(&mut self) -> Result<bool, Error> {
+        match self.peek_id() {
+            Some(END) | Some(OPEN) => Ok(false),
+            _ => Ok(matches!(
+                self.peek_after_scalar()?,
+                Some(EQUAL) | Some(OPEN)
+            )),
+        }
+    }
+
+    fn visit_seq<V>(&mut self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut seq = OndemandSeq { de: self };
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.finish()?;
+        Ok(value)
+    }
+}
+
+/// Deserializes the top level key value pairs of the data
+pub(super) struct OndemandRootDeserializer<'c, 'b, 'de, 'res: 'de, RES, F> {
+    de: &'c mut OndemandDeserializer<'b, 'de, 'res, RES, F>,
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES, F> OndemandRootDeserializer<'c, 'b, 'de, 'res, RES, F> {
+    pub(super) fn new(de: &'c mut OndemandDeserializer<'b, 'de, 'res, RES, F>) -> Self {
+        OndemandRootDeserializer { de }
+    }
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor> de::Deserializer<'de>
+    for OndemandRootDeserializer<'c, 'b, 'de, 'res, RES, F>
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::from(DeserializeError {
+            kind: DeserializeErrorKind::Unsupported(String::from(
+                "root deserializer can only work with key value pairs",
+            )),
+        }))
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.visit_map(visitor, MapKind::Root)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.visit_entries(visitor, MapKind::Root)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct tuple
+        tuple_struct enum ignored_any identifier
+    }
+}
+
+impl<'a, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor> de::Deserializer<'de>
+    for &'a mut OndemandDeserializer<'b, 'de, 'res, RES, F>
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.next_id()? {
+            OPEN if self.is_object_start()? => self.visit_map(visitor, MapKind::Object),
+            OPEN => self.visit_seq(visitor),
+            x => match self.read_scalar(x)? {
+                BinaryToken::Rgb(x) => Ok(visit_rgb(x, self.config.rgb, visitor)?),
+                token => Ok(visit_token(&token, self.config, visitor)?),
+            },
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.next_id()? {
+            OPEN if self.is_object_start()? => self.visit_entries(visitor, MapKind::Object),
+            OPEN => self.visit_seq(visitor),
+            RGB => match self.read_scalar(RGB)? {
+                BinaryToken::Rgb(x) => Ok(visit_rgb(x, self.config.rgb, visitor)?),
+                _ => unreachable!(),
+            },
+            _ => Err(self.syntax_error("encountered non-array when trying to deserialize array")),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_id() {
+            None | Some(OPEN) | Some(RGB) => self.deserialize_any(visitor),
+            Some(_) => {
+                let id = self.next_id()?;
+                let token = self.read_scalar(id)?;
+                Ok(visit_str_token(&token, self.config, visitor)?)
+            }
+        }
+    }
+
+    integer_methods!();
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let empty_block = self.data.len() >= 4
+            && le_u16(&self.data[..2]) == OPEN
+            && le_u16(&self.data[2..4]) == END;
+        if self.config.empty_block_as_none && empty_block {
+            self.data = &self.data[4..];
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // A color deserialized as a tuple of its channels skips the header
+        if len != 2 && self.peek_id() == Some(RGB) {
+            self.next_id()?;
+            if let BinaryToken::Rgb(x) = self.read_scalar(RGB)? {
+                return Ok(visit_rgb(x, RgbRepresentation::Array, visitor)?);
+            }
+        }
+
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.skip_value()?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.peek_id() == Some(RGB) {
+            self.next_id()?;
+            if let BinaryToken::Rgb(x) = self.read_scalar(RGB)? {
+                return Ok(visit_rgb(x, self.config.rgb, visitor)?);
+            }
+        }
+
+        self.expect_open()?;
+        self.visit_map(visitor, MapKind::Object)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char
+        bytes byte_buf unit unit_struct
+        enum identifier
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MapKind {
+    /// Top level object, terminated by the end of the data
+    Root,
+
+    /// Object terminated by its own end token
+    Object,
+
+    /// Object embedded in an array (`levels={ 10 0=2 1=2 }`) that shares the
+    /// end token of the array
+    Hidden,
+}
+
+struct OndemandMap<'c, 'b, 'de, 'res: 'de, RES, F> {
+    de: &'c mut OndemandDeserializer<'b, 'de, 'res, RES, F>,
+    kind: MapKind,
+
+    // The key of the value that is next, to report where errors occurred
+    key: Option<BinaryToken<'de>>,
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor>
+    OndemandMap<'c, 'b, 'de, 'res, RES, F>
+{
+    /// Reads the next key and the operator that follows it. The cursor is
+    /// left at the end token when there are no more keys.
+    fn next_key_token(&mut self) -> Result<Option<BinaryToken<'de>>, Error> {
+        loop {
+            match self.de.peek_id() {
+                None if self.kind == MapKind::Root => return Ok(None),
+                None => return Err(Error::eof("an end token")),
+                Some(END) if self.kind == MapKind::Root => {
+                    return Err(Error::new(ErrorKind::StackEmpty {
+                        offset: self.de.offset(),
+                    }))
+                }
+                Some(END) => return Ok(None),
+
+                // Skip empty object
+                Some(OPEN) => {
+                    self.de.next_id()?;
+                    if self.de.next_id()? != END {
+                        return Err(Error::new(ErrorKind::InvalidEmptyObject {
+                            offset: self.de.offset() - 2,
+                        }));
+                    }
+                }
+                Some(_) => break,
+            }
+        }
+
+        let id = self.de.next_id()?;
+        let token = self.de.read_scalar(id)?;
+        match self.de.peek_id() {
+            Some(EQUAL) => {
+                self.de.next_id()?;
+            }
+
+            // For those lovely `a{b=c}` objects
+            Some(OPEN) => {}
+            Some(_) => return Err(self.de.syntax_error("expected an equal after a key")),
+            None => return Err(Error::eof("an equal or open token")),
+        }
+
+        Ok(Some(token))
+    }
+
+    /// Skips any entries that the visitor did not consume and steps past the
+    /// end of the object
+    fn finish(&mut self) -> Result<(), Error> {
+        while self.next_key_token()?.is_some() {
+            self.de.skip_value()?;
+        }
+
+        if self.kind == MapKind::Object {
+            self.de.next_id()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor> MapAccess<'de>
+    for OndemandMap<'c, 'b, 'de, 'res, RES, F>
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.next_key_token()? {
+            Some(token) => {
+                self.key = Some(token.clone());
+                seed.deserialize(OndemandKeyDeserializer {
+                    token,
+                    config: self.de.config,
+                })
+                .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let config = self.de.config;
+        seed.deserialize(&mut *self.de)
+            .map_err(|e| match &self.key {
+                Some(key) => e.within(&key_name(key, config)),
+                None => e,
+            })
+    }
+}
+
+/// Deserializes the fields of an object as a sequence of key value pairs in
+/// the order they appear
+struct OndemandEntries<'c, 'b, 'de, 'res: 'de, RES, F> {
+    map: OndemandMap<'c, 'b, 'de, 'res, RES, F>,
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor> SeqAccess<'de>
+    for OndemandEntries<'c, 'b, 'de, 'res, RES, F>
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let token = match self.map.next_key_token()? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let key = OndemandKeyDeserializer {
+            token,
+            config: self.map.de.config,
+        };
+        let mut pair = KeyValuePair::new(key, &mut *self.map.de);
+        let result = seed.deserialize(&mut pair)?;
+
+        // Keep the cursor in sync when only the key was requested
+        if pair.is_value_pending() {
+            self.map.de.skip_value()?;
+        }
+
+        Ok(Some(result))
+    }
+}
+
+struct OndemandKeyDeserializer<'b, 'de, 'res: 'de, RES, F> {
+    token: BinaryToken<'de>,
+    config: &'b BinaryConfig<'res, RES, F>,
+}
+
+impl<'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor> de::Deserializer<'de>
+    for OndemandKeyDeserializer<'b, 'de, 'res, RES, F>
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Ok(visit_token(&self.token, self.config, visitor)?)
+    }
+
+    typed_key_methods!(self => &self.token);
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Ok(visit_str_token(&self.token, self.config, visitor)?)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        char
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum ignored_any identifier struct
+    }
+}
+
+struct OndemandSeq<'c, 'b, 'de, 'res: 'de, RES, F> {
+    de: &'c mut OndemandDeserializer<'b, 'de, 'res, RES, F>,
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor>
+    OndemandSeq<'c, 'b, 'de, 'res, RES, F>
+{
+    /// Skips any values that the visitor did not consume and steps past the
+    /// end of the array
+    fn finish(&mut self) -> Result<(), Error> {
+        loop {
+            match self.de.peek_id() {
+                None => return Err(Error::eof("an end token")),
+                Some(END) => {
+                    self.de.next_id()?;
+                    return Ok(());
+                }
+                Some(EQUAL) => {
+                    self.de.next_id()?;
+                }
+                Some(_) => self.de.skip_value()?,
+            }
+        }
+    }
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor> SeqAccess<'de>
+    for OndemandSeq<'c, 'b, 'de, 'res, RES, F>
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.de.peek_id() {
+            None => Err(Error::eof("an end token")),
+            Some(END) => Ok(None),
+            Some(_) => {
+                if self.de.peek_after_scalar()? == Some(EQUAL) {
+                    seed.deserialize(OndemandHiddenDeserializer { de: &mut *self.de })
+                        .map(Some)
+                } else {
+                    seed.deserialize(&mut *self.de).map(Some)
+                }
+            }
+        }
+    }
+}
+
+/// Deserializes the remainder of an array as an object as the array contains
+/// a hidden object
+struct OndemandHiddenDeserializer<'c, 'b, 'de, 'res: 'de, RES, F> {
+    de: &'c mut OndemandDeserializer<'b, 'de, 'res, RES, F>,
+}
+
+impl<'c, 'b, 'de, 'res: 'de, RES: TokenResolver, F: BinaryFlavor> de::Deserializer<'de>
+    for OndemandHiddenDeserializer<'c, 'b, 'de, 'res, RES, F>
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.visit_map(visitor, MapKind::Hidden)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum ignored_any identifier struct
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BinaryDeserializer, Error, ErrorKind};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    fn from_slice<'a, T>(
+        data: &'a [u8],
+        resolver: &'a HashMap<u16, &'static str>,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        BinaryDeserializer::eu4_builder().from_slice_ondemand(data, resolver)
+    }
+
+    /// Asserts that the ondemand and tape deserializers agree
+    fn assert_same<'a, T>(data: &'a [u8], resolver: &'a HashMap<u16, &'static str>) -> T
+    where
+        T: Deserialize<'a> + PartialEq + std::fmt::Debug,
+    {
+        let ondemand: T = from_slice(data, resolver).unwrap();
+        let tape: T = BinaryDeserializer::eu4_builder()
+            .from_slice(data, resolver)
+            .unwrap();
+        assert_eq!(ondemand, tape);
+        ondemand
+    }
+
+    #[test]
+    fn test_ondemand_scalars() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x83, 0x2d, 0x01,
+            0x00, 0x0c, 0x00, 0x59, 0x00, 0x00, 0x00, 0x84, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct<'a> {
+            field1: &'a str,
+            field2: i32,
+            field3: bool,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "field1");
+        map.insert(0x2d83, "field2");
+        map.insert(0x2d84, "field3");
+
+        let actual: MyStruct = assert_same(&data, &map);
+        assert_eq!(
+            actual,
+            MyStruct {
+                field1: "ENG",
+                field2: 89,
+                field3: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_ondemand_skips_unknown_values() {
+        // a = { b = { c = 1 } } d = ENG
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x84, 0x2d,
+            0x01, 0x00, 0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x04, 0x00, 0x85, 0x2d,
+            0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            d: String,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d83, "b");
+        map.insert(0x2d84, "c");
+        map.insert(0x2d85, "d");
+
+        let actual: MyStruct = assert_same(&data, &map);
+        assert_eq!(
+            actual,
+            MyStruct {
+                d: String::from("ENG")
+            }
+        );
+    }
+
+    #[test]
+    fn test_ondemand_nested_objects_and_arrays() {
+        // a = { b = { 1 2 } c = { { 3 } {} } } d = 4
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x14, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x84, 0x2d,
+            0x01, 0x00, 0x03, 0x00, 0x03, 0x00, 0x14, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00,
+            0x03, 0x00, 0x04, 0x00, 0x04, 0x00, 0x04, 0x00, 0x85, 0x2d, 0x01, 0x00, 0x14, 0x00,
+            0x04, 0x00, 0x00, 0x00,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            a: Inner,
+            d: u32,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Inner {
+            b: Vec<u32>,
+            c: Vec<Vec<u32>>,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d83, "b");
+        map.insert(0x2d84, "c");
+        map.insert(0x2d85, "d");
+
+        let actual: MyStruct = assert_same(&data, &map);
+        assert_eq!(
+            actual,
+            MyStruct {
+                a: Inner {
+                    b: vec![1, 2],
+                    c: vec![vec![3], vec![]],
+                },
+                d: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_ondemand_any_map() {
+        // a = { b = 1 c = 2 }
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x14, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x84, 0x2d, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d83, "b");
+        map.insert(0x2d84, "c");
+
+        let actual: HashMap<String, HashMap<String, u32>> = assert_same(&data, &map);
+        assert_eq!(actual["a"]["b"], 1);
+        assert_eq!(actual["a"]["c"], 2);
+    }
+
+    #[test]
+    fn test_ondemand_rgb() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x43, 0x02, 0x03, 0x00, 0x14, 0x00, 0x6e, 0x00, 0x00, 0x00,
+            0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            color: (String, (u8, u8, u8)),
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "color");
+
+        let actual: MyStruct = assert_same(&data, &map);
+        assert_eq!(
+            actual,
+            MyStruct {
+                color: (String::from("rgb"), (110, 27, 27))
+            }
+        );
+    }
+
+    #[test]
+    fn test_ondemand_hidden_object() {
+        // levels = { 10 0=2 1=2 }
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x14, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x14, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x14, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            levels: (u32, HashMap<u32, u32>),
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "levels");
+
+        let actual: MyStruct = assert_same(&data, &map);
+        assert_eq!(actual.levels.0, 10);
+        assert_eq!(actual.levels.1[&0], 2);
+        assert_eq!(actual.levels.1[&1], 2);
+    }
+
+    #[test]
+    fn test_ondemand_eof() {
+        let data = [0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x14, 0x00];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            a: Vec<u32>,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+
+        let err = from_slice::<MyStruct>(&data, &map).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Eof { .. }));
+    }
+
+    #[test]
+    fn test_ondemand_too_many_close() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            a: u32,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+
+        let err = from_slice::<MyStruct>(&data, &map).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::StackEmpty { .. }));
+    }
+}
@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 
 /// Resolves binary 16bit tokens to field names
 ///
-/// One can create their own `TokenResolver` or rely on the HashMap implementation
+/// One can create their own `TokenResolver` or rely on one of the provided
+/// implementations for `HashMap`, `BTreeMap`, a `Vec` indexed by token, and
+/// function pointers.
 ///
 /// ```
 /// use std::collections::HashMap;
@@ -11,7 +14,7 @@ use std::collections::HashMap;
 /// let mut map = HashMap::new();
 /// map.insert(0x2d82, String::from("field1"));
 ///
-/// assert_eq!(map.resolve(0x2d82), Some("field1"));
+/// assert_eq!(map.resolve(0x2d82).as_deref(), Some("field1"));
 /// ```
 ///
 /// The HashMap implementation works with string slices as well
@@ -25,9 +28,27 @@ use std::collections::HashMap;
 ///
 /// assert_eq!(map.resolve(0x0000), None);
 /// ```
+///
+/// Resolvers that don't own their names, like those backed by a memory
+/// mapped token file or a database, can return owned strings
+///
+/// ```
+/// use jomini::TokenResolver;
+/// use std::borrow::Cow;
+///
+/// struct Lookup;
+///
+/// impl TokenResolver for Lookup {
+///     fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+///         Some(Cow::Owned(format!("token_{}", token)))
+///     }
+/// }
+///
+/// assert_eq!(Lookup.resolve(10).as_deref(), Some("token_10"));
+/// ```
 pub trait TokenResolver {
     /// Return the string field name of the 16bit token if found
-    fn resolve(&self, token: u16) -> Option<&str>;
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>>;
 }
 
 impl<S, V> TokenResolver for HashMap<u16, V, S>
@@ -35,13 +56,63 @@ where
     S: ::std::hash::BuildHasher,
     V: AsRef<str>,
 {
-    fn resolve(&self, token: u16) -> Option<&str> {
-        self.get(&token).map(|x| x.as_ref())
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+        self.get(&token).map(|x| Cow::Borrowed(x.as_ref()))
+    }
+}
+
+impl<V> TokenResolver for BTreeMap<u16, V>
+where
+    V: AsRef<str>,
+{
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+        self.get(&token).map(|x| Cow::Borrowed(x.as_ref()))
+    }
+}
+
+/// A vector indexed by the token, which is the most compact lookup for the
+/// dense token ranges of the games
+///
+/// ```
+/// use jomini::TokenResolver;
+///
+/// let tokens = vec![None, Some(String::from("field1"))];
+/// assert_eq!(tokens.resolve(0x0001).as_deref(), Some("field1"));
+/// assert_eq!(tokens.resolve(0x0000), None);
+/// assert_eq!(tokens.resolve(0x2d82), None);
+/// ```
+impl<V> TokenResolver for Vec<Option<V>>
+where
+    V: AsRef<str>,
+{
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+        self.get(usize::from(token))
+            .and_then(|x| x.as_ref())
+            .map(|x| Cow::Borrowed(x.as_ref()))
+    }
+}
+
+/// ```
+/// use jomini::TokenResolver;
+///
+/// fn lookup(token: u16) -> Option<&'static str> {
+///     match token {
+///         0x2d82 => Some("field1"),
+///         _ => None,
+///     }
+/// }
+///
+/// let resolver = lookup as fn(u16) -> Option<&'static str>;
+/// assert_eq!(resolver.resolve(0x2d82).as_deref(), Some("field1"));
+/// ```
+impl TokenResolver for fn(u16) -> Option<&'static str> {
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+        self(token).map(Cow::Borrowed)
     }
 }
 
 impl<T: TokenResolver> TokenResolver for &'_ T {
-    fn resolve(&self, token: u16) -> Option<&str> {
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
         (**self).resolve(token)
     }
 }
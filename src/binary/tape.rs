@@ -1,10 +1,24 @@
+use super::event::{next_lexeme, BinaryLexeme};
+use crate::data::{validate_structure, TapeNode, TokenCounter};
+#[cfg(feature = "metrics")]
+use crate::observer::{Observer, ParseObserver};
+use crate::trace::span;
 use crate::{
     util::{le_i32, le_u16, le_u32, le_u64},
     Ck3Flavor,
 };
-use crate::{BinaryFlavor, Error, ErrorKind, Eu4Flavor, Rgb, Scalar};
+use crate::{
+    BinaryFlavor, Diagnostic, Diagnostics, Error, ErrorKind, Eu4Flavor, HiddenObjectStrategy, Rgb,
+    Scalar,
+};
+use std::collections::HashMap;
 
 /// Represents any valid binary value
+///
+/// In the data, every value starts with a little endian 16bit id. Ids that
+/// are not one of the raw value types listed on the variants below (nor an
+/// open `0x0003`, end `0x0004`, or equal `0x0001` token) are tokens that are
+/// resolved to a name.
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryToken<'a> {
     /// Index of the `BinaryToken::End` that signifies this array's termination
@@ -16,7 +30,7 @@ pub enum BinaryToken<'a> {
     /// format is more strict
     Object(usize),
 
-    /// Index of the `TextToken::End` that signifies this objects's termination
+    /// Index of the `BinaryToken::End` that signifies this objects's termination
     ///
     /// A hidden object occurs where the first element is part of an array:
     ///
@@ -24,57 +38,185 @@ pub enum BinaryToken<'a> {
     /// a = { 10 a=b c=d}
     /// ```
     ///
-    /// In the above example, a and c would be part of the hidden object
+    /// In the above example, a and c would be part of the hidden object.
+    ///
+    /// As with text, a hidden object has no open or close tokens in the data.
+    /// Its `End` is synthesized and immediately followed by the `End` of the
+    /// enclosing array.
     HiddenObject(usize),
 
     /// Index of the start of this object
     End(usize),
 
     /// Represents a binary boolean.
+    ///
+    /// Id `0x000e` followed by a byte, where anything but zero is true
     Bool(bool),
 
     /// Represents a binary unsigned 32bit integer
+    ///
+    /// Id `0x0014` followed by a little endian u32
     U32(u32),
 
     /// Represents a binary unsigned 64bit integer
+    ///
+    /// Id `0x029c` followed by a little endian u64
     U64(u64),
 
-    /// Represents a binary signed 32bit integer
+    /// Represents a binary signed 32bit integer. The flavor may interpret it
+    /// as a date (see `BinaryFlavor::visit_date`).
+    ///
+    /// Id `0x000c` followed by a little endian i32
     I32(i32),
 
-    /// Represents a binary encoded string
+    /// Represents a binary encoded string that was quoted in the text format
+    ///
+    /// Id `0x000f` followed by a little endian u16 length and then that many
+    /// bytes, which are decoded by the flavor
     Text(Scalar<'a>),
 
     /// Represents the first binary encoding for representing a rational number
+    ///
+    /// Id `0x000d` followed by 4 bytes that are decoded by the flavor (see
+    /// `BinaryFlavor::visit_f32_1`)
     F32_1(f32),
 
     /// Represents the second binary encoding for representing a rational number
+    ///
+    /// Id `0x0167` followed by 8 bytes that are decoded by the flavor (see
+    /// `BinaryFlavor::visit_f32_2`)
     F32_2(f32),
 
     /// Represents a 16bit token key that can be resolved to an equivalent textual representation.
     Token(u16),
 
     /// Represents the index of the encoded rgb value
+    ///
+    /// Id `0x0243` followed by an open token, three u32 values that are each
+    /// preceded by the `0x0014` id, and an end token
     Rgb(Rgb),
+
+    /// Represents a binary encoded string that was unquoted in the text
+    /// format. Consumers that don't care about the distinction should treat
+    /// it the same as `Text` (see `BinaryToken::as_scalar`).
+    ///
+    /// Id `0x0017` followed by a little endian u16 length and then that many
+    /// bytes, which are decoded by the flavor
+    Unquoted(Scalar<'a>),
+}
+
+impl<'a> BinaryToken<'a> {
+    /// Returns the boolean of a `Bool`
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            BinaryToken::Bool(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer of a `U32`
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self {
+            BinaryToken::U32(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer of a `U64`
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            BinaryToken::U64(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer of an `I32`
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self {
+            BinaryToken::I32(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of either float encoding
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the undecoded bytes of a string, whether it was quoted or not
+    ///
+    /// ```
+    /// use jomini::{BinaryTape, Scalar};
+    ///
+    /// let data = [
+    ///     0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47,
+    ///     0x83, 0x2d, 0x01, 0x00, 0x17, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x48,
+    /// ];
+    /// let tape = BinaryTape::from_eu4(&data[..])?;
+    /// let tokens = tape.tokens();
+    /// assert_eq!(tokens[1].as_scalar(), Some(Scalar::new(b"ENG")));
+    /// assert_eq!(tokens[3].as_scalar(), Some(Scalar::new(b"ENH")));
+    /// assert!(tokens[1].is_quoted());
+    /// assert!(!tokens[3].is_quoted());
+    /// assert_eq!(tokens[2].as_token(), Some(0x2d83));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn as_scalar(&self) -> Option<Scalar<'a>> {
+        match *self {
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the token is a string that was quoted in the text format
+    pub fn is_quoted(&self) -> bool {
+        matches!(self, BinaryToken::Text(_))
+    }
+
+    /// Returns the id of a `Token`
+    pub fn as_token(&self) -> Option<u16> {
+        match *self {
+            BinaryToken::Token(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the color of an `Rgb`
+    pub fn as_rgb(&self) -> Option<Rgb> {
+        match *self {
+            BinaryToken::Rgb(x) => Some(x),
+            _ => None,
+        }
+    }
 }
 
-const END: u16 = 0x0004;
-const OPEN: u16 = 0x0003;
-const EQUAL: u16 = 0x0001;
-const U32: u16 = 0x0014;
-const U64: u16 = 0x029c;
-const I32: u16 = 0x000c;
-const BOOL: u16 = 0x000e;
-const STRING_1: u16 = 0x000f;
-const STRING_2: u16 = 0x0017;
-const F32_1: u16 = 0x000d;
-const F32_2: u16 = 0x0167;
-const RGB: u16 = 0x0243;
+pub(crate) const END: u16 = 0x0004;
+pub(crate) const OPEN: u16 = 0x0003;
+pub(crate) const EQUAL: u16 = 0x0001;
+pub(crate) const U32: u16 = 0x0014;
+pub(crate) const U64: u16 = 0x029c;
+pub(crate) const I32: u16 = 0x000c;
+pub(crate) const BOOL: u16 = 0x000e;
+pub(crate) const STRING_1: u16 = 0x000f;
+pub(crate) const STRING_2: u16 = 0x0017;
+pub(crate) const F32_1: u16 = 0x000d;
+pub(crate) const F32_2: u16 = 0x0167;
+pub(crate) const RGB: u16 = 0x0243;
 
 /// Customizes how the binary tape is parsed from data
 #[derive(Debug)]
 pub struct BinaryTapeParser<F> {
     flavor: F,
+    hidden_object_strategy: HiddenObjectStrategy,
+    exact_capacity: bool,
+    truncate_strings: bool,
+    max_depth: Option<usize>,
+    diagnostics: Option<Diagnostics>,
+    #[cfg(feature = "metrics")]
+    observer: Option<Observer>,
 }
 
 impl<F> BinaryTapeParser<F>
@@ -83,11 +225,101 @@ where
 {
     /// Create a binary parser with a given flavor
     pub fn with_flavor(flavor: F) -> Self {
-        BinaryTapeParser { flavor }
+        BinaryTapeParser {
+            flavor,
+            hidden_object_strategy: HiddenObjectStrategy::default(),
+            exact_capacity: false,
+            truncate_strings: false,
+            max_depth: None,
+            diagnostics: None,
+            #[cfg(feature = "metrics")]
+            observer: None,
+        }
+    }
+
+    /// Set the behavior when an array turns into an object partway through
+    /// (`levels={ 10 0=2 1=2 }`). See `TextTapeParser::on_hidden_object`.
+    pub fn on_hidden_object(mut self, strategy: HiddenObjectStrategy) -> Self {
+        self.hidden_object_strategy = strategy;
+        self
+    }
+
+    /// Return an error when objects and arrays are nested deeper than the
+    /// given depth. See `TextTapeParser::max_depth`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Count the tokens in a quick pass over the data before parsing so that
+    /// the tape is allocated once. See `TextTapeParser::exact_capacity`.
+    pub fn exact_capacity(mut self, enabled: bool) -> Self {
+        self.exact_capacity = enabled;
+        self
+    }
+
+    /// Recover from a string whose length prefix exceeds the remaining data,
+    /// which is a telltale sign of a corrupted save, instead of failing the
+    /// whole parse. The string is truncated to the available bytes, and as
+    /// the string consumes the rest of the data, any objects and arrays that
+    /// are still open are closed and a dangling key is dropped.
+    ///
+    /// Each truncation is recorded in the diagnostics, if attached.
+    ///
+    /// ```
+    /// use jomini::{BinaryTape, BinaryToken, Diagnostic, Diagnostics, Scalar};
+    ///
+    /// // `a = { b = "ENGLAND" }` where the string claims to be 0x10 bytes
+    /// let data = [
+    ///     0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00,
+    ///     0x0f, 0x00, 0x10, 0x00, 0x45, 0x4e, 0x47, 0x4c, 0x41, 0x4e, 0x44,
+    /// ];
+    /// assert!(BinaryTape::from_eu4(&data[..]).is_err());
+    ///
+    /// let diagnostics = Diagnostics::new();
+    /// let tape = BinaryTape::eu4_parser()
+    ///     .truncate_strings(true)
+    ///     .diagnostics(&diagnostics)
+    ///     .parse_slice(&data[..])?;
+    /// assert_eq!(
+    ///     tape.tokens(),
+    ///     &[
+    ///         BinaryToken::Token(0x2d82),
+    ///         BinaryToken::Object(4),
+    ///         BinaryToken::Token(0x2d83),
+    ///         BinaryToken::Text(Scalar::new(b"ENGLAND")),
+    ///         BinaryToken::End(1),
+    ///     ]
+    /// );
+    ///
+    /// let report = diagnostics.take();
+    /// assert!(matches!(
+    ///     report[0],
+    ///     Diagnostic::TruncatedString { offset: 12, length: 16, available: 7 }
+    /// ));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn truncate_strings(mut self, enabled: bool) -> Self {
+        self.truncate_strings = enabled;
+        self
+    }
+
+    /// Record recoverable problems into the given diagnostics
+    pub fn diagnostics(mut self, diagnostics: &Diagnostics) -> Self {
+        self.diagnostics = Some(diagnostics.clone());
+        self
+    }
+
+    /// Report the bytes parsed and tokens produced by each parse to the
+    /// observer. See `ParseObserver`.
+    #[cfg(feature = "metrics")]
+    pub fn observer(mut self, observer: &'static dyn ParseObserver) -> Self {
+        self.observer = Some(Observer(observer));
+        self
     }
 
     /// Parse the binary format according to the parser's flavor and return the data tape
-    pub fn parse_slice(self, data: &[u8]) -> Result<BinaryTape, Error> {
+    pub fn parse_slice(self, data: &[u8]) -> Result<BinaryTape<'_>, Error> {
         let mut res = BinaryTape::default();
         self.parse_slice_into_tape(data, &mut res)?;
         Ok(res)
@@ -99,20 +331,224 @@ where
         data: &'a [u8],
         tape: &mut BinaryTape<'a>,
     ) -> Result<(), Error> {
+        let span = span!("parse_binary", bytes = data.len(); tokens);
         let token_tape = &mut tape.token_tape;
         token_tape.clear();
+        if self.exact_capacity {
+            token_tape.reserve_exact(token_count(data));
+        } else {
+            token_tape.reserve(data.len() / 5);
+        }
 
-        token_tape.reserve(data.len() / 5);
         let mut state = ParserState {
             data,
             flavor: self.flavor,
             original_length: data.len(),
             token_tape,
+            hidden_object_strategy: self.hidden_object_strategy,
+            truncate_strings: self.truncate_strings,
+            truncated: false,
+            max_depth: self.max_depth.unwrap_or(usize::MAX),
+            depth: 0,
+            diagnostics: self.diagnostics.as_ref(),
         };
 
         state.parse()?;
+        span.record("tokens", tape.token_tape.len());
+
+        #[cfg(feature = "metrics")]
+        if let Some(Observer(observer)) = self.observer {
+            observer.bytes_parsed(data.len());
+            observer.tokens_produced(tape.token_tape.len());
+        }
+
         Ok(())
     }
+
+    /// Walk the binary data and give each lexeme to the callback without
+    /// allocating a tape. This is the fastest way to count, search for a
+    /// token, or compute statistics over a save, as the structure of the
+    /// data is not validated: `Open` and `Close` are reported as they are
+    /// encountered and it is up to the callback to track depth.
+    ///
+    /// ```
+    /// use jomini::{BinaryLexeme, BinaryTape};
+    ///
+    /// let data = [
+    ///     0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x04,
+    ///     0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x00,
+    /// ];
+    ///
+    /// let mut occurrences = 0;
+    /// BinaryTape::eu4_parser().parse_with(&data[..], |lexeme| {
+    ///     if lexeme == BinaryLexeme::Token(0x2d83) {
+    ///         occurrences += 1;
+    ///     }
+    /// })?;
+    /// assert_eq!(occurrences, 2);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parse_with<'a, V>(self, data: &'a [u8], mut visit: V) -> Result<(), Error>
+    where
+        V: FnMut(BinaryLexeme<'a>),
+    {
+        let mut d = data;
+        while !d.is_empty() {
+            let (len, lexeme) = next_lexeme(d, &self.flavor)
+                .ok_or_else(|| Error::eof("the rest of a binary token"))?;
+            visit(lexeme);
+            d = &d[len..];
+        }
+
+        Ok(())
+    }
+
+    /// Parse several documents that form a single logical namespace into one
+    /// tape. When a top level key appears in a later document, all the top
+    /// level entries of that key from earlier documents are dropped. See
+    /// `TextTapeParser::parse_slices`.
+    ///
+    /// ```
+    /// use jomini::BinaryTape;
+    ///
+    /// let base = [0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00];
+    /// let patch = [0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00];
+    /// let tape = BinaryTape::eu4_parser().parse_slices(vec![&base[..], &patch[..]])?;
+    /// assert_eq!(tape.tokens(), BinaryTape::from_eu4(&patch[..])?.tokens());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parse_slices<'a, I>(self, data: I) -> Result<BinaryTape<'a>, Error>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut tapes = Vec::new();
+        for d in data {
+            let parser = BinaryTapeParser {
+                flavor: &self.flavor,
+                hidden_object_strategy: self.hidden_object_strategy,
+                exact_capacity: self.exact_capacity,
+                truncate_strings: self.truncate_strings,
+                max_depth: self.max_depth,
+                diagnostics: self.diagnostics.clone(),
+                #[cfg(feature = "metrics")]
+                observer: self.observer,
+            };
+            tapes.push(parser.parse_slice(d)?);
+        }
+
+        // (document index, key, start index, end index) of every top level entry
+        let mut entries = Vec::new();
+        for (doc, tape) in tapes.iter().enumerate() {
+            let tokens = tape.tokens();
+            let mut ind = 0;
+            while ind + 1 < tokens.len() {
+                let end = match tokens[ind + 1] {
+                    BinaryToken::Array(x)
+                    | BinaryToken::Object(x)
+                    | BinaryToken::HiddenObject(x) => x + 1,
+                    _ => ind + 2,
+                };
+
+                let key = match tokens[ind] {
+                    BinaryToken::Token(x) => Some(EntryKey::Token(x)),
+                    BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                        Some(EntryKey::Text(x.view_data()))
+                    }
+                    _ => None,
+                };
+
+                entries.push((doc, key, ind, end));
+                ind = end;
+            }
+        }
+
+        let mut last_doc = HashMap::new();
+        for &(doc, key, _, _) in entries.iter() {
+            last_doc.insert(key, doc);
+        }
+
+        let mut res = BinaryTape::new();
+        let total = tapes.iter().map(|x| x.tokens().len()).sum();
+        res.token_tape.reserve(total);
+        for &(doc, key, start, end) in entries.iter() {
+            if last_doc.get(&key) != Some(&doc) {
+                continue;
+            }
+
+            extend_tape(&mut res.token_tape, &tapes[doc].tokens()[start..end], start);
+        }
+
+        Ok(res)
+    }
+}
+
+/// The identity of a top level key when merging documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum EntryKey<'a> {
+    Token(u16),
+    Text(&'a [u8]),
+}
+
+/// Append tokens that were sliced from another tape starting at `start`,
+/// rebasing the indices that they hold
+pub(crate) fn extend_tape<'a>(
+    dst: &mut Vec<BinaryToken<'a>>,
+    tokens: &[BinaryToken<'a>],
+    start: usize,
+) {
+    let new_start = dst.len();
+    let shift = |x: usize| x - start + new_start;
+    dst.extend(tokens.iter().map(|token| match *token {
+        BinaryToken::Array(x) => BinaryToken::Array(shift(x)),
+        BinaryToken::Object(x) => BinaryToken::Object(shift(x)),
+        BinaryToken::HiddenObject(x) => BinaryToken::HiddenObject(shift(x)),
+        BinaryToken::End(x) => BinaryToken::End(shift(x)),
+        ref x => x.clone(),
+    }));
+}
+
+/// Returns the number of tokens that parsing the data is expected to write
+/// to the tape
+fn token_count(data: &[u8]) -> usize {
+    let mut counter = TokenCounter::new();
+    let mut d = data;
+    while d.len() >= 2 {
+        let token_id = le_u16(d);
+        d = &d[2..];
+        let payload = match token_id {
+            OPEN => {
+                counter.open();
+                0
+            }
+            END => {
+                counter.close();
+                0
+            }
+            EQUAL => {
+                counter.operator(false);
+                0
+            }
+            U32 | I32 | F32_1 => 4,
+            U64 | F32_2 => 8,
+            BOOL => 1,
+            STRING_1 | STRING_2 => match d.get(..2) {
+                Some(x) => 2 + usize::from(le_u16(x)),
+                None => break,
+            },
+
+            // The rgb token wraps its own braces
+            RGB => 22,
+            _ => 0,
+        };
+
+        if !matches!(token_id, OPEN | END | EQUAL) {
+            counter.scalar();
+        }
+
+        d = d.get(payload..).unwrap_or_default();
+    }
+
+    counter.count()
 }
 
 struct ParserState<'a, 'b, F> {
@@ -120,6 +556,18 @@ struct ParserState<'a, 'b, F> {
     flavor: F,
     original_length: usize,
     token_tape: &'b mut Vec<BinaryToken<'a>>,
+    hidden_object_strategy: HiddenObjectStrategy,
+    truncate_strings: bool,
+    diagnostics: Option<&'b Diagnostics>,
+
+    // A string was truncated to the end of the data, so the end tokens of
+    // any open objects and arrays are synthesized
+    truncated: bool,
+
+    max_depth: usize,
+
+    // The number of objects and arrays that are currently open
+    depth: usize,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone, Eq)]
@@ -135,10 +583,58 @@ impl<'a, 'b, F> ParserState<'a, 'b, F>
 where
     F: BinaryFlavor,
 {
+    /// Returns true if the hidden object that was just started with a key
+    /// is only preceded by empty arrays in its array. The array is then
+    /// actually an object with leading empty objects:
+    /// `history={{} 1444.11.11={core=AAA}}`
+    fn only_empty_arrays_precede(&self, array_ind: usize) -> bool {
+        let hidden_ind = self.token_tape.len() - 2;
+        if !matches!(self.token_tape[hidden_ind], BinaryToken::Object(x) if x == array_ind) {
+            return false;
+        }
+
+        let mut start = hidden_ind - 1;
+        while start > array_ind {
+            match self.token_tape[start] {
+                BinaryToken::End(x) if x == start - 1 => start -= 2,
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Removes the hidden object token at the given index so that its fields
+    /// become elements of the enclosing array
+    fn flatten_hidden_object(&mut self, hidden_ind: usize) {
+        self.token_tape.remove(hidden_ind);
+        let shift = |x: usize| if x > hidden_ind { x - 1 } else { x };
+        for token in &mut self.token_tape[hidden_ind..] {
+            *token = match *token {
+                BinaryToken::Array(x) => BinaryToken::Array(shift(x)),
+                BinaryToken::Object(x) => BinaryToken::Object(shift(x)),
+                BinaryToken::HiddenObject(x) => BinaryToken::HiddenObject(shift(x)),
+                BinaryToken::End(x) => BinaryToken::End(shift(x)),
+                ref x => x.clone(),
+            };
+        }
+    }
+
     fn offset(&self, data: &[u8]) -> usize {
         self.original_length - data.len()
     }
 
+    /// Record that an object or array has been opened at the given offset
+    #[inline]
+    fn enter(&mut self, offset: usize) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(Error::depth_exceeded(offset));
+        }
+
+        Ok(())
+    }
+
     #[inline]
     fn parse_next_id_opt(&mut self, data: &'a [u8]) -> Option<(&'a [u8], u16)> {
         if let Some(val) = data.get(..2).map(le_u16) {
@@ -150,26 +646,39 @@ where
 
     #[inline]
     fn parse_next_id(&mut self, data: &'a [u8]) -> Result<(&'a [u8], u16), Error> {
-        self.parse_next_id_opt(data).ok_or_else(Error::eof)
+        match self.parse_next_id_opt(data) {
+            Some(x) => Ok(x),
+            None if self.truncated => Ok((data, END)),
+            None => Err(Error::eof("a token id")),
+        }
     }
 
     #[inline]
     fn parse_u32(&mut self, data: &'a [u8]) -> Result<&'a [u8], Error> {
-        let val = data.get(..4).map(le_u32).ok_or_else(Error::eof)?;
+        let val = data
+            .get(..4)
+            .map(le_u32)
+            .ok_or_else(|| Error::eof("u32 data"))?;
         self.token_tape.push(BinaryToken::U32(val));
         Ok(&data[4..])
     }
 
     #[inline]
     fn parse_u64(&mut self, data: &'a [u8]) -> Result<&'a [u8], Error> {
-        let val = data.get(..8).map(le_u64).ok_or_else(Error::eof)?;
+        let val = data
+            .get(..8)
+            .map(le_u64)
+            .ok_or_else(|| Error::eof("u64 data"))?;
         self.token_tape.push(BinaryToken::U64(val));
         Ok(&data[8..])
     }
 
     #[inline]
     fn parse_i32(&mut self, data: &'a [u8]) -> Result<&'a [u8], Error> {
-        let val = data.get(..4).map(le_i32).ok_or_else(Error::eof)?;
+        let val = data
+            .get(..4)
+            .map(le_i32)
+            .ok_or_else(|| Error::eof("i32 data"))?;
         self.token_tape.push(BinaryToken::I32(val));
         Ok(&data[4..])
     }
@@ -179,7 +688,7 @@ where
         let val = data
             .get(..4)
             .map(|x| self.flavor.visit_f32_1(x))
-            .ok_or_else(Error::eof)?;
+            .ok_or_else(|| Error::eof("f32 data"))?;
         self.token_tape.push(BinaryToken::F32_1(val));
         Ok(&data[4..])
     }
@@ -189,14 +698,17 @@ where
         let val = data
             .get(..8)
             .map(|x| self.flavor.visit_f32_2(x))
-            .ok_or_else(Error::eof)?;
+            .ok_or_else(|| Error::eof("f32 data"))?;
         self.token_tape.push(BinaryToken::F32_2(val));
         Ok(&data[8..])
     }
 
     #[inline]
     fn parse_bool(&mut self, data: &'a [u8]) -> Result<&'a [u8], Error> {
-        let val = data.get(0).map(|&x| x != 0).ok_or_else(Error::eof)?;
+        let val = data
+            .first()
+            .map(|&x| x != 0)
+            .ok_or_else(|| Error::eof("bool data"))?;
         self.token_tape.push(BinaryToken::Bool(val));
         Ok(&data[1..])
     }
@@ -209,25 +721,49 @@ where
                 g: le_u32(&x[10..]),
                 b: le_u32(&x[16..]),
             })
-            .ok_or_else(Error::eof)?;
+            .ok_or_else(|| Error::eof("rgb data"))?;
         self.token_tape.push(BinaryToken::Rgb(val));
         Ok(&data[22..])
     }
 
     #[inline]
-    fn parse_string(&mut self, data: &'a [u8]) -> Result<&'a [u8], Error> {
+    fn string_token(token_id: u16, scalar: Scalar<'a>) -> BinaryToken<'a> {
+        if token_id == STRING_2 {
+            BinaryToken::Unquoted(scalar)
+        } else {
+            BinaryToken::Text(scalar)
+        }
+    }
+
+    #[inline]
+    fn parse_string(&mut self, data: &'a [u8], token_id: u16) -> Result<&'a [u8], Error> {
         if data.len() >= 2 {
             let (text_len_data, rest) = data.split_at(2);
             let text_len = usize::from(le_u16(text_len_data));
             if rest.len() >= text_len {
                 let (text, rest) = rest.split_at(text_len);
                 let scalar = Scalar::new(text);
-                self.token_tape.push(BinaryToken::Text(scalar));
+                self.token_tape.push(Self::string_token(token_id, scalar));
                 return Ok(rest);
             }
+
+            if self.truncate_strings {
+                if let Some(diagnostics) = self.diagnostics {
+                    diagnostics.push(Diagnostic::TruncatedString {
+                        offset: self.offset(data),
+                        length: text_len,
+                        available: rest.len(),
+                    });
+                }
+
+                self.truncated = true;
+                let scalar = Scalar::new(rest);
+                self.token_tape.push(Self::string_token(token_id, scalar));
+                return Ok(&rest[rest.len()..]);
+            }
         }
 
-        Err(Error::eof())
+        Err(Error::eof("string data"))
     }
 
     fn parse(&mut self) -> Result<(), Error> {
@@ -255,11 +791,24 @@ where
         while state != ParseState::Error {
             let (d, token_id) = match self.parse_next_id_opt(data) {
                 Some((d, token_id)) => (d, token_id),
+                None if self.truncated => {
+                    // The value of a truncated key is lost
+                    if state == ParseState::KeyValueSeparator {
+                        self.token_tape.pop();
+                        state = ParseState::Key;
+                    }
+
+                    if parent_ind == 0 && state == ParseState::Key {
+                        return Ok(());
+                    }
+
+                    (data, END)
+                }
                 None => {
                     if parent_ind == 0 && state == ParseState::Key {
                         return Ok(());
                     } else {
-                        return Err(Error::eof());
+                        return Err(Error::eof("an end token"));
                     }
                 }
             };
@@ -282,7 +831,7 @@ where
                     state = SCALAR_STATE_NEXT[state as usize];
                 }
                 STRING_1 | STRING_2 => {
-                    data = self.parse_string(d)?;
+                    data = self.parse_string(d, token_id)?;
                     state = SCALAR_STATE_NEXT[state as usize];
                 }
                 F32_1 => {
@@ -296,50 +845,43 @@ where
 
                 OPEN => {
                     if state == ParseState::ObjectValue {
-                        if array_ind_of_hidden_obj.is_some() {
-                            // the `is_some` + `unwrap` approach is consistently 8-10% faster
-                            // on the eu4 benchmark and I'm not sure why
-                            let array_ind = array_ind_of_hidden_obj.take().unwrap();
-
-                            // before we error, we should check if we previously parsed an empty array
-                            // `history={{} 1444.11.11={core=AAA}}`
-                            // so we're going to go back up the stack until we see our parent object
-                            // and ensure that everything along the way is an empty array
-
-                            let mut start = self.token_tape.len() - 3;
-                            while start > array_ind {
-                                match self.token_tape[start] {
-                                    BinaryToken::End(x) if x == start - 1 => {
-                                        start -= 2;
-                                    }
-                                    _ => {
-                                        return Err(Error::new(ErrorKind::InvalidSyntax {
-                                            offset: self.offset(data) - 2,
-                                            msg: String::from(
-                                                "nested values inside a hidden object are unsupported",
-                                            ),
-                                        }));
-                                    }
-                                }
-                            }
-
-                            let empty_objects_to_remove = self.token_tape.len() - 2 - array_ind;
+                        // A container that follows a hidden object's key is either the
+                        // first field of an object with leading empty objects, or a
+                        // value of the hidden object, which only some strategies support
+                        match array_ind_of_hidden_obj {
+                            Some(array_ind) if self.only_empty_arrays_precede(array_ind) => {
+                                array_ind_of_hidden_obj = None;
+                                let empty_objects_to_remove = self.token_tape.len() - 2 - array_ind;
+
+                                let grand_ind = match self.token_tape[array_ind] {
+                                    BinaryToken::Array(x) => x,
+                                    _ => 0,
+                                };
 
-                            let grand_ind = match self.token_tape[array_ind] {
-                                BinaryToken::Array(x) => x,
-                                _ => 0,
-                            };
+                                for _ in 0..empty_objects_to_remove {
+                                    self.token_tape.remove(self.token_tape.len() - 3);
+                                }
 
-                            for _ in 0..empty_objects_to_remove {
-                                self.token_tape.remove(self.token_tape.len() - 3);
+                                parent_ind = array_ind;
+                                self.token_tape[parent_ind] = BinaryToken::Object(grand_ind);
                             }
-
-                            parent_ind = array_ind;
-                            self.token_tape[parent_ind] = BinaryToken::Object(grand_ind);
+                            Some(_)
+                                if self.hidden_object_strategy
+                                    == HiddenObjectStrategy::Synthesize =>
+                            {
+                                return Err(Error::new(ErrorKind::InvalidSyntax {
+                                    offset: self.offset(data) - 2,
+                                    msg: String::from(
+                                        "nested values inside a hidden object are unsupported",
+                                    ),
+                                }));
+                            }
+                            _ => {}
                         }
 
                         let ind = self.token_tape.len();
                         self.token_tape.push(BinaryToken::Array(0));
+                        self.enter(self.offset(data))?;
 
                         data = d;
                         let (d, token_id) = self.parse_next_id(data)?;
@@ -357,6 +899,7 @@ where
 
                                 self.token_tape[ind] = BinaryToken::Array(ind + 1);
                                 self.token_tape.push(BinaryToken::End(ind));
+                                self.depth -= 1;
                                 continue;
                             }
 
@@ -385,7 +928,7 @@ where
                                 data = self.parse_bool(data)?;
                             }
                             STRING_1 | STRING_2 => {
-                                data = self.parse_string(data)?;
+                                data = self.parse_string(data, token_id)?;
                             }
                             F32_1 => {
                                 data = self.parse_f32_1(data)?;
@@ -425,6 +968,8 @@ where
                     } else if state == ParseState::ArrayValue {
                         let ind = self.token_tape.len();
                         self.token_tape.push(BinaryToken::Array(0));
+                        self.enter(self.offset(data))?;
+                        let old_data = d;
                         let (d, token_id) = self.parse_next_id(d)?;
                         data = d;
 
@@ -439,15 +984,18 @@ where
 
                                 self.token_tape[ind] = BinaryToken::Array(ind + 1);
                                 self.token_tape.push(BinaryToken::End(ind));
+                                self.depth -= 1;
                                 continue;
                             }
 
                             // array of objects or another array
                             OPEN => {
                                 self.token_tape[ind] = BinaryToken::Array(parent_ind);
-                                parent_ind = self.token_tape.len();
-                                self.token_tape.push(BinaryToken::Array(ind));
+                                parent_ind = ind;
                                 state = ParseState::ArrayValue;
+
+                                // Rewind the data so that we can parse the nested open
+                                data = old_data;
                                 continue;
                             }
 
@@ -464,7 +1012,7 @@ where
                                 data = self.parse_bool(data)?;
                             }
                             STRING_1 | STRING_2 => {
-                                data = self.parse_string(data)?;
+                                data = self.parse_string(data, token_id)?;
                             }
                             F32_1 => {
                                 data = self.parse_f32_1(data)?;
@@ -541,11 +1089,37 @@ where
                             }));
                         }
 
-                        self.token_tape.push(BinaryToken::End(parent_ind));
-                        if let Some(array_ind) = array_ind_of_hidden_obj.take() {
-                            self.token_tape[parent_ind] = BinaryToken::HiddenObject(end_idx);
+                        // A hidden object is terminated by the end of its array. The
+                        // objects nested inside the hidden object end as normal.
+                        let hidden_array_ind = match array_ind_of_hidden_obj {
+                            Some(array_ind) if grand_ind == array_ind => {
+                                array_ind_of_hidden_obj.take()
+                            }
+                            _ => None,
+                        };
+
+                        if let Some(array_ind) = hidden_array_ind {
+                            match self.hidden_object_strategy {
+                                HiddenObjectStrategy::Synthesize
+                                | HiddenObjectStrategy::SynthesizeNested => {
+                                    self.token_tape.push(BinaryToken::End(parent_ind));
+                                    self.token_tape[parent_ind] =
+                                        BinaryToken::HiddenObject(end_idx);
+                                }
+                                HiddenObjectStrategy::Flatten => {
+                                    self.flatten_hidden_object(parent_ind)
+                                }
+                                HiddenObjectStrategy::Error => {
+                                    return Err(Error::new(ErrorKind::InvalidSyntax {
+                                        msg: String::from("hidden objects are not allowed"),
+                                        offset: self.offset(data),
+                                    }));
+                                }
+                            }
+
                             let end_idx = self.token_tape.len();
                             self.token_tape.push(BinaryToken::End(array_ind));
+                            self.depth -= 1;
 
                             // Grab the grand parent from the outer array. Even though the logic should
                             // be more strict (ie: throwing an error when if the parent array index doesn't exist,
@@ -571,7 +1145,9 @@ where
 
                             parent_ind = grand_ind;
                         } else {
+                            self.token_tape.push(BinaryToken::End(parent_ind));
                             self.token_tape[parent_ind] = BinaryToken::Object(end_idx);
+                            self.depth -= 1;
                             parent_ind = grand_ind;
                         }
                     } else if state == ParseState::ArrayValue {
@@ -590,6 +1166,7 @@ where
                         let end_idx = self.token_tape.len();
                         self.token_tape[parent_ind] = BinaryToken::Array(end_idx);
                         self.token_tape.push(BinaryToken::End(parent_ind));
+                        self.depth -= 1;
                         parent_ind = grand_ind;
                     } else if state == ParseState::ObjectValue {
                         return Err(Error::new(ErrorKind::InvalidSyntax {
@@ -624,6 +1201,13 @@ where
                             }));
                         }
 
+                        if array_ind_of_hidden_obj.is_some() {
+                            return Err(Error::new(ErrorKind::InvalidSyntax {
+                                msg: String::from("nested hidden objects are unsupported"),
+                                offset: self.offset(data),
+                            }));
+                        }
+
                         let hidden_object = BinaryToken::Object(parent_ind);
                         array_ind_of_hidden_obj = Some(parent_ind);
                         parent_ind = self.token_tape.len() - 1;
@@ -697,6 +1281,98 @@ impl<'a> BinaryTape<'a> {
     pub fn tokens(&self) -> &[BinaryToken<'a>] {
         self.token_tape.as_slice()
     }
+
+    /// Iterate over the ids of the 16bit tokens in the order that they
+    /// appear, whether they are keys or values
+    ///
+    /// ```
+    /// use jomini::BinaryTape;
+    ///
+    /// let data = [0x82, 0x2d, 0x01, 0x00, 0x83, 0x2d, 0x84, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00];
+    /// let tape = BinaryTape::from_eu4(&data[..])?;
+    /// assert_eq!(tape.token_ids().collect::<Vec<_>>(), vec![0x2d82, 0x2d83, 0x2d84]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn token_ids(&self) -> impl Iterator<Item = u16> + '_ {
+        self.token_tape.iter().filter_map(BinaryToken::as_token)
+    }
+
+    /// Count the occurrences of each token id, ordered by most occurrences
+    /// first (ties are ordered by id). Filtering the histogram with a token
+    /// resolver shows which unknown tokens are most worth identifying after
+    /// a game update.
+    ///
+    /// ```
+    /// use jomini::{BinaryTape, TokenResolver};
+    /// use std::collections::HashMap;
+    ///
+    /// let data = [
+    ///     0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ///     0x83, 0x2d, 0x01, 0x00, 0x84, 0x2d,
+    ///     0x83, 0x2d, 0x01, 0x00, 0x84, 0x2d,
+    /// ];
+    /// let tape = BinaryTape::from_eu4(&data[..])?;
+    /// assert_eq!(
+    ///     tape.token_histogram(),
+    ///     vec![(0x2d83, 2), (0x2d84, 2), (0x2d82, 1)]
+    /// );
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(0x2d83, "owner");
+    /// let unknown: Vec<_> = tape
+    ///     .token_histogram()
+    ///     .into_iter()
+    ///     .filter(|(id, _)| map.resolve(*id).is_none())
+    ///     .collect();
+    /// assert_eq!(unknown, vec![(0x2d84, 2), (0x2d82, 1)]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn token_histogram(&self) -> Vec<(u16, usize)> {
+        let mut counts: HashMap<u16, usize> = HashMap::new();
+        for id in self.token_ids() {
+            *counts.entry(id).or_insert(0) += 1;
+        }
+
+        let mut result: Vec<_> = counts.into_iter().collect();
+        result.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        result
+    }
+
+    pub(crate) fn from_tokens(token_tape: Vec<BinaryToken<'a>>) -> Self {
+        BinaryTape { token_tape }
+    }
+
+    /// Create a tape from tokens that were constructed by hand (eg: in
+    /// tests), after checking that they are structurally valid. See
+    /// `BinaryTape::validate`.
+    pub fn try_from_tokens(token_tape: Vec<BinaryToken<'a>>) -> Result<Self, Error> {
+        let tape = BinaryTape::from_tokens(token_tape);
+        tape.validate()?;
+        Ok(tape)
+    }
+
+    /// Check that every object and array points at the end token that
+    /// closes it, that the end token points back, and that objects and
+    /// arrays are properly nested. See `TextTape::validate`.
+    ///
+    /// ```
+    /// use jomini::{BinaryTape, BinaryToken};
+    ///
+    /// let tokens = vec![BinaryToken::Token(0x2d82), BinaryToken::Object(2), BinaryToken::End(1)];
+    /// assert!(BinaryTape::try_from_tokens(tokens).is_ok());
+    ///
+    /// let tokens = vec![BinaryToken::Token(0x2d82), BinaryToken::Object(3), BinaryToken::End(1)];
+    /// assert!(BinaryTape::try_from_tokens(tokens).is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_structure(self.tokens().iter().map(|x| match *x {
+            BinaryToken::Array(end) | BinaryToken::Object(end) | BinaryToken::HiddenObject(end) => {
+                TapeNode::Open(end)
+            }
+            BinaryToken::End(start) => TapeNode::End(start),
+            _ => TapeNode::Leaf,
+        }))
+    }
 }
 
 /// Returns the number of fields left in an object
@@ -761,6 +1437,74 @@ mod tests {
         assert!(!std::mem::needs_drop::<BinaryToken>());
     }
 
+    #[test]
+    fn test_truncated_strings() {
+        fn parse(data: &[u8]) -> Vec<BinaryToken<'_>> {
+            let tape = BinaryTape::eu4_parser()
+                .truncate_strings(true)
+                .parse_slice(data)
+                .unwrap();
+            tape.tokens().to_vec()
+        }
+
+        // A truncated key is dropped along with its missing value
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x0f, 0x00, 0x05, 0x00, 0x45,
+        ];
+        assert!(BinaryTape::from_eu4(&data[..]).is_err());
+        assert_eq!(
+            parse(&data[..]),
+            vec![
+                BinaryToken::Token(0x2d82),
+                BinaryToken::Object(4),
+                BinaryToken::Token(0x2d83),
+                BinaryToken::I32(1),
+                BinaryToken::End(1),
+            ]
+        );
+
+        // The first element of an array closes the array
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0f, 0x00, 0x05, 0x00, 0x45, 0x4e,
+        ];
+        assert_eq!(
+            parse(&data[..]),
+            vec![
+                BinaryToken::Token(0x2d82),
+                BinaryToken::Array(3),
+                BinaryToken::Text(Scalar::new(b"EN")),
+                BinaryToken::End(1),
+            ]
+        );
+
+        // Nested containers are all closed
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x05, 0x00, 0x45,
+        ];
+        assert_eq!(
+            parse(&data[..]),
+            vec![
+                BinaryToken::Token(0x2d82),
+                BinaryToken::Object(7),
+                BinaryToken::Token(0x2d83),
+                BinaryToken::Array(6),
+                BinaryToken::I32(1),
+                BinaryToken::Text(Scalar::new(b"E")),
+                BinaryToken::End(3),
+                BinaryToken::End(1),
+            ]
+        );
+
+        // A string that fits is untouched
+        let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x01, 0x00, 0x45];
+        assert_eq!(
+            parse(&data[..]),
+            BinaryTape::from_eu4(&data[..]).unwrap().tokens()
+        );
+    }
+
     #[test]
     fn test_parse_offset() {
         let data = [0x82, 0x2d, 0x01, 0x00, 0x4c, 0x28, 0x01, 0x00, 0x4c, 0x28];
@@ -769,7 +1513,7 @@ mod tests {
             ErrorKind::InvalidSyntax { offset, .. } => {
                 assert_eq!(*offset, 6);
             }
-            _ => assert!(false),
+            _ => panic!("unexpected error kind"),
         }
 
         let data2 = [0x82, 0x2d, 0x01, 0x00, 0x01, 0x00];
@@ -778,7 +1522,7 @@ mod tests {
             ErrorKind::InvalidSyntax { offset, .. } => {
                 assert_eq!(*offset, 4);
             }
-            _ => assert!(false),
+            _ => panic!("unexpected error kind"),
         }
     }
 
@@ -915,7 +1659,7 @@ mod tests {
             tape.token_tape,
             vec![
                 BinaryToken::Token(0x2d82),
-                BinaryToken::Text(Scalar::new(b"ENG")),
+                BinaryToken::Unquoted(Scalar::new(b"ENG")),
             ]
         );
     }
@@ -1104,7 +1848,7 @@ mod tests {
                 BinaryToken::Token(0x2838),
                 BinaryToken::Object(6),
                 BinaryToken::Token(0x2863),
-                BinaryToken::Text(Scalar::new(b"western")),
+                BinaryToken::Unquoted(Scalar::new(b"western")),
                 BinaryToken::Text(Scalar::new(b"1446.5.31")),
                 BinaryToken::Token(0x2838),
                 BinaryToken::End(1),
@@ -1156,6 +1900,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_with() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x0c,
+            0x00, 0x59, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        let mut lexemes = Vec::new();
+        BinaryTape::eu4_parser()
+            .parse_with(&data[..], |x| lexemes.push(x))
+            .unwrap();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                BinaryLexeme::Token(0x2d82),
+                BinaryLexeme::Equal,
+                BinaryLexeme::Open,
+                BinaryLexeme::Text(Scalar::new(b"ENG")),
+                BinaryLexeme::I32(89),
+                BinaryLexeme::Close,
+            ]
+        );
+
+        let err = BinaryTape::eu4_parser()
+            .parse_with(&data[..data.len() - 3], |_| {})
+            .unwrap_err();
+        assert!(err.is_eof());
+    }
+
     #[test]
     fn test_rgb() {
         let data = [
@@ -1210,6 +1984,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_object_in_nested_arrays() {
+        // field1={ { { field2=yes } } }
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x03, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00,
+            0x4b, 0x28, 0x04, 0x00, 0x04, 0x00, 0x04, 0x00,
+        ];
+
+        assert_eq!(
+            parse(&data[..]).unwrap().token_tape,
+            vec![
+                BinaryToken::Token(0x2d82),
+                BinaryToken::Array(8),
+                BinaryToken::Array(7),
+                BinaryToken::Object(6),
+                BinaryToken::Token(0x2d83),
+                BinaryToken::Token(0x284b),
+                BinaryToken::End(3),
+                BinaryToken::End(2),
+                BinaryToken::End(1),
+            ]
+        );
+    }
+
     #[test]
     fn test_heterogenous_list() {
         let data = [
@@ -1241,6 +2039,96 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_max_depth() {
+        // field1={ { { field2=yes } } }
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x03, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00,
+            0x4b, 0x28, 0x04, 0x00, 0x04, 0x00, 0x04, 0x00,
+        ];
+
+        let parser = || BinaryTapeParser::with_flavor(Eu4Flavor::new());
+        assert!(parser().max_depth(3).parse_slice(&data[..]).is_ok());
+        let err = parser().max_depth(2).parse_slice(&data[..]).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::LimitExceeded { offset: 8, .. }
+        ));
+
+        // field1={ {} } field2={ field3=yes }
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x03, 0x00, 0x04, 0x00, 0x04, 0x00, 0x83, 0x2d,
+            0x01, 0x00, 0x03, 0x00, 0x84, 0x2d, 0x01, 0x00, 0x4b, 0x28, 0x04, 0x00,
+        ];
+        assert!(parser().max_depth(2).parse_slice(&data[..]).is_ok());
+        assert!(parser().max_depth(1).parse_slice(&data[..]).is_err());
+    }
+
+    #[test]
+    fn test_hidden_object_with_containers() {
+        let data = [
+            0x6f, 0x34, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00,
+            0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        assert!(parse(&data[..]).is_err());
+
+        let mut tape = BinaryTape::new();
+        BinaryTapeParser::with_flavor(Eu4Flavor::new())
+            .on_hidden_object(HiddenObjectStrategy::SynthesizeNested)
+            .parse_slice_into_tape(&data[..], &mut tape)
+            .unwrap();
+
+        assert_eq!(
+            tape.tokens(),
+            vec![
+                BinaryToken::Token(0x346f),
+                BinaryToken::Array(12),
+                BinaryToken::I32(10),
+                BinaryToken::HiddenObject(11),
+                BinaryToken::I32(0),
+                BinaryToken::Array(8),
+                BinaryToken::I32(1),
+                BinaryToken::I32(2),
+                BinaryToken::End(5),
+                BinaryToken::I32(1),
+                BinaryToken::U32(2),
+                BinaryToken::End(3),
+                BinaryToken::End(1),
+            ]
+        );
+
+        let mut tape = BinaryTape::new();
+        BinaryTapeParser::with_flavor(Eu4Flavor::new())
+            .on_hidden_object(HiddenObjectStrategy::Flatten)
+            .parse_slice_into_tape(&data[..], &mut tape)
+            .unwrap();
+
+        assert_eq!(
+            tape.tokens(),
+            vec![
+                BinaryToken::Token(0x346f),
+                BinaryToken::Array(10),
+                BinaryToken::I32(10),
+                BinaryToken::I32(0),
+                BinaryToken::Array(7),
+                BinaryToken::I32(1),
+                BinaryToken::I32(2),
+                BinaryToken::End(4),
+                BinaryToken::I32(1),
+                BinaryToken::U32(2),
+                BinaryToken::End(1),
+            ]
+        );
+
+        let res = BinaryTapeParser::with_flavor(Eu4Flavor::new())
+            .on_hidden_object(HiddenObjectStrategy::Error)
+            .parse_slice(&data[..]);
+        assert!(res.is_err());
+    }
+
     #[test]
     fn test_binary_tape_parser() {
         let mut tape = BinaryTape::new();
@@ -1368,6 +2256,58 @@ mod tests {
         assert!(parse(&data[..]).is_err());
     }
 
+    #[test]
+    fn test_token_count_matches_parse() {
+        let meta = include_bytes!("../../tests/fixtures/meta.bin");
+        let ck3 = include_bytes!("../../tests/fixtures/ck3-header.bin");
+        let hidden = [
+            0x6f, 0x34, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        let tape = BinaryTape::from_eu4(&meta["EU4bin".len()..]).unwrap();
+        assert_eq!(token_count(&meta["EU4bin".len()..]), tape.tokens().len());
+
+        let tape = BinaryTape::from_ck3(&ck3[..]).unwrap();
+        assert_eq!(token_count(&ck3[..]), tape.tokens().len());
+
+        let tape = parse(&hidden[..]).unwrap();
+        assert_eq!(token_count(&hidden[..]), tape.tokens().len());
+
+        let exact = BinaryTape::eu4_parser()
+            .exact_capacity(true)
+            .parse_slice(&hidden[..])
+            .unwrap();
+        assert_eq!(exact.tokens(), tape.tokens());
+    }
+
+    #[test]
+    fn test_validate_parsed_tapes() {
+        let meta = include_bytes!("../../tests/fixtures/meta.bin");
+        let ck3 = include_bytes!("../../tests/fixtures/ck3-header.bin");
+        let hidden = [
+            0x6f, 0x34, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0c, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x14, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+
+        let tape = BinaryTape::from_eu4(&meta["EU4bin".len()..]).unwrap();
+        tape.validate().unwrap();
+        BinaryTape::from_ck3(&ck3[..]).unwrap().validate().unwrap();
+        parse(&hidden[..]).unwrap().validate().unwrap();
+
+        let mut tokens = tape.tokens().to_vec();
+        let idx = tokens
+            .iter()
+            .position(|x| matches!(x, BinaryToken::Object(_)))
+            .unwrap();
+        if let BinaryToken::Object(end) = &mut tokens[idx] {
+            *end += 1;
+        }
+        assert!(BinaryTape::try_from_tokens(tokens).is_err());
+    }
+
     #[test]
     fn test_binary_protect_against_deeply_nested() {
         let mut data = vec![0x63, 0x28, 0x01, 0x00];
@@ -1393,8 +2333,14 @@ mod tests {
     #[test]
     fn test_should_not_parse_on_eof() {
         let data = [65, 1, 3, 0, 3, 0, 3, 0, 4, 0];
-        let res = parse(&data[..]);
-        assert!(res.is_err());
+        let err = parse(&data[..]).unwrap_err();
+        assert!(err.is_eof());
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::Eof {
+                expected: "an end token"
+            }
+        ));
     }
 
     #[test]
@@ -0,0 +1,254 @@
+use super::TokenResolver;
+use crate::{Error, ErrorKind};
+use std::borrow::Cow;
+
+const MAGIC: &[u8] = b"JTOK";
+const VERSION: u8 = 1;
+const METHOD_XOR: u8 = 0;
+const METHOD_AES: u8 = 1;
+#[cfg(feature = "token-aes")]
+const NONCE_LEN: usize = 16;
+
+/// Token names loaded from a token file.
+///
+/// A token file lists a token and its name on each line, separated by
+/// whitespace and in either order. Tokens can be written in decimal or in
+/// hex with a `0x` prefix. Blank lines and lines starting with `#` are
+/// skipped.
+///
+/// Since token files may not be distributed openly, applications can instead
+/// ship them obfuscated or encrypted with [`TokenTable::obfuscate`] (or
+/// `TokenTable::encrypt` with the `token-aes` feature) and load them at
+/// runtime with [`TokenTable::from_obfuscated`], which accepts either form.
+/// Keep in mind that a key embedded in an application can be recovered, so
+/// this only keeps the names from being trivially extracted.
+///
+/// ```
+/// use jomini::{TokenResolver, TokenTable};
+///
+/// let data = b"0x2d82 field1\nfield2 11651\n";
+/// let key = b"not a real secret";
+/// let shipped = TokenTable::obfuscate(&data[..], &key[..]);
+/// assert!(!shipped.windows(6).any(|x| x == b"field1"));
+///
+/// let tokens = TokenTable::from_obfuscated(&shipped, &key[..])?;
+/// assert_eq!(tokens.resolve(0x2d82).as_deref(), Some("field1"));
+/// assert_eq!(tokens.resolve(0x2d83).as_deref(), Some("field2"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenTable {
+    names: Vec<Option<String>>,
+}
+
+impl TokenTable {
+    /// Parse a plain token file
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let data = std::str::from_utf8(data).map_err(|e| Error::new(ErrorKind::Utf8(e)))?;
+        let mut names = Vec::new();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let (first, second) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(first), Some(second), None) => (first, second),
+                _ => return Err(invalid_line(i)),
+            };
+
+            let (token, name) = match (parse_token(first), parse_token(second)) {
+                (Some(token), _) => (token, second),
+                (None, Some(token)) => (token, first),
+                (None, None) => return Err(invalid_line(i)),
+            };
+
+            let idx = usize::from(token);
+            if names.len() <= idx {
+                names.resize(idx + 1, None);
+            }
+            names[idx] = Some(String::from(name));
+        }
+
+        Ok(TokenTable { names })
+    }
+
+    /// Load a token file that was obfuscated or encrypted with the given key
+    pub fn from_obfuscated(data: &[u8], key: &[u8]) -> Result<Self, Error> {
+        let header_len = MAGIC.len() + 2;
+        if data.len() < header_len || &data[..MAGIC.len()] != MAGIC {
+            return Err(resolve_error("token file is missing its header"));
+        }
+
+        if data[MAGIC.len()] != VERSION {
+            return Err(resolve_error("unsupported token file version"));
+        }
+
+        let body = &data[header_len..];
+        let plain = match data[MAGIC.len() + 1] {
+            METHOD_XOR => xor(body, key)?,
+            METHOD_AES => decrypt_aes(body, key)?,
+            _ => return Err(resolve_error("unknown token file encryption method")),
+        };
+
+        Self::parse(&plain)
+    }
+
+    /// Obfuscate a plain token file by xor-ing it with the key
+    ///
+    /// # Panics
+    ///
+    /// If the key is empty
+    pub fn obfuscate(data: &[u8], key: &[u8]) -> Vec<u8> {
+        assert!(!key.is_empty(), "obfuscation key must not be empty");
+        let mut out = header(METHOD_XOR);
+        out.extend(data.iter().zip(key.iter().cycle()).map(|(x, k)| x ^ k));
+        out
+    }
+
+    /// Encrypt a plain token file with AES-256 in counter mode. The nonce
+    /// should be unique for each file encrypted with the same key.
+    #[cfg(feature = "token-aes")]
+    pub fn encrypt(data: &[u8], key: &[u8; 32], nonce: &[u8; NONCE_LEN]) -> Vec<u8> {
+        let mut out = header(METHOD_AES);
+        out.extend_from_slice(nonce);
+        let start = out.len();
+        out.extend_from_slice(data);
+        apply_aes(&mut out[start..], key, nonce);
+        out
+    }
+
+    /// Returns the number of tokens that have a name
+    pub fn len(&self) -> usize {
+        self.names.iter().filter(|x| x.is_some()).count()
+    }
+
+    /// Returns true if no tokens have a name
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl TokenResolver for TokenTable {
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+        self.names.resolve(token)
+    }
+}
+
+fn header(method: u8) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(method);
+    out
+}
+
+fn parse_token(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn invalid_line(line: usize) -> Error {
+    resolve_error(&format!(
+        "expected a token and name on line {} of token file",
+        line + 1
+    ))
+}
+
+fn resolve_error(msg: &str) -> Error {
+    Error::new(ErrorKind::Resolve(String::from(msg)))
+}
+
+fn xor(data: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    if key.is_empty() {
+        return Err(resolve_error("obfuscation key must not be empty"));
+    }
+
+    Ok(data
+        .iter()
+        .zip(key.iter().cycle())
+        .map(|(x, k)| x ^ k)
+        .collect())
+}
+
+#[cfg(feature = "token-aes")]
+fn apply_aes(data: &mut [u8], key: &[u8; 32], nonce: &[u8; NONCE_LEN]) {
+    use aes::cipher::{KeyIvInit, StreamCipher};
+    let mut cipher = ctr::Ctr128BE::<aes::Aes256>::new(key.into(), nonce.into());
+    cipher.apply_keystream(data);
+}
+
+#[cfg(feature = "token-aes")]
+fn decrypt_aes(data: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    use std::convert::TryFrom;
+    let key = <&[u8; 32]>::try_from(key)
+        .map_err(|_| resolve_error("aes token files require a 32 byte key"))?;
+
+    if data.len() < NONCE_LEN {
+        return Err(Error::eof("token file nonce"));
+    }
+
+    let (nonce, body) = data.split_at(NONCE_LEN);
+    let nonce = <&[u8; NONCE_LEN]>::try_from(nonce).unwrap();
+    let mut plain = body.to_vec();
+    apply_aes(&mut plain, key, nonce);
+    Ok(plain)
+}
+
+#[cfg(not(feature = "token-aes"))]
+fn decrypt_aes(_data: &[u8], _key: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(resolve_error(
+        "aes token files require the token-aes feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_token_file() {
+        let data = b"# eu4\n0x284d date\n\nplayer 10808\n  0x000b   id  \n";
+        let tokens = TokenTable::parse(&data[..]).unwrap();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens.resolve(0x284d).as_deref(), Some("date"));
+        assert_eq!(tokens.resolve(0x2a38).as_deref(), Some("player"));
+        assert_eq!(tokens.resolve(0x000b).as_deref(), Some("id"));
+        assert_eq!(tokens.resolve(0x000c), None);
+
+        let err = TokenTable::parse(b"0x284d date\nplayer\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        assert!(TokenTable::parse(b"a b").is_err());
+    }
+
+    #[test]
+    fn test_obfuscated_token_file() {
+        let data = b"0x284d date";
+        let shipped = TokenTable::obfuscate(&data[..], b"key");
+        assert_eq!(
+            TokenTable::from_obfuscated(&shipped, b"key").unwrap(),
+            TokenTable::parse(&data[..]).unwrap()
+        );
+
+        assert!(TokenTable::from_obfuscated(&shipped, b"").is_err());
+        assert!(TokenTable::from_obfuscated(&data[..], b"key").is_err());
+        assert!(TokenTable::from_obfuscated(&shipped[..5], b"key").is_err());
+    }
+
+    #[cfg(feature = "token-aes")]
+    #[test]
+    fn test_encrypted_token_file() {
+        let data = b"0x284d date\n0x2a38 player";
+        let key = [7u8; 32];
+        let shipped = TokenTable::encrypt(&data[..], &key, &[1u8; 16]);
+        assert!(!shipped.windows(4).any(|x| x == b"date"));
+
+        let tokens = TokenTable::from_obfuscated(&shipped, &key).unwrap();
+        assert_eq!(tokens.resolve(0x2a38).as_deref(), Some("player"));
+
+        assert!(TokenTable::from_obfuscated(&shipped, &key[..16]).is_err());
+        assert!(TokenTable::from_obfuscated(&shipped, &[8u8; 32]).is_err());
+    }
+}
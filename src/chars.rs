@@ -0,0 +1,72 @@
+//! Character classification used by the text parser.
+//!
+//! Tools that tokenize the text format on their own, like syntax
+//! highlighters and language servers, can use these functions to split
+//! scalars exactly where the parser does.
+//!
+//! ```
+//! use jomini::chars;
+//!
+//! let data = b"a=b{c}";
+//! let ends: Vec<_> = data.iter().map(|&x| chars::is_boundary(x)).collect();
+//! assert_eq!(ends, vec![false, true, false, true, false, true]);
+//! ```
+
+use crate::data::{self, CHARACTER_CLASS, COMMENT, OPERATOR};
+
+/// Returns true if the byte is whitespace that separates tokens: a space,
+/// tab, line feed, vertical tab, form feed, or carriage return
+///
+/// ```
+/// use jomini::chars;
+///
+/// assert!(chars::is_whitespace(b'\t'));
+/// assert!(!chars::is_whitespace(b'='));
+/// ```
+#[inline]
+pub fn is_whitespace(b: u8) -> bool {
+    data::is_whitespace(b)
+}
+
+/// Returns true if the byte ends an unquoted scalar: whitespace, an operator
+/// character, the start of a comment, or a brace
+///
+/// ```
+/// use jomini::chars;
+///
+/// assert!(chars::is_boundary(b'}'));
+/// assert!(chars::is_boundary(b'#'));
+/// assert!(!chars::is_boundary(b'"'));
+/// assert!(!chars::is_boundary(b'.'));
+/// ```
+#[inline]
+pub fn is_boundary(b: u8) -> bool {
+    data::is_boundary(b)
+}
+
+/// Returns true if the byte is one of the characters that make up an
+/// operator (`=`, `<`, `>`, and `!`)
+///
+/// ```
+/// use jomini::chars;
+///
+/// assert!(chars::is_operator(b'<'));
+/// assert!(!chars::is_operator(b'{'));
+/// ```
+#[inline]
+pub fn is_operator(b: u8) -> bool {
+    CHARACTER_CLASS[usize::from(b)] == OPERATOR
+}
+
+/// Returns true if the byte starts a comment that runs to the end of the line
+///
+/// ```
+/// use jomini::chars;
+///
+/// assert!(chars::is_comment_start(b'#'));
+/// assert!(!chars::is_comment_start(b'/'));
+/// ```
+#[inline]
+pub fn is_comment_start(b: u8) -> bool {
+    CHARACTER_CLASS[usize::from(b)] == COMMENT
+}
@@ -8,7 +8,10 @@ const DAYS_PER_MONTH: [u8; 13] = [0, 31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30,
 ///
 /// A game date does not follow any traditional calendar and instead views the
 /// world on simpler terms: that every year should be treated as a non-leap year.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Years span the full range of an `i16`, so dates before year 0 (common in
+/// CK3) and after year 9999 are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Date {
     year: i16,
     month: u8,
@@ -44,14 +47,11 @@ impl Date {
     /// assert!(Date::new(800, 13, 1).is_none());
     /// assert!(Date::new(800, 12, 32).is_none());
     /// assert!(Date::new(2020, 2, 29).is_none());
+    /// assert_eq!(Date::new(-2500, 3, 1).map(|x| x.year()), Some(-2500));
+    /// assert_eq!(Date::new(12000, 3, 1).map(|x| x.year()), Some(12000));
     /// ```
     pub fn new(year: i16, month: u8, day: u8) -> Option<Self> {
-        // Years can be negative in EU4 (but seemingly can't be zero). Antonio
-        // I holds the record at with `birth_date=-58.1.1` and since I don't
-        // anticipate more rulers to be added that nearly double the negative,
-        // we cap what we're looking for at -100 years (we don't want the
-        // melter to accidentally think a number is a date)
-        if year != 0 && month != 0 && day != 0 && year > -100 {
+        if month != 0 && day != 0 {
             if let Some(&days) = DAYS_PER_MONTH.get(usize::from(month)) {
                 if day <= days {
                     return Some(Date { year, month, day });
@@ -115,7 +115,7 @@ impl Date {
         // is outside our upper bound (ie: not a number). This micro optimization doesn't
         // harm the happy path (input is a date) by more than a few percent, but if the input
         // is not a date, this shaves off 20-25% in date parsing benchmarks.
-        if data.get(0).map_or(true, |c| *c > b'9') {
+        if !matches!(data.first(), Some(c) if *c <= b'9') {
             return None;
         }
 
@@ -141,36 +141,24 @@ impl Date {
         let year = Scalar::new(span1)
             .to_i64()
             .ok()
-            .and_then(|x| i16::try_from(x).ok());
-
-        let year = match year {
-            Some(x) => x,
-            None => return None,
-        };
+            .and_then(|x| i16::try_from(x).ok())?;
 
         let month = Scalar::new(span2)
             .to_u64()
             .ok()
-            .and_then(|x| u8::try_from(x).ok());
-
-        let month = match month {
-            Some(x) => x,
-            None => return None,
-        };
+            .and_then(|x| u8::try_from(x).ok())?;
 
         let day = Scalar::new(span3)
             .to_u64()
             .ok()
-            .and_then(|x| u8::try_from(x).ok());
-
-        let day = match day {
-            Some(x) => x,
-            None => return None,
-        };
+            .and_then(|x| u8::try_from(x).ok())?;
 
         Date::new(year, month, day)
     }
 
+    /// The number of days since 0.1.1, which is negative for dates before
+    /// then. Every year has 365 days, so the day numbering is continuous
+    /// across negative years and year 0.
     fn days(&self) -> i32 {
         let month_days = match self.month {
             1 => -1,
@@ -188,12 +176,7 @@ impl Date {
             _ => unreachable!(),
         };
 
-        let year_day = i32::from(self.year) * 365;
-        if year_day < 0 {
-            year_day - month_days - i32::from(self.day)
-        } else {
-            year_day + month_days + i32::from(self.day)
-        }
+        i32::from(self.year) * 365 + month_days + i32::from(self.day)
     }
 
     /// Returns the number of days between two dates
@@ -233,33 +216,186 @@ impl Date {
             .checked_add(days)
             .expect("adding days overflowed");
 
-        let days_since_jan1 = (new_days % 365).abs();
-        let year = new_days / 365;
+        let days_since_jan1 = new_days.rem_euclid(365);
+        let year = new_days.div_euclid(365);
         let (month, day) = month_day_from_julian(days_since_jan1);
 
         let year = i16::try_from(year).expect("year to fit inside signed 32bits");
         Date { year, month, day }
     }
 
+    /// Returns the number of days between two dates regardless of their order
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    /// let start = Date::parse_from_str("1444.11.11").unwrap();
+    /// let end = Date::parse_from_str("1445.11.11").unwrap();
+    /// assert_eq!(start.days_between(&end), 365);
+    /// assert_eq!(end.days_between(&start), 365);
+    /// ```
+    pub fn days_between(&self, other: &Date) -> u32 {
+        self.days_until(other).unsigned_abs()
+    }
+
+    /// Return a new date that is the given number of months in the future
+    /// from the current date. If the day does not exist in the new month,
+    /// the last day of the month is used.
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    ///
+    /// let date = Date::parse_from_str("1444.11.11").unwrap();
+    /// assert_eq!(date.add_months(2).game_fmt(), "1445.1.11");
+    /// assert_eq!(date.add_months(-11).game_fmt(), "1443.12.11");
+    ///
+    /// let date = Date::parse_from_str("1444.1.31").unwrap();
+    /// assert_eq!(date.add_months(1).game_fmt(), "1444.2.28");
+    /// ```
+    ///
+    /// Will panic on overflow or underflow.
+    pub fn add_months(&self, months: i32) -> Date {
+        let total = i32::from(self.year) * 12 + i32::from(self.month) - 1;
+        let total = total.checked_add(months).expect("adding months overflowed");
+
+        let year = i16::try_from(total.div_euclid(12)).expect("year to fit inside signed 16bits");
+        let month = (total.rem_euclid(12) + 1) as u8;
+        let day = self.day.min(DAYS_PER_MONTH[usize::from(month)]);
+        Date { year, month, day }
+    }
+
+    /// Return a new date that is the given number of years in the future
+    /// from the current date. As game calendars have no leap years, the
+    /// month and day are always preserved.
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    ///
+    /// let start = Date::parse_from_str("1444.11.11").unwrap();
+    /// let date = Date::parse_from_str("1541.3.2").unwrap();
+    /// assert_eq!(start.add_years(100).game_fmt(), "1544.11.11");
+    /// assert!(date < start.add_years(100));
+    /// ```
+    ///
+    /// Will panic on overflow or underflow.
+    pub fn add_years(&self, years: i16) -> Date {
+        let year = self
+            .year
+            .checked_add(years)
+            .expect("adding years overflowed");
+
+        Date {
+            year,
+            month: self.month,
+            day: self.day,
+        }
+    }
+
     /// Decodes a date from a number that had been parsed from binary data
-    pub fn from_binary(mut s: i32) -> Option<Self> {
+    ///
+    /// Binary dates are the number of hours since the start of year -5000.
+    /// Since any large enough integer decodes to a date, years before -100
+    /// and year 0 (which EU4 doesn't use) are rejected so that numbers that
+    /// aren't dates are not mistaken for one (EU4's earliest date is a ruler
+    /// born in -58). Use [`Date::from_binary_epoch`] to decode the full
+    /// range of years.
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    /// assert_eq!(Date::from_binary(43800000), None);
+    /// assert_eq!(
+    ///     Date::from_binary_epoch(43800000, 5000).map(|x| x.game_fmt()),
+    ///     Some(String::from("0.1.1"))
+    /// );
+    /// ```
+    pub fn from_binary(s: i32) -> Option<Self> {
+        Date::from_binary_epoch(s, 5000).filter(|x| x.year > -100 && x.year != 0)
+    }
+
+    /// Decodes a date from binary data where it is not known whether the
+    /// integer is a date or a plain number.
+    ///
+    /// Any sufficiently large integer can be decoded as a date, so this is
+    /// the single place that decides whether an integer is likely a date.
+    /// On top of the checks of [`Date::from_binary`], the integer must fall
+    /// on the first hour of a day, as dates in game files never have an hour
+    /// component. Deserializers and tools that convert binary data to text
+    /// should use this function so that they all agree on which integers are
+    /// dates.
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    /// assert_eq!(
+    ///     Date::from_binary_heuristic(43808760).map(|x| x.game_fmt()),
+    ///     Some(String::from("1.1.1"))
+    /// );
+    /// assert_eq!(Date::from_binary_heuristic(43808761), None);
+    /// assert_eq!(Date::from_binary_heuristic(380947), None);
+    /// assert_eq!(Date::from_binary_heuristic(-1), None);
+    /// ```
+    pub fn from_binary_heuristic(s: i32) -> Option<Self> {
+        if s % 24 != 0 {
+            return None;
+        }
+
+        Date::from_binary(s)
+    }
+
+    /// Decodes a date from a number of hours since the start of the year
+    /// `-epoch`, which is how games encode dates in binary data. The epoch
+    /// is game specific and is supplied by a binary flavor.
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    /// let date = Date::from_binary_epoch(56379360, 5000).unwrap();
+    /// assert_eq!(date.game_fmt(), "1436.1.1");
+    /// assert_eq!(date.to_binary_epoch(5000), Some(56379360));
+    ///
+    /// let date = Date::from_binary_epoch(8760, 5000).unwrap();
+    /// assert_eq!(date.game_fmt(), "-4999.1.1");
+    /// assert_eq!(Date::from_binary(8760), None);
+    /// ```
+    pub fn from_binary_epoch(s: i32, epoch: i32) -> Option<Self> {
         if s < 0 {
-            return None
+            return None;
         }
 
-        let _hours = s % 24;
-        s /= 24;
-        let days_since_jan1 = s % 365;
-        s /= 365;
-        let year = match s.checked_sub(5000).and_then(|x| i16::try_from(x).ok()) {
-            Some(y) => y,
-            None => return None,
-        };
+        let days = s / 24;
+        let days_since_jan1 = days % 365;
+        let year = (days / 365)
+            .checked_sub(epoch)
+            .and_then(|x| i16::try_from(x).ok())?;
 
         let (month, day) = month_day_from_julian(days_since_jan1);
         Date::new(year, month, day)
     }
 
+    /// Encodes the date as the number of hours since the start of the year
+    /// `-epoch`. Returns `None` if the date is before the epoch or the hours
+    /// overflow.
+    pub fn to_binary_epoch(&self, epoch: i32) -> Option<i32> {
+        let days_since_jan1 = self.days().rem_euclid(365);
+        let days = (i32::from(self.year) + epoch)
+            .checked_mul(365)?
+            .checked_add(days_since_jan1)?;
+
+        if days < 0 {
+            return None;
+        }
+
+        days.checked_mul(24)
+    }
+
+    /// Encodes the date in the EU4 binary format
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    /// let date = Date::parse_from_str("1444.11.11").unwrap();
+    /// assert_eq!(Date::from_binary(date.to_binary().unwrap()), Some(date));
+    /// ```
+    pub fn to_binary(&self) -> Option<i32> {
+        self.to_binary_epoch(5000)
+    }
+
     /// Formats a date in the ISO 8601 format: YYYY-MM-DD
     ///
     /// ```
@@ -267,8 +403,23 @@ impl Date {
     /// let date = Date::parse_from_str("1400.1.2").expect("to parse date");
     /// assert_eq!(date.iso_8601(), String::from("1400-01-02"));
     /// ```
+    ///
+    /// Years outside of 0 to 9999 are written with a sign as ISO 8601 allows
+    /// for expanded years
+    ///
+    /// ```
+    /// use jomini::common::Date;
+    /// let date = Date::parse_from_str("-58.1.2").expect("to parse date");
+    /// assert_eq!(date.iso_8601(), String::from("-0058-01-02"));
+    /// let date = Date::parse_from_str("12000.1.2").expect("to parse date");
+    /// assert_eq!(date.iso_8601(), String::from("+12000-01-02"));
+    /// ```
     pub fn iso_8601(&self) -> String {
-        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+        if (0..=9999).contains(&self.year) {
+            format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+        } else {
+            format!("{:+05}-{:02}-{:02}", self.year, self.month, self.day)
+        }
     }
 
     /// Formats a date in the game format: Y.M.D
@@ -408,6 +559,58 @@ mod tests {
         assert_eq!(Date::parse_from_str("-60000.1.1"), None);
     }
 
+    #[test]
+    fn test_negative_and_large_years() {
+        for case in &[
+            "-2500.3.1",
+            "-1.12.31",
+            "0.1.1",
+            "9999.12.31",
+            "10000.1.1",
+            "32767.1.1",
+        ] {
+            let date = Date::parse_from_str(case).unwrap();
+            assert_eq!(date.game_fmt(), case.to_string());
+            let data = date.to_binary_epoch(5000).unwrap();
+            assert_eq!(Date::from_binary_epoch(data, 5000), Some(date));
+        }
+
+        let early = Date::parse_from_str("-2500.3.1").unwrap();
+        let late = Date::parse_from_str("10000.1.1").unwrap();
+        assert!(early < late);
+        assert_eq!(early.add_days(early.days_until(&late)), late);
+        assert_eq!(Date::parse_from_str("32768.1.1"), None);
+    }
+
+    #[test]
+    fn test_negative_year_arithmetic() {
+        let date = |x: &str| Date::parse_from_str(x).unwrap();
+        assert_eq!(date("-1.1.1").add_days(1), date("-1.1.2"));
+        assert_eq!(date("-1.12.31").add_days(1), date("0.1.1"));
+        assert_eq!(date("0.1.1").add_days(-1), date("-1.12.31"));
+        assert_eq!(date("0.12.31").add_days(1), date("1.1.1"));
+        assert_eq!(date("1.1.1").add_days(-366), date("-1.12.31"));
+        assert_eq!(date("-2.3.1").add_days(730), date("0.3.1"));
+        assert_eq!(date("-1.1.1").days_until(&date("-1.12.31")), 364);
+        assert_eq!(date("-1.1.1").days_until(&date("1.1.1")), 730);
+        assert_eq!(date("1.1.1").days_until(&date("-1.1.1")), -730);
+
+        for case in &["-1.1.1", "-1.12.31", "0.6.15", "-4999.1.1"] {
+            let start = date(case);
+            for days in &[-400, -1, 0, 1, 364, 365, 1000] {
+                let end = start.add_days(*days);
+                assert_eq!(start.days_until(&end), *days);
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_year_zero_is_not_a_date() {
+        assert_eq!(Date::from_binary(43800000), None);
+        assert_eq!(Date::from_binary_heuristic(43800000), None);
+        assert_eq!(Date::from_binary_epoch(43800000, 5000), Date::new(0, 1, 1));
+    }
+
     #[test]
     fn test_binary_date_overflow() {
         assert_eq!(Date::from_binary(999379360), None);
@@ -420,6 +623,53 @@ mod tests {
         let _ = date.add_days(100000000);
     }
 
+    #[test]
+    fn test_add_months_and_years() {
+        let date = Date::parse_from_str("1444.11.30").unwrap();
+        assert_eq!(date.add_months(0), date);
+        assert_eq!(date.add_months(3).game_fmt(), "1445.2.28");
+        assert_eq!(date.add_months(-14).game_fmt(), "1443.9.30");
+        assert_eq!(date.add_months(12), date.add_years(1));
+        assert_eq!(date.add_months(-1200), date.add_years(-100));
+
+        let date = Date::parse_from_str("1.1.15").unwrap();
+        assert_eq!(date.add_months(-1).game_fmt(), "0.12.15");
+        assert_eq!(date.add_months(-13).game_fmt(), "-1.12.15");
+        assert_eq!(date.add_years(-2).game_fmt(), "-1.1.15");
+
+        let start = Date::parse_from_str("1444.11.11").unwrap();
+        let end = start.add_years(100);
+        assert_eq!(start.days_between(&end), 36500);
+    }
+
+    #[test]
+    fn test_negative_year_durations() {
+        let date = |x: &str| Date::parse_from_str(x).unwrap();
+        assert_eq!(date("-1.1.1").days_between(&date("-1.12.31")), 364);
+        assert_eq!(date("-1.12.31").days_between(&date("0.1.1")), 1);
+        assert_eq!(date("1.1.1").days_between(&date("-1.1.1")), 730);
+        assert_eq!(date("-100.3.1").days_between(&date("100.3.1")), 73000);
+
+        let start = date("-1.11.30");
+        assert_eq!(start.add_months(1).game_fmt(), "-1.12.30");
+        assert_eq!(start.add_months(2).game_fmt(), "0.1.30");
+        assert_eq!(start.add_months(3).game_fmt(), "0.2.28");
+        assert_eq!(start.add_months(-12).game_fmt(), "-2.11.30");
+        assert_eq!(start.add_months(14).game_fmt(), "1.1.30");
+
+        assert_eq!(start.add_years(1).game_fmt(), "0.11.30");
+        assert_eq!(start.add_years(-99).game_fmt(), "-100.11.30");
+        assert_eq!(start.days_between(&start.add_years(-10)), 3650);
+        assert_eq!(start.days_between(&start.add_years(10)), 3650);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_add_years_overflow() {
+        let date = Date::parse_from_str("1400.1.2").unwrap();
+        let _ = date.add_years(i16::MAX);
+    }
+
     #[test]
     #[should_panic]
     fn test_add_adds_day_overflow() {
@@ -589,22 +839,22 @@ mod tests {
         assert_eq!(date.days(), -365);
 
         let date = Date::parse_from_str("-1.1.2").unwrap();
-        assert_eq!(date.days(), -366);
+        assert_eq!(date.days(), -364);
 
         let date = Date::parse_from_str("-1.2.2").unwrap();
-        assert_eq!(date.days(), -397);
+        assert_eq!(date.days(), -333);
     }
 
     #[test]
     fn test_negative_date_math() {
         let date = Date::parse_from_str("-1.1.2").unwrap();
         let d1 = date.add_days(1);
-        assert_eq!(d1.game_fmt(), "-1.1.1");
+        assert_eq!(d1.game_fmt(), "-1.1.3");
         assert_eq!(date.days_until(&d1), 1);
 
         let date = Date::parse_from_str("-3.6.3").unwrap();
         let d1 = date.add_days(1);
-        assert_eq!(d1.game_fmt(), "-3.6.2");
+        assert_eq!(d1.game_fmt(), "-3.6.4");
         assert_eq!(date.days_until(&d1), 1);
     }
 }
@@ -0,0 +1,105 @@
+use std::fmt;
+
+/// Displays a float with a fixed number of decimal places the same way the
+/// games write out their fixed point numbers (eg: `1.000`).
+///
+/// The value is rounded to an integer number of the smallest decimal unit
+/// before it is written, so the output does not drift with how the closest
+/// f32 to a decimal is represented (`0.1` is `0.100` and not `0.10000000149`).
+///
+/// ```
+/// use jomini::common::FixedFloat;
+///
+/// assert_eq!(FixedFloat::new(1.0, 3).to_string(), "1.000");
+/// assert_eq!(FixedFloat::new(-0.5, 3).to_string(), "-0.500");
+/// assert_eq!(FixedFloat::new(0.1, 5).to_string(), "0.10000");
+/// assert_eq!(FixedFloat::new(2.5, 0).to_string(), "3");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedFloat {
+    value: f32,
+    decimals: usize,
+}
+
+impl FixedFloat {
+    /// Create a float that is displayed with the given number of decimal places
+    pub fn new(value: f32, decimals: usize) -> Self {
+        FixedFloat { value, decimals }
+    }
+}
+
+impl fmt::Display for FixedFloat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scale = 10i64.checked_pow(self.decimals as u32).unwrap_or(0);
+        let scaled = (self.value as f64 * scale as f64).round();
+
+        // Values that can't be represented as a scaled integer are written
+        // with the standard library formatting
+        if scale == 0 || !scaled.is_finite() || scaled.abs() >= i64::MAX as f64 {
+            return write!(f, "{:.*}", self.decimals, self.value);
+        }
+
+        let scaled = scaled as i64;
+        if scaled < 0 {
+            f.write_str("-")?;
+        }
+
+        let scaled = scaled.unsigned_abs();
+        let scale = scale as u64;
+        if self.decimals == 0 {
+            write!(f, "{}", scaled)
+        } else {
+            write!(
+                f,
+                "{}.{:0width$}",
+                scaled / scale,
+                scaled % scale,
+                width = self.decimals
+            )
+        }
+    }
+}
+
+/// Formats a float with the 3 decimal places that EU4 writes for fixed point
+/// numbers
+///
+/// ```
+/// use jomini::common::format_f32_eu4;
+///
+/// assert_eq!(format_f32_eu4(10.5), "10.500");
+/// assert_eq!(format_f32_eu4(-0.001), "-0.001");
+/// ```
+pub fn format_f32_eu4(value: f32) -> String {
+    FixedFloat::new(value, 3).to_string()
+}
+
+/// Formats a float with the 5 decimal places that EU4 writes for Q17.15 fixed
+/// point numbers
+///
+/// ```
+/// use jomini::common::format_q_eu4;
+///
+/// assert_eq!(format_q_eu4(1.0), "1.00000");
+/// assert_eq!(format_q_eu4(0.30000305), "0.30000");
+/// ```
+pub fn format_q_eu4(value: f32) -> String {
+    FixedFloat::new(value, 5).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_float_rounding() {
+        assert_eq!(FixedFloat::new(0.0, 3).to_string(), "0.000");
+        assert_eq!(FixedFloat::new(-0.0004, 3).to_string(), "0.000");
+        assert_eq!(FixedFloat::new(-1.2345, 3).to_string(), "-1.235");
+        assert_eq!(FixedFloat::new(65535.99, 2).to_string(), "65535.99");
+        assert_eq!(FixedFloat::new(f32::NAN, 3).to_string(), "NaN");
+        assert_eq!(
+            FixedFloat::new(1e30, 3).to_string(),
+            format!("{:.3}", 1e30f32)
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{Error, ErrorKind};
+use std::convert::TryFrom;
+
+/// The format of the data that follows a save header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveHeaderKind {
+    /// Uncompressed plaintext
+    Text,
+
+    /// Uncompressed binary
+    Binary,
+
+    /// Plaintext metadata followed by a zip that contains the plaintext
+    /// gamestate
+    UnifiedText,
+
+    /// Binary metadata followed by a zip that contains the binary gamestate
+    UnifiedBinary,
+
+    /// Plaintext metadata followed by a zip, where the gamestate entry
+    /// doesn't contain the metadata
+    SplitText,
+
+    /// Binary metadata followed by a zip, where the gamestate entry doesn't
+    /// contain the metadata
+    SplitBinary,
+
+    /// A kind that isn't known
+    Other(u16),
+}
+
+impl SaveHeaderKind {
+    fn new(kind: u16) -> Self {
+        match kind {
+            0 => SaveHeaderKind::Text,
+            1 => SaveHeaderKind::Binary,
+            2 => SaveHeaderKind::UnifiedText,
+            3 => SaveHeaderKind::UnifiedBinary,
+            4 => SaveHeaderKind::SplitText,
+            5 => SaveHeaderKind::SplitBinary,
+            x => SaveHeaderKind::Other(x),
+        }
+    }
+
+    /// Returns true if the save is encoded in the binary format
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            SaveHeaderKind::Binary | SaveHeaderKind::UnifiedBinary | SaveHeaderKind::SplitBinary
+        )
+    }
+
+    /// Returns true if the gamestate is stored in a zip
+    pub fn is_compressed(&self) -> bool {
+        matches!(
+            self,
+            SaveHeaderKind::UnifiedText
+                | SaveHeaderKind::UnifiedBinary
+                | SaveHeaderKind::SplitText
+                | SaveHeaderKind::SplitBinary
+        )
+    }
+}
+
+/// The header line that CK3 and Victoria 3 saves start with
+/// (`SAV0103a5e2d5e600004e24`), which describes how the rest of the file is
+/// encoded and how long the metadata section is.
+///
+/// The header is made of hex digits after the `SAV` magic: 2 hex digits for
+/// the version, 2 for the kind, 8 of randomness, and 8 for the length of the
+/// metadata that follows the header.
+///
+/// ```
+/// use jomini::common::{SaveHeader, SaveHeaderKind};
+///
+/// let data = b"SAV0103a5e2d5e600000006\nmeta=1PK\x03\x04";
+/// let header = SaveHeader::from_slice(&data[..])?;
+/// assert_eq!(header.version(), 1);
+/// assert_eq!(header.kind(), SaveHeaderKind::UnifiedBinary);
+/// assert_eq!(header.metadata(&data[..]), b"meta=1");
+/// assert_eq!(header.gamestate(&data[..]), b"PK\x03\x04");
+/// # Ok::<(), jomini::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveHeader {
+    version: u16,
+    kind: SaveHeaderKind,
+    random: [u8; 8],
+    metadata_len: u64,
+    header_len: usize,
+}
+
+impl SaveHeader {
+    /// The number of bytes in the header before the line ending
+    pub const SIZE: usize = 23;
+
+    /// Parse the save header at the start of the data
+    pub fn from_slice(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < Self::SIZE {
+            return Err(Error::eof("a save header"));
+        }
+
+        if &data[..3] != b"SAV" {
+            return Err(header_error("expected save header to start with SAV", 0));
+        }
+
+        let version = hex_digits(&data[3..5], 3)? as u16;
+        let kind = SaveHeaderKind::new(hex_digits(&data[5..7], 5)? as u16);
+        let mut random = [0u8; 8];
+        random.copy_from_slice(&data[7..15]);
+        let metadata_len = hex_digits(&data[15..23], 15)?;
+
+        let header_len = match &data[Self::SIZE..] {
+            [b'\r', b'\n', ..] => Self::SIZE + 2,
+            [b'\n', ..] => Self::SIZE + 1,
+            [] => Self::SIZE,
+            _ => {
+                return Err(header_error(
+                    "expected save header to end with a newline",
+                    Self::SIZE,
+                ))
+            }
+        };
+
+        Ok(SaveHeader {
+            version,
+            kind,
+            random,
+            metadata_len,
+            header_len,
+        })
+    }
+
+    /// The version of the header format
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// How the data that follows the header is encoded
+    pub fn kind(&self) -> SaveHeaderKind {
+        self.kind
+    }
+
+    /// The random bytes of the header, which differ between saves
+    pub fn random(&self) -> &[u8; 8] {
+        &self.random
+    }
+
+    /// The number of bytes in the metadata section
+    pub fn metadata_len(&self) -> u64 {
+        self.metadata_len
+    }
+
+    /// The number of bytes in the header, including the line ending
+    pub fn header_len(&self) -> usize {
+        self.header_len
+    }
+
+    /// Returns the metadata section of the save. The section is truncated
+    /// if the data is shorter than what the header describes.
+    pub fn metadata<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let start = self.header_len.min(data.len());
+        let end = self.metadata_end().min(data.len());
+        &data[start..end]
+    }
+
+    /// Returns the data that follows the metadata section, which is the zip
+    /// for compressed saves and the rest of the gamestate otherwise
+    pub fn gamestate<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.metadata_end().min(data.len())..]
+    }
+
+    fn metadata_end(&self) -> usize {
+        let len = usize::try_from(self.metadata_len).unwrap_or(usize::MAX);
+        self.header_len.saturating_add(len)
+    }
+}
+
+fn header_error(msg: &str, offset: usize) -> Error {
+    Error::new(ErrorKind::InvalidSyntax {
+        msg: String::from(msg),
+        offset,
+    })
+}
+
+fn hex_digits(data: &[u8], offset: usize) -> Result<u64, Error> {
+    let mut result = 0u64;
+    for (i, &c) in data.iter().enumerate() {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => {
+                return Err(header_error(
+                    "expected hex digit in save header",
+                    offset + i,
+                ))
+            }
+        };
+        result = (result << 4) | u64::from(digit);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_header_text() {
+        let data = b"SAV0100a5e2d5e600000004\r\na=1 b=2";
+        let header = SaveHeader::from_slice(&data[..]).unwrap();
+        assert_eq!(header.kind(), SaveHeaderKind::Text);
+        assert!(!header.kind().is_binary());
+        assert!(!header.kind().is_compressed());
+        assert_eq!(header.header_len(), 25);
+        assert_eq!(header.random(), b"a5e2d5e6");
+        assert_eq!(header.metadata(&data[..]), b"a=1 ");
+        assert_eq!(header.gamestate(&data[..]), b"b=2");
+    }
+
+    #[test]
+    fn test_save_header_truncated_metadata() {
+        let data = b"SAV01050000000000001000\nab";
+        let header = SaveHeader::from_slice(&data[..]).unwrap();
+        assert_eq!(header.kind(), SaveHeaderKind::SplitBinary);
+        assert_eq!(header.metadata_len(), 0x1000);
+        assert_eq!(header.metadata(&data[..]), b"ab");
+        assert_eq!(header.gamestate(&data[..]), b"");
+    }
+
+    #[test]
+    fn test_save_header_errors() {
+        assert!(SaveHeader::from_slice(b"SAV01").unwrap_err().is_eof());
+
+        let err = SaveHeader::from_slice(b"EU4txt\ndate=1444.11.11\n").unwrap_err();
+        assert_eq!(err.offset(), Some(0));
+
+        let err = SaveHeader::from_slice(b"SAV0103a5e2d5e60000000g\n").unwrap_err();
+        assert_eq!(err.offset(), Some(22));
+
+        let err = SaveHeader::from_slice(b"SAV0103a5e2d5e600000001a").unwrap_err();
+        assert_eq!(err.offset(), Some(23));
+    }
+
+    #[test]
+    fn test_save_header_other_kind() {
+        let header = SaveHeader::from_slice(b"SAV0209000000000000000a").unwrap();
+        assert_eq!(header.version(), 2);
+        assert_eq!(header.kind(), SaveHeaderKind::Other(9));
+    }
+}
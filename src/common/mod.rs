@@ -1,4 +1,8 @@
 //! Common data structures used across games
 mod date;
+mod float;
+mod header;
 
 pub use date::*;
+pub use float::*;
+pub use header::*;
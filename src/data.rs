@@ -1,4 +1,28 @@
 /// Extracted color info
+///
+/// With the `derive` feature, a color can be deserialized into an `Rgb`
+/// field from either the text (`color = rgb { 110 27 27 }`) or binary
+/// format. Colors can also be deserialized into a tuple of the channels (eg:
+/// `(u32, u32, u32)` or `[u8; 3]`), which skips the `rgb` header.
+///
+/// ```
+/// # #[cfg(feature = "derive")] {
+/// use jomini::{Rgb, TextDeserializer};
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Country {
+///     color: Rgb,
+///     map_color: [u8; 3],
+/// }
+///
+/// let data = b"color = rgb { 110 27 27 } map_color = { 10 20 30 }";
+/// let country: Country = TextDeserializer::from_windows1252_slice(&data[..])?;
+/// assert_eq!(country.color, Rgb { r: 110, g: 27, b: 27 });
+/// assert_eq!(country.map_color, [10, 20, 30]);
+/// # }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rgb {
     /// Red channel
@@ -11,6 +35,278 @@ pub struct Rgb {
     pub b: u32,
 }
 
+impl Rgb {
+    /// Format the color as a hex string. Channels are expected to be
+    /// within 0 to 255.
+    ///
+    /// ```
+    /// use jomini::Rgb;
+    /// assert_eq!(Rgb { r: 10, g: 255, b: 0 }.to_hex(), "#0aff00");
+    /// ```
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[cfg(feature = "derive")]
+impl<'de> serde::Deserialize<'de> for Rgb {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct RgbVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for RgbVisitor {
+            type Value = Rgb;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a color of three channels")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut channel = |i| {
+                    seq.next_element::<u32>()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(i, &self))
+                };
+
+                let r = channel(0)?;
+                let g = channel(1)?;
+                let b = channel(2)?;
+                Ok(Rgb { r, g, b })
+            }
+        }
+
+        deserializer.deserialize_tuple(3, RgbVisitor)
+    }
+}
+
+/// How an rgb color is represented when it is converted into another shape,
+/// like JSON or a user defined type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RgbRepresentation {
+    /// Mirror the text format where the `rgb` header precedes the channels.
+    /// A deserializer sees a sequence of the header and an array of the
+    /// channels (`("rgb", [1, 2, 3])`) while JSON is an object keyed by the
+    /// header (`{"rgb":[1,2,3]}`). This is the default.
+    #[default]
+    Header,
+
+    /// An array of the channels: `[1, 2, 3]`
+    Array,
+
+    /// An object with `r`, `g`, and `b` fields: `{"r":1,"g":2,"b":3}`
+    Object,
+
+    /// A hex string: `"#010203"`
+    Hex,
+}
+
+/// Customize how a parser handles arrays that turn into objects partway
+/// through, known as hidden objects:
+///
+/// ```ignore
+/// levels={ 10 0=2 1=2 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HiddenObjectStrategy {
+    /// Group the trailing key value pairs into a hidden object token that
+    /// is the last element of the array. The values must be scalars, so an
+    /// object or array nested inside the hidden object is an error. This is
+    /// the default.
+    #[default]
+    Synthesize,
+
+    /// Same as `Synthesize`, but the values may also be objects and arrays
+    /// (`levels={ 10 0={ 1 2 } 1=2 }`)
+    SynthesizeNested,
+
+    /// Discard the structure so that the keys and values become elements of
+    /// the array (`levels={ 10 0 2 1 2 }`)
+    Flatten,
+
+    /// Return an error
+    Error,
+}
+
+/// The part that a token plays in the structure of a tape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TapeNode {
+    /// Opens an object or array that ends at the given index
+    Open(usize),
+
+    /// Closes the object or array that opened at the given index
+    End(usize),
+
+    /// Any other token
+    Leaf,
+}
+
+/// Checks that every object and array of a tape is closed by the end token
+/// that it points to, and that every end token points back at the object or
+/// array that it closes.
+pub(crate) fn validate_structure<I>(nodes: I) -> Result<(), crate::Error>
+where
+    I: IntoIterator<Item = TapeNode>,
+{
+    let invalid = |msg: String, index: usize| {
+        Err(crate::Error::new(crate::ErrorKind::InvalidTape {
+            msg,
+            index,
+        }))
+    };
+
+    // (index, end index) of the objects and arrays that are open
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    for (idx, node) in nodes.into_iter().enumerate() {
+        match node {
+            TapeNode::Open(end) if end <= idx => {
+                return invalid(format!("end index {} does not follow its start", end), idx)
+            }
+            TapeNode::Open(end) => open.push((idx, end)),
+            TapeNode::End(start) => match open.pop() {
+                Some((x, end)) if x == start && end == idx => {}
+                Some((x, end)) if x == start => {
+                    return invalid(format!("expected the end of {} at {}", x, end), idx)
+                }
+                Some((x, _)) => {
+                    return invalid(format!("end of {} found before end of {}", start, x), idx)
+                }
+                None => return invalid(format!("end of {} has no start", start), idx),
+            },
+            TapeNode::Leaf => {}
+        }
+    }
+
+    match open.pop() {
+        Some((idx, end)) => invalid(format!("end index {} is out of bounds", end), idx),
+        None => Ok(()),
+    }
+}
+
+/// Counts the tokens that a parser will write to a tape from a stream of
+/// lexed events, so that the tape can be allocated once up front.
+///
+/// The count is exact for well formed data, except for empty objects that
+/// the parsers skip, which are overcounted.
+#[derive(Debug)]
+pub(crate) struct TokenCounter {
+    count: usize,
+    frames: Vec<CounterFrame>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ContainerKind {
+    Unknown,
+    Object,
+    Array,
+    HiddenObject,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CounterFrame {
+    kind: ContainerKind,
+
+    /// A scalar has been seen that isn't a value of a key
+    pending_key: bool,
+
+    /// An operator has been seen so the next element is a value
+    after_operator: bool,
+}
+
+impl CounterFrame {
+    fn new(kind: ContainerKind) -> Self {
+        CounterFrame {
+            kind,
+            pending_key: false,
+            after_operator: false,
+        }
+    }
+}
+
+impl TokenCounter {
+    pub(crate) fn new() -> Self {
+        TokenCounter {
+            count: 0,
+            frames: vec![CounterFrame::new(ContainerKind::Object)],
+        }
+    }
+
+    #[inline]
+    fn frame(&mut self) -> &mut CounterFrame {
+        let last = self.frames.len() - 1;
+        &mut self.frames[last]
+    }
+
+    #[inline]
+    pub(crate) fn scalar(&mut self) {
+        self.count += 1;
+        let frame = self.frame();
+        if frame.after_operator {
+            frame.after_operator = false;
+            frame.pending_key = false;
+        } else {
+            // Two values in a row without an operator is an array
+            if frame.pending_key && frame.kind == ContainerKind::Unknown {
+                frame.kind = ContainerKind::Array;
+            }
+            frame.pending_key = true;
+        }
+    }
+
+    /// An operator that is written to the tape (`<`, `>=`, etc) or not (`=`)
+    #[inline]
+    pub(crate) fn operator(&mut self, written: bool) {
+        if written {
+            self.count += 1;
+        }
+
+        let mut hidden = false;
+        let frame = self.frame();
+        if frame.pending_key {
+            match frame.kind {
+                ContainerKind::Unknown => frame.kind = ContainerKind::Object,
+                ContainerKind::Array => {
+                    frame.kind = ContainerKind::HiddenObject;
+                    hidden = true;
+                }
+                _ => {}
+            }
+        }
+
+        frame.pending_key = false;
+        frame.after_operator = true;
+        if hidden {
+            self.count += 2;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn open(&mut self) {
+        self.count += 2;
+        let frame = self.frame();
+        if !frame.after_operator && !frame.pending_key && frame.kind == ContainerKind::Unknown {
+            frame.kind = ContainerKind::Array;
+        }
+        frame.pending_key = false;
+        frame.after_operator = false;
+        self.frames.push(CounterFrame::new(ContainerKind::Unknown));
+    }
+
+    #[inline]
+    pub(crate) fn close(&mut self) {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+        }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+}
+
 pub(crate) static WINDOWS_1252: [char; 256] = [
     0 as char,
     1 as char,
@@ -1,7 +1,358 @@
-use crate::{DeserializeError, Rgb};
+use crate::{DeserializeError, DeserializeErrorKind, Rgb, RgbRepresentation};
 use de::{DeserializeSeed, SeqAccess, Visitor};
 use serde::de;
 
+/// Customize how the deserializer reacts when a key occurs multiple times
+/// within the same object
+///
+/// Games differ in how duplicate keys are interpreted: some only consider the
+/// first occurrence, others let later occurrences override earlier ones, and
+/// some accumulate all occurrences into a list.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DuplicateKeyStrategy {
+    /// Pass every occurrence to the deserialized type. This is the default
+    /// and allows the type to decide (eg: a derived struct errors unless the
+    /// field is marked as `duplicated`)
+    Preserve,
+
+    /// Only the first occurrence of a key is deserialized
+    TakeFirst,
+
+    /// Only the last occurrence of a key is deserialized
+    TakeLast,
+
+    /// Stop deserializing and return an error
+    Error,
+
+    /// When the field is a sequence, all occurrences of a key are
+    /// deserialized as elements of the sequence, otherwise only the last
+    /// occurrence is deserialized. A key that occurs once with a scalar value
+    /// is deserialized as a sequence of one element, while a lone array
+    /// (`core = { ENG NOR }`) is deserialized as is.
+    Collect,
+}
+
+/// Customize how the deserializer reacts when an integer in the data doesn't
+/// fit in the requested type (eg: `300` for a `u8` field)
+///
+/// The behavior is the same for text and binary data.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum IntegerOverflow {
+    /// Stop deserializing and return an error containing the path of keys
+    /// that lead to the value. This is the default.
+    #[default]
+    Error,
+
+    /// Clamp the value to the closest value the type can hold, so `300` as a
+    /// `u8` is `255` and `-1` is `0`
+    Saturate,
+
+    /// Keep the low bits of the value, like an `as` cast, so `300` as a `u8`
+    /// is `44`
+    Wrap,
+}
+
+/// The integer types that are narrowed according to an [IntegerOverflow]
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum IntegerType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntegerType {
+    fn name(self) -> &'static str {
+        match self {
+            IntegerType::I8 => "i8",
+            IntegerType::I16 => "i16",
+            IntegerType::I32 => "i32",
+            IntegerType::I64 => "i64",
+            IntegerType::U8 => "u8",
+            IntegerType::U16 => "u16",
+            IntegerType::U32 => "u32",
+            IntegerType::U64 => "u64",
+        }
+    }
+
+    fn bounds(self) -> (i128, i128) {
+        match self {
+            IntegerType::I8 => (i8::MIN.into(), i8::MAX.into()),
+            IntegerType::I16 => (i16::MIN.into(), i16::MAX.into()),
+            IntegerType::I32 => (i32::MIN.into(), i32::MAX.into()),
+            IntegerType::I64 => (i64::MIN.into(), i64::MAX.into()),
+            IntegerType::U8 => (0, u8::MAX.into()),
+            IntegerType::U16 => (0, u16::MAX.into()),
+            IntegerType::U32 => (0, u32::MAX.into()),
+            IntegerType::U64 => (0, u64::MAX.into()),
+        }
+    }
+}
+
+#[cold]
+fn integer_overflow_error(value: i128, ty: IntegerType) -> DeserializeError {
+    DeserializeError {
+        kind: DeserializeErrorKind::IntegerOverflow {
+            path: String::new(),
+            value,
+            target: ty.name(),
+        },
+    }
+}
+
+/// Narrow the integer to the requested type according to the overflow
+/// policy and visit it as that type
+pub(crate) fn visit_integer<'de, V>(
+    value: i128,
+    ty: IntegerType,
+    overflow: IntegerOverflow,
+    visitor: V,
+) -> Result<V::Value, DeserializeError>
+where
+    V: Visitor<'de>,
+{
+    let (min, max) = ty.bounds();
+    let x = match overflow {
+        _ if (min..=max).contains(&value) => value,
+        IntegerOverflow::Saturate => value.clamp(min, max),
+        IntegerOverflow::Wrap => value,
+        IntegerOverflow::Error => return Err(integer_overflow_error(value, ty)),
+    };
+
+    // Out of range values are only left for wrapping, where the `as` casts
+    // keep the low bits
+    match ty {
+        IntegerType::I8 => visitor.visit_i8(x as i8),
+        IntegerType::I16 => visitor.visit_i16(x as i16),
+        IntegerType::I32 => visitor.visit_i32(x as i32),
+        IntegerType::I64 => visitor.visit_i64(x as i64),
+        IntegerType::U8 => visitor.visit_u8(x as u8),
+        IntegerType::U16 => visitor.visit_u16(x as u16),
+        IntegerType::U32 => visitor.visit_u32(x as u32),
+        IntegerType::U64 => visitor.visit_u64(x as u64),
+    }
+}
+
+/// Returns the tag of an internally tagged enum derived with
+/// `JominiDeserialize`, which deserializes the enum as a struct with this
+/// name so that deserializers that can look ahead move the tag to the front
+pub(crate) fn tagged_struct(name: &str, fields: &'static [&'static str]) -> Option<&'static str> {
+    match fields {
+        [tag] if name == "$jomini::tagged" => Some(tag),
+        _ => None,
+    }
+}
+
+/// Error for when the `Error` duplicate key strategy encounters a duplicate
+#[cold]
+pub(crate) fn duplicate_key_error(key: &str) -> DeserializeError {
+    DeserializeError {
+        kind: DeserializeErrorKind::Message(format!("duplicate field `{}`", key)),
+    }
+}
+
+/// Deserializes the values of a key that occurs multiple times within an
+/// object. If a sequence is requested, the sequence consists of each value,
+/// otherwise the last value is deserialized.
+pub(crate) struct DuplicatedValues<D> {
+    values: Vec<D>,
+}
+
+impl<D> DuplicatedValues<D> {
+    /// Create duplicated values from a non-empty list of value deserializers
+    pub(crate) fn new(values: Vec<D>) -> Self {
+        debug_assert!(!values.is_empty());
+        DuplicatedValues { values }
+    }
+
+    fn last(mut self) -> D {
+        self.values.pop().unwrap()
+    }
+}
+
+macro_rules! forward_to_last {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.last().$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, D> de::Deserializer<'de> for DuplicatedValues<D>
+where
+    D: de::Deserializer<'de, Error = DeserializeError>,
+{
+    type Error = DeserializeError;
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(DuplicatedSequence {
+            values: self.values.into_iter(),
+        })
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_last! {
+        deserialize_any() deserialize_bool() deserialize_i8() deserialize_i16()
+        deserialize_i32() deserialize_i64() deserialize_i128() deserialize_u8()
+        deserialize_u16() deserialize_u32() deserialize_u64() deserialize_u128()
+        deserialize_f32() deserialize_f64() deserialize_char() deserialize_str()
+        deserialize_string() deserialize_bytes() deserialize_byte_buf()
+        deserialize_option() deserialize_unit() deserialize_map()
+        deserialize_identifier() deserialize_ignored_any()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+    }
+}
+
+struct DuplicatedSequence<D> {
+    values: std::vec::IntoIter<D>,
+}
+
+impl<'de, D> SeqAccess<'de> for DuplicatedSequence<D>
+where
+    D: de::Deserializer<'de, Error = DeserializeError>,
+{
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.values.next() {
+            Some(value) => seed.deserialize(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+/// Deserializes a field of an object as a two element sequence of the key
+/// and the value. Sequences of these pairs allow an object to be
+/// deserialized into a `Vec<(K, V)>` that preserves the order and the
+/// duplicates of the object's fields.
+pub(crate) struct KeyValuePair<K, V> {
+    key: Option<K>,
+    value: Option<V>,
+}
+
+impl<K, V> KeyValuePair<K, V> {
+    pub(crate) fn new(key: K, value: V) -> Self {
+        KeyValuePair {
+            key: Some(key),
+            value: Some(value),
+        }
+    }
+
+    /// Returns true if the value was not deserialized
+    pub(crate) fn is_value_pending(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+impl<'de, K, V, E> de::Deserializer<'de> for &mut KeyValuePair<K, V>
+where
+    K: de::Deserializer<'de, Error = E>,
+    V: de::Deserializer<'de, Error = E>,
+    E: de::Error,
+{
+    type Error = E;
+
+    fn deserialize_any<Vis>(self, visitor: Vis) -> Result<Vis::Value, Self::Error>
+    where
+        Vis: Visitor<'de>,
+    {
+        visitor.visit_seq(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        <Vis: Visitor<'de>>
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, K, V, E> SeqAccess<'de> for &mut KeyValuePair<K, V>
+where
+    K: de::Deserializer<'de, Error = E>,
+    V: de::Deserializer<'de, Error = E>,
+    E: de::Error,
+{
+    type Error = E;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if let Some(key) = self.key.take() {
+            seed.deserialize(key).map(Some)
+        } else if let Some(value) = self.value.take() {
+            seed.deserialize(value).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(usize::from(self.key.is_some()) + usize::from(self.value.is_some()))
+    }
+}
+
+/// Feed a color to the visitor in the requested representation
+pub(crate) fn visit_rgb<'de, V>(
+    data: Rgb,
+    representation: RgbRepresentation,
+    visitor: V,
+) -> Result<V::Value, DeserializeError>
+where
+    V: Visitor<'de>,
+{
+    match representation {
+        RgbRepresentation::Header => visitor.visit_seq(ColorSequence::new(data)),
+        RgbRepresentation::Array => visitor.visit_seq(InnerColorSequence::new(data)),
+        RgbRepresentation::Object => {
+            let fields = vec![("r", data.r), ("g", data.g), ("b", data.b)];
+            visitor.visit_map(de::value::MapDeserializer::new(fields.into_iter()))
+        }
+        RgbRepresentation::Hex => visitor.visit_string(data.to_hex()),
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ColorSequence {
     data: Rgb,
@@ -14,7 +365,7 @@ impl ColorSequence {
     }
 }
 
-impl<'b, 'de, 'r> de::Deserializer<'de> for &'r mut ColorSequence {
+impl<'de> de::Deserializer<'de> for &mut ColorSequence {
     type Error = DeserializeError;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -35,7 +386,7 @@ impl<'b, 'de, 'r> de::Deserializer<'de> for &'r mut ColorSequence {
     }
 }
 
-impl<'b, 'de> SeqAccess<'de> for ColorSequence {
+impl<'de> SeqAccess<'de> for ColorSequence {
     type Error = DeserializeError;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
@@ -72,7 +423,7 @@ impl InnerColorSequence {
     }
 }
 
-impl<'b, 'de, 'r> de::Deserializer<'de> for &'r mut InnerColorSequence {
+impl<'de> de::Deserializer<'de> for &mut InnerColorSequence {
     type Error = DeserializeError;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -89,7 +440,7 @@ impl<'b, 'de, 'r> de::Deserializer<'de> for &'r mut InnerColorSequence {
     }
 }
 
-impl<'b, 'de> SeqAccess<'de> for InnerColorSequence {
+impl<'de> SeqAccess<'de> for InnerColorSequence {
     type Error = DeserializeError;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
@@ -0,0 +1,162 @@
+use crate::Error;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// A recoverable problem that was encountered while processing a file
+#[derive(Debug)]
+pub enum Diagnostic {
+    /// A binary token could not be resolved to a name. Each token id is
+    /// reported once no matter how often it occurs.
+    UnknownToken {
+        /// The id of the token that failed to resolve
+        token_id: u16,
+    },
+
+    /// A malformed section of the data was skipped by a lossy parse
+    SkippedSection(Error),
+
+    /// Invalid bytes in a string were replaced when the string was decoded
+    EncodingReplacement {
+        /// The decoded string with the replacement characters
+        text: String,
+    },
+
+    /// The length prefix of a binary string exceeded the remaining data, so
+    /// the string was truncated to the available bytes
+    TruncatedString {
+        /// The byte offset of the length prefix
+        offset: usize,
+
+        /// The length that the prefix claimed
+        length: usize,
+
+        /// The number of bytes that were available
+        available: usize,
+    },
+}
+
+#[derive(Debug, Default)]
+struct DiagnosticsInner {
+    entries: Vec<Diagnostic>,
+    unknown_tokens: HashSet<u16>,
+}
+
+/// Accumulates the diagnostics of a file so that tools can report everything
+/// that went wrong in a single structured report instead of failing on the
+/// first problem.
+///
+/// Clones share the same underlying list, so a clone can be given to a
+/// deserializer builder and the original inspected afterwards.
+///
+/// ```
+/// # #[cfg(feature = "derive")] {
+/// use jomini::{Diagnostic, Diagnostics, TextDeserializer};
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Save {
+///     player: String,
+///     name: String,
+/// }
+///
+/// let data = b"player=ENG\nbroken={\n  a={ b=c\n}\nname=\"Jos\xe9\"\n";
+/// let diagnostics = Diagnostics::new();
+/// let save: Save = TextDeserializer::builder()
+///     .lossy(true)
+///     .diagnostics(&diagnostics)
+///     .from_utf8_slice(&data[..])?;
+///
+/// let report = diagnostics.take();
+/// assert_eq!(report.len(), 2);
+/// assert!(matches!(report[0], Diagnostic::SkippedSection(_)));
+/// assert!(matches!(report[1], Diagnostic::EncodingReplacement { .. }));
+/// # }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Diagnostics {
+    inner: Arc<Mutex<DiagnosticsInner>>,
+}
+
+impl Diagnostics {
+    /// Create an empty diagnostics accumulator
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    /// Returns the number of diagnostics recorded
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns true if no diagnostics have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns the recorded diagnostics in the order that they
+    /// were encountered
+    pub fn take(&self) -> Vec<Diagnostic> {
+        let mut inner = self.lock();
+        inner.unknown_tokens.clear();
+        std::mem::take(&mut inner.entries)
+    }
+
+    /// Record a diagnostic
+    pub fn push(&self, diagnostic: Diagnostic) {
+        let mut inner = self.lock();
+        if let Diagnostic::UnknownToken { token_id } = diagnostic {
+            if !inner.unknown_tokens.insert(token_id) {
+                return;
+            }
+        }
+
+        inner.entries.push(diagnostic);
+    }
+
+    /// Record a replacement if decoding the data introduced replacement
+    /// characters
+    #[cfg(feature = "derive")]
+    pub(crate) fn check_decode(&self, data: &[u8], decoded: &str) {
+        const REPLACEMENT: &[u8] = "\u{FFFD}".as_bytes();
+        if decoded.contains('\u{FFFD}') && !data.windows(3).any(|x| x == REPLACEMENT) {
+            self.push(Diagnostic::EncodingReplacement {
+                text: String::from(decoded),
+            });
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DiagnosticsInner> {
+        // A panic while holding the lock can't leave the list in an
+        // inconsistent state, so a poisoned lock is still usable
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_tokens_reported_once() {
+        let diagnostics = Diagnostics::new();
+        diagnostics.push(Diagnostic::UnknownToken { token_id: 1 });
+        diagnostics.push(Diagnostic::UnknownToken { token_id: 2 });
+        diagnostics.push(Diagnostic::UnknownToken { token_id: 1 });
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics.take().len(), 2);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "derive")]
+    fn test_check_decode() {
+        let diagnostics = Diagnostics::new();
+        diagnostics.check_decode(b"abc", "abc");
+        diagnostics.check_decode("a\u{FFFD}".as_bytes(), "a\u{FFFD}");
+        assert!(diagnostics.is_empty());
+
+        diagnostics.check_decode(b"a\xff", "a\u{FFFD}");
+        assert_eq!(diagnostics.len(), 1);
+    }
+}
@@ -0,0 +1,423 @@
+//! An owned document model for structured fuzzing
+//!
+//! A [`Document`] is a simplified view of a clausewitz document that can be
+//! written out as plaintext or binary data and read back from the parsed
+//! tape. It is constrained such that parsing what was written yields the same
+//! document:
+//!
+//! - Keys are non-empty and consist of ascii alphanumerics and underscores
+//! - Values are always written as quoted strings and never contain quotes or
+//!   escapes
+//! - Objects are never empty (an empty object is indistinguishable from an
+//!   empty array)
+//! - Within an array, a scalar is never followed by an array or object (in
+//!   the binary format, this is indistinguishable from `key{ ... }`)
+//!
+//! With the `arbitrary` feature enabled, documents implement `Arbitrary` so
+//! that fuzz targets can generate well formed input directly. The `testutil`
+//! feature exposes the same generators for property tests.
+//!
+//! ```
+//! use jomini::document::{Document, Value};
+//! use jomini::TextTape;
+//!
+//! let doc = Document::new(vec![
+//!     (b"name".to_vec(), Value::Scalar(b"Jomini".to_vec())),
+//!     (b"core".to_vec(), Value::Array(vec![Value::Scalar(b"ENG".to_vec())])),
+//! ]);
+//!
+//! let data = doc.to_text();
+//! assert_eq!(data.as_slice(), &b"name=\"Jomini\"\ncore={ \"ENG\" }\n"[..]);
+//!
+//! let tape = TextTape::from_slice(&data)?;
+//! assert_eq!(Document::from_text_tape(&tape)?, doc);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use crate::{
+    BinaryTape, BinaryToken, DeserializeError, DeserializeErrorKind, Error, TextTape, TextToken,
+};
+
+/// A value in a document
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A string value
+    Scalar(Vec<u8>),
+
+    /// A sequence of values
+    Array(Vec<Value>),
+
+    /// A non-empty sequence of key value pairs
+    Object(Vec<(Vec<u8>, Value)>),
+}
+
+/// The root of a document: a sequence of key value pairs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    fields: Vec<(Vec<u8>, Value)>,
+}
+
+impl Document {
+    /// Create a document from the given fields
+    pub fn new(fields: Vec<(Vec<u8>, Value)>) -> Self {
+        Document { fields }
+    }
+
+    /// The top level fields of the document
+    pub fn fields(&self) -> &[(Vec<u8>, Value)] {
+        &self.fields
+    }
+
+    /// Write the document as plaintext
+    pub fn to_text(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (key, value) in self.fields.iter() {
+            write_text_field(key, value, &mut out);
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// Write the document in the binary format. Keys are written as unquoted
+    /// strings and values as quoted strings so no token resolution is
+    /// necessary.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (key, value) in self.fields.iter() {
+            write_binary_field(key, value, &mut out);
+        }
+        out
+    }
+
+    /// Read the document from a parsed plaintext tape
+    pub fn from_text_tape(tape: &TextTape) -> Result<Document, Error> {
+        let tokens = tape.tokens();
+        text_fields(tokens, 0, tokens.len()).map(Document::new)
+    }
+
+    /// Read the document from a parsed binary tape
+    pub fn from_binary_tape(tape: &BinaryTape) -> Result<Document, Error> {
+        let tokens = tape.tokens();
+        binary_fields(tokens, 0, tokens.len()).map(Document::new)
+    }
+}
+
+fn write_text_field(key: &[u8], value: &Value, out: &mut Vec<u8>) {
+    out.extend_from_slice(key);
+    out.push(b'=');
+    write_text_value(value, out);
+}
+
+fn write_text_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Scalar(x) => {
+            out.push(b'"');
+            out.extend_from_slice(x);
+            out.push(b'"');
+        }
+        Value::Array(values) => {
+            out.push(b'{');
+            for value in values {
+                out.push(b' ');
+                write_text_value(value, out);
+            }
+            out.extend_from_slice(b" }");
+        }
+        Value::Object(fields) => {
+            out.push(b'{');
+            for (key, value) in fields {
+                out.push(b' ');
+                write_text_field(key, value, out);
+            }
+            out.extend_from_slice(b" }");
+        }
+    }
+}
+
+const EQUAL: u16 = 0x0001;
+const OPEN: u16 = 0x0003;
+const END: u16 = 0x0004;
+const QUOTED: u16 = 0x000f;
+const UNQUOTED: u16 = 0x0017;
+
+fn write_binary_string(token: u16, data: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&token.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+fn write_binary_field(key: &[u8], value: &Value, out: &mut Vec<u8>) {
+    write_binary_string(UNQUOTED, key, out);
+    out.extend_from_slice(&EQUAL.to_le_bytes());
+    write_binary_value(value, out);
+}
+
+fn write_binary_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Scalar(x) => write_binary_string(QUOTED, x, out),
+        Value::Array(values) => {
+            out.extend_from_slice(&OPEN.to_le_bytes());
+            for value in values {
+                write_binary_value(value, out);
+            }
+            out.extend_from_slice(&END.to_le_bytes());
+        }
+        Value::Object(fields) => {
+            out.extend_from_slice(&OPEN.to_le_bytes());
+            for (key, value) in fields {
+                write_binary_field(key, value, out);
+            }
+            out.extend_from_slice(&END.to_le_bytes());
+        }
+    }
+}
+
+fn unsupported(msg: &str) -> Error {
+    Error::from(DeserializeError {
+        kind: DeserializeErrorKind::Unsupported(String::from(msg)),
+    })
+}
+
+fn text_fields(
+    tokens: &[TextToken],
+    mut ind: usize,
+    end: usize,
+) -> Result<Vec<(Vec<u8>, Value)>, Error> {
+    let mut fields = Vec::new();
+    while ind < end {
+        let key = match tokens[ind] {
+            TextToken::Scalar(x) => x.view_data().to_vec(),
+            _ => return Err(unsupported("expected a scalar key")),
+        };
+
+        let (value, next) = text_value(tokens, ind + 1)?;
+        fields.push((key, value));
+        ind = next;
+    }
+
+    Ok(fields)
+}
+
+fn text_value(tokens: &[TextToken], ind: usize) -> Result<(Value, usize), Error> {
+    match tokens.get(ind) {
+        Some(TextToken::Scalar(x)) => Ok((Value::Scalar(x.view_data().to_vec()), ind + 1)),
+        Some(TextToken::Object(end)) => {
+            let fields = text_fields(tokens, ind + 1, *end)?;
+            Ok((Value::Object(fields), end + 1))
+        }
+        Some(TextToken::Array(end)) => {
+            let mut values = Vec::new();
+            let mut i = ind + 1;
+            while i < *end {
+                let (value, next) = text_value(tokens, i)?;
+                values.push(value);
+                i = next;
+            }
+            Ok((Value::Array(values), end + 1))
+        }
+        Some(_) => Err(unsupported("token not supported by the document model")),
+        None => Err(Error::eof("a value")),
+    }
+}
+
+fn binary_fields(
+    tokens: &[BinaryToken],
+    mut ind: usize,
+    end: usize,
+) -> Result<Vec<(Vec<u8>, Value)>, Error> {
+    let mut fields = Vec::new();
+    while ind < end {
+        let key = match tokens[ind] {
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => x.view_data().to_vec(),
+            _ => return Err(unsupported("expected a string key")),
+        };
+
+        let (value, next) = binary_value(tokens, ind + 1)?;
+        fields.push((key, value));
+        ind = next;
+    }
+
+    Ok(fields)
+}
+
+fn binary_value(tokens: &[BinaryToken], ind: usize) -> Result<(Value, usize), Error> {
+    match tokens.get(ind) {
+        Some(BinaryToken::Text(x) | BinaryToken::Unquoted(x)) => {
+            Ok((Value::Scalar(x.view_data().to_vec()), ind + 1))
+        }
+        Some(BinaryToken::Object(end)) => {
+            let fields = binary_fields(tokens, ind + 1, *end)?;
+            Ok((Value::Object(fields), end + 1))
+        }
+        Some(BinaryToken::Array(end)) => {
+            let mut values = Vec::new();
+            let mut i = ind + 1;
+            while i < *end {
+                let (value, next) = binary_value(tokens, i)?;
+                values.push(value);
+                i = next;
+            }
+            Ok((Value::Array(values), end + 1))
+        }
+        Some(_) => Err(unsupported("token not supported by the document model")),
+        None => Err(Error::eof("a value")),
+    }
+}
+
+/// A source of randomness that documents can be generated from
+pub(crate) trait Entropy {
+    type Error;
+
+    /// Return a number in the inclusive range
+    fn int_in_range(&mut self, low: usize, high: usize) -> Result<usize, Self::Error>;
+}
+
+const MAX_DEPTH: usize = 4;
+const KEY_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_";
+const SCALAR_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_.- ";
+
+fn generate_bytes<E: Entropy>(e: &mut E, alphabet: &[u8], min: usize) -> Result<Vec<u8>, E::Error> {
+    let len = e.int_in_range(min, 8)?;
+    let mut result = Vec::with_capacity(len);
+    for _ in 0..len {
+        result.push(alphabet[e.int_in_range(0, alphabet.len() - 1)?]);
+    }
+    Ok(result)
+}
+
+fn generate_fields<E: Entropy>(
+    e: &mut E,
+    min: usize,
+    max: usize,
+    depth: usize,
+) -> Result<Vec<(Vec<u8>, Value)>, E::Error> {
+    let len = e.int_in_range(min, max)?;
+    let mut result = Vec::with_capacity(len);
+    for _ in 0..len {
+        let key = generate_bytes(e, KEY_CHARS, 1)?;
+        result.push((key, generate_value(e, depth)?));
+    }
+    Ok(result)
+}
+
+pub(crate) fn generate_value<E: Entropy>(e: &mut E, depth: usize) -> Result<Value, E::Error> {
+    let kind = if depth >= MAX_DEPTH {
+        0
+    } else {
+        e.int_in_range(0, 2)?
+    };
+
+    match kind {
+        0 => Ok(Value::Scalar(generate_bytes(e, SCALAR_CHARS, 0)?)),
+        1 => {
+            let len = e.int_in_range(0, 4)?;
+            let mut values = Vec::with_capacity(len);
+            for _ in 0..len {
+                let value = match values.last() {
+                    Some(Value::Scalar(_)) => Value::Scalar(generate_bytes(e, SCALAR_CHARS, 0)?),
+                    _ => generate_value(e, depth + 1)?,
+                };
+                values.push(value);
+            }
+            Ok(Value::Array(values))
+        }
+        _ => Ok(Value::Object(generate_fields(e, 1, 4, depth + 1)?)),
+    }
+}
+
+pub(crate) fn generate_document<E: Entropy>(e: &mut E) -> Result<Document, E::Error> {
+    generate_fields(e, 0, 8, 0).map(Document::new)
+}
+
+#[cfg(feature = "arbitrary")]
+mod generate {
+    use super::{Document, Entropy, Value};
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    impl<'a> Entropy for Unstructured<'a> {
+        type Error = arbitrary::Error;
+
+        fn int_in_range(&mut self, low: usize, high: usize) -> Result<usize> {
+            Unstructured::int_in_range(self, low..=high)
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Value {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            super::generate_value(u, 1)
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Document {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            super::generate_document(u)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Document {
+        Document::new(vec![
+            (b"a".to_vec(), Value::Scalar(Vec::new())),
+            (
+                b"b".to_vec(),
+                Value::Array(vec![
+                    Value::Array(Vec::new()),
+                    Value::Scalar(b"x y".to_vec()),
+                    Value::Object(vec![(b"c".to_vec(), Value::Scalar(b"d".to_vec()))]),
+                ]),
+            ),
+            (
+                b"10".to_vec(),
+                Value::Object(vec![(b"e".to_vec(), Value::Array(Vec::new()))]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let doc = sample();
+        let data = doc.to_text();
+        let tape = TextTape::from_slice(&data).unwrap();
+        assert_eq!(Document::from_text_tape(&tape).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let doc = sample();
+        let data = doc.to_binary();
+        let tape = BinaryTape::from_eu4(&data).unwrap();
+        assert_eq!(Document::from_binary_tape(&tape).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_unsupported_tokens() {
+        let tape = TextTape::from_slice(b"a > 1").unwrap();
+        assert!(Document::from_text_tape(&tape).is_err());
+
+        let tape = TextTape::from_slice(b"a = { 10 0=1 }").unwrap();
+        assert!(Document::from_text_tape(&tape).is_err());
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_roundtrip() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let raw: Vec<u8> = (0..4096u32)
+            .map(|x| (x.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let mut u = Unstructured::new(&raw);
+        while !u.is_empty() {
+            let doc = Document::arbitrary(&mut u).unwrap();
+            let text = TextTape::from_slice(&doc.to_text()).map(|x| Document::from_text_tape(&x));
+            assert_eq!(text.unwrap().unwrap(), doc);
+
+            let binary = doc.to_binary();
+            let tape = BinaryTape::from_eu4(&binary).unwrap();
+            assert_eq!(Document::from_binary_tape(&tape).unwrap(), doc);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    Encoding, Error, NormalizedTape, ObjectReader, TextTape, Utf8Encoding, Windows1252Encoding,
+};
+use std::borrow::Cow;
+
+/// The encoding of the strings in a `DocumentTape`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentEncoding {
+    /// Strings are windows1252 encoded
+    Windows1252,
+
+    /// Strings are utf-8 encoded
+    Utf8,
+}
+
+impl Encoding for DocumentEncoding {
+    fn decode<'a>(&self, data: &'a [u8]) -> Cow<'a, str> {
+        match self {
+            DocumentEncoding::Windows1252 => Windows1252Encoding::decode(data),
+            DocumentEncoding::Utf8 => Utf8Encoding::decode(data),
+        }
+    }
+
+    fn decode_scratch<'a>(&self, data: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+        match self {
+            DocumentEncoding::Windows1252 => Windows1252Encoding.decode_scratch(data, scratch),
+            DocumentEncoding::Utf8 => Utf8Encoding.decode_scratch(data, scratch),
+        }
+    }
+}
+
+/// A parsed document that may have originated from either text or binary
+/// data, so that logic like save analysis can be written once against a
+/// single reader.
+///
+/// Binary data is first converted with `BinaryTape::normalize`.
+///
+/// ```
+/// use jomini::{BinaryTape, DocumentTape, Eu4Flavor, TextTape};
+/// use std::collections::HashMap;
+///
+/// fn player(doc: &DocumentTape) -> Option<String> {
+///     let mut reader = doc.reader();
+///     while let Some((key, _op, value)) = reader.next_field() {
+///         if key.read_str() == "player" {
+///             return value.read_string().ok();
+///         }
+///     }
+///     None
+/// }
+///
+/// let text = TextTape::from_slice(b"player=\"ENG\"")?;
+/// assert_eq!(player(&DocumentTape::windows1252(text)).as_deref(), Some("ENG"));
+///
+/// let data = [0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47];
+/// let mut map = HashMap::new();
+/// map.insert(0x2d82, "player");
+/// let normalized = BinaryTape::from_eu4(&data)?.normalize(&map, Eu4Flavor::new());
+/// let doc = DocumentTape::from_normalized(&normalized)?;
+/// assert_eq!(player(&doc).as_deref(), Some("ENG"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct DocumentTape<'a> {
+    tape: TextTape<'a>,
+    encoding: DocumentEncoding,
+}
+
+impl<'a> DocumentTape<'a> {
+    /// Create a document from a text tape with windows1252 encoded strings
+    pub fn windows1252(tape: TextTape<'a>) -> Self {
+        DocumentTape {
+            tape,
+            encoding: DocumentEncoding::Windows1252,
+        }
+    }
+
+    /// Create a document from a text tape with utf-8 encoded strings
+    pub fn utf8(tape: TextTape<'a>) -> Self {
+        DocumentTape {
+            tape,
+            encoding: DocumentEncoding::Utf8,
+        }
+    }
+
+    /// Create a document from a normalized binary tape
+    pub fn from_normalized(normalized: &'a NormalizedTape) -> Result<Self, Error> {
+        Ok(DocumentTape::utf8(normalized.tape()?))
+    }
+
+    /// Return the underlying text tape
+    pub fn tape(&self) -> &TextTape<'a> {
+        &self.tape
+    }
+
+    /// Return the encoding of the strings in the document
+    pub fn encoding(&self) -> DocumentEncoding {
+        self.encoding
+    }
+
+    /// Creates an object reader of the root of the document
+    pub fn reader(&self) -> ObjectReader<'a, '_, DocumentEncoding> {
+        ObjectReader::new(&self.tape, self.encoding)
+    }
+}
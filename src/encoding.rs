@@ -10,9 +10,39 @@ use std::borrow::Cow;
 ///
 /// - trailing whitespace is removed
 /// - escape sequences are unescaped
-pub trait Encoding: Sized {
+///
+/// The trait is object safe, so an encoding chosen at runtime can be used
+/// as a `&dyn Encoding` or `Box<dyn Encoding>`.
+pub trait Encoding {
     /// Decodes bytes into a utf-8 compatible string -- allocating if necessary
     fn decode<'a>(&self, data: &'a [u8]) -> Cow<'a, str>;
+
+    /// Decodes bytes into a utf-8 compatible string, writing to the scratch
+    /// buffer instead of allocating when the string can't be borrowed from
+    /// the data. Returns the borrowed string or `None` when the string was
+    /// written to the scratch buffer.
+    ///
+    /// Reusing one buffer across many decodes avoids an allocation for each
+    /// escaped or non-ascii string.
+    ///
+    /// ```
+    /// use jomini::{Encoding, Windows1252Encoding};
+    ///
+    /// let encoding = Windows1252Encoding::new();
+    /// let mut scratch = String::new();
+    /// assert_eq!(encoding.decode_scratch(b"Common Sense", &mut scratch), Some("Common Sense"));
+    /// assert_eq!(encoding.decode_scratch(b"\xa7GHello", &mut scratch), None);
+    /// assert_eq!(scratch, "§GHello");
+    /// ```
+    fn decode_scratch<'a>(&self, data: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+        match self.decode(data) {
+            Cow::Borrowed(s) => Some(s),
+            Cow::Owned(s) => {
+                *scratch = s;
+                None
+            }
+        }
+    }
 }
 
 /// Decodes bytes according to the windows1252 code page
@@ -39,11 +69,11 @@ pub struct Windows1252Encoding;
 impl Windows1252Encoding {
     /// Creates a new windows 1252 decoder
     pub fn new() -> Self {
-        Self::default()
+        Windows1252Encoding
     }
 
     /// Static method for decoding windows 1252 data
-    pub fn decode(data: &[u8]) -> Cow<str> {
+    pub fn decode(data: &[u8]) -> Cow<'_, str> {
         decode_windows1252(data)
     }
 }
@@ -52,12 +82,30 @@ impl Encoding for Windows1252Encoding {
     fn decode<'a>(&self, data: &'a [u8]) -> Cow<'a, str> {
         Windows1252Encoding::decode(data)
     }
+
+    fn decode_scratch<'a>(&self, data: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+        decode_windows1252_scratch(data, scratch)
+    }
+}
+
+impl<T: Encoding + ?Sized> Encoding for &'_ T {
+    fn decode<'a>(&self, data: &'a [u8]) -> Cow<'a, str> {
+        (**self).decode(data)
+    }
+
+    fn decode_scratch<'a>(&self, data: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+        (**self).decode_scratch(data, scratch)
+    }
 }
 
-impl<T: Encoding> Encoding for &'_ T {
+impl<T: Encoding + ?Sized> Encoding for Box<T> {
     fn decode<'a>(&self, data: &'a [u8]) -> Cow<'a, str> {
         (**self).decode(data)
     }
+
+    fn decode_scratch<'a>(&self, data: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+        (**self).decode_scratch(data, scratch)
+    }
 }
 
 /// Decodes bytes according to the utf8 standard
@@ -78,11 +126,11 @@ pub struct Utf8Encoding;
 impl Utf8Encoding {
     /// Creates a new utf8 decoder
     pub fn new() -> Self {
-        Self::default()
+        Utf8Encoding
     }
 
     /// Static method for decoding utf8 data
-    pub fn decode(data: &[u8]) -> Cow<str> {
+    pub fn decode(data: &[u8]) -> Cow<'_, str> {
         decode_utf8(data)
     }
 }
@@ -91,6 +139,10 @@ impl Encoding for Utf8Encoding {
     fn decode<'a>(&self, data: &'a [u8]) -> Cow<'a, str> {
         Utf8Encoding::decode(data)
     }
+
+    fn decode_scratch<'a>(&self, data: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+        decode_utf8_scratch(data, scratch)
+    }
 }
 
 #[inline]
@@ -102,7 +154,7 @@ fn trim_trailing_whitepsace(d: &[u8]) -> &[u8] {
             .iter()
             .rev()
             .position(|x| !is_whitespace(*x))
-            .unwrap_or_else(|| d.len());
+            .unwrap_or(d.len());
         &d[0..d.len() - ind]
     } else {
         d
@@ -110,17 +162,43 @@ fn trim_trailing_whitepsace(d: &[u8]) -> &[u8] {
 }
 
 #[inline]
-pub(crate) fn decode_windows1252(d: &[u8]) -> Cow<str> {
+pub(crate) fn decode_windows1252(d: &[u8]) -> Cow<'_, str> {
+    match windows1252_borrowed(d) {
+        Ok(s) => Cow::Borrowed(s),
+        Err((d, offset)) => {
+            let mut result = String::new();
+            windows_1252_create(d, offset, &mut result);
+            Cow::Owned(result)
+        }
+    }
+}
+
+#[inline]
+fn decode_windows1252_scratch<'a>(d: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+    match windows1252_borrowed(d) {
+        Ok(s) => Some(s),
+        Err((d, offset)) => {
+            scratch.clear();
+            windows_1252_create(d, offset, scratch);
+            None
+        }
+    }
+}
+
+/// Returns the data as a string if it can be borrowed, else the trimmed data
+/// and the offset of the first byte that needs to be converted
+#[inline]
+fn windows1252_borrowed(d: &[u8]) -> Result<&str, (&[u8], usize)> {
     let d = trim_trailing_whitepsace(d);
 
     // Then we iterate through the data in 8 byte chunks and ensure that each chunk
     // is contained of ascii characters with no escape characters
     let mut chunk_iter = d.chunks_exact(8);
     let mut offset = 0;
-    while let Some(n) = chunk_iter.next() {
+    for n in chunk_iter.by_ref() {
         let wide = le_u64(n);
         if wide & 0x80808080_80808080 != 0 || contains_zero_byte(wide ^ repeat_byte(b'\\')) {
-            return Cow::Owned(windows_1252_create(d, offset));
+            return Err((d, offset));
         }
 
         offset += 8;
@@ -130,7 +208,7 @@ pub(crate) fn decode_windows1252(d: &[u8]) -> Cow<str> {
     let remainder = chunk_iter.remainder();
     for &byte in remainder {
         if !byte.is_ascii() || byte == b'\\' {
-            return Cow::Owned(windows_1252_create(d, offset));
+            return Err((d, offset));
         }
 
         offset += 1;
@@ -139,23 +217,47 @@ pub(crate) fn decode_windows1252(d: &[u8]) -> Cow<str> {
     // This is safe as we just checked that the data is ascii and ascii is a subset of utf8
     debug_assert!(std::str::from_utf8(d).is_ok());
     let s = unsafe { std::str::from_utf8_unchecked(d) };
-    Cow::Borrowed(s)
+    Ok(s)
 }
 
-fn windows_1252_create(d: &[u8], offset: usize) -> String {
+fn windows_1252_create(d: &[u8], offset: usize, result: &mut String) {
     let (upto, rest) = d.split_at(offset);
-    let mut result = String::with_capacity(d.len());
+    result.reserve(d.len());
     let head = unsafe { std::str::from_utf8_unchecked(upto) };
     result.push_str(head);
     for &c in rest.iter().filter(|&x| *x != b'\\') {
         result.push(WINDOWS_1252[c as usize]);
     }
+}
 
-    result
+#[inline]
+pub(crate) fn decode_utf8(d: &[u8]) -> Cow<'_, str> {
+    match utf8_borrowed(d) {
+        Ok(s) => Cow::Borrowed(s),
+        Err((d, offset)) => {
+            let mut result = String::new();
+            utf8_create(d, offset, &mut result);
+            Cow::Owned(result)
+        }
+    }
 }
 
 #[inline]
-pub(crate) fn decode_utf8(d: &[u8]) -> Cow<str> {
+fn decode_utf8_scratch<'a>(d: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+    match utf8_borrowed(d) {
+        Ok(s) => Some(s),
+        Err((d, offset)) => {
+            utf8_create(d, offset, scratch);
+            None
+        }
+    }
+}
+
+/// Returns the data as a string if it can be borrowed, else the trimmed data
+/// and the offset of the first byte that needs to be unescaped. Invalid utf-8
+/// is reported at the end of the data.
+#[inline]
+fn utf8_borrowed(d: &[u8]) -> Result<&str, (&[u8], usize)> {
     let d = trim_trailing_whitepsace(d);
 
     // Then we iterate through the data in 8 byte chunks and ensure that each chunk
@@ -163,11 +265,11 @@ pub(crate) fn decode_utf8(d: &[u8]) -> Cow<str> {
     let mut chunk_iter = d.chunks_exact(8);
     let mut offset = 0;
     let mut is_ascii = true;
-    while let Some(n) = chunk_iter.next() {
+    for n in chunk_iter.by_ref() {
         let wide = le_u64(n);
         is_ascii &= wide & 0x80808080_80808080 == 0;
         if contains_zero_byte(wide ^ repeat_byte(b'\\')) {
-            return Cow::Owned(utf8_create(d, offset));
+            return Err((d, offset));
         }
 
         offset += 8;
@@ -178,7 +280,7 @@ pub(crate) fn decode_utf8(d: &[u8]) -> Cow<str> {
     for &byte in remainder {
         is_ascii &= byte.is_ascii();
         if byte == b'\\' {
-            return Cow::Owned(utf8_create(d, offset));
+            return Err((d, offset));
         }
 
         offset += 1;
@@ -192,22 +294,25 @@ pub(crate) fn decode_utf8(d: &[u8]) -> Cow<str> {
         // This is safe as we just checked that the data is ascii and ascii is a subset of utf8
         debug_assert!(std::str::from_utf8(d).is_ok());
         let s = unsafe { std::str::from_utf8_unchecked(d) };
-        Cow::Borrowed(s)
+        Ok(s)
     } else {
-        String::from_utf8_lossy(d)
+        std::str::from_utf8(d).map_err(|_| (d, d.len()))
     }
 }
 
-fn utf8_create(d: &[u8], offset: usize) -> String {
+/// Writes the unescaped data to the result, replacing invalid utf-8
+fn utf8_create(d: &[u8], offset: usize, result: &mut String) {
     let (upto, rest) = d.split_at(offset);
-    let mut result = Vec::with_capacity(d.len());
-    result.extend_from_slice(upto);
+    let mut buf = std::mem::take(result).into_bytes();
+    buf.clear();
+    buf.reserve(d.len());
+    buf.extend_from_slice(upto);
     for &c in rest.iter().filter(|&x| *x != b'\\') {
-        result.push(c);
+        buf.push(c);
     }
 
-    String::from_utf8(result)
-        .unwrap_or_else(|e| String::from_utf8_lossy(&e.into_bytes()).into_owned())
+    *result = String::from_utf8(buf)
+        .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
 }
 
 #[cfg(test)]
@@ -220,6 +325,32 @@ mod tests {
         assert_eq!(Windows1252Encoding::decode(b"\t"), "");
     }
 
+    #[test]
+    fn scalar_decode_scratch_reuses_buffer() {
+        let utf8 = Utf8Encoding::new();
+        let mut scratch = String::with_capacity(64);
+        let ptr = scratch.as_ptr();
+        assert_eq!(
+            utf8.decode_scratch(b"J\xc3\xa5hk\n", &mut scratch),
+            Some("Jåhk")
+        );
+        assert_eq!(utf8.decode_scratch(br#"a \"b\""#, &mut scratch), None);
+        assert_eq!(utf8.decode_scratch(br#"\"c\""#, &mut scratch), None);
+        assert_eq!(scratch, r#""c""#);
+        assert_eq!(scratch.as_ptr(), ptr);
+
+        assert_eq!(utf8.decode_scratch(b"a\xffb", &mut scratch), None);
+        assert_eq!(scratch, "a\u{fffd}b");
+
+        let win = Windows1252Encoding::new();
+        let mut scratch = String::with_capacity(64);
+        let ptr = scratch.as_ptr();
+        assert_eq!(win.decode_scratch(b"\xa7G", &mut scratch), None);
+        assert_eq!(win.decode_scratch(b"\x8a", &mut scratch), None);
+        assert_eq!(scratch, "Š");
+        assert_eq!(scratch.as_ptr(), ptr);
+    }
+
     #[test]
     fn scalar_string_escapes() {
         let data = br#"Joe \"Captain\" Rogers"#;
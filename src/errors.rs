@@ -6,12 +6,23 @@ use std::fmt;
 pub struct Error(Box<ErrorKind>);
 
 impl Error {
+    #[cold]
     pub(crate) fn new(kind: ErrorKind) -> Error {
         Error(Box::new(kind))
     }
 
-    pub(crate) fn eof() -> Error {
-        Self::new(ErrorKind::Eof)
+    #[cold]
+    pub(crate) fn eof(expected: &'static str) -> Error {
+        Self::new(ErrorKind::Eof { expected })
+    }
+
+    /// Objects and arrays were nested deeper than a parser's `max_depth`
+    #[cold]
+    pub(crate) fn depth_exceeded(offset: usize) -> Error {
+        Self::new(ErrorKind::LimitExceeded {
+            limit: "maximum depth",
+            offset,
+        })
     }
 
     /// Return the specific type of error
@@ -19,17 +30,63 @@ impl Error {
         &self.0
     }
 
+    /// Consumes the error and returns the specific type of error
+    pub fn into_kind(self) -> ErrorKind {
+        *self.0
+    }
+
     /// Returns the byte offset that the error occurs (if available)
     pub fn offset(&self) -> Option<usize> {
         self.0.offset()
     }
+
+    /// Returns true if the error was caused by the data ending prematurely,
+    /// which may be resolved by retrying with more data
+    pub fn is_eof(&self) -> bool {
+        matches!(*self.0, ErrorKind::Eof { .. })
+    }
+
+    /// Returns the binary token that caused the error (if available)
+    pub fn token_id(&self) -> Option<u16> {
+        self.0.token_id()
+    }
+
+    /// Record that the error occurred within the value of the given key. See
+    /// `DeserializeError::within`.
+    #[cfg(feature = "derive")]
+    #[cold]
+    pub(crate) fn within(mut self, key: &str) -> Error {
+        if let ErrorKind::Deserialize(err) = *self.0 {
+            *self.0 = ErrorKind::Deserialize(err.within(key));
+        }
+        self
+    }
+
+    /// Shifts the offset of an error that occurred in a subslice of the data
+    pub(crate) fn offset_by(mut self, by: usize) -> Error {
+        match *self.0 {
+            ErrorKind::StackEmpty { ref mut offset }
+            | ErrorKind::InvalidEmptyObject { ref mut offset }
+            | ErrorKind::InvalidSyntax { ref mut offset, .. }
+            | ErrorKind::LimitExceeded { ref mut offset, .. } => *offset += by,
+            _ => {}
+        }
+        self
+    }
 }
 
 /// Specific type of error
+///
+/// New variants may be added in the future, so matches should include a
+/// wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ErrorKind {
     /// Unexpected end of input
-    Eof,
+    Eof {
+        /// A description of what was expected when the input ended
+        expected: &'static str,
+    },
 
     /// Too many close delimiters were encountered
     StackEmpty {
@@ -52,6 +109,53 @@ pub enum ErrorKind {
         offset: usize,
     },
 
+    /// A binary token could not be resolved to a name
+    UnknownToken {
+        /// The unresolved 16bit token
+        token_id: u16,
+    },
+
+    /// Data was not valid utf-8
+    Utf8(std::str::Utf8Error),
+
+    /// An error occurred when reading the data
+    Io(std::io::Error),
+
+    /// A token resolver could not be created or queried
+    Resolve(String),
+
+    /// The data exceeded a configured limit (eg: `TextTapeParser::max_depth`)
+    LimitExceeded {
+        /// A description of the limit that was exceeded
+        limit: &'static str,
+
+        /// The byte offset where the limit was exceeded
+        offset: usize,
+    },
+
+    /// A writer was used incorrectly (eg: a value was written in an object
+    /// without a key)
+    Writer(String),
+
+    /// The objects and arrays of a tape are not properly nested. See
+    /// `TextTape::validate`.
+    InvalidTape {
+        /// A description of the problem
+        msg: String,
+
+        /// The index of the offending token
+        index: usize,
+    },
+
+    /// An edit of a tape would have broken its structure
+    InvalidEdit {
+        /// A description of why the edit was rejected
+        msg: String,
+
+        /// The index of the token that was to be edited
+        index: usize,
+    },
+
     /// An error occurred when deserializing the data
     Deserialize(DeserializeError),
 }
@@ -63,6 +167,15 @@ impl ErrorKind {
             ErrorKind::StackEmpty { offset, .. } => Some(offset),
             ErrorKind::InvalidEmptyObject { offset, .. } => Some(offset),
             ErrorKind::InvalidSyntax { offset, .. } => Some(offset),
+            ErrorKind::LimitExceeded { offset, .. } => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// The binary token that caused the error
+    pub fn token_id(&self) -> Option<u16> {
+        match *self {
+            ErrorKind::UnknownToken { token_id } => Some(token_id),
             _ => None,
         }
     }
@@ -71,6 +184,8 @@ impl ErrorKind {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self.0 {
+            ErrorKind::Utf8(ref err) => Some(err),
+            ErrorKind::Io(ref err) => Some(err),
             ErrorKind::Deserialize(ref err) => Some(err),
             _ => None,
         }
@@ -80,7 +195,9 @@ impl std::error::Error for Error {
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self.0 {
-            ErrorKind::Eof => write!(f, "unexpected end of file"),
+            ErrorKind::Eof { expected } => {
+                write!(f, "unexpected end of file, expected {}", expected)
+            }
             ErrorKind::StackEmpty { offset } => write!(f,
                 "stack empty, too many close tokens encountered (offset: {})", offset
             ),
@@ -90,14 +207,54 @@ impl std::fmt::Display for Error {
             ErrorKind::InvalidSyntax { ref msg, offset } => write!(f,
                 "invalid syntax encountered: {} (offset: {})", msg, offset
             ),
+            ErrorKind::UnknownToken { token_id } => {
+                write!(f, "unknown binary token encountered (id: {})", token_id)
+            }
+            ErrorKind::Utf8(ref err) => write!(f, "invalid utf-8: {}", err),
+            ErrorKind::Io(ref err) => write!(f, "io error: {}", err),
+            ErrorKind::Resolve(ref msg) => write!(f, "token resolver error: {}", msg),
+            ErrorKind::LimitExceeded { limit, offset } => {
+                write!(f, "limit exceeded: {} (offset: {})", limit, offset)
+            }
+            ErrorKind::Writer(ref msg) => write!(f, "writer error: {}", msg),
+            ErrorKind::InvalidTape { ref msg, index } => {
+                write!(f, "invalid tape: {} (index: {})", msg, index)
+            }
+            ErrorKind::InvalidEdit { ref msg, index } => {
+                write!(f, "invalid tape edit: {} (index: {})", msg, index)
+            }
             ErrorKind::Deserialize(ref err) => write!(f, "deserialize error: {}", err),
         }
     }
 }
 
 impl From<DeserializeError> for Error {
+    #[cold]
     fn from(error: DeserializeError) -> Self {
-        Error::new(ErrorKind::Deserialize(error))
+        match error.kind {
+            DeserializeErrorKind::UnknownToken { token_id } => {
+                Error::new(ErrorKind::UnknownToken { token_id })
+            }
+            _ => Error::new(ErrorKind::Deserialize(error)),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::new(ErrorKind::Io(error))
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(error: std::str::Utf8Error) -> Self {
+        Error::new(ErrorKind::Utf8(error))
+    }
+}
+
+impl From<ScalarError> for Error {
+    fn from(error: ScalarError) -> Self {
+        Error::from(DeserializeError::from(error))
     }
 }
 
@@ -112,6 +269,22 @@ impl DeserializeError {
     pub fn kind(&self) -> &DeserializeErrorKind {
         &self.kind
     }
+
+    /// Record that the error occurred within the value of the given key, so
+    /// that errors that report a path build it up as they bubble out
+    #[cfg(feature = "derive")]
+    #[cold]
+    pub(crate) fn within(mut self, key: &str) -> Self {
+        if let DeserializeErrorKind::IntegerOverflow { ref mut path, .. } = self.kind {
+            if path.is_empty() {
+                *path = String::from(key);
+            } else {
+                path.insert(0, '.');
+                path.insert_str(0, key);
+            }
+        }
+        self
+    }
 }
 
 /// The type of a Serde deserialization error.
@@ -131,6 +304,20 @@ pub enum DeserializeErrorKind {
         /// The unknown 16bit token
         token_id: u16,
     },
+
+    /// An integer in the data doesn't fit in the requested type. See
+    /// `IntegerOverflow`.
+    IntegerOverflow {
+        /// The keys that lead to the value separated by periods (eg:
+        /// `countries.ENG.treasury`). Empty for a value at the root.
+        path: String,
+
+        /// The integer in the data
+        value: i128,
+
+        /// The name of the requested type (eg: `u8`)
+        target: &'static str,
+    },
 }
 
 impl std::error::Error for DeserializeError {
@@ -153,12 +340,24 @@ impl std::fmt::Display for DeserializeError {
             DeserializeErrorKind::UnknownToken { token_id } => {
                 write!(f, "unknown binary token encountered (id: {})", token_id)
             }
+            DeserializeErrorKind::IntegerOverflow {
+                ref path,
+                value,
+                target,
+            } => {
+                write!(f, "integer {} does not fit in {}", value, target)?;
+                if !path.is_empty() {
+                    write!(f, " at `{}`", path)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
 #[cfg(feature = "serde")]
 impl serde::de::Error for DeserializeError {
+    #[cold]
     fn custom<T: fmt::Display>(msg: T) -> Self {
         DeserializeError {
             kind: DeserializeErrorKind::Message(msg.to_string()),
@@ -166,10 +365,51 @@ impl serde::de::Error for DeserializeError {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::from(<DeserializeError as serde::de::Error>::custom(msg))
+    }
+}
+
 impl From<ScalarError> for DeserializeError {
+    #[cold]
     fn from(error: ScalarError) -> Self {
         DeserializeError {
             kind: DeserializeErrorKind::Scalar(error),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_io_error_source() {
+        let io = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated");
+        let err = Error::from(io);
+        assert!(matches!(err.kind(), ErrorKind::Io(_)));
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "truncated");
+    }
+
+    #[test]
+    fn test_utf8_error_source() {
+        let data = vec![b'a', 0xff];
+        let utf8 = std::str::from_utf8(&data).unwrap_err();
+        let err = Error::from(utf8);
+        assert!(matches!(err.kind(), ErrorKind::Utf8(_)));
+        assert!(err.source().unwrap().is::<std::str::Utf8Error>());
+    }
+
+    #[test]
+    fn test_scalar_error_source_chain() {
+        let err = Error::from(ScalarError::Overflow);
+        let deserialize = err.source().unwrap();
+        assert!(deserialize.is::<DeserializeError>());
+        let scalar = deserialize.source().unwrap();
+        assert!(scalar.is::<ScalarError>());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{
+    BinaryDeserializer, BinaryTape, Error, ErrorKind, FailedResolveStrategy, TextDeserializer,
+    TextTape, TokenResolver,
+};
+use serde::Deserialize;
+
+#[cfg(feature = "zip")]
+use crate::trace::span;
+#[cfg(feature = "zip")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "zip")]
+use std::io::Read;
+
+const TEXT_HEADER: &[u8] = b"EU4txt";
+const BINARY_HEADER: &[u8] = b"EU4bin";
+
+/// Deserializes an EU4 save whose data is spread across the entries of a
+/// zip (`meta`, `gamestate`, and `ai`) into a single user type.
+///
+/// Each entry starts with its own `EU4txt` / `EU4bin` header, and the
+/// `meta` entry repeats fields that are also found in the `gamestate` (like
+/// `date`), so simply concatenating the entries leads to duplicate fields.
+/// Instead, the entries are merged in order: when a top level key appears in
+/// a later entry, the occurrences from earlier entries are dropped, so the
+/// gamestate takes precedence over the meta. Duplicate keys within a single
+/// entry are kept as is.
+///
+/// ```
+/// use jomini::Eu4SaveDeserializer;
+/// use serde::Deserialize;
+/// use std::collections::HashMap;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// struct Save {
+///     date: String,
+///     savegame_version: String,
+///     player: String,
+/// }
+///
+/// let meta = b"EU4txt\ndate=1444.11.11\nsavegame_version=\"1.30\"";
+/// let gamestate = b"EU4txt\ndate=1444.11.11\nplayer=\"ENG\"";
+/// let resolver: HashMap<u16, &str> = HashMap::new();
+/// let save: Save = Eu4SaveDeserializer::new().from_entries(&[&meta[..], &gamestate[..]], &resolver)?;
+/// assert_eq!(save.player, "ENG");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Eu4SaveDeserializer {
+    entries: Vec<String>,
+    failed_resolve_strategy: FailedResolveStrategy,
+}
+
+impl Default for Eu4SaveDeserializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Eu4SaveDeserializer {
+    /// Create a deserializer that merges the `meta`, `gamestate`, and `ai`
+    /// entries of a zip
+    pub fn new() -> Self {
+        Eu4SaveDeserializer {
+            entries: vec![
+                String::from("meta"),
+                String::from("gamestate"),
+                String::from("ai"),
+            ],
+            failed_resolve_strategy: FailedResolveStrategy::Ignore,
+        }
+    }
+
+    /// Set the names of the zip entries to merge, in the order of increasing
+    /// precedence. Entries that are missing from the zip are skipped.
+    pub fn entries<I, S>(&mut self, names: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.entries = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the behavior when a unknown token is encountered in binary entries
+    pub fn on_failed_resolve(&mut self, strategy: FailedResolveStrategy) -> &mut Self {
+        self.failed_resolve_strategy = strategy;
+        self
+    }
+
+    /// Deserialize entries that have already been extracted from the zip.
+    /// The entries are given in the order of increasing precedence and must
+    /// all be either plaintext or binary.
+    pub fn from_entries<'a, 'res: 'a, RES, T>(
+        &self,
+        entries: &[&'a [u8]],
+        resolver: &'res RES,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        RES: TokenResolver,
+    {
+        let mut binary = None;
+        let mut bodies = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let (is_binary, body) = if let Some(body) = entry.strip_prefix(BINARY_HEADER) {
+                (true, body)
+            } else if let Some(body) = entry.strip_prefix(TEXT_HEADER) {
+                (false, body)
+            } else {
+                return Err(entry_error(
+                    "expected save entry to start with EU4txt or EU4bin",
+                ));
+            };
+
+            if binary.is_some_and(|x| x != is_binary) {
+                return Err(entry_error(
+                    "expected save entries to all be plaintext or all be binary",
+                ));
+            }
+
+            binary = Some(is_binary);
+            bodies.push(body);
+        }
+
+        match binary {
+            Some(true) => {
+                let tape = BinaryTape::eu4_parser().parse_slices(bodies)?;
+                BinaryDeserializer::eu4_builder()
+                    .on_failed_resolve(self.failed_resolve_strategy)
+                    .from_tape(&tape, resolver)
+            }
+            Some(false) => {
+                let tape = TextTape::parser().parse_slices(bodies)?;
+                TextDeserializer::from_windows1252_tape(&tape)
+            }
+            None => Err(entry_error("expected at least one save entry")),
+        }
+    }
+
+    /// Extract the configured entries from the zip and deserialize them
+    #[cfg(feature = "zip")]
+    pub fn from_zip_slice<RES, T>(&self, data: &[u8], resolver: &RES) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+        RES: TokenResolver,
+    {
+        let span = span!("extract_zip", bytes = data.len(); entries, extracted_bytes);
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(zip_error)?;
+        let mut buffers = Vec::new();
+        for name in &self.entries {
+            let mut file = match archive.by_name(name) {
+                Ok(file) => file,
+                Err(zip::result::ZipError::FileNotFound) => continue,
+                Err(e) => return Err(zip_error(e)),
+            };
+
+            let mut buf = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut buf)?;
+            buffers.push(buf);
+        }
+
+        span.record("entries", buffers.len());
+        span.record("extracted_bytes", buffers.iter().map(|x| x.len()).sum());
+        drop(span);
+
+        let entries: Vec<&[u8]> = buffers.iter().map(|x| x.as_slice()).collect();
+        self.from_entries(&entries, resolver)
+    }
+}
+
+fn entry_error(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidSyntax {
+        msg: String::from(msg),
+        offset: 0,
+    })
+}
+
+#[cfg(feature = "zip")]
+fn zip_error(err: zip::result::ZipError) -> Error {
+    match err {
+        zip::result::ZipError::Io(e) => Error::from(e),
+        e => Error::from(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Save {
+        date: String,
+        player: String,
+        #[serde(default)]
+        ironman: bool,
+    }
+
+    #[test]
+    fn test_merge_binary_entries() {
+        let meta = [
+            b"EU4bin".as_ref(),
+            &[0x4d, 0x28, 0x01, 0x00, 0x0f, 0x00, 0x02, 0x00, 0x31, 0x31],
+            &[0x36, 0x2f, 0x01, 0x00, 0x0e, 0x00, 0x01],
+        ]
+        .concat();
+        let gamestate = [
+            b"EU4bin".as_ref(),
+            &[0x4d, 0x28, 0x01, 0x00, 0x0f, 0x00, 0x02, 0x00, 0x31, 0x32],
+            &[
+                0x38, 0x2a, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47,
+            ],
+        ]
+        .concat();
+
+        let mut map = HashMap::new();
+        map.insert(0x284d, "date");
+        map.insert(0x2a38, "player");
+        map.insert(0x2f36, "ironman");
+
+        let save: Save = Eu4SaveDeserializer::new()
+            .from_entries(&[&meta[..], &gamestate[..]], &map)
+            .unwrap();
+        assert_eq!(
+            save,
+            Save {
+                date: String::from("12"),
+                player: String::from("ENG"),
+                ironman: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_mixed_entries() {
+        let map: HashMap<u16, &str> = HashMap::new();
+        let err = Eu4SaveDeserializer::new()
+            .from_entries::<_, Save>(&[&b"EU4txt\na=1"[..], &b"EU4bin"[..]], &map)
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidSyntax { .. }));
+
+        let err = Eu4SaveDeserializer::new()
+            .from_entries::<_, Save>(&[&b"a=1"[..]], &map)
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidSyntax { .. }));
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_from_zip_slice() {
+        use std::io::Write;
+        use zip::write::FileOptions;
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer.start_file("meta", options).unwrap();
+        writer
+            .write_all(b"EU4txt\ndate=1444.11.11\nironman=yes\nplayer=\"FRA\"")
+            .unwrap();
+        writer.start_file("gamestate", options).unwrap();
+        writer
+            .write_all(b"EU4txt\ndate=1445.1.1\nplayer=\"ENG\"")
+            .unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let map: HashMap<u16, &str> = HashMap::new();
+        let save: Save = Eu4SaveDeserializer::new()
+            .from_zip_slice(&data, &map)
+            .unwrap();
+        assert_eq!(
+            save,
+            Save {
+                date: String::from("1445.1.1"),
+                player: String::from("ENG"),
+                ironman: true,
+            }
+        );
+
+        let err = Eu4SaveDeserializer::new()
+            .from_zip_slice::<_, Save>(b"not a zip", &map)
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Io(_)));
+    }
+}
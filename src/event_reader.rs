@@ -0,0 +1,258 @@
+use crate::{BinaryEvent, BinaryFlavor, BinaryPushParser, Error, TextEvent, TextPushParser};
+use std::collections::VecDeque;
+use std::io::Read;
+
+const CHUNK_SIZE: usize = 32 * 1024;
+
+/// Reads text events from an `io::Read` source, so that a save can be
+/// processed without holding the entire file in memory.
+///
+/// With the `flate2` feature, the reader can also decompress the data as it
+/// is read, which avoids a full decompressed copy of a compressed save.
+///
+/// ```
+/// use jomini::{TextEvent, TextEventReader};
+///
+/// let data = &b"player=\"ENG\""[..];
+/// let mut reader = TextEventReader::new(data);
+/// let mut events = Vec::new();
+/// while let Some(event) = reader.next_event()? {
+///     events.push(event);
+/// }
+///
+/// assert_eq!(events[2], TextEvent::Quoted(b"ENG".to_vec()));
+/// # Ok::<(), jomini::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct TextEventReader<R> {
+    reader: R,
+    parser: TextPushParser,
+    events: VecDeque<TextEvent>,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R> TextEventReader<R>
+where
+    R: Read,
+{
+    /// Create a reader of text events
+    pub fn new(reader: R) -> Self {
+        TextEventReader {
+            reader,
+            parser: TextPushParser::new(),
+            events: VecDeque::new(),
+            buf: vec![0; CHUNK_SIZE],
+            done: false,
+        }
+    }
+
+    /// Return the next event or None when the data has been exhausted
+    pub fn next_event(&mut self) -> Result<Option<TextEvent>, Error> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            if self.done {
+                return Ok(None);
+            }
+
+            let read = self.reader.read(&mut self.buf)?;
+            if read == 0 {
+                self.done = true;
+                self.parser.finish_into(&mut self.events)?;
+            } else {
+                self.parser.feed_into(&self.buf[..read], &mut self.events)?;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl<R: Read> TextEventReader<flate2::read::ZlibDecoder<R>> {
+    /// Create a reader of text events from zlib compressed data
+    pub fn zlib(reader: R) -> Self {
+        TextEventReader::new(flate2::read::ZlibDecoder::new(reader))
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl<R: Read> TextEventReader<flate2::read::GzDecoder<R>> {
+    /// Create a reader of text events from gzip compressed data
+    pub fn gzip(reader: R) -> Self {
+        TextEventReader::new(flate2::read::GzDecoder::new(reader))
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl<R: Read> TextEventReader<flate2::read::DeflateDecoder<R>> {
+    /// Create a reader of text events from raw deflate data, which is how
+    /// entries are compressed inside of zip files
+    pub fn deflate(reader: R) -> Self {
+        TextEventReader::new(flate2::read::DeflateDecoder::new(reader))
+    }
+}
+
+/// Reads binary events from an `io::Read` source, so that a save can be
+/// processed without holding the entire file in memory. See
+/// `TextEventReader`.
+///
+/// ```
+/// use jomini::{BinaryEvent, BinaryEventReader, Eu4Flavor};
+///
+/// let data = &[0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x59, 0x00, 0x00, 0x00][..];
+/// let mut reader = BinaryEventReader::new(data, Eu4Flavor::new());
+/// let mut events = Vec::new();
+/// while let Some(event) = reader.next_event()? {
+///     events.push(event);
+/// }
+///
+/// assert_eq!(
+///     events,
+///     vec![BinaryEvent::Token(0x2d82), BinaryEvent::Equal, BinaryEvent::I32(89)]
+/// );
+/// # Ok::<(), jomini::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct BinaryEventReader<R, F> {
+    reader: R,
+    parser: BinaryPushParser<F>,
+    events: VecDeque<BinaryEvent>,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R, F> BinaryEventReader<R, F>
+where
+    R: Read,
+    F: BinaryFlavor,
+{
+    /// Create a reader of binary events with the given flavor
+    pub fn new(reader: R, flavor: F) -> Self {
+        BinaryEventReader {
+            reader,
+            parser: BinaryPushParser::new(flavor),
+            events: VecDeque::new(),
+            buf: vec![0; CHUNK_SIZE],
+            done: false,
+        }
+    }
+
+    /// Return the next event or None when the data has been exhausted
+    pub fn next_event(&mut self) -> Result<Option<BinaryEvent>, Error> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            if self.done {
+                return Ok(None);
+            }
+
+            let read = self.reader.read(&mut self.buf)?;
+            if read == 0 {
+                self.done = true;
+                self.parser.check_finished()?;
+            } else {
+                self.parser.feed_into(&self.buf[..read], &mut self.events);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl<R: Read, F: BinaryFlavor> BinaryEventReader<flate2::read::ZlibDecoder<R>, F> {
+    /// Create a reader of binary events from zlib compressed data
+    pub fn zlib(reader: R, flavor: F) -> Self {
+        BinaryEventReader::new(flate2::read::ZlibDecoder::new(reader), flavor)
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl<R: Read, F: BinaryFlavor> BinaryEventReader<flate2::read::GzDecoder<R>, F> {
+    /// Create a reader of binary events from gzip compressed data
+    pub fn gzip(reader: R, flavor: F) -> Self {
+        BinaryEventReader::new(flate2::read::GzDecoder::new(reader), flavor)
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl<R: Read, F: BinaryFlavor> BinaryEventReader<flate2::read::DeflateDecoder<R>, F> {
+    /// Create a reader of binary events from raw deflate data, which is how
+    /// entries are compressed inside of zip files
+    pub fn deflate(reader: R, flavor: F) -> Self {
+        BinaryEventReader::new(flate2::read::DeflateDecoder::new(reader), flavor)
+    }
+}
+
+#[cfg(all(test, feature = "flate2"))]
+mod tests {
+    use super::*;
+    use crate::Eu4Flavor;
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn text_events<R: Read>(mut reader: TextEventReader<R>) -> Vec<TextEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event().unwrap() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_compressed_text_events() {
+        let data = b"a=1 b={ c=d }";
+        let expected = text_events(TextEventReader::new(&data[..]));
+
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(data).unwrap();
+        let zlib = zlib.finish().unwrap();
+        assert_eq!(text_events(TextEventReader::zlib(&zlib[..])), expected);
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(data).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(text_events(TextEventReader::gzip(&gzip[..])), expected);
+
+        let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(data).unwrap();
+        let deflate = deflate.finish().unwrap();
+        assert_eq!(
+            text_events(TextEventReader::deflate(&deflate[..])),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_compressed_binary_events() {
+        let data = [0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x59, 0x00, 0x00, 0x00];
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&data).unwrap();
+        let zlib = zlib.finish().unwrap();
+
+        let mut reader = BinaryEventReader::zlib(&zlib[..], Eu4Flavor::new());
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event().unwrap() {
+            events.push(event);
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                BinaryEvent::Token(0x2d82),
+                BinaryEvent::Equal,
+                BinaryEvent::I32(89)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_corrupt_compressed_data() {
+        let mut reader = TextEventReader::zlib(&b"not zlib data"[..]);
+        let err = reader.next_event().unwrap_err();
+        assert!(matches!(err.kind(), crate::ErrorKind::Io(_)));
+    }
+}
@@ -0,0 +1,866 @@
+use super::operator_symbol;
+use crate::{
+    BinaryFlavor, BinaryTape, BinaryToken, Encoding, Interner, Operator, Rgb, RgbRepresentation,
+    TextTape, TextToken, TokenResolver,
+};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// How keys that occur multiple times within an object are written to JSON
+///
+/// Given `core=ENG core=FRA capital=1`:
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeyMode {
+    /// Write every occurrence as is, which is valid, but not well supported,
+    /// JSON. This is the default.
+    ///
+    /// `{"core":"ENG","core":"FRA","capital":"1"}`
+    Preserve,
+
+    /// Group the values of every key into an array, even when the key
+    /// occurs once
+    ///
+    /// `{"core":["ENG","FRA"],"capital":["1"]}`
+    ArraysAlways,
+
+    /// Group the values of a key into an array only when the key occurs
+    /// more than once
+    ///
+    /// `{"core":["ENG","FRA"],"capital":"1"}`
+    ArraysWhenDuplicated,
+
+    /// Only write the first occurrence of a key
+    ///
+    /// `{"core":"ENG","capital":"1"}`
+    FirstWins,
+
+    /// Only write the last occurrence of a key, at the position of the
+    /// first occurrence
+    ///
+    /// `{"core":"FRA","capital":"1"}`
+    LastWins,
+
+    /// Write objects and arrays with a type tag so that their shape is
+    /// never ambiguous: objects are a list of key value pairs and arrays are
+    /// wrapped. This mirrors the output used by Rakaly.
+    ///
+    /// `{"type":"obj","val":[["core","ENG"],["core","FRA"],["capital","1"]]}`
+    Typed,
+}
+
+/// Options for converting a tape into JSON.
+///
+/// Values are written as they would be seen when deserialized into a
+/// dynamic value: text scalars are strings while binary scalars keep their
+/// type, values with a non-equal operator are wrapped in an object keyed by
+/// the operator (`{">":"1"}`), and values with a header are wrapped in an
+/// object keyed by the header (`{"rgb":["1","2","3"]}`). Colors can be
+/// written in another [representation](RgbRepresentation).
+///
+/// ```
+/// use jomini::{export::{DuplicateKeyMode, JsonOptions}, TextTape, Windows1252Encoding};
+///
+/// let tape = TextTape::from_slice(b"core=ENG core=FRA capital=1")?;
+/// let json = JsonOptions::new()
+///     .duplicate_keys(DuplicateKeyMode::ArraysWhenDuplicated)
+///     .text(&tape, Windows1252Encoding::new());
+/// assert_eq!(json, r#"{"core":["ENG","FRA"],"capital":"1"}"#);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonOptions {
+    pretty: bool,
+    duplicate_keys: DuplicateKeyMode,
+    rgb: RgbRepresentation,
+    intern_keys: bool,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JsonOptions {
+    /// Create options for compact JSON that preserves duplicate keys
+    pub fn new() -> Self {
+        JsonOptions {
+            pretty: false,
+            duplicate_keys: DuplicateKeyMode::Preserve,
+            rgb: RgbRepresentation::Header,
+            intern_keys: false,
+        }
+    }
+
+    /// Set whether the JSON is indented with two spaces
+    pub fn pretty(&mut self, pretty: bool) -> &mut Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Set how duplicate keys are written
+    pub fn duplicate_keys(&mut self, mode: DuplicateKeyMode) -> &mut Self {
+        self.duplicate_keys = mode;
+        self
+    }
+
+    /// Set how colors are written. In text, a color is an `rgb` header
+    /// followed by three integers (`rgb { 1 2 3 }`). Other headers (like
+    /// `hsv`) are always written with the header.
+    pub fn rgb(&mut self, representation: RgbRepresentation) -> &mut Self {
+        self.rgb = representation;
+        self
+    }
+
+    /// Set whether keys are interned while the JSON is written. Keys are
+    /// held onto until their object is written, and with interning, each
+    /// distinct key is allocated once instead of for every occurrence,
+    /// which saves many allocations on large saves.
+    ///
+    /// ```
+    /// use jomini::{export::JsonOptions, TextTape, Utf8Encoding};
+    ///
+    /// let tape = TextTape::from_slice(b"a={ owner=ENG } b={ owner=FRA }")?;
+    /// let json = JsonOptions::new()
+    ///     .intern_keys(true)
+    ///     .text(&tape, Utf8Encoding::new());
+    /// assert_eq!(json, r#"{"a":{"owner":"ENG"},"b":{"owner":"FRA"}}"#);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn intern_keys(&mut self, intern: bool) -> &mut Self {
+        self.intern_keys = intern;
+        self
+    }
+
+    fn interner(&self) -> Option<Interner> {
+        if self.intern_keys {
+            Some(Interner::new())
+        } else {
+            None
+        }
+    }
+
+    /// Convert a text tape into JSON, decoding scalars with the given encoding
+    pub fn text<E>(&self, tape: &TextTape, encoding: E) -> String
+    where
+        E: Encoding,
+    {
+        let tokens = tape.tokens();
+        let mut walker = TextJson {
+            tokens,
+            encoding,
+            interner: self.interner(),
+            out: JsonEmitter::new(self, tokens.len() * 4),
+        };
+        let fields = walker.fields(0, tokens.len());
+        write_object(&mut walker, fields);
+        walker.out.data
+    }
+
+    /// Convert a binary tape into JSON, resolving tokens with the given
+    /// resolver and decoding strings with the given flavor. Tokens that
+    /// can't be resolved are written as their hex id (`0x2d82`).
+    pub fn binary<RES, F>(&self, tape: &BinaryTape, resolver: &RES, flavor: F) -> String
+    where
+        RES: TokenResolver,
+        F: BinaryFlavor,
+    {
+        let tokens = tape.tokens();
+        let mut walker = BinaryJson {
+            tokens,
+            resolver,
+            flavor,
+            interner: self.interner(),
+            out: JsonEmitter::new(self, tokens.len() * 4),
+        };
+        let fields = walker.fields(0, tokens.len());
+        write_object(&mut walker, fields);
+        walker.out.data
+    }
+}
+
+/// Writes JSON punctuation, keeping track of when separators and
+/// indentation are needed
+struct JsonEmitter {
+    data: String,
+    pretty: bool,
+    mode: DuplicateKeyMode,
+    rgb: RgbRepresentation,
+    depth: usize,
+    first: bool,
+}
+
+impl JsonEmitter {
+    fn new(options: &JsonOptions, capacity: usize) -> Self {
+        JsonEmitter {
+            data: String::with_capacity(capacity),
+            pretty: options.pretty,
+            mode: options.duplicate_keys,
+            rgb: options.rgb,
+            depth: 0,
+            first: true,
+        }
+    }
+
+    fn newline(&mut self) {
+        if self.pretty {
+            self.data.push('\n');
+            for _ in 0..self.depth {
+                self.data.push_str("  ");
+            }
+        }
+    }
+
+    fn begin(&mut self, c: char) {
+        self.data.push(c);
+        self.depth += 1;
+        self.first = true;
+    }
+
+    fn end(&mut self, c: char) {
+        self.depth -= 1;
+        if !self.first {
+            self.newline();
+        }
+        self.data.push(c);
+        self.first = false;
+    }
+
+    /// Writes the separator before an array element
+    fn element(&mut self) {
+        if !self.first {
+            self.data.push(',');
+        }
+        self.newline();
+        self.first = false;
+    }
+
+    fn key(&mut self, key: &str) {
+        self.element();
+        self.str(key);
+        self.data.push(':');
+        if self.pretty {
+            self.data.push(' ');
+        }
+    }
+
+    fn str(&mut self, s: &str) {
+        self.data.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.data.push_str("\\\""),
+                '\\' => self.data.push_str("\\\\"),
+                '\n' => self.data.push_str("\\n"),
+                '\r' => self.data.push_str("\\r"),
+                '\t' => self.data.push_str("\\t"),
+                c if (c as u32) < 0x20 => {
+                    let _ = write!(self.data, "\\u{:04x}", c as u32);
+                }
+                c => self.data.push(c),
+            }
+        }
+        self.data.push('"');
+    }
+
+    fn rgb(&mut self, rgb: Rgb) {
+        match self.rgb {
+            RgbRepresentation::Header => {
+                self.begin('{');
+                self.key("rgb");
+                self.rgb_channels(rgb);
+                self.end('}');
+            }
+            RgbRepresentation::Array => self.rgb_channels(rgb),
+            RgbRepresentation::Object => {
+                self.begin('{');
+                for (key, value) in [("r", rgb.r), ("g", rgb.g), ("b", rgb.b)].iter() {
+                    self.key(key);
+                    let _ = write!(self.data, "{}", value);
+                }
+                self.end('}');
+            }
+            RgbRepresentation::Hex => self.str(&rgb.to_hex()),
+        }
+    }
+
+    fn rgb_channels(&mut self, rgb: Rgb) {
+        self.begin('[');
+        for value in [rgb.r, rgb.g, rgb.b].iter() {
+            self.element();
+            let _ = write!(self.data, "{}", value);
+        }
+        self.end(']');
+    }
+
+    fn raw(&mut self, s: &str) {
+        self.data.push_str(s);
+    }
+
+    fn f32(&mut self, x: f32) {
+        if x.is_finite() {
+            let _ = write!(self.data, "{}", x);
+        } else {
+            self.data.push_str("null");
+        }
+    }
+}
+
+/// The key of a field, which is shared with the other occurrences of the
+/// key when keys are interned
+enum JsonKey {
+    Owned(String),
+    Interned(Arc<str>),
+}
+
+impl JsonKey {
+    fn new(key: Cow<str>, interner: &mut Option<Interner>) -> Self {
+        match interner {
+            Some(interner) => JsonKey::Interned(interner.intern(&key)),
+            None => JsonKey::Owned(key.into_owned()),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            JsonKey::Owned(x) => x,
+            JsonKey::Interned(x) => x,
+        }
+    }
+}
+
+/// A field of an object where the value is the tape index of the value
+struct JsonField {
+    key: JsonKey,
+    op: Option<Operator>,
+    value: usize,
+}
+
+/// Common interface between the text and binary walkers so that objects are
+/// grouped the same way for both
+trait JsonWalker {
+    fn out(&mut self) -> &mut JsonEmitter;
+    fn write_value(&mut self, idx: usize);
+}
+
+fn write_field_value<T: JsonWalker>(walker: &mut T, field: &JsonField) {
+    match field.op {
+        Some(op) => {
+            walker.out().begin('{');
+            walker.out().key(operator_symbol(op));
+            walker.write_value(field.value);
+            walker.out().end('}');
+        }
+        None => walker.write_value(field.value),
+    }
+}
+
+fn write_object<T: JsonWalker>(walker: &mut T, fields: Vec<JsonField>) {
+    let mode = walker.out().mode;
+    if mode == DuplicateKeyMode::Typed {
+        walker.out().begin('{');
+        walker.out().key("type");
+        walker.out().str("obj");
+        walker.out().key("val");
+        walker.out().begin('[');
+        for field in &fields {
+            walker.out().element();
+            walker.out().begin('[');
+            walker.out().element();
+            walker.out().str(field.key.as_str());
+            walker.out().element();
+            write_field_value(walker, field);
+            walker.out().end(']');
+        }
+        walker.out().end(']');
+        walker.out().end('}');
+        return;
+    }
+
+    walker.out().begin('{');
+    if mode == DuplicateKeyMode::Preserve {
+        for field in &fields {
+            walker.out().key(field.key.as_str());
+            write_field_value(walker, field);
+        }
+        walker.out().end('}');
+        return;
+    }
+
+    // Group the occurrences of each key by the position of its first
+    // occurrence
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for (i, field) in fields.iter().enumerate() {
+        match positions.get(field.key.as_str()) {
+            Some(&pos) => groups[pos].push(i),
+            None => {
+                positions.insert(field.key.as_str(), groups.len());
+                groups.push(vec![i]);
+            }
+        }
+    }
+
+    for group in groups {
+        let key = fields[group[0]].key.as_str();
+        walker.out().key(key);
+        match mode {
+            DuplicateKeyMode::FirstWins => write_field_value(walker, &fields[group[0]]),
+            DuplicateKeyMode::LastWins => {
+                write_field_value(walker, &fields[group[group.len() - 1]])
+            }
+            DuplicateKeyMode::ArraysWhenDuplicated if group.len() == 1 => {
+                write_field_value(walker, &fields[group[0]])
+            }
+            _ => {
+                walker.out().begin('[');
+                for i in group {
+                    walker.out().element();
+                    write_field_value(walker, &fields[i]);
+                }
+                walker.out().end(']');
+            }
+        }
+    }
+    walker.out().end('}');
+}
+
+/// Writes the elements of an array, where a trailing hidden object is given
+/// as its fields
+fn write_array<T: JsonWalker>(
+    walker: &mut T,
+    elements: Vec<usize>,
+    hidden: Option<Vec<JsonField>>,
+) {
+    let typed = walker.out().mode == DuplicateKeyMode::Typed;
+    if typed {
+        walker.out().begin('{');
+        walker.out().key("type");
+        walker.out().str("array");
+        walker.out().key("val");
+    }
+
+    walker.out().begin('[');
+    for idx in elements {
+        walker.out().element();
+        walker.write_value(idx);
+    }
+
+    if let Some(fields) = hidden {
+        walker.out().element();
+        write_object(walker, fields);
+    }
+    walker.out().end(']');
+
+    if typed {
+        walker.out().end('}');
+    }
+}
+
+struct TextJson<'a, 'b, E> {
+    tokens: &'b [TextToken<'a>],
+    encoding: E,
+    interner: Option<Interner>,
+    out: JsonEmitter,
+}
+
+impl<'a, 'b, E> TextJson<'a, 'b, E>
+where
+    E: Encoding,
+{
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+            TextToken::Header(_) => self.next_idx(idx + 1),
+            _ => idx + 1,
+        }
+    }
+
+    fn fields(&mut self, mut idx: usize, end: usize) -> Vec<JsonField> {
+        let mut fields = Vec::new();
+        while idx < end {
+            let key = match self.tokens[idx] {
+                TextToken::Scalar(x) => {
+                    JsonKey::new(self.encoding.decode(x.view_data()), &mut self.interner)
+                }
+
+                // An object or array without a key (eg: `a={ b=c {} }`)
+                _ => {
+                    fields.push(JsonField {
+                        key: JsonKey::Owned(String::new()),
+                        op: None,
+                        value: idx,
+                    });
+                    idx = self.next_idx(idx);
+                    continue;
+                }
+            };
+
+            let (op, value_idx) = match self.tokens.get(idx + 1) {
+                Some(TextToken::Operator(op)) => (Some(*op), idx + 2),
+                _ => (None, idx + 1),
+            };
+
+            if value_idx >= end {
+                break;
+            }
+
+            fields.push(JsonField {
+                key,
+                op,
+                value: value_idx,
+            });
+            idx = self.next_idx(value_idx);
+        }
+        fields
+    }
+}
+
+impl<'a, 'b, E> TextJson<'a, 'b, E>
+where
+    E: Encoding,
+{
+    /// Reads the color from an array of three integers
+    fn rgb(&self, idx: usize) -> Option<Rgb> {
+        let end = match self.tokens[idx] {
+            TextToken::Array(end) if end == idx + 4 => end,
+            _ => return None,
+        };
+
+        let mut channels = self.tokens[idx + 1..end]
+            .iter()
+            .map(|x| x.as_scalar().and_then(|x| x.to_u64().ok()))
+            .map(|x| x.and_then(|x| u32::try_from(x).ok()));
+        Some(Rgb {
+            r: channels.next()??,
+            g: channels.next()??,
+            b: channels.next()??,
+        })
+    }
+}
+
+impl<'a, 'b, E> JsonWalker for TextJson<'a, 'b, E>
+where
+    E: Encoding,
+{
+    fn out(&mut self) -> &mut JsonEmitter {
+        &mut self.out
+    }
+
+    fn write_value(&mut self, idx: usize) {
+        match self.tokens[idx] {
+            TextToken::Scalar(x) => {
+                let s = self.encoding.decode(x.view_data());
+                self.out.str(&s);
+            }
+            TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                let fields = self.fields(idx + 1, end);
+                write_object(self, fields);
+            }
+            TextToken::Array(end) => {
+                let mut elements = Vec::new();
+                let mut hidden = None;
+                let mut i = idx + 1;
+                while i < end {
+                    if let TextToken::HiddenObject(x) = self.tokens[i] {
+                        hidden = Some(self.fields(i + 1, x));
+                    } else {
+                        elements.push(i);
+                    }
+                    i = self.next_idx(i);
+                }
+                write_array(self, elements, hidden);
+            }
+            TextToken::Header(x) => {
+                if self.out.rgb != RgbRepresentation::Header && x.view_data() == b"rgb" {
+                    if let Some(rgb) = self.rgb(idx + 1) {
+                        self.out.rgb(rgb);
+                        return;
+                    }
+                }
+
+                self.out.begin('{');
+                let s = self.encoding.decode(x.view_data());
+                self.out.key(&s);
+                self.write_value(idx + 1);
+                self.out.end('}');
+            }
+            TextToken::Operator(_) | TextToken::End(_) => self.out.raw("null"),
+        }
+    }
+}
+
+struct BinaryJson<'a, 'b, 'res, RES, F> {
+    tokens: &'b [BinaryToken<'a>],
+    resolver: &'res RES,
+    flavor: F,
+    interner: Option<Interner>,
+    out: JsonEmitter,
+}
+
+impl<'a, 'b, 'res, RES, F> BinaryJson<'a, 'b, 'res, RES, F>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => x + 1,
+            _ => idx + 1,
+        }
+    }
+
+    fn token_name(&self, token: u16) -> String {
+        self.resolver
+            .resolve(token)
+            .map(|x| x.into_owned())
+            .unwrap_or_else(|| format!("0x{:x}", token))
+    }
+
+    fn fields(&mut self, mut idx: usize, end: usize) -> Vec<JsonField> {
+        let resolver = self.resolver;
+        let mut fields = Vec::new();
+        while idx + 1 < end {
+            let key = match self.tokens[idx] {
+                BinaryToken::Token(x) => resolver
+                    .resolve(x)
+                    .unwrap_or_else(|| Cow::Owned(format!("0x{:x}", x))),
+                BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                    self.flavor.decode(x.view_data())
+                }
+                BinaryToken::U32(x) => Cow::Owned(x.to_string()),
+                BinaryToken::U64(x) => Cow::Owned(x.to_string()),
+                BinaryToken::I32(x) => Cow::Owned(x.to_string()),
+                BinaryToken::Bool(x) => Cow::Borrowed(if x { "yes" } else { "no" }),
+                BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => Cow::Owned(x.to_string()),
+                _ => Cow::Borrowed(""),
+            };
+
+            fields.push(JsonField {
+                key: JsonKey::new(key, &mut self.interner),
+                op: None,
+                value: idx + 1,
+            });
+            idx = self.next_idx(idx + 1);
+        }
+        fields
+    }
+}
+
+impl<'a, 'b, 'res, RES, F> JsonWalker for BinaryJson<'a, 'b, 'res, RES, F>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    fn out(&mut self) -> &mut JsonEmitter {
+        &mut self.out
+    }
+
+    fn write_value(&mut self, idx: usize) {
+        match self.tokens[idx] {
+            BinaryToken::Object(end) | BinaryToken::HiddenObject(end) => {
+                let fields = self.fields(idx + 1, end);
+                write_object(self, fields);
+            }
+            BinaryToken::Array(end) => {
+                let mut elements = Vec::new();
+                let mut hidden = None;
+                let mut i = idx + 1;
+                while i < end {
+                    if let BinaryToken::HiddenObject(x) = self.tokens[i] {
+                        hidden = Some(self.fields(i + 1, x));
+                    } else {
+                        elements.push(i);
+                    }
+                    i = self.next_idx(i);
+                }
+                write_array(self, elements, hidden);
+            }
+            BinaryToken::Bool(x) => self.out.raw(if x { "true" } else { "false" }),
+            BinaryToken::U32(x) => self.out.raw(&x.to_string()),
+            BinaryToken::U64(x) => self.out.raw(&x.to_string()),
+            BinaryToken::I32(x) => self.out.raw(&x.to_string()),
+            BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => self.out.f32(x),
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                let s = self.flavor.decode(x.view_data());
+                self.out.str(&s);
+            }
+            BinaryToken::Token(x) => {
+                let s = self.token_name(x);
+                self.out.str(&s);
+            }
+            BinaryToken::Rgb(x) => self.out.rgb(x),
+            BinaryToken::End(_) => self.out.raw("null"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Eu4Flavor, Utf8Encoding};
+
+    fn text_json(data: &[u8], mode: DuplicateKeyMode) -> String {
+        let tape = TextTape::from_slice(data).unwrap();
+        JsonOptions::new()
+            .duplicate_keys(mode)
+            .text(&tape, Utf8Encoding::new())
+    }
+
+    #[test]
+    fn test_duplicate_key_modes() {
+        let data = b"core=ENG capital=1 core=FRA";
+        let cases = [
+            (
+                DuplicateKeyMode::Preserve,
+                r#"{"core":"ENG","capital":"1","core":"FRA"}"#,
+            ),
+            (
+                DuplicateKeyMode::ArraysAlways,
+                r#"{"core":["ENG","FRA"],"capital":["1"]}"#,
+            ),
+            (
+                DuplicateKeyMode::ArraysWhenDuplicated,
+                r#"{"core":["ENG","FRA"],"capital":"1"}"#,
+            ),
+            (
+                DuplicateKeyMode::FirstWins,
+                r#"{"core":"ENG","capital":"1"}"#,
+            ),
+            (
+                DuplicateKeyMode::LastWins,
+                r#"{"core":"FRA","capital":"1"}"#,
+            ),
+            (
+                DuplicateKeyMode::Typed,
+                r#"{"type":"obj","val":[["core","ENG"],["capital","1"],["core","FRA"]]}"#,
+            ),
+        ];
+
+        for (mode, expected) in cases.iter() {
+            assert_eq!(text_json(data, *mode), *expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_interned_keys_match() {
+        let tape =
+            TextTape::from_slice(b"a={ owner=ENG } b={ owner=FRA owner=SWE } c=\"\xc3\xa9\"")
+                .unwrap();
+        for mode in [
+            DuplicateKeyMode::Preserve,
+            DuplicateKeyMode::ArraysWhenDuplicated,
+        ]
+        .iter()
+        {
+            let mut options = JsonOptions::new();
+            options.duplicate_keys(*mode);
+            let expected = options.text(&tape, Utf8Encoding::new());
+            let actual = options.intern_keys(true).text(&tape, Utf8Encoding::new());
+            assert_eq!(actual, expected);
+        }
+
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let json = JsonOptions::new()
+            .intern_keys(true)
+            .duplicate_keys(DuplicateKeyMode::ArraysWhenDuplicated)
+            .binary(&tape, &map, Eu4Flavor::new());
+        assert_eq!(json, r#"{"a":{"0x2d83":[1,2]}}"#);
+    }
+
+    #[test]
+    fn test_text_values() {
+        let data = b"a={ 1 b=c } d>\"e\\\"\" color=rgb { 1 2 } f={ { g=h } }";
+        assert_eq!(
+            text_json(data, DuplicateKeyMode::Preserve),
+            r#"{"a":["1",{"b":"c"}],"d":{">":"e\""},"color":{"rgb":["1","2"]},"f":[{"g":"h"}]}"#
+        );
+        assert_eq!(
+            text_json(b"a={ 1 2 }", DuplicateKeyMode::Typed),
+            r#"{"type":"obj","val":[["a",{"type":"array","val":["1","2"]}]]}"#
+        );
+    }
+
+    #[test]
+    fn test_pretty() {
+        let tape = TextTape::from_slice(b"a={ b=c d={ 1 } } e={}").unwrap();
+        let json = JsonOptions::new()
+            .pretty(true)
+            .text(&tape, Utf8Encoding::new());
+        assert_eq!(
+            json,
+            "{\n  \"a\": {\n    \"b\": \"c\",\n    \"d\": [\n      \"1\"\n    ]\n  },\n  \"e\": []\n}"
+        );
+    }
+
+    #[test]
+    fn test_rgb() {
+        let tape =
+            TextTape::from_slice(b"a=rgb { 10 255 0 } b=hsv { 1 2 3 } c=rgb { 1 2 }").unwrap();
+        let cases = [
+            (
+                RgbRepresentation::Header,
+                r#"{"a":{"rgb":["10","255","0"]},"b":{"hsv":["1","2","3"]},"c":{"rgb":["1","2"]}}"#,
+            ),
+            (
+                RgbRepresentation::Array,
+                r#"{"a":[10,255,0],"b":{"hsv":["1","2","3"]},"c":{"rgb":["1","2"]}}"#,
+            ),
+            (
+                RgbRepresentation::Object,
+                r#"{"a":{"r":10,"g":255,"b":0},"b":{"hsv":["1","2","3"]},"c":{"rgb":["1","2"]}}"#,
+            ),
+            (
+                RgbRepresentation::Hex,
+                r##"{"a":"#0aff00","b":{"hsv":["1","2","3"]},"c":{"rgb":["1","2"]}}"##,
+            ),
+        ];
+
+        for (representation, expected) in cases.iter() {
+            let json = JsonOptions::new()
+                .rgb(*representation)
+                .text(&tape, Utf8Encoding::new());
+            assert_eq!(json, *expected, "{:?}", representation);
+        }
+
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x43, 0x02, 0x03, 0x00, 0x14, 0x00, 0x6e, 0x00, 0x00, 0x00,
+            0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x04, 0x00,
+        ];
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "color");
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let json = JsonOptions::new().binary(&tape, &map, Eu4Flavor::new());
+        assert_eq!(json, r#"{"color":{"rgb":[110,27,27]}}"#);
+        let json =
+            JsonOptions::new()
+                .rgb(RgbRepresentation::Hex)
+                .binary(&tape, &map, Eu4Flavor::new());
+        assert_eq!(json, r##"{"color":"#6e1b1b"}"##);
+    }
+
+    #[test]
+    fn test_binary() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x83, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0c,
+            0x00, 0xff, 0xff, 0xff, 0xff, 0x84, 0x2d, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "ironman");
+        map.insert(0x2d83, "ids");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let json = JsonOptions::new().binary(&tape, &map, Eu4Flavor::new());
+        assert_eq!(json, r#"{"ironman":true,"ids":[-1,"0x2d84"]}"#);
+    }
+}
@@ -0,0 +1,539 @@
+//! Convert tapes directly into compact binary interchange formats
+//!
+//! Parsed saves are often cached, and going through serde into a format like
+//! JSON first is wasted work when the cache only needs a self describing
+//! binary blob. The functions in this module walk a tape and write
+//! [MessagePack](https://msgpack.org) or [CBOR](https://cbor.io) without any
+//! intermediate representation.
+//!
+//! The output mirrors what is seen when the tape is deserialized into a
+//! dynamic value:
+//!
+//! - Objects are maps with string keys. Duplicate keys are kept in order.
+//! - Arrays are arrays. The fields of a hidden object are a map at the end of
+//!   the array.
+//! - Text scalars are strings. Binary scalars keep their type (booleans,
+//!   integers, floats, and strings) and tokens are resolved to their names.
+//! - Values with a non-equal operator (`a > 1`) are wrapped in a map keyed by
+//!   the operator (`{">": "1"}`).
+//! - Values with a header (`rgb { 1 2 3 }`) are wrapped in a map keyed by the
+//!   header (`{"rgb": ["1", "2", "3"]}`). An rgb binary value is an array of
+//!   its three components.
+//!
+//! ```
+//! use jomini::{export::{self, ExportFormat}, TextTape, Windows1252Encoding};
+//!
+//! let tape = TextTape::from_slice(b"a=b")?;
+//! let out = export::text(&tape, Windows1252Encoding::new(), ExportFormat::MessagePack);
+//! assert_eq!(out, vec![0x81, 0xa1, b'a', 0xa1, b'b']);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! For analytics, a tape can instead be flattened into `(path, key, value,
+//! type)` [rows](Row), which can be written out as CSV or SQL, or inserted
+//! into a SQLite database when the `sqlite` feature is enabled.
+//!
+//! Tapes can also be converted into JSON with [JsonOptions], which controls
+//! how duplicate keys are represented.
+mod json;
+mod rows;
+
+pub use self::json::*;
+pub use self::rows::*;
+
+use crate::{
+    BinaryFlavor, BinaryTape, BinaryToken, Encoding, Operator, TextTape, TextToken, TokenResolver,
+};
+use std::borrow::Cow;
+
+/// The binary format that a tape is exported into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// MessagePack
+    MessagePack,
+
+    /// CBOR (RFC 8949)
+    Cbor,
+}
+
+/// Export a text tape, decoding scalars with the given encoding
+pub fn text<E>(tape: &TextTape, encoding: E, format: ExportFormat) -> Vec<u8>
+where
+    E: Encoding,
+{
+    let tokens = tape.tokens();
+    let mut exporter = TextExporter {
+        tokens,
+        encoding,
+        out: Encoder::new(format, tokens.len() * 8),
+    };
+    exporter.write_object(0, tokens.len());
+    exporter.out.data
+}
+
+/// Export a binary tape, resolving tokens with the given resolver and
+/// decoding strings with the given flavor. Tokens that can't be resolved are
+/// written as their hex id (`0x2d82`).
+pub fn binary<RES, F>(tape: &BinaryTape, resolver: &RES, flavor: F, format: ExportFormat) -> Vec<u8>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    let tokens = tape.tokens();
+    let mut exporter = BinaryExporter {
+        tokens,
+        resolver,
+        flavor,
+        out: Encoder::new(format, tokens.len() * 8),
+    };
+    exporter.write_object(0, tokens.len());
+    exporter.out.data
+}
+
+/// Writes the primitives of the export format
+struct Encoder {
+    format: ExportFormat,
+    data: Vec<u8>,
+}
+
+impl Encoder {
+    fn new(format: ExportFormat, capacity: usize) -> Self {
+        Encoder {
+            format,
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Writes a CBOR head: the major type and its argument
+    fn cbor_head(&mut self, major: u8, n: u64) {
+        let major = major << 5;
+        if n < 24 {
+            self.data.push(major | n as u8);
+        } else if n <= u64::from(u8::MAX) {
+            self.data.push(major | 24);
+            self.data.push(n as u8);
+        } else if n <= u64::from(u16::MAX) {
+            self.data.push(major | 25);
+            self.data.extend_from_slice(&(n as u16).to_be_bytes());
+        } else if n <= u64::from(u32::MAX) {
+            self.data.push(major | 26);
+            self.data.extend_from_slice(&(n as u32).to_be_bytes());
+        } else {
+            self.data.push(major | 27);
+            self.data.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+
+    /// Writes a MessagePack length prefix, which has a fix variant that
+    /// stores the length in the marker when the length is less than `fix_max`
+    fn msgpack_len(&mut self, len: usize, fix: u8, fix_max: usize, markers: [u8; 3]) {
+        if len < fix_max {
+            self.data.push(fix | len as u8);
+        } else if len <= usize::from(u8::MAX) && markers[0] != 0 {
+            self.data.push(markers[0]);
+            self.data.push(len as u8);
+        } else if len <= usize::from(u16::MAX) {
+            self.data.push(markers[1]);
+            self.data.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            self.data.push(markers[2]);
+            self.data.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+
+    fn map(&mut self, len: usize) {
+        match self.format {
+            ExportFormat::MessagePack => self.msgpack_len(len, 0x80, 16, [0, 0xde, 0xdf]),
+            ExportFormat::Cbor => self.cbor_head(5, len as u64),
+        }
+    }
+
+    fn array(&mut self, len: usize) {
+        match self.format {
+            ExportFormat::MessagePack => self.msgpack_len(len, 0x90, 16, [0, 0xdc, 0xdd]),
+            ExportFormat::Cbor => self.cbor_head(4, len as u64),
+        }
+    }
+
+    fn str(&mut self, s: &str) {
+        match self.format {
+            ExportFormat::MessagePack => self.msgpack_len(s.len(), 0xa0, 32, [0xd9, 0xda, 0xdb]),
+            ExportFormat::Cbor => self.cbor_head(3, s.len() as u64),
+        }
+        self.data.extend_from_slice(s.as_bytes());
+    }
+
+    fn bool(&mut self, x: bool) {
+        self.data.push(match (self.format, x) {
+            (ExportFormat::MessagePack, false) => 0xc2,
+            (ExportFormat::MessagePack, true) => 0xc3,
+            (ExportFormat::Cbor, false) => 0xf4,
+            (ExportFormat::Cbor, true) => 0xf5,
+        });
+    }
+
+    fn u64(&mut self, x: u64) {
+        match self.format {
+            ExportFormat::Cbor => self.cbor_head(0, x),
+            ExportFormat::MessagePack if x < 128 => self.data.push(x as u8),
+            ExportFormat::MessagePack if x <= u64::from(u8::MAX) => {
+                self.data.push(0xcc);
+                self.data.push(x as u8);
+            }
+            ExportFormat::MessagePack if x <= u64::from(u16::MAX) => {
+                self.data.push(0xcd);
+                self.data.extend_from_slice(&(x as u16).to_be_bytes());
+            }
+            ExportFormat::MessagePack if x <= u64::from(u32::MAX) => {
+                self.data.push(0xce);
+                self.data.extend_from_slice(&(x as u32).to_be_bytes());
+            }
+            ExportFormat::MessagePack => {
+                self.data.push(0xcf);
+                self.data.extend_from_slice(&x.to_be_bytes());
+            }
+        }
+    }
+
+    fn i64(&mut self, x: i64) {
+        if x >= 0 {
+            return self.u64(x as u64);
+        }
+
+        match self.format {
+            ExportFormat::Cbor => self.cbor_head(1, (-1 - x) as u64),
+            ExportFormat::MessagePack if x >= -32 => self.data.push(x as u8),
+            ExportFormat::MessagePack if x >= i64::from(i8::MIN) => {
+                self.data.push(0xd0);
+                self.data.push(x as u8);
+            }
+            ExportFormat::MessagePack if x >= i64::from(i16::MIN) => {
+                self.data.push(0xd1);
+                self.data.extend_from_slice(&(x as i16).to_be_bytes());
+            }
+            ExportFormat::MessagePack if x >= i64::from(i32::MIN) => {
+                self.data.push(0xd2);
+                self.data.extend_from_slice(&(x as i32).to_be_bytes());
+            }
+            ExportFormat::MessagePack => {
+                self.data.push(0xd3);
+                self.data.extend_from_slice(&x.to_be_bytes());
+            }
+        }
+    }
+
+    fn f32(&mut self, x: f32) {
+        self.data.push(match self.format {
+            ExportFormat::MessagePack => 0xca,
+            ExportFormat::Cbor => 0xfa,
+        });
+        self.data.extend_from_slice(&x.to_be_bytes());
+    }
+}
+
+fn operator_symbol(op: Operator) -> &'static str {
+    match op {
+        Operator::LessThan => "<",
+        Operator::LessThanEqual => "<=",
+        Operator::GreaterThan => ">",
+        Operator::GreaterThanEqual => ">=",
+    }
+}
+
+struct TextExporter<'a, 'b, E> {
+    tokens: &'b [TextToken<'a>],
+    encoding: E,
+    out: Encoder,
+}
+
+impl<'a, 'b, E> TextExporter<'a, 'b, E>
+where
+    E: Encoding,
+{
+    /// Returns the (key index, operator, value index) of each field in the
+    /// object
+    fn fields(&self, mut idx: usize, end: usize) -> Vec<(usize, Option<Operator>, usize)> {
+        let mut fields = Vec::new();
+        while idx < end {
+            let (op, value_idx) = match self.tokens.get(idx + 1) {
+                Some(TextToken::Operator(op)) => (Some(*op), idx + 2),
+                _ => (None, idx + 1),
+            };
+
+            // A key without a value (eg: the trailer of `a={ b=c {} }`)
+            // is given an empty value
+            if value_idx >= end || !matches!(self.tokens[idx], TextToken::Scalar(_)) {
+                fields.push((idx, None, end));
+                idx = self.next_idx(idx);
+                continue;
+            }
+
+            fields.push((idx, op, value_idx));
+            idx = self.next_idx(value_idx);
+        }
+        fields
+    }
+
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+            TextToken::Header(_) => self.next_idx(idx + 1),
+            _ => idx + 1,
+        }
+    }
+
+    fn write_object(&mut self, start: usize, end: usize) {
+        let fields = self.fields(start, end);
+        self.out.map(fields.len());
+        for (key_idx, op, value_idx) in fields {
+            match self.tokens[key_idx] {
+                TextToken::Scalar(key) if value_idx < end => {
+                    let key = self.encoding.decode(key.view_data());
+                    self.out.str(&key);
+                    if let Some(op) = op {
+                        self.out.map(1);
+                        self.out.str(operator_symbol(op));
+                    }
+                    self.write_value(value_idx);
+                }
+                TextToken::Scalar(key) => {
+                    let key = self.encoding.decode(key.view_data());
+                    self.out.str(&key);
+                    self.out.str("");
+                }
+                _ => {
+                    self.out.str("");
+                    self.write_value(key_idx);
+                }
+            }
+        }
+    }
+
+    fn write_array(&mut self, start: usize, end: usize) {
+        let mut len = 0;
+        let mut idx = start;
+        while idx < end {
+            len += 1;
+            idx = self.next_idx(idx);
+        }
+
+        self.out.array(len);
+        let mut idx = start;
+        while idx < end {
+            self.write_value(idx);
+            idx = self.next_idx(idx);
+        }
+    }
+
+    fn write_value(&mut self, idx: usize) {
+        match self.tokens[idx] {
+            TextToken::Scalar(x) => {
+                let s = self.encoding.decode(x.view_data());
+                self.out.str(&s);
+            }
+            TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                self.write_object(idx + 1, end)
+            }
+            TextToken::Array(end) => self.write_array(idx + 1, end),
+            TextToken::Header(x) => {
+                self.out.map(1);
+                let s = self.encoding.decode(x.view_data());
+                self.out.str(&s);
+                self.write_value(idx + 1);
+            }
+            TextToken::Operator(_) | TextToken::End(_) => self.out.str(""),
+        }
+    }
+}
+
+struct BinaryExporter<'a, 'b, 'res, RES, F> {
+    tokens: &'b [BinaryToken<'a>],
+    resolver: &'res RES,
+    flavor: F,
+    out: Encoder,
+}
+
+impl<'a, 'b, 'res, RES, F> BinaryExporter<'a, 'b, 'res, RES, F>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => x + 1,
+            _ => idx + 1,
+        }
+    }
+
+    fn write_object(&mut self, start: usize, end: usize) {
+        let mut len = 0;
+        let mut idx = start;
+        while idx + 1 < end {
+            len += 1;
+            idx = self.next_idx(idx + 1);
+        }
+
+        self.out.map(len);
+        let mut idx = start;
+        while idx + 1 < end {
+            self.write_key(idx);
+            self.write_value(idx + 1);
+            idx = self.next_idx(idx + 1);
+        }
+    }
+
+    fn write_array(&mut self, start: usize, end: usize) {
+        let mut len = 0;
+        let mut idx = start;
+        while idx < end {
+            len += 1;
+            idx = self.next_idx(idx);
+        }
+
+        self.out.array(len);
+        let mut idx = start;
+        while idx < end {
+            self.write_value(idx);
+            idx = self.next_idx(idx);
+        }
+    }
+
+    fn token_name(&self, token: u16) -> Cow<'res, str> {
+        self.resolver
+            .resolve(token)
+            .unwrap_or_else(|| Cow::Owned(format!("0x{:x}", token)))
+    }
+
+    fn write_key(&mut self, idx: usize) {
+        let key = match self.tokens[idx] {
+            BinaryToken::Token(x) => self.token_name(x),
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => self.flavor.decode(x.view_data()),
+            BinaryToken::U32(x) => Cow::Owned(x.to_string()),
+            BinaryToken::U64(x) => Cow::Owned(x.to_string()),
+            BinaryToken::I32(x) => Cow::Owned(x.to_string()),
+            BinaryToken::Bool(x) => Cow::Borrowed(if x { "yes" } else { "no" }),
+            BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => Cow::Owned(x.to_string()),
+            _ => Cow::Borrowed(""),
+        };
+        self.out.str(&key);
+    }
+
+    fn write_value(&mut self, idx: usize) {
+        match self.tokens[idx] {
+            BinaryToken::Object(end) | BinaryToken::HiddenObject(end) => {
+                self.write_object(idx + 1, end)
+            }
+            BinaryToken::Array(end) => self.write_array(idx + 1, end),
+            BinaryToken::Bool(x) => self.out.bool(x),
+            BinaryToken::U32(x) => self.out.u64(u64::from(x)),
+            BinaryToken::U64(x) => self.out.u64(x),
+            BinaryToken::I32(x) => self.out.i64(i64::from(x)),
+            BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => self.out.f32(x),
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                let s = self.flavor.decode(x.view_data());
+                self.out.str(&s);
+            }
+            BinaryToken::Token(x) => {
+                let s = self.token_name(x);
+                self.out.str(&s);
+            }
+            BinaryToken::Rgb(x) => {
+                self.out.array(3);
+                self.out.u64(u64::from(x.r));
+                self.out.u64(u64::from(x.g));
+                self.out.u64(u64::from(x.b));
+            }
+            BinaryToken::End(_) => self.out.str(""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Eu4Flavor, Utf8Encoding};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_encoder_integers() {
+        let cases: &[(i64, &[u8], &[u8])] = &[
+            (0, &[0x00], &[0x00]),
+            (127, &[0x7f], &[0x18, 0x7f]),
+            (255, &[0xcc, 0xff], &[0x18, 0xff]),
+            (1000, &[0xcd, 0x03, 0xe8], &[0x19, 0x03, 0xe8]),
+            (-1, &[0xff], &[0x20]),
+            (-33, &[0xd0, 0xdf], &[0x38, 0x20]),
+            (-1000, &[0xd1, 0xfc, 0x18], &[0x39, 0x03, 0xe7]),
+            (
+                i64::from(i32::MIN),
+                &[0xd2, 0x80, 0x00, 0x00, 0x00],
+                &[0x3a, 0x7f, 0xff, 0xff, 0xff],
+            ),
+        ];
+
+        for &(x, msgpack, cbor) in cases {
+            let mut encoder = Encoder::new(ExportFormat::MessagePack, 0);
+            encoder.i64(x);
+            assert_eq!(encoder.data, msgpack, "msgpack {}", x);
+
+            let mut encoder = Encoder::new(ExportFormat::Cbor, 0);
+            encoder.i64(x);
+            assert_eq!(encoder.data, cbor, "cbor {}", x);
+        }
+    }
+
+    #[test]
+    fn test_encoder_lengths() {
+        let s = "a".repeat(40);
+        let mut encoder = Encoder::new(ExportFormat::MessagePack, 0);
+        encoder.str(&s);
+        encoder.map(20);
+        encoder.array(3);
+        assert_eq!(&encoder.data[..2], &[0xd9, 40]);
+        assert_eq!(&encoder.data[42..], &[0xde, 0x00, 20, 0x93]);
+
+        let mut encoder = Encoder::new(ExportFormat::Cbor, 0);
+        encoder.str(&s);
+        encoder.map(20);
+        encoder.array(3);
+        assert_eq!(&encoder.data[..2], &[0x78, 40]);
+        assert_eq!(&encoder.data[42..], &[0xb4, 0x83]);
+    }
+
+    #[test]
+    fn test_export_text() {
+        let tape = TextTape::from_slice(b"a={ 1 b=c } d>e c=rgb { 1 }").unwrap();
+        let out = text(&tape, Utf8Encoding::new(), ExportFormat::Cbor);
+        assert_eq!(
+            out,
+            vec![
+                0xa3, // map(3)
+                0x61, b'a', 0x82, 0x61, b'1', 0xa1, 0x61, b'b', 0x61, b'c', // a=[1, {b: c}]
+                0x61, b'd', 0xa1, 0x61, b'>', 0x61, b'e', // d={">": e}
+                0x61, b'c', 0xa1, 0x63, b'r', b'g', b'b', 0x81, 0x61, b'1', // c={rgb: [1]}
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_binary() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x83, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0c,
+            0x00, 0xff, 0xff, 0xff, 0xff, 0x84, 0x2d, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "ironman");
+        map.insert(0x2d83, "ids");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let out = binary(&tape, &map, Eu4Flavor::new(), ExportFormat::MessagePack);
+        let mut expected = vec![0x82, 0xa7];
+        expected.extend_from_slice(b"ironman");
+        expected.extend_from_slice(&[0xc3, 0xa3]);
+        expected.extend_from_slice(b"ids");
+        expected.extend_from_slice(&[0x92, 0xff, 0xa6]);
+        expected.extend_from_slice(b"0x2d84");
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,608 @@
+use crate::{
+    BinaryFlavor, BinaryTape, BinaryToken, Encoding, Error, TextTape, TextToken, TokenResolver,
+};
+use std::io::Write;
+
+/// The kind of value that a [Row] holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowType {
+    /// An object. Its fields are rows whose path ends with this row's key
+    Object,
+
+    /// An array. Its elements are rows whose path ends with this row's key
+    /// and whose keys are the element indices
+    Array,
+
+    /// A text scalar
+    Scalar,
+
+    /// A binary boolean
+    Bool,
+
+    /// A binary unsigned 32bit integer
+    U32,
+
+    /// A binary unsigned 64bit integer
+    U64,
+
+    /// A binary signed 32bit integer
+    I32,
+
+    /// A binary rational number
+    F32,
+
+    /// A binary string
+    String,
+
+    /// A binary token resolved to its name
+    Token,
+
+    /// A binary rgb value
+    Rgb,
+}
+
+impl RowType {
+    /// The name of the type as written to the `type` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RowType::Object => "object",
+            RowType::Array => "array",
+            RowType::Scalar => "scalar",
+            RowType::Bool => "bool",
+            RowType::U32 => "u32",
+            RowType::U64 => "u64",
+            RowType::I32 => "i32",
+            RowType::F32 => "f32",
+            RowType::String => "string",
+            RowType::Token => "token",
+            RowType::Rgb => "rgb",
+        }
+    }
+}
+
+/// A flattened value of a tape
+///
+/// The path is the keys of the enclosing objects and arrays joined with a
+/// `/` (dates are common keys and contain periods). Top level rows have an
+/// empty path. Objects and arrays have no value, except for arrays with a
+/// header (`color = rgb { 1 2 3 }`) which have the header as the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// The keys leading up to the row
+    pub path: String,
+
+    /// The key of the row, or the index of the row in an array
+    pub key: String,
+
+    /// The value of the row
+    pub value: Option<String>,
+
+    /// The kind of value
+    pub kind: RowType,
+}
+
+/// Flatten a text tape into rows, decoding scalars with the given encoding.
+///
+/// Non-equal operators are not recorded, so `a > 1` is the same row as `a = 1`.
+///
+/// ```
+/// use jomini::{export::{self, Row, RowType}, TextTape, Windows1252Encoding};
+///
+/// let tape = TextTape::from_slice(b"player=ENG\nstats={ gold=10 }")?;
+/// let rows = export::text_rows(&tape, Windows1252Encoding::new());
+/// assert_eq!(rows.len(), 3);
+/// assert_eq!(
+///     rows[2],
+///     Row {
+///         path: String::from("stats"),
+///         key: String::from("gold"),
+///         value: Some(String::from("10")),
+///         kind: RowType::Scalar,
+///     }
+/// );
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn text_rows<E>(tape: &TextTape, encoding: E) -> Vec<Row>
+where
+    E: Encoding,
+{
+    let tokens = tape.tokens();
+    let mut walker = TextRows {
+        tokens,
+        encoding,
+        path: String::new(),
+        rows: Vec::with_capacity(tokens.len() / 2),
+    };
+    walker.object(0, tokens.len());
+    walker.rows
+}
+
+/// Flatten a binary tape into rows, resolving tokens with the given resolver
+/// and decoding strings with the given flavor. Tokens that can't be resolved
+/// are written as their hex id (`0x2d82`).
+pub fn binary_rows<RES, F>(tape: &BinaryTape, resolver: &RES, flavor: F) -> Vec<Row>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    let tokens = tape.tokens();
+    let mut walker = BinaryRows {
+        tokens,
+        resolver,
+        flavor,
+        path: String::new(),
+        rows: Vec::with_capacity(tokens.len() / 2),
+    };
+    walker.object(0, tokens.len());
+    walker.rows
+}
+
+/// Write the rows as CSV with a `path,key,value,type` header. Fields are
+/// quoted when they contain a comma, quote, or line break.
+pub fn write_csv<W>(rows: &[Row], mut writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    writer.write_all(b"path,key,value,type\n")?;
+    for row in rows {
+        write_csv_field(&mut writer, &row.path)?;
+        writer.write_all(b",")?;
+        write_csv_field(&mut writer, &row.key)?;
+        writer.write_all(b",")?;
+        write_csv_field(&mut writer, row.value.as_deref().unwrap_or(""))?;
+        writer.write_all(b",")?;
+        writer.write_all(row.kind.as_str().as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+/// Write the rows as a SQL script that creates the table (if it does not
+/// exist) and inserts the rows inside a single transaction
+///
+/// ```
+/// use jomini::{export, TextTape, Windows1252Encoding};
+///
+/// let tape = TextTape::from_slice(b"player=\"O'Neill\"")?;
+/// let rows = export::text_rows(&tape, Windows1252Encoding::new());
+/// let mut out = Vec::new();
+/// export::write_sql(&rows, "save", &mut out)?;
+/// let sql = String::from_utf8(out)?;
+/// assert!(sql.contains("INSERT INTO \"save\" VALUES ('', 'player', 'O''Neill', 'scalar');"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn write_sql<W>(rows: &[Row], table: &str, mut writer: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let table = quote_identifier(table);
+    writeln!(writer, "{}", create_table_sql(&table))?;
+    writer.write_all(b"BEGIN;\n")?;
+    for row in rows {
+        write!(writer, "INSERT INTO {} VALUES (", table)?;
+        write_sql_string(&mut writer, &row.path)?;
+        writer.write_all(b", ")?;
+        write_sql_string(&mut writer, &row.key)?;
+        writer.write_all(b", ")?;
+        match &row.value {
+            Some(value) => write_sql_string(&mut writer, value)?,
+            None => writer.write_all(b"NULL")?,
+        }
+        writeln!(writer, ", '{}');", row.kind.as_str())?;
+    }
+    writer.write_all(b"COMMIT;\n")?;
+    Ok(())
+}
+
+/// Create the table (if it does not exist) and bulk insert the rows into it
+/// with a single transaction on the given connection
+#[cfg(feature = "sqlite")]
+pub fn insert_sqlite(conn: &rusqlite::Connection, table: &str, rows: &[Row]) -> Result<(), Error> {
+    let table = quote_identifier(table);
+    let tx = conn.unchecked_transaction().map_err(sqlite_error)?;
+    tx.execute_batch(&create_table_sql(&table))
+        .map_err(sqlite_error)?;
+
+    {
+        let sql = format!(
+            "INSERT INTO {} (path, key, value, type) VALUES (?1, ?2, ?3, ?4)",
+            table
+        );
+        let mut stmt = tx.prepare(&sql).map_err(sqlite_error)?;
+        for row in rows {
+            stmt.execute(rusqlite::params![
+                row.path,
+                row.key,
+                row.value,
+                row.kind.as_str()
+            ])
+            .map_err(sqlite_error)?;
+        }
+    }
+
+    tx.commit().map_err(sqlite_error)
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_error(err: rusqlite::Error) -> Error {
+    Error::from(std::io::Error::other(err))
+}
+
+fn create_table_sql(table: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (path TEXT NOT NULL, key TEXT NOT NULL, value TEXT, type TEXT NOT NULL);",
+        table
+    )
+}
+
+fn quote_identifier(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+fn write_sql_string<W: Write>(writer: &mut W, s: &str) -> Result<(), Error> {
+    writer.write_all(b"'")?;
+    for (i, part) in s.split('\'').enumerate() {
+        if i != 0 {
+            writer.write_all(b"''")?;
+        }
+        writer.write_all(part.as_bytes())?;
+    }
+    writer.write_all(b"'")?;
+    Ok(())
+}
+
+fn write_csv_field<W: Write>(writer: &mut W, s: &str) -> Result<(), Error> {
+    if !s.contains([',', '"', '\n', '\r']) {
+        writer.write_all(s.as_bytes())?;
+        return Ok(());
+    }
+
+    writer.write_all(b"\"")?;
+    for (i, part) in s.split('"').enumerate() {
+        if i != 0 {
+            writer.write_all(b"\"\"")?;
+        }
+        writer.write_all(part.as_bytes())?;
+    }
+    writer.write_all(b"\"")?;
+    Ok(())
+}
+
+/// Appends the key to the path and returns the length of the path beforehand
+/// so that the key can be popped with a truncate
+fn push_path(path: &mut String, key: &str) -> usize {
+    let len = path.len();
+    if len != 0 {
+        path.push('/');
+    }
+    path.push_str(key);
+    len
+}
+
+struct TextRows<'a, 'b, E> {
+    tokens: &'b [TextToken<'a>],
+    encoding: E,
+    path: String,
+    rows: Vec<Row>,
+}
+
+impl<'a, 'b, E> TextRows<'a, 'b, E>
+where
+    E: Encoding,
+{
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+            TextToken::Header(_) => self.next_idx(idx + 1),
+            _ => idx + 1,
+        }
+    }
+
+    fn push(&mut self, key: String, value: Option<String>, kind: RowType) {
+        self.rows.push(Row {
+            path: self.path.clone(),
+            key,
+            value,
+            kind,
+        });
+    }
+
+    fn object(&mut self, mut idx: usize, end: usize) {
+        while idx < end {
+            let key = match self.tokens[idx] {
+                TextToken::Scalar(x) => self.encoding.decode(x.view_data()).into_owned(),
+
+                // An object or array without a key (eg: `a={ b=c {} }`)
+                _ => {
+                    self.value(String::new(), idx);
+                    idx = self.next_idx(idx);
+                    continue;
+                }
+            };
+
+            let mut value_idx = idx + 1;
+            if let Some(TextToken::Operator(_)) = self.tokens.get(value_idx) {
+                value_idx += 1;
+            }
+
+            if value_idx >= end {
+                self.push(key, Some(String::new()), RowType::Scalar);
+                break;
+            }
+
+            self.value(key, value_idx);
+            idx = self.next_idx(value_idx);
+        }
+    }
+
+    fn array(&mut self, mut idx: usize, end: usize) {
+        let mut i = 0;
+        while idx < end {
+            match self.tokens[idx] {
+                TextToken::HiddenObject(x) => self.object(idx + 1, x),
+                _ => {
+                    self.value(i.to_string(), idx);
+                    i += 1;
+                }
+            }
+            idx = self.next_idx(idx);
+        }
+    }
+
+    fn value(&mut self, key: String, idx: usize) {
+        match self.tokens[idx] {
+            TextToken::Scalar(x) => {
+                let value = self.encoding.decode(x.view_data()).into_owned();
+                self.push(key, Some(value), RowType::Scalar);
+            }
+            TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                self.push(key.clone(), None, RowType::Object);
+                let len = push_path(&mut self.path, &key);
+                self.object(idx + 1, end);
+                self.path.truncate(len);
+            }
+            TextToken::Array(end) => {
+                self.push(key.clone(), None, RowType::Array);
+                let len = push_path(&mut self.path, &key);
+                self.array(idx + 1, end);
+                self.path.truncate(len);
+            }
+            TextToken::Header(x) => {
+                let header = self.encoding.decode(x.view_data()).into_owned();
+                let (kind, end) = match self.tokens[idx + 1] {
+                    TextToken::Object(end) => (RowType::Object, end),
+                    TextToken::Array(end) => (RowType::Array, end),
+                    _ => (RowType::Array, idx + 2),
+                };
+                self.push(key.clone(), Some(header), kind);
+                let len = push_path(&mut self.path, &key);
+                match kind {
+                    RowType::Object => self.object(idx + 2, end),
+                    _ => self.array(idx + 2, end),
+                }
+                self.path.truncate(len);
+            }
+            TextToken::Operator(_) | TextToken::End(_) => {
+                self.push(key, Some(String::new()), RowType::Scalar)
+            }
+        }
+    }
+}
+
+struct BinaryRows<'a, 'b, 'res, RES, F> {
+    tokens: &'b [BinaryToken<'a>],
+    resolver: &'res RES,
+    flavor: F,
+    path: String,
+    rows: Vec<Row>,
+}
+
+impl<'a, 'b, 'res, RES, F> BinaryRows<'a, 'b, 'res, RES, F>
+where
+    RES: TokenResolver,
+    F: BinaryFlavor,
+{
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => x + 1,
+            _ => idx + 1,
+        }
+    }
+
+    fn token_name(&self, token: u16) -> String {
+        self.resolver
+            .resolve(token)
+            .map(|x| x.into_owned())
+            .unwrap_or_else(|| format!("0x{:x}", token))
+    }
+
+    fn push(&mut self, key: String, value: Option<String>, kind: RowType) {
+        self.rows.push(Row {
+            path: self.path.clone(),
+            key,
+            value,
+            kind,
+        });
+    }
+
+    fn object(&mut self, mut idx: usize, end: usize) {
+        while idx + 1 < end {
+            let key = match self.tokens[idx] {
+                BinaryToken::Token(x) => self.token_name(x),
+                BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                    self.flavor.decode(x.view_data()).into_owned()
+                }
+                BinaryToken::U32(x) => x.to_string(),
+                BinaryToken::U64(x) => x.to_string(),
+                BinaryToken::I32(x) => x.to_string(),
+                BinaryToken::Bool(x) => String::from(if x { "yes" } else { "no" }),
+                BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => x.to_string(),
+                _ => String::new(),
+            };
+
+            self.value(key, idx + 1);
+            idx = self.next_idx(idx + 1);
+        }
+    }
+
+    fn array(&mut self, mut idx: usize, end: usize) {
+        let mut i = 0;
+        while idx < end {
+            match self.tokens[idx] {
+                BinaryToken::HiddenObject(x) => self.object(idx + 1, x),
+                _ => {
+                    self.value(i.to_string(), idx);
+                    i += 1;
+                }
+            }
+            idx = self.next_idx(idx);
+        }
+    }
+
+    fn value(&mut self, key: String, idx: usize) {
+        let (value, kind) = match self.tokens[idx] {
+            BinaryToken::Object(end) | BinaryToken::HiddenObject(end) => {
+                self.push(key.clone(), None, RowType::Object);
+                let len = push_path(&mut self.path, &key);
+                self.object(idx + 1, end);
+                self.path.truncate(len);
+                return;
+            }
+            BinaryToken::Array(end) => {
+                self.push(key.clone(), None, RowType::Array);
+                let len = push_path(&mut self.path, &key);
+                self.array(idx + 1, end);
+                self.path.truncate(len);
+                return;
+            }
+            BinaryToken::Bool(x) => (String::from(if x { "yes" } else { "no" }), RowType::Bool),
+            BinaryToken::U32(x) => (x.to_string(), RowType::U32),
+            BinaryToken::U64(x) => (x.to_string(), RowType::U64),
+            BinaryToken::I32(x) => (x.to_string(), RowType::I32),
+            BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => (x.to_string(), RowType::F32),
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => (
+                self.flavor.decode(x.view_data()).into_owned(),
+                RowType::String,
+            ),
+            BinaryToken::Token(x) => (self.token_name(x), RowType::Token),
+            BinaryToken::Rgb(x) => (format!("rgb {{ {} {} {} }}", x.r, x.g, x.b), RowType::Rgb),
+            BinaryToken::End(_) => (String::new(), RowType::String),
+        };
+
+        self.push(key, Some(value), kind);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Eu4Flavor, Utf8Encoding};
+    use std::collections::HashMap;
+
+    fn row(path: &str, key: &str, value: Option<&str>, kind: RowType) -> Row {
+        Row {
+            path: String::from(path),
+            key: String::from(key),
+            value: value.map(String::from),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_text_rows() {
+        let data = b"a={ 10 b=c } 1444.11.11={ d=e } f>g color=rgb { 1 2 } e={ {f=g} }";
+        let tape = TextTape::from_slice(data).unwrap();
+        let rows = text_rows(&tape, Utf8Encoding::new());
+        assert_eq!(
+            rows,
+            vec![
+                row("", "a", None, RowType::Array),
+                row("a", "0", Some("10"), RowType::Scalar),
+                row("a", "b", Some("c"), RowType::Scalar),
+                row("", "1444.11.11", None, RowType::Object),
+                row("1444.11.11", "d", Some("e"), RowType::Scalar),
+                row("", "f", Some("g"), RowType::Scalar),
+                row("", "color", Some("rgb"), RowType::Array),
+                row("color", "0", Some("1"), RowType::Scalar),
+                row("color", "1", Some("2"), RowType::Scalar),
+                row("", "e", None, RowType::Array),
+                row("e", "0", None, RowType::Object),
+                row("e/0", "f", Some("g"), RowType::Scalar),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_binary_rows() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x83, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0c,
+            0x00, 0xff, 0xff, 0xff, 0xff, 0x84, 0x2d, 0x04, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "ironman");
+        map.insert(0x2d83, "ids");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let rows = binary_rows(&tape, &map, Eu4Flavor::new());
+        assert_eq!(
+            rows,
+            vec![
+                row("", "ironman", Some("yes"), RowType::Bool),
+                row("", "ids", None, RowType::Array),
+                row("ids", "0", Some("-1"), RowType::I32),
+                row("ids", "1", Some("0x2d84"), RowType::Token),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let rows = vec![
+            row("", "a", None, RowType::Object),
+            row("a", "b", Some("say \"hi\", ok"), RowType::Scalar),
+        ];
+        let mut out = Vec::new();
+        write_csv(&rows, &mut out).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&out).unwrap(),
+            "path,key,value,type\n,a,,object\na,b,\"say \"\"hi\"\", ok\",scalar\n"
+        );
+    }
+
+    #[test]
+    fn test_write_sql() {
+        let rows = vec![row("", "a", None, RowType::Object)];
+        let mut out = Vec::new();
+        write_sql(&rows, "my\"table", &mut out).unwrap();
+        let sql = std::str::from_utf8(&out).unwrap();
+        assert!(sql.starts_with("CREATE TABLE IF NOT EXISTS \"my\"\"table\" ("));
+        assert!(sql.ends_with(
+            "BEGIN;\nINSERT INTO \"my\"\"table\" VALUES ('', 'a', NULL, 'object');\nCOMMIT;\n"
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_insert_sqlite() {
+        let tape = TextTape::from_slice(b"a={ b=c d=e } f=g").unwrap();
+        let rows = text_rows(&tape, Utf8Encoding::new());
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        insert_sqlite(&conn, "save", &rows).unwrap();
+
+        let value: String = conn
+            .query_row(
+                "SELECT value FROM save WHERE path = 'a' AND key = 'd'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(value, "e");
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM save", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 4);
+    }
+}
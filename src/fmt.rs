@@ -0,0 +1,302 @@
+//! Normalize the style of text data.
+//!
+//! The formatter works on the lexemes of the data rather than the tape, so
+//! comments are kept and the data doesn't need to parse successfully to be
+//! formatted, which makes it suitable for checking mods in CI and for
+//! formatting documents in an editor while they are being written.
+//!
+//! ```
+//! use jomini::fmt::{format, Options};
+//!
+//! let data = b"a={b=c   # comment\n\n\n\nd={1 2 3}}";
+//! let out = format(&data[..], Options::default());
+//! assert_eq!(
+//!     out,
+//!     b"a = {\n\tb = c # comment\n\n\td = { 1 2 3 }\n}\n".to_vec()
+//! );
+//! ```
+
+use crate::{lex, LexemeKind, Newline};
+
+/// Style options for the formatter
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Written for each level of depth. Defaults to a tab.
+    pub indent: String,
+
+    /// The line ending. Defaults to a line feed.
+    pub newline: Newline,
+
+    /// Write a space on either side of operators (`a = b` instead of `a=b`).
+    /// Defaults to true.
+    pub operator_spacing: bool,
+
+    /// The number of consecutive blank lines that are kept between fields.
+    /// Defaults to one.
+    pub max_blank_lines: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            indent: String::from("\t"),
+            newline: Newline::Lf,
+            operator_spacing: true,
+            max_blank_lines: 1,
+        }
+    }
+}
+
+/// A lexeme that isn't whitespace
+struct Item<'a> {
+    kind: LexemeKind,
+    data: &'a [u8],
+
+    /// The number of line feeds between this and the previous item
+    newlines: usize,
+
+    /// For open braces, whether the block only contains scalars and can be
+    /// written on a single line
+    inline: bool,
+}
+
+/// Format text data.
+///
+/// Each field is written on its own line and indented according to its
+/// depth. Blocks that only contain scalars (eg: `{ 1 2 3 }`) are written on a
+/// single line. Comments and blank lines between fields are preserved, and the
+/// output ends with a newline.
+///
+/// Formatting never fails. Malformed data, like unbalanced braces, is
+/// formatted as well as it can be and left for the parser to report.
+pub fn format(data: &[u8], options: Options) -> Vec<u8> {
+    let items = items(data);
+    let mut out = Formatter {
+        out: Vec::with_capacity(data.len()),
+        options,
+        blocks: Vec::new(),
+        line_start: true,
+        block_start: true,
+    };
+
+    let mut after_operator = false;
+    let mut prev: Option<&Item> = None;
+    for item in &items {
+        match item.kind {
+            LexemeKind::Whitespace => {}
+            LexemeKind::Comment => {
+                if out.line_start || item.newlines > 0 {
+                    out.begin_line(item.newlines);
+                } else {
+                    out.out.push(b' ');
+                }
+                out.out.extend_from_slice(item.data);
+                out.end_line();
+                out.block_start = false;
+            }
+            LexemeKind::Operator => {
+                if out.options.operator_spacing {
+                    out.out.push(b' ');
+                }
+                out.out.extend_from_slice(item.data);
+                if out.options.operator_spacing {
+                    out.out.push(b' ');
+                }
+            }
+            LexemeKind::CloseBrace => match out.blocks.pop() {
+                Some(true) => {
+                    if !out.block_start {
+                        out.out.push(b' ');
+                    }
+                    out.out.push(b'}');
+                    out.block_start = false;
+                }
+                _ => {
+                    if !out.line_start {
+                        out.end_line();
+                    }
+                    out.indent();
+                    out.out.push(b'}');
+                    out.line_start = false;
+                    out.block_start = false;
+                }
+            },
+            LexemeKind::OpenBrace | LexemeKind::Scalar | LexemeKind::Quoted => {
+                // An object that directly follows a value on the same line is
+                // a header like `rgb { 1 2 3 }`, so it continues the field
+                let is_header = item.kind == LexemeKind::OpenBrace
+                    && item.newlines == 0
+                    && prev.is_some_and(|x| x.kind == LexemeKind::Scalar);
+
+                if is_header {
+                    out.out.push(b' ');
+                } else if !after_operator {
+                    out.begin_value(item.newlines);
+                }
+
+                out.out.extend_from_slice(item.data);
+                out.line_start = false;
+                out.block_start = false;
+                if item.kind == LexemeKind::OpenBrace {
+                    out.blocks.push(item.inline);
+                    out.block_start = true;
+                }
+            }
+        }
+
+        after_operator = item.kind == LexemeKind::Operator;
+        prev = Some(item);
+    }
+
+    if !out.line_start {
+        out.end_line();
+    }
+
+    out.out
+}
+
+struct Formatter {
+    out: Vec<u8>,
+    options: Options,
+
+    /// Whether each open block is written on a single line
+    blocks: Vec<bool>,
+
+    /// Nothing has been written on the current line yet
+    line_start: bool,
+
+    /// Nothing has been written in the current block yet
+    block_start: bool,
+}
+
+impl Formatter {
+    /// Separate a value that starts a new field or array element
+    fn begin_value(&mut self, newlines: usize) {
+        if self.blocks.last() == Some(&true) {
+            self.out.push(b' ');
+        } else {
+            self.begin_line(newlines);
+        }
+    }
+
+    /// Start an indented line, keeping blank lines from the original data
+    fn begin_line(&mut self, newlines: usize) {
+        if !self.line_start {
+            self.end_line();
+        }
+
+        if !self.block_start && !self.out.is_empty() {
+            let blank_lines = newlines.saturating_sub(1).min(self.options.max_blank_lines);
+            for _ in 0..blank_lines {
+                self.out.extend_from_slice(self.options.newline.as_bytes());
+            }
+        }
+
+        self.indent();
+        self.line_start = false;
+    }
+
+    fn end_line(&mut self) {
+        self.out.extend_from_slice(self.options.newline.as_bytes());
+        self.line_start = true;
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.blocks.len() {
+            self.out.extend_from_slice(self.options.indent.as_bytes());
+        }
+    }
+}
+
+fn items(data: &[u8]) -> Vec<Item<'_>> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut newlines = 0;
+    for lexeme in lex(data) {
+        if lexeme.kind == LexemeKind::Whitespace {
+            newlines += lexeme.data.iter().filter(|&&x| x == b'\n').count();
+            continue;
+        }
+
+        if lexeme.kind == LexemeKind::CloseBrace {
+            open.pop();
+        } else if !matches!(lexeme.kind, LexemeKind::Scalar | LexemeKind::Quoted) {
+            if let Some(&idx) = open.last() {
+                items[idx].inline = false;
+            }
+        }
+
+        if lexeme.kind == LexemeKind::OpenBrace {
+            open.push(items.len());
+        }
+
+        items.push(Item {
+            kind: lexeme.kind,
+            data: lexeme.data,
+            newlines,
+            inline: lexeme.kind == LexemeKind::OpenBrace,
+        });
+        newlines = 0;
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextTape;
+
+    fn fmt(data: &[u8]) -> String {
+        String::from_utf8(format(data, Options::default())).unwrap()
+    }
+
+    #[test]
+    fn test_format_nested() {
+        let data = b"a={b=c d={1 2 3} e={}} f=rgb{1 2 3} g={{x=1}{x=2}}";
+        assert_eq!(
+            fmt(&data[..]),
+            "a = {\n\tb = c\n\td = { 1 2 3 }\n\te = {}\n}\nf = rgb { 1 2 3 }\ng = {\n\t{\n\t\tx = 1\n\t}\n\t{\n\t\tx = 2\n\t}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let data = b"# header\n\n\na=b # trailing\n  # own line\nc={ # open\nd=e\n}";
+        assert_eq!(
+            fmt(&data[..]),
+            "# header\n\na = b # trailing\n# own line\nc = { # open\n\td = e\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_options() {
+        let options = Options {
+            indent: String::from("  "),
+            newline: Newline::CrLf,
+            operator_spacing: false,
+            max_blank_lines: 0,
+        };
+
+        let out = format(b"a = { b >= 1\n\n c = 2 }", options);
+        assert_eq!(out, b"a={\r\n  b>=1\r\n  c=2\r\n}\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_format_is_stable() {
+        let data = b"a={b=\"c d\" e={ 1 2 } } # x\nf=hsv { 0.5 0.5 0.5 }\n\ng={ a b { c=d } }\n";
+        let once = format(&data[..], Options::default());
+        let twice = format(&once, Options::default());
+        assert_eq!(once, twice);
+
+        let original = TextTape::from_slice(&data[..]).unwrap();
+        let formatted = TextTape::from_slice(&once).unwrap();
+        assert_eq!(original.tokens(), formatted.tokens());
+    }
+
+    #[test]
+    fn test_format_empty() {
+        assert!(format(b"", Options::default()).is_empty());
+        assert!(format(b" \n ", Options::default()).is_empty());
+    }
+}
@@ -0,0 +1,398 @@
+//! Resolve the files of a game directory and the mods layered on top of it
+//!
+//! Game data is spread across many files that together form a single
+//! namespace (eg: every file in `common/ideas`). Mods are overlaid on top of
+//! the game in load order where:
+//!
+//! - A file in a mod replaces a file of the same name from the game or a mod
+//!   earlier in the load order
+//! - A `replace_path` in the mod descriptor discards all files of that
+//!   directory from the game and earlier mods
+//! - A mod is loaded after the mods listed in its `dependencies`
+//! - Files are loaded in alphabetical order of their file name
+//!
+//! ```no_run
+//! use jomini::{fs::{FileSystem, ModDescriptor}, TextTape};
+//!
+//! let descriptor = std::fs::read("mod/my_mod.mod")?;
+//! let descriptor = ModDescriptor::from_slice(&descriptor)?;
+//!
+//! let mut fs = FileSystem::new("/games/eu4");
+//! fs.add_mod("mod/my_mod", &descriptor);
+//!
+//! let mut contents = Vec::new();
+//! let tape = fs.parse_files("common/ideas", &mut contents)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! `FileSystem::parse_files` lets top level keys of later files override
+//! earlier ones. For directories where the game uses other override rules,
+//! read the contents with `FileSystem::read_files` and merge the tapes with
+//! `TextTape::merge` and the appropriate `MergeStrategy`.
+use crate::{Error, TextTape};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The information contained in a mod's `.mod` descriptor file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModDescriptor {
+    name: Option<String>,
+    path: Option<String>,
+    replace_paths: Vec<String>,
+    dependencies: Vec<String>,
+}
+
+impl ModDescriptor {
+    /// Parse a utf-8 encoded mod descriptor
+    ///
+    /// ```
+    /// use jomini::fs::ModDescriptor;
+    ///
+    /// let data = br#"
+    ///     name="My Mod"
+    ///     path="mod/my_mod"
+    ///     replace_path="common/ideas"
+    ///     replace_path="history/countries"
+    ///     dependencies={ "Base Mod" }
+    /// "#;
+    ///
+    /// let descriptor = ModDescriptor::from_slice(&data[..])?;
+    /// assert_eq!(descriptor.name(), Some("My Mod"));
+    /// assert_eq!(descriptor.path(), Some("mod/my_mod"));
+    /// assert_eq!(descriptor.replace_paths(), &["common/ideas", "history/countries"]);
+    /// assert_eq!(descriptor.dependencies(), &["Base Mod"]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_slice(data: &[u8]) -> Result<ModDescriptor, Error> {
+        let tape = TextTape::from_slice(strip_bom(data))?;
+        let mut reader = tape.utf8_reader();
+        let mut result = ModDescriptor::default();
+        while let Some((key, _op, value)) = reader.next_field() {
+            match key.read_str().as_ref() {
+                "name" => result.name = Some(value.read_string()?),
+                "path" => result.path = Some(value.read_string()?),
+                "replace_path" => result.replace_paths.push(value.read_string()?),
+                "dependencies" => {
+                    let mut deps = value.read_array()?;
+                    while let Some(dep) = deps.next_value() {
+                        result.dependencies.push(dep.read_string()?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// The display name of the mod
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The path of the mod relative to the user directory
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Directories where the mod discards the files from the game and
+    /// earlier mods
+    pub fn replace_paths(&self) -> &[String] {
+        &self.replace_paths
+    }
+
+    /// Names of the mods that this mod depends on, which are loaded before
+    /// this mod
+    pub fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    root: PathBuf,
+    name: Option<String>,
+    replace_paths: Vec<PathBuf>,
+    dependencies: Vec<String>,
+}
+
+/// A game directory with mods overlaid on top in load order
+#[derive(Debug, Clone)]
+pub struct FileSystem {
+    layers: Vec<Layer>,
+}
+
+impl FileSystem {
+    /// Create a file system rooted at the game directory
+    pub fn new<P: Into<PathBuf>>(game_dir: P) -> Self {
+        FileSystem {
+            layers: vec![Layer {
+                root: game_dir.into(),
+                name: None,
+                replace_paths: Vec::new(),
+                dependencies: Vec::new(),
+            }],
+        }
+    }
+
+    /// Overlay a mod. Mods added later take precedence over those added
+    /// earlier, except that a mod always takes precedence over the mods
+    /// that it depends on (matched by name), regardless of the order they
+    /// are added in.
+    pub fn add_mod<P: Into<PathBuf>>(
+        &mut self,
+        mod_dir: P,
+        descriptor: &ModDescriptor,
+    ) -> &mut Self {
+        self.layers.push(Layer {
+            root: mod_dir.into(),
+            name: descriptor.name().map(String::from),
+            replace_paths: descriptor
+                .replace_paths()
+                .iter()
+                .map(|x| normalize(Path::new(x)))
+                .collect(),
+            dependencies: descriptor.dependencies().to_vec(),
+        });
+        self
+    }
+
+    /// Return the paths of the files in the given directory (relative to the
+    /// game directory) after the mod overrides have been applied. The paths
+    /// are ordered as the game would load them. Subdirectories are not
+    /// descended into.
+    pub fn files<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<PathBuf>> {
+        let dir = normalize(dir.as_ref());
+        let mut files = BTreeMap::new();
+        for layer in self.load_order() {
+            if layer.replace_paths.contains(&dir) {
+                files.clear();
+            }
+
+            let entries = match std::fs::read_dir(layer.root.join(&dir)) {
+                Ok(x) => x,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    files.insert(entry.file_name(), entry.path());
+                }
+            }
+        }
+
+        Ok(files.into_values().collect())
+    }
+
+    /// The game followed by the mods in the order that they were added,
+    /// except that a mod is moved after any of the mods that it depends on.
+    /// Dependency cycles are broken by the order that the mods were added.
+    fn load_order(&self) -> Vec<&Layer> {
+        let mut result = vec![&self.layers[0]];
+        let mut remaining: Vec<&Layer> = self.layers[1..].iter().collect();
+        while !remaining.is_empty() {
+            let pending = |dep: &String| {
+                remaining
+                    .iter()
+                    .any(|x| x.name.as_deref() == Some(dep.as_str()))
+            };
+
+            let next = remaining
+                .iter()
+                .position(|layer| !layer.dependencies.iter().any(pending))
+                .unwrap_or(0);
+            result.push(remaining.remove(next));
+        }
+
+        result
+    }
+
+    /// Read the contents of all the files in the given directory in load
+    /// order. A leading utf-8 byte order mark is stripped from the contents
+    /// so that they are suitable to be parsed directly.
+    pub fn read_files<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<Vec<u8>>> {
+        self.files(dir)?
+            .iter()
+            .map(|path| {
+                let mut data = std::fs::read(path)?;
+                let bom_len = data.len() - strip_bom(&data).len();
+                data.drain(..bom_len);
+                Ok(data)
+            })
+            .collect()
+    }
+
+    /// Parse all the files in the given directory into a single tape where
+    /// top level keys of files later in the load order override those of
+    /// earlier files. The file contents are stored in the given buffer,
+    /// which the returned tape borrows from.
+    ///
+    /// See `TextTape::from_slices` for how documents are merged.
+    pub fn parse_files<'a, P: AsRef<Path>>(
+        &self,
+        dir: P,
+        contents: &'a mut Vec<Vec<u8>>,
+    ) -> Result<TextTape<'a>, Error> {
+        *contents = self.read_files(dir)?;
+        TextTape::from_slices(contents.iter().map(|x| x.as_slice()))
+    }
+}
+
+fn strip_bom(data: &[u8]) -> &[u8] {
+    if data.starts_with(b"\xef\xbb\xbf") {
+        &data[3..]
+    } else {
+        data
+    }
+}
+
+/// Drop empty and `.` components so that `common/ideas/` and
+/// `./common/ideas` compare equal
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|x| !matches!(x, std::path::Component::CurDir))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("jomini-fs-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn write(&self, path: &str, data: &[u8]) {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_mod_descriptor_bom() {
+        let data = b"\xef\xbb\xbfname=\"A\"";
+        let descriptor = ModDescriptor::from_slice(&data[..]).unwrap();
+        assert_eq!(descriptor.name(), Some("A"));
+        assert_eq!(descriptor.path(), None);
+        assert!(descriptor.replace_paths().is_empty());
+    }
+
+    #[test]
+    fn test_mod_overrides_file() {
+        let game = TempDir::new("override-game");
+        game.write("common/ideas/00_a.txt", b"a=1");
+        game.write("common/ideas/01_b.txt", b"b=1");
+        let modded = TempDir::new("override-mod");
+        modded.write("common/ideas/01_b.txt", b"b=2");
+        modded.write("common/ideas/00_0.txt", b"c=2");
+
+        let mut fs = FileSystem::new(&game.0);
+        fs.add_mod(&modded.0, &ModDescriptor::default());
+        let files = fs.files("common/ideas").unwrap();
+        assert_eq!(
+            files,
+            vec![
+                modded.0.join("common/ideas/00_0.txt"),
+                game.0.join("common/ideas/00_a.txt"),
+                modded.0.join("common/ideas/01_b.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mod_replace_path() {
+        let game = TempDir::new("replace-game");
+        game.write("common/ideas/00_a.txt", b"a=1");
+        game.write("common/other/00_a.txt", b"a=1");
+        let modded = TempDir::new("replace-mod");
+        modded.write("common/ideas/zz.txt", b"\xef\xbb\xbfb=2");
+
+        let descriptor = ModDescriptor::from_slice(b"replace_path=\"common/ideas/\"").unwrap();
+        let mut fs = FileSystem::new(&game.0);
+        fs.add_mod(&modded.0, &descriptor);
+
+        let contents = fs.read_files("common/ideas").unwrap();
+        assert_eq!(contents, vec![b"b=2".to_vec()]);
+        assert_eq!(fs.files("./common/other").unwrap().len(), 1);
+        assert!(fs.files("common/missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mod_dependencies_load_first() {
+        let game = TempDir::new("deps-game");
+        game.write("common/ideas/00_a.txt", b"a=1");
+        let base = TempDir::new("deps-base");
+        base.write("common/ideas/00_a.txt", b"a=2");
+        let patch = TempDir::new("deps-patch");
+        patch.write("common/ideas/00_a.txt", b"a=3");
+
+        let base_descriptor = ModDescriptor::from_slice(b"name=\"Base\"").unwrap();
+        let patch_descriptor =
+            ModDescriptor::from_slice(b"name=\"Patch\" dependencies={ \"Base\" \"Missing\" }")
+                .unwrap();
+
+        let mut fs = FileSystem::new(&game.0);
+        fs.add_mod(&patch.0, &patch_descriptor);
+        fs.add_mod(&base.0, &base_descriptor);
+        assert_eq!(
+            fs.read_files("common/ideas").unwrap(),
+            vec![b"a=3".to_vec()]
+        );
+
+        // A cycle falls back to the order that the mods were added
+        let base_descriptor =
+            ModDescriptor::from_slice(b"name=\"Base\" dependencies={ \"Patch\" }").unwrap();
+        let mut fs = FileSystem::new(&game.0);
+        fs.add_mod(&patch.0, &patch_descriptor);
+        fs.add_mod(&base.0, &base_descriptor);
+        assert_eq!(
+            fs.read_files("common/ideas").unwrap(),
+            vec![b"a=2".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_parse_files_merges_documents() {
+        let game = TempDir::new("parse-game");
+        game.write("common/ideas/00_a.txt", b"a=1 b=1");
+        let modded = TempDir::new("parse-mod");
+        modded.write("common/ideas/01_b.txt", b"\xef\xbb\xbfb=2 c=2");
+
+        let mut fs = FileSystem::new(&game.0);
+        fs.add_mod(&modded.0, &ModDescriptor::default());
+
+        let mut contents = Vec::new();
+        let tape = fs.parse_files("common/ideas", &mut contents).unwrap();
+        let mut reader = tape.utf8_reader();
+        let mut fields = Vec::new();
+        while let Some((key, _op, value)) = reader.next_field() {
+            fields.push((key.read_string(), value.read_string().unwrap()));
+        }
+
+        assert_eq!(
+            fields,
+            vec![
+                (String::from("a"), String::from("1")),
+                (String::from("b"), String::from("2")),
+                (String::from("c"), String::from("2")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Deduplicates strings so that each distinct string is allocated once.
+///
+/// Save files repeat the same keys over and over, so when converting a tape
+/// into owned data (JSON, a dynamic value, etc), giving every occurrence of
+/// a key its own allocation is wasteful. An interner hands out shared
+/// references to a single copy instead.
+///
+/// ```
+/// use jomini::Interner;
+/// use std::sync::Arc;
+///
+/// let mut interner = Interner::new();
+/// let a = interner.intern("owner");
+/// let b = interner.intern("owner");
+/// let c = interner.intern("controller");
+/// assert!(Arc::ptr_eq(&a, &b));
+/// assert_eq!(&*c, "controller");
+/// assert_eq!(interner.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// An interner without any strings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the shared copy of the string, allocating it on first sight
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(x) = self.strings.get(s) {
+            return Arc::clone(x);
+        }
+
+        let x: Arc<str> = Arc::from(s);
+        self.strings.insert(Arc::clone(&x));
+        x
+    }
+
+    /// The number of distinct strings that have been interned
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns true if no strings have been interned
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
@@ -91,6 +91,30 @@ without any duplication.
 
 One can configure the behavior when a token is unknown (ie: fail immediately or try to continue).
 
+## Field Order
+
+Fields are given to a deserializer in the order that they appear in the data, so order preserving
+maps (like `IndexMap`) reflect the source. When order and duplicates both matter, as they do in
+history blocks, an object can be deserialized into a sequence of key value pairs:
+
+```rust
+# #[cfg(feature = "derive")] {
+use jomini::{common::Date, TextDeserializer};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Event {
+    owner: String,
+}
+
+let data = b"1444.11.11={ owner=ENG } 1400.1.1={ owner=FRA } 1444.11.11={ owner=NOR }";
+let history: Vec<(Date, Event)> = TextDeserializer::from_windows1252_slice(data)?;
+assert_eq!(history.len(), 3);
+assert_eq!(history[1].1.owner, "FRA");
+# }
+# Ok::<(), Box<dyn std::error::Error>>(())
+```
+
 ## Caveats
 
 Caller is responsible for:
@@ -99,7 +123,7 @@ Caller is responsible for:
 - Stripping off any header that may be present (eg: `EU4txt` / `EU4bin`)
 - Providing the token resolver for the binary format
 - Providing the conversion to reconcile how, for example, a date may be encoded as an integer in
-the binary format, but as a string when in plaintext.
+  the binary format, but as a string when in plaintext.
 
 ## The Mid-level API
 
@@ -144,23 +168,72 @@ features, resulting in a build without dependencies.
 */
 #![warn(missing_docs)]
 pub(crate) mod ascii;
+#[cfg(feature = "async")]
+mod async_reader;
+#[cfg(feature = "bench")]
+pub mod bench;
 mod binary;
+pub mod chars;
 pub mod common;
 mod data;
 #[cfg(feature = "derive")]
 pub(crate) mod de;
+mod diagnostics;
+#[cfg(any(feature = "arbitrary", feature = "testutil"))]
+pub mod document;
+mod document_tape;
 mod encoding;
 mod errors;
+#[cfg(feature = "derive")]
+mod eu4_save;
+mod event_reader;
+#[cfg(feature = "export")]
+pub mod export;
+pub mod fmt;
+pub mod fs;
+mod intern;
+pub mod lint;
+#[cfg(feature = "metrics")]
+mod observer;
+#[cfg(any(feature = "eu4-schema", feature = "ck3-schema"))]
+pub mod packs;
+mod query;
 mod scalar;
+mod schema;
+mod selector;
+mod stats;
+#[cfg(feature = "testutil")]
+pub mod testutil;
 mod text;
+#[cfg(feature = "tooling")]
+pub mod tooling;
+mod trace;
 pub(crate) mod util;
+mod validate;
 
+#[cfg(feature = "async")]
+pub use self::async_reader::{AsyncBinaryEventReader, AsyncTextEventReader};
 pub use self::binary::*;
-pub use self::data::Rgb;
+pub use self::data::{HiddenObjectStrategy, Rgb, RgbRepresentation};
+pub use self::diagnostics::{Diagnostic, Diagnostics};
+pub use self::document_tape::{DocumentEncoding, DocumentTape};
 pub use self::encoding::*;
 pub use self::errors::*;
+#[cfg(feature = "derive")]
+pub use self::eu4_save::Eu4SaveDeserializer;
+pub use self::event_reader::{BinaryEventReader, TextEventReader};
+pub use self::intern::Interner;
+#[cfg(feature = "metrics")]
+pub use self::observer::ParseObserver;
+pub use self::query::{Query, QueryResult};
 pub use self::scalar::{Scalar, ScalarError};
+pub use self::schema::Schema;
+pub use self::selector::{SelectedValue, Selector};
+pub use self::stats::TapeStats;
 pub use self::text::*;
+pub use self::validate::{Definition, Violation, ViolationKind};
 
+#[cfg(feature = "derive")]
+pub use self::de::{DuplicateKeyStrategy, IntegerOverflow};
 #[cfg(feature = "derive")]
 pub use jomini_derive::*;
@@ -0,0 +1,552 @@
+//! A rules engine for checking script files.
+//!
+//! A [`Linter`] walks a text document and gives each object and scalar to
+//! its rules, which report [`Lint`]s with the byte span of the offending
+//! data. Rules are pluggable: besides the bundled rules, anything that
+//! implements [`Rule`] can be added.
+//!
+//! ```
+//! use jomini::lint::{Linter, NoDuplicateKeys, NumberRange, Severity, UnknownKeys};
+//!
+//! let linter = Linter::new()
+//!     .rule(UnknownKeys::new("", &["name", "stability", "core"]))
+//!     .rule(NoDuplicateKeys::new("").severity(Severity::Error))
+//!     .rule(NumberRange::new("stability", -3.0, 3.0));
+//!
+//! let data = b"name=Jean name=Paul stability=5 gold=10";
+//! let lints = linter.lint(&data[..])?;
+//! let messages: Vec<_> = lints.iter().map(|x| x.to_string()).collect();
+//! assert_eq!(
+//!     messages,
+//!     vec![
+//!         "error[duplicate-key] name: key occurs more than once",
+//!         "warning[number-out-of-range] stability: value 5 is outside of the range -3 to 3",
+//!         "warning[unknown-key] gold: unknown key",
+//!     ]
+//! );
+//! assert_eq!(lints[0].span, 10..14);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{Error, Operator, Scalar, TextTape, TextToken};
+use std::fmt;
+use std::ops::Range;
+
+/// How serious a lint is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// A suggestion
+    Info,
+
+    /// Likely a mistake, but the game will still load the file
+    Warning,
+
+    /// The game will misinterpret or reject the data
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem reported by a rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    /// The name of the rule that reported the lint
+    pub rule: String,
+
+    /// How serious the problem is
+    pub severity: Severity,
+
+    /// The location of the data: object keys and array indices joined by `/`
+    pub path: String,
+
+    /// The byte range of the offending data
+    pub span: Range<usize>,
+
+    /// A description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}] {}: {}",
+            self.severity, self.rule, self.path, self.message
+        )
+    }
+}
+
+/// A field of an object given to [`Rule::check_object`]
+#[derive(Debug, Clone)]
+pub struct Field<'data> {
+    /// The key of the field
+    pub key: Scalar<'data>,
+
+    /// The byte range of the key
+    pub key_span: Range<usize>,
+
+    /// The operator between the key and value, if it isn't an equal sign
+    pub operator: Option<Operator>,
+
+    /// The value of the field if it is a scalar
+    pub value: Option<Scalar<'data>>,
+
+    /// The byte range of the value if it is a scalar
+    pub value_span: Option<Range<usize>>,
+}
+
+/// Where a rule reports its lints
+#[derive(Debug)]
+pub struct Context<'a> {
+    rule: &'a str,
+    path: &'a str,
+    lints: &'a mut Vec<Lint>,
+}
+
+impl<'a> Context<'a> {
+    /// The location of the object or scalar being checked
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// Report a lint for a field of the object being checked
+    pub fn report_field(&mut self, field: &Field, severity: Severity, message: impl Into<String>) {
+        let path = join(self.path, &field.key.to_string());
+        self.push(path, field.key_span.clone(), severity, message.into());
+    }
+
+    /// Report a lint for a span of the data
+    pub fn report(&mut self, span: Range<usize>, severity: Severity, message: impl Into<String>) {
+        let path = String::from(self.path);
+        self.push(path, span, severity, message.into());
+    }
+
+    fn push(&mut self, path: String, span: Range<usize>, severity: Severity, message: String) {
+        self.lints.push(Lint {
+            rule: String::from(self.rule),
+            severity,
+            path,
+            span,
+            message,
+        });
+    }
+}
+
+/// A check that is run over a document
+pub trait Rule {
+    /// The name of the rule, which is attached to the lints it reports
+    fn name(&self) -> &str;
+
+    /// Inspect the fields of an object, including the document's top level
+    /// object whose path is empty
+    fn check_object(&self, _fields: &[Field], _cx: &mut Context) {}
+
+    /// Inspect a scalar, which is called for every key and scalar value
+    fn check_scalar(&self, _scalar: Scalar, _span: Range<usize>, _cx: &mut Context) {}
+}
+
+/// Runs rules over text documents
+#[derive(Default)]
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl fmt::Debug for Linter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self.rules.iter().map(|x| x.name()).collect();
+        f.debug_struct("Linter").field("rules", &names).finish()
+    }
+}
+
+impl Linter {
+    /// A linter without any rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule
+    pub fn rule<R: Rule + 'static>(mut self, rule: R) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Parse the data and run every rule over it. Lints are ordered by
+    /// their position in the data.
+    pub fn lint(&self, data: &[u8]) -> Result<Vec<Lint>, Error> {
+        let tape = TextTape::from_slice(data)?;
+        let mut walker = Walker {
+            rules: &self.rules,
+            data,
+            tokens: tape.tokens(),
+            lints: Vec::new(),
+        };
+
+        walker.object(String::new(), 0, walker.tokens.len());
+        let mut lints = walker.lints;
+        lints.sort_by_key(|x| x.span.start);
+        Ok(lints)
+    }
+}
+
+struct Walker<'a, 'data> {
+    rules: &'a [Box<dyn Rule>],
+    data: &'data [u8],
+    tokens: &'a [TextToken<'data>],
+    lints: Vec<Lint>,
+}
+
+impl<'a, 'data> Walker<'a, 'data> {
+    fn span(&self, scalar: Scalar) -> Range<usize> {
+        let start = scalar.view_data().as_ptr() as usize - self.data.as_ptr() as usize;
+        start..start + scalar.view_data().len()
+    }
+
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+            TextToken::Header(_) => self.next_idx(idx + 1),
+            _ => idx + 1,
+        }
+    }
+
+    fn scalar(&mut self, path: &str, scalar: Scalar) {
+        let span = self.span(scalar);
+        for rule in self.rules {
+            let mut cx = Context {
+                rule: rule.name(),
+                path,
+                lints: &mut self.lints,
+            };
+            rule.check_scalar(scalar, span.clone(), &mut cx);
+        }
+    }
+
+    fn value(&mut self, path: String, idx: usize) {
+        match self.tokens[idx] {
+            TextToken::Scalar(x) => self.scalar(&path, x),
+            TextToken::Header(x) => {
+                self.scalar(&path, x);
+                self.value(path, idx + 1);
+            }
+            TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                self.object(path, idx + 1, end)
+            }
+            TextToken::Array(end) => {
+                let mut i = idx + 1;
+                let mut element = 0;
+                while i < end {
+                    self.value(join(&path, &element.to_string()), i);
+                    i = self.next_idx(i);
+                    element += 1;
+                }
+            }
+            TextToken::Operator(_) | TextToken::End(_) => {}
+        }
+    }
+
+    fn object(&mut self, path: String, mut idx: usize, end: usize) {
+        let mut fields = Vec::new();
+        while idx < end {
+            let key = match self.tokens[idx] {
+                TextToken::Scalar(x) => x,
+                _ => {
+                    idx = self.next_idx(idx);
+                    continue;
+                }
+            };
+
+            let mut value_idx = idx + 1;
+            let mut operator = None;
+            if let Some(TextToken::Operator(op)) = self.tokens.get(value_idx) {
+                operator = Some(*op);
+                value_idx += 1;
+            }
+
+            if value_idx >= end {
+                break;
+            }
+
+            let value = self.tokens[value_idx].as_scalar();
+            fields.push(Field {
+                key,
+                key_span: self.span(key),
+                operator,
+                value,
+                value_span: value.map(|x| self.span(x)),
+            });
+
+            let field_path = join(&path, &key.to_string());
+            self.scalar(&field_path, key);
+            self.value(field_path, value_idx);
+            idx = self.next_idx(value_idx);
+        }
+
+        for rule in self.rules {
+            let mut cx = Context {
+                rule: rule.name(),
+                path: &path,
+                lints: &mut self.lints,
+            };
+            rule.check_object(&fields, &mut cx);
+        }
+    }
+}
+
+fn join(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        String::from(segment)
+    } else {
+        format!("{}/{}", path, segment)
+    }
+}
+
+/// Reports keys that are not allowed in the objects at a path
+#[derive(Debug, Clone)]
+pub struct UnknownKeys {
+    path: String,
+    keys: Vec<String>,
+    severity: Severity,
+}
+
+impl UnknownKeys {
+    /// Only allow the given keys in objects at the path. An empty path is
+    /// the document's top level object.
+    pub fn new(path: &str, keys: &[&str]) -> Self {
+        UnknownKeys {
+            path: String::from(path),
+            keys: keys.iter().map(|x| String::from(*x)).collect(),
+            severity: Severity::Warning,
+        }
+    }
+
+    /// Set the severity of the reported lints. Defaults to a warning.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+}
+
+impl Rule for UnknownKeys {
+    fn name(&self) -> &str {
+        "unknown-key"
+    }
+
+    fn check_object(&self, fields: &[Field], cx: &mut Context) {
+        if cx.path() != self.path {
+            return;
+        }
+
+        for field in fields {
+            let key = field.key.view_data();
+            if !self.keys.iter().any(|x| x.as_bytes() == key) {
+                cx.report_field(field, self.severity, "unknown key");
+            }
+        }
+    }
+}
+
+/// Reports keys that occur more than once in the objects at a path
+#[derive(Debug, Clone)]
+pub struct NoDuplicateKeys {
+    path: String,
+    severity: Severity,
+}
+
+impl NoDuplicateKeys {
+    /// Disallow duplicate keys in objects at the path. An empty path is the
+    /// document's top level object.
+    pub fn new(path: &str) -> Self {
+        NoDuplicateKeys {
+            path: String::from(path),
+            severity: Severity::Warning,
+        }
+    }
+
+    /// Set the severity of the reported lints. Defaults to a warning.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+}
+
+impl Rule for NoDuplicateKeys {
+    fn name(&self) -> &str {
+        "duplicate-key"
+    }
+
+    fn check_object(&self, fields: &[Field], cx: &mut Context) {
+        if cx.path() != self.path {
+            return;
+        }
+
+        for (i, field) in fields.iter().enumerate() {
+            let key = field.key.view_data();
+            if fields[..i].iter().any(|x| x.key.view_data() == key) {
+                cx.report_field(field, self.severity, "key occurs more than once");
+            }
+        }
+    }
+}
+
+/// Reports numeric values of a key that fall outside of a range
+#[derive(Debug, Clone)]
+pub struct NumberRange {
+    key: String,
+    min: f64,
+    max: f64,
+    severity: Severity,
+}
+
+impl NumberRange {
+    /// Values of the key, at any depth, must be within the inclusive range
+    pub fn new(key: &str, min: f64, max: f64) -> Self {
+        NumberRange {
+            key: String::from(key),
+            min,
+            max,
+            severity: Severity::Warning,
+        }
+    }
+
+    /// Set the severity of the reported lints. Defaults to a warning.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+}
+
+impl Rule for NumberRange {
+    fn name(&self) -> &str {
+        "number-out-of-range"
+    }
+
+    fn check_object(&self, fields: &[Field], cx: &mut Context) {
+        for field in fields {
+            if field.key.view_data() != self.key.as_bytes() {
+                continue;
+            }
+
+            let value = match field.value.map(|x| x.to_f64()) {
+                Some(Ok(x)) => x,
+                _ => continue,
+            };
+
+            if value < self.min || value > self.max {
+                let msg = format!(
+                    "value {} is outside of the range {} to {}",
+                    value, self.min, self.max
+                );
+                cx.report_field(field, self.severity, msg);
+            }
+        }
+    }
+}
+
+/// Reports scalars with non-ascii characters. Files that the games read as
+/// Windows-1252 are often saved as UTF-8 by mistake, which garbles accented
+/// characters in game.
+#[derive(Debug, Clone)]
+pub struct NonAscii {
+    severity: Severity,
+}
+
+impl Default for NonAscii {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NonAscii {
+    /// Report every scalar that contains a non-ascii character
+    pub fn new() -> Self {
+        NonAscii {
+            severity: Severity::Warning,
+        }
+    }
+
+    /// Set the severity of the reported lints. Defaults to a warning.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+}
+
+impl Rule for NonAscii {
+    fn name(&self) -> &str {
+        "non-ascii"
+    }
+
+    fn check_scalar(&self, scalar: Scalar, span: Range<usize>, cx: &mut Context) {
+        if !scalar.is_ascii() {
+            cx.report(span, self.severity, "contains non-ascii characters");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoYes;
+
+    impl Rule for NoYes {
+        fn name(&self) -> &str {
+            "no-yes"
+        }
+
+        fn check_scalar(&self, scalar: Scalar, span: Range<usize>, cx: &mut Context) {
+            if scalar.view_data() == b"yes" {
+                cx.report(span, Severity::Info, "prefer no");
+            }
+        }
+    }
+
+    #[test]
+    fn test_nested_paths_and_custom_rules() {
+        let data = "a={ b={ c=yes c=1 } list={ yes { d=\"Ã©\" } } }".as_bytes();
+        let linter = Linter::new()
+            .rule(NoDuplicateKeys::new("a/b"))
+            .rule(UnknownKeys::new("a/list/1", &["e"]).severity(Severity::Error))
+            .rule(NonAscii::new())
+            .rule(NoYes);
+
+        let lints = linter.lint(data).unwrap();
+        let found: Vec<_> = lints
+            .iter()
+            .map(|x| (x.rule.as_str(), x.severity, x.path.as_str(), x.span.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("no-yes", Severity::Info, "a/b/c", 10..13),
+                ("duplicate-key", Severity::Warning, "a/b/c", 14..15),
+                ("no-yes", Severity::Info, "a/list/0", 27..30),
+                ("unknown-key", Severity::Error, "a/list/1/d", 33..34),
+                ("non-ascii", Severity::Warning, "a/list/1/d", 36..40),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_number_range_at_any_depth() {
+        let linter = Linter::new().rule(NumberRange::new("x", 0.0, 1.0));
+        let lints = linter
+            .lint(b"x=2 a={ x=0.5 b={ x=-1 } } c={ x=abc }")
+            .unwrap();
+        let paths: Vec<_> = lints.iter().map(|x| x.path.as_str()).collect();
+        assert_eq!(paths, vec!["x", "a/b/x"]);
+    }
+
+    #[test]
+    fn test_lint_parse_error() {
+        assert!(Linter::new().lint(b"a={").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+/// Receives counts of the work done by parsers, so that services that embed
+/// jomini can export them as metrics (eg: Prometheus counters).
+///
+/// Each method has a default implementation that does nothing, so an
+/// observer only needs to implement the counts that it's interested in.
+/// Observers are attached with `TextTapeParser::observer`,
+/// `BinaryTapeParser::observer`, `NormalizerBuilder::observer`, and
+/// `BinaryDeserializerBuilder::observer`.
+///
+/// ```
+/// use jomini::{ParseObserver, TextTape};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// #[derive(Debug, Default)]
+/// struct Counters {
+///     bytes: AtomicUsize,
+///     tokens: AtomicUsize,
+/// }
+///
+/// impl ParseObserver for Counters {
+///     fn bytes_parsed(&self, bytes: usize) {
+///         self.bytes.fetch_add(bytes, Ordering::Relaxed);
+///     }
+///
+///     fn tokens_produced(&self, tokens: usize) {
+///         self.tokens.fetch_add(tokens, Ordering::Relaxed);
+///     }
+/// }
+///
+/// static COUNTERS: Counters = Counters {
+///     bytes: AtomicUsize::new(0),
+///     tokens: AtomicUsize::new(0),
+/// };
+///
+/// TextTape::parser().observer(&COUNTERS).parse_slice(b"a=1 b=2")?;
+/// TextTape::parser().observer(&COUNTERS).parse_slice(b"c=3")?;
+/// assert_eq!(COUNTERS.bytes.load(Ordering::Relaxed), 10);
+/// assert_eq!(COUNTERS.tokens.load(Ordering::Relaxed), 6);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait ParseObserver: Send + Sync {
+    /// Called with the length of the data after it has been parsed
+    fn bytes_parsed(&self, bytes: usize) {
+        let _ = bytes;
+    }
+
+    /// Called with the number of tokens in a tape after it has been parsed
+    fn tokens_produced(&self, tokens: usize) {
+        let _ = tokens;
+    }
+
+    /// Called with the number of binary tokens that could not be resolved to
+    /// a name while melting or deserializing
+    fn unknown_tokens(&self, count: usize) {
+        let _ = count;
+    }
+}
+
+/// An attached observer, which is a static reference so that parsers that
+/// are `Copy` stay that way
+#[derive(Clone, Copy)]
+pub(crate) struct Observer(pub(crate) &'static dyn ParseObserver);
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer")
+    }
+}
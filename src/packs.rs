@@ -0,0 +1,181 @@
+//! Bundled token and schema metadata for known games.
+//!
+//! Each pack is enabled by its own feature (`eu4-schema` and `ck3-schema`)
+//! and contains a token table that can be given to any API that takes a
+//! [`TokenResolver`] along with a [`Definition`] of the save's metadata, so
+//! that the metadata of a save can be validated and converted to text without
+//! assembling a token file.
+//!
+//! The token tables only cover the metadata fields whose names are already
+//! public. Tokens for the rest of a save are distributed under the terms of
+//! the game's license and still need to be provided by the user.
+//!
+//! ```
+//! # #[cfg(feature = "eu4-schema")] {
+//! use jomini::{packs, BinaryTape};
+//!
+//! let data = [0x4d, 0x28, 0x01, 0x00, 0x0c, 0x00, 0x10, 0x77, 0x5d, 0x03];
+//! let tape = BinaryTape::from_eu4(&data[..])?;
+//! assert!(packs::EU4.meta_definition().validate_binary(&tape, &packs::EU4).is_empty());
+//! # }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{Definition, TokenResolver};
+use std::borrow::Cow;
+
+/// Versioned token and schema metadata for a game
+#[derive(Debug)]
+pub struct SchemaPack {
+    game: &'static str,
+    version: &'static str,
+
+    /// Sorted by token
+    tokens: &'static [(u16, &'static str)],
+    meta: fn() -> Definition,
+}
+
+impl SchemaPack {
+    /// The name of the game that the pack describes
+    pub fn game(&self) -> &'static str {
+        self.game
+    }
+
+    /// The version of the game that the metadata was taken from
+    pub fn version(&self) -> &'static str {
+        self.version
+    }
+
+    /// The bundled tokens and their field names, sorted by token
+    pub fn tokens(&self) -> &'static [(u16, &'static str)] {
+        self.tokens
+    }
+
+    /// The definition of the save's metadata section
+    pub fn meta_definition(&self) -> Definition {
+        (self.meta)()
+    }
+}
+
+impl TokenResolver for SchemaPack {
+    fn resolve(&self, token: u16) -> Option<Cow<'_, str>> {
+        self.tokens
+            .binary_search_by_key(&token, |(id, _)| *id)
+            .ok()
+            .map(|idx| Cow::Borrowed(self.tokens[idx].1))
+    }
+}
+
+/// Metadata for EU4 saves
+#[cfg(feature = "eu4-schema")]
+pub static EU4: SchemaPack = SchemaPack {
+    game: "eu4",
+    version: "1.29.4",
+    tokens: &[
+        (0x000b, "id"),
+        (0x00dc, "key"),
+        (0x0179, "checksum"),
+        (0x0209, "localization"),
+        (0x284d, "date"),
+        (0x28e2, "first"),
+        (0x28e3, "second"),
+        (0x2a38, "player"),
+        (0x2c69, "save_game"),
+        (0x2ec7, "third"),
+        (0x2ec8, "forth"),
+        (0x2ec9, "savegame_version"),
+        (0x314b, "savegame_versions"),
+        (0x32b8, "displayed_country_name"),
+        (0x3329, "multi_player"),
+        (0x3382, "campaign_length"),
+        (0x354d, "comparison"),
+        (0x354e, "selector"),
+        (0x3551, "campaign_stats"),
+    ],
+    meta: eu4_meta,
+};
+
+#[cfg(feature = "eu4-schema")]
+fn eu4_meta() -> Definition {
+    let version = Definition::object()
+        .required("first", Definition::integer())
+        .required("second", Definition::integer())
+        .required("third", Definition::integer())
+        .required("forth", Definition::integer());
+
+    let stat = Definition::object()
+        .required("id", Definition::integer())
+        .required("comparison", Definition::integer())
+        .required("key", Definition::string())
+        .optional("selector", Definition::string())
+        .optional("localization", Definition::string());
+
+    Definition::object()
+        .required("date", Definition::date())
+        .optional("save_game", Definition::string())
+        .optional("player", Definition::string())
+        .optional("displayed_country_name", Definition::string())
+        .optional("savegame_version", version)
+        .optional("savegame_versions", Definition::array(Definition::string()))
+        .optional("multi_player", Definition::bool())
+        .optional("campaign_length", Definition::integer())
+        .optional("campaign_stats", Definition::array(stat))
+        .optional("checksum", Definition::string())
+}
+
+/// Metadata for CK3 saves
+#[cfg(feature = "ck3-schema")]
+pub static CK3: SchemaPack = SchemaPack {
+    game: "ck3",
+    version: "1.0.3",
+    tokens: &[
+        (0x00e1, "type"),
+        (0x00ee, "version"),
+        (0x058f, "save_game_version"),
+        (0x0626, "male"),
+        (0x06ef, "age"),
+        (0x29e6, "meta_player_name"),
+        (0x29e7, "meta_title_name"),
+        (0x3155, "meta_data"),
+        (0x3391, "meta_main_portrait"),
+    ],
+    meta: ck3_meta,
+};
+
+#[cfg(feature = "ck3-schema")]
+fn ck3_meta() -> Definition {
+    let portrait = Definition::object()
+        .required("type", Definition::string())
+        .required("age", Definition::float().range(0.0, 1.0));
+
+    let meta = Definition::object()
+        .required("save_game_version", Definition::integer())
+        .required("version", Definition::string())
+        .optional("meta_player_name", Definition::string())
+        .optional("meta_title_name", Definition::string())
+        .optional("meta_main_portrait", portrait);
+
+    Definition::object().required("meta_data", meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_sorted() {
+        #[cfg(feature = "eu4-schema")]
+        assert!(EU4.tokens().windows(2).all(|x| x[0].0 < x[1].0));
+
+        #[cfg(feature = "ck3-schema")]
+        assert!(CK3.tokens().windows(2).all(|x| x[0].0 < x[1].0));
+    }
+
+    #[cfg(feature = "eu4-schema")]
+    #[test]
+    fn test_eu4_resolve() {
+        assert_eq!(EU4.resolve(0x284d).as_deref(), Some("date"));
+        assert_eq!(EU4.resolve(0x0001), None);
+        assert_eq!(EU4.game(), "eu4");
+    }
+}
@@ -0,0 +1,566 @@
+use crate::{binary, text};
+use crate::{BinaryTape, BinaryToken, TextTape, TextToken, TokenResolver};
+
+/// A set of paths compiled once and then executed against many tapes.
+///
+/// Extracting the same fields out of many documents by walking each
+/// document by hand means repeatedly comparing every key against every
+/// wanted field. A query compiles its paths into a tree of keys so that
+/// execution is a single pass over the tape that only descends into objects
+/// that lead to a wanted field and skips everything else.
+///
+/// Paths are keys joined with a `/` (as dates are common keys and contain
+/// periods). Keys are compared byte for byte with the key's data, so any
+/// quotes surrounding a key in the document are ignored. Every occurrence
+/// of a path is matched, so duplicate keys result in multiple matches.
+///
+/// ```
+/// use jomini::{Query, TextTape};
+///
+/// let query = Query::new(["date", "player/tag", "player/gold"]);
+/// let tape = TextTape::from_slice(b"date=1444.11.11 player={ tag=ENG gold=10 }")?;
+/// let result = query.execute_text(&tape);
+/// let tokens = tape.tokens();
+/// let tag = result.first(1).and_then(|x| tokens[x].as_scalar());
+/// assert_eq!(tag.map(|x| x.view_data()), Some(&b"ENG"[..]));
+/// assert_eq!(result.get(2).len(), 1);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    nodes: Vec<QueryNode>,
+    paths: usize,
+}
+
+#[derive(Debug, Clone)]
+struct QueryNode {
+    key: Vec<u8>,
+    children: Vec<usize>,
+    outputs: Vec<usize>,
+}
+
+impl QueryNode {
+    fn new(key: &[u8]) -> Self {
+        QueryNode {
+            key: key.to_vec(),
+            children: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+}
+
+/// The token indices matched by each path of a [Query]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryResult {
+    matches: Vec<Vec<usize>>,
+}
+
+impl QueryResult {
+    /// Create an empty result that can be reused across executions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The tape indices of the values matched by the path at the given
+    /// position in the query. Out of bounds positions have no matches.
+    pub fn get(&self, path: usize) -> &[usize] {
+        self.matches.get(path).map_or(&[], |x| x.as_slice())
+    }
+
+    /// The tape index of the first value matched by the path at the given
+    /// position in the query
+    pub fn first(&self, path: usize) -> Option<usize> {
+        self.get(path).first().copied()
+    }
+
+    /// The number of paths in the result
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Returns true if the result has no paths
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    fn reset(&mut self, paths: usize) {
+        self.matches.resize_with(paths, Vec::new);
+        self.matches.truncate(paths);
+        for path in &mut self.matches {
+            path.clear();
+        }
+    }
+
+    fn push(&mut self, outputs: &[usize], idx: usize) {
+        for &output in outputs {
+            self.matches[output].push(idx);
+        }
+    }
+}
+
+impl Query {
+    /// Compile the paths into a query. A match for a path is retrieved by
+    /// the path's position in the given paths.
+    pub fn new<I, S>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut nodes = vec![QueryNode::new(b"")];
+        let mut count = 0;
+        for (i, path) in paths.into_iter().enumerate() {
+            let mut node = 0;
+            for key in path.as_ref().split('/') {
+                let existing = nodes[node]
+                    .children
+                    .iter()
+                    .copied()
+                    .find(|&x| nodes[x].key == key.as_bytes());
+
+                node = match existing {
+                    Some(x) => x,
+                    None => {
+                        nodes.push(QueryNode::new(key.as_bytes()));
+                        let child = nodes.len() - 1;
+                        nodes[node].children.push(child);
+                        child
+                    }
+                };
+            }
+
+            nodes[node].outputs.push(i);
+            count = i + 1;
+        }
+
+        Query {
+            nodes,
+            paths: count,
+        }
+    }
+
+    /// The number of paths in the query
+    pub fn len(&self) -> usize {
+        self.paths
+    }
+
+    /// Returns true if the query has no paths
+    pub fn is_empty(&self) -> bool {
+        self.paths == 0
+    }
+
+    /// Execute the query against a text tape
+    pub fn execute_text(&self, tape: &TextTape) -> QueryResult {
+        let mut result = QueryResult::new();
+        self.execute_text_into(tape, &mut result);
+        result
+    }
+
+    /// Execute the query against a text tape, reusing the allocations of a
+    /// previous result
+    pub fn execute_text_into(&self, tape: &TextTape, result: &mut QueryResult) {
+        result.reset(self.paths);
+        let tokens = tape.tokens();
+        self.text_object(tokens, 0, tokens.len(), 0, result);
+    }
+
+    fn text_object(
+        &self,
+        tokens: &[TextToken],
+        mut idx: usize,
+        end: usize,
+        node: usize,
+        result: &mut QueryResult,
+    ) {
+        while idx < end {
+            let key = match tokens[idx] {
+                TextToken::Scalar(x) => x,
+                _ => {
+                    idx = text_next_idx(tokens, idx);
+                    continue;
+                }
+            };
+
+            let mut value_idx = idx + 1;
+            if let Some(TextToken::Operator(_)) = tokens.get(value_idx) {
+                value_idx += 1;
+            }
+
+            if value_idx >= end {
+                break;
+            }
+
+            if let Some(child) = self.child_idx(node, key.view_data()) {
+                result.push(&self.nodes[child].outputs, value_idx);
+                if !self.nodes[child].children.is_empty() {
+                    if let TextToken::Object(x) = tokens[value_idx] {
+                        self.text_object(tokens, value_idx + 1, x, child, result);
+                    }
+                }
+            }
+
+            idx = text_next_idx(tokens, value_idx);
+        }
+    }
+
+    /// Execute the query against a binary tape, resolving token keys with
+    /// the given resolver
+    pub fn execute_binary<RES>(&self, tape: &BinaryTape, resolver: &RES) -> QueryResult
+    where
+        RES: TokenResolver,
+    {
+        let mut result = QueryResult::new();
+        self.execute_binary_into(tape, resolver, &mut result);
+        result
+    }
+
+    /// Execute the query against a binary tape, reusing the allocations of a
+    /// previous result
+    pub fn execute_binary_into<RES>(
+        &self,
+        tape: &BinaryTape,
+        resolver: &RES,
+        result: &mut QueryResult,
+    ) where
+        RES: TokenResolver,
+    {
+        result.reset(self.paths);
+        let tokens = tape.tokens();
+        self.binary_object(tokens, resolver, 0, tokens.len(), 0, result);
+    }
+
+    fn binary_object<RES>(
+        &self,
+        tokens: &[BinaryToken],
+        resolver: &RES,
+        mut idx: usize,
+        end: usize,
+        node: usize,
+        result: &mut QueryResult,
+    ) where
+        RES: TokenResolver,
+    {
+        while idx + 1 < end {
+            let value_idx = idx + 1;
+            let child = match tokens[idx] {
+                BinaryToken::Token(x) => resolver
+                    .resolve(x)
+                    .and_then(|key| self.child_idx(node, key.as_bytes())),
+                BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                    self.child_idx(node, x.view_data())
+                }
+                _ => None,
+            };
+
+            if let Some(child) = child {
+                result.push(&self.nodes[child].outputs, value_idx);
+                if !self.nodes[child].children.is_empty() {
+                    if let BinaryToken::Object(x) = tokens[value_idx] {
+                        self.binary_object(tokens, resolver, value_idx + 1, x, child, result);
+                    }
+                }
+            }
+
+            idx = match tokens[value_idx] {
+                BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => {
+                    x + 1
+                }
+                _ => value_idx + 1,
+            };
+        }
+    }
+
+    /// Create a tape that only contains the fields along the query's paths,
+    /// so that only an allowlisted set of fields is kept when a document is
+    /// stored or shared. The projected tape can be written out with the
+    /// `TextWriter` or converted to JSON like any other tape. Objects that
+    /// lead to a path are kept only if they contain a match.
+    ///
+    /// ```
+    /// use jomini::{Query, TextTape, TextWriter};
+    ///
+    /// let data = b"date=1444.11.11 player_name=Alice countries={ ENG={ gold=10 name=x } }";
+    /// let tape = TextTape::from_slice(data)?;
+    /// let projected = Query::new(["date", "countries/ENG/gold"]).project_text(&tape);
+    ///
+    /// let mut writer = TextWriter::from_writer(Vec::new());
+    /// writer.write_tape(&projected)?;
+    /// assert_eq!(
+    ///     writer.into_inner(),
+    ///     &b"date=1444.11.11\ncountries={ ENG={ gold=10 } }\n"[..]
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn project_text<'a>(&self, tape: &TextTape<'a>) -> TextTape<'a> {
+        let tokens = tape.tokens();
+        let mut out = Vec::new();
+        self.project_text_object(tokens, 0, tokens.len(), 0, &mut out);
+        TextTape::from_tokens(out)
+    }
+
+    /// Returns true if anything was projected
+    fn project_text_object<'a>(
+        &self,
+        tokens: &[TextToken<'a>],
+        mut idx: usize,
+        end: usize,
+        node: usize,
+        out: &mut Vec<TextToken<'a>>,
+    ) -> bool {
+        let mut projected = false;
+        while idx < end {
+            let key = match tokens[idx] {
+                TextToken::Scalar(x) => x,
+                _ => {
+                    idx = text_next_idx(tokens, idx);
+                    continue;
+                }
+            };
+
+            let mut value_idx = idx + 1;
+            if let Some(TextToken::Operator(_)) = tokens.get(value_idx) {
+                value_idx += 1;
+            }
+
+            if value_idx >= end {
+                break;
+            }
+
+            let next = text_next_idx(tokens, value_idx);
+            if let Some(child) = self.child_idx(node, key.view_data()) {
+                if !self.nodes[child].outputs.is_empty() {
+                    text::extend_tape(out, &tokens[idx..next], idx);
+                    projected = true;
+                } else if let TextToken::Object(x) = tokens[value_idx] {
+                    let start = out.len();
+                    out.extend_from_slice(&tokens[idx..value_idx]);
+                    let obj = out.len();
+                    out.push(TextToken::Object(0));
+                    if self.project_text_object(tokens, value_idx + 1, x, child, out) {
+                        out[obj] = TextToken::Object(out.len());
+                        out.push(TextToken::End(obj));
+                        projected = true;
+                    } else {
+                        out.truncate(start);
+                    }
+                }
+            }
+
+            idx = next;
+        }
+
+        projected
+    }
+
+    /// Create a binary tape that only contains the fields along the query's
+    /// paths. See `project_text`.
+    pub fn project_binary<'a, RES>(&self, tape: &BinaryTape<'a>, resolver: &RES) -> BinaryTape<'a>
+    where
+        RES: TokenResolver,
+    {
+        let tokens = tape.tokens();
+        let mut out = Vec::new();
+        self.project_binary_object(tokens, resolver, 0, tokens.len(), 0, &mut out);
+        BinaryTape::from_tokens(out)
+    }
+
+    /// Returns true if anything was projected
+    fn project_binary_object<'a, RES>(
+        &self,
+        tokens: &[BinaryToken<'a>],
+        resolver: &RES,
+        mut idx: usize,
+        end: usize,
+        node: usize,
+        out: &mut Vec<BinaryToken<'a>>,
+    ) -> bool
+    where
+        RES: TokenResolver,
+    {
+        let mut projected = false;
+        while idx + 1 < end {
+            let value_idx = idx + 1;
+            let next = match tokens[value_idx] {
+                BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => {
+                    x + 1
+                }
+                _ => value_idx + 1,
+            };
+
+            let child = match tokens[idx] {
+                BinaryToken::Token(x) => resolver
+                    .resolve(x)
+                    .and_then(|key| self.child_idx(node, key.as_bytes())),
+                BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                    self.child_idx(node, x.view_data())
+                }
+                _ => None,
+            };
+
+            if let Some(child) = child {
+                if !self.nodes[child].outputs.is_empty() {
+                    binary::extend_tape(out, &tokens[idx..next], idx);
+                    projected = true;
+                } else if let BinaryToken::Object(x) = tokens[value_idx] {
+                    let start = out.len();
+                    out.push(tokens[idx].clone());
+                    let obj = out.len();
+                    out.push(BinaryToken::Object(0));
+                    if self.project_binary_object(tokens, resolver, value_idx + 1, x, child, out) {
+                        out[obj] = BinaryToken::Object(out.len());
+                        out.push(BinaryToken::End(obj));
+                        projected = true;
+                    } else {
+                        out.truncate(start);
+                    }
+                }
+            }
+
+            idx = next;
+        }
+
+        projected
+    }
+
+    fn child_idx(&self, node: usize, key: &[u8]) -> Option<usize> {
+        self.nodes[node]
+            .children
+            .iter()
+            .copied()
+            .find(|&x| self.nodes[x].key == key)
+    }
+}
+
+fn text_next_idx(tokens: &[TextToken], idx: usize) -> usize {
+    match tokens[idx] {
+        TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+        TextToken::Header(_) => text_next_idx(tokens, idx + 1),
+        _ => idx + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scalar;
+    use std::collections::HashMap;
+
+    fn scalars<'a>(tape: &'a TextTape, result: &QueryResult, path: usize) -> Vec<&'a [u8]> {
+        result
+            .get(path)
+            .iter()
+            .filter_map(|&x| tape.tokens()[x].as_scalar())
+            .map(|x| x.view_data())
+            .collect()
+    }
+
+    #[test]
+    fn test_text_query() {
+        let query = Query::new(["a/b", "a/c/d", "e", "a/b", "missing/x"]);
+        let data = b"a={ b=1 c={ d=2 } b=3 } e>4 f={ b=5 } e=rgb { 1 2 3 }";
+        let tape = TextTape::from_slice(data).unwrap();
+        let result = query.execute_text(&tape);
+        assert_eq!(result.len(), 5);
+        assert_eq!(scalars(&tape, &result, 0), vec![b"1", b"3"]);
+        assert_eq!(scalars(&tape, &result, 1), vec![b"2"]);
+        assert_eq!(result.get(2).len(), 2);
+        assert_eq!(
+            tape.tokens()[result.get(2)[1]],
+            TextToken::Header(Scalar::new(b"rgb"))
+        );
+        assert_eq!(result.get(3), result.get(0));
+        assert!(result.get(4).is_empty());
+        assert!(result.get(5).is_empty());
+    }
+
+    #[test]
+    fn test_text_query_reuse() {
+        let query = Query::new(["a"]);
+        let mut result = QueryResult::new();
+        let tape = TextTape::from_slice(b"a=1 a=2").unwrap();
+        query.execute_text_into(&tape, &mut result);
+        assert_eq!(result.get(0).len(), 2);
+
+        let tape = TextTape::from_slice(b"b=1").unwrap();
+        query.execute_text_into(&tape, &mut result);
+        assert_eq!(result.len(), 1);
+        assert!(result.get(0).is_empty());
+    }
+
+    #[test]
+    fn test_binary_query() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x04,
+            0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d83, "b");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let query = Query::new(["a/b", "b"]);
+        let result = query.execute_binary(&tape, &map);
+        assert_eq!(tape.tokens()[result.get(0)[0]], BinaryToken::Bool(true));
+        assert_eq!(tape.tokens()[result.get(1)[0]], BinaryToken::Bool(false));
+    }
+
+    #[test]
+    fn test_text_projection() {
+        let data = b"a={ b=1 c={ d=2 } b=3 } e>4 f={ b=5 } e=rgb { 1 2 3 } g={ x=1 } h={ 10 y=2 }";
+        let tape = TextTape::from_slice(data).unwrap();
+        let query = Query::new(["a/b", "e", "g/missing", "h", "f/b/deeper"]);
+        let projected = query.project_text(&tape);
+        let expected =
+            TextTape::from_slice(b"a={ b=1 b=3 } e>4 e=rgb { 1 2 3 } h={ 10 y=2 }").unwrap();
+        assert_eq!(projected.tokens(), expected.tokens());
+
+        // projecting the projection is a no-op
+        let again = query.project_text(&projected);
+        assert_eq!(again.tokens(), projected.tokens());
+
+        let empty = Query::new(["missing"]).project_text(&tape);
+        assert!(empty.tokens().is_empty());
+    }
+
+    #[test]
+    fn test_binary_projection() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x04,
+            0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d83, "b");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let projected = Query::new(["a/b"]).project_binary(&tape, &map);
+        assert_eq!(
+            projected.tokens(),
+            &[
+                BinaryToken::Token(0x2d82),
+                BinaryToken::Object(4),
+                BinaryToken::Token(0x2d83),
+                BinaryToken::Bool(true),
+                BinaryToken::End(1),
+            ]
+        );
+
+        let projected = Query::new(["b"]).project_binary(&tape, &map);
+        assert_eq!(
+            projected.tokens(),
+            &[BinaryToken::Token(0x2d83), BinaryToken::Bool(false)]
+        );
+    }
+
+    #[cfg(feature = "export")]
+    #[test]
+    fn test_projection_json() {
+        use crate::{export::JsonOptions, Windows1252Encoding};
+
+        let tape = TextTape::from_slice(b"player=Alice stats={ score=10 secret=x }").unwrap();
+        let projected = Query::new(["stats/score"]).project_text(&tape);
+        let json = JsonOptions::new().text(&projected, Windows1252Encoding::new());
+        assert_eq!(json, r#"{"stats":{"score":"10"}}"#);
+    }
+}
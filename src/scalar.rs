@@ -1,4 +1,4 @@
-use crate::{ascii::is_ascii, decode_windows1252};
+use crate::{ascii::is_ascii, common::Date, decode_windows1252};
 use std::convert::TryFrom;
 use std::error;
 use std::fmt;
@@ -73,10 +73,37 @@ impl<'a> Scalar<'a> {
     /// let v2 = Scalar::new(b"-5.67821");
     /// assert_eq!(v2.to_f64(), Ok(-5.67821));
     /// ```
+    ///
+    /// Notation that the games accept but don't write themselves, like an
+    /// exponent, an explicit plus sign, or a missing leading zero, is
+    /// tolerated as it can be found in modded files:
+    ///
+    /// ```
+    /// use jomini::Scalar;
+    ///
+    /// assert_eq!(Scalar::new(b"1e5").to_f64(), Ok(100000.0));
+    /// assert_eq!(Scalar::new(b"+3").to_f64(), Ok(3.0));
+    /// assert_eq!(Scalar::new(b".5").to_f64(), Ok(0.5));
+    /// ```
     pub fn to_f64(&self) -> Result<f64, ScalarError> {
         to_f64(self.data)
     }
 
+    /// Try converting the scalar to f64 where the decimal separator may be
+    /// either a period or a comma, as some third party tools write floats
+    /// according to their locale
+    ///
+    /// ```
+    /// use jomini::Scalar;
+    ///
+    /// assert_eq!(Scalar::new(b"1,5").to_f64_comma(), Ok(1.5));
+    /// assert_eq!(Scalar::new(b"1.5").to_f64_comma(), Ok(1.5));
+    /// assert!(Scalar::new(b"1,5").to_f64().is_err());
+    /// ```
+    pub fn to_f64_comma(&self) -> Result<f64, ScalarError> {
+        to_f64_sep(self.data, b',')
+    }
+
     /// Try converting the scalar to boolean, only "yes" and "no" can be mapped:
     ///
     /// ```
@@ -92,6 +119,25 @@ impl<'a> Scalar<'a> {
         to_bool(self.data)
     }
 
+    /// Try converting the scalar to boolean, accepting "true" / "false" and
+    /// "1" / "0" in addition to "yes" and "no"
+    ///
+    /// ```
+    /// use jomini::Scalar;
+    ///
+    /// assert_eq!(Scalar::new(b"true").to_bool_lenient(), Ok(true));
+    /// assert_eq!(Scalar::new(b"0").to_bool_lenient(), Ok(false));
+    /// assert_eq!(Scalar::new(b"yes").to_bool_lenient(), Ok(true));
+    /// assert!(Scalar::new(b"true").to_bool().is_err());
+    /// ```
+    pub fn to_bool_lenient(&self) -> Result<bool, ScalarError> {
+        match self.data {
+            b"true" | b"1" => Ok(true),
+            b"false" | b"0" => Ok(false),
+            _ => to_bool(self.data),
+        }
+    }
+
     /// Try converting the scalar to i64
     ///
     /// ```
@@ -122,6 +168,36 @@ impl<'a> Scalar<'a> {
         to_u64(self.data)
     }
 
+    /// Try converting the scalar to i128 for ids that don't fit in 64 bits
+    ///
+    /// ```
+    /// use jomini::Scalar;
+    ///
+    /// let v1 = Scalar::new(b"-50");
+    /// assert_eq!(v1.to_i128(), Ok(-50));
+    ///
+    /// let v2 = Scalar::new(b"-18446744073709551616");
+    /// assert_eq!(v2.to_i128(), Ok(-18446744073709551616));
+    /// ```
+    pub fn to_i128(&self) -> Result<i128, ScalarError> {
+        to_i128(self.data)
+    }
+
+    /// Try converting the scalar to u128 for ids that don't fit in 64 bits
+    ///
+    /// ```
+    /// use jomini::Scalar;
+    ///
+    /// let v1 = Scalar::new(b"50");
+    /// assert_eq!(v1.to_u128(), Ok(50));
+    ///
+    /// let v2 = Scalar::new(b"18446744073709551616");
+    /// assert_eq!(v2.to_u128(), Ok(18446744073709551616));
+    /// ```
+    pub fn to_u128(&self) -> Result<u128, ScalarError> {
+        to_u128(self.data)
+    }
+
     /// Returns if the scalar contains only ascii values
     ///
     /// ```
@@ -136,6 +212,22 @@ impl<'a> Scalar<'a> {
     pub fn is_ascii(&self) -> bool {
         is_ascii(self.data)
     }
+
+    /// Returns if the scalar is shaped like a date (eg: `1444.11.11`). The
+    /// surrounding quotes of a quoted date are not part of the scalar, so
+    /// quoted and unquoted dates are detected alike.
+    ///
+    /// ```
+    /// use jomini::Scalar;
+    ///
+    /// assert!(Scalar::new(b"1444.11.11").is_date_like());
+    /// assert!(Scalar::new(b"1.1.1").is_date_like());
+    /// assert!(!Scalar::new(b"1444.11").is_date_like());
+    /// assert!(!Scalar::new(b"1444.13.1").is_date_like());
+    /// ```
+    pub fn is_date_like(&self) -> bool {
+        std::str::from_utf8(self.data).is_ok_and(|x| Date::parse_from_str(x).is_some())
+    }
 }
 
 impl<'a> fmt::Debug for Scalar<'a> {
@@ -165,27 +257,140 @@ fn to_bool(d: &[u8]) -> Result<bool, ScalarError> {
 
 #[inline]
 fn to_f64(d: &[u8]) -> Result<f64, ScalarError> {
-    match d.iter().position(|&x| x == b'.') {
-        Some(idx) => {
-            let lead = to_i64(&d[..idx])?;
-
-            // https://graphics.stanford.edu/~seander/bithacks.html#CopyIntegerSign
-            let sign = 1 | (lead >> (std::mem::size_of::<i64>() * 8 - 1));
-            let leadf = lead as f64;
-            let trail = &d[idx + 1..];
-            let frac = to_i64(&trail)? as f64;
-            let digits = 10u32
-                .checked_pow(trail.len() as u32)
-                .ok_or(ScalarError::Overflow)? as f64;
-            Ok((sign as f64).mul_add(frac / digits, leadf))
+    to_f64_sep(d, b'.')
+}
+
+/// Powers of ten that are exactly representable as an f64
+const POW10: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16,
+    1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+
+/// Parse a float where the given separator is accepted as the decimal
+/// separator in addition to a period.
+///
+/// The digits are validated and accumulated into an integer mantissa and a
+/// decimal exponent. When both are small enough, the float is computed with
+/// a single exactly rounded multiplication or division (Clinger's fast path),
+/// which covers nearly all numbers found in save files. Everything else
+/// (long fractions, large exponents) is handed to the standard library's
+/// correctly rounded parser, so the result is always the closest f64 to the
+/// input regardless of how many digits it has.
+#[inline]
+fn to_f64_sep(d: &[u8], sep: u8) -> Result<f64, ScalarError> {
+    let (is_negative, rest) = match d {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, d),
+    };
+
+    let mut mantissa: u64 = 0;
+    let mut exponent: i64 = 0;
+    let mut truncated = false;
+    let mut idx = 0;
+
+    let int_start = idx;
+    while let Some(digit) = rest.get(idx).filter(|x| x.is_ascii_digit()) {
+        if mantissa < 1_000_000_000_000_000_000 {
+            mantissa = mantissa * 10 + u64::from(digit - b'0');
+        } else {
+            truncated = true;
+            exponent += 1;
         }
-        None => to_i64(d).map(|x| x as f64),
+        idx += 1;
     }
+    let int_len = idx - int_start;
+
+    let mut frac_len = 0;
+    if matches!(rest.get(idx), Some(&x) if x == b'.' || x == sep) {
+        idx += 1;
+        let frac_start = idx;
+        while let Some(digit) = rest.get(idx).filter(|x| x.is_ascii_digit()) {
+            if mantissa < 1_000_000_000_000_000_000 {
+                mantissa = mantissa * 10 + u64::from(digit - b'0');
+                exponent -= 1;
+            } else {
+                truncated = true;
+            }
+            idx += 1;
+        }
+
+        // A separator needs digits after it, but a missing leading zero
+        // (`.5`) is tolerated
+        frac_len = idx - frac_start;
+        if frac_len == 0 {
+            return Err(ScalarError::AllDigits);
+        }
+    }
+
+    if int_len == 0 && frac_len == 0 {
+        return Err(ScalarError::AllDigits);
+    }
+
+    if matches!(rest.get(idx), Some(b'e') | Some(b'E')) {
+        idx += 1;
+        let exp_negative = match rest.get(idx) {
+            Some(b'-') => {
+                idx += 1;
+                true
+            }
+            Some(b'+') => {
+                idx += 1;
+                false
+            }
+            _ => false,
+        };
+
+        let exp_start = idx;
+        let mut exp: i64 = 0;
+        while let Some(digit) = rest.get(idx).filter(|x| x.is_ascii_digit()) {
+            exp = (exp * 10 + i64::from(digit - b'0')).min(100_000);
+            idx += 1;
+        }
+
+        if idx == exp_start {
+            return Err(ScalarError::AllDigits);
+        }
+
+        exponent += if exp_negative { -exp } else { exp };
+    }
+
+    if idx != rest.len() {
+        return Err(ScalarError::AllDigits);
+    }
+
+    let result = if !truncated && mantissa <= (1 << 53) && exponent.abs() < POW10.len() as i64 {
+        let m = mantissa as f64;
+        if exponent < 0 {
+            m / POW10[(-exponent) as usize]
+        } else {
+            m * POW10[exponent as usize]
+        }
+    } else {
+        to_f64_slow(rest, sep)?
+    };
+
+    if !result.is_finite() {
+        return Err(ScalarError::Overflow);
+    }
+
+    Ok(if is_negative { -result } else { result })
+}
+
+/// Parse an already validated, unsigned float with the standard library
+#[cold]
+fn to_f64_slow(d: &[u8], sep: u8) -> Result<f64, ScalarError> {
+    let normalized: Vec<u8> = d.iter().map(|&x| if x == sep { b'.' } else { x }).collect();
+
+    std::str::from_utf8(&normalized)
+        .ok()
+        .and_then(|x| x.parse::<f64>().ok())
+        .ok_or(ScalarError::AllDigits)
 }
 
 #[inline]
 fn to_i64(d: &[u8]) -> Result<i64, ScalarError> {
-    let is_negative = d.get(0).map_or(false, |&x| x == b'-');
+    let is_negative = d.first() == Some(&b'-');
     let isn = is_negative as u64;
     let sign = -((isn as i64 * 2).wrapping_sub(1));
     let rest = to_u64(&d[isn as usize..])?;
@@ -224,6 +429,82 @@ fn to_u64(d: &[u8]) -> Result<u64, ScalarError> {
     Ok(result)
 }
 
+#[inline]
+fn to_i128(d: &[u8]) -> Result<i128, ScalarError> {
+    let is_negative = d.first() == Some(&b'-');
+    let rest = to_u128(&d[is_negative as usize..])?;
+    if !is_negative {
+        i128::try_from(rest).map_err(|_| ScalarError::Overflow)
+    } else if rest <= i128::MIN.unsigned_abs() {
+        // The magnitude of i128::MIN is one more than i128::MAX, so it only
+        // fits once negated
+        Ok((rest as i128).wrapping_neg())
+    } else {
+        Err(ScalarError::Overflow)
+    }
+}
+
+/// Convert a buffer to an u128. Almost all numbers fit within 19 digits, so
+/// those are delegated to the faster 64 bit conversion. Longer numbers are
+/// converted 8 digits at a time with SWAR (SIMD within a register), as the
+/// per digit loop would need 20 or more 128 bit multiplications.
+#[inline]
+fn to_u128(d: &[u8]) -> Result<u128, ScalarError> {
+    if d.len() < 20 {
+        return to_u64(d).map(u128::from);
+    }
+
+    let mut result: u128 = 0;
+    let mut chunks = d.chunks_exact(8);
+    for chunk in &mut chunks {
+        let digits = eight_digits(chunk).ok_or(ScalarError::AllDigits)?;
+        result = result
+            .checked_mul(100_000_000)
+            .and_then(|x| x.checked_add(u128::from(digits)))
+            .ok_or(ScalarError::Overflow)?;
+    }
+
+    for digit in chunks.remainder().iter().map(|x| x.wrapping_sub(b'0')) {
+        if digit > 9 {
+            return Err(ScalarError::AllDigits);
+        }
+
+        let (new_result1, overflow1) = result.overflowing_mul(10);
+        let (new_result2, overflow2) = new_result1.overflowing_add(u128::from(digit));
+        if overflow1 | overflow2 {
+            return Err(ScalarError::Overflow);
+        }
+
+        result = new_result2;
+    }
+
+    Ok(result)
+}
+
+/// Convert 8 ascii digits to their value, or return `None` if any of the
+/// bytes isn't a digit
+#[inline]
+fn eight_digits(chunk: &[u8]) -> Option<u64> {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(chunk);
+    let val = u64::from_le_bytes(buf);
+
+    // Every byte of a digit is 0x30 to 0x39, so its high nibble is 3 and
+    // adding 6 leaves the high nibble at 3
+    let hi = val & 0xF0F0_F0F0_F0F0_F0F0;
+    let carried = (val.wrapping_add(0x0606_0606_0606_0606) & 0xF0F0_F0F0_F0F0_F0F0) >> 4;
+    if hi | carried != 0x3333_3333_3333_3333 {
+        return None;
+    }
+
+    // Combine adjacent digits into pairs, then the pairs into the final value
+    let val = val - 0x3030_3030_3030_3030;
+    let val = val.wrapping_mul(10).wrapping_add(val >> 8);
+    let lo = (val & 0x0000_00FF_0000_00FF).wrapping_mul(100 + (1_000_000 << 32));
+    let hi = ((val >> 16) & 0x0000_00FF_0000_00FF).wrapping_mul(1 + (10_000 << 32));
+    Some(lo.wrapping_add(hi) >> 32)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -271,6 +552,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn scalar_to_f64_tolerant() {
+        assert_eq!(Scalar::new(b"1e5").to_f64(), Ok(100000.0));
+        assert_eq!(Scalar::new(b"1E+2").to_f64(), Ok(100.0));
+        assert_eq!(Scalar::new(b"2.5e-1").to_f64(), Ok(0.25));
+        assert_eq!(Scalar::new(b"-1e2").to_f64(), Ok(-100.0));
+        assert_eq!(Scalar::new(b"+3").to_f64(), Ok(3.0));
+        assert_eq!(Scalar::new(b"+3.5").to_f64(), Ok(3.5));
+        assert_eq!(Scalar::new(b".5").to_f64(), Ok(0.5));
+        assert_eq!(Scalar::new(b"-.5").to_f64(), Ok(-0.5));
+        assert_eq!(Scalar::new(b"-0.5").to_f64(), Ok(-0.5));
+
+        assert!(Scalar::new(b"+-3").to_f64().is_err());
+        assert!(Scalar::new(b".").to_f64().is_err());
+        assert!(Scalar::new(b"1.-5").to_f64().is_err());
+        assert!(Scalar::new(b"e5").to_f64().is_err());
+        assert!(Scalar::new(b"1e").to_f64().is_err());
+        assert!(Scalar::new(b"1e400").to_f64().is_err());
+    }
+
+    #[test]
+    fn scalar_to_f64_comma() {
+        assert_eq!(Scalar::new(b"-1,25").to_f64_comma(), Ok(-1.25));
+        assert_eq!(Scalar::new(b",5").to_f64_comma(), Ok(0.5));
+        assert_eq!(Scalar::new(b"1,5e2").to_f64_comma(), Ok(150.0));
+        assert_eq!(Scalar::new(b"10").to_f64_comma(), Ok(10.0));
+        assert!(Scalar::new(b"1,5,5").to_f64_comma().is_err());
+        assert!(Scalar::new(b"1,5").to_f64().is_err());
+    }
+
     #[test]
     fn scalar_to_i64() {
         assert_eq!((Scalar::new(b"0").to_i64()), Ok(0));
@@ -310,6 +621,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn scalar_to_u128() {
+        assert_eq!(Scalar::new(b"0").to_u128(), Ok(0));
+        assert_eq!(
+            Scalar::new(b"9999999999999999999").to_u128(),
+            Ok(9999999999999999999)
+        );
+        assert_eq!(
+            Scalar::new(b"340282366920938463463374607431768211455").to_u128(),
+            Ok(u128::MAX)
+        );
+        assert!(Scalar::new(b"340282366920938463463374607431768211456")
+            .to_u128()
+            .is_err());
+        assert_eq!(
+            Scalar::new(b"12345678901234567890123").to_u128(),
+            Ok(12345678901234567890123)
+        );
+        assert!(Scalar::new(b"1000000000000000000000a").to_u128().is_err());
+        assert!(Scalar::new(b"1000000/0000000000000000").to_u128().is_err());
+        assert!(Scalar::new(b"10000000:0000000000000000").to_u128().is_err());
+        assert!(Scalar::new(b"").to_u128().is_err());
+    }
+
+    #[test]
+    fn scalar_to_i128() {
+        assert_eq!(Scalar::new(b"-1").to_i128(), Ok(-1));
+        assert_eq!(
+            Scalar::new(b"170141183460469231731687303715884105727").to_i128(),
+            Ok(i128::MAX)
+        );
+        assert_eq!(
+            Scalar::new(b"-170141183460469231731687303715884105727").to_i128(),
+            Ok(-i128::MAX)
+        );
+        assert_eq!(
+            Scalar::new(b"-170141183460469231731687303715884105728").to_i128(),
+            Ok(i128::MIN)
+        );
+        assert!(Scalar::new(b"170141183460469231731687303715884105728")
+            .to_i128()
+            .is_err());
+        assert!(Scalar::new(b"-170141183460469231731687303715884105729")
+            .to_i128()
+            .is_err());
+    }
+
     #[test]
     fn scalar_to_u64_overflow() {
         assert!(Scalar::new(b"888888888888888888888888888888888")
@@ -319,15 +677,53 @@ mod tests {
     }
 
     #[test]
-    fn scalar_to_f64_overflow() {
-        assert!(Scalar::new(b"9999999999.99999999999999999")
-            .to_f64()
-            .is_err());
-        assert!(Scalar::new(b"999999999999999999999.999999999")
-            .to_f64()
-            .is_err());
-        assert!(Scalar::new(b"10.99999990999999999999999").to_f64().is_err());
-        assert!(Scalar::new(b"10.99999999999999").to_f64().is_err());
+    fn scalar_to_f64_long_fractions() {
+        let inputs = [
+            "9999999999.99999999999999999",
+            "999999999999999999999.999999999",
+            "10.99999990999999999999999",
+            "10.99999999999999",
+            "0.1000000000000000055511151231257827",
+            "-123456789012345678901234567890",
+            "9007199254740993",
+            "1.7976931348623157e308",
+            "4.9e-324",
+        ];
+
+        for input in inputs.iter() {
+            assert_eq!(
+                Scalar::new(input.as_bytes()).to_f64(),
+                Ok(input.parse::<f64>().unwrap()),
+                "{}",
+                input
+            );
+        }
+
+        assert!(Scalar::new(b"1e400").to_f64().is_err());
+        assert!(Scalar::new(b"1.8e308").to_f64().is_err());
+        assert_eq!(Scalar::new(b"1e-400").to_f64(), Ok(0.0));
+    }
+
+    #[quickcheck]
+    fn to_f64_matches_std(x: f64) -> bool {
+        if !x.is_finite() {
+            return true;
+        }
+
+        let display = x.to_string();
+        let exponential = format!("{:e}", x);
+        Scalar::new(display.as_bytes()).to_f64() == Ok(x)
+            && Scalar::new(exponential.as_bytes()).to_f64() == Ok(x)
+    }
+
+    #[quickcheck]
+    fn to_i128_matches_std(x: i64, y: u64) -> bool {
+        // Spread the values across the full range so long inputs are common
+        let x = (i128::from(x) << 64) | i128::from(y);
+        let display = x.to_string();
+        Scalar::new(display.as_bytes()).to_i128() == Ok(x)
+            && Scalar::new(display.trim_start_matches('-').as_bytes()).to_u128()
+                == Ok(x.unsigned_abs())
     }
 
     #[test]
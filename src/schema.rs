@@ -0,0 +1,693 @@
+use crate::common::Date;
+use crate::{BinaryTape, BinaryToken, Scalar, TextTape, TextToken, TokenResolver};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Infers the shape of documents by walking one or more tapes.
+///
+/// Each field records the types of values it has held, whether it was
+/// missing from some of the objects that could have contained it, and
+/// whether it occurred multiple times within an object. The more documents
+/// that are added, the more accurate the schema becomes.
+///
+/// The schema can be printed as a report with one line per field path, or
+/// as Rust struct definitions with `JominiDeserialize` attributes that serve
+/// as a starting point for modeling a new game file.
+///
+/// ```
+/// use jomini::{Schema, TextTape};
+///
+/// let mut schema = Schema::new();
+/// schema.add_text(&TextTape::from_slice(b"date=1444.11.11 core=ENG core=FRA army={ size=1.5 }")?);
+/// schema.add_text(&TextTape::from_slice(b"date=1445.1.1 core=ENG")?);
+///
+/// assert_eq!(
+///     schema.report(),
+///     "date: date\ncore: string (duplicated)\narmy: object (optional)\narmy/size: float\n"
+/// );
+///
+/// let code = schema.rust_structs("Save");
+/// assert!(code.contains("#[jomini(duplicated)]\n    pub core: Vec<String>,"));
+/// assert!(code.contains("pub army: Option<Army>,"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    root: ObjectSchema,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ObjectSchema {
+    fields: Vec<FieldSchema>,
+    index: HashMap<Vec<u8>, usize>,
+
+    /// The number of objects that were observed
+    seen: usize,
+}
+
+#[derive(Debug, Clone)]
+struct FieldSchema {
+    key: Vec<u8>,
+    value: ValueSchema,
+
+    /// The number of objects that contained the field
+    present: usize,
+
+    /// If the field occurred more than once in an object
+    duplicated: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ValueSchema {
+    bool: bool,
+    integer: Option<(i64, i64)>,
+    float: bool,
+    date: bool,
+    string: bool,
+    rgb: bool,
+    empty: bool,
+    object: Option<Box<ObjectSchema>>,
+    array: Option<Box<ValueSchema>>,
+}
+
+impl Schema {
+    /// Create an empty schema
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Incorporate a text document into the schema
+    pub fn add_text(&mut self, tape: &TextTape) {
+        let tokens = tape.tokens();
+        self.root.observe_text(tokens, 0, tokens.len());
+    }
+
+    /// Incorporate a binary document into the schema, resolving token keys
+    /// with the given resolver. Unresolved keys are recorded by their hex id.
+    pub fn add_binary<RES>(&mut self, tape: &BinaryTape, resolver: &RES)
+    where
+        RES: TokenResolver,
+    {
+        let tokens = tape.tokens();
+        self.root.observe_binary(tokens, resolver, 0, tokens.len());
+    }
+
+    /// A line for each field path (keys joined with `/`) with the types that
+    /// the field has held and whether it is optional or duplicated
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        self.root.report("", &mut out);
+        out
+    }
+
+    /// Rust struct definitions that model the schema, with the given name
+    /// for the root struct. Nested objects are given a struct named after
+    /// their key, and keys that aren't valid identifiers are aliased.
+    pub fn rust_structs(&self, name: &str) -> String {
+        let mut generator = RustGenerator {
+            out: String::new(),
+            names: RESERVED_TYPES.iter().map(|x| (x.to_string(), 1)).collect(),
+            queue: Vec::new(),
+        };
+
+        let name = generator.unique_name(name);
+        generator.queue.push((name, &self.root));
+        let mut i = 0;
+        while i < generator.queue.len() {
+            let (name, object) = generator.queue[i].clone();
+            generator.write_struct(&name, object);
+            i += 1;
+        }
+
+        generator.out
+    }
+
+    /// Writes a self contained Rust module to the writer: the import of the
+    /// derive macro followed by the [`rust_structs`](Schema::rust_structs)
+    /// definitions. The output compiles as is when the `derive` feature is
+    /// enabled.
+    ///
+    /// ```
+    /// use jomini::{Schema, TextTape};
+    ///
+    /// let mut schema = Schema::new();
+    /// schema.add_text(&TextTape::from_slice(b"name=Bob age=30")?);
+    ///
+    /// let mut out = Vec::new();
+    /// schema.write_rust("Person", &mut out)?;
+    /// assert_eq!(
+    ///     String::from_utf8(out)?,
+    ///     "use jomini::JominiDeserialize;\n\n\
+    ///      #[derive(Debug, JominiDeserialize)]\n\
+    ///      pub struct Person {\n    pub name: String,\n    pub age: i32,\n}\n"
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_rust<W>(&self, name: &str, mut writer: W) -> Result<(), std::io::Error>
+    where
+        W: std::io::Write,
+    {
+        writer.write_all(b"use jomini::JominiDeserialize;\n\n")?;
+        writer.write_all(self.rust_structs(name).as_bytes())
+    }
+}
+
+/// Type names that generated structs must not shadow
+const RESERVED_TYPES: &[&str] = &[
+    "Option",
+    "Vec",
+    "String",
+    "Box",
+    "Result",
+    "Some",
+    "None",
+    "Ok",
+    "Err",
+    "Self",
+    "JominiDeserialize",
+];
+
+impl ObjectSchema {
+    fn field(&mut self, key: &[u8]) -> &mut FieldSchema {
+        let fields = &mut self.fields;
+        let idx = *self.index.entry(key.to_vec()).or_insert_with(|| {
+            fields.push(FieldSchema {
+                key: key.to_vec(),
+                value: ValueSchema::default(),
+                present: 0,
+                duplicated: false,
+            });
+            fields.len() - 1
+        });
+        &mut self.fields[idx]
+    }
+
+    /// Records how many times each key occurred in an observed object
+    fn finish(&mut self, counts: HashMap<Vec<u8>, usize>) {
+        self.seen += 1;
+        for (key, count) in counts {
+            let field = self.field(&key);
+            field.present += 1;
+            field.duplicated |= count > 1;
+        }
+    }
+
+    fn observe_text(&mut self, tokens: &[TextToken], mut idx: usize, end: usize) {
+        let mut counts = HashMap::new();
+        while idx < end {
+            let key = match tokens[idx] {
+                TextToken::Scalar(x) => x,
+
+                // a value without a key (eg: the trailer of `a={ b=c {} }`)
+                _ => {
+                    idx = text_next_idx(tokens, idx);
+                    continue;
+                }
+            };
+
+            let mut value_idx = idx + 1;
+            if let Some(TextToken::Operator(_)) = tokens.get(value_idx) {
+                value_idx += 1;
+            }
+
+            if value_idx >= end {
+                break;
+            }
+
+            *counts.entry(key.view_data().to_vec()).or_insert(0) += 1;
+            self.field(key.view_data())
+                .value
+                .observe_text(tokens, value_idx);
+            idx = text_next_idx(tokens, value_idx);
+        }
+
+        self.finish(counts);
+    }
+
+    fn observe_binary<RES>(
+        &mut self,
+        tokens: &[BinaryToken],
+        resolver: &RES,
+        mut idx: usize,
+        end: usize,
+    ) where
+        RES: TokenResolver,
+    {
+        let mut counts = HashMap::new();
+        while idx + 1 < end {
+            let value_idx = idx + 1;
+            let key = match tokens[idx] {
+                BinaryToken::Token(x) => resolver
+                    .resolve(x)
+                    .map(|x| x.as_bytes().to_vec())
+                    .unwrap_or_else(|| format!("0x{:04x}", x).into_bytes()),
+                BinaryToken::Text(x) | BinaryToken::Unquoted(x) => x.view_data().to_vec(),
+                _ => {
+                    idx = binary_next_idx(tokens, idx);
+                    continue;
+                }
+            };
+
+            self.field(&key)
+                .value
+                .observe_binary(tokens, resolver, value_idx);
+            *counts.entry(key).or_insert(0) += 1;
+            idx = binary_next_idx(tokens, value_idx);
+        }
+
+        self.finish(counts);
+    }
+
+    fn report(&self, prefix: &str, out: &mut String) {
+        for field in &self.fields {
+            let path = format!("{}{}", prefix, String::from_utf8_lossy(&field.key));
+            let _ = write!(out, "{}: {}", path, field.value.describe());
+            if field.present < self.seen {
+                out.push_str(" (optional)");
+            }
+            if field.duplicated {
+                out.push_str(" (duplicated)");
+            }
+            out.push('\n');
+
+            let mut value = &field.value;
+            while let Some(element) = &value.array {
+                value = element;
+            }
+
+            if let Some(object) = &value.object {
+                object.report(&format!("{}/", path), out);
+            }
+        }
+    }
+}
+
+impl ValueSchema {
+    fn observe_scalar(&mut self, scalar: Scalar) {
+        if let Ok(x) = scalar.to_i64() {
+            self.integer = Some(match self.integer {
+                Some((min, max)) => (min.min(x), max.max(x)),
+                None => (x, x),
+            });
+        } else if scalar.to_bool().is_ok() {
+            self.bool = true;
+        } else if scalar.to_f64().is_ok() {
+            self.float = true;
+        } else if std::str::from_utf8(scalar.view_data())
+            .is_ok_and(|x| Date::parse_from_str(x).is_some())
+        {
+            self.date = true;
+        } else {
+            self.string = true;
+        }
+    }
+
+    fn observe_text(&mut self, tokens: &[TextToken], idx: usize) {
+        match tokens[idx] {
+            TextToken::Scalar(x) => self.observe_scalar(x),
+            TextToken::Header(x) if x.view_data() == b"rgb" => self.rgb = true,
+            TextToken::Header(_) => self.observe_text(tokens, idx + 1),
+            TextToken::Object(end) | TextToken::HiddenObject(end) => self
+                .object
+                .get_or_insert_with(Default::default)
+                .observe_text(tokens, idx + 1, end),
+            TextToken::Array(end) if end == idx + 1 => self.empty = true,
+            TextToken::Array(end) => {
+                let element = self.array.get_or_insert_with(Default::default);
+                let mut i = idx + 1;
+                while i < end {
+                    element.observe_text(tokens, i);
+                    i = text_next_idx(tokens, i);
+                }
+            }
+            TextToken::Operator(_) | TextToken::End(_) => {}
+        }
+    }
+
+    fn observe_binary<RES>(&mut self, tokens: &[BinaryToken], resolver: &RES, idx: usize)
+    where
+        RES: TokenResolver,
+    {
+        match tokens[idx] {
+            BinaryToken::Bool(_) => self.bool = true,
+            BinaryToken::U32(x) => self.observe_integer(i64::from(x)),
+            BinaryToken::I32(x) if Date::from_binary_heuristic(x).is_some() => self.date = true,
+            BinaryToken::I32(x) => self.observe_integer(i64::from(x)),
+            BinaryToken::U64(x) => self.observe_integer(x.min(i64::MAX as u64) as i64),
+            BinaryToken::F32_1(_) | BinaryToken::F32_2(_) => self.float = true,
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => self.observe_scalar(x),
+            BinaryToken::Token(_) => self.string = true,
+            BinaryToken::Rgb(_) => self.rgb = true,
+            BinaryToken::Object(end) | BinaryToken::HiddenObject(end) => self
+                .object
+                .get_or_insert_with(Default::default)
+                .observe_binary(tokens, resolver, idx + 1, end),
+            BinaryToken::Array(end) if end == idx + 1 => self.empty = true,
+            BinaryToken::Array(end) => {
+                let element = self.array.get_or_insert_with(Default::default);
+                let mut i = idx + 1;
+                while i < end {
+                    element.observe_binary(tokens, resolver, i);
+                    i = binary_next_idx(tokens, i);
+                }
+            }
+            BinaryToken::End(_) => {}
+        }
+    }
+
+    fn observe_integer(&mut self, x: i64) {
+        self.integer = Some(match self.integer {
+            Some((min, max)) => (min.min(x), max.max(x)),
+            None => (x, x),
+        });
+    }
+
+    fn describe(&self) -> String {
+        let mut types = Vec::new();
+        if self.bool {
+            types.push(String::from("bool"));
+        }
+        if self.integer.is_some() {
+            types.push(String::from("integer"));
+        }
+        if self.float {
+            types.push(String::from("float"));
+        }
+        if self.date {
+            types.push(String::from("date"));
+        }
+        if self.string {
+            types.push(String::from("string"));
+        }
+        if self.rgb {
+            types.push(String::from("rgb"));
+        }
+        if self.object.is_some() {
+            types.push(String::from("object"));
+        }
+        if let Some(element) = &self.array {
+            types.push(format!("array of {}", element.describe()));
+        }
+        if types.is_empty() && self.empty {
+            types.push(String::from("empty"));
+        }
+
+        types.join(" | ")
+    }
+
+    /// The rust type of a single scalar value, if the value is a scalar
+    fn scalar_type(&self) -> Option<&'static str> {
+        let numeric = self.integer.is_some() || self.float;
+        if self.string || (self.date && numeric) || (self.bool && (numeric || self.date)) {
+            return Some("String");
+        }
+
+        if self.float {
+            Some("f64")
+        } else if let Some((min, max)) = self.integer {
+            if min >= i64::from(i32::MIN) && max <= i64::from(i32::MAX) {
+                Some("i32")
+            } else {
+                Some("i64")
+            }
+        } else if self.bool {
+            Some("bool")
+        } else if self.date {
+            Some("jomini::common::Date")
+        } else if self.rgb {
+            Some("jomini::Rgb")
+        } else {
+            None
+        }
+    }
+}
+
+struct RustGenerator<'a> {
+    out: String,
+    names: HashMap<String, usize>,
+    queue: Vec<(String, &'a ObjectSchema)>,
+}
+
+impl<'a> RustGenerator<'a> {
+    fn unique_name(&mut self, name: &str) -> String {
+        let count = self.names.entry(name.to_string()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            name.to_string()
+        } else {
+            format!("{}{}", name, count)
+        }
+    }
+
+    fn write_struct(&mut self, name: &str, object: &'a ObjectSchema) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+
+        let _ = writeln!(self.out, "#[derive(Debug, JominiDeserialize)]");
+        let _ = writeln!(self.out, "pub struct {} {{", name);
+        let mut idents = HashMap::new();
+        for field in &object.fields {
+            let key = String::from_utf8_lossy(&field.key);
+            let mut ident = field_ident(&key);
+            let count = idents.entry(ident.clone()).or_insert(0);
+            *count += 1;
+            if *count > 1 {
+                ident = format!("{}_{}", ident, count);
+            }
+
+            let ty = self.rust_type(&key, &field.value);
+
+            let mut attributes = Vec::new();
+            if ident != key {
+                attributes.push(format!("alias = {:?}", key));
+            }
+
+            let ty = if field.duplicated {
+                attributes.push(String::from("duplicated"));
+                format!("Vec<{}>", ty)
+            } else if field.present < object.seen {
+                format!("Option<{}>", ty)
+            } else {
+                ty
+            };
+
+            if !attributes.is_empty() {
+                let _ = writeln!(self.out, "    #[jomini({})]", attributes.join(", "));
+            }
+            let _ = writeln!(self.out, "    pub {}: {},", ident, ty);
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn rust_type(&mut self, key: &str, value: &'a ValueSchema) -> String {
+        if let Some(ty) = value.scalar_type() {
+            return String::from(ty);
+        }
+
+        if let Some(object) = &value.object {
+            let name = self.unique_name(&type_name(key));
+            self.queue.push((name.clone(), object));
+            return name;
+        }
+
+        match &value.array {
+            Some(element) => format!("Vec<{}>", self.rust_type(key, element)),
+            None if value.empty => String::from("Vec<String>"),
+            None => String::from("String"),
+        }
+    }
+}
+
+/// Converts a key into a snake case identifier
+fn field_ident(key: &str) -> String {
+    let mut ident = String::with_capacity(key.len());
+    for c in key.chars() {
+        if c.is_ascii_alphanumeric() {
+            ident.push(c.to_ascii_lowercase());
+        } else if !ident.ends_with('_') {
+            ident.push('_');
+        }
+    }
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert_str(0, "field_");
+    }
+
+    if is_keyword(&ident) {
+        ident.insert_str(0, "r#");
+    }
+
+    ident
+}
+
+/// Converts a key into a pascal case type name
+fn type_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    let mut upper = true;
+    for c in key.chars() {
+        if !c.is_ascii_alphanumeric() {
+            upper = true;
+        } else if upper {
+            name.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            name.push(c);
+        }
+    }
+
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, 'T');
+    }
+
+    name
+}
+
+fn is_keyword(ident: &str) -> bool {
+    matches!(
+        ident,
+        "as" | "break"
+            | "const"
+            | "continue"
+            | "crate"
+            | "else"
+            | "enum"
+            | "extern"
+            | "false"
+            | "fn"
+            | "for"
+            | "if"
+            | "impl"
+            | "in"
+            | "let"
+            | "loop"
+            | "match"
+            | "mod"
+            | "move"
+            | "mut"
+            | "pub"
+            | "ref"
+            | "return"
+            | "static"
+            | "struct"
+            | "trait"
+            | "true"
+            | "type"
+            | "unsafe"
+            | "use"
+            | "where"
+            | "while"
+            | "async"
+            | "await"
+            | "dyn"
+    )
+}
+
+fn text_next_idx(tokens: &[TextToken], idx: usize) -> usize {
+    match tokens[idx] {
+        TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+        TextToken::Header(_) => text_next_idx(tokens, idx + 1),
+        _ => idx + 1,
+    }
+}
+
+fn binary_next_idx(tokens: &[BinaryToken], idx: usize) -> usize {
+    match tokens[idx] {
+        BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => x + 1,
+        _ => idx + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_report() {
+        let mut schema = Schema::new();
+        let data = b"name=\"Bob\" gold=10 flags={ a b } color=rgb { 1 2 3 } \
+            history={ 1444.11.11={ owner=ENG } } empty={} \
+            units={ { id=1 } { id=2 speed=1.5 } } levels={ 10 0=2 }";
+        schema.add_text(&TextTape::from_slice(&data[..]).unwrap());
+        schema.add_text(&TextTape::from_slice(b"gold=-3.5 empty={ 1 }").unwrap());
+
+        assert_eq!(
+            schema.report(),
+            "name: string (optional)\n\
+             gold: integer | float\n\
+             flags: array of string (optional)\n\
+             color: rgb (optional)\n\
+             history: object (optional)\n\
+             history/1444.11.11: object\n\
+             history/1444.11.11/owner: string\n\
+             empty: array of integer\n\
+             units: array of object (optional)\n\
+             units/id: integer\n\
+             units/speed: float (optional)\n\
+             levels: array of integer | object (optional)\n\
+             levels/0: integer\n"
+        );
+    }
+
+    #[test]
+    fn test_binary_report() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x04,
+            0x00, 0x84, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x59, 0x00, 0x00, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d83, "b");
+
+        let mut schema = Schema::new();
+        schema.add_binary(&BinaryTape::from_eu4(&data[..]).unwrap(), &map);
+        assert_eq!(schema.report(), "a: object\na/b: bool\n0x2d84: integer\n");
+    }
+
+    #[test]
+    fn test_rust_structs() {
+        let mut schema = Schema::new();
+        let data = b"date=1444.11.11 type=a core=ENG core=FRA \
+            army={ name=x unit={ strength=1.0 } } navy={ unit={ ships=3 } } 1444.1.1=yes";
+        schema.add_text(&TextTape::from_slice(&data[..]).unwrap());
+
+        assert_eq!(
+            schema.rust_structs("Save"),
+            "#[derive(Debug, JominiDeserialize)]
+pub struct Save {
+    pub date: jomini::common::Date,
+    #[jomini(alias = \"type\")]
+    pub r#type: String,
+    #[jomini(duplicated)]
+    pub core: Vec<String>,
+    pub army: Army,
+    pub navy: Navy,
+    #[jomini(alias = \"1444.1.1\")]
+    pub field_1444_1_1: bool,
+}
+
+#[derive(Debug, JominiDeserialize)]
+pub struct Army {
+    pub name: String,
+    pub unit: Unit,
+}
+
+#[derive(Debug, JominiDeserialize)]
+pub struct Navy {
+    pub unit: Unit2,
+}
+
+#[derive(Debug, JominiDeserialize)]
+pub struct Unit {
+    pub strength: f64,
+}
+
+#[derive(Debug, JominiDeserialize)]
+pub struct Unit2 {
+    pub ships: i32,
+}
+"
+        );
+    }
+}
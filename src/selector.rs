@@ -0,0 +1,867 @@
+use crate::{Encoding, Error, ErrorKind, Scalar, TextTape, TextToken};
+use std::cmp::Ordering;
+
+/// A jq-like selector for ad-hoc analysis of text tapes.
+///
+/// Supported is a small subset of jq:
+///
+/// - `.` for the input, `.key` or `."key"` for a field, `.[n]` for an array
+///   element, and `.[]` to iterate the values of an object or array
+/// - `a | b` to pipe every output of `a` into `b`
+/// - comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) along with `and`, `or`
+/// - literals: numbers, strings, `true`, `false`, and `null`
+/// - functions: `select(f)`, `to_entries`, `keys`, `length`, and `not`
+///
+/// As the text format is untyped, all scalars are strings. A scalar compared
+/// against a number is parsed as a number, and a scalar compared against a
+/// boolean is parsed as `yes` or `no`. Fields are looked up by their first
+/// occurrence, and keys are returned in document order.
+///
+/// ```
+/// use jomini::{SelectedValue, Selector, TextTape, Windows1252Encoding};
+///
+/// let data = b"countries={ ENG={ treasury=1200 } FRA={ treasury=500 } }";
+/// let tape = TextTape::from_slice(data)?;
+/// let selector = Selector::parse(".countries | to_entries[] | select(.value.treasury > 1000) | .key")?;
+/// let result = selector.execute_text(&tape, Windows1252Encoding::new());
+/// assert_eq!(result, vec![SelectedValue::String(String::from("ENG"))]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    expr: Expr,
+}
+
+/// An owned value output by a [Selector]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectedValue {
+    /// A missing value
+    Null,
+
+    /// A boolean from a comparison or literal
+    Bool(bool),
+
+    /// A number from a function like `length` or a literal
+    Number(f64),
+
+    /// A scalar
+    String(String),
+
+    /// An array
+    Array(Vec<SelectedValue>),
+
+    /// An object with its fields in document order. Duplicate keys are kept.
+    Object(Vec<(String, SelectedValue)>),
+}
+
+#[cfg(feature = "derive")]
+impl serde::Serialize for SelectedValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::{SerializeMap, SerializeSeq};
+        match self {
+            SelectedValue::Null => serializer.serialize_unit(),
+            SelectedValue::Bool(x) => serializer.serialize_bool(*x),
+            SelectedValue::Number(x) => serializer.serialize_f64(*x),
+            SelectedValue::String(x) => serializer.serialize_str(x),
+            SelectedValue::Array(x) => {
+                let mut seq = serializer.serialize_seq(Some(x.len()))?;
+                for value in x {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            SelectedValue::Object(x) => {
+                let mut map = serializer.serialize_map(Some(x.len()))?;
+                for (key, value) in x {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Identity,
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, usize),
+    Iterate(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, Comparison, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Literal(Literal),
+    Select(Box<Expr>),
+    ToEntries,
+    Keys,
+    Length,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEqual,
+    GreaterThan,
+    GreaterThanEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Selector {
+    /// Parse a selector. The offset of a syntax error is the byte position
+    /// in the selector.
+    pub fn parse(selector: &str) -> Result<Selector, Error> {
+        let mut parser = SelectorParser {
+            data: selector.as_bytes(),
+            pos: 0,
+        };
+        let expr = parser.pipeline()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.data.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+
+        Ok(Selector { expr })
+    }
+
+    /// Execute the selector against a text tape, decoding scalars with the
+    /// given encoding
+    pub fn execute_text<E>(&self, tape: &TextTape, encoding: E) -> Vec<SelectedValue>
+    where
+        E: Encoding,
+    {
+        let tokens = tape.tokens();
+        let eval = Evaluator { tokens, encoding };
+        let root = Value::Object(0, tokens.len());
+        eval.eval(&self.expr, root)
+            .iter()
+            .map(|x| eval.materialize(x))
+            .collect()
+    }
+}
+
+struct SelectorParser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SelectorParser<'a> {
+    fn error(&self, msg: &str) -> Error {
+        Error::new(ErrorKind::InvalidSyntax {
+            msg: format!("selector: {}", msg),
+            offset: self.pos,
+        })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|x| x.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.data.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_whitespace();
+        if self.data[self.pos..].starts_with(s.as_bytes()) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), Error> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", s)))
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+        if self.eat(keyword)
+            && !self
+                .data
+                .get(self.pos)
+                .is_some_and(|&x| x.is_ascii_alphanumeric() || x == b'_')
+        {
+            return true;
+        }
+
+        self.pos = start;
+        false
+    }
+
+    fn pipeline(&mut self) -> Result<Expr, Error> {
+        let mut lhs = self.or()?;
+        while self.eat("|") {
+            let rhs = self.or()?;
+            lhs = Expr::Pipe(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut lhs = self.and()?;
+        while self.eat_keyword("or") {
+            let rhs = self.and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut lhs = self.comparison()?;
+        while self.eat_keyword("and") {
+            let rhs = self.comparison()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Error> {
+        let lhs = self.postfix()?;
+        let op = if self.eat("==") {
+            Comparison::Equal
+        } else if self.eat("!=") {
+            Comparison::NotEqual
+        } else if self.eat("<=") {
+            Comparison::LessThanEqual
+        } else if self.eat(">=") {
+            Comparison::GreaterThanEqual
+        } else if self.eat("<") {
+            Comparison::LessThan
+        } else if self.eat(">") {
+            Comparison::GreaterThan
+        } else {
+            return Ok(lhs);
+        };
+
+        let rhs = self.postfix()?;
+        Ok(Expr::Compare(Box::new(lhs), op, Box::new(rhs)))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.primary()?;
+        loop {
+            self.skip_whitespace();
+            match self.data.get(self.pos) {
+                Some(b'.') => {
+                    self.pos += 1;
+                    expr = self.suffix(expr)?;
+                }
+                Some(b'[') => expr = self.bracket(expr)?,
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    /// Parses what follows a `.`: a field name, a quoted field name, or a
+    /// bracket
+    fn suffix(&mut self, expr: Expr) -> Result<Expr, Error> {
+        match self.data.get(self.pos) {
+            Some(b'"') => {
+                let key = self.string()?;
+                Ok(Expr::Field(Box::new(expr), key))
+            }
+            Some(b'[') => self.bracket(expr),
+            Some(x) if is_ident_start(*x) => {
+                let key = self.ident();
+                Ok(Expr::Field(Box::new(expr), key))
+            }
+            _ => Err(self.error("expected field name")),
+        }
+    }
+
+    fn bracket(&mut self, expr: Expr) -> Result<Expr, Error> {
+        self.expect("[")?;
+        if self.eat("]") {
+            return Ok(Expr::Iterate(Box::new(expr)));
+        }
+
+        let result = match self.peek() {
+            Some(b'"') => Expr::Field(Box::new(expr), self.string()?),
+            Some(x) if x.is_ascii_digit() => {
+                let start = self.pos;
+                while self.data.get(self.pos).is_some_and(|x| x.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                let index = std::str::from_utf8(&self.data[start..self.pos])
+                    .ok()
+                    .and_then(|x| x.parse::<usize>().ok())
+                    .ok_or_else(|| self.error("invalid index"))?;
+                Expr::Index(Box::new(expr), index)
+            }
+            _ => return Err(self.error("expected index or string")),
+        };
+
+        self.expect("]")?;
+        Ok(result)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        match self.peek() {
+            Some(b'.') => {
+                self.pos += 1;
+                match self.data.get(self.pos) {
+                    Some(x) if *x == b'"' || *x == b'[' || is_ident_start(*x) => {
+                        self.suffix(Expr::Identity)
+                    }
+                    _ => Ok(Expr::Identity),
+                }
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let expr = self.pipeline()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(b'"') => Ok(Expr::Literal(Literal::String(self.string()?))),
+            Some(x) if x.is_ascii_digit() || x == b'-' => self.number(),
+            Some(x) if is_ident_start(x) => self.function(),
+            _ => Err(self.error("expected expression")),
+        }
+    }
+
+    fn function(&mut self) -> Result<Expr, Error> {
+        let start = self.pos;
+        let name = self.ident();
+        match name.as_str() {
+            "null" => Ok(Expr::Literal(Literal::Null)),
+            "true" => Ok(Expr::Literal(Literal::Bool(true))),
+            "false" => Ok(Expr::Literal(Literal::Bool(false))),
+            "to_entries" => Ok(Expr::ToEntries),
+            "keys" => Ok(Expr::Keys),
+            "length" => Ok(Expr::Length),
+            "not" => Ok(Expr::Not),
+            "select" => {
+                self.expect("(")?;
+                let expr = self.pipeline()?;
+                self.expect(")")?;
+                Ok(Expr::Select(Box::new(expr)))
+            }
+            _ => {
+                self.pos = start;
+                Err(self.error(&format!("unknown function '{}'", name)))
+            }
+        }
+    }
+
+    fn ident(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|&x| x.is_ascii_alphanumeric() || x == b'_')
+        {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.data[start..self.pos]).into_owned()
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect("\"")?;
+        let mut result = Vec::new();
+        loop {
+            match self.data.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return String::from_utf8(result).map_err(|_| self.error("invalid utf-8"));
+                }
+                Some(b'\\') if self.pos + 1 < self.data.len() => {
+                    result.push(self.data[self.pos + 1]);
+                    self.pos += 2;
+                }
+                Some(x) => {
+                    result.push(*x);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|x| x.is_ascii_digit() || *x == b'.')
+        {
+            self.pos += 1;
+        }
+
+        std::str::from_utf8(&self.data[start..self.pos])
+            .ok()
+            .and_then(|x| x.parse::<f64>().ok())
+            .map(|x| Expr::Literal(Literal::Number(x)))
+            .ok_or_else(|| {
+                self.pos = start;
+                self.error("invalid number")
+            })
+    }
+}
+
+fn is_ident_start(x: u8) -> bool {
+    x.is_ascii_alphabetic() || x == b'_'
+}
+
+/// A value that is being evaluated. Values from the tape are kept as
+/// references into the tape and are only converted into owned values when
+/// output.
+#[derive(Debug, Clone)]
+enum Value<'a> {
+    /// The start and end indices of an object's fields
+    Object(usize, usize),
+
+    /// The start and end indices of an array's elements
+    Array(usize, usize),
+    Scalar(Scalar<'a>),
+    Entry(Scalar<'a>, Box<Value<'a>>),
+    List(Vec<Value<'a>>),
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+struct Evaluator<'a, 'b, E> {
+    tokens: &'b [TextToken<'a>],
+    encoding: E,
+}
+
+impl<'a, 'b, E> Evaluator<'a, 'b, E>
+where
+    E: Encoding,
+{
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+            TextToken::Header(_) => self.next_idx(idx + 1),
+            _ => idx + 1,
+        }
+    }
+
+    fn value_at(&self, idx: usize) -> Value<'a> {
+        match self.tokens[idx] {
+            TextToken::Scalar(x) => Value::Scalar(x),
+            TextToken::Object(end) | TextToken::HiddenObject(end) => Value::Object(idx + 1, end),
+            TextToken::Array(end) => Value::Array(idx + 1, end),
+            TextToken::Header(_) => self.value_at(idx + 1),
+            TextToken::Operator(_) | TextToken::End(_) => Value::Null,
+        }
+    }
+
+    fn fields(&self, mut idx: usize, end: usize) -> Vec<(Scalar<'a>, usize)> {
+        let mut result = Vec::new();
+        while idx < end {
+            let key = match self.tokens[idx] {
+                TextToken::Scalar(x) => x,
+                _ => {
+                    idx = self.next_idx(idx);
+                    continue;
+                }
+            };
+
+            let mut value_idx = idx + 1;
+            if let Some(TextToken::Operator(_)) = self.tokens.get(value_idx) {
+                value_idx += 1;
+            }
+
+            if value_idx >= end {
+                break;
+            }
+
+            result.push((key, value_idx));
+            idx = self.next_idx(value_idx);
+        }
+        result
+    }
+
+    fn elements(&self, mut idx: usize, end: usize) -> Vec<Value<'a>> {
+        let mut result = Vec::new();
+        while idx < end {
+            result.push(self.value_at(idx));
+            idx = self.next_idx(idx);
+        }
+        result
+    }
+
+    fn decode(&self, scalar: Scalar) -> String {
+        self.encoding.decode(scalar.view_data()).into_owned()
+    }
+
+    fn field(&self, value: &Value<'a>, key: &str) -> Value<'a> {
+        match value {
+            Value::Object(start, end) => self
+                .fields(*start, *end)
+                .into_iter()
+                .find(|(k, _)| self.encoding.decode(k.view_data()) == key)
+                .map_or(Value::Null, |(_, idx)| self.value_at(idx)),
+            Value::Entry(k, _) if key == "key" => Value::Scalar(*k),
+            Value::Entry(_, v) if key == "value" => (**v).clone(),
+            _ => Value::Null,
+        }
+    }
+
+    fn values(&self, value: &Value<'a>) -> Vec<Value<'a>> {
+        match value {
+            Value::Object(start, end) => self
+                .fields(*start, *end)
+                .into_iter()
+                .map(|(_, idx)| self.value_at(idx))
+                .collect(),
+            Value::Array(start, end) => self.elements(*start, *end),
+            Value::Entry(k, v) => vec![Value::Scalar(*k), (**v).clone()],
+            Value::List(x) => x.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn eval(&self, expr: &Expr, input: Value<'a>) -> Vec<Value<'a>> {
+        match expr {
+            Expr::Identity => vec![input],
+            Expr::Field(inner, key) => self
+                .eval(inner, input)
+                .iter()
+                .map(|x| self.field(x, key))
+                .collect(),
+            Expr::Index(inner, index) => self
+                .eval(inner, input)
+                .iter()
+                .map(|x| match x {
+                    Value::Array(..) | Value::List(_) => self
+                        .values(x)
+                        .into_iter()
+                        .nth(*index)
+                        .unwrap_or(Value::Null),
+                    _ => Value::Null,
+                })
+                .collect(),
+            Expr::Iterate(inner) => self
+                .eval(inner, input)
+                .iter()
+                .flat_map(|x| self.values(x))
+                .collect(),
+            Expr::Pipe(lhs, rhs) => self
+                .eval(lhs, input)
+                .into_iter()
+                .flat_map(|x| self.eval(rhs, x))
+                .collect(),
+            Expr::Compare(lhs, op, rhs) => {
+                let lhs = self.eval(lhs, input.clone());
+                let rhs = self.eval(rhs, input);
+                let mut result = Vec::with_capacity(lhs.len() * rhs.len());
+                for a in &lhs {
+                    for b in &rhs {
+                        result.push(Value::Bool(self.compare(a, *op, b)));
+                    }
+                }
+                result
+            }
+            Expr::And(lhs, rhs) => {
+                let lhs = self.eval(lhs, input.clone());
+                let rhs = self.eval(rhs, input);
+                let result =
+                    lhs.iter().all(|x| self.truthy(x)) && rhs.iter().all(|x| self.truthy(x));
+                vec![Value::Bool(result)]
+            }
+            Expr::Or(lhs, rhs) => {
+                let lhs = self.eval(lhs, input.clone());
+                let rhs = self.eval(rhs, input);
+                let result =
+                    lhs.iter().any(|x| self.truthy(x)) || rhs.iter().any(|x| self.truthy(x));
+                vec![Value::Bool(result)]
+            }
+            Expr::Literal(x) => vec![match x {
+                Literal::Null => Value::Null,
+                Literal::Bool(x) => Value::Bool(*x),
+                Literal::Number(x) => Value::Number(*x),
+                Literal::String(x) => Value::String(x.clone()),
+            }],
+            Expr::Select(cond) => {
+                let keep = self
+                    .eval(cond, input.clone())
+                    .iter()
+                    .any(|x| self.truthy(x));
+                if keep {
+                    vec![input]
+                } else {
+                    Vec::new()
+                }
+            }
+            Expr::ToEntries => match input {
+                Value::Object(start, end) => {
+                    let entries = self
+                        .fields(start, end)
+                        .into_iter()
+                        .map(|(k, idx)| Value::Entry(k, Box::new(self.value_at(idx))))
+                        .collect();
+                    vec![Value::List(entries)]
+                }
+                _ => vec![Value::Null],
+            },
+            Expr::Keys => match input {
+                Value::Object(start, end) => {
+                    let keys = self
+                        .fields(start, end)
+                        .into_iter()
+                        .map(|(k, _)| Value::Scalar(k))
+                        .collect();
+                    vec![Value::List(keys)]
+                }
+                Value::Array(..) | Value::List(_) => {
+                    let len = self.values(&input).len();
+                    let keys = (0..len).map(|x| Value::Number(x as f64)).collect();
+                    vec![Value::List(keys)]
+                }
+                _ => vec![Value::Null],
+            },
+            Expr::Length => {
+                let len = match &input {
+                    Value::Object(start, end) => self.fields(*start, *end).len(),
+                    Value::Array(..) | Value::List(_) => self.values(&input).len(),
+                    Value::Entry(..) => 2,
+                    Value::Scalar(x) => self.decode(*x).chars().count(),
+                    Value::String(x) => x.chars().count(),
+                    Value::Number(x) => return vec![Value::Number(x.abs())],
+                    Value::Bool(_) | Value::Null => 0,
+                };
+                vec![Value::Number(len as f64)]
+            }
+            Expr::Not => vec![Value::Bool(!self.truthy(&input))],
+        }
+    }
+
+    fn truthy(&self, value: &Value) -> bool {
+        !matches!(value, Value::Null | Value::Bool(false))
+    }
+
+    fn as_f64(&self, value: &Value) -> Option<f64> {
+        match value {
+            Value::Number(x) => Some(*x),
+            Value::Scalar(x) => x.to_f64().ok(),
+            Value::String(x) => x.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self, value: &Value) -> Option<bool> {
+        match value {
+            Value::Bool(x) => Some(*x),
+            Value::Scalar(x) => x.to_bool().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_string(&self, value: &Value) -> Option<String> {
+        match value {
+            Value::Scalar(x) => Some(self.decode(*x)),
+            Value::String(x) => Some(x.clone()),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, lhs: &Value, op: Comparison, rhs: &Value) -> bool {
+        let ordering = if matches!(lhs, Value::Number(_)) || matches!(rhs, Value::Number(_)) {
+            match (self.as_f64(lhs), self.as_f64(rhs)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => None,
+            }
+        } else if matches!(lhs, Value::Bool(_)) || matches!(rhs, Value::Bool(_)) {
+            match (self.as_bool(lhs), self.as_bool(rhs)) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => None,
+            }
+        } else if matches!(lhs, Value::Null) || matches!(rhs, Value::Null) {
+            match (lhs, rhs) {
+                (Value::Null, Value::Null) => Some(Ordering::Equal),
+                _ => None,
+            }
+        } else {
+            match (self.as_string(lhs), self.as_string(rhs)) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => None,
+            }
+        };
+
+        match (op, ordering) {
+            (Comparison::Equal, x) => x == Some(Ordering::Equal),
+            (Comparison::NotEqual, x) => x != Some(Ordering::Equal),
+            (Comparison::LessThan, x) => x == Some(Ordering::Less),
+            (Comparison::LessThanEqual, x) => {
+                matches!(x, Some(Ordering::Less) | Some(Ordering::Equal))
+            }
+            (Comparison::GreaterThan, x) => x == Some(Ordering::Greater),
+            (Comparison::GreaterThanEqual, x) => {
+                matches!(x, Some(Ordering::Greater) | Some(Ordering::Equal))
+            }
+        }
+    }
+
+    fn materialize(&self, value: &Value) -> SelectedValue {
+        match value {
+            Value::Object(start, end) => SelectedValue::Object(
+                self.fields(*start, *end)
+                    .into_iter()
+                    .map(|(k, idx)| (self.decode(k), self.materialize(&self.value_at(idx))))
+                    .collect(),
+            ),
+            Value::Array(start, end) => SelectedValue::Array(
+                self.elements(*start, *end)
+                    .iter()
+                    .map(|x| self.materialize(x))
+                    .collect(),
+            ),
+            Value::Scalar(x) => SelectedValue::String(self.decode(*x)),
+            Value::Entry(k, v) => SelectedValue::Object(vec![
+                (String::from("key"), SelectedValue::String(self.decode(*k))),
+                (String::from("value"), self.materialize(v)),
+            ]),
+            Value::List(x) => SelectedValue::Array(x.iter().map(|x| self.materialize(x)).collect()),
+            Value::String(x) => SelectedValue::String(x.clone()),
+            Value::Number(x) => SelectedValue::Number(*x),
+            Value::Bool(x) => SelectedValue::Bool(*x),
+            Value::Null => SelectedValue::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Utf8Encoding;
+
+    fn select(selector: &str, data: &[u8]) -> Vec<SelectedValue> {
+        let tape = TextTape::from_slice(data).unwrap();
+        Selector::parse(selector)
+            .unwrap()
+            .execute_text(&tape, Utf8Encoding::new())
+    }
+
+    fn string(s: &str) -> SelectedValue {
+        SelectedValue::String(String::from(s))
+    }
+
+    #[test]
+    fn test_identity_and_fields() {
+        assert_eq!(
+            select(".", b"a=b"),
+            vec![SelectedValue::Object(vec![(
+                String::from("a"),
+                string("b")
+            )])]
+        );
+        assert_eq!(select(".a.b", b"a={ b=c }"), vec![string("c")]);
+        assert_eq!(select(".a | .\"b\"", b"a={ b=c }"), vec![string("c")]);
+        assert_eq!(select(".[\"a\"]", b"a=b"), vec![string("b")]);
+        assert_eq!(select(".missing.x", b"a=b"), vec![SelectedValue::Null]);
+        assert_eq!(select(".a", b"a=b a=c"), vec![string("b")]);
+    }
+
+    #[test]
+    fn test_arrays() {
+        let data = b"a={ 1 2 3 }";
+        assert_eq!(select(".a[1]", data), vec![string("2")]);
+        assert_eq!(select(".a[5]", data), vec![SelectedValue::Null]);
+        assert_eq!(
+            select(".a[]", data),
+            vec![string("1"), string("2"), string("3")]
+        );
+        assert_eq!(
+            select(".a | length", data),
+            vec![SelectedValue::Number(3.0)]
+        );
+        assert_eq!(
+            select(".a | keys", data),
+            vec![SelectedValue::Array(vec![
+                SelectedValue::Number(0.0),
+                SelectedValue::Number(1.0),
+                SelectedValue::Number(2.0),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let data = b"countries={ ENG={ treasury=1200 ai=no } FRA={ treasury=500 ai=yes } }";
+        assert_eq!(
+            select(
+                ".countries | to_entries[] | select(.value.treasury > 1000) | .key",
+                data
+            ),
+            vec![string("ENG")]
+        );
+        assert_eq!(
+            select(".countries[] | select(.ai == true) | .treasury", data),
+            vec![string("500")]
+        );
+        assert_eq!(
+            select(
+                ".countries[] | select(.treasury >= 500 and (.ai == false or .treasury < 10)) | .treasury",
+                data
+            ),
+            vec![string("1200")]
+        );
+        assert_eq!(
+            select(".countries | keys", data),
+            vec![SelectedValue::Array(vec![string("ENG"), string("FRA")])]
+        );
+        assert_eq!(
+            select(".countries.ENG.ai == \"no\" | not", data),
+            vec![SelectedValue::Bool(false)]
+        );
+    }
+
+    #[test]
+    fn test_to_entries() {
+        assert_eq!(
+            select("to_entries[0]", b"a=b"),
+            vec![SelectedValue::Object(vec![
+                (String::from("key"), string("a")),
+                (String::from("value"), string("b")),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Selector::parse(".a | unknown").unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::InvalidSyntax { offset: 5, .. }
+        ));
+
+        let err = Selector::parse(".a[").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidSyntax { .. }));
+
+        let err = Selector::parse(".a )").unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::InvalidSyntax { offset: 3, .. }
+        ));
+    }
+}
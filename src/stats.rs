@@ -0,0 +1,321 @@
+use crate::{BinaryTape, BinaryToken, TextTape, TextToken};
+use std::collections::HashMap;
+
+/// Summary statistics of a parsed tape, useful for performance tuning and for
+/// getting a feel for an unknown document.
+///
+/// ```
+/// use jomini::{TapeStats, TextTape};
+///
+/// let tape = TextTape::from_slice(b"core=ENG core=FRA a={ b=1 c={ 1 2 } }")?;
+/// let stats = TapeStats::from(&tape);
+/// assert_eq!(stats.objects(), 1);
+/// assert_eq!(stats.arrays(), 1);
+/// assert_eq!(stats.scalars(), 10);
+/// assert_eq!(stats.max_depth(), 2);
+/// assert_eq!(stats.duplicate_keys(), &[(b"core".to_vec(), 2)]);
+/// assert_eq!(stats.byte_histogram()[usize::from(b'c')], 3);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TapeStats {
+    objects: usize,
+    hidden_objects: usize,
+    arrays: usize,
+    scalars: usize,
+    max_depth: usize,
+    duplicate_keys: Vec<(Vec<u8>, usize)>,
+    byte_histogram: Box<[u64; 256]>,
+}
+
+impl TapeStats {
+    fn new() -> Self {
+        TapeStats {
+            objects: 0,
+            hidden_objects: 0,
+            arrays: 0,
+            scalars: 0,
+            max_depth: 0,
+            duplicate_keys: Vec::new(),
+            byte_histogram: Box::new([0; 256]),
+        }
+    }
+
+    /// Number of objects (excluding the root object and hidden objects)
+    pub fn objects(&self) -> usize {
+        self.objects
+    }
+
+    /// Number of hidden objects (`levels={ 10 0=2 1=2 }`)
+    pub fn hidden_objects(&self) -> usize {
+        self.hidden_objects
+    }
+
+    /// Number of arrays
+    pub fn arrays(&self) -> usize {
+        self.arrays
+    }
+
+    /// Number of scalars, which includes both keys and values
+    pub fn scalars(&self) -> usize {
+        self.scalars
+    }
+
+    /// Deepest level of nesting where the root object is at depth zero
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Keys that occur multiple times within the same object along with the
+    /// total number of occurrences, ordered by most occurrences first. Binary
+    /// tokens that are not strings are formatted as text (eg: `0x2d82`).
+    pub fn duplicate_keys(&self) -> &[(Vec<u8>, usize)] {
+        &self.duplicate_keys
+    }
+
+    /// Number of times each byte occurs in the textual scalars
+    pub fn byte_histogram(&self) -> &[u64; 256] {
+        &self.byte_histogram
+    }
+
+    fn scalar(&mut self, data: &[u8]) {
+        self.scalars += 1;
+        for &b in data {
+            self.byte_histogram[usize::from(b)] += 1;
+        }
+    }
+
+    fn finish(&mut self, duplicates: HashMap<Vec<u8>, usize>) {
+        let mut duplicates: Vec<_> = duplicates.into_iter().collect();
+        duplicates.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        self.duplicate_keys = duplicates;
+    }
+}
+
+impl<'a, 'b> From<&'b TextTape<'a>> for TapeStats {
+    fn from(tape: &'b TextTape<'a>) -> Self {
+        let mut walker = TextWalker {
+            tokens: tape.tokens(),
+            stats: TapeStats::new(),
+            duplicates: HashMap::new(),
+        };
+
+        walker.object(0, tape.tokens().len(), 0);
+        let TextWalker {
+            mut stats,
+            duplicates,
+            ..
+        } = walker;
+        stats.finish(duplicates);
+        stats
+    }
+}
+
+impl<'a, 'b> From<&'b BinaryTape<'a>> for TapeStats {
+    fn from(tape: &'b BinaryTape<'a>) -> Self {
+        let mut walker = BinaryWalker {
+            tokens: tape.tokens(),
+            stats: TapeStats::new(),
+            duplicates: HashMap::new(),
+        };
+
+        walker.object(0, tape.tokens().len(), 0);
+        let BinaryWalker {
+            mut stats,
+            duplicates,
+            ..
+        } = walker;
+        stats.finish(duplicates);
+        stats
+    }
+}
+
+struct TextWalker<'a, 'b> {
+    tokens: &'b [TextToken<'a>],
+    stats: TapeStats,
+    duplicates: HashMap<Vec<u8>, usize>,
+}
+
+impl<'a, 'b> TextWalker<'a, 'b> {
+    fn object(&mut self, mut ind: usize, end: usize, depth: usize) {
+        let mut keys: HashMap<&'a [u8], usize> = HashMap::new();
+        while ind < end {
+            let value_ind = match self.tokens.get(ind + 1) {
+                Some(TextToken::Operator(_)) => ind + 2,
+                _ => ind + 1,
+            };
+
+            if let Some(key) = self.tokens[ind].as_scalar() {
+                self.stats.scalar(key.view_data());
+                *keys.entry(key.view_data()).or_insert(0) += 1;
+            }
+
+            if value_ind >= end {
+                break;
+            }
+
+            ind = self.value(value_ind, depth);
+        }
+
+        self.record_duplicates(keys);
+    }
+
+    fn record_duplicates(&mut self, keys: HashMap<&'a [u8], usize>) {
+        for (key, count) in keys.into_iter().filter(|(_, count)| *count > 1) {
+            *self.duplicates.entry(key.to_vec()).or_insert(0) += count;
+        }
+    }
+
+    /// Walks the value and returns the index of the following token
+    fn value(&mut self, ind: usize, depth: usize) -> usize {
+        match self.tokens[ind] {
+            TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                if let TextToken::Object(_) = self.tokens[ind] {
+                    self.stats.objects += 1;
+                } else {
+                    self.stats.hidden_objects += 1;
+                }
+                self.stats.max_depth = std::cmp::max(self.stats.max_depth, depth + 1);
+                self.object(ind + 1, end, depth + 1);
+                end + 1
+            }
+            TextToken::Array(end) => {
+                self.stats.arrays += 1;
+                self.stats.max_depth = std::cmp::max(self.stats.max_depth, depth + 1);
+                let mut i = ind + 1;
+                while i < end {
+                    i = self.value(i, depth + 1);
+                }
+                end + 1
+            }
+            TextToken::Scalar(x) => {
+                self.stats.scalar(x.view_data());
+                ind + 1
+            }
+            TextToken::Header(x) => {
+                self.stats.scalar(x.view_data());
+                self.value(ind + 1, depth)
+            }
+            TextToken::Operator(_) | TextToken::End(_) => ind + 1,
+        }
+    }
+}
+
+struct BinaryWalker<'a, 'b> {
+    tokens: &'b [BinaryToken<'a>],
+    stats: TapeStats,
+    duplicates: HashMap<Vec<u8>, usize>,
+}
+
+impl<'a, 'b> BinaryWalker<'a, 'b> {
+    fn object(&mut self, mut ind: usize, end: usize, depth: usize) {
+        let mut keys: HashMap<Vec<u8>, usize> = HashMap::new();
+        while ind < end {
+            self.scalar(&self.tokens[ind]);
+            *keys.entry(binary_key(&self.tokens[ind])).or_insert(0) += 1;
+            if ind + 1 >= end {
+                break;
+            }
+
+            ind = self.value(ind + 1, depth);
+        }
+
+        for (key, count) in keys.into_iter().filter(|(_, count)| *count > 1) {
+            *self.duplicates.entry(key).or_insert(0) += count;
+        }
+    }
+
+    fn scalar(&mut self, token: &BinaryToken<'a>) {
+        match token {
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => self.stats.scalar(x.view_data()),
+            _ => self.stats.scalar(&[]),
+        }
+    }
+
+    /// Walks the value and returns the index of the following token
+    fn value(&mut self, ind: usize, depth: usize) -> usize {
+        match self.tokens[ind] {
+            BinaryToken::Object(end) | BinaryToken::HiddenObject(end) => {
+                if let BinaryToken::Object(_) = self.tokens[ind] {
+                    self.stats.objects += 1;
+                } else {
+                    self.stats.hidden_objects += 1;
+                }
+                self.stats.max_depth = std::cmp::max(self.stats.max_depth, depth + 1);
+                self.object(ind + 1, end, depth + 1);
+                end + 1
+            }
+            BinaryToken::Array(end) => {
+                self.stats.arrays += 1;
+                self.stats.max_depth = std::cmp::max(self.stats.max_depth, depth + 1);
+                let mut i = ind + 1;
+                while i < end {
+                    i = self.value(i, depth + 1);
+                }
+                end + 1
+            }
+            BinaryToken::End(_) => ind + 1,
+            ref x => {
+                self.scalar(x);
+                ind + 1
+            }
+        }
+    }
+}
+
+fn binary_key(token: &BinaryToken) -> Vec<u8> {
+    match token {
+        BinaryToken::Text(x) | BinaryToken::Unquoted(x) => x.view_data().to_vec(),
+        BinaryToken::Token(x) => format!("0x{:x}", x).into_bytes(),
+        BinaryToken::U32(x) => x.to_string().into_bytes(),
+        BinaryToken::U64(x) => x.to_string().into_bytes(),
+        BinaryToken::I32(x) => x.to_string().into_bytes(),
+        BinaryToken::Bool(x) => x.to_string().into_bytes(),
+        BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => x.to_string().into_bytes(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_stats_hidden_object_and_header() {
+        let tape = TextTape::from_slice(b"a={ 10 0=2 0=3 } c=rgb { 1 2 3 } d > 4").unwrap();
+        let stats = TapeStats::from(&tape);
+        assert_eq!(stats.objects(), 0);
+        assert_eq!(stats.hidden_objects(), 1);
+        assert_eq!(stats.arrays(), 2);
+        assert_eq!(stats.max_depth(), 2);
+        assert_eq!(stats.duplicate_keys(), &[(b"0".to_vec(), 2)]);
+        assert_eq!(stats.scalars(), 13);
+    }
+
+    #[test]
+    fn test_text_stats_empty() {
+        let tape = TextTape::from_slice(b"").unwrap();
+        let stats = TapeStats::from(&tape);
+        assert_eq!(stats.scalars(), 0);
+        assert_eq!(stats.max_depth(), 0);
+        assert!(stats.duplicate_keys().is_empty());
+    }
+
+    #[test]
+    fn test_binary_stats() {
+        // field1=ENG field1={ 1 } field2=yes
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x82, 0x2d, 0x01,
+            0x00, 0x03, 0x00, 0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x83, 0x2d, 0x01,
+            0x00, 0x4b, 0x28,
+        ];
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let stats = TapeStats::from(&tape);
+        assert_eq!(stats.arrays(), 1);
+        assert_eq!(stats.scalars(), 6);
+        assert_eq!(stats.max_depth(), 1);
+        assert_eq!(stats.duplicate_keys(), &[(b"0x2d82".to_vec(), 2)]);
+        assert_eq!(stats.byte_histogram()[usize::from(b'E')], 1);
+    }
+}
@@ -0,0 +1,224 @@
+//! Generators for property testing round trips of documents
+//!
+//! The [`Document`] model implements quickcheck's `Arbitrary` (including
+//! shrinking) when the `testutil` feature is enabled. [`TextDocument`] and
+//! [`BinaryDocument`] pair a generated document with its serialized form so
+//! that a property can be written against the raw data.
+//!
+//! ```
+//! use jomini::{document::Document, testutil::TextDocument, TextTape};
+//! use quickcheck::QuickCheck;
+//!
+//! fn roundtrip(doc: TextDocument) -> bool {
+//!     let tape = TextTape::from_slice(doc.data()).unwrap();
+//!     Document::from_text_tape(&tape).unwrap() == *doc.document()
+//! }
+//!
+//! QuickCheck::new().tests(50).quickcheck(roundtrip as fn(TextDocument) -> bool);
+//! ```
+use crate::document::{self, Document, Entropy, Value};
+use crate::{BinaryTape, Error, TextTape};
+use quickcheck::{Arbitrary, Gen};
+use std::convert::Infallible;
+
+struct GenEntropy<'a, G>(&'a mut G);
+
+impl<'a, G: Gen> Entropy for GenEntropy<'a, G> {
+    type Error = Infallible;
+
+    fn int_in_range(&mut self, low: usize, high: usize) -> Result<usize, Infallible> {
+        let range = (high - low) as u64 + 1;
+        Ok(low + (self.0.next_u64() % range) as usize)
+    }
+}
+
+fn infallible<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(x) => x,
+        Err(e) => match e {},
+    }
+}
+
+impl Arbitrary for Value {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        infallible(document::generate_value(&mut GenEntropy(g), 1))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(shrink_value(self).into_iter())
+    }
+}
+
+impl Arbitrary for Document {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        infallible(document::generate_document(&mut GenEntropy(g)))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let shrunk = shrink_fields(self.fields(), 0);
+        Box::new(shrunk.into_iter().map(Document::new))
+    }
+}
+
+/// Simpler variants of the fields: with a field removed or with a value
+/// simplified. At least `min` fields are kept.
+fn shrink_fields(fields: &[(Vec<u8>, Value)], min: usize) -> Vec<Vec<(Vec<u8>, Value)>> {
+    let mut result = Vec::new();
+    if fields.len() > min {
+        for i in 0..fields.len() {
+            let mut shrunk = fields.to_vec();
+            shrunk.remove(i);
+            result.push(shrunk);
+        }
+    }
+
+    for (i, (_, value)) in fields.iter().enumerate() {
+        for value in shrink_value(value) {
+            let mut shrunk = fields.to_vec();
+            shrunk[i].1 = value;
+            result.push(shrunk);
+        }
+    }
+
+    result
+}
+
+fn shrink_value(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Scalar(x) if x.is_empty() => Vec::new(),
+        Value::Scalar(_) => vec![Value::Scalar(Vec::new())],
+        Value::Array(values) => {
+            // Removing elements maintains that scalars are not followed by
+            // containers
+            let mut result = vec![Value::Scalar(Vec::new())];
+            for i in 0..values.len() {
+                let mut shrunk = values.clone();
+                shrunk.remove(i);
+                result.push(Value::Array(shrunk));
+            }
+            result
+        }
+        Value::Object(fields) => {
+            let mut result = vec![Value::Scalar(Vec::new())];
+            result.extend(shrink_fields(fields, 1).into_iter().map(Value::Object));
+            result
+        }
+    }
+}
+
+/// A generated document along with its plaintext representation
+#[derive(Debug, Clone)]
+pub struct TextDocument {
+    document: Document,
+    data: Vec<u8>,
+}
+
+impl TextDocument {
+    /// The generated document
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The document written as plaintext
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl From<Document> for TextDocument {
+    fn from(document: Document) -> Self {
+        let data = document.to_text();
+        TextDocument { document, data }
+    }
+}
+
+impl Arbitrary for TextDocument {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        TextDocument::from(Document::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.document.shrink().map(TextDocument::from))
+    }
+}
+
+/// A generated document along with its binary representation. The data does
+/// not require a token resolver.
+#[derive(Debug, Clone)]
+pub struct BinaryDocument {
+    document: Document,
+    data: Vec<u8>,
+}
+
+impl BinaryDocument {
+    /// The generated document
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The document written in the binary format
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl From<Document> for BinaryDocument {
+    fn from(document: Document) -> Self {
+        let data = document.to_binary();
+        BinaryDocument { document, data }
+    }
+}
+
+impl Arbitrary for BinaryDocument {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        BinaryDocument::from(Document::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.document.shrink().map(BinaryDocument::from))
+    }
+}
+
+/// Write the document as plaintext and read it back
+pub fn text_roundtrip(document: &Document) -> Result<Document, Error> {
+    let data = document.to_text();
+    let tape = TextTape::from_slice(&data)?;
+    Document::from_text_tape(&tape)
+}
+
+/// Write the document in the binary format and read it back
+pub fn binary_roundtrip(document: &Document) -> Result<Document, Error> {
+    let data = document.to_binary();
+    let tape = BinaryTape::from_eu4(&data)?;
+    Document::from_binary_tape(&tape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    #[quickcheck]
+    fn text_roundtrip_prop(doc: TextDocument) -> bool {
+        text_roundtrip(doc.document()).unwrap() == *doc.document()
+    }
+
+    #[quickcheck]
+    fn binary_roundtrip_prop(doc: BinaryDocument) -> bool {
+        binary_roundtrip(doc.document()).unwrap() == *doc.document()
+    }
+
+    #[test]
+    fn test_shrink_keeps_objects_non_empty() {
+        let doc = Document::new(vec![(
+            b"a".to_vec(),
+            Value::Object(vec![(b"b".to_vec(), Value::Scalar(b"c".to_vec()))]),
+        )]);
+
+        for shrunk in doc.shrink() {
+            assert_eq!(text_roundtrip(&shrunk).unwrap(), shrunk);
+        }
+
+        assert_eq!(doc.shrink().count(), 3);
+    }
+}
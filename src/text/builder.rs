@@ -0,0 +1,142 @@
+use crate::{Operator, Scalar, TextTape, TextToken};
+
+/// Builds a text tape by hand, so that code that consumes tapes (readers,
+/// deserializers, etc) can be tested without writing out text fixtures.
+///
+/// The tokens are written in the order that they appear in the text, so the
+/// keys and values of an object alternate. The indices that objects and
+/// arrays store are filled in as each one is finished, so the built tape is
+/// always structurally valid.
+///
+/// ```
+/// use jomini::{Operator, TapeBuilder, TextTape};
+///
+/// let tape = TapeBuilder::new()
+///     .scalar("name")
+///     .scalar("Jean")
+///     .scalar("age")
+///     .operator(Operator::GreaterThan)
+///     .scalar("16")
+///     .scalar("stats")
+///     .object(|b| b.scalar("diplomacy").scalar("5"))
+///     .scalar("traits")
+///     .array(|b| b.scalar("brave").scalar("just"))
+///     .scalar("color")
+///     .header("rgb", |b| b.scalar("10").scalar("20").scalar("30"))
+///     .build();
+///
+/// let data = b"name=Jean age>16 stats={ diplomacy=5 } traits={ brave just } color=rgb { 10 20 30 }";
+/// assert_eq!(tape.tokens(), TextTape::from_slice(data)?.tokens());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct TapeBuilder<'a> {
+    tokens: Vec<TextToken<'a>>,
+}
+
+impl<'a> TapeBuilder<'a> {
+    /// A builder of an empty tape
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write a scalar, which is either a key or a value
+    pub fn scalar<S>(mut self, data: &'a S) -> Self
+    where
+        S: AsRef<[u8]> + ?Sized,
+    {
+        self.tokens
+            .push(TextToken::Scalar(Scalar::new(data.as_ref())));
+        self
+    }
+
+    /// Write an operator that separates a key from its value (eg: `>` in
+    /// `age > 16`). The default `=` is not written to the tape.
+    pub fn operator(mut self, op: Operator) -> Self {
+        self.tokens.push(TextToken::Operator(op));
+        self
+    }
+
+    /// Write an object whose keys and values are written by the closure
+    pub fn object<F>(self, f: F) -> Self
+    where
+        F: FnOnce(Self) -> Self,
+    {
+        self.container(TextToken::Object, f)
+    }
+
+    /// Write an array whose values are written by the closure
+    pub fn array<F>(self, f: F) -> Self
+    where
+        F: FnOnce(Self) -> Self,
+    {
+        self.container(TextToken::Array, f)
+    }
+
+    /// Write a header (eg: `rgb` in `color = rgb { 10 20 30 }`) followed by
+    /// the array of values that are written by the closure
+    pub fn header<S, F>(mut self, name: &'a S, f: F) -> Self
+    where
+        S: AsRef<[u8]> + ?Sized,
+        F: FnOnce(Self) -> Self,
+    {
+        self.tokens
+            .push(TextToken::Header(Scalar::new(name.as_ref())));
+        self.array(f)
+    }
+
+    /// Finish the tape
+    pub fn build(self) -> TextTape<'a> {
+        TextTape::from_tokens(self.tokens)
+    }
+
+    fn container<F>(mut self, open: fn(usize) -> TextToken<'a>, f: F) -> Self
+    where
+        F: FnOnce(Self) -> Self,
+    {
+        let start = self.tokens.len();
+        self.tokens.push(open(0));
+        let mut result = f(self);
+        let end = result.tokens.len();
+        result.tokens[start] = open(end);
+        result.tokens.push(TextToken::End(start));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_nested_containers() {
+        let tape = TapeBuilder::new()
+            .scalar("a")
+            .array(|b| {
+                b.object(|b| b.scalar("b").array(|b| b.scalar("1")))
+                    .object(|b| b.scalar("c").scalar("d"))
+            })
+            .scalar(&b"e"[..])
+            .scalar("f")
+            .build();
+
+        let expected = TextTape::from_slice(b"a={ { b={ 1 } } { c=d } } e=f").unwrap();
+        assert_eq!(tape.tokens(), expected.tokens());
+        tape.validate().unwrap();
+    }
+
+    #[test]
+    fn test_build_empty() {
+        assert!(TapeBuilder::new().build().tokens().is_empty());
+
+        let tape = TapeBuilder::new().scalar("a").array(|b| b).build();
+        assert_eq!(
+            tape.tokens(),
+            &[
+                TextToken::Scalar(Scalar::new(b"a")),
+                TextToken::Array(2),
+                TextToken::End(1),
+            ]
+        );
+    }
+}
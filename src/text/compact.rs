@@ -0,0 +1,168 @@
+use crate::{Operator, Scalar, TextTape, TextToken};
+use std::convert::TryFrom;
+
+const TAG_SHIFT: u32 = 61;
+const LEN_SHIFT: u32 = 32;
+const LEN_MASK: u64 = (1 << (TAG_SHIFT - LEN_SHIFT)) - 1;
+
+const ARRAY: u64 = 0;
+const OBJECT: u64 = 1;
+const HIDDEN_OBJECT: u64 = 2;
+const SCALAR: u64 = 3;
+const OPERATOR: u64 = 4;
+const END: u64 = 5;
+const HEADER: u64 = 6;
+
+/// A text tape that stores each token in 8 bytes instead of the size of a
+/// `TextToken` (24 bytes on 64 bit platforms).
+///
+/// Scalars are stored as an offset and length into the data that was parsed
+/// rather than as a slice, so on 64 bit platforms a compact tape is a third
+/// of the size of the tape it is created from, which adds up when a save has
+/// tens of millions of tokens. Tokens are expanded back into a `TextToken`
+/// when read.
+///
+/// The limits of the encoding are data of up to 4 GiB and scalars of up to
+/// 512 MiB, and the tape must have been parsed from a single slice. Tapes
+/// that break these limits can't be compacted.
+///
+/// ```
+/// use jomini::{CompactTextTape, Scalar, TextTape, TextToken};
+///
+/// let data = b"a={ b=c }";
+/// let tape = TextTape::from_slice(&data[..])?;
+/// let compact = CompactTextTape::new(&tape).unwrap();
+/// assert_eq!(compact.len(), tape.tokens().len());
+/// assert_eq!(compact.get(3), Some(TextToken::Scalar(Scalar::new(b"c"))));
+/// assert!(compact.tokens().eq(tape.tokens().iter().cloned()));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactTextTape<'a> {
+    data: &'a [u8],
+    tokens: Vec<u64>,
+}
+
+impl<'a> CompactTextTape<'a> {
+    /// Compact the tape. Returns `None` if the tape doesn't fit within the
+    /// limits of the encoding.
+    pub fn new(tape: &TextTape<'a>) -> Option<Self> {
+        let data = tape.source();
+        if u32::try_from(data.len()).is_err() {
+            return None;
+        }
+
+        let tokens = tape
+            .tokens()
+            .iter()
+            .map(|token| match *token {
+                TextToken::Array(x) => index(ARRAY, x),
+                TextToken::Object(x) => index(OBJECT, x),
+                TextToken::HiddenObject(x) => index(HIDDEN_OBJECT, x),
+                TextToken::End(x) => index(END, x),
+                TextToken::Operator(x) => Some(OPERATOR << TAG_SHIFT | operator_id(x)),
+                TextToken::Scalar(x) => scalar(SCALAR, data, x),
+                TextToken::Header(x) => scalar(HEADER, data, x),
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(CompactTextTape { data, tokens })
+    }
+
+    /// The number of tokens
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Returns true if there are no tokens
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Expand the token at the given index
+    pub fn get(&self, idx: usize) -> Option<TextToken<'a>> {
+        self.tokens.get(idx).map(|&x| self.expand(x))
+    }
+
+    /// Iterate over the expanded tokens
+    pub fn tokens(&self) -> impl Iterator<Item = TextToken<'a>> + '_ {
+        self.tokens.iter().map(move |&x| self.expand(x))
+    }
+
+    /// Expand the compact tape back into a regular tape
+    pub fn to_tape(&self) -> TextTape<'a> {
+        TextTape::from_parts(self.tokens().collect(), self.data)
+    }
+
+    fn expand(&self, token: u64) -> TextToken<'a> {
+        let low = (token & u64::from(u32::MAX)) as usize;
+        match token >> TAG_SHIFT {
+            ARRAY => TextToken::Array(low),
+            OBJECT => TextToken::Object(low),
+            HIDDEN_OBJECT => TextToken::HiddenObject(low),
+            END => TextToken::End(low),
+            OPERATOR => TextToken::Operator(match low {
+                0 => Operator::LessThan,
+                1 => Operator::LessThanEqual,
+                2 => Operator::GreaterThan,
+                _ => Operator::GreaterThanEqual,
+            }),
+            tag => {
+                let len = ((token >> LEN_SHIFT) & LEN_MASK) as usize;
+                let scalar = Scalar::new(&self.data[low..low + len]);
+                if tag == HEADER {
+                    TextToken::Header(scalar)
+                } else {
+                    TextToken::Scalar(scalar)
+                }
+            }
+        }
+    }
+}
+
+fn operator_id(op: Operator) -> u64 {
+    match op {
+        Operator::LessThan => 0,
+        Operator::LessThanEqual => 1,
+        Operator::GreaterThan => 2,
+        Operator::GreaterThanEqual => 3,
+    }
+}
+
+fn index(tag: u64, idx: usize) -> Option<u64> {
+    let idx = u32::try_from(idx).ok()?;
+    Some(tag << TAG_SHIFT | u64::from(idx))
+}
+
+fn scalar(tag: u64, data: &[u8], scalar: Scalar) -> Option<u64> {
+    let view = scalar.view_data();
+    let offset = (view.as_ptr() as usize).checked_sub(data.as_ptr() as usize)?;
+    if offset + view.len() > data.len() || view.len() as u64 > LEN_MASK {
+        return None;
+    }
+
+    Some(tag << TAG_SHIFT | (view.len() as u64) << LEN_SHIFT | offset as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_roundtrip() {
+        let data = b"a={ 10 b=c } d>=1 e<2 color=rgb { 1 2 3 } f={ { g=h } } i=\"j k\"";
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+        let compact = CompactTextTape::new(&tape).unwrap();
+        assert_eq!(compact.to_tape().tokens(), tape.tokens());
+        assert!(compact.get(compact.len()).is_none());
+    }
+
+    #[test]
+    fn test_compact_requires_single_slice() {
+        let tape = TextTape::from_slices([&b"a=b"[..], &b"c=d"[..]].iter().copied()).unwrap();
+        assert!(CompactTextTape::new(&tape).is_none());
+
+        let tape = TextTape::from_slice(b"").unwrap();
+        assert!(CompactTextTape::new(&tape).unwrap().is_empty());
+    }
+}
@@ -1,10 +1,42 @@
+use crate::de::{
+    duplicate_key_error, tagged_struct, visit_integer, DuplicatedValues, IntegerType, KeyValuePair,
+};
+use crate::trace::span;
 use crate::{
-    ArrayReader, DeserializeError, DeserializeErrorKind, Encoding, Error, ObjectReader, Reader,
-    TextTape, TextToken, Utf8Encoding, ValueReader, Windows1252Encoding,
+    ArrayReader, DeserializeError, DeserializeErrorKind, Diagnostic, Diagnostics,
+    DuplicateKeyStrategy, Encoding, Error, IntegerOverflow, ObjectReader, Reader, Scalar,
+    ScalarReader, TextTape, TextToken, Utf8Encoding, ValueReader, Windows1252Encoding,
 };
 use serde::de::{self, Deserialize, DeserializeSeed, Visitor};
 use std::borrow::Cow;
 
+/// A type that knows how the quoted strings of its text data are encoded, so
+/// that it can be deserialized with `TextDeserializer::from_slice` without
+/// naming the encoding at every call site.
+///
+/// Instead of implementing this trait by hand, annotate a
+/// `JominiDeserialize` struct with `#[jomini(encoding = "utf8")]` or
+/// `#[jomini(encoding = "windows1252")]`.
+///
+/// ```
+/// use jomini::{JominiDeserialize, TextDeserializer};
+///
+/// #[derive(JominiDeserialize, Debug, PartialEq)]
+/// #[jomini(encoding = "utf8")]
+/// struct Character {
+///     name: String,
+/// }
+///
+/// let data = "name=\"Jérôme\"";
+/// let actual: Character = TextDeserializer::from_slice(data.as_bytes())?;
+/// assert_eq!(actual, Character { name: String::from("Jérôme") });
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait TextEncoded {
+    /// The encoding of the quoted strings in the text data
+    type Encoding: Encoding + Clone + Default;
+}
+
 /// A structure to deserialize text data into Rust values.
 ///
 /// By default, if a token is unable to be resolved then it will be ignored by the default.
@@ -55,13 +87,17 @@ use std::borrow::Cow;
 pub struct TextDeserializer;
 
 impl TextDeserializer {
+    /// Create a builder to tweak how the data is deserialized
+    pub fn builder() -> TextDeserializerBuilder {
+        TextDeserializerBuilder::new()
+    }
+
     /// Convenience method for parsing the given text data and deserializing as windows1252 encoded.
     pub fn from_windows1252_slice<'a, T>(data: &'a [u8]) -> Result<T, Error>
     where
         T: Deserialize<'a>,
     {
-        let tape = TextTape::from_slice(data)?;
-        Ok(TextDeserializer::from_windows1252_tape(&tape)?)
+        Self::builder().from_windows1252_slice(data)
     }
 
     /// Deserialize the given text tape assuming quoted strings are windows1252 encoded.
@@ -69,7 +105,7 @@ impl TextDeserializer {
     where
         T: Deserialize<'a>,
     {
-        Self::from_encoded_tape(tape, Windows1252Encoding::new())
+        Self::builder().from_windows1252_tape(tape)
     }
 
     /// Convenience method for parsing the given text data and deserializing as utf8 encoded.
@@ -77,8 +113,7 @@ impl TextDeserializer {
     where
         T: Deserialize<'a>,
     {
-        let tape = TextTape::from_slice(data)?;
-        Ok(TextDeserializer::from_utf8_tape(&tape)?)
+        Self::builder().from_utf8_slice(data)
     }
 
     /// Deserialize the given text tape assuming quoted strings are utf8 encoded.
@@ -86,12 +121,341 @@ impl TextDeserializer {
     where
         T: Deserialize<'a>,
     {
-        Self::from_encoded_tape(tape, Utf8Encoding::new())
+        Self::builder().from_utf8_tape(tape)
+    }
+
+    /// Deserialize the given text tape assuming quoted strings can be decoded
+    /// according to the given encoder
+    pub fn from_encoded_tape<'b, 'a: 'b, T, E>(
+        tape: &'b TextTape<'a>,
+        encoding: E,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        E: Encoding + Clone,
+    {
+        Self::builder().from_encoded_tape(tape, encoding)
+    }
+
+    /// Convenience method for parsing the given text data and deserializing
+    /// with the given encoding. Generic code can accept the encoding as a
+    /// value instead of choosing between the differently named methods.
+    ///
+    /// ```
+    /// use jomini::{Encoding, TextDeserializer, Utf8Encoding, Windows1252Encoding};
+    /// use std::collections::HashMap;
+    ///
+    /// fn name<E: Encoding + Clone>(data: &[u8], encoding: E) -> String {
+    ///     let map: HashMap<String, String> = TextDeserializer::from_slice_with(data, encoding).unwrap();
+    ///     map["name"].clone()
+    /// }
+    ///
+    /// assert_eq!(name(b"name=\"J\xe9r\xf4me\"", Windows1252Encoding::new()), "Jérôme");
+    /// assert_eq!(name("name=\"Jérôme\"".as_bytes(), Utf8Encoding::new()), "Jérôme");
+    /// ```
+    pub fn from_slice_with<'a, T, E>(data: &'a [u8], encoding: E) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        E: Encoding + Clone,
+    {
+        Self::builder().from_slice_with(data, encoding)
+    }
+
+    /// Convenience method for parsing the given text data and deserializing
+    /// with the encoding declared by the type. See [TextEncoded].
+    pub fn from_slice<'a, T>(data: &'a [u8]) -> Result<T, Error>
+    where
+        T: Deserialize<'a> + TextEncoded,
+    {
+        Self::builder().from_slice(data)
+    }
+
+    /// Deserialize the given text tape with the encoding declared by the
+    /// type. See [TextEncoded].
+    pub fn from_tape<'a, 'b, T>(tape: &'b TextTape<'a>) -> Result<T, Error>
+    where
+        T: Deserialize<'a> + TextEncoded,
+    {
+        Self::builder().from_tape(tape)
+    }
+
+    /// Deserialize an object that was previously located with the mid-level
+    /// readers. This allows a tape to be inspected ad hoc and then have only
+    /// the interesting portions deserialized, without parsing the data again.
+    ///
+    /// ```
+    /// use jomini::{TextDeserializer, TextTape};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Player {
+    ///     name: String,
+    ///     score: u32,
+    /// }
+    ///
+    /// let tape = TextTape::from_slice(b"version=2 player={ name=Bob score=10 }")?;
+    /// let mut reader = tape.windows1252_reader();
+    /// while let Some((key, _op, value)) = reader.next_field() {
+    ///     if key.read_str() == "player" {
+    ///         let player: Player = TextDeserializer::from_reader(value.read_object()?)?;
+    ///         assert_eq!(player, Player { name: String::from("Bob"), score: 10 });
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_reader<'a, 'tokens, T, E>(reader: ObjectReader<'a, 'tokens, E>) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        E: Encoding + Clone,
+    {
+        Self::builder().from_reader(reader)
+    }
+}
+
+/// Build a tweaked text deserializer
+///
+/// ```
+/// use jomini::{DuplicateKeyStrategy, TextDeserializer};
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// struct Country {
+///     capital: u32,
+///     core: Vec<String>,
+/// }
+///
+/// let data = b"capital=1 core=ENG capital=236 core=NOR";
+/// let country: Country = TextDeserializer::builder()
+///     .on_duplicate_key(DuplicateKeyStrategy::Collect)
+///     .from_windows1252_slice(&data[..])?;
+/// assert_eq!(country, Country { capital: 236, core: vec![String::from("ENG"), String::from("NOR")] });
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TextDeserializerBuilder {
+    duplicate_key_strategy: DuplicateKeyStrategy,
+    options: ValueOptions,
+    lossy: bool,
+    diagnostics: Option<Diagnostics>,
+}
+
+impl Default for TextDeserializerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextDeserializerBuilder {
+    /// Create a new builder instance
+    pub fn new() -> Self {
+        TextDeserializerBuilder {
+            duplicate_key_strategy: DuplicateKeyStrategy::Preserve,
+            options: ValueOptions::default(),
+            lossy: false,
+            diagnostics: None,
+        }
+    }
+
+    /// Set the behavior when a key occurs multiple times within an object
+    pub fn on_duplicate_key(&mut self, strategy: DuplicateKeyStrategy) -> &mut Self {
+        self.duplicate_key_strategy = strategy;
+        self
+    }
+
+    /// Accept a comma as the decimal separator of floats (`1,5`), which some
+    /// third party tools write. See `Scalar::to_f64_comma`.
+    pub fn comma_decimals(&mut self, allow: bool) -> &mut Self {
+        self.options.comma_decimals = allow;
+        self
+    }
+
+    /// Accept `true` / `false` and `1` / `0` as booleans in addition to `yes`
+    /// and `no`, as some files and older games write flags that way. See
+    /// `Scalar::to_bool_lenient`.
+    pub fn lenient_bools(&mut self, allow: bool) -> &mut Self {
+        self.options.lenient_bools = allow;
+        self
+    }
+
+    /// Accept quoted strings (`"yes"`) as booleans. Defaults to false, so a
+    /// quoted string is only ever deserialized as a string and deserializing
+    /// it as a boolean is an error. Unquoted `yes` and `no` can always be
+    /// deserialized as either a boolean or a string.
+    ///
+    /// Quotes can only be detected when deserializing from a tape or slice,
+    /// a deserializer created from a reader treats every scalar as unquoted.
+    ///
+    /// ```
+    /// use jomini::TextDeserializer;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Player {
+    ///     human: bool,
+    /// }
+    ///
+    /// let data = br#"human = "yes""#;
+    /// assert!(TextDeserializer::from_windows1252_slice::<Player>(&data[..]).is_err());
+    ///
+    /// let player: Player = TextDeserializer::builder()
+    ///     .quoted_bools(true)
+    ///     .from_windows1252_slice(&data[..])?;
+    /// assert!(player.human);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn quoted_bools(&mut self, allow: bool) -> &mut Self {
+        self.options.quoted_bools = allow;
+        self
+    }
+
+    /// Deserialize an empty block (`field = {}`) into an `Option` as `None`
+    /// instead of `Some` of an empty value. Defaults to false.
+    ///
+    /// How a field maps onto sequences and optional values:
+    ///
+    /// | data              | `Vec<T>`                  | `Option<Vec<T>>`  | `Option<T>`                  |
+    /// |-------------------|---------------------------|-------------------|------------------------------|
+    /// | absent            | missing field<sup>1</sup> | `None`            | `None`                       |
+    /// | `field = {}`      | empty                     | `Some` of empty   | `Some` of an empty object<sup>2</sup> |
+    /// | `field = { 1 2 }` | elements                  | `Some` of elements | `Some`                      |
+    ///
+    /// 1. Unless the field has a default or is `duplicated`, which results in
+    ///    an empty vector
+    /// 2. Which fails unless every field of `T` has a default
+    ///
+    /// When enabled, both `Option` columns are `None` for an empty block. An
+    /// empty block as a `Vec<T>` is always empty.
+    ///
+    /// ```
+    /// use jomini::TextDeserializer;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Country {
+    ///     ideas: Option<Vec<String>>,
+    /// }
+    ///
+    /// let data = b"ideas = {}";
+    /// let country: Country = TextDeserializer::from_windows1252_slice(&data[..])?;
+    /// assert_eq!(country.ideas, Some(vec![]));
+    ///
+    /// let country: Country = TextDeserializer::builder()
+    ///     .empty_block_as_none(true)
+    ///     .from_windows1252_slice(&data[..])?;
+    /// assert_eq!(country.ideas, None);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn empty_block_as_none(&mut self, enabled: bool) -> &mut Self {
+        self.options.empty_block_as_none = enabled;
+        self
+    }
+
+    /// Set the behavior when an integer doesn't fit in the requested type,
+    /// which defaults to an error that names the path to the value.
+    ///
+    /// ```
+    /// use jomini::{IntegerOverflow, TextDeserializer};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct Country {
+    ///     stability: i8,
+    ///     morale: u8,
+    /// }
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct Save {
+    ///     country: Country,
+    /// }
+    ///
+    /// let data = b"country = { stability = -300 morale = 300 }";
+    /// let err = TextDeserializer::from_windows1252_slice::<Save>(&data[..]).unwrap_err();
+    /// assert!(err.to_string().contains("integer -300 does not fit in i8 at `country.stability`"));
+    ///
+    /// let save: Save = TextDeserializer::builder()
+    ///     .integer_overflow(IntegerOverflow::Saturate)
+    ///     .from_windows1252_slice(&data[..])?;
+    /// assert_eq!((save.country.stability, save.country.morale), (-128, 255));
+    ///
+    /// let save: Save = TextDeserializer::builder()
+    ///     .integer_overflow(IntegerOverflow::Wrap)
+    ///     .from_windows1252_slice(&data[..])?;
+    /// assert_eq!((save.country.stability, save.country.morale), (-44, 44));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn integer_overflow(&mut self, overflow: IntegerOverflow) -> &mut Self {
+        self.options.integer_overflow = overflow;
+        self
+    }
+
+    /// Parse slices with `TextTapeParser::parse_slice_lossy` so that
+    /// malformed sections are skipped instead of failing the whole file.
+    /// Skipped sections are recorded in the diagnostics.
+    pub fn lossy(&mut self, lossy: bool) -> &mut Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Record recoverable problems (skipped sections and strings that could
+    /// not be decoded cleanly) into the given diagnostics
+    pub fn diagnostics(&mut self, diagnostics: &Diagnostics) -> &mut Self {
+        self.diagnostics = Some(diagnostics.clone());
+        self
+    }
+
+    fn parse_slice<'a>(&self, data: &'a [u8]) -> Result<TextTape<'a>, Error> {
+        if !self.lossy {
+            return TextTape::from_slice(data);
+        }
+
+        let (tape, errors) = TextTape::parser().parse_slice_lossy(data);
+        if let Some(diagnostics) = &self.diagnostics {
+            for error in errors {
+                diagnostics.push(Diagnostic::SkippedSection(error));
+            }
+        }
+
+        Ok(tape)
+    }
+
+    /// Convenience method for parsing the given text data and deserializing as windows1252 encoded.
+    pub fn from_windows1252_slice<'a, T>(&self, data: &'a [u8]) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        let tape = self.parse_slice(data)?;
+        self.from_windows1252_tape(&tape)
+    }
+
+    /// Deserialize the given text tape assuming quoted strings are windows1252 encoded.
+    pub fn from_windows1252_tape<'a, T>(&self, tape: &TextTape<'a>) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        self.from_encoded_tape(tape, Windows1252Encoding::new())
+    }
+
+    /// Convenience method for parsing the given text data and deserializing as utf8 encoded.
+    pub fn from_utf8_slice<'a, T>(&self, data: &'a [u8]) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        let tape = self.parse_slice(data)?;
+        self.from_utf8_tape(&tape)
+    }
+
+    /// Deserialize the given text tape assuming quoted strings are utf8 encoded.
+    pub fn from_utf8_tape<'a, 'b, T>(&self, tape: &'b TextTape<'a>) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        self.from_encoded_tape(tape, Utf8Encoding::new())
     }
 
     /// Deserialize the given text tape assuming quoted strings can be decoded
     /// according to the given encoder
     pub fn from_encoded_tape<'b, 'a: 'b, T, E>(
+        &self,
         tape: &'b TextTape<'a>,
         encoding: E,
     ) -> Result<T, Error>
@@ -99,18 +463,209 @@ impl TextDeserializer {
         T: Deserialize<'a>,
         E: Encoding + Clone,
     {
-        let reader = Reader::Object(ObjectReader::new(tape, encoding));
-        let mut root = InternalDeserializer { readers: reader };
-        Ok(T::deserialize(&mut root)?)
+        let encoding = DiagnosticEncoding {
+            encoding,
+            diagnostics: self.diagnostics.as_ref(),
+        };
+        let _span = span!("deserialize_text", tokens = tape.tokens().len());
+        let mut deserializer = self.deserializer(ObjectReader::new(tape, encoding));
+        deserializer.source = tape.source();
+        Ok(T::deserialize(&mut deserializer)?)
+    }
+
+    /// Convenience method for parsing the given text data and deserializing
+    /// with the given encoding
+    pub fn from_slice_with<'a, T, E>(&self, data: &'a [u8], encoding: E) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        E: Encoding + Clone,
+    {
+        let tape = self.parse_slice(data)?;
+        self.from_encoded_tape(&tape, encoding)
+    }
+
+    /// Convenience method for parsing the given text data and deserializing
+    /// with the encoding declared by the type
+    pub fn from_slice<'a, T>(&self, data: &'a [u8]) -> Result<T, Error>
+    where
+        T: Deserialize<'a> + TextEncoded,
+    {
+        self.from_slice_with(data, T::Encoding::default())
+    }
+
+    /// Deserialize the given text tape with the encoding declared by the type
+    pub fn from_tape<'a, 'b, T>(&self, tape: &'b TextTape<'a>) -> Result<T, Error>
+    where
+        T: Deserialize<'a> + TextEncoded,
+    {
+        self.from_encoded_tape(tape, T::Encoding::default())
+    }
+
+    /// Deserialize an object that was previously located with the mid-level
+    /// readers
+    pub fn from_reader<'a, 'tokens, T, E>(
+        &self,
+        reader: ObjectReader<'a, 'tokens, E>,
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+        E: Encoding + Clone,
+    {
+        Ok(T::deserialize(&mut self.deserializer(reader))?)
+    }
+
+    /// Deserialize the fields of an object into a map, like a `HashMap` of
+    /// provinces, where the values are deserialized in parallel. Entries are
+    /// independent of each other, so large objects with thousands of entries
+    /// can be split across threads.
+    ///
+    /// Each field of the object is an entry regardless of the duplicate key
+    /// strategy, so when a key occurs more than once, collecting into a
+    /// `HashMap` keeps the last value.
+    ///
+    /// ```
+    /// use jomini::{TextDeserializer, TextTape};
+    /// use serde::Deserialize;
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Province {
+    ///     owner: String,
+    /// }
+    ///
+    /// let data = b"provinces={ 1={ owner=SWE } 2={ owner=DAN } }";
+    /// let tape = TextTape::from_slice(&data[..])?;
+    /// let (_key, _op, value) = tape.windows1252_reader().next_field().unwrap();
+    /// let provinces: HashMap<u32, Province> =
+    ///     TextDeserializer::builder().from_reader_par(value.read_object()?)?;
+    /// assert_eq!(provinces[&2], Province { owner: String::from("DAN") });
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn from_reader_par<'a, 'tokens, K, V, M, E>(
+        &self,
+        mut reader: ObjectReader<'a, 'tokens, E>,
+    ) -> Result<M, Error>
+    where
+        K: Deserialize<'a> + Send,
+        V: Deserialize<'a> + Send,
+        M: std::iter::FromIterator<(K, V)>,
+        E: Encoding + Clone + Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        let fields: Vec<_> = std::iter::from_fn(|| reader.next_field())
+            .map(|(key, _op, value)| (key, value))
+            .collect();
+
+        let entries = fields
+            .into_par_iter()
+            .map(|(key, value)| {
+                let mut de = self.deserializer_of(Reader::Scalar(key.clone()));
+                let k = K::deserialize(&mut de)?;
+                let mut de = self.deserializer_of(Reader::Value(value));
+                let v = V::deserialize(&mut de).map_err(|e| e.within(&key.read_str()))?;
+                Ok((k, v))
+            })
+            .collect::<Result<Vec<_>, DeserializeError>>()?;
+
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Create a serde deserializer of the object, so that it can be used with
+    /// crates that drive a deserializer directly, like `serde_transcode` to
+    /// convert the data into another format without an intermediate value.
+    ///
+    /// Scalars are given to serde as strings, as the text format doesn't
+    /// distinguish numbers from strings.
+    ///
+    /// ```
+    /// use jomini::{TextDeserializer, TextTape};
+    /// use serde::Deserialize;
+    /// use std::collections::HashMap;
+    ///
+    /// let tape = TextTape::from_slice(b"player=ENG")?;
+    /// let builder = TextDeserializer::builder();
+    /// let mut deserializer = builder.deserializer(tape.windows1252_reader());
+    /// let data = HashMap::<String, String>::deserialize(&mut deserializer)?;
+    /// assert_eq!(data.get("player").map(String::as_str), Some("ENG"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn deserializer<'a, 'tokens, E>(
+        &self,
+        reader: ObjectReader<'a, 'tokens, E>,
+    ) -> TextTapeDeserializer<'a, 'tokens, E>
+    where
+        E: Encoding + Clone,
+    {
+        self.deserializer_of(Reader::Object(reader))
+    }
+
+    fn deserializer_of<'a, 'tokens, E>(
+        &self,
+        readers: Reader<'a, 'tokens, E>,
+    ) -> TextTapeDeserializer<'a, 'tokens, E> {
+        TextTapeDeserializer {
+            readers,
+            duplicate_key_strategy: self.duplicate_key_strategy,
+            options: self.options,
+            source: &[],
+            scratch: String::new(),
+        }
+    }
+}
+
+/// Wraps an encoding to record strings that were decoded with replacements
+#[derive(Debug, Clone)]
+struct DiagnosticEncoding<'a, E> {
+    encoding: E,
+    diagnostics: Option<&'a Diagnostics>,
+}
+
+impl<'a, E: Encoding> Encoding for DiagnosticEncoding<'a, E> {
+    fn decode<'b>(&self, data: &'b [u8]) -> Cow<'b, str> {
+        let result = self.encoding.decode(data);
+        if let (Some(diagnostics), Cow::Owned(s)) = (self.diagnostics, &result) {
+            diagnostics.check_decode(data, s);
+        }
+        result
+    }
+
+    fn decode_scratch<'b>(&self, data: &'b [u8], scratch: &mut String) -> Option<&'b str> {
+        let result = self.encoding.decode_scratch(data, scratch);
+        if let (Some(diagnostics), None) = (self.diagnostics, result) {
+            diagnostics.check_decode(data, scratch);
+        }
+        result
     }
 }
 
+/// Opt-in relaxations of how values are interpreted
+#[derive(Debug, Clone, Copy, Default)]
+struct ValueOptions {
+    comma_decimals: bool,
+    lenient_bools: bool,
+    quoted_bools: bool,
+    empty_block_as_none: bool,
+    integer_overflow: IntegerOverflow,
+}
+
+/// A serde deserializer of text data. See `TextDeserializerBuilder::deserializer`.
 #[derive(Debug)]
-struct InternalDeserializer<'de, 'tokens, E> {
+pub struct TextTapeDeserializer<'de, 'tokens, E> {
     readers: Reader<'de, 'tokens, E>,
+    duplicate_key_strategy: DuplicateKeyStrategy,
+    options: ValueOptions,
+
+    // The data that the tape was parsed from, if known, to tell whether a
+    // scalar was quoted
+    source: &'de [u8],
+
+    // Reused across values for strings that can't be borrowed from the data
+    scratch: String,
 }
 
-impl<'de, 'tokens, E> InternalDeserializer<'de, 'tokens, E>
+impl<'de, 'tokens, E> TextTapeDeserializer<'de, 'tokens, E>
 where
     E: Clone,
 {
@@ -121,18 +676,89 @@ where
     fn reader_ref(&self) -> &Reader<'de, 'tokens, E> {
         &self.readers
     }
+
+    /// Returns true if the scalar is surrounded by quotes in the source data.
+    /// Without the source data, scalars are assumed to be unquoted.
+    fn is_quoted(&self, scalar: Scalar) -> bool {
+        let data = scalar.view_data();
+        let start = (data.as_ptr() as usize).wrapping_sub(self.source.as_ptr() as usize);
+        let end = start.wrapping_add(data.len());
+        start > 0
+            && end < self.source.len()
+            && self.source[start - 1] == b'"'
+            && self.source[end] == b'"'
+    }
 }
 
 macro_rules! visit_str {
-    ($data: expr, $visitor: expr) => {
-        match $data {
-            Cow::Borrowed(s) => $visitor.visit_borrowed_str(s),
-            Cow::Owned(s) => $visitor.visit_string(s),
+    ($self: expr, $visitor: expr) => {
+        match $self.readers.read_str_scratch(&mut $self.scratch)? {
+            Some(s) => $visitor.visit_borrowed_str(s),
+            None => $visitor.visit_str(&$self.scratch),
         }
     };
 }
 
-impl<'a, 'de, 'tokens, E> de::Deserializer<'de> for &'a mut InternalDeserializer<'de, 'tokens, E>
+impl<'de, 'tokens, E> TextTapeDeserializer<'de, 'tokens, E>
+where
+    E: Encoding + Clone,
+{
+    /// Visit the current scalar as the integer type, narrowing it according
+    /// to the integer overflow policy
+    fn visit_integer<'a, V>(
+        &self,
+        ty: IntegerType,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'a>,
+    {
+        let value = self.reader_ref().read_scalar()?.to_i128()?;
+        visit_integer(value, ty, self.options.integer_overflow, visitor)
+    }
+
+    /// Visit the current object as a map. When given a tag, the first field
+    /// with the tag as its key is visited before the other fields.
+    fn deserialize_object<V>(
+        &mut self,
+        tag: Option<&'static str>,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'de>,
+    {
+        let reader = match self.reader() {
+            Reader::Object(x) => x,
+            Reader::Value(x) => x.read_object()?,
+            _ => {
+                return Err(DeserializeError {
+                    kind: DeserializeErrorKind::Unsupported(String::from(
+                        "can only deserialize an object as a map",
+                    )),
+                })
+            }
+        };
+
+        let tagged = tag.and_then(|tag| {
+            let mut fields = reader.clone();
+            std::iter::from_fn(|| fields.next_field())
+                .find(|(key, _op, _value)| key.read_str() == tag)
+                .map(|(key, _op, value)| (key, value))
+        });
+
+        visitor.visit_map(MapAccess {
+            de: self,
+            reader,
+            value: None,
+            duplicates: Vec::new(),
+            key: None,
+            tag,
+            tagged,
+        })
+    }
+}
+
+impl<'a, 'de, 'tokens, E> de::Deserializer<'de> for &'a mut TextTapeDeserializer<'de, 'tokens, E>
 where
     E: Encoding + Clone,
 {
@@ -143,9 +769,9 @@ where
         V: Visitor<'de>,
     {
         match &self.readers {
-            Reader::Scalar(x) => visit_str!(x.read_str(), visitor),
+            Reader::Scalar(_) => visit_str!(self, visitor),
             Reader::Value(x) => match x.token() {
-                TextToken::Scalar(s) => visit_str!(x.decode(s.view_data()), visitor),
+                TextToken::Scalar(_) => visit_str!(self, visitor),
                 TextToken::Header(_) | TextToken::Array(_) => self.deserialize_seq(visitor),
                 TextToken::Object(_) | TextToken::HiddenObject(_) => self.deserialize_map(visitor),
                 _ => Err(DeserializeError {
@@ -170,7 +796,7 @@ where
     where
         V: Visitor<'de>,
     {
-        visit_str!(self.reader_ref().read_str()?, visitor)
+        visit_str!(self, visitor)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -205,84 +831,103 @@ where
     where
         V: Visitor<'de>,
     {
-        visitor.visit_bool(self.reader_ref().read_scalar()?.to_bool()?)
+        let scalar = self.reader_ref().read_scalar()?;
+        if !self.options.quoted_bools && self.is_quoted(scalar) {
+            return Err(DeserializeError {
+                kind: DeserializeErrorKind::Unsupported(format!(
+                    "quoted string \"{}\" is not a boolean",
+                    scalar
+                )),
+            });
+        }
+
+        if self.options.lenient_bools {
+            visitor.visit_bool(scalar.to_bool_lenient()?)
+        } else {
+            visitor.visit_bool(scalar.to_bool()?)
+        }
     }
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_i64(visitor)
+        self.visit_integer(IntegerType::I8, visitor)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_i64(visitor)
+        self.visit_integer(IntegerType::I16, visitor)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_i64(visitor)
+        self.visit_integer(IntegerType::I32, visitor)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i64(self.reader_ref().read_scalar()?.to_i64()?)
+        self.visit_integer(IntegerType::I64, visitor)
     }
 
     fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_i64(visitor)
+        visitor.visit_i128(self.reader_ref().read_scalar()?.to_i128()?)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_u64(visitor)
+        self.visit_integer(IntegerType::U8, visitor)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_u64(visitor)
+        self.visit_integer(IntegerType::U16, visitor)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_u64(visitor)
+        self.visit_integer(IntegerType::U32, visitor)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_u64(self.reader_ref().read_scalar()?.to_u64()?)
+        self.visit_integer(IntegerType::U64, visitor)
     }
 
     fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_u64(visitor)
+        visitor.visit_u128(self.reader_ref().read_scalar()?.to_u128()?)
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_f64(self.reader_ref().read_scalar()?.to_f64()?)
+        let scalar = self.reader_ref().read_scalar()?;
+        if self.options.comma_decimals {
+            visitor.visit_f64(scalar.to_f64_comma()?)
+        } else {
+            visitor.visit_f64(scalar.to_f64()?)
+        }
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -296,73 +941,74 @@ where
     where
         V: Visitor<'de>,
     {
-        match self.reader() {
-            Reader::Object(x) => {
-                let map = MapAccess {
-                    de: self,
-                    reader: x,
-                    value: None,
-                };
-                visitor.visit_map(map)
-            }
-            Reader::Value(x) => {
-                let map = MapAccess {
-                    de: self,
-                    reader: x.read_object()?,
-                    value: None,
-                };
-                visitor.visit_map(map)
-            }
-            _ => Err(DeserializeError {
-                kind: DeserializeErrorKind::Unsupported(String::from(
-                    "can only deserialize an object as a map",
-                )),
-            }),
-        }
+        self.deserialize_object(None, visitor)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        if let Reader::Value(x) = self.reader() {
-            let map = SeqAccess {
-                de: self,
-                reader: x.read_array()?,
-            };
-            visitor.visit_seq(map)
-        } else {
-            Err(DeserializeError {
+        match self.reader() {
+            Reader::Object(x) => visitor.visit_seq(EntryAccess {
+                reader: x,
+                duplicate_key_strategy: self.duplicate_key_strategy,
+                options: self.options,
+                source: self.source,
+            }),
+            Reader::Value(x) => {
+                let map = SeqAccess {
+                    de: self,
+                    reader: x.read_array()?,
+                    object: matches!(x.token(), TextToken::Object(_)),
+                };
+                visitor.visit_seq(map)
+            }
+            _ => Err(DeserializeError {
                 kind: DeserializeErrorKind::Unsupported(String::from(
                     "unexpected reader for sequence",
                 )),
-            })
+            }),
         }
     }
 
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
-        _fields: &'static [&'static str],
+        name: &'static str,
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        self.deserialize_object(tagged_struct(name, fields), visitor)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_some(self)
+        match &self.readers {
+            Reader::Value(x) if self.options.empty_block_as_none && x.is_empty_block() => {
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
+        // A color (`rgb { 1 2 3 }`) deserialized as a tuple of its channels
+        // skips the header
+        if len != 2 {
+            if let Reader::Value(x) = &self.readers {
+                if let Some(value) = x.header_value() {
+                    self.readers = Reader::Value(value);
+                }
+            }
+        }
+
         self.deserialize_seq(visitor)
     }
 
@@ -449,9 +1095,74 @@ where
 }
 
 struct MapAccess<'a, 'de, 'tokens, E> {
-    de: &'a mut InternalDeserializer<'de, 'tokens, E>,
+    de: &'a mut TextTapeDeserializer<'de, 'tokens, E>,
     reader: ObjectReader<'de, 'tokens, E>,
     value: Option<ValueReader<'de, 'tokens, E>>,
+    duplicates: Vec<ValueReader<'de, 'tokens, E>>,
+
+    // The key of the staged value, to report where errors occurred
+    key: Option<ScalarReader<'de, E>>,
+
+    // The key of a tagged struct and its field, which is visited first
+    tag: Option<&'static str>,
+    tagged: Option<(ScalarReader<'de, E>, ValueReader<'de, 'tokens, E>)>,
+}
+
+impl<'a, 'de: 'a, 'tokens, E> MapAccess<'a, 'de, 'tokens, E>
+where
+    E: Encoding + Clone,
+{
+    /// Advance to the next key, visiting the tag of a tagged struct first and
+    /// skipping it afterwards
+    fn next_key(&mut self) -> Result<Option<ScalarReader<'de, E>>, DeserializeError> {
+        if let Some((key, value)) = self.tagged.take() {
+            self.value = Some(value);
+            return Ok(Some(key));
+        }
+
+        while let Some(key) = self.next_entry()? {
+            match self.tag {
+                Some(tag) if key.read_str() == tag => self.duplicates.clear(),
+                _ => return Ok(Some(key)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Advance to the next key and stage its value(s) according to the
+    /// duplicate key strategy
+    fn next_entry(&mut self) -> Result<Option<ScalarReader<'de, E>>, DeserializeError> {
+        if self.de.duplicate_key_strategy == DuplicateKeyStrategy::Preserve {
+            return Ok(self.reader.next_field().map(|(key, _op, value)| {
+                self.value = Some(value);
+                key
+            }));
+        }
+
+        let (key, mut values) = match self.reader.next_fields() {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        match self.de.duplicate_key_strategy {
+            DuplicateKeyStrategy::Error if values.len() > 1 => {
+                return Err(duplicate_key_error(&key.read_str()))
+            }
+            DuplicateKeyStrategy::Collect
+                if values.len() > 1 || matches!(values[0].1.token(), TextToken::Scalar(_)) =>
+            {
+                self.duplicates = values.into_iter().map(|(_op, value)| value).collect();
+            }
+            DuplicateKeyStrategy::TakeFirst => {
+                values.truncate(1);
+                self.value = values.pop().map(|(_op, value)| value);
+            }
+            _ => self.value = values.pop().map(|(_op, value)| value),
+        }
+
+        Ok(Some(key))
+    }
 }
 
 impl<'a, 'de: 'a, 'tokens, E> de::MapAccess<'de> for MapAccess<'a, 'de, 'tokens, E>
@@ -464,8 +1175,8 @@ where
     where
         K: DeserializeSeed<'de>,
     {
-        if let Some((key, _op, value)) = self.reader.next_field() {
-            self.value = Some(value);
+        if let Some(key) = self.next_key()? {
+            self.key = Some(key.clone());
             let old = std::mem::replace(&mut self.de.readers, Reader::Scalar(key));
             let res = seed.deserialize(&mut *self.de).map(Some);
             let _ = std::mem::replace(&mut self.de.readers, old);
@@ -479,11 +1190,37 @@ where
     where
         V: DeserializeSeed<'de>,
     {
+        if !self.duplicates.is_empty() {
+            let strategy = self.de.duplicate_key_strategy;
+            let options = self.de.options;
+            let source = self.de.source;
+            let mut values: Vec<_> = self
+                .duplicates
+                .drain(..)
+                .map(|value| TextTapeDeserializer {
+                    readers: Reader::Value(value),
+                    duplicate_key_strategy: strategy,
+                    options,
+                    source,
+                    scratch: String::new(),
+                })
+                .collect();
+
+            let res = seed.deserialize(DuplicatedValues::new(values.iter_mut().collect()));
+            return res.map_err(|e| match &self.key {
+                Some(key) => e.within(&key.read_str()),
+                None => e,
+            });
+        }
+
         let r = self.value.take().unwrap();
         let old = std::mem::replace(&mut self.de.readers, Reader::Value(r));
         let res = seed.deserialize(&mut *self.de);
         let _ = std::mem::replace(&mut self.de.readers, old);
-        res
+        res.map_err(|e| match &self.key {
+            Some(key) => e.within(&key.read_str()),
+            None => e,
+        })
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -492,8 +1229,11 @@ where
 }
 
 struct SeqAccess<'a, 'de, 'tokens, E> {
-    de: &'a mut InternalDeserializer<'de, 'tokens, E>,
+    de: &'a mut TextTapeDeserializer<'de, 'tokens, E>,
     reader: ArrayReader<'de, 'tokens, E>,
+
+    // An object viewed as an array of alternating keys and values
+    object: bool,
 }
 
 impl<'a, 'de: 'a, 'tokens, E> de::SeqAccess<'de> for SeqAccess<'a, 'de, 'tokens, E>
@@ -508,9 +1248,16 @@ where
     {
         if let Some(x) = self.reader.next_value() {
             let old = std::mem::replace(&mut self.de.readers, Reader::Value(x));
-            let res = seed.deserialize(&mut *self.de).map(Some);
+            let res = if self.object {
+                seed.deserialize(ObjectElement {
+                    de: &mut *self.de,
+                    reader: &mut self.reader,
+                })
+            } else {
+                seed.deserialize(&mut *self.de)
+            };
             let _ = std::mem::replace(&mut self.de.readers, old);
-            res
+            res.map(Some)
         } else {
             Ok(None)
         }
@@ -521,8 +1268,157 @@ where
     }
 }
 
+/// An element of an object that is deserialized as a sequence. Elements are
+/// the individual keys and values, unless a tuple is requested, in which case
+/// the element is the key and value pair (eg: `Vec<(Date, Event)>`).
+struct ObjectElement<'a, 'b, 'de, 'tokens, E> {
+    de: &'a mut TextTapeDeserializer<'de, 'tokens, E>,
+    reader: &'b mut ArrayReader<'de, 'tokens, E>,
+}
+
+impl<'a, 'b, 'de, 'tokens, E> ObjectElement<'a, 'b, 'de, 'tokens, E>
+where
+    E: Encoding + Clone,
+{
+    fn visit_pair<V>(self, visitor: V) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'de>,
+    {
+        let value = self.reader.next_value().ok_or_else(|| DeserializeError {
+            kind: DeserializeErrorKind::Unsupported(String::from("expected a value after key")),
+        })?;
+
+        let mut value = TextTapeDeserializer {
+            readers: Reader::Value(value),
+            duplicate_key_strategy: self.de.duplicate_key_strategy,
+            options: self.de.options,
+            source: self.de.source,
+            scratch: String::new(),
+        };
+        visitor.visit_seq(&mut KeyValuePair::new(&mut *self.de, &mut value))
+    }
+}
+
+macro_rules! forward_to_de {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.de.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'a, 'b, 'de, 'tokens, E> de::Deserializer<'de> for ObjectElement<'a, 'b, 'de, 'tokens, E>
+where
+    E: Encoding + Clone,
+{
+    type Error = DeserializeError;
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_pair(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_pair(visitor)
+    }
+
+    forward_to_de! {
+        deserialize_any()
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i64()
+        deserialize_i128()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_u128()
+        deserialize_f32()
+        deserialize_f64()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_option()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq()
+        deserialize_map()
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+        deserialize_ignored_any()
+    }
+}
+
+/// Deserializes the fields of the root object as a sequence of key value
+/// pairs in the order they appear. Every occurrence of a duplicate key is
+/// kept.
+struct EntryAccess<'de, 'tokens, E> {
+    reader: ObjectReader<'de, 'tokens, E>,
+    duplicate_key_strategy: DuplicateKeyStrategy,
+    options: ValueOptions,
+    source: &'de [u8],
+}
+
+impl<'de, 'tokens, E> de::SeqAccess<'de> for EntryAccess<'de, 'tokens, E>
+where
+    E: Encoding + Clone,
+{
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if let Some((key, _op, value)) = self.reader.next_field() {
+            let mut key = TextTapeDeserializer {
+                readers: Reader::Scalar(key),
+                duplicate_key_strategy: self.duplicate_key_strategy,
+                options: self.options,
+                source: self.source,
+                scratch: String::new(),
+            };
+            let mut value = TextTapeDeserializer {
+                readers: Reader::Value(value),
+                duplicate_key_strategy: self.duplicate_key_strategy,
+                options: self.options,
+                source: self.source,
+                scratch: String::new(),
+            };
+            seed.deserialize(&mut KeyValuePair::new(&mut key, &mut value))
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.reader.fields_len())
+    }
+}
+
 struct VariantAccess<'a, 'de, 'tokens, E> {
-    de: &'a mut InternalDeserializer<'de, 'tokens, E>,
+    de: &'a mut TextTapeDeserializer<'de, 'tokens, E>,
     reader: ArrayReader<'de, 'tokens, E>,
 }
 
@@ -608,6 +1504,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Rgb;
     use jomini_derive::JominiDeserialize;
     use serde::{
         de::{self, Deserializer},
@@ -620,7 +1517,40 @@ mod tests {
     where
         T: Deserialize<'a>,
     {
-        Ok(TextDeserializer::from_windows1252_slice(data)?)
+        TextDeserializer::from_windows1252_slice(data)
+    }
+
+    #[test]
+    fn test_deserialize_from_nested_reader() {
+        let data = b"a={ b=1 } c={ d=ENG e={ f=2 } }";
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct MyStruct {
+            d: String,
+            e: Inner,
+        }
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct Inner {
+            f: u32,
+        }
+
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+        let mut reader = tape.windows1252_reader();
+        let mut actual: Option<MyStruct> = None;
+        while let Some((key, _op, value)) = reader.next_field() {
+            if key.read_str() == "c" {
+                actual = Some(TextDeserializer::from_reader(value.read_object().unwrap()).unwrap());
+            }
+        }
+
+        assert_eq!(
+            actual,
+            Some(MyStruct {
+                d: String::from("ENG"),
+                e: Inner { f: 2 },
+            })
+        );
     }
 
     #[test]
@@ -690,6 +1620,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_scratch_decoded_keys_and_values() {
+        let data = b"\"na\\me\" = Jos\xe9 kind = \"cap\\tain\"";
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct MyStruct {
+            #[serde(rename = "name")]
+            first: String,
+            kind: String,
+        }
+
+        let actual: HashMap<String, String> = from_slice(&data[..]).unwrap();
+        assert_eq!(actual.get("name").map(String::as_str), Some("José"));
+        assert_eq!(actual.get("kind").map(String::as_str), Some("captain"));
+
+        let actual: MyStruct = from_slice(&data[..]).unwrap();
+        assert_eq!(
+            actual,
+            MyStruct {
+                first: String::from("José"),
+                kind: String::from("captain"),
+            }
+        );
+    }
+
     #[test]
     fn test_false_field() {
         let data = b"field1=no";
@@ -729,6 +1684,26 @@ mod tests {
         assert_eq!(actual, MyStruct { field1: 1000 });
     }
 
+    #[test]
+    fn test_u128_field() {
+        let data = b"field1=18446744073709551616 field2=-18446744073709551616";
+
+        #[derive(Deserialize, PartialEq, Eq, Debug)]
+        struct MyStruct {
+            field1: u128,
+            field2: i128,
+        }
+
+        let actual: MyStruct = from_slice(&data[..]).unwrap();
+        assert_eq!(
+            actual,
+            MyStruct {
+                field1: 18446744073709551616,
+                field2: -18446744073709551616
+            }
+        );
+    }
+
     #[test]
     fn test_u32_field() {
         let data = b"field1=1000";
@@ -846,6 +1821,96 @@ mod tests {
         assert_eq!(actual, MyStruct { field1: -100.535 });
     }
 
+    #[test]
+    fn test_comma_decimal_fields() {
+        let data = b"field1=-100,535 field2={ 1,5 2.5 }";
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            field1: f64,
+            field2: Vec<f32>,
+        }
+
+        assert!(from_slice::<MyStruct>(&data[..]).is_err());
+
+        let actual: MyStruct = TextDeserializer::builder()
+            .comma_decimals(true)
+            .from_windows1252_slice(&data[..])
+            .unwrap();
+        assert_eq!(
+            actual,
+            MyStruct {
+                field1: -100.535,
+                field2: vec![1.5, 2.5]
+            }
+        );
+    }
+
+    #[test]
+    fn test_lenient_bool_fields() {
+        let data = b"a=true b=0 c=yes";
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            a: bool,
+            b: bool,
+            c: bool,
+        }
+
+        assert!(from_slice::<MyStruct>(&data[..]).is_err());
+
+        let actual: MyStruct = TextDeserializer::builder()
+            .lenient_bools(true)
+            .from_windows1252_slice(&data[..])
+            .unwrap();
+        assert_eq!(
+            actual,
+            MyStruct {
+                a: true,
+                b: false,
+                c: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_quoted_bool_fields() {
+        let data = br#"a=yes b="no" c="yes" d=no"#;
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Bools {
+            a: bool,
+            b: bool,
+            c: String,
+            d: String,
+        }
+
+        let err = from_slice::<Bools>(&data[..]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("quoted string \"no\" is not a boolean"));
+
+        let expected = Bools {
+            a: true,
+            b: false,
+            c: String::from("yes"),
+            d: String::from("no"),
+        };
+
+        let actual: Bools = TextDeserializer::builder()
+            .quoted_bools(true)
+            .from_windows1252_slice(&data[..])
+            .unwrap();
+        assert_eq!(actual, expected);
+
+        // Quotes are unknown without the source data
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+        let actual: Bools = TextDeserializer::builder()
+            .from_reader(tape.windows1252_reader())
+            .unwrap();
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn test_multiple_to_level_events() {
         let data = b"field1=yes\r\nfield2=no";
@@ -1004,6 +2069,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_duplicate_key_strategies() {
+        let data = b"a = 1 b = { c = x c = y } a = 2";
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Single {
+            a: u32,
+            b: Inner,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Inner {
+            c: String,
+        }
+
+        let deserialize = |strategy| {
+            TextDeserializer::builder()
+                .on_duplicate_key(strategy)
+                .from_windows1252_slice::<Single>(&data[..])
+        };
+
+        let first = deserialize(DuplicateKeyStrategy::TakeFirst).unwrap();
+        assert_eq!(first.a, 1);
+        assert_eq!(first.b.c, "x");
+
+        let last = deserialize(DuplicateKeyStrategy::TakeLast).unwrap();
+        assert_eq!(last.a, 2);
+        assert_eq!(last.b.c, "y");
+
+        let err = deserialize(DuplicateKeyStrategy::Error).unwrap_err();
+        assert!(err.to_string().contains("duplicate field `a`"));
+        assert!(deserialize(DuplicateKeyStrategy::Preserve).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_key_collect() {
+        let data = b"a = 1 b = { 1 2 } c = x a = 2 b = { 3 } d = { e = 1 } d = { e = 2 }";
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            a: Vec<u32>,
+            b: Vec<Vec<u32>>,
+            c: Vec<String>,
+            d: Inner,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Inner {
+            e: u32,
+        }
+
+        let actual: MyStruct = TextDeserializer::builder()
+            .on_duplicate_key(DuplicateKeyStrategy::Collect)
+            .from_windows1252_slice(&data[..])
+            .unwrap();
+        assert_eq!(
+            actual,
+            MyStruct {
+                a: vec![1, 2],
+                b: vec![vec![1, 2], vec![3]],
+                c: vec![String::from("x")],
+                d: Inner { e: 2 },
+            }
+        );
+    }
+
     #[test]
     fn test_non_consecutive_fields() {
         let data = b"c = d1\r\na = b\r\nc = d2\r\ne = f";
@@ -1110,6 +2241,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deserialize_typed_keys() {
+        use crate::common::Date;
+
+        let data = b"provinces={ 1={ name=a } 2={ name=b } } events={ 1444.11.11=a 1.1.1=b }";
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            provinces: HashMap<u32, Province>,
+            events: HashMap<Date, String>,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Province {
+            name: String,
+        }
+
+        let actual: MyStruct = from_slice(&data[..]).unwrap();
+        assert_eq!(actual.provinces[&1].name, "a");
+        assert_eq!(actual.provinces[&2].name, "b");
+
+        let date = Date::parse_from_str("1444.11.11").unwrap();
+        assert_eq!(actual.events[&date], "a");
+        assert_eq!(actual.events.len(), 2);
+    }
+
+    #[test]
+    fn test_deserialize_ordered_entries() {
+        use crate::common::Date;
+
+        let data = b"history={ 1444.11.11={ owner=ENG } 1400.1.1={ owner=FRA } 1444.11.11={ owner=NOR } } a=1 b=2 a=3";
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct MyStruct {
+            history: Vec<(Date, Event)>,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Event {
+            owner: String,
+        }
+
+        let actual: MyStruct = from_slice(&data[..]).unwrap();
+        let owners: Vec<_> = actual
+            .history
+            .iter()
+            .map(|(date, event)| (date.game_fmt(), event.owner.as_str()))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![
+                (String::from("1444.11.11"), "ENG"),
+                (String::from("1400.1.1"), "FRA"),
+                (String::from("1444.11.11"), "NOR"),
+            ]
+        );
+
+        let root: Vec<(String, serde::de::IgnoredAny)> = from_slice(&data[..]).unwrap();
+        let keys: Vec<_> = root.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["history", "a", "b", "a"]);
+    }
+
     #[test]
     fn test_empty_objects() {
         let data = b"a={foo={bar=val} {} { } me=you}";
@@ -1246,6 +2439,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deserialize_rgb_fields() {
+        let data = b"a = rgb { 10 11 12 } b = rgb { 10 11 12 } c = rgb { 10 11 12 } d = rgb { 10 11 12 } e = { 1 2 3 }";
+
+        #[derive(Debug, PartialEq)]
+        struct Hex(String);
+
+        fn hex<'de, D>(deserializer: D) -> Result<Hex, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Rgb::deserialize(deserializer).map(|x| Hex(x.to_hex()))
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct MyStruct {
+            a: Rgb,
+            b: (u32, u32, u32),
+            c: [u8; 3],
+            #[serde(deserialize_with = "hex")]
+            d: Hex,
+            e: Rgb,
+        }
+
+        let actual: MyStruct = from_slice(&data[..]).unwrap();
+        assert_eq!(
+            actual,
+            MyStruct {
+                a: Rgb {
+                    r: 10,
+                    g: 11,
+                    b: 12
+                },
+                b: (10, 11, 12),
+                c: [10, 11, 12],
+                d: Hex(String::from("#0a0b0c")),
+                e: Rgb { r: 1, g: 2, b: 3 },
+            },
+        );
+
+        // The header is still visible to types that ask for a pair
+        let actual: HashMap<String, (String, Vec<u8>)> =
+            from_slice(&b"a = rgb { 10 11 12 }"[..]).unwrap();
+        assert_eq!(actual["a"], (String::from("rgb"), vec![10, 11, 12]));
+    }
+
     #[test]
     fn test_deserialize_mixed_object() {
         let data = br#"brittany_area = { #5
@@ -1296,6 +2535,46 @@ mod tests {
             deserializer.deserialize_seq(MapVisitor)
         }
     }
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_from_reader_par() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Province {
+            owner: String,
+            base_tax: u8,
+        }
+
+        let mut data = String::from("version=1 ");
+        for i in 0..500 {
+            data.push_str(&format!("{}={{ owner=C{} base_tax={} }} ", i, i, i % 10));
+        }
+        let tape = TextTape::from_slice(data.as_bytes()).unwrap();
+        let mut reader = tape.windows1252_reader();
+        reader.next_field();
+        let rest = reader.clone();
+
+        let builder = TextDeserializer::builder();
+        let provinces: HashMap<u32, Province> = builder.from_reader_par(reader).unwrap();
+        assert_eq!(provinces.len(), 500);
+        assert_eq!(
+            provinces[&123],
+            Province {
+                owner: String::from("C123"),
+                base_tax: 3
+            }
+        );
+
+        let sequential: HashMap<u32, Province> = builder.from_reader(rest).unwrap();
+        assert_eq!(provinces, sequential);
+
+        let tape =
+            TextTape::from_slice(b"1={ owner=A base_tax=1 } 2={ owner=B base_tax=300 }").unwrap();
+        let err = builder
+            .from_reader_par::<u32, Province, Vec<_>, _>(tape.windows1252_reader())
+            .unwrap_err();
+        assert!(err.to_string().contains("`2.base_tax`"), "{}", err);
+    }
+
     #[test]
     fn test_deserialize_colors() {
         let data = b"color = rgb { 100 200 150 } color2 = hsv { 0.3 0.2 0.8 }";
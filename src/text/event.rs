@@ -0,0 +1,414 @@
+use super::tape::{parse_quote_scalar, split_at_scalar};
+use crate::{Error, Operator, Scalar};
+
+/// A lexical element of the text format that is produced when data is read
+/// incrementally instead of parsed into a tape all at once.
+///
+/// The events are not grouped into objects and arrays, as whether an open
+/// brace starts an object or an array isn't known until later in the data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextEvent {
+    /// An open brace (`{`)
+    Open,
+
+    /// A close brace (`}`)
+    Close,
+
+    /// An equal sign that separates a key and value
+    Equal,
+
+    /// A comparison operator that separates a key and value (`a > 1`)
+    Operator(Operator),
+
+    /// An unquoted scalar
+    Scalar(Vec<u8>),
+
+    /// The contents of a quoted scalar. Escape sequences are left as is, the
+    /// same as with `TextToken::Scalar`.
+    Quoted(Vec<u8>),
+}
+
+/// Callbacks for `TextTapeParser::visit`, which walks text data without
+/// building a tape or allocating.
+///
+/// A scalar is reported as a key when it is followed by an equal sign or
+/// operator and as a value otherwise. Whether an open brace starts an object
+/// or an array isn't known when it is reported. All callbacks do nothing by
+/// default, so a visitor only implements the ones it is interested in.
+///
+/// ```
+/// use jomini::{Scalar, TextTape, TextVisitor};
+///
+/// struct Grep<'a> {
+///     depth: usize,
+///     found: Vec<(usize, Scalar<'a>)>,
+/// }
+///
+/// impl<'a> TextVisitor<'a> for Grep<'a> {
+///     fn visit_key(&mut self, key: Scalar<'a>) {
+///         if key.view_data() == b"name" {
+///             self.found.push((self.depth, key));
+///         }
+///     }
+///
+///     fn visit_open(&mut self) {
+///         self.depth += 1;
+///     }
+///
+///     fn visit_close(&mut self) {
+///         self.depth -= 1;
+///     }
+/// }
+///
+/// let mut grep = Grep { depth: 0, found: Vec::new() };
+/// TextTape::parser().visit(b"name=a army={ name=b }", &mut grep)?;
+/// assert_eq!(grep.found.iter().map(|x| x.0).collect::<Vec<_>>(), vec![0, 1]);
+/// # Ok::<(), jomini::Error>(())
+/// ```
+pub trait TextVisitor<'a> {
+    /// A scalar that is followed by an equal sign or operator
+    fn visit_key(&mut self, key: Scalar<'a>) {
+        let _ = key;
+    }
+
+    /// A comparison operator that separates a key and value (`a > 1`).
+    /// Equal signs are not reported.
+    fn visit_operator(&mut self, operator: Operator) {
+        let _ = operator;
+    }
+
+    /// A scalar that is not a key. The contents of quoted scalars are given
+    /// with their escape sequences left as is.
+    fn visit_value(&mut self, value: Scalar<'a>, quoted: bool) {
+        let _ = (value, quoted);
+    }
+
+    /// An open brace (`{`)
+    fn visit_open(&mut self) {}
+
+    /// A close brace (`}`)
+    fn visit_close(&mut self) {}
+}
+
+/// A lexical element that borrows from the data
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lexeme<'a> {
+    Open,
+    Close,
+    Equal,
+    Operator(Operator),
+    Scalar(Scalar<'a>),
+    Quoted(Scalar<'a>),
+}
+
+impl<'a> From<Lexeme<'a>> for TextEvent {
+    fn from(lexeme: Lexeme<'a>) -> Self {
+        match lexeme {
+            Lexeme::Open => TextEvent::Open,
+            Lexeme::Close => TextEvent::Close,
+            Lexeme::Equal => TextEvent::Equal,
+            Lexeme::Operator(x) => TextEvent::Operator(x),
+            Lexeme::Scalar(x) => TextEvent::Scalar(x.view_data().to_vec()),
+            Lexeme::Quoted(x) => TextEvent::Quoted(x.view_data().to_vec()),
+        }
+    }
+}
+
+/// Walk the data and give each element to the visitor. See `TextVisitor`.
+pub(crate) fn visit<'a, V>(data: &'a [u8], visitor: &mut V) -> Result<(), Error>
+where
+    V: TextVisitor<'a>,
+{
+    let mut d = data;
+    let mut pending: Option<(Scalar<'a>, bool)> = None;
+    while let Some((len, lexeme)) = next_lexeme(d, true)? {
+        d = &d[len..];
+        let lexeme = match lexeme {
+            Some(x) => x,
+            None => continue,
+        };
+
+        match (pending.take(), lexeme) {
+            (Some((key, _)), Lexeme::Equal) => visitor.visit_key(key),
+            (Some((key, _)), Lexeme::Operator(op)) => {
+                visitor.visit_key(key);
+                visitor.visit_operator(op);
+            }
+            (previous, lexeme) => {
+                if let Some((value, quoted)) = previous {
+                    visitor.visit_value(value, quoted);
+                }
+
+                match lexeme {
+                    Lexeme::Open => visitor.visit_open(),
+                    Lexeme::Close => visitor.visit_close(),
+                    Lexeme::Equal => {}
+                    Lexeme::Operator(op) => visitor.visit_operator(op),
+                    Lexeme::Scalar(x) => pending = Some((x, false)),
+                    Lexeme::Quoted(x) => pending = Some((x, true)),
+                }
+            }
+        }
+    }
+
+    if let Some((value, quoted)) = pending {
+        visitor.visit_value(value, quoted);
+    }
+
+    Ok(())
+}
+
+/// An incremental parser of the text format that is driven by pushing chunks
+/// of data into it, so it can sit behind any transport (websockets,
+/// decompression streams, etc) without committing to an IO trait.
+///
+/// Data that may be the start of an incomplete event is held until the next
+/// chunk arrives or the parser is finished.
+///
+/// ```
+/// use jomini::{TextEvent, TextPushParser};
+///
+/// let mut parser = TextPushParser::new();
+/// let mut events = parser.feed(b"player=\"EN")?;
+/// assert_eq!(events, vec![TextEvent::Scalar(b"player".to_vec()), TextEvent::Equal]);
+/// assert_eq!(parser.pending(), 3);
+///
+/// events = parser.feed(b"G\" date=1444.11.11")?;
+/// assert_eq!(events.len(), 3);
+///
+/// events = parser.finish()?;
+/// assert_eq!(events, vec![TextEvent::Scalar(b"1444.11.11".to_vec())]);
+/// # Ok::<(), jomini::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct TextPushParser {
+    buf: Vec<u8>,
+}
+
+impl TextPushParser {
+    /// Create a text push parser
+    pub fn new() -> Self {
+        TextPushParser::default()
+    }
+
+    /// Append a chunk of data and return the events that it completes
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<TextEvent>, Error> {
+        let mut events = Vec::new();
+        self.feed_into(data, &mut events)?;
+        Ok(events)
+    }
+
+    /// Signal that there is no more data and return the remaining events
+    pub fn finish(mut self) -> Result<Vec<TextEvent>, Error> {
+        let mut events = Vec::new();
+        self.finish_into(&mut events)?;
+        Ok(events)
+    }
+
+    /// The number of bytes held while waiting for the rest of an event
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(crate) fn feed_into<E>(&mut self, data: &[u8], events: &mut E) -> Result<(), Error>
+    where
+        E: Extend<TextEvent>,
+    {
+        self.buf.extend_from_slice(data);
+        self.lex(false, events)
+    }
+
+    pub(crate) fn finish_into<E>(&mut self, events: &mut E) -> Result<(), Error>
+    where
+        E: Extend<TextEvent>,
+    {
+        self.lex(true, events)
+    }
+
+    fn lex<E>(&mut self, eof: bool, events: &mut E) -> Result<(), Error>
+    where
+        E: Extend<TextEvent>,
+    {
+        let mut pos = 0;
+        let result = loop {
+            match next_lexeme(&self.buf[pos..], eof) {
+                Ok(Some((len, lexeme))) => {
+                    pos += len;
+                    events.extend(lexeme.map(TextEvent::from));
+                }
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.buf.drain(..pos);
+        result
+    }
+}
+
+/// Returns the number of bytes that make up the next lexeme (whitespace and
+/// comments don't produce a lexeme). None is returned when more data is
+/// needed to know where the next lexeme ends.
+fn next_lexeme(d: &[u8], eof: bool) -> Result<Option<(usize, Option<Lexeme<'_>>)>, Error> {
+    let c = match d.first() {
+        Some(&c) => c,
+        None => return Ok(None),
+    };
+
+    let res = match c {
+        b' ' | b'\t' | b'\n' | b'\r' => (1, None),
+        b'#' => match d.iter().position(|&x| x == b'\n') {
+            Some(ind) => (ind + 1, None),
+            None if eof => (d.len(), None),
+            None => return Ok(None),
+        },
+        b'{' => (1, Some(Lexeme::Open)),
+        b'}' => (1, Some(Lexeme::Close)),
+        b'=' => (1, Some(Lexeme::Equal)),
+        b'<' | b'>' => {
+            let equal = match d.get(1) {
+                Some(&x) => x == b'=',
+                None if eof => false,
+                None => return Ok(None),
+            };
+
+            let op = match (c, equal) {
+                (b'<', false) => Operator::LessThan,
+                (b'<', true) => Operator::LessThanEqual,
+                (_, false) => Operator::GreaterThan,
+                (_, true) => Operator::GreaterThanEqual,
+            };
+
+            (1 + usize::from(equal), Some(Lexeme::Operator(op)))
+        }
+        b'"' => match parse_quote_scalar(d) {
+            Ok((scalar, rest)) => (d.len() - rest.len(), Some(Lexeme::Quoted(scalar))),
+            Err(e) if eof => return Err(e),
+            Err(_) => return Ok(None),
+        },
+        b'@' if d.get(1) == Some(&b'[') || (d.len() == 1 && !eof) => {
+            if d.len() == 1 {
+                return Ok(None);
+            }
+
+            match d.iter().position(|&x| x == b']') {
+                Some(ind) => (ind + 1, Some(Lexeme::Scalar(Scalar::new(&d[..=ind])))),
+                None if eof => return Err(Error::eof("a closing bracket")),
+                None => return Ok(None),
+            }
+        }
+        _ => {
+            let (scalar, rest) = split_at_scalar(d);
+            if rest.is_empty() && !eof {
+                return Ok(None);
+            }
+
+            (scalar.view_data().len(), Some(Lexeme::Scalar(scalar)))
+        }
+    };
+
+    Ok(Some(res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex_chunks(data: &[u8], chunk_size: usize) -> Vec<TextEvent> {
+        let mut parser = TextPushParser::new();
+        let mut events = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            events.extend(parser.feed(chunk).unwrap());
+        }
+        events.extend(parser.finish().unwrap());
+        events
+    }
+
+    #[test]
+    fn test_text_events() {
+        let data = b"a=1 # comment\nb={ \"x y\" } c<=@[x * 2] d>e";
+        let expected = vec![
+            TextEvent::Scalar(b"a".to_vec()),
+            TextEvent::Equal,
+            TextEvent::Scalar(b"1".to_vec()),
+            TextEvent::Scalar(b"b".to_vec()),
+            TextEvent::Equal,
+            TextEvent::Open,
+            TextEvent::Quoted(b"x y".to_vec()),
+            TextEvent::Close,
+            TextEvent::Scalar(b"c".to_vec()),
+            TextEvent::Operator(Operator::LessThanEqual),
+            TextEvent::Scalar(b"@[x * 2]".to_vec()),
+            TextEvent::Scalar(b"d".to_vec()),
+            TextEvent::Operator(Operator::GreaterThan),
+            TextEvent::Scalar(b"e".to_vec()),
+        ];
+
+        for chunk_size in 1..=data.len() {
+            assert_eq!(lex_chunks(&data[..], chunk_size), expected);
+        }
+    }
+
+    #[test]
+    fn test_text_visitor() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+
+        impl<'a> TextVisitor<'a> for Recorder {
+            fn visit_key(&mut self, key: Scalar<'a>) {
+                self.0.push(format!("key:{}", key));
+            }
+
+            fn visit_operator(&mut self, operator: Operator) {
+                self.0.push(format!("op:{:?}", operator));
+            }
+
+            fn visit_value(&mut self, value: Scalar<'a>, quoted: bool) {
+                let kind = if quoted { "quoted" } else { "value" };
+                self.0.push(format!("{}:{}", kind, value));
+            }
+
+            fn visit_open(&mut self) {
+                self.0.push(String::from("{"));
+            }
+
+            fn visit_close(&mut self) {
+                self.0.push(String::from("}"));
+            }
+        }
+
+        let data = b"a=1 # comment\nb={ \"x y\" c = rgb { 1 } } d>e f";
+        let mut recorder = Recorder::default();
+        visit(&data[..], &mut recorder).unwrap();
+        assert_eq!(
+            recorder.0,
+            vec![
+                "key:a",
+                "value:1",
+                "key:b",
+                "{",
+                "quoted:x y",
+                "key:c",
+                "value:rgb",
+                "{",
+                "value:1",
+                "}",
+                "}",
+                "key:d",
+                "op:GreaterThan",
+                "value:e",
+                "value:f",
+            ]
+        );
+
+        let mut recorder = Recorder::default();
+        assert!(visit(&b"a=\"b"[..], &mut recorder).unwrap_err().is_eof());
+    }
+
+    #[test]
+    fn test_text_events_unterminated_quote() {
+        let mut parser = TextPushParser::new();
+        assert_eq!(parser.feed(b"a=\"b").unwrap().len(), 2);
+        assert!(parser.finish().unwrap_err().is_eof());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::chars::{is_boundary, is_operator, is_whitespace};
+use std::ops::Range;
+
+/// The kind of a lexeme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexemeKind {
+    /// A run of whitespace
+    Whitespace,
+
+    /// A comment from the `#` up to, but not including, the next line feed
+    Comment,
+
+    /// An operator like `=` or `<=`
+    Operator,
+
+    /// A `{`
+    OpenBrace,
+
+    /// A `}`
+    CloseBrace,
+
+    /// An unquoted scalar
+    Scalar,
+
+    /// A quoted scalar, including the quotes. A quoted scalar that is never
+    /// closed runs to the end of the data.
+    Quoted,
+}
+
+/// A span of the data that was lexed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lexeme<'a> {
+    /// The kind of lexeme
+    pub kind: LexemeKind,
+
+    /// The byte range of the lexeme in the data
+    pub span: Range<usize>,
+
+    /// The bytes of the lexeme
+    pub data: &'a [u8],
+}
+
+/// An iterator over the lexemes of text data. See [`lex`].
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+/// Split text data into lexemes for syntax highlighting and editor tooling.
+///
+/// Unlike the tape, lexing is lossless: every byte of the data belongs to
+/// exactly one lexeme, including whitespace and comments, so the lexemes can
+/// be written back out to reproduce the data. Lexing never fails and does not
+/// check that braces are balanced or that keys are followed by values, which
+/// is left to the parser.
+///
+/// ```
+/// use jomini::{lex, LexemeKind};
+///
+/// let data = b"a = \"b\" # c\n";
+/// let kinds: Vec<_> = lex(&data[..]).map(|x| x.kind).collect();
+/// assert_eq!(
+///     kinds,
+///     vec![
+///         LexemeKind::Scalar,
+///         LexemeKind::Whitespace,
+///         LexemeKind::Operator,
+///         LexemeKind::Whitespace,
+///         LexemeKind::Quoted,
+///         LexemeKind::Whitespace,
+///         LexemeKind::Comment,
+///         LexemeKind::Whitespace,
+///     ]
+/// );
+///
+/// let quoted = lex(&data[..]).nth(4).unwrap();
+/// assert_eq!(quoted.span, 4..7);
+/// assert_eq!(quoted.data, b"\"b\"");
+/// ```
+pub fn lex(data: &[u8]) -> Lexer<'_> {
+    Lexer { data, pos: 0 }
+}
+
+impl<'a> Lexer<'a> {
+    /// Returns the byte offset of the next lexeme
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn advance_while<F: Fn(u8) -> bool>(&self, start: usize, f: F) -> usize {
+        self.data[start..]
+            .iter()
+            .position(|&x| !f(x))
+            .map_or(self.data.len(), |x| start + x)
+    }
+
+    fn quote_end(&self) -> usize {
+        let mut pos = self.pos + 1;
+        while pos < self.data.len() {
+            match self.data[pos] {
+                b'\\' => pos += 2,
+                b'"' => return pos + 1,
+                _ => pos += 1,
+            }
+        }
+
+        self.data.len()
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Lexeme<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.pos;
+        let c = *self.data.get(start)?;
+        let (kind, end) = match c {
+            b'{' => (LexemeKind::OpenBrace, start + 1),
+            b'}' => (LexemeKind::CloseBrace, start + 1),
+            b'#' => (
+                LexemeKind::Comment,
+                self.advance_while(start, |x| x != b'\n'),
+            ),
+            b'"' => (LexemeKind::Quoted, self.quote_end()),
+            x if is_operator(x) => (LexemeKind::Operator, self.advance_while(start, is_operator)),
+            x if is_whitespace(x) => (
+                LexemeKind::Whitespace,
+                self.advance_while(start, is_whitespace),
+            ),
+            _ => (
+                LexemeKind::Scalar,
+                self.advance_while(start, |x| !is_boundary(x)),
+            ),
+        };
+
+        self.pos = end;
+        Some(Lexeme {
+            kind,
+            span: start..end,
+            data: &self.data[start..end],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexemes(data: &[u8]) -> Vec<(LexemeKind, &[u8])> {
+        lex(data).map(|x| (x.kind, x.data)).collect()
+    }
+
+    #[test]
+    fn test_lex_object() {
+        assert_eq!(
+            lexemes(b"a={b>=1}\r\n"),
+            vec![
+                (LexemeKind::Scalar, &b"a"[..]),
+                (LexemeKind::Operator, &b"="[..]),
+                (LexemeKind::OpenBrace, &b"{"[..]),
+                (LexemeKind::Scalar, &b"b"[..]),
+                (LexemeKind::Operator, &b">="[..]),
+                (LexemeKind::Scalar, &b"1"[..]),
+                (LexemeKind::CloseBrace, &b"}"[..]),
+                (LexemeKind::Whitespace, &b"\r\n"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_quotes_and_comments() {
+        assert_eq!(
+            lexemes(br##"a="b \"c\" #d"#e#f"##),
+            vec![
+                (LexemeKind::Scalar, &b"a"[..]),
+                (LexemeKind::Operator, &b"="[..]),
+                (LexemeKind::Quoted, &br##""b \"c\" #d""##[..]),
+                (LexemeKind::Comment, &b"#e#f"[..]),
+            ]
+        );
+
+        assert_eq!(
+            lexemes(b"a=\"b"),
+            vec![
+                (LexemeKind::Scalar, &b"a"[..]),
+                (LexemeKind::Operator, &b"="[..]),
+                (LexemeKind::Quoted, &b"\"b"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_is_lossless() {
+        let data = b"  a = { 1 2 } # x\n\"b\"<=c\\\"\td=\"\\\"";
+        let mut pos = 0;
+        let mut out = Vec::new();
+        for lexeme in lex(&data[..]) {
+            assert_eq!(lexeme.span.start, pos);
+            assert!(!lexeme.data.is_empty());
+            pos = lexeme.span.end;
+            out.extend_from_slice(lexeme.data);
+        }
+        assert_eq!(out, &data[..]);
+    }
+}
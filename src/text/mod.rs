@@ -1,9 +1,25 @@
+mod builder;
+mod compact;
 #[cfg(feature = "derive")]
 mod de;
+mod event;
+mod lexer;
+mod parameters;
 mod reader;
+mod search;
 mod tape;
+mod variables;
+mod writer;
 
+pub use self::builder::TapeBuilder;
+pub use self::compact::CompactTextTape;
 #[cfg(feature = "derive")]
-pub use self::de::TextDeserializer;
+pub use self::de::{TextDeserializer, TextDeserializerBuilder, TextEncoded, TextTapeDeserializer};
+pub use self::event::{TextEvent, TextPushParser, TextVisitor};
+pub use self::lexer::{lex, Lexeme, LexemeKind, Lexer};
+pub use self::parameters::Parameters;
 pub use self::reader::{ArrayReader, ObjectReader, Reader, ScalarReader, ValueReader};
-pub use self::tape::{Operator, TextTape, TextToken};
+pub(crate) use self::tape::extend_tape;
+pub use self::tape::{MergeStrategy, Operator, TextTape, TextTapeParser, TextToken};
+pub use self::variables::Variables;
+pub use self::writer::{DateQuoting, Newline, QuotePolicy, TextWriter, TextWriterBuilder};
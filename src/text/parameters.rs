@@ -0,0 +1,206 @@
+use crate::{Error, ErrorKind};
+use std::collections::HashMap;
+
+/// Expands the parameters of scripted effects and triggers.
+///
+/// Scripted effects are templates that are instantiated with a set of
+/// parameters. Within the template:
+///
+/// - `$NAME$` is substituted with the value of the parameter
+/// - `$NAME|default$` is substituted with the value of the parameter or the
+///   default when the parameter is not given
+/// - `[[NAME] ... ]` is kept only when the parameter is given
+/// - `[[!NAME] ... ]` is kept only when the parameter is not given
+///
+/// Expansion is textual and the result can be parsed as usual. References to
+/// parameters that are not given (and have no default) are left untouched.
+///
+/// ```
+/// use jomini::{Parameters, TextTape};
+///
+/// let data = b"add_gold = $AMOUNT$ [[BONUS] add_prestige = $BONUS$ ] [[!BONUS] add_piety = 1 ]";
+/// let mut params = Parameters::new();
+/// params.insert("AMOUNT", "100");
+/// let expanded = params.expand(&data[..])?;
+/// assert_eq!(expanded.as_slice(), &b"add_gold = 100   add_piety = 1 "[..]);
+///
+/// let tape = TextTape::from_slice(&expanded)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Parameters {
+    values: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Parameters {
+    /// Create an empty set of parameters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of a parameter
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.values
+            .insert(name.as_bytes().to_vec(), value.as_bytes().to_vec());
+    }
+
+    /// Return the value of a parameter
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.values.get(name.as_bytes()).map(|x| x.as_slice())
+    }
+
+    /// Return the data with the parameters substituted and the conditional
+    /// blocks resolved
+    pub fn expand(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::with_capacity(data.len());
+        self.expand_into(data, 0, &mut out)?;
+        Ok(out)
+    }
+
+    fn expand_into(&self, data: &[u8], base: usize, out: &mut Vec<u8>) -> Result<(), Error> {
+        let mut ind = 0;
+        while ind < data.len() {
+            match data[ind] {
+                b'$' => {
+                    ind += self.substitute(&data[ind..], out);
+                }
+                b'[' if data.get(ind + 1) == Some(&b'[') => {
+                    ind += self.conditional(&data[ind..], base + ind, out)?;
+                }
+                x => {
+                    out.push(x);
+                    ind += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the substitution of the parameter at the start of data and
+    /// returns the number of bytes consumed
+    fn substitute(&self, data: &[u8], out: &mut Vec<u8>) -> usize {
+        let end = data[1..]
+            .iter()
+            .position(|&x| x == b'$' || !is_parameter_char(x));
+
+        let end = match end {
+            Some(x) if data[x + 1] == b'$' => x + 1,
+            _ => {
+                out.push(b'$');
+                return 1;
+            }
+        };
+
+        let reference = &data[1..end];
+        let (name, default) = match reference.iter().position(|&x| x == b'|') {
+            Some(x) => (&reference[..x], Some(&reference[x + 1..])),
+            None => (reference, None),
+        };
+
+        match (self.values.get(name), default) {
+            (Some(value), _) => out.extend_from_slice(value),
+            (None, Some(default)) => out.extend_from_slice(default),
+            (None, None) => out.extend_from_slice(&data[..=end]),
+        }
+
+        end + 1
+    }
+
+    /// Writes the contents of the conditional block at the start of data if
+    /// the condition holds and returns the number of bytes consumed
+    fn conditional(&self, data: &[u8], offset: usize, out: &mut Vec<u8>) -> Result<usize, Error> {
+        let name_end = data[2..].iter().position(|&x| x == b']').map(|x| x + 2);
+        let name_end = name_end.ok_or_else(|| unterminated(offset))?;
+        let condition = &data[2..name_end];
+        let (negated, name) = match condition {
+            [b'!', rest @ ..] => (true, rest),
+            _ => (false, condition),
+        };
+
+        let mut depth = 1;
+        let mut end = name_end + 1;
+        while depth > 0 {
+            match data.get(end) {
+                Some(b'[') => depth += 1,
+                Some(b']') => depth -= 1,
+                Some(_) => {}
+                None => return Err(unterminated(offset)),
+            }
+            end += 1;
+        }
+
+        if self.values.contains_key(name) != negated {
+            let body_start = name_end + 1;
+            self.expand_into(&data[body_start..end - 1], offset + body_start, out)?;
+        }
+
+        Ok(end)
+    }
+}
+
+fn is_parameter_char(x: u8) -> bool {
+    x.is_ascii_alphanumeric() || x == b'_' || x == b'|' || x == b'.' || x == b'-'
+}
+
+fn unterminated(offset: usize) -> Error {
+    Error::new(ErrorKind::InvalidSyntax {
+        msg: String::from("unterminated conditional block"),
+        offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(params: &[(&str, &str)], data: &[u8]) -> Result<String, Error> {
+        let mut parameters = Parameters::new();
+        for (name, value) in params {
+            parameters.insert(name, value);
+        }
+
+        parameters
+            .expand(data)
+            .map(|x| String::from_utf8(x).unwrap())
+    }
+
+    #[test]
+    fn test_substitution() {
+        let data = b"a = $A$ b = \"$A$_key\" c = $B$ d = $B|10$ e = $A|10$";
+        assert_eq!(
+            expand(&[("A", "yes")], &data[..]).unwrap(),
+            "a = yes b = \"yes_key\" c = $B$ d = 10 e = yes"
+        );
+    }
+
+    #[test]
+    fn test_lone_dollar() {
+        let data = b"a = \"costs $\" b = $A c";
+        assert_eq!(
+            expand(&[("A", "1")], &data[..]).unwrap(),
+            "a = \"costs $\" b = $A c"
+        );
+    }
+
+    #[test]
+    fn test_nested_conditionals() {
+        let data = b"[[A] a = { [[B] b = $B$ ] [[!B] c = @[1 + 1] ] } ]";
+        assert_eq!(
+            expand(&[("A", "1")], &data[..]).unwrap(),
+            " a = {   c = @[1 + 1]  } "
+        );
+        assert_eq!(
+            expand(&[("A", "1"), ("B", "2")], &data[..]).unwrap(),
+            " a = {  b = 2   } "
+        );
+        assert_eq!(expand(&[], &data[..]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_unterminated_conditional() {
+        let err = expand(&[], b"a = 1 [[A] b = 2").unwrap_err();
+        assert_eq!(err.offset(), Some(6));
+        assert!(expand(&[], b"a = 1 [[A").is_err());
+    }
+}
@@ -1,7 +1,8 @@
 use crate::{
-    DeserializeError, DeserializeErrorKind, Encoding, Operator, Scalar, TextTape, TextToken,
+    DeserializeError, DeserializeErrorKind, Encoding, Operator, Rgb, Scalar, TextTape, TextToken,
 };
 use std::borrow::Cow;
+use std::convert::TryFrom;
 
 pub type KeyValue<'data, 'tokens, E> = (
     ScalarReader<'data, E>,
@@ -28,7 +29,7 @@ fn next_idx_header(tokens: &[TextToken], idx: usize) -> usize {
 /// Calculate what index the next value is. This assumes that a header + value
 /// is one value
 #[inline]
-fn next_idx(tokens: &[TextToken], idx: usize) -> usize {
+pub(crate) fn next_idx(tokens: &[TextToken], idx: usize) -> usize {
     match tokens[idx] {
         TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
         TextToken::Operator(_) => idx + 2,
@@ -69,6 +70,25 @@ where
         }
     }
 
+    /// Interpret value as a string, writing it to the scratch buffer when it
+    /// can't be borrowed from the data. See `Encoding::decode_scratch`.
+    #[cfg(feature = "derive")]
+    #[inline]
+    pub(crate) fn read_str_scratch(
+        &self,
+        scratch: &mut String,
+    ) -> Result<Option<&'data str>, DeserializeError> {
+        match &self {
+            Reader::Scalar(x) => Ok(x.encoding.decode_scratch(x.scalar.view_data(), scratch)),
+            Reader::Value(x) => x
+                .read_scalar()
+                .map(|s| x.encoding.decode_scratch(s.view_data(), scratch)),
+            _ => Err(DeserializeError {
+                kind: DeserializeErrorKind::Unsupported(String::from("not a scalar")),
+            }),
+        }
+    }
+
     /// Interpret value as a string
     #[inline]
     pub fn read_string(&self) -> Result<String, DeserializeError> {
@@ -103,6 +123,7 @@ pub struct ObjectReader<'data, 'tokens, E> {
     encoding: E,
     val_ind: usize,
     seen: Vec<bool>,
+    hidden: bool,
 }
 
 impl<'data, 'tokens, E> ObjectReader<'data, 'tokens, E>
@@ -119,9 +140,18 @@ where
             val_ind: 0,
             encoding,
             seen: Vec::new(),
+            hidden: false,
         }
     }
 
+    /// Returns true if the object is a hidden object: an object that was
+    /// synthesized from the trailing key value pairs of an array
+    /// (`levels={ 10 0=2 1=2 }`) and so was not enclosed in braces in the
+    /// original data.
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
     /// Return the number of key value pairs that the object contains
     pub fn fields_len(&self) -> usize {
         let mut ind = self.token_ind;
@@ -305,12 +335,42 @@ where
     fn decode<'a>(&self, data: &'a [u8]) -> Cow<'a, str> {
         self.encoding.decode(data)
     }
+
+    #[inline]
+    fn decode_scratch<'a>(&self, data: &'a [u8], scratch: &mut String) -> Option<&'a str> {
+        self.encoding.decode_scratch(data, scratch)
+    }
 }
 
 impl<'data, 'tokens, E> ValueReader<'data, 'tokens, E>
 where
     E: Encoding + Clone,
 {
+    /// The value that follows a header (the array of `rgb { 1 2 3 }`)
+    #[inline]
+    pub(crate) fn header_value(&self) -> Option<ValueReader<'data, 'tokens, E>> {
+        match self.tokens[self.value_ind] {
+            TextToken::Header(_) => Some(ValueReader {
+                value_ind: self.value_ind + 1,
+                tokens: self.tokens,
+                encoding: self.encoding.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the value is a block without any values (`{}`)
+    #[cfg(feature = "derive")]
+    #[inline]
+    pub(crate) fn is_empty_block(&self) -> bool {
+        match self.tokens[self.value_ind] {
+            TextToken::Array(end) | TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                end == self.value_ind + 1
+            }
+            _ => false,
+        }
+    }
+
     /// Interpret the current value as string
     #[inline]
     pub fn read_str(&self) -> Result<Cow<'data, str>, DeserializeError> {
@@ -343,6 +403,52 @@ where
             })
     }
 
+    /// Interpret the current value as a color: an `rgb` header followed by
+    /// three channels. This is the text equivalent of a binary `Rgb` token.
+    ///
+    /// ```
+    /// use jomini::{Rgb, TextTape};
+    ///
+    /// let tape = TextTape::from_slice(b"color = rgb { 110 27 27 }")?;
+    /// let mut reader = tape.windows1252_reader();
+    /// let (_key, _op, value) = reader.next_field().unwrap();
+    /// assert_eq!(value.read_rgb()?, Rgb { r: 110, g: 27, b: 27 });
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn read_rgb(&self) -> Result<Rgb, DeserializeError> {
+        let err = || DeserializeError {
+            kind: DeserializeErrorKind::Unsupported(String::from("not an rgb value")),
+        };
+
+        match self.tokens[self.value_ind] {
+            TextToken::Header(x) if x.view_data() == b"rgb" => {}
+            _ => return Err(err()),
+        }
+
+        let mut values = self.header_value().ok_or_else(err)?.read_array()?;
+        let mut channels = [0u32; 3];
+        if values.values_len() != channels.len() {
+            return Err(err());
+        }
+
+        for channel in channels.iter_mut() {
+            *channel = values
+                .next_value()
+                .ok_or_else(err)?
+                .read_scalar()?
+                .to_u64()
+                .ok()
+                .and_then(|x| u32::try_from(x).ok())
+                .ok_or_else(err)?;
+        }
+
+        Ok(Rgb {
+            r: channels[0],
+            g: channels[1],
+            b: channels[2],
+        })
+    }
+
     /// Interpret the current value as an object
     #[inline]
     pub fn read_object(&self) -> Result<ObjectReader<'data, 'tokens, E>, DeserializeError> {
@@ -354,6 +460,7 @@ where
                 end_ind: ind,
                 seen: Vec::new(),
                 encoding: self.encoding.clone(),
+                hidden: matches!(self.tokens[self.value_ind], TextToken::HiddenObject(_)),
             }),
 
             // An array can be an object if it is empty
@@ -364,6 +471,7 @@ where
                 end_ind: ind,
                 encoding: self.encoding.clone(),
                 seen: Vec::new(),
+                hidden: false,
             }),
             _ => Err(DeserializeError {
                 kind: DeserializeErrorKind::Unsupported(String::from("not an object")),
@@ -452,7 +560,7 @@ where
 mod tests {
     use super::*;
 
-    fn iterate_array<'data, 'tokens, E>(mut reader: ArrayReader<E>)
+    fn iterate_array<E>(mut reader: ArrayReader<E>)
     where
         E: crate::Encoding + Clone,
     {
@@ -473,7 +581,7 @@ mod tests {
         }
     }
 
-    fn iterate_object<'data, 'tokens, E>(mut reader: ObjectReader<E>)
+    fn iterate_object<E>(mut reader: ObjectReader<E>)
     where
         E: crate::Encoding + Clone,
     {
@@ -562,6 +670,8 @@ mod tests {
         assert_eq!(value1, String::from("10"));
 
         let mut hidden = nested.next_value().unwrap().read_object().unwrap();
+        assert!(hidden.is_hidden());
+        assert!(!reader.is_hidden());
         assert_eq!(hidden.fields_len(), 2);
         let (key, _op, value) = hidden.next_field().unwrap();
         assert_eq!(key.read_string(), String::from("0"));
@@ -654,7 +764,7 @@ mod tests {
         let mut reader = tape.windows1252_reader();
         let mut count = 0;
         while let Some((_key, mut entries)) = reader.next_fields() {
-            for (_i, (_op, value)) in entries.drain(..).enumerate() {
+            for (_op, value) in entries.drain(..) {
                 count += value.read_scalar().map(|_| 1).unwrap_or(0);
             }
         }
@@ -0,0 +1,227 @@
+use super::reader::next_idx;
+use crate::data::{is_boundary, is_whitespace};
+use crate::util::{contains_zero_byte, le_u64, repeat_byte};
+use crate::TextToken;
+
+/// Returns the tape indices of the values of the given key at any depth.
+///
+/// When the data that the tape was parsed from is known, the data is scanned
+/// for the key's bytes and each candidate is then validated against the
+/// tape, so the tape is only consulted at candidates. Otherwise the tape is
+/// walked.
+pub(crate) fn find_keys(
+    data: &[u8],
+    tokens: &[TextToken],
+    key: &[u8],
+    first_only: bool,
+) -> Vec<usize> {
+    let mut result = Vec::new();
+    if key.is_empty() || tokens.is_empty() {
+        return result;
+    }
+
+    if data.is_empty() {
+        walk(tokens, 0, tokens.len(), true, key, first_only, &mut result);
+        return result;
+    }
+
+    let mut pos = 0;
+    while let Some(found) = find_substring(&data[pos..], key) {
+        let offset = pos + found;
+        pos = offset + 1;
+
+        let before = offset.checked_sub(1).map(|x| data[x]);
+        if before.is_some_and(|x| x != b'"' && !is_boundary(x)) {
+            continue;
+        }
+
+        let mut after = offset + key.len();
+        if data.get(after) == Some(&b'"') {
+            after += 1;
+        } else if data.get(after).is_some_and(|&x| !is_boundary(x)) {
+            continue;
+        }
+
+        while data.get(after).is_some_and(|&x| is_whitespace(x)) {
+            after += 1;
+        }
+
+        if !matches!(data.get(after), Some(b'=') | Some(b'<') | Some(b'>')) {
+            continue;
+        }
+
+        let idx = match token_at(data, tokens, offset) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        if tokens[idx].as_scalar().map(|x| x.view_data().len()) != Some(key.len()) {
+            continue;
+        }
+
+        let value_idx = match tokens.get(idx + 1) {
+            Some(TextToken::Operator(_)) => idx + 2,
+            _ => idx + 1,
+        };
+
+        if value_idx < tokens.len() && !matches!(tokens[value_idx], TextToken::End(_)) {
+            result.push(value_idx);
+            if first_only {
+                break;
+            }
+        }
+    }
+
+    result
+}
+
+/// Binary search for the token whose scalar starts at the given offset of
+/// the data. Scalars are written to the tape in the order they appear in the
+/// data, so their offsets are increasing.
+fn token_at(data: &[u8], tokens: &[TextToken], offset: usize) -> Option<usize> {
+    let start = data.as_ptr() as usize;
+    let token_offset = |idx: usize| {
+        tokens[idx]
+            .as_scalar()
+            .map(|x| (x.view_data().as_ptr() as usize).wrapping_sub(start))
+    };
+
+    let mut lo = 0;
+    let mut hi = tokens.len();
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let found = (mid..hi).find_map(|x| token_offset(x).map(|off| (x, off)));
+        match found {
+            None => hi = mid,
+            Some((idx, off)) if off == offset => return Some(idx),
+            Some((idx, off)) if off < offset => lo = idx + 1,
+            Some(_) => hi = mid,
+        }
+    }
+
+    None
+}
+
+fn walk(
+    tokens: &[TextToken],
+    mut idx: usize,
+    end: usize,
+    is_object: bool,
+    key: &[u8],
+    first_only: bool,
+    result: &mut Vec<usize>,
+) {
+    while idx < end {
+        if first_only && !result.is_empty() {
+            return;
+        }
+
+        let mut value_idx = idx;
+        if is_object {
+            value_idx = match tokens.get(idx + 1) {
+                Some(TextToken::Operator(_)) => idx + 2,
+                _ => idx + 1,
+            };
+
+            if value_idx >= end {
+                return;
+            }
+
+            if tokens[idx]
+                .as_scalar()
+                .is_some_and(|x| x.view_data() == key)
+            {
+                result.push(value_idx);
+            }
+        }
+
+        match tokens[value_idx] {
+            TextToken::Object(x) | TextToken::HiddenObject(x) => {
+                walk(tokens, value_idx + 1, x, true, key, first_only, result)
+            }
+            TextToken::Array(x) => walk(tokens, value_idx + 1, x, false, key, first_only, result),
+            _ => {}
+        }
+
+        idx = next_idx(tokens, value_idx);
+    }
+}
+
+/// Finds the first occurrence of the needle in the haystack, scanning for
+/// the needle's first byte a word at a time
+fn find_substring(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let first = needle[0];
+    let mut pos = 0;
+    while pos + needle.len() <= haystack.len() {
+        let candidate = pos + find_byte(&haystack[pos..], first)?;
+        if haystack[candidate..].starts_with(needle) {
+            return Some(candidate);
+        }
+        pos = candidate + 1;
+    }
+
+    None
+}
+
+fn find_byte(haystack: &[u8], byte: u8) -> Option<usize> {
+    let repeated = repeat_byte(byte);
+    let mut pos = 0;
+    while pos + 8 <= haystack.len() {
+        if contains_zero_byte(le_u64(&haystack[pos..]) ^ repeated) {
+            break;
+        }
+        pos += 8;
+    }
+
+    haystack[pos..]
+        .iter()
+        .position(|&x| x == byte)
+        .map(|x| x + pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TextTape;
+
+    fn check(data: &[u8], key: &str) {
+        let tape = TextTape::from_slice(data).unwrap();
+        let merged = TextTape::from_slices(vec![data]).unwrap();
+        assert_eq!(tape.tokens(), merged.tokens());
+        assert_eq!(tape.find_all(key), merged.find_all(key), "{}", key);
+        assert_eq!(tape.find_key(key), merged.find_key(key), "{}", key);
+    }
+
+    #[test]
+    fn test_find_key() {
+        let data = b"checksum=\"abc\" save={ battle={ name=a } xbattle=1 battle > 2 } \
+            names={ \"battle\" battle } list={ { battle=3 } } \"battle\"=4 levels={ 10 battle=5 }";
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+        let values: Vec<_> = tape
+            .find_all("battle")
+            .into_iter()
+            .map(|x| tape.tokens()[x].clone())
+            .collect();
+
+        assert_eq!(values.len(), 5);
+        assert!(matches!(values[0], crate::TextToken::Object(_)));
+        let scalars: Vec<_> = values[1..]
+            .iter()
+            .filter_map(|x| x.as_scalar())
+            .map(|x| x.view_data())
+            .collect();
+        assert_eq!(scalars, vec![&b"2"[..], b"3", b"4", b"5"]);
+
+        let checksum = tape.find_key("checksum").unwrap();
+        assert_eq!(
+            tape.tokens()[checksum].as_scalar().map(|x| x.view_data()),
+            Some(&b"abc"[..])
+        );
+        assert_eq!(tape.find_key("missing"), None);
+
+        for key in &[
+            "battle", "checksum", "name", "xbattle", "levels", "missing", "",
+        ] {
+            check(&data[..], key);
+        }
+    }
+}
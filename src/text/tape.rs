@@ -1,5 +1,13 @@
-use crate::{data::is_boundary, ObjectReader, Utf8Encoding, Windows1252Encoding};
-use crate::{Error, ErrorKind, Scalar};
+use super::reader::next_idx;
+use super::search::find_keys;
+use crate::data::{is_boundary, validate_structure, TapeNode, TokenCounter};
+#[cfg(feature = "metrics")]
+use crate::observer::{Observer, ParseObserver};
+use crate::trace::span;
+use crate::{Error, ErrorKind, HiddenObjectStrategy, Scalar};
+use crate::{ObjectReader, TextVisitor, Utf8Encoding, Windows1252Encoding};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 
 /// An operator token
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -42,7 +50,33 @@ pub enum TextToken<'a> {
     /// a = { 10 a=b c=d}
     /// ```
     ///
-    /// In the above example, a and c would be part of the hidden object
+    /// In the above example, a and c would be part of the hidden object.
+    ///
+    /// A hidden object is synthesized structure: there are no braces around
+    /// it in the data. Its `End` token does not correspond to a closing brace
+    /// and is immediately followed by the `End` of the enclosing array, so
+    /// those writing the tape back out should skip both the hidden object's
+    /// opening and closing brace to reproduce the original form.
+    ///
+    /// ```
+    /// use jomini::{Scalar, TextTape, TextToken};
+    ///
+    /// let tape = TextTape::from_slice(b"levels={ 10 0=2 }")?;
+    /// assert_eq!(
+    ///     tape.tokens(),
+    ///     &[
+    ///         TextToken::Scalar(Scalar::new(b"levels")),
+    ///         TextToken::Array(7),
+    ///         TextToken::Scalar(Scalar::new(b"10")),
+    ///         TextToken::HiddenObject(6),
+    ///         TextToken::Scalar(Scalar::new(b"0")),
+    ///         TextToken::Scalar(Scalar::new(b"2")),
+    ///         TextToken::End(3),
+    ///         TextToken::End(1),
+    ///     ]
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     HiddenObject(usize),
 
     /// Extracted scalar value
@@ -81,9 +115,63 @@ impl<'a> TextToken<'a> {
     }
 }
 
+/// How top level entries are combined when a tape is merged with another
+/// document. See `TextTape::merge`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Last in, only served: entries of a key in the later document replace
+    /// all the entries of that key from the earlier document. This is how
+    /// most game files (ideas, events, decisions) are overridden and is what
+    /// `TextTapeParser::parse_slices` uses.
+    Lios,
+
+    /// First in, only served: entries of a key in the later document are
+    /// dropped when the earlier document already has that key, which some
+    /// games use for a subset of their files.
+    Fios,
+
+    /// Entries of the later document are appended after those of the
+    /// earlier document, keeping every duplicate
+    Append,
+}
+
+/// How many tokens to reserve for the tape before parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum CapacityHint {
+    /// Guess from the length of the data
+    #[default]
+    Estimate,
+
+    /// A fixed number of tokens
+    Tokens(usize),
+
+    /// Scale the ratio of tokens to bytes of a previous parse
+    Density { tokens: usize, bytes: usize },
+}
+
+impl CapacityHint {
+    fn tokens(self, data: &[u8]) -> usize {
+        match self {
+            CapacityHint::Estimate => data.len() / 5,
+            CapacityHint::Tokens(x) => x,
+            CapacityHint::Density { tokens, bytes } => {
+                let tokens = tokens as u128 * data.len() as u128 / bytes as u128;
+                usize::try_from(tokens).unwrap_or(usize::MAX)
+            }
+        }
+    }
+}
+
 /// Creates a parser that a writes to a text tape
-#[derive(Debug, Default)]
-pub struct TextTapeParser;
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TextTapeParser {
+    hidden_object_strategy: HiddenObjectStrategy,
+    exact_capacity: bool,
+    capacity: CapacityHint,
+    max_depth: Option<usize>,
+    #[cfg(feature = "metrics")]
+    observer: Option<Observer>,
+}
 
 impl TextTapeParser {
     /// Create a text parser
@@ -91,8 +179,129 @@ impl TextTapeParser {
         TextTapeParser::default()
     }
 
+    /// Set the behavior when an array turns into an object partway through
+    /// (`levels={ 10 0=2 1=2 }`)
+    ///
+    /// ```
+    /// use jomini::{HiddenObjectStrategy, TextTape};
+    ///
+    /// let data = b"levels={ 10 0=2 1=2 }";
+    /// let tape = TextTape::parser()
+    ///     .on_hidden_object(HiddenObjectStrategy::Flatten)
+    ///     .parse_slice(data)?;
+    /// assert_eq!(tape.tokens(), TextTape::from_slice(b"levels={ 10 0 2 1 2 }")?.tokens());
+    ///
+    /// let err = TextTape::parser()
+    ///     .on_hidden_object(HiddenObjectStrategy::Error)
+    ///     .parse_slice(data);
+    /// assert!(err.is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn on_hidden_object(mut self, strategy: HiddenObjectStrategy) -> Self {
+        self.hidden_object_strategy = strategy;
+        self
+    }
+
+    /// Return an error when objects and arrays are nested deeper than the
+    /// given depth, so that untrusted data can't make code that walks the
+    /// tape recurse without bound. There is no limit by default.
+    ///
+    /// ```
+    /// use jomini::{ErrorKind, TextTape};
+    ///
+    /// let parser = TextTape::parser().max_depth(2);
+    /// assert!(parser.parse_slice(b"a={ b={ c=d } }").is_ok());
+    ///
+    /// let err = parser.parse_slice(b"a={ b={ c={ d=e } } }").unwrap_err();
+    /// assert!(matches!(err.kind(), ErrorKind::LimitExceeded { offset: 10, .. }));
+    /// ```
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Count the tokens in a quick pass over the data before parsing, so
+    /// that the tape is allocated once with the capacity that it needs
+    /// instead of estimating the capacity and growing the tape as needed.
+    ///
+    /// This trades some parsing speed for predictable memory usage.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let data = b"a=1 b={ c=d } e={ 1 2 }";
+    /// let tape = TextTape::parser().exact_capacity(true).parse_slice(data)?;
+    /// assert_eq!(tape.tokens(), TextTape::from_slice(data)?.tokens());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn exact_capacity(mut self, enabled: bool) -> Self {
+        self.exact_capacity = enabled;
+        self
+    }
+
+    /// Reserve room for the given number of tokens before parsing instead of
+    /// estimating the number from the length of the data. The estimate is
+    /// poor for files that are mostly comments or long strings. The tape
+    /// still grows if more tokens are needed.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let tape = TextTape::parser().initial_capacity(4).parse_slice(b"a=1 b=2")?;
+    /// assert_eq!(tape.tokens().len(), 4);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn initial_capacity(mut self, tokens: usize) -> Self {
+        self.capacity = CapacityHint::Tokens(tokens);
+        self
+    }
+
+    /// Reserve room for tokens based on a previous parse of a similar file
+    /// (eg: an earlier save from the same game), so that the tape is sized
+    /// from the number of tokens per byte that the previous file had. Tapes
+    /// that weren't parsed from a single slice carry no hint and leave the
+    /// estimate unchanged.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let previous = TextTape::from_slice(b"# comment\na=1")?;
+    /// let tape = TextTape::parser()
+    ///     .capacity_hint(&previous)
+    ///     .parse_slice(b"# comment\nb=2")?;
+    /// assert_eq!(tape.tokens().len(), 2);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn capacity_hint(mut self, tape: &TextTape) -> Self {
+        let bytes = tape.source().len();
+        if bytes != 0 {
+            let tokens = tape.tokens().len();
+            self.capacity = CapacityHint::Density { tokens, bytes };
+        }
+        self
+    }
+
+    /// Report the bytes parsed and tokens produced by each parse to the
+    /// observer. See `ParseObserver`.
+    #[cfg(feature = "metrics")]
+    pub fn observer(mut self, observer: &'static dyn ParseObserver) -> Self {
+        self.observer = Some(Observer(observer));
+        self
+    }
+
+    /// Walk the text data and give each key, value, and brace to the
+    /// visitor without building a tape. The structure of the data is not
+    /// validated, which makes this the fastest way to search or gather
+    /// statistics when the tape isn't needed. See `TextVisitor`.
+    pub fn visit<'a, V>(self, data: &'a [u8], visitor: &mut V) -> Result<(), Error>
+    where
+        V: TextVisitor<'a>,
+    {
+        super::event::visit(data, visitor)
+    }
+
     /// Parse the text format and return the data tape
-    pub fn parse_slice(self, data: &[u8]) -> Result<TextTape, Error> {
+    pub fn parse_slice(self, data: &[u8]) -> Result<TextTape<'_>, Error> {
         let mut res = TextTape::default();
         self.parse_slice_into_tape(data, &mut res)?;
         Ok(res)
@@ -104,41 +313,421 @@ impl TextTapeParser {
         data: &'a [u8],
         tape: &mut TextTape<'a>,
     ) -> Result<(), Error> {
+        let span = span!("parse_text", bytes = data.len(); tokens);
+        tape.data = data;
         let token_tape = &mut tape.token_tape;
         token_tape.clear();
-        token_tape.reserve(data.len() / 5);
+        if self.exact_capacity {
+            token_tape.reserve_exact(token_count(data));
+        } else {
+            token_tape.reserve(self.capacity.tokens(data));
+        }
+
         let mut state = ParserState {
             data,
             original_length: data.len(),
             token_tape,
+            hidden_object_strategy: self.hidden_object_strategy,
+            max_depth: self.max_depth.unwrap_or(usize::MAX),
+            depth: 0,
         };
 
         state.parse()?;
+        span.record("tokens", tape.token_tape.len());
+
+        #[cfg(feature = "metrics")]
+        if let Some(Observer(observer)) = self.observer {
+            observer.bytes_parsed(data.len());
+            observer.tokens_produced(tape.token_tape.len());
+        }
+
         Ok(())
     }
+
+    /// Parse several documents that form a single logical namespace (eg: all
+    /// the files in a game's `common/ideas` directory) into one tape.
+    ///
+    /// Documents are applied in order. When a top level key appears in a later
+    /// document, all the top level entries of that key from earlier documents
+    /// are dropped, which mirrors how the games let later files override
+    /// earlier definitions. Duplicate keys within a single document are kept.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let base = b"a = { x = 1 } b = { x = 2 }";
+    /// let patch = b"a = { x = 3 }";
+    /// let tape = TextTape::parser().parse_slices(vec![&base[..], &patch[..]])?;
+    /// let merged = TextTape::from_slice(b"b = { x = 2 } a = { x = 3 }")?;
+    /// assert_eq!(tape.tokens(), merged.tokens());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parse_slices<'a, I>(self, data: I) -> Result<TextTape<'a>, Error>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut tapes = Vec::new();
+        for d in data {
+            tapes.push(self.parse_slice(d)?);
+        }
+
+        // (document index, key, start index, end index) of every top level entry
+        let mut entries = Vec::new();
+        for (doc, tape) in tapes.iter().enumerate() {
+            let doc_entries = top_level_entries(tape.tokens());
+            entries.extend(
+                doc_entries
+                    .into_iter()
+                    .map(|(key, start, end)| (doc, key, start, end)),
+            );
+        }
+
+        let mut last_doc = HashMap::new();
+        for &(doc, key, _, _) in entries.iter() {
+            last_doc.insert(key, doc);
+        }
+
+        let mut res = TextTape::new();
+        let total = tapes.iter().map(|x| x.tokens().len()).sum();
+        res.token_tape.reserve(total);
+        for &(doc, key, start, end) in entries.iter() {
+            if last_doc.get(&key) != Some(&doc) {
+                continue;
+            }
+
+            extend_tape(&mut res.token_tape, &tapes[doc].tokens()[start..end], start);
+        }
+
+        Ok(res)
+    }
+
+    /// Parse data that may be corrupted (eg: from disk issues or an
+    /// interrupted write). A section that fails to parse is skipped and
+    /// parsing resumes at the next top level key, so the returned tape
+    /// contains everything that could be recovered along with an error for
+    /// each section that was skipped.
+    ///
+    /// Top level keys are recognized as lines that start without
+    /// indentation, which is how the games write saves.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let data = b"a=1\nb={\n  c={ d=e\n}\nf=2\n";
+    /// let (tape, errors) = TextTape::parser().parse_slice_lossy(&data[..]);
+    /// assert_eq!(tape.tokens(), TextTape::from_slice(b"a=1 f=2")?.tokens());
+    /// assert_eq!(errors.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parse_slice_lossy(self, data: &[u8]) -> (TextTape<'_>, Vec<Error>) {
+        let mut res = TextTape::new();
+        let mut errors = Vec::new();
+        if self.parse_slice_into_tape(data, &mut res).is_ok() {
+            return (res, errors);
+        }
+
+        res.token_tape.clear();
+        let mut section = TextTape::new();
+        for (start, end) in section_ranges(data, true) {
+            if self
+                .parse_slice_into_tape(&data[start..end], &mut section)
+                .is_ok()
+            {
+                extend_tape(&mut res.token_tape, section.tokens(), 0);
+                continue;
+            }
+
+            // The section is unbalanced, so fall back to splitting on every
+            // unindented line to salvage the keys that follow the corruption
+            let sub_data = &data[start..end];
+            for (sub_start, sub_end) in section_ranges(sub_data, false) {
+                match self.parse_slice_into_tape(&sub_data[sub_start..sub_end], &mut section) {
+                    Ok(()) => extend_tape(&mut res.token_tape, section.tokens(), 0),
+                    Err(e) => errors.push(e.offset_by(start + sub_start)),
+                }
+            }
+        }
+
+        (res, errors)
+    }
+
+    /// Parse the text format into several tapes that each hold a run of
+    /// consecutive top level entries totalling at least `segment_len` bytes
+    /// of input.
+    ///
+    /// Large saves produce a token tape of hundreds of megabytes, and growing
+    /// a single contiguous vector that large leads to spikes in memory usage
+    /// whenever it reallocates. Each segment is a standalone tape that is
+    /// sized for its own slice of the input, so memory grows incrementally.
+    ///
+    /// Top level entries are recognized as lines that start outside of any
+    /// braces, which is how the games write saves.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let data = b"a=1\nb={ c=d }\ne=2\n";
+    /// let segments = TextTape::parser().parse_slice_segmented(&data[..], 8)?;
+    /// assert_eq!(segments.len(), 2);
+    /// assert_eq!(segments[0].tokens(), TextTape::from_slice(b"a=1 b={ c=d }")?.tokens());
+    /// assert_eq!(segments[1].tokens(), TextTape::from_slice(b"e=2")?.tokens());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn parse_slice_segmented(
+        self,
+        data: &[u8],
+        segment_len: usize,
+    ) -> Result<Vec<TextTape<'_>>, Error> {
+        let sections = section_ranges(data, true);
+        let mut segments = Vec::new();
+        let mut ind = 0;
+        while ind < sections.len() {
+            let start = sections[ind].0;
+            let mut end_ind = ind;
+            while end_ind + 1 < sections.len() && sections[end_ind].1 - start < segment_len {
+                end_ind += 1;
+            }
+
+            loop {
+                let end = sections[end_ind].1;
+                match self.parse_slice(&data[start..end]) {
+                    Ok(tape) => {
+                        segments.push(tape);
+                        break;
+                    }
+
+                    // A value may continue onto an unindented line (`a=\n1`),
+                    // so the segment is extended until the value is complete
+                    Err(e) if e.is_eof() && end_ind + 1 < sections.len() => end_ind += 1,
+                    Err(e) => return Err(e.offset_by(start)),
+                }
+            }
+
+            ind = end_ind + 1;
+        }
+
+        Ok(segments)
+    }
+}
+
+/// Returns the number of tokens that parsing the data is expected to write
+/// to the tape
+fn token_count(data: &[u8]) -> usize {
+    let mut counter = TokenCounter::new();
+    let mut d = data;
+    while let Some(&c) = d.first() {
+        d = match c {
+            b' ' | b'\t' | b'\n' | b'\r' => &d[1..],
+            b'#' => match d.iter().position(|&x| x == b'\n') {
+                Some(ind) => &d[ind..],
+                None => &[],
+            },
+            b'{' => {
+                counter.open();
+                &d[1..]
+            }
+            b'}' => {
+                counter.close();
+                &d[1..]
+            }
+            b'=' => {
+                counter.operator(false);
+                &d[1..]
+            }
+            b'<' | b'>' => {
+                counter.operator(true);
+                if d.get(1) == Some(&b'=') {
+                    &d[2..]
+                } else {
+                    &d[1..]
+                }
+            }
+            b'"' => match parse_quote_scalar(d) {
+                Ok((_, rest)) => {
+                    counter.scalar();
+                    rest
+                }
+                Err(_) => break,
+            },
+            b'@' if d.get(1) == Some(&b'[') => {
+                counter.scalar();
+                match d.iter().position(|&x| x == b']') {
+                    Some(ind) => &d[ind + 1..],
+                    None => break,
+                }
+            }
+            _ => {
+                counter.scalar();
+                split_at_scalar(d).1
+            }
+        };
+    }
+
+    counter.count()
+}
+
+/// Appends tokens taken from another tape, where `start` is the index that
+/// the first token had in that tape.
+/// Returns the key, start index, and end index of every top level entry
+fn top_level_entries<'a>(tokens: &[TextToken<'a>]) -> Vec<(Option<&'a [u8]>, usize, usize)> {
+    let mut entries = Vec::new();
+    let mut ind = 0;
+    while ind < tokens.len() {
+        let value_ind = match tokens.get(ind + 1) {
+            Some(TextToken::Operator(_)) => ind + 2,
+            Some(_) => ind + 1,
+            None => break,
+        };
+
+        if value_ind >= tokens.len() {
+            break;
+        }
+
+        let end = next_idx(tokens, value_ind);
+        let key = tokens[ind].as_scalar().map(|x| x.view_data());
+        entries.push((key, ind, end));
+        ind = end;
+    }
+
+    entries
+}
+
+pub(crate) fn extend_tape<'a>(
+    dst: &mut Vec<TextToken<'a>>,
+    tokens: &[TextToken<'a>],
+    start: usize,
+) {
+    let new_start = dst.len();
+    let shift = |x: usize| x - start + new_start;
+    dst.extend(tokens.iter().map(|token| match *token {
+        TextToken::Array(x) => TextToken::Array(shift(x)),
+        TextToken::Object(x) => TextToken::Object(shift(x)),
+        TextToken::HiddenObject(x) => TextToken::HiddenObject(shift(x)),
+        TextToken::End(x) => TextToken::End(shift(x)),
+        ref x => x.clone(),
+    }));
+}
+
+/// Splits the data into ranges that each start on a line that begins with a
+/// top level key. When `balanced` is set, lines that are nested inside braces
+/// are not considered the start of a range.
+fn section_ranges(data: &[u8], balanced: bool) -> Vec<(usize, usize)> {
+    let mut starts = vec![0];
+    let mut depth = 0usize;
+    let mut line_start = false;
+    let mut i = 0;
+    while i < data.len() {
+        let c = data[i];
+        if line_start
+            && (!balanced || depth == 0)
+            && !c.is_ascii_whitespace()
+            && c != b'}'
+            && c != b'#'
+        {
+            starts.push(i);
+        }
+
+        line_start = c == b'\n';
+        if balanced {
+            match c {
+                b'{' => depth += 1,
+                // A stray close brace is given its own range so that it
+                // doesn't take down the preceding key
+                b'}' if depth == 0 => {
+                    if starts.last() != Some(&i) {
+                        starts.push(i);
+                    }
+                    line_start = true;
+                }
+                b'}' => depth -= 1,
+                b'#' => {
+                    while i + 1 < data.len() && data[i + 1] != b'\n' {
+                        i += 1;
+                    }
+                }
+                b'"' => {
+                    i += 1;
+                    while i < data.len() && data[i] != b'"' {
+                        i += if data[i] == b'\\' { 2 } else { 1 };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        i += 1;
+    }
+
+    let ends = starts
+        .iter()
+        .skip(1)
+        .copied()
+        .chain(std::iter::once(data.len()));
+    starts.iter().copied().zip(ends).collect()
 }
 
 struct ParserState<'a, 'b> {
     data: &'a [u8],
     original_length: usize,
     token_tape: &'b mut Vec<TextToken<'a>>,
+    hidden_object_strategy: HiddenObjectStrategy,
+    max_depth: usize,
+
+    // The number of objects and arrays that are currently open
+    depth: usize,
 }
 
 /// Houses the tape of tokens that is extracted from plaintext data
-#[derive(Debug, Default)]
+///
+/// The tape only stores raw byte slices of the input, so it is not tied to any
+/// particular text encoding. The encoding is decided when the tape is read,
+/// which allows a single parse to be interpreted in multiple ways:
+///
+/// ```
+/// use jomini::TextTape;
+///
+/// let data = b"name=\"J\xc3\xa5hk\xc3\xa5m\xc3\xa5hkke\"";
+/// let tape = TextTape::from_slice(&data[..])?;
+///
+/// let (_key, _op, value) = tape.utf8_reader().next_field().unwrap();
+/// assert_eq!(value.read_str()?, "Jåhkåmåhkke");
+///
+/// let (_key, _op, value) = tape.windows1252_reader().next_field().unwrap();
+/// assert_eq!(value.read_str()?, "JÃ¥hkÃ¥mÃ¥hkke");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Default)]
 pub struct TextTape<'a> {
     token_tape: Vec<TextToken<'a>>,
+
+    /// The data that the tape was parsed from, if it was parsed from a
+    /// single slice. Used to speed up searches.
+    data: &'a [u8],
+}
+
+impl<'a> std::fmt::Debug for TextTape<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextTape")
+            .field("token_tape", &self.token_tape)
+            .finish()
+    }
 }
 
 impl<'a> TextTape<'a> {
     /// Creates a windows 1252 object reader from the parsed tape
-    pub fn windows1252_reader(&self) -> ObjectReader<Windows1252Encoding> {
-        ObjectReader::new(&self, Windows1252Encoding::new())
+    pub fn windows1252_reader(&self) -> ObjectReader<'a, '_, Windows1252Encoding> {
+        ObjectReader::new(self, Windows1252Encoding::new())
+    }
+
+    /// The data that the tape was parsed from, which is empty when the tape
+    /// wasn't parsed from a single slice
+    pub(crate) fn source(&self) -> &'a [u8] {
+        self.data
     }
 
     /// Creates a utf-8 object reader from the parsed tape
-    pub fn utf8_reader(&self) -> ObjectReader<Utf8Encoding> {
-        ObjectReader::new(&self, Utf8Encoding::new())
+    pub fn utf8_reader(&self) -> ObjectReader<'a, '_, Utf8Encoding> {
+        ObjectReader::new(self, Utf8Encoding::new())
     }
 }
 
@@ -157,7 +746,7 @@ enum ParseState {
 /// when scanning multi-bytes, so this fallback is for when I was to reset and
 /// process bytewise. It is much slower, but escaped strings should be rare enough
 /// that this shouldn't be an issue
-fn parse_quote_scalar_fallback(d: &[u8]) -> Result<(Scalar, &[u8]), Error> {
+fn parse_quote_scalar_fallback(d: &[u8]) -> Result<(Scalar<'_>, &[u8]), Error> {
     let mut pos = 1;
     while pos < d.len() {
         if d[pos] == b'\\' {
@@ -170,11 +759,11 @@ fn parse_quote_scalar_fallback(d: &[u8]) -> Result<(Scalar, &[u8]), Error> {
         }
     }
 
-    Err(Error::eof())
+    Err(Error::eof("a closing quote"))
 }
 
 #[cfg(not(target_arch = "x86_64"))]
-fn parse_quote_scalar(d: &[u8]) -> Result<(Scalar, &[u8]), Error> {
+pub(crate) fn parse_quote_scalar(d: &[u8]) -> Result<(Scalar<'_>, &[u8]), Error> {
     use crate::util::{contains_zero_byte, repeat_byte};
     let sd = &d[1..];
     unsafe {
@@ -204,9 +793,9 @@ fn parse_quote_scalar(d: &[u8]) -> Result<(Scalar, &[u8]), Error> {
 }
 
 #[cfg(target_arch = "x86_64")]
-fn parse_quote_scalar(d: &[u8]) -> Result<(Scalar, &[u8]), Error> {
+pub(crate) fn parse_quote_scalar(d: &[u8]) -> Result<(Scalar<'_>, &[u8]), Error> {
     #[target_feature(enable = "sse2")]
-    unsafe fn inner(d: &[u8]) -> Result<(Scalar, &[u8]), Error> {
+    unsafe fn inner(d: &[u8]) -> Result<(Scalar<'_>, &[u8]), Error> {
         // This is a re-implementation of memchr for a few reasons:
         //   - We maintain zero dependencies
         //   - memchr is optimized for finding a needle in a large haystack so we don't need the
@@ -247,16 +836,16 @@ fn parse_quote_scalar(d: &[u8]) -> Result<(Scalar, &[u8]), Error> {
     }
 
     // from memchr: "SSE2 is avalbale on all x86_64 targets, so no CPU feature detection is necessary"
-    unsafe { inner(&d) }
+    unsafe { inner(d) }
 }
 
 #[inline]
-fn split_at_scalar_fallback(d: &[u8]) -> (Scalar, &[u8]) {
+fn split_at_scalar_fallback(d: &[u8]) -> (Scalar<'_>, &[u8]) {
     let start_ptr = d.as_ptr();
     let end_ptr = unsafe { start_ptr.add(d.len()) };
 
     let nind = unsafe { forward_search(start_ptr, end_ptr, is_boundary) };
-    let mut ind = nind.unwrap_or_else(|| d.len());
+    let mut ind = nind.unwrap_or(d.len());
 
     // To work with cases where we have "==bar" we ensure that found index is at least one
     ind = std::cmp::max(ind, 1);
@@ -266,17 +855,17 @@ fn split_at_scalar_fallback(d: &[u8]) -> (Scalar, &[u8]) {
 
 #[cfg(not(target_arch = "x86_64"))]
 #[inline]
-fn split_at_scalar(d: &[u8]) -> (Scalar, &[u8]) {
+pub(crate) fn split_at_scalar(d: &[u8]) -> (Scalar<'_>, &[u8]) {
     split_at_scalar_fallback(d)
 }
 
 #[cfg(target_arch = "x86_64")]
 #[inline]
-fn split_at_scalar(d: &[u8]) -> (Scalar, &[u8]) {
+pub(crate) fn split_at_scalar(d: &[u8]) -> (Scalar<'_>, &[u8]) {
     #[target_feature(enable = "sse2")]
     #[inline]
     #[allow(overflowing_literals)]
-    unsafe fn inner(d: &[u8]) -> (Scalar, &[u8]) {
+    unsafe fn inner(d: &[u8]) -> (Scalar<'_>, &[u8]) {
         use core::arch::x86_64::*;
         let start_ptr = d.as_ptr();
         let loop_size = std::mem::size_of::<__m128i>();
@@ -369,7 +958,7 @@ fn split_at_scalar(d: &[u8]) -> (Scalar, &[u8]) {
     }
 
     // from memchr: "SSE2 is avalbale on all x86_64 targets, so no CPU feature detection is necessary"
-    unsafe { inner(&d) }
+    unsafe { inner(d) }
 }
 
 impl<'a> TextTape<'a> {
@@ -380,25 +969,294 @@ impl<'a> TextTape<'a> {
 
     /// Convenience method for creating a text parser and parsing the given input
     pub fn from_slice(data: &[u8]) -> Result<TextTape<'_>, Error> {
-        TextTapeParser.parse_slice(data)
+        TextTapeParser::new().parse_slice(data)
+    }
+
+    /// Convenience method for parsing multiple documents into a single tape
+    /// where later documents override top level keys of earlier documents.
+    /// See `TextTapeParser::parse_slices` for more details.
+    pub fn from_slices<I>(data: I) -> Result<TextTape<'a>, Error>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        TextTapeParser::new().parse_slices(data)
     }
 
     /// Returns a parser for text data
     pub fn parser() -> TextTapeParser {
-        TextTapeParser
+        TextTapeParser::new()
     }
 
     /// Return the parsed tokens
     pub fn tokens(&self) -> &[TextToken<'a>] {
         self.token_tape.as_slice()
     }
+
+    /// Replace the scalar at the given index and return the previous one, so
+    /// that small edits (like changing the value of a field) don't require
+    /// building a document. A header (`rgb` in `color = rgb { ... }`) can be
+    /// replaced too.
+    ///
+    /// Only scalars can be replaced so that the indices that objects and
+    /// arrays store remain valid. Replacing any other token is an error.
+    ///
+    /// ```
+    /// use jomini::{Scalar, TextTape};
+    ///
+    /// let mut tape = TextTape::from_slice(b"name=Jean age=30 traits={ brave }")?;
+    /// let old = tape.set_scalar(3, Scalar::new(b"31"))?;
+    /// assert_eq!(old, Scalar::new(b"30"));
+    /// assert_eq!(tape.tokens(), TextTape::from_slice(b"name=Jean age=31 traits={ brave }")?.tokens());
+    ///
+    /// // the value of `traits` is an array
+    /// assert!(tape.set_scalar(5, Scalar::new(b"none")).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_scalar(&mut self, idx: usize, scalar: Scalar<'a>) -> Result<Scalar<'a>, Error> {
+        match self.token_tape.get_mut(idx) {
+            Some(TextToken::Scalar(x)) | Some(TextToken::Header(x)) => {
+                Ok(std::mem::replace(x, scalar))
+            }
+            Some(_) => Err(Error::new(ErrorKind::InvalidEdit {
+                msg: String::from("only scalars can be replaced"),
+                index: idx,
+            })),
+            None => Err(Error::new(ErrorKind::InvalidEdit {
+                msg: String::from("index is out of bounds"),
+                index: idx,
+            })),
+        }
+    }
+
+    /// Combine the top level entries of another document into this tape
+    /// according to the game's override rules. Duplicate keys within a
+    /// single document are always kept.
+    ///
+    /// ```
+    /// use jomini::{MergeStrategy, TextTape};
+    ///
+    /// let base = b"a = { x = 1 } b = { x = 2 }";
+    /// let patch = b"a = { x = 3 } c = { x = 4 }";
+    ///
+    /// let mut tape = TextTape::from_slice(&base[..])?;
+    /// tape.merge(&TextTape::from_slice(&patch[..])?, MergeStrategy::Lios);
+    /// let expected = TextTape::from_slice(b"b = { x = 2 } a = { x = 3 } c = { x = 4 }")?;
+    /// assert_eq!(tape.tokens(), expected.tokens());
+    ///
+    /// let mut tape = TextTape::from_slice(&base[..])?;
+    /// tape.merge(&TextTape::from_slice(&patch[..])?, MergeStrategy::Fios);
+    /// let expected = TextTape::from_slice(b"a = { x = 1 } b = { x = 2 } c = { x = 4 }")?;
+    /// assert_eq!(tape.tokens(), expected.tokens());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn merge(&mut self, other: &TextTape<'a>, strategy: MergeStrategy) {
+        let tokens = self.tokens();
+        let other_tokens = other.tokens();
+        let own = top_level_entries(tokens);
+        let theirs = top_level_entries(other_tokens);
+
+        let mut res = Vec::with_capacity(tokens.len() + other_tokens.len());
+        match strategy {
+            MergeStrategy::Lios => {
+                let replaced: HashSet<_> = theirs.iter().map(|x| x.0).collect();
+                for &(key, start, end) in own.iter() {
+                    if !replaced.contains(&key) {
+                        extend_tape(&mut res, &tokens[start..end], start);
+                    }
+                }
+                extend_tape(&mut res, other_tokens, 0);
+            }
+            MergeStrategy::Fios => {
+                let existing: HashSet<_> = own.iter().map(|x| x.0).collect();
+                extend_tape(&mut res, tokens, 0);
+                for &(key, start, end) in theirs.iter() {
+                    if !existing.contains(&key) {
+                        extend_tape(&mut res, &other_tokens[start..end], start);
+                    }
+                }
+            }
+            MergeStrategy::Append => {
+                extend_tape(&mut res, tokens, 0);
+                extend_tape(&mut res, other_tokens, 0);
+            }
+        }
+
+        // The tape no longer corresponds to a single slice of data
+        self.token_tape = res;
+        self.data = &[];
+    }
+
+    pub(crate) fn from_tokens(token_tape: Vec<TextToken<'a>>) -> Self {
+        TextTape {
+            token_tape,
+            data: &[],
+        }
+    }
+
+    /// Create a tape from tokens that were constructed by hand (eg: in
+    /// tests), after checking that they are structurally valid. See
+    /// `TextTape::validate`.
+    ///
+    /// ```
+    /// use jomini::{Scalar, TextTape, TextToken};
+    ///
+    /// let tape = TextTape::try_from_tokens(vec![
+    ///     TextToken::Scalar(Scalar::new(b"a")),
+    ///     TextToken::Object(4),
+    ///     TextToken::Scalar(Scalar::new(b"b")),
+    ///     TextToken::Scalar(Scalar::new(b"c")),
+    ///     TextToken::End(1),
+    /// ])?;
+    /// assert_eq!(tape.tokens(), TextTape::from_slice(b"a={b=c}")?.tokens());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn try_from_tokens(token_tape: Vec<TextToken<'a>>) -> Result<Self, Error> {
+        let tape = TextTape::from_tokens(token_tape);
+        tape.validate()?;
+        Ok(tape)
+    }
+
+    /// Check the structure of the tape: every object and array must point
+    /// at the end token that closes it and the end token must point back,
+    /// objects and arrays must be properly nested, and a header must be
+    /// followed by an array.
+    ///
+    /// Tapes produced by the parser are always valid, so this is meant for
+    /// tapes that were constructed or edited by hand and for fuzzing.
+    ///
+    /// ```
+    /// use jomini::{Scalar, TextTape, TextToken};
+    ///
+    /// let tape = TextTape::from_slice(b"a={ b={ 1 2 } } c=rgb { 1 2 3 }")?;
+    /// assert!(tape.validate().is_ok());
+    ///
+    /// let tokens = vec![
+    ///     TextToken::Scalar(Scalar::new(b"a")),
+    ///     TextToken::Array(3),
+    ///     TextToken::Scalar(Scalar::new(b"1")),
+    ///     TextToken::End(0),
+    /// ];
+    /// assert!(TextTape::try_from_tokens(tokens).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn validate(&self) -> Result<(), Error> {
+        let tokens = self.tokens();
+        for (idx, token) in tokens.iter().enumerate() {
+            if let TextToken::Header(_) = token {
+                if !matches!(tokens.get(idx + 1), Some(TextToken::Array(_))) {
+                    return Err(Error::new(ErrorKind::InvalidTape {
+                        msg: String::from("header is not followed by an array"),
+                        index: idx,
+                    }));
+                }
+            }
+        }
+
+        validate_structure(tokens.iter().map(|x| match *x {
+            TextToken::Array(end) | TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                TapeNode::Open(end)
+            }
+            TextToken::End(start) => TapeNode::End(start),
+            _ => TapeNode::Leaf,
+        }))
+    }
+
+    /// A tape of tokens that were parsed from the data
+    pub(crate) fn from_parts(token_tape: Vec<TextToken<'a>>, data: &'a [u8]) -> Self {
+        TextTape { token_tape, data }
+    }
+
+    /// Return the tape index of the value of the first occurrence of the
+    /// key at any depth.
+    ///
+    /// When the tape was parsed from a single slice, the data is scanned for
+    /// the key's bytes and only the candidates are validated against the
+    /// tape, which is much faster than walking the tape for a single field.
+    /// Only keys followed by an equal sign or operator are found.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let tape = TextTape::from_slice(b"date=1444.11.11 checksum=\"abc\"")?;
+    /// let idx = tape.find_key("checksum").unwrap();
+    /// let checksum = tape.tokens()[idx].as_scalar().unwrap();
+    /// assert_eq!(checksum.view_data(), b"abc");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn find_key(&self, key: &str) -> Option<usize> {
+        find_keys(self.data, self.tokens(), key.as_bytes(), true)
+            .first()
+            .copied()
+    }
+
+    /// Return the tape indices of the values of every occurrence of the key
+    /// at any depth, in the order they appear. See `find_key`.
+    ///
+    /// ```
+    /// use jomini::TextTape;
+    ///
+    /// let tape = TextTape::from_slice(b"battle={ a=1 } war={ battle={ a=2 } }")?;
+    /// assert_eq!(tape.find_all("battle").len(), 2);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn find_all(&self, key: &str) -> Vec<usize> {
+        find_keys(self.data, self.tokens(), key.as_bytes(), false)
+    }
 }
 
 impl<'a, 'b> ParserState<'a, 'b> {
+    /// Returns true if the hidden object that was just started with a key
+    /// is only preceded by empty arrays in its array. The array is then
+    /// actually an object with leading empty objects:
+    /// `history={{} 1444.11.11={core=AAA}}`
+    fn only_empty_arrays_precede(&self, array_ind: usize) -> bool {
+        let hidden_ind = self.token_tape.len() - 2;
+        if !matches!(self.token_tape[hidden_ind], TextToken::Object(x) if x == array_ind) {
+            return false;
+        }
+
+        let mut start = hidden_ind - 1;
+        while start > array_ind {
+            match self.token_tape[start] {
+                TextToken::End(x) if x == start - 1 => start -= 2,
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Removes the hidden object token at the given index so that its fields
+    /// become elements of the enclosing array
+    fn flatten_hidden_object(&mut self, hidden_ind: usize) {
+        self.token_tape.remove(hidden_ind);
+        let shift = |x: usize| if x > hidden_ind { x - 1 } else { x };
+        for token in &mut self.token_tape[hidden_ind..] {
+            *token = match *token {
+                TextToken::Array(x) => TextToken::Array(shift(x)),
+                TextToken::Object(x) => TextToken::Object(shift(x)),
+                TextToken::HiddenObject(x) => TextToken::HiddenObject(shift(x)),
+                TextToken::End(x) => TextToken::End(shift(x)),
+                ref x => x.clone(),
+            };
+        }
+    }
+
     fn offset(&self, data: &[u8]) -> usize {
         self.original_length - data.len()
     }
 
+    /// Record that an object or array has been opened at the given offset
+    #[inline]
+    fn enter(&mut self, offset: usize) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(Error::depth_exceeded(offset));
+        }
+
+        Ok(())
+    }
+
     /// Skips whitespace that may terminate the file
     #[inline]
     fn skip_ws_t(&mut self, data: &'a [u8]) -> Option<&'a [u8]> {
@@ -436,10 +1294,31 @@ impl<'a, 'b> ParserState<'a, 'b> {
     }
 
     #[inline]
-    fn parse_scalar(&mut self, d: &'a [u8]) -> &'a [u8] {
+    fn parse_scalar(&mut self, d: &'a [u8]) -> Result<&'a [u8], Error> {
+        if d[0] == b'@' && d.get(1) == Some(&b'[') {
+            return self.parse_inline_math(d);
+        }
+
         let (scalar, rest) = split_at_scalar(d);
         self.token_tape.push(TextToken::Scalar(scalar));
-        rest
+        Ok(rest)
+    }
+
+    /// Inline math (`@[stability_cost * 2]`) may contain whitespace and
+    /// operators so it is captured up to the closing bracket as one scalar
+    #[cold]
+    fn parse_inline_math(&mut self, d: &'a [u8]) -> Result<&'a [u8], Error> {
+        match d.iter().position(|&x| x == b']') {
+            Some(ind) => {
+                let (scalar, rest) = d.split_at(ind + 1);
+                self.token_tape.push(TextToken::Scalar(Scalar::new(scalar)));
+                Ok(rest)
+            }
+            None => Err(Error::new(ErrorKind::InvalidSyntax {
+                msg: String::from("unterminated inline math"),
+                offset: self.offset(d),
+            })),
+        }
     }
 
     #[inline]
@@ -464,7 +1343,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
         if d[0] == b'=' {
             &d[1..]
         } else if d[0] == b'<' {
-            if d.get(1).map_or(false, |c| *c == b'=') {
+            if d.get(1) == Some(&b'=') {
                 self.token_tape
                     .push(TextToken::Operator(Operator::LessThanEqual));
                 &d[2..]
@@ -474,7 +1353,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                 &d[1..]
             }
         } else if d[0] == b'>' {
-            if d.get(1).map_or(false, |c| *c == b'=') {
+            if d.get(1) == Some(&b'=') {
                 self.token_tape
                     .push(TextToken::Operator(Operator::GreaterThanEqual));
                 &d[2..]
@@ -511,7 +1390,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                     if parent_ind == 0 && state == ParseState::Key {
                         return Ok(());
                     } else {
-                        return Err(Error::eof());
+                        return Err(Error::eof("a closing brace"));
                     }
                 }
             };
@@ -549,12 +1428,37 @@ impl<'a, 'b> ParserState<'a, 'b> {
                                 }));
                             }
 
-                            self.token_tape.push(TextToken::End(parent_ind));
-                            if let Some(array_ind) = array_ind_of_hidden_obj.take() {
-                                self.token_tape[parent_ind] = TextToken::HiddenObject(end_idx);
+                            // A hidden object is terminated by the end of its array. The
+                            // objects nested inside the hidden object end as normal.
+                            let hidden_array_ind = match array_ind_of_hidden_obj {
+                                Some(array_ind) if grand_ind == array_ind => {
+                                    array_ind_of_hidden_obj.take()
+                                }
+                                _ => None,
+                            };
+
+                            if let Some(array_ind) = hidden_array_ind {
+                                match self.hidden_object_strategy {
+                                    HiddenObjectStrategy::Synthesize
+                                    | HiddenObjectStrategy::SynthesizeNested => {
+                                        self.token_tape.push(TextToken::End(parent_ind));
+                                        self.token_tape[parent_ind] =
+                                            TextToken::HiddenObject(end_idx);
+                                    }
+                                    HiddenObjectStrategy::Flatten => {
+                                        self.flatten_hidden_object(parent_ind)
+                                    }
+                                    HiddenObjectStrategy::Error => {
+                                        return Err(Error::new(ErrorKind::InvalidSyntax {
+                                            msg: String::from("hidden objects are not allowed"),
+                                            offset: self.offset(data),
+                                        }));
+                                    }
+                                }
 
                                 let end_idx = self.token_tape.len();
                                 self.token_tape.push(TextToken::End(array_ind));
+                                self.depth -= 1;
 
                                 // Grab the grand parent from the outer array. Even though the logic should
                                 // be more strict (ie: throwing an error when if the parent array index doesn't exist,
@@ -579,7 +1483,9 @@ impl<'a, 'b> ParserState<'a, 'b> {
                                 };
                                 parent_ind = grand_ind;
                             } else {
+                                self.token_tape.push(TextToken::End(parent_ind));
                                 self.token_tape[parent_ind] = TextToken::Object(end_idx);
+                                self.depth -= 1;
                                 parent_ind = grand_ind;
                             }
 
@@ -591,7 +1497,10 @@ impl<'a, 'b> ParserState<'a, 'b> {
                             data = &data[1..];
                             if let Some(last) = self.token_tape.last_mut() {
                                 if let TextToken::Scalar(x) = last {
-                                    if array_ind_of_hidden_obj.is_some() {
+                                    if array_ind_of_hidden_obj.is_some()
+                                        && self.hidden_object_strategy
+                                            == HiddenObjectStrategy::Synthesize
+                                    {
                                         return Err(Error::new(ErrorKind::InvalidSyntax {
                                             offset: self.offset(data) - 2,
                                             msg: String::from(
@@ -602,6 +1511,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
 
                                     *last = TextToken::Header(*x);
                                     self.token_tape.push(TextToken::Array(0));
+                                    self.enter(self.offset(data) - 1)?;
                                     state = ParseState::ParseOpen;
                                 } else {
                                     state = ParseState::EmptyObject;
@@ -616,7 +1526,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                             state = ParseState::KeyValueSeparator;
                         }
                         _ => {
-                            data = self.parse_scalar(data);
+                            data = self.parse_scalar(data)?;
                             state = ParseState::KeyValueSeparator;
                         }
                     }
@@ -628,45 +1538,44 @@ impl<'a, 'b> ParserState<'a, 'b> {
                 ParseState::ObjectValue => {
                     match data[0] {
                         b'{' => {
-                            if let Some(array_ind) = array_ind_of_hidden_obj.take() {
-                                // before we error, we should check if we previously parsed an empty array
-                                // `history={{} 1444.11.11={core=AAA}}`
-                                // so we're going to go back up the stack until we see our parent object
-                                // and ensure that everything along the way is an empty array
-
-                                let mut start = self.token_tape.len() - 3;
-                                while start > array_ind {
-                                    match self.token_tape[start] {
-                                        TextToken::End(x) if x == start - 1 => {
-                                            start -= 2;
-                                        }
-                                        _ => {
-                                            return Err(Error::new(ErrorKind::InvalidSyntax {
-                                                offset: self.offset(data) - 2,
-                                                msg: String::from(
-                                                    "header values inside a hidden object are unsupported",
-                                                ),
-                                            }));
-                                        }
-                                    }
-                                }
-
-                                let empty_objects_to_remove = self.token_tape.len() - 2 - array_ind;
+                            // A container that follows a hidden object's key is either
+                            // the first field of an object with leading empty objects,
+                            // or a value of the hidden object, which only some
+                            // strategies support
+                            match array_ind_of_hidden_obj {
+                                Some(array_ind) if self.only_empty_arrays_precede(array_ind) => {
+                                    array_ind_of_hidden_obj = None;
+                                    let empty_objects_to_remove =
+                                        self.token_tape.len() - 2 - array_ind;
+
+                                    let grand_ind = match self.token_tape[array_ind] {
+                                        TextToken::Array(x) => x,
+                                        _ => 0,
+                                    };
 
-                                let grand_ind = match self.token_tape[array_ind] {
-                                    TextToken::Array(x) => x,
-                                    _ => 0,
-                                };
+                                    for _ in 0..empty_objects_to_remove {
+                                        self.token_tape.remove(self.token_tape.len() - 3);
+                                    }
 
-                                for _ in 0..empty_objects_to_remove {
-                                    self.token_tape.remove(self.token_tape.len() - 3);
+                                    parent_ind = array_ind;
+                                    self.token_tape[parent_ind] = TextToken::Object(grand_ind);
                                 }
-
-                                parent_ind = array_ind;
-                                self.token_tape[parent_ind] = TextToken::Object(grand_ind);
+                                Some(_)
+                                    if self.hidden_object_strategy
+                                        == HiddenObjectStrategy::Synthesize =>
+                                {
+                                    return Err(Error::new(ErrorKind::InvalidSyntax {
+                                        offset: self.offset(data) - 1,
+                                        msg: String::from(
+                                            "nested values inside a hidden object are unsupported",
+                                        ),
+                                    }));
+                                }
+                                _ => {}
                             }
 
                             self.token_tape.push(TextToken::Array(0));
+                            self.enter(self.offset(data))?;
                             state = ParseState::ParseOpen;
                             data = &data[1..];
                         }
@@ -681,7 +1590,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                             state = ParseState::Key;
                         }
                         _ => {
-                            data = self.parse_scalar(data);
+                            data = self.parse_scalar(data)?;
                             state = ParseState::Key
                         }
                     }
@@ -699,6 +1608,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
 
                             self.token_tape[ind] = TextToken::Array(ind + 1);
                             self.token_tape.push(TextToken::End(ind));
+                            self.depth -= 1;
                             data = &data[1..];
                         }
 
@@ -714,7 +1624,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                             state = ParseState::FirstValue;
                         }
                         _ => {
-                            data = self.parse_scalar(data);
+                            data = self.parse_scalar(data)?;
                             state = ParseState::FirstValue;
                         }
                     }
@@ -737,6 +1647,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                 ParseState::ArrayValue => match data[0] {
                     b'{' => {
                         self.token_tape.push(TextToken::Array(0));
+                        self.enter(self.offset(data))?;
                         state = ParseState::ParseOpen;
                         data = &data[1..];
                     }
@@ -756,6 +1667,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                         let end_idx = self.token_tape.len();
                         self.token_tape[parent_ind] = TextToken::Array(end_idx);
                         self.token_tape.push(TextToken::End(parent_ind));
+                        self.depth -= 1;
                         parent_ind = grand_ind;
                         data = &data[1..];
                     }
@@ -779,6 +1691,13 @@ impl<'a, 'b> ParserState<'a, 'b> {
                             }));
                         }
 
+                        if array_ind_of_hidden_obj.is_some() {
+                            return Err(Error::new(ErrorKind::InvalidSyntax {
+                                msg: String::from("nested hidden objects are unsupported"),
+                                offset: self.offset(data) - 1,
+                            }));
+                        }
+
                         let hidden_object = TextToken::Object(parent_ind);
                         array_ind_of_hidden_obj = Some(parent_ind);
                         parent_ind = self.token_tape.len() - 1;
@@ -788,7 +1707,7 @@ impl<'a, 'b> ParserState<'a, 'b> {
                         data = &data[1..];
                     }
                     _ => {
-                        data = self.parse_scalar(data);
+                        data = self.parse_scalar(data)?;
                         state = ParseState::ArrayValue;
                     }
                 },
@@ -827,6 +1746,88 @@ mod tests {
         TextTape::from_slice(data)
     }
 
+    #[test]
+    fn test_parse_slices_later_overrides() {
+        let tape = TextTape::from_slices(vec![&b"a={b=c} d=e"[..], &b"f=g a={h=i}"[..]]).unwrap();
+        let expected = parse(b"d=e f=g a={h=i}").unwrap();
+        assert_eq!(tape.tokens(), expected.tokens());
+    }
+
+    #[test]
+    fn test_parse_slices_keeps_duplicates_within_document() {
+        let tape = TextTape::from_slices(vec![
+            &b"a=1"[..],
+            &b"a=2 b={ 1 2 } a=3"[..],
+            &b"c=rgb { 1 2 3 } d > 4"[..],
+        ])
+        .unwrap();
+        let expected = parse(b"a=2 b={ 1 2 } a=3 c=rgb { 1 2 3 } d > 4").unwrap();
+        assert_eq!(tape.tokens(), expected.tokens());
+    }
+
+    #[test]
+    fn test_parse_slices_empty() {
+        let tape = TextTape::from_slices(vec![&b""[..], &b"a=b"[..], &b""[..]]).unwrap();
+        assert_eq!(tape.tokens(), parse(b"a=b").unwrap().tokens());
+
+        let tape = TextTape::from_slices(Vec::new()).unwrap();
+        assert!(tape.tokens().is_empty());
+    }
+
+    #[test]
+    fn test_parse_slices_error() {
+        assert!(TextTape::from_slices(vec![&b"a=b"[..], &b"a={"[..]]).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = b"a=1 b={ x=1 } a=2";
+        let patch = b"c=3 a={ y=2 } a=4";
+
+        let mut tape = parse(&base[..]).unwrap();
+        tape.merge(&parse(&patch[..]).unwrap(), MergeStrategy::Lios);
+        let expected = parse(b"b={ x=1 } c=3 a={ y=2 } a=4").unwrap();
+        assert_eq!(tape.tokens(), expected.tokens());
+        assert_eq!(tape.find_all("y").len(), 1);
+
+        let mut tape = parse(&base[..]).unwrap();
+        tape.merge(&parse(&patch[..]).unwrap(), MergeStrategy::Fios);
+        let expected = parse(b"a=1 b={ x=1 } a=2 c=3").unwrap();
+        assert_eq!(tape.tokens(), expected.tokens());
+
+        let mut tape = parse(&base[..]).unwrap();
+        tape.merge(&parse(&patch[..]).unwrap(), MergeStrategy::Append);
+        let expected = parse(b"a=1 b={ x=1 } a=2 c=3 a={ y=2 } a=4").unwrap();
+        assert_eq!(tape.tokens(), expected.tokens());
+        assert_eq!(tape.find_all("a").len(), 4);
+
+        let mut tape = TextTape::new();
+        tape.merge(&parse(&patch[..]).unwrap(), MergeStrategy::Fios);
+        assert_eq!(tape.tokens(), parse(&patch[..]).unwrap().tokens());
+    }
+
+    #[test]
+    fn test_inline_math() {
+        let data = b"a = @[stability_cost * 2] b = { @[1+1] @c }";
+        assert_eq!(
+            parse(&data[..]).unwrap().tokens(),
+            vec![
+                TextToken::Scalar(Scalar::new(b"a")),
+                TextToken::Scalar(Scalar::new(b"@[stability_cost * 2]")),
+                TextToken::Scalar(Scalar::new(b"b")),
+                TextToken::Array(6),
+                TextToken::Scalar(Scalar::new(b"@[1+1]")),
+                TextToken::Scalar(Scalar::new(b"@c")),
+                TextToken::End(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_inline_math() {
+        assert!(parse(b"a = @[1 + 1").is_err());
+    }
+
     #[test]
     fn test_size_of_text_token() {
         let token_size = std::mem::size_of::<TextToken>();
@@ -863,7 +1864,7 @@ mod tests {
             ErrorKind::StackEmpty { offset, .. } => {
                 assert_eq!(*offset, 6);
             }
-            _ => assert!(false),
+            _ => panic!("unexpected error kind"),
         }
     }
 
@@ -1588,6 +2589,333 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hidden_object_with_containers() {
+        let data = b"levels={ 10 0={ 1 2 } 1={ a=b } }";
+        assert!(parse(&data[..]).is_err());
+        assert!(parse(b"levels={ 10 0=rgb { 1 2 3 } }").is_err());
+
+        let tape = TextTapeParser::new()
+            .on_hidden_object(HiddenObjectStrategy::SynthesizeNested)
+            .parse_slice(&data[..])
+            .unwrap();
+
+        assert_eq!(
+            tape.tokens(),
+            vec![
+                TextToken::Scalar(Scalar::new(b"levels")),
+                TextToken::Array(15),
+                TextToken::Scalar(Scalar::new(b"10")),
+                TextToken::HiddenObject(14),
+                TextToken::Scalar(Scalar::new(b"0")),
+                TextToken::Array(8),
+                TextToken::Scalar(Scalar::new(b"1")),
+                TextToken::Scalar(Scalar::new(b"2")),
+                TextToken::End(5),
+                TextToken::Scalar(Scalar::new(b"1")),
+                TextToken::Object(13),
+                TextToken::Scalar(Scalar::new(b"a")),
+                TextToken::Scalar(Scalar::new(b"b")),
+                TextToken::End(10),
+                TextToken::End(3),
+                TextToken::End(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hidden_object_flatten() {
+        let data = b"levels={ 10 0={ 1 2 } 1=2 } foo=bar";
+        let mut tape = TextTape::new();
+        TextTapeParser::new()
+            .on_hidden_object(HiddenObjectStrategy::Flatten)
+            .parse_slice_into_tape(&data[..], &mut tape)
+            .unwrap();
+
+        assert_eq!(
+            tape.tokens(),
+            vec![
+                TextToken::Scalar(Scalar::new(b"levels")),
+                TextToken::Array(10),
+                TextToken::Scalar(Scalar::new(b"10")),
+                TextToken::Scalar(Scalar::new(b"0")),
+                TextToken::Array(7),
+                TextToken::Scalar(Scalar::new(b"1")),
+                TextToken::Scalar(Scalar::new(b"2")),
+                TextToken::End(4),
+                TextToken::Scalar(Scalar::new(b"1")),
+                TextToken::Scalar(Scalar::new(b"2")),
+                TextToken::End(1),
+                TextToken::Scalar(Scalar::new(b"foo")),
+                TextToken::Scalar(Scalar::new(b"bar")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let cases: &[(&[u8], usize)] = &[
+            (b"a=1", 0),
+            (b"a={}", 1),
+            (b"a={ } b={ { } }", 2),
+            (b"a={ b={ c=d } }", 2),
+            (b"a={ { b=c } { d={ 1 } } }", 3),
+            (b"color=rgb { 1 2 3 }", 1),
+            (b"levels={ 10 0=2 1=2 } b={ c=d }", 1),
+            (b"history={{} 1444.11.11={core=AAA}}", 2),
+        ];
+
+        for (data, depth) in cases {
+            let parser = TextTapeParser::new().max_depth(*depth);
+            assert!(parser.parse_slice(data).is_ok());
+            if *depth > 0 {
+                let err = TextTapeParser::new()
+                    .max_depth(depth - 1)
+                    .parse_slice(data)
+                    .unwrap_err();
+                assert!(matches!(err.kind(), ErrorKind::LimitExceeded { .. }));
+            }
+        }
+
+        let parser = TextTapeParser::new()
+            .on_hidden_object(HiddenObjectStrategy::Flatten)
+            .max_depth(2);
+        assert!(parser
+            .parse_slice(b"a={ 10 0={ 1 } 1=2 } b={ c={ d=e } }")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_hidden_object_error() {
+        let parser = TextTapeParser::new().on_hidden_object(HiddenObjectStrategy::Error);
+        assert!(parser.parse_slice(b"levels={ 10 0=2 1=2 }").is_err());
+        assert!(parser
+            .parse_slice(b"history={{} 1444.11.11={core=AAA}}")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_nested_hidden_objects_not_supported() {
+        let data = b"levels={ 10 0={ 1 a=b } }";
+        assert!(parse(&data[..]).is_err());
+    }
+
+    #[test]
+    fn test_lossy_parse_of_valid_data() {
+        let data = b"a=1\nb={ c=d }\n";
+        let (tape, errors) = TextTapeParser::new().parse_slice_lossy(&data[..]);
+        assert_eq!(tape.tokens(), parse(&data[..]).unwrap().tokens());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_lossy_parse_skips_unbalanced_section() {
+        let data = b"a=1\nb={\n\tc={ d=\"}\"\n}\n# e={\nf={ g=h }\n";
+        let (tape, errors) = TextTapeParser::new().parse_slice_lossy(&data[..]);
+        assert_eq!(
+            tape.tokens(),
+            vec![
+                TextToken::Scalar(Scalar::new(b"a")),
+                TextToken::Scalar(Scalar::new(b"1")),
+                TextToken::Scalar(Scalar::new(b"f")),
+                TextToken::Object(6),
+                TextToken::Scalar(Scalar::new(b"g")),
+                TextToken::Scalar(Scalar::new(b"h")),
+                TextToken::End(3),
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_lossy_parse_stray_close() {
+        let data = b"a=1 }\nb=2\n";
+        let (tape, errors) = TextTapeParser::new().parse_slice_lossy(&data[..]);
+        assert_eq!(tape.tokens(), parse(b"a=1 b=2").unwrap().tokens());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].offset(), Some(4));
+    }
+
+    #[test]
+    fn test_segmented_parse_matches_full_parse() {
+        let data = b"a=1\nb={\n  c=d\n}\n# comment\ne=\"x}\"\nf={ 1 2 }\n";
+        let full = parse(&data[..]).unwrap();
+        for segment_len in [0, 4, 16, data.len()].iter() {
+            let segments = TextTapeParser::new()
+                .parse_slice_segmented(&data[..], *segment_len)
+                .unwrap();
+            let mut tokens = Vec::new();
+            for segment in segments.iter() {
+                extend_tape(&mut tokens, segment.tokens(), 0);
+            }
+            assert_eq!(tokens, full.token_tape);
+        }
+    }
+
+    #[test]
+    fn test_segmented_parse_value_on_next_line() {
+        let data = b"a=\n1\nb=2\n";
+        let segments = TextTapeParser::new()
+            .parse_slice_segmented(&data[..], 0)
+            .unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].tokens(), parse(b"a=1").unwrap().tokens());
+    }
+
+    #[test]
+    fn test_segmented_parse_error_offset() {
+        let data = b"a=1\nb=2 }\n";
+        let err = TextTapeParser::new()
+            .parse_slice_segmented(&data[..], 0)
+            .unwrap_err();
+        assert_eq!(err.offset(), Some(8));
+    }
+
+    #[test]
+    fn test_set_scalar() {
+        let mut tape = parse(b"color = rgb { 100 200 150 } a>1").unwrap();
+        let hsv = String::from("hsv");
+        assert_eq!(
+            tape.set_scalar(1, Scalar::new(hsv.as_bytes())).unwrap(),
+            Scalar::new(b"rgb")
+        );
+        assert_eq!(tape.tokens()[1], TextToken::Header(Scalar::new(b"hsv")));
+
+        let err = tape.set_scalar(8, Scalar::new(b"<")).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::InvalidEdit { index: 8, .. }
+        ));
+
+        let len = tape.tokens().len();
+        let err = tape.set_scalar(len, Scalar::new(b"b")).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidEdit { .. }));
+    }
+
+    #[test]
+    fn test_validate_parsed_tapes() {
+        let cases: &[&[u8]] = &[
+            b"",
+            b"a={ 1 2 3 } b={ { c=d } { e=f } } g={}",
+            b"levels={ 10 0=2 1=2 } x=y",
+            b"color=rgb { 100 200 150 } name=@[stability_cost * 2]",
+            include_bytes!("../../tests/fixtures/savegame.txt"),
+        ];
+
+        for data in cases {
+            parse(data).unwrap().validate().unwrap();
+        }
+
+        let mut tape = parse(b"a={ x=1 } b={ y={ 2 } }").unwrap();
+        tape.merge(&parse(b"a={ z=3 }").unwrap(), MergeStrategy::Lios);
+        tape.validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_rejects_broken_tapes() {
+        let a = || TextToken::Scalar(Scalar::new(b"a"));
+        let invalid = |tokens: Vec<TextToken>| match TextTape::try_from_tokens(tokens) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidTape { index, .. } => *index,
+                _ => panic!("unexpected error"),
+            },
+            Ok(_) => panic!("expected an invalid tape"),
+        };
+
+        // object points past its end
+        assert_eq!(
+            invalid(vec![a(), TextToken::Object(3), TextToken::End(1)]),
+            2
+        );
+
+        // object is never closed
+        assert_eq!(invalid(vec![a(), TextToken::Object(5), a()]), 1);
+
+        // end without a start
+        assert_eq!(invalid(vec![a(), a(), TextToken::End(0)]), 2);
+
+        // crossed nesting
+        let tokens = vec![
+            a(),
+            TextToken::Object(4),
+            a(),
+            TextToken::Array(5),
+            TextToken::End(1),
+            TextToken::End(3),
+        ];
+        assert_eq!(invalid(tokens), 4);
+
+        // end index before the start
+        assert_eq!(invalid(vec![a(), TextToken::Array(0)]), 1);
+
+        // header without an array
+        let header = TextToken::Header(Scalar::new(b"rgb"));
+        assert_eq!(invalid(vec![a(), header, a()]), 1);
+    }
+
+    #[test]
+    fn test_capacity_hint() {
+        let data = b"a=1 b=2";
+        assert_eq!(CapacityHint::Estimate.tokens(data), 1);
+        assert_eq!(CapacityHint::Tokens(10).tokens(data), 10);
+
+        let previous = parse(b"# comment\nc=dddd").unwrap();
+        let parser = TextTapeParser::new().capacity_hint(&previous);
+        assert_eq!(parser.capacity.tokens(&[0; 160]), 20);
+
+        let merged = TextTapeParser::new()
+            .parse_slices(vec![&b"a=1"[..], &b"b=2"[..]])
+            .unwrap();
+        let parser = TextTapeParser::new().capacity_hint(&merged);
+        assert_eq!(parser.capacity, CapacityHint::Estimate);
+    }
+
+    #[test]
+    fn test_token_count_matches_parse() {
+        let cases: &[&[u8]] = &[
+            b"",
+            b"a=1 b=\"hello } world\" c={ d=e }",
+            b"a={ 1 2 3 } b={ { c=d } { e=f } } g={}",
+            b"levels={ 10 0=2 1=2 } x=y",
+            b"color=rgb { 100 200 150 } name=@[stability_cost * 2]",
+            b"# comment { }\na > 1 b <= 2 c >= 3 d < 4",
+            b"map_area_data{ brittany_area={ state=a } }",
+            b"history={ 1444.11.11={ core=AAA } }",
+        ];
+
+        for data in cases {
+            let tape = parse(data).unwrap();
+            assert_eq!(token_count(data), tape.tokens().len());
+
+            let exact = TextTapeParser::new()
+                .exact_capacity(true)
+                .parse_slice(data)
+                .unwrap();
+            assert_eq!(exact.tokens(), tape.tokens());
+        }
+    }
+
+    #[test]
+    fn test_token_count_matches_fixtures() {
+        let fixtures: &[&[u8]] = &[
+            include_bytes!("../../tests/fixtures/savegame.txt"),
+            include_bytes!("../../tests/fixtures/campaign_stats.txt"),
+            include_bytes!("../../tests/fixtures/ck3-header.txt"),
+        ];
+
+        for data in fixtures {
+            let tape = parse(data).unwrap();
+            assert_eq!(token_count(data), tape.tokens().len());
+        }
+    }
+
+    #[test]
+    fn test_token_count_overcounts_skipped_empty_objects() {
+        let data = b"a={ x=1 } {} b=2";
+        let tape = parse(data).unwrap();
+        assert!(token_count(data) > tape.tokens().len());
+    }
+
     #[test]
     fn test_hidden_object_needs_key() {
         let data = b"a{{}=}";
@@ -0,0 +1,407 @@
+use super::reader::next_idx;
+use crate::{Error, ErrorKind, Scalar, TextTape, TextToken};
+use std::collections::HashMap;
+
+/// Resolves script variables (`@cost = 5`), references to them
+/// (`price = @cost`), and inline math (`price = @[cost * 2]`).
+///
+/// Resolution is a preprocessing pass over the raw data. The returned data has
+/// references and inline math substituted with their values so that it can be
+/// parsed and deserialized as usual. Variable definitions are left in place.
+/// Variables are scoped to the file (a variable defined inside an object is
+/// visible to everything after it) and references to unknown variables are
+/// left untouched.
+///
+/// ```
+/// use jomini::{TextTape, Variables};
+///
+/// let data = b"@cost = 5 a = @cost b = { @[cost * 2 + 1] }";
+/// let resolved = Variables::new().resolve(&data[..])?;
+/// assert_eq!(resolved.as_slice(), &b"@cost = 5 a = 5 b = { 11 }"[..]);
+///
+/// let tape = TextTape::from_slice(&resolved)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Variables {
+    values: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Variables {
+    /// Create an empty set of variables
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define a variable (without the leading `@`) ahead of time, like those
+    /// that come from a game's `scripted_variables` files
+    pub fn insert(&mut self, name: &str, value: &str) {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        self.values
+            .insert(name.as_bytes().to_vec(), value.as_bytes().to_vec());
+    }
+
+    /// Return the value of a variable (without the leading `@`)
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        self.values.get(name.as_bytes()).map(|x| x.as_slice())
+    }
+
+    /// Evaluate the contents of an inline math expression. Supported are
+    /// numbers, variables, parentheses, and the `+ - * /` operators.
+    ///
+    /// ```
+    /// use jomini::Variables;
+    ///
+    /// let mut vars = Variables::new();
+    /// vars.insert("base", "1.5");
+    /// assert_eq!(vars.evaluate("(base + 0.5) * -2")?, -4.0);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn evaluate(&self, expr: &str) -> Result<f64, Error> {
+        self.evaluate_bytes(expr.as_bytes(), 0)
+    }
+
+    fn evaluate_bytes(&self, expr: &[u8], base_offset: usize) -> Result<f64, Error> {
+        let mut parser = ExprParser {
+            data: expr,
+            pos: 0,
+            base_offset,
+            vars: self,
+        };
+
+        let result = parser.expr()?;
+        parser.skip_ws();
+        if parser.pos != expr.len() {
+            return Err(parser.error("unexpected character in inline math"));
+        }
+
+        Ok(result)
+    }
+
+    /// Collect the variables defined in the data and return the data with all
+    /// known references and inline math substituted
+    pub fn resolve(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let tape = TextTape::from_slice(data)?;
+        let tokens = tape.tokens();
+        let mut resolution = Resolution {
+            data,
+            tokens,
+            replacements: Vec::new(),
+        };
+
+        resolution.object(self, 0, tokens.len())?;
+
+        let mut result = Vec::with_capacity(data.len());
+        let mut last = 0;
+        for (offset, len, value) in resolution.replacements {
+            result.extend_from_slice(&data[last..offset]);
+            result.extend_from_slice(&value);
+            last = offset + len;
+        }
+        result.extend_from_slice(&data[last..]);
+        Ok(result)
+    }
+}
+
+struct Resolution<'a, 'b> {
+    data: &'a [u8],
+    tokens: &'b [TextToken<'a>],
+    replacements: Vec<(usize, usize, Vec<u8>)>,
+}
+
+impl<'a, 'b> Resolution<'a, 'b> {
+    fn object(&mut self, vars: &mut Variables, mut ind: usize, end: usize) -> Result<(), Error> {
+        while ind < end {
+            let value_ind = match self.tokens.get(ind + 1) {
+                Some(TextToken::Operator(_)) => ind + 2,
+                _ => ind + 1,
+            };
+
+            if value_ind >= end {
+                break;
+            }
+
+            let definition = match (&self.tokens[ind], &self.tokens[value_ind]) {
+                (TextToken::Scalar(key), TextToken::Scalar(_)) => variable_name(*key),
+                _ => None,
+            };
+
+            match definition {
+                Some(name) => {
+                    let value = match self.value(vars, value_ind)? {
+                        Some(x) => x,
+                        None => self.tokens[value_ind]
+                            .as_scalar()
+                            .map(|x| x.view_data().to_vec())
+                            .unwrap_or_default(),
+                    };
+                    vars.values.insert(name.to_vec(), value);
+                }
+                None => {
+                    self.value(vars, value_ind)?;
+                }
+            }
+
+            ind = next_idx(self.tokens, value_ind);
+        }
+
+        Ok(())
+    }
+
+    fn array(&mut self, vars: &mut Variables, mut ind: usize, end: usize) -> Result<(), Error> {
+        while ind < end {
+            self.value(vars, ind)?;
+            ind = next_idx(self.tokens, ind);
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the value at the given index. If the value is a scalar that
+    /// was substituted, the substitution is returned.
+    fn value(&mut self, vars: &mut Variables, ind: usize) -> Result<Option<Vec<u8>>, Error> {
+        match self.tokens[ind] {
+            TextToken::Object(end) | TextToken::HiddenObject(end) => {
+                self.object(vars, ind + 1, end)?;
+                Ok(None)
+            }
+            TextToken::Array(end) => {
+                self.array(vars, ind + 1, end)?;
+                Ok(None)
+            }
+            TextToken::Header(_) => self.value(vars, ind + 1),
+            TextToken::Scalar(x) => self.scalar(vars, x),
+            TextToken::Operator(_) | TextToken::End(_) => Ok(None),
+        }
+    }
+
+    fn scalar(&mut self, vars: &Variables, scalar: Scalar<'a>) -> Result<Option<Vec<u8>>, Error> {
+        let raw = scalar.view_data();
+        let offset = raw.as_ptr() as usize - self.data.as_ptr() as usize;
+
+        // Leave quoted strings alone
+        if offset > 0 && self.data[offset - 1] == b'"' {
+            return Ok(None);
+        }
+
+        let value = if raw.starts_with(b"@[") && raw.ends_with(b"]") {
+            let expr = &raw[2..raw.len() - 1];
+            let result = vars.evaluate_bytes(expr, offset + 2)?;
+            format_number(result).into_bytes()
+        } else if let Some(name) = variable_name(scalar) {
+            match vars.values.get(name) {
+                Some(x) => x.clone(),
+                None => return Ok(None),
+            }
+        } else {
+            return Ok(None);
+        };
+
+        self.replacements.push((offset, raw.len(), value.clone()));
+        Ok(Some(value))
+    }
+}
+
+fn variable_name(scalar: Scalar<'_>) -> Option<&[u8]> {
+    match scalar.view_data() {
+        [b'@', rest @ ..] if !rest.is_empty() && rest[0] != b'[' => Some(rest),
+        _ => None,
+    }
+}
+
+/// Inline math results are written with up to 5 decimal places
+fn format_number(x: f64) -> String {
+    let x = (x * 100_000.0).round() / 100_000.0;
+    if x == 0.0 {
+        String::from("0")
+    } else {
+        format!("{}", x)
+    }
+}
+
+struct ExprParser<'b> {
+    data: &'b [u8],
+    pos: usize,
+    base_offset: usize,
+    vars: &'b Variables,
+}
+
+impl<'b> ExprParser<'b> {
+    fn error(&self, msg: &str) -> Error {
+        Error::new(ErrorKind::InvalidSyntax {
+            msg: String::from(msg),
+            offset: self.base_offset + self.pos,
+        })
+    }
+
+    fn skip_ws(&mut self) {
+        while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        self.data.get(self.pos).copied()
+    }
+
+    fn expr(&mut self) -> Result<f64, Error> {
+        let mut result = self.term()?;
+        loop {
+            match self.peek() {
+                Some(b'+') => {
+                    self.pos += 1;
+                    result += self.term()?;
+                }
+                Some(b'-') => {
+                    self.pos += 1;
+                    result -= self.term()?;
+                }
+                _ => return Ok(result),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, Error> {
+        let mut result = self.factor()?;
+        loop {
+            match self.peek() {
+                Some(b'*') => {
+                    self.pos += 1;
+                    result *= self.factor()?;
+                }
+                Some(b'/') => {
+                    self.pos += 1;
+                    let divisor = self.factor()?;
+                    if divisor == 0.0 {
+                        return Err(self.error("division by zero in inline math"));
+                    }
+                    result /= divisor;
+                }
+                _ => return Ok(result),
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<f64, Error> {
+        match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                Ok(-self.factor()?)
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let result = self.expr()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.error("expected closing parenthesis in inline math"));
+                }
+                self.pos += 1;
+                Ok(result)
+            }
+            Some(x) if x.is_ascii_digit() || x == b'.' => {
+                let start = self.pos;
+                let len = self.data[start..]
+                    .iter()
+                    .take_while(|x| x.is_ascii_digit() || **x == b'.')
+                    .count();
+                self.pos += len;
+                Scalar::new(&self.data[start..self.pos])
+                    .to_f64()
+                    .map_err(|_| self.error("invalid number in inline math"))
+            }
+            Some(x) if x.is_ascii_alphabetic() || x == b'_' || x == b'@' => {
+                let start = self.pos;
+                if x == b'@' {
+                    self.pos += 1;
+                }
+                let name_start = self.pos;
+                let len = self.data[name_start..]
+                    .iter()
+                    .take_while(|x| x.is_ascii_alphanumeric() || **x == b'_')
+                    .count();
+                self.pos += len;
+                let name = &self.data[name_start..self.pos];
+                let value = match self.vars.values.get(name) {
+                    Some(x) => x,
+                    None => {
+                        self.pos = start;
+                        return Err(self.error("undefined variable in inline math"));
+                    }
+                };
+
+                Scalar::new(value).to_f64().map_err(|_| {
+                    self.pos = start;
+                    self.error("variable in inline math is not a number")
+                })
+            }
+            _ => Err(self.error("expected a number or variable in inline math")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(data: &[u8]) -> Result<String, Error> {
+        Variables::new()
+            .resolve(data)
+            .map(|x| String::from_utf8(x).unwrap())
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let data = b"@a = 5 @b = @a c = { d = @b e = @unknown } f = \"@a\"";
+        assert_eq!(
+            resolve(&data[..]).unwrap(),
+            "@a = 5 @b = 5 c = { d = 5 e = @unknown } f = \"@a\""
+        );
+    }
+
+    #[test]
+    fn test_resolve_inline_math() {
+        let data = b"@a = 5 @b = @[a / 2] c = @[ b * (a - 1) ] d = @[-a]";
+        assert_eq!(resolve(&data[..]).unwrap(), "@a = 5 @b = 2.5 c = 10 d = -5");
+    }
+
+    #[test]
+    fn test_resolve_nested_definitions() {
+        let data = b"a = { @x = 1 b = @x } c = rgb { @x 0 0 } d = { @x @[x + 1] }";
+        assert_eq!(
+            resolve(&data[..]).unwrap(),
+            "a = { @x = 1 b = 1 } c = rgb { 1 0 0 } d = { 1 2 }"
+        );
+    }
+
+    #[test]
+    fn test_resolve_predefined() {
+        let mut vars = Variables::new();
+        vars.insert("@cost", "10");
+        let res = vars.resolve(b"a = @cost").unwrap();
+        assert_eq!(res.as_slice(), b"a = 10");
+        assert_eq!(vars.get("cost"), Some(&b"10"[..]));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let vars = Variables::new();
+        assert!(vars.evaluate("1 / 0").is_err());
+        assert!(vars.evaluate("1 +").is_err());
+        assert!(vars.evaluate("(1 + 2").is_err());
+        assert!(vars.evaluate("1 2").is_err());
+
+        let err = resolve(b"a = @[1 + missing]").unwrap_err();
+        assert_eq!(err.offset(), Some(10));
+    }
+
+    #[test]
+    fn test_evaluate_precedence() {
+        let vars = Variables::new();
+        assert_eq!(vars.evaluate("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(vars.evaluate("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(vars.evaluate("10 - 4 - 3").unwrap(), 3.0);
+        assert_eq!(format_number(1.0 / 3.0), "0.33333");
+        assert_eq!(format_number(-0.0), "0");
+    }
+}
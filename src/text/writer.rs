@@ -0,0 +1,1068 @@
+use crate::common::Date;
+use crate::{Error, ErrorKind, Operator, Scalar, TextTape, TextToken};
+use std::io::Write;
+
+/// The line ending that separates lines of output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Newline {
+    /// A line feed (`\n`)
+    Lf,
+
+    /// A carriage return and line feed (`\r\n`), which is what the games
+    /// write on all platforms
+    CrLf,
+}
+
+impl Newline {
+    pub(crate) fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Newline::Lf => b"\n",
+            Newline::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// Decides which scalars are written with quotes. Scalars that can't be
+/// written without quotes (eg: ones that contain whitespace) are always
+/// quoted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotePolicy {
+    /// Quote every value that isn't a number, boolean, or date. Keys are only
+    /// quoted when needed.
+    Always,
+
+    /// Only quote scalars when needed
+    WhenNeeded,
+
+    /// Quote scalars that were quoted in the original data. This requires
+    /// the data that the tape was parsed from (see
+    /// `TextWriter::write_tape_with_source`), otherwise scalars are only
+    /// quoted when needed.
+    Preserve,
+}
+
+/// Decides whether date shaped scalars (eg: `1444.11.11`) are written with
+/// quotes. The games are inconsistent here, so some text saves quote dates
+/// while others don't. Keys are never quoted by this option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateQuoting {
+    /// Dates are quoted according to the quote policy
+    Policy,
+
+    /// Always quote dates
+    Always,
+
+    /// Never quote dates
+    Never,
+}
+
+/// Build a tweaked text writer
+///
+/// By default, output is compact: each top level field is written on its own
+/// line and nested objects and arrays are written on a single line, which is
+/// how the games write inner objects. In pretty mode, each field of an object
+/// is written on its own line and indented according to its depth. Arrays of
+/// only scalars are kept on a single line in both modes.
+///
+/// ```
+/// use jomini::{Newline, TextTape, TextWriterBuilder};
+///
+/// let tape = TextTape::from_slice(b"a={ b=c d={ 1 2 } }")?;
+///
+/// let mut writer = TextWriterBuilder::new().from_writer(Vec::new());
+/// writer.write_tape(&tape)?;
+/// assert_eq!(writer.into_inner(), b"a={ b=c d={ 1 2 } }\n");
+///
+/// let mut writer = TextWriterBuilder::new()
+///     .pretty(true)
+///     .indent("  ")
+///     .newline(Newline::CrLf)
+///     .from_writer(Vec::new());
+/// writer.write_tape(&tape)?;
+/// assert_eq!(writer.into_inner(), b"a={\r\n  b=c\r\n  d={ 1 2 }\r\n}\r\n");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TextWriterBuilder {
+    pretty: bool,
+    indent: Vec<u8>,
+    newline: Newline,
+    quote_policy: QuotePolicy,
+    date_quoting: DateQuoting,
+    escape: bool,
+}
+
+impl Default for TextWriterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextWriterBuilder {
+    /// Create a new builder instance
+    pub fn new() -> Self {
+        TextWriterBuilder {
+            pretty: false,
+            indent: vec![b'\t'],
+            newline: Newline::Lf,
+            quote_policy: QuotePolicy::WhenNeeded,
+            date_quoting: DateQuoting::Policy,
+            escape: false,
+        }
+    }
+
+    /// Write each field of an object on its own line
+    pub fn pretty(&mut self, enabled: bool) -> &mut Self {
+        self.pretty = enabled;
+        self
+    }
+
+    /// Set what is written for each level of depth in pretty mode. Defaults
+    /// to a tab.
+    pub fn indent(&mut self, indent: &str) -> &mut Self {
+        self.indent = indent.as_bytes().to_vec();
+        self
+    }
+
+    /// Set the line ending. Defaults to a line feed.
+    pub fn newline(&mut self, newline: Newline) -> &mut Self {
+        self.newline = newline;
+        self
+    }
+
+    /// Set which scalars are written with quotes. Defaults to only quoting
+    /// when needed.
+    pub fn quote_policy(&mut self, policy: QuotePolicy) -> &mut Self {
+        self.quote_policy = policy;
+        self
+    }
+
+    /// Set whether date values are written with quotes, regardless of how
+    /// they were written in the original data. Defaults to following the
+    /// quote policy.
+    ///
+    /// ```
+    /// use jomini::{DateQuoting, TextTape, TextWriterBuilder};
+    ///
+    /// let tape = TextTape::from_slice(b"start=\"1444.11.11\" end=1821.1.1")?;
+    /// let mut writer = TextWriterBuilder::new()
+    ///     .date_quoting(DateQuoting::Never)
+    ///     .from_writer(Vec::new());
+    /// writer.write_tape(&tape)?;
+    /// assert_eq!(writer.into_inner(), b"start=1444.11.11\nend=1821.1.1\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn date_quoting(&mut self, quoting: DateQuoting) -> &mut Self {
+        self.date_quoting = quoting;
+        self
+    }
+
+    /// Escape quotes and backslashes (`\"` and `\\`) in quoted scalars.
+    ///
+    /// Scalars parsed from the text format keep their escape sequences as is,
+    /// so they are written out unchanged by default. Enable escaping when the
+    /// data is unescaped, like strings decoded from the binary format.
+    pub fn escape(&mut self, enabled: bool) -> &mut Self {
+        self.escape = enabled;
+        self
+    }
+
+    /// Create a text writer with the configured options
+    pub fn from_writer<W>(&self, writer: W) -> TextWriter<W>
+    where
+        W: Write,
+    {
+        TextWriter {
+            writer,
+            config: self.clone(),
+            frames: Vec::new(),
+            pending_value: false,
+        }
+    }
+
+    /// Parse the text data and write it out to the writer, replacing scalar
+    /// values with what the callback returns. The callback is given the keys
+    /// that lead to each value, so selected fields can be rewritten, like
+    /// anonymizing player names before a save is shared in a bug report.
+    ///
+    /// ```
+    /// use jomini::{QuotePolicy, TextWriterBuilder};
+    ///
+    /// let data = b"player_name=\"Alice\" countries={ ENG={ name=\"Alice's Empire\" gold=10 } }";
+    /// let out = TextWriterBuilder::new()
+    ///     .quote_policy(QuotePolicy::Preserve)
+    ///     .transform(&data[..], Vec::new(), |path, _value| {
+    ///         match path.last().map(|x| x.view_data()) {
+    ///             Some(b"player_name") | Some(b"name") => Some(b"anonymous".to_vec()),
+    ///             _ => None,
+    ///         }
+    ///     })?;
+    ///
+    /// assert_eq!(
+    ///     out,
+    ///     &b"player_name=\"anonymous\"\ncountries={ ENG={ name=\"anonymous\" gold=10 } }\n"[..]
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn transform<'data, W, F>(&self, data: &'data [u8], writer: W, f: F) -> Result<W, Error>
+    where
+        W: Write,
+        F: FnMut(&[Scalar<'data>], Scalar<'data>) -> Option<Vec<u8>>,
+    {
+        let tape = TextTape::from_slice(data)?;
+        let mut out = self.from_writer(writer);
+        out.write_tape_transformed(&tape, data, f)?;
+        out.finish()
+    }
+}
+
+/// Writes data in the text format
+///
+/// Besides writing out tapes, data can be written by hand. The writer keeps
+/// track of the depth to indent nested blocks and validates that every key
+/// is followed by a value and that values in objects have a key.
+///
+/// ```
+/// use jomini::TextWriterBuilder;
+///
+/// let mut writer = TextWriterBuilder::new().pretty(true).from_writer(Vec::new());
+/// writer.write_key("country")?;
+/// writer.write_object(|w| {
+///     w.write_key("tag")?;
+///     w.write_quoted("ENG")?;
+///     w.write_key("provinces")?;
+///     w.write_array(|w| {
+///         w.write_i64(236)?;
+///         w.write_i64(234)
+///     })
+/// })?;
+///
+/// let out = writer.finish()?;
+/// assert_eq!(out, b"country={\n\ttag=\"ENG\"\n\tprovinces={ 236 234 }\n}\n");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct TextWriter<W> {
+    writer: W,
+    config: TextWriterBuilder,
+
+    /// The blocks that are currently open
+    frames: Vec<Frame>,
+
+    /// If a key has been written and is waiting on its value
+    pending_value: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Object,
+    Array,
+}
+
+#[derive(Debug)]
+struct Frame {
+    kind: FrameKind,
+
+    /// If the block's elements are written on separate lines
+    multiline: bool,
+}
+
+impl<W> TextWriter<W>
+where
+    W: Write,
+{
+    /// Create a compact text writer. See `TextWriterBuilder` for other
+    /// options.
+    pub fn from_writer(writer: W) -> Self {
+        TextWriterBuilder::new().from_writer(writer)
+    }
+
+    /// Write out the contents of the tape at the top level
+    pub fn write_tape(&mut self, tape: &TextTape) -> Result<(), Error> {
+        self.write_tokens(tape.tokens(), None, None)
+    }
+
+    /// Write out the contents of the tape that was parsed from the given
+    /// data, which allows `QuotePolicy::Preserve` to see which scalars were
+    /// quoted.
+    ///
+    /// ```
+    /// use jomini::{QuotePolicy, TextTape, TextWriterBuilder};
+    ///
+    /// let data = b"name=\"Jean\" culture=french";
+    /// let tape = TextTape::from_slice(&data[..])?;
+    /// let mut writer = TextWriterBuilder::new()
+    ///     .quote_policy(QuotePolicy::Preserve)
+    ///     .from_writer(Vec::new());
+    /// writer.write_tape_with_source(&tape, &data[..])?;
+    /// assert_eq!(writer.into_inner(), b"name=\"Jean\"\nculture=french\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_tape_with_source(&mut self, tape: &TextTape, data: &[u8]) -> Result<(), Error> {
+        self.write_tokens(tape.tokens(), Some(data), None)
+    }
+
+    /// Write out the contents of the tape that was parsed from the given
+    /// data, giving each scalar value to the callback along with the keys
+    /// that lead to it. When the callback returns a replacement, the
+    /// replacement is written instead of the value. Keys are never given to
+    /// the callback. See `TextWriterBuilder::transform`.
+    pub fn write_tape_transformed<'data, F>(
+        &mut self,
+        tape: &TextTape<'data>,
+        data: &[u8],
+        mut f: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&[Scalar<'data>], Scalar<'data>) -> Option<Vec<u8>>,
+    {
+        self.write_tokens(tape.tokens(), Some(data), Some(&mut f))
+    }
+
+    /// Consume the writer and return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Consume the writer and return the underlying writer after checking
+    /// that the last key was given a value
+    pub fn finish(self) -> Result<W, Error> {
+        if self.pending_value {
+            return Err(writer_error("expected a value after the last key"));
+        }
+
+        Ok(self.writer)
+    }
+
+    /// The number of blocks that are currently open
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Write the key of a field. Keys can only be written in objects (or at
+    /// the top level) and must be followed by a value.
+    pub fn write_key(&mut self, key: &str) -> Result<(), Error> {
+        self.write_key_operator(key, None)
+    }
+
+    /// Write the key of a field that is separated from its value with the
+    /// given operator instead of an equal sign (`a > 1`)
+    pub fn write_key_operator(&mut self, key: &str, op: Option<Operator>) -> Result<(), Error> {
+        if self.pending_value {
+            return Err(writer_error("expected a value after the previous key"));
+        }
+
+        let depth = self.frames.len();
+        match self.frames.last_mut() {
+            None => {}
+            Some(frame) if frame.kind == FrameKind::Array => {
+                return Err(writer_error("keys can't be written in an array"));
+            }
+            Some(frame) => {
+                frame.multiline = self.config.pretty;
+                if self.config.pretty {
+                    self.writer.write_all(self.config.newline.as_bytes())?;
+                    self.write_indent(depth)?;
+                } else {
+                    self.writer.write_all(b" ")?;
+                }
+            }
+        }
+
+        self.write_scalar(key.as_bytes(), true, None)?;
+        self.write_operator(op)?;
+        self.pending_value = true;
+        Ok(())
+    }
+
+    /// Write a string value without quotes, unless it can't be written
+    /// without them
+    pub fn write_unquoted(&mut self, value: &str) -> Result<(), Error> {
+        self.begin_value(false)?;
+        self.write_scalar_data(value.as_bytes(), needs_quotes(value.as_bytes()))?;
+        self.end_value()
+    }
+
+    /// Write a string value surrounded by quotes
+    pub fn write_quoted(&mut self, value: &str) -> Result<(), Error> {
+        self.begin_value(false)?;
+        self.write_scalar_data(value.as_bytes(), true)?;
+        self.end_value()
+    }
+
+    /// Write a boolean value as `yes` or `no`
+    pub fn write_bool(&mut self, value: bool) -> Result<(), Error> {
+        self.write_unquoted(if value { "yes" } else { "no" })
+    }
+
+    /// Write a signed integer value
+    pub fn write_i64(&mut self, value: i64) -> Result<(), Error> {
+        self.write_unquoted(&value.to_string())
+    }
+
+    /// Write an unsigned integer value
+    pub fn write_u64(&mut self, value: u64) -> Result<(), Error> {
+        self.write_unquoted(&value.to_string())
+    }
+
+    /// Write a float value
+    pub fn write_f64(&mut self, value: f64) -> Result<(), Error> {
+        self.write_unquoted(&value.to_string())
+    }
+
+    /// Write a date value in the game's format (`1444.11.11`)
+    pub fn write_date(&mut self, value: Date) -> Result<(), Error> {
+        match self.config.date_quoting {
+            DateQuoting::Always => self.write_quoted(&value.game_fmt()),
+            DateQuoting::Policy | DateQuoting::Never => self.write_unquoted(&value.game_fmt()),
+        }
+    }
+
+    /// Write an object value whose contents are written by the given closure
+    pub fn write_object<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        self.write_block(FrameKind::Object, f)
+    }
+
+    /// Write an array value whose contents are written by the given closure
+    pub fn write_array<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        self.write_block(FrameKind::Array, f)
+    }
+
+    fn write_block<F>(&mut self, kind: FrameKind, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        self.begin_value(true)?;
+        self.writer.write_all(b"{")?;
+        self.pending_value = false;
+
+        let depth = self.frames.len();
+        self.frames.push(Frame {
+            kind,
+            multiline: false,
+        });
+
+        let result = f(self);
+        let pending_value = std::mem::replace(&mut self.pending_value, false);
+        let frame = self.frames.split_off(depth).swap_remove(0);
+        result?;
+
+        if pending_value {
+            return Err(writer_error("expected a value after the last key"));
+        }
+
+        if frame.multiline {
+            self.writer.write_all(self.config.newline.as_bytes())?;
+            self.write_indent(depth)?;
+            self.writer.write_all(b"}")?;
+        } else {
+            self.writer.write_all(b" }")?;
+        }
+
+        self.end_value()
+    }
+
+    /// Validates that a value can be written and writes what separates it
+    /// from the previous value
+    fn begin_value(&mut self, is_block: bool) -> Result<(), Error> {
+        let depth = self.frames.len();
+        match self.frames.last_mut() {
+            Some(frame) if frame.kind == FrameKind::Array => {
+                if self.config.pretty && (is_block || frame.multiline) {
+                    frame.multiline = true;
+                    self.writer.write_all(self.config.newline.as_bytes())?;
+                    self.write_indent(depth)?;
+                } else {
+                    self.writer.write_all(b" ")?;
+                }
+                Ok(())
+            }
+            _ if !self.pending_value => Err(writer_error("expected a key before the value")),
+            _ => Ok(()),
+        }
+    }
+
+    fn end_value(&mut self) -> Result<(), Error> {
+        self.pending_value = false;
+        if self.frames.is_empty() {
+            self.writer.write_all(self.config.newline.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_tokens<'data>(
+        &mut self,
+        tokens: &[TextToken<'data>],
+        source: Option<&[u8]>,
+        transform: Option<Transform<'_, 'data>>,
+    ) -> Result<(), Error> {
+        if !self.frames.is_empty() || self.pending_value {
+            return Err(writer_error("tapes can only be written at the top level"));
+        }
+
+        let mut tape_writer = TapeWriter {
+            out: self,
+            tokens,
+            source,
+            transform,
+            path: Vec::new(),
+        };
+
+        let mut idx = 0;
+        while idx < tokens.len() {
+            idx = tape_writer.write_field(idx, tokens.len(), 0)?;
+            tape_writer.out.write_newline()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the scalar, quoting it according to the quote policy. The
+    /// `quoted` hint is whether the scalar was quoted in the original data,
+    /// if known.
+    fn write_scalar(
+        &mut self,
+        data: &[u8],
+        is_key: bool,
+        quoted: Option<bool>,
+    ) -> Result<(), Error> {
+        if !is_key && Scalar::new(data).is_date_like() {
+            match self.config.date_quoting {
+                DateQuoting::Always => return self.write_scalar_data(data, true),
+                DateQuoting::Never => return self.write_scalar_data(data, false),
+                DateQuoting::Policy => {}
+            }
+        }
+
+        let quote = needs_quotes(data)
+            || match self.config.quote_policy {
+                QuotePolicy::Always => !is_key && !is_plain_value(data),
+                QuotePolicy::WhenNeeded => false,
+                QuotePolicy::Preserve => quoted.unwrap_or(false),
+            };
+        self.write_scalar_data(data, quote)
+    }
+
+    fn write_scalar_data(&mut self, data: &[u8], quote: bool) -> Result<(), Error> {
+        if !quote {
+            self.writer.write_all(data)?;
+            return Ok(());
+        }
+
+        self.writer.write_all(b"\"")?;
+        if self.config.escape {
+            let mut start = 0;
+            for (i, &c) in data.iter().enumerate() {
+                if c == b'"' || c == b'\\' {
+                    self.writer.write_all(&data[start..i])?;
+                    self.writer.write_all(b"\\")?;
+                    start = i;
+                }
+            }
+            self.writer.write_all(&data[start..])?;
+        } else {
+            self.writer.write_all(data)?;
+        }
+        self.writer.write_all(b"\"")?;
+        Ok(())
+    }
+
+    fn write_operator(&mut self, op: Option<Operator>) -> Result<(), Error> {
+        self.writer
+            .write_all(op.map_or(&b"="[..], operator_symbol))?;
+        Ok(())
+    }
+
+    fn write_indent(&mut self, depth: usize) -> Result<(), Error> {
+        for _ in 0..depth {
+            self.writer.write_all(&self.config.indent)?;
+        }
+        Ok(())
+    }
+
+    fn write_newline(&mut self) -> Result<(), Error> {
+        self.writer.write_all(self.config.newline.as_bytes())?;
+        Ok(())
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+}
+
+/// A callback that may replace a scalar value given the keys leading to it
+type Transform<'f, 'data> = &'f mut dyn FnMut(&[Scalar<'data>], Scalar<'data>) -> Option<Vec<u8>>;
+
+/// Walks the tokens of a tape and writes them out
+struct TapeWriter<'a, 'b, 'f, 'data, W> {
+    out: &'a mut TextWriter<W>,
+    tokens: &'b [TextToken<'data>],
+    source: Option<&'b [u8]>,
+    transform: Option<Transform<'f, 'data>>,
+
+    /// The keys leading to the value being written
+    path: Vec<Scalar<'data>>,
+}
+
+impl<'a, 'b, 'f, 'data, W> TapeWriter<'a, 'b, 'f, 'data, W>
+where
+    W: Write,
+{
+    /// Writes the field at the given index and returns the index of the next
+    /// field
+    fn write_field(&mut self, idx: usize, end: usize, depth: usize) -> Result<usize, Error> {
+        let key = match self.tokens[idx] {
+            TextToken::Scalar(x) => x,
+
+            // a value without a key (eg: the trailer of `a={ b=c {} }`)
+            _ => return self.write_value(idx, depth),
+        };
+
+        self.write_scalar(key, true)?;
+        if idx + 1 >= end {
+            return Ok(idx + 1);
+        }
+
+        let value_idx = match self.tokens[idx + 1] {
+            TextToken::Operator(op) => {
+                self.out.write_operator(Some(op))?;
+                idx + 2
+            }
+            _ => {
+                self.out.write_operator(None)?;
+                idx + 1
+            }
+        };
+
+        if value_idx >= end {
+            return Ok(value_idx);
+        }
+
+        self.path.push(key);
+        let result = self.write_value(value_idx, depth);
+        self.path.pop();
+        result
+    }
+
+    /// Writes the value at the given index and returns the index of the next
+    /// token
+    fn write_value(&mut self, idx: usize, depth: usize) -> Result<usize, Error> {
+        match self.tokens[idx] {
+            TextToken::Scalar(x) => {
+                let path = &self.path;
+                let replacement = self.transform.as_mut().and_then(|f| f(path, x));
+                match replacement {
+                    Some(data) => {
+                        let quoted = self.source.map(|s| was_quoted(s, x.view_data()));
+                        self.out.write_scalar(&data, false, quoted)?;
+                    }
+                    None => self.write_scalar(x, false)?,
+                }
+                Ok(idx + 1)
+            }
+            TextToken::Header(x) => {
+                self.out.write_raw(x.view_data())?;
+                self.out.write_raw(b" ")?;
+                self.write_value(idx + 1, depth)
+            }
+            TextToken::Object(end) => {
+                self.write_object(idx + 1, end, depth)?;
+                Ok(end + 1)
+            }
+            TextToken::Array(end) => {
+                self.write_array(idx + 1, end, depth)?;
+                Ok(end + 1)
+            }
+            TextToken::HiddenObject(end) => {
+                let mut idx = idx + 1;
+                while idx < end {
+                    self.out.write_raw(b" ")?;
+                    idx = self.write_field(idx, end, depth)?;
+                }
+                Ok(end + 1)
+            }
+            TextToken::Operator(_) | TextToken::End(_) => Ok(idx + 1),
+        }
+    }
+
+    fn write_object(&mut self, start: usize, end: usize, depth: usize) -> Result<(), Error> {
+        self.out.write_raw(b"{")?;
+        if start == end {
+            self.out.write_raw(b" }")?;
+            return Ok(());
+        }
+
+        if !self.out.config.pretty {
+            let mut idx = start;
+            while idx < end {
+                self.out.write_raw(b" ")?;
+                idx = self.write_field(idx, end, depth + 1)?;
+            }
+            self.out.write_raw(b" }")?;
+            return Ok(());
+        }
+
+        self.out.write_newline()?;
+        let mut idx = start;
+        while idx < end {
+            self.out.write_indent(depth + 1)?;
+            idx = self.write_field(idx, end, depth + 1)?;
+            self.out.write_newline()?;
+        }
+
+        self.out.write_indent(depth)?;
+        self.out.write_raw(b"}")
+    }
+
+    fn write_array(&mut self, start: usize, end: usize, depth: usize) -> Result<(), Error> {
+        self.out.write_raw(b"{")?;
+        let inline = !self.out.config.pretty
+            || self.tokens[start..end]
+                .iter()
+                .all(|x| matches!(x, TextToken::Scalar(_)));
+
+        if inline {
+            let mut idx = start;
+            while idx < end {
+                if !matches!(self.tokens[idx], TextToken::HiddenObject(_)) {
+                    self.out.write_raw(b" ")?;
+                }
+                idx = self.write_value(idx, depth + 1)?;
+            }
+            return self.out.write_raw(b" }");
+        }
+
+        self.out.write_newline()?;
+        let mut idx = start;
+        while idx < end {
+            if let TextToken::HiddenObject(hidden_end) = self.tokens[idx] {
+                idx += 1;
+                while idx < hidden_end {
+                    self.out.write_indent(depth + 1)?;
+                    idx = self.write_field(idx, hidden_end, depth + 1)?;
+                    self.out.write_newline()?;
+                }
+                idx = hidden_end + 1;
+            } else {
+                self.out.write_indent(depth + 1)?;
+                idx = self.write_value(idx, depth + 1)?;
+                self.out.write_newline()?;
+            }
+        }
+
+        self.out.write_indent(depth)?;
+        self.out.write_raw(b"}")
+    }
+
+    fn write_scalar(&mut self, scalar: Scalar, is_key: bool) -> Result<(), Error> {
+        let data = scalar.view_data();
+        let quoted = self.source.map(|source| was_quoted(source, data));
+        self.out.write_scalar(data, is_key, quoted)
+    }
+}
+
+/// Returns true if the scalar is surrounded by quotes in the source data that
+/// it was sliced from
+fn was_quoted(source: &[u8], data: &[u8]) -> bool {
+    let source_start = source.as_ptr() as usize;
+    let data_start = data.as_ptr() as usize;
+    if data_start <= source_start || data_start + data.len() >= source_start + source.len() {
+        return false;
+    }
+
+    let offset = data_start - source_start;
+    source[offset - 1] == b'"' && source[offset + data.len()] == b'"'
+}
+
+/// Returns true if the scalar is a number, boolean, or date, which the games
+/// write without quotes
+fn is_plain_value(data: &[u8]) -> bool {
+    let scalar = Scalar::new(data);
+    scalar.to_f64().is_ok() || scalar.to_bool().is_ok() || scalar.is_date_like()
+}
+
+fn writer_error(msg: &str) -> Error {
+    Error::new(ErrorKind::Writer(String::from(msg)))
+}
+
+fn operator_symbol(op: Operator) -> &'static [u8] {
+    match op {
+        Operator::LessThan => b"<",
+        Operator::LessThanEqual => b"<=",
+        Operator::GreaterThan => b">",
+        Operator::GreaterThanEqual => b">=",
+    }
+}
+
+/// Returns true if the scalar can't be written without quotes
+fn needs_quotes(data: &[u8]) -> bool {
+    data.is_empty()
+        || data.iter().any(|&b| {
+            b.is_ascii_whitespace() || matches!(b, b'"' | b'=' | b'{' | b'}' | b'#' | b'<' | b'>')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HiddenObjectStrategy;
+
+    fn write(data: &[u8], builder: &TextWriterBuilder) -> String {
+        let tape = TextTape::from_slice(data).unwrap();
+        let mut writer = builder.from_writer(Vec::new());
+        writer.write_tape(&tape).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_write_compact() {
+        let data = b"a = 1 b = { c = \"d e\" f > 2 } color = rgb { 1 2 3 } levels = { 10 0=2 1=2 } empty = {}";
+        let out = write(&data[..], &TextWriterBuilder::new());
+        assert_eq!(
+            out,
+            "a=1\nb={ c=\"d e\" f>2 }\ncolor=rgb { 1 2 3 }\nlevels={ 10 0=2 1=2 }\nempty={ }\n"
+        );
+
+        let original = TextTape::from_slice(&data[..]).unwrap();
+        let written = TextTape::from_slice(out.as_bytes()).unwrap();
+        assert_eq!(original.tokens(), written.tokens());
+    }
+
+    #[test]
+    fn test_write_pretty() {
+        let data = b"a={ b={ c=d } e={ { x=1 } { y=2 } } f={ 1 2 } }";
+        let out = write(&data[..], TextWriterBuilder::new().pretty(true));
+        assert_eq!(
+            out,
+            "a={\n\tb={\n\t\tc=d\n\t}\n\te={\n\t\t{\n\t\t\tx=1\n\t\t}\n\t\t{\n\t\t\ty=2\n\t\t}\n\t}\n\tf={ 1 2 }\n}\n"
+        );
+
+        let original = TextTape::from_slice(&data[..]).unwrap();
+        let written = TextTape::from_slice(out.as_bytes()).unwrap();
+        assert_eq!(original.tokens(), written.tokens());
+    }
+
+    #[test]
+    fn test_write_pretty_hidden_object() {
+        let data = b"levels={ 10 0=2 1=2 } other={ a 0={ b=c } }";
+        let parser = TextTape::parser().on_hidden_object(HiddenObjectStrategy::SynthesizeNested);
+        let original = parser.parse_slice(&data[..]).unwrap();
+        let mut writer = TextWriterBuilder::new()
+            .pretty(true)
+            .indent(" ")
+            .from_writer(Vec::new());
+        writer.write_tape(&original).unwrap();
+        let out = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(
+            out,
+            "levels={\n 10\n 0=2\n 1=2\n}\nother={\n a\n 0={\n  b=c\n }\n}\n"
+        );
+
+        let written = parser.parse_slice(out.as_bytes()).unwrap();
+        assert_eq!(original.tokens(), written.tokens());
+    }
+
+    #[test]
+    fn test_write_quote_policies() {
+        let data = b"name=\"Jean\" culture=french date=1444.11.11 \"key\"=yes gold=10.5 id=\"10\"";
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+
+        let mut writer = TextWriterBuilder::new()
+            .quote_policy(QuotePolicy::Always)
+            .from_writer(Vec::new());
+        writer.write_tape(&tape).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"name=\"Jean\"\nculture=\"french\"\ndate=1444.11.11\nkey=yes\ngold=10.5\nid=10\n"
+        );
+
+        let mut writer = TextWriterBuilder::new()
+            .quote_policy(QuotePolicy::Preserve)
+            .from_writer(Vec::new());
+        writer.write_tape(&tape).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"name=Jean\nculture=french\ndate=1444.11.11\nkey=yes\ngold=10.5\nid=10\n"
+        );
+
+        let mut writer = TextWriterBuilder::new()
+            .quote_policy(QuotePolicy::Preserve)
+            .from_writer(Vec::new());
+        writer.write_tape_with_source(&tape, &data[..]).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"name=\"Jean\"\nculture=french\ndate=1444.11.11\n\"key\"=yes\ngold=10.5\nid=\"10\"\n"
+        );
+    }
+
+    #[test]
+    fn test_write_date_quoting() {
+        let data = b"a=\"1444.11.11\" b=1444.11.12 \"1444.11.13\"=c";
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+
+        let mut writer = TextWriterBuilder::new()
+            .quote_policy(QuotePolicy::Preserve)
+            .from_writer(Vec::new());
+        writer.write_tape_with_source(&tape, &data[..]).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"a=\"1444.11.11\"\nb=1444.11.12\n\"1444.11.13\"=c\n"
+        );
+
+        let mut writer = TextWriterBuilder::new()
+            .quote_policy(QuotePolicy::Preserve)
+            .date_quoting(DateQuoting::Always)
+            .from_writer(Vec::new());
+        writer.write_tape_with_source(&tape, &data[..]).unwrap();
+        writer.write_key("c").unwrap();
+        writer.write_date(Date::new(1444, 11, 14).unwrap()).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"a=\"1444.11.11\"\nb=\"1444.11.12\"\n\"1444.11.13\"=c\nc=\"1444.11.14\"\n"
+        );
+
+        let mut writer = TextWriterBuilder::new()
+            .quote_policy(QuotePolicy::Preserve)
+            .date_quoting(DateQuoting::Never)
+            .from_writer(Vec::new());
+        writer.write_tape_with_source(&tape, &data[..]).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            b"a=1444.11.11\nb=1444.11.12\n\"1444.11.13\"=c\n"
+        );
+    }
+
+    #[test]
+    fn test_write_escapes() {
+        let data = br#"a="x \"y\"" b="""#;
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+
+        let mut writer = TextWriter::from_writer(Vec::new());
+        writer.write_tape(&tape).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            br#"a="x \"y\""
+b=""
+"#
+        );
+
+        let mut writer = TextWriterBuilder::new()
+            .escape(true)
+            .from_writer(Vec::new());
+        writer.write_tape(&tape).unwrap();
+        assert_eq!(
+            writer.into_inner(),
+            br#"a="x \\\"y\\\""
+b=""
+"#
+        );
+    }
+
+    fn write_manual(builder: &TextWriterBuilder) -> Result<Vec<u8>, Error> {
+        let mut writer = builder.from_writer(Vec::new());
+        writer.write_key("date")?;
+        writer.write_date(Date::new(1444, 11, 11).unwrap())?;
+        writer.write_key("empty")?;
+        writer.write_object(|_| Ok(()))?;
+        writer.write_key("flags")?;
+        writer.write_array(|w| {
+            w.write_unquoted("a")?;
+            w.write_object(|w| {
+                w.write_key_operator("b", Some(Operator::GreaterThan))?;
+                w.write_f64(1.5)
+            })?;
+            w.write_bool(true)
+        })?;
+        writer.write_key("ids")?;
+        writer.write_array(|w| {
+            assert_eq!(w.depth(), 1);
+            w.write_u64(1)?;
+            w.write_i64(-2)
+        })?;
+        writer.finish()
+    }
+
+    #[test]
+    fn test_write_manual() {
+        let out = write_manual(&TextWriterBuilder::new()).unwrap();
+        assert_eq!(
+            out,
+            b"date=1444.11.11\nempty={ }\nflags={ a { b>1.5 } yes }\nids={ 1 -2 }\n"
+        );
+
+        let out = write_manual(TextWriterBuilder::new().pretty(true)).unwrap();
+        assert_eq!(
+            out,
+            b"date=1444.11.11\nempty={ }\nflags={ a\n\t{\n\t\tb>1.5\n\t}\n\tyes\n}\nids={ 1 -2 }\n"
+        );
+
+        let original = TextTape::from_slice(
+            b"date=1444.11.11 empty={} flags={ a { b>1.5 } yes } ids={ 1 -2 }",
+        )
+        .unwrap();
+        let written = TextTape::from_slice(&out).unwrap();
+        assert_eq!(original.tokens(), written.tokens());
+    }
+
+    #[test]
+    fn test_write_manual_validation() {
+        let mut writer = TextWriter::from_writer(Vec::new());
+        assert!(writer.write_i64(1).is_err());
+
+        writer.write_key("a").unwrap();
+        assert!(writer.write_key("b").is_err());
+
+        let mut writer = TextWriter::from_writer(Vec::new());
+        writer.write_key("a").unwrap();
+        let err = writer.write_array(|w| w.write_key("b")).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Writer(_)));
+        assert_eq!(writer.depth(), 0);
+
+        let mut writer = TextWriter::from_writer(Vec::new());
+        writer.write_key("a").unwrap();
+        assert!(writer.write_object(|w| w.write_key("b")).is_err());
+
+        let mut writer = TextWriter::from_writer(Vec::new());
+        writer.write_key("a").unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn test_write_transformed() {
+        let data = b"names={ \"a\" b } levels={ 10 name=x } list={ { name=y } } name>z";
+        let mut paths = Vec::new();
+        let out = TextWriterBuilder::new()
+            .transform(&data[..], Vec::new(), |path, value| {
+                let path: Vec<_> = path.iter().map(|x| x.to_string()).collect();
+                paths.push(path.join("/"));
+                match value.view_data() {
+                    b"a" | b"x" => Some(b"hello world".to_vec()),
+                    _ => None,
+                }
+            })
+            .unwrap();
+
+        assert_eq!(
+            paths,
+            vec![
+                "names",
+                "names",
+                "levels",
+                "levels/name",
+                "list/name",
+                "name"
+            ]
+        );
+        assert_eq!(
+            out,
+            &b"names={ \"hello world\" b }\nlevels={ 10 name=\"hello world\" }\nlist={ { name=y } }\nname>z\n"[..]
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Building blocks for editor integrations like a language server.
+//!
+//! A [`SourceFile`] keeps the lexemes of a text document up to date as the
+//! document is edited. Since lexing a position only depends on the data that
+//! follows it, an edit is applied by lexing from the last lexeme before the
+//! edit until the new lexemes line up with the old ones again, so a
+//! keystroke doesn't require the whole document to be lexed.
+//!
+//! ```
+//! use jomini::{tooling::SourceFile, LexemeKind};
+//!
+//! let mut file = SourceFile::new(b"a = { b = c }".to_vec());
+//! assert!(file.diagnostics().is_empty());
+//!
+//! file.edit(12..13, b"");
+//! assert_eq!(file.text(), b"a = { b = c ");
+//! assert_eq!(file.diagnostics()[0].to_string(), "unclosed brace at 0:4");
+//!
+//! let token = file.token_at(10).unwrap();
+//! assert_eq!(token.kind, LexemeKind::Scalar);
+//! assert_eq!(token.span, 10..11);
+//! ```
+
+use crate::{lex, LexemeKind};
+use std::fmt;
+use std::ops::Range;
+
+/// A lexeme of a [`SourceFile`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// The kind of lexeme
+    pub kind: LexemeKind,
+
+    /// The byte range of the lexeme in the file
+    pub span: Range<usize>,
+}
+
+/// The kind of syntax problem that a diagnostic describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxDiagnosticKind {
+    /// An opening brace without a closing brace
+    UnclosedBrace,
+
+    /// A closing brace without an opening brace
+    UnexpectedCloseBrace,
+
+    /// A quoted scalar without a closing quote
+    UnterminatedQuote,
+}
+
+/// A syntax problem found in a [`SourceFile`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxDiagnostic {
+    /// The kind of problem
+    pub kind: SyntaxDiagnosticKind,
+
+    /// The byte range of the offending lexeme
+    pub span: Range<usize>,
+
+    /// The zero based line and column of the start of the span
+    pub position: (usize, usize),
+}
+
+impl fmt::Display for SyntaxDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self.kind {
+            SyntaxDiagnosticKind::UnclosedBrace => "unclosed brace",
+            SyntaxDiagnosticKind::UnexpectedCloseBrace => "unexpected closing brace",
+            SyntaxDiagnosticKind::UnterminatedQuote => "unterminated quote",
+        };
+
+        write!(f, "{} at {}:{}", msg, self.position.0, self.position.1)
+    }
+}
+
+/// A text document whose lexemes are kept up to date across edits
+#[derive(Debug, Clone, Default)]
+pub struct SourceFile {
+    data: Vec<u8>,
+    tokens: Vec<Token>,
+
+    /// Byte offset of the start of each line after the first
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    /// Lex the document
+    pub fn new(data: Vec<u8>) -> Self {
+        let tokens = lex(&data)
+            .map(|x| Token {
+                kind: x.kind,
+                span: x.span,
+            })
+            .collect();
+        let line_starts = line_starts(&data, 0);
+        SourceFile {
+            data,
+            tokens,
+            line_starts,
+        }
+    }
+
+    /// The current contents of the document
+    pub fn text(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The lexemes of the document in order
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Replace the given byte range of the document. Returns the range of
+    /// indices in `tokens` that were relexed, everything outside of it was
+    /// only shifted.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds
+    pub fn edit(&mut self, range: Range<usize>, replacement: &[u8]) -> Range<usize> {
+        let inserted = replacement.len();
+        let removed = range.end - range.start;
+        let shift = |x: usize| x + inserted - removed;
+        self.data.splice(range.clone(), replacement.iter().copied());
+
+        // Update the line index
+        let first_line = self.line_starts.partition_point(|&x| x <= range.start);
+        let last_line = self.line_starts.partition_point(|&x| x <= range.end);
+        let mut new_lines = line_starts(replacement, range.start);
+        new_lines.extend(self.line_starts[last_line..].iter().map(|&x| shift(x)));
+        self.line_starts.truncate(first_line);
+        self.line_starts.extend(new_lines);
+
+        // Lexemes that end before the edit are unaffected, so lexing restarts
+        // at the last lexeme that starts before the edit
+        let restart = self
+            .tokens
+            .partition_point(|x| x.span.start < range.start)
+            .saturating_sub(1);
+        let start = self.tokens.get(restart).map_or(0, |x| x.span.start);
+
+        // Old lexemes that start after the edit are candidates to resync with
+        let mut old = self.tokens.partition_point(|x| x.span.start < range.end);
+        let mut relexed = Vec::new();
+        let mut resynced = false;
+        for lexeme in lex(&self.data[start..]) {
+            let span = lexeme.span.start + start..lexeme.span.end + start;
+            while old < self.tokens.len() && shift(self.tokens[old].span.start) < span.start {
+                old += 1;
+            }
+
+            if span.start >= range.start + inserted
+                && old < self.tokens.len()
+                && shift(self.tokens[old].span.start) == span.start
+            {
+                resynced = true;
+                break;
+            }
+
+            relexed.push(Token {
+                kind: lexeme.kind,
+                span,
+            });
+        }
+
+        if !resynced {
+            old = self.tokens.len();
+        }
+
+        let changed = restart..restart + relexed.len();
+        for token in &mut self.tokens[old..] {
+            token.span = shift(token.span.start)..shift(token.span.end);
+        }
+        self.tokens.splice(restart..old, relexed);
+        changed
+    }
+
+    /// Returns the lexeme that contains the byte offset
+    pub fn token_at(&self, offset: usize) -> Option<&Token> {
+        let idx = self.tokens.partition_point(|x| x.span.end <= offset);
+        self.tokens.get(idx).filter(|x| x.span.start <= offset)
+    }
+
+    /// Returns the zero based line and column (in bytes) of the byte offset
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&x| x <= offset);
+        let start = line.checked_sub(1).map_or(0, |x| self.line_starts[x]);
+        (line, offset - start)
+    }
+
+    /// Check the document for unbalanced braces and unterminated quotes
+    pub fn diagnostics(&self) -> Vec<SyntaxDiagnostic> {
+        let mut result = Vec::new();
+        let mut open = Vec::new();
+        for token in &self.tokens {
+            match token.kind {
+                LexemeKind::OpenBrace => open.push(token.span.clone()),
+                LexemeKind::CloseBrace if open.pop().is_none() => {
+                    result.push(self.diagnostic(
+                        SyntaxDiagnosticKind::UnexpectedCloseBrace,
+                        token.span.clone(),
+                    ));
+                }
+                LexemeKind::Quoted => {
+                    let data = &self.data[token.span.clone()];
+                    if data.len() < 2 || !is_closed_quote(data) {
+                        result.push(self.diagnostic(
+                            SyntaxDiagnosticKind::UnterminatedQuote,
+                            token.span.clone(),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for span in open {
+            result.push(self.diagnostic(SyntaxDiagnosticKind::UnclosedBrace, span));
+        }
+
+        result.sort_by_key(|x| x.span.start);
+        result
+    }
+
+    fn diagnostic(&self, kind: SyntaxDiagnosticKind, span: Range<usize>) -> SyntaxDiagnostic {
+        SyntaxDiagnostic {
+            kind,
+            position: self.position(span.start),
+            span,
+        }
+    }
+}
+
+/// Returns true if the quoted lexeme ends with a quote that isn't escaped
+fn is_closed_quote(data: &[u8]) -> bool {
+    let mut pos = 1;
+    while pos < data.len() {
+        match data[pos] {
+            b'\\' => pos += 2,
+            b'"' => return pos + 1 == data.len(),
+            _ => pos += 1,
+        }
+    }
+
+    false
+}
+
+fn line_starts(data: &[u8], offset: usize) -> Vec<usize> {
+    data.iter()
+        .enumerate()
+        .filter(|(_, &c)| c == b'\n')
+        .map(|(i, _)| offset + i + 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matches_full_lex(file: &SourceFile) {
+        let expected = SourceFile::new(file.text().to_vec());
+        assert_eq!(file.tokens(), expected.tokens());
+        assert_eq!(file.line_starts, expected.line_starts);
+    }
+
+    #[test]
+    fn test_edits_match_full_lex() {
+        let mut file = SourceFile::new(b"a = { b = c }\nd = \"e f\"\n# g\nh = 1".to_vec());
+
+        // Extend a scalar
+        assert_eq!(file.edit(11..11, b"cc"), 10..11);
+        assert_matches_full_lex(&file);
+
+        // Open a quote that swallows everything up to the next quote
+        file.edit(0..0, b"\"");
+        assert_matches_full_lex(&file);
+        assert_eq!(file.tokens()[0].kind, LexemeKind::Quoted);
+
+        file.edit(0..1, b"");
+        assert_matches_full_lex(&file);
+
+        // Turn a line into a comment and back
+        file.edit(16..16, b"#");
+        assert_matches_full_lex(&file);
+        file.edit(16..17, b"");
+        assert_matches_full_lex(&file);
+
+        // Join and split lines
+        file.edit(15..16, b"");
+        assert_matches_full_lex(&file);
+        file.edit(14..14, b"\n\n x={\n}");
+        assert_matches_full_lex(&file);
+
+        file.edit(0..file.text().len(), b"");
+        assert_matches_full_lex(&file);
+        assert!(file.tokens().is_empty());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let file = SourceFile::new(b"a = {\n b = }}\nc = \"d".to_vec());
+        let diagnostics: Vec<_> = file.diagnostics().iter().map(|x| x.to_string()).collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                "unexpected closing brace at 1:6",
+                "unterminated quote at 2:4"
+            ]
+        );
+
+        let file = SourceFile::new(br#"a = "b\" c = { "#.to_vec());
+        let kinds: Vec<_> = file.diagnostics().iter().map(|x| x.kind).collect();
+        assert_eq!(kinds, vec![SyntaxDiagnosticKind::UnterminatedQuote]);
+    }
+
+    #[test]
+    fn test_position() {
+        let file = SourceFile::new(b"a\nbc\n\nd".to_vec());
+        assert_eq!(file.position(0), (0, 0));
+        assert_eq!(file.position(3), (1, 1));
+        assert_eq!(file.position(5), (2, 0));
+        assert_eq!(file.position(6), (3, 0));
+    }
+}
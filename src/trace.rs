@@ -0,0 +1,57 @@
+/// A span around a unit of work (parsing a tape, deserializing, etc) that is
+/// reported to `tracing` when the `tracing` feature is enabled. The span is
+/// exited when dropped, so subscribers see how long the work took, and the
+/// counts of what was processed are recorded as fields.
+///
+/// Without the feature, spans compile away to nothing.
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl Span {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn new(span: tracing::Span) -> Self {
+        Span {
+            span: span.entered(),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Span {}
+    }
+
+    /// Record a count that is known once the work is done. The field must
+    /// have been declared as empty when the span was created.
+    #[inline]
+    pub(crate) fn record(&self, field: &'static str, value: usize) {
+        #[cfg(feature = "tracing")]
+        self.span.record(field, value as u64);
+
+        #[cfg(not(feature = "tracing"))]
+        let _ = (field, value);
+    }
+}
+
+/// Enters a debug level span with the given name and fields. The fields
+/// after the semicolon are empty and are filled in with `Span::record`.
+macro_rules! span {
+    ($name:literal, $($field:ident = $value:expr),+ $(; $($empty:ident),+)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = $crate::trace::Span::new(tracing::debug_span!(
+            target: "jomini",
+            $name,
+            $($field = $value as u64),+
+            $($(, $empty = tracing::field::Empty)+)?
+        ));
+
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::Span::new();
+
+        span
+    }};
+}
+
+pub(crate) use span;
@@ -0,0 +1,689 @@
+use crate::common::Date;
+use crate::{BinaryTape, BinaryToken, Scalar, TextTape, TextToken, TokenResolver};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A declarative description of the values that a document may contain.
+///
+/// A definition is built up from constructors for each kind of value, with
+/// objects listing the keys that they require or allow. Validating a tape
+/// against the definition collects every [`Violation`] instead of stopping
+/// at the first one, so tools like mod CI pipelines can report all the
+/// problems in a file at once.
+///
+/// ```
+/// use jomini::{Definition, TextTape, ViolationKind};
+///
+/// let definition = Definition::object()
+///     .required("date", Definition::date())
+///     .required("religion", Definition::string().one_of(&["catholic", "protestant"]))
+///     .optional("stability", Definition::integer().range(-3.0, 3.0))
+///     .repeated("core", Definition::string())
+///     .deny_unknown_keys();
+///
+/// let tape = TextTape::from_slice(b"religion=sunni stability=5 core=ENG core=FRA gold=10")?;
+/// let violations = definition.validate_text(&tape);
+/// let messages: Vec<_> = violations.iter().map(|x| x.to_string()).collect();
+/// assert_eq!(
+///     messages,
+///     vec![
+///         "religion: value \"sunni\" is not one of the allowed values",
+///         "stability: value 5 is outside of the range -3 to 3",
+///         "gold: unknown key",
+///         "date: missing required key",
+///     ]
+/// );
+/// assert_eq!(violations[3].kind, ViolationKind::Missing);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    kind: DefinitionKind,
+    allowed: Option<Vec<Vec<u8>>>,
+    range: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DefinitionKind {
+    Any,
+    Bool,
+    Integer,
+    Float,
+    Date,
+    String,
+    Rgb,
+    Array(Box<Definition>),
+    Object(ObjectDefinition),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct ObjectDefinition {
+    fields: Vec<FieldDefinition>,
+    deny_unknown: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FieldDefinition {
+    key: String,
+    definition: Definition,
+    required: bool,
+    repeated: bool,
+}
+
+/// A value that did not conform to a [`Definition`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The location of the value: object keys and array indices joined by `/`
+    pub path: String,
+
+    /// How the value failed validation
+    pub kind: ViolationKind,
+}
+
+/// The ways in which a value can fail validation
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+    /// A required key was not present in its object
+    Missing,
+
+    /// A key not listed in an object that denies unknown keys
+    Unknown,
+
+    /// A key that is not repeatable occurred multiple times in its object
+    Duplicated,
+
+    /// The value was of a different type than the definition
+    Type {
+        /// The type of the definition
+        expected: &'static str,
+
+        /// The type of the encountered value
+        found: &'static str,
+    },
+
+    /// The value was not one of the definition's allowed values
+    NotAllowed(String),
+
+    /// The numeric value fell outside of the definition's range
+    OutOfRange {
+        /// The encountered value
+        value: f64,
+
+        /// The inclusive lower bound of the range
+        min: f64,
+
+        /// The inclusive upper bound of the range
+        max: f64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match self.kind {
+            ViolationKind::Missing => write!(f, "missing required key"),
+            ViolationKind::Unknown => write!(f, "unknown key"),
+            ViolationKind::Duplicated => write!(f, "key occurs more than once"),
+            ViolationKind::Type { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ViolationKind::NotAllowed(ref value) => {
+                write!(f, "value {:?} is not one of the allowed values", value)
+            }
+            ViolationKind::OutOfRange { value, min, max } => {
+                write!(
+                    f,
+                    "value {} is outside of the range {} to {}",
+                    value, min, max
+                )
+            }
+        }
+    }
+}
+
+impl Definition {
+    fn new(kind: DefinitionKind) -> Self {
+        Definition {
+            kind,
+            allowed: None,
+            range: None,
+        }
+    }
+
+    /// A value of any type
+    pub fn any() -> Self {
+        Definition::new(DefinitionKind::Any)
+    }
+
+    /// A boolean value like `yes` or `no`
+    pub fn bool() -> Self {
+        Definition::new(DefinitionKind::Bool)
+    }
+
+    /// A whole number
+    pub fn integer() -> Self {
+        Definition::new(DefinitionKind::Integer)
+    }
+
+    /// A number, which may be whole or rational
+    pub fn float() -> Self {
+        Definition::new(DefinitionKind::Float)
+    }
+
+    /// A date like `1444.11.11`
+    pub fn date() -> Self {
+        Definition::new(DefinitionKind::Date)
+    }
+
+    /// Any scalar value
+    pub fn string() -> Self {
+        Definition::new(DefinitionKind::String)
+    }
+
+    /// An rgb color
+    pub fn rgb() -> Self {
+        Definition::new(DefinitionKind::Rgb)
+    }
+
+    /// An array where every element satisfies the given definition
+    pub fn array(element: Definition) -> Self {
+        Definition::new(DefinitionKind::Array(Box::new(element)))
+    }
+
+    /// An object with no known keys. Keys are added with
+    /// [`required`](Definition::required), [`optional`](Definition::optional),
+    /// and [`repeated`](Definition::repeated).
+    pub fn object() -> Self {
+        Definition::new(DefinitionKind::Object(ObjectDefinition::default()))
+    }
+
+    /// Restrict scalar values to the given set
+    pub fn one_of(mut self, values: &[&str]) -> Self {
+        self.allowed = Some(values.iter().map(|x| x.as_bytes().to_vec()).collect());
+        self
+    }
+
+    /// Restrict numeric values to the inclusive range
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Add a key that must occur exactly once. Has no effect on definitions
+    /// that aren't objects.
+    pub fn required(self, key: &str, definition: Definition) -> Self {
+        self.field(key, definition, true, false)
+    }
+
+    /// Add a key that may occur at most once. Has no effect on definitions
+    /// that aren't objects.
+    pub fn optional(self, key: &str, definition: Definition) -> Self {
+        self.field(key, definition, false, false)
+    }
+
+    /// Add a key that may occur any number of times. Has no effect on
+    /// definitions that aren't objects.
+    pub fn repeated(self, key: &str, definition: Definition) -> Self {
+        self.field(key, definition, false, true)
+    }
+
+    /// Report keys in the object that weren't added to the definition. By
+    /// default unknown keys are allowed.
+    pub fn deny_unknown_keys(mut self) -> Self {
+        if let DefinitionKind::Object(object) = &mut self.kind {
+            object.deny_unknown = true;
+        }
+        self
+    }
+
+    fn field(mut self, key: &str, definition: Definition, required: bool, repeated: bool) -> Self {
+        if let DefinitionKind::Object(object) = &mut self.kind {
+            object.fields.push(FieldDefinition {
+                key: String::from(key),
+                definition,
+                required,
+                repeated,
+            });
+        }
+        self
+    }
+
+    /// Validate a text document, where the definition describes the
+    /// document's top level object
+    pub fn validate_text(&self, tape: &TextTape) -> Vec<Violation> {
+        let tokens = tape.tokens();
+        self.validate(&TextSource { tokens }, Node::Object(0, tokens.len()))
+    }
+
+    /// Validate a binary document, where the definition describes the
+    /// document's top level object. Token keys are resolved with the given
+    /// resolver and unresolved keys are written as their hex id.
+    pub fn validate_binary<RES>(&self, tape: &BinaryTape, resolver: &RES) -> Vec<Violation>
+    where
+        RES: TokenResolver,
+    {
+        let tokens = tape.tokens();
+        let source = BinarySource { tokens, resolver };
+        self.validate(&source, Node::Object(0, tokens.len()))
+    }
+
+    fn validate<'a, S: Source<'a>>(&self, source: &S, node: Node<'a>) -> Vec<Violation> {
+        let mut validator = Validator {
+            path: Vec::new(),
+            violations: Vec::new(),
+        };
+        validator.value(self, source, node);
+        validator.violations
+    }
+}
+
+/// A value of a document abstracted over the text and binary tapes
+enum Node<'a> {
+    Scalar(Cow<'a, [u8]>),
+    Bool,
+    Integer(i64),
+    Float(f64),
+    Rgb,
+    Header,
+    Object(usize, usize),
+    Array(usize, usize),
+}
+
+impl<'a> Node<'a> {
+    fn name(&self) -> &'static str {
+        match self {
+            Node::Scalar(_) => "scalar",
+            Node::Bool => "bool",
+            Node::Integer(_) => "integer",
+            Node::Float(_) => "float",
+            Node::Rgb => "rgb",
+            Node::Header => "header",
+            Node::Object(..) => "object",
+            Node::Array(..) => "array",
+        }
+    }
+}
+
+trait Source<'a> {
+    fn node(&self, idx: usize) -> Node<'a>;
+    fn entries(&self, start: usize, end: usize) -> Vec<(Cow<'a, [u8]>, usize)>;
+    fn elements(&self, start: usize, end: usize) -> Vec<usize>;
+}
+
+struct TextSource<'a, 'b> {
+    tokens: &'b [TextToken<'a>],
+}
+
+impl<'a, 'b> TextSource<'a, 'b> {
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            TextToken::Array(x) | TextToken::Object(x) | TextToken::HiddenObject(x) => x + 1,
+            TextToken::Header(_) => self.next_idx(idx + 1),
+            _ => idx + 1,
+        }
+    }
+}
+
+impl<'a, 'b> Source<'a> for TextSource<'a, 'b> {
+    fn node(&self, idx: usize) -> Node<'a> {
+        match self.tokens[idx] {
+            TextToken::Scalar(x) => Node::Scalar(Cow::Borrowed(x.view_data())),
+            TextToken::Header(x) if x.view_data() == b"rgb" => Node::Rgb,
+            TextToken::Header(_) => Node::Header,
+            TextToken::Array(end) => Node::Array(idx + 1, end),
+            TextToken::Object(end) | TextToken::HiddenObject(end) => Node::Object(idx + 1, end),
+            TextToken::Operator(_) | TextToken::End(_) => Node::Header,
+        }
+    }
+
+    fn entries(&self, mut idx: usize, end: usize) -> Vec<(Cow<'a, [u8]>, usize)> {
+        let mut result = Vec::new();
+        while idx < end {
+            let key = match self.tokens[idx] {
+                TextToken::Scalar(x) => x,
+                _ => {
+                    idx = self.next_idx(idx);
+                    continue;
+                }
+            };
+
+            let mut value_idx = idx + 1;
+            if let Some(TextToken::Operator(_)) = self.tokens.get(value_idx) {
+                value_idx += 1;
+            }
+
+            if value_idx >= end {
+                break;
+            }
+
+            result.push((Cow::Borrowed(key.view_data()), value_idx));
+            idx = self.next_idx(value_idx);
+        }
+        result
+    }
+
+    fn elements(&self, mut idx: usize, end: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+        while idx < end {
+            result.push(idx);
+            idx = self.next_idx(idx);
+        }
+        result
+    }
+}
+
+struct BinarySource<'a, 'b, 'res, RES> {
+    tokens: &'b [BinaryToken<'a>],
+    resolver: &'res RES,
+}
+
+impl<'a, 'b, 'res, RES> BinarySource<'a, 'b, 'res, RES> {
+    fn next_idx(&self, idx: usize) -> usize {
+        match self.tokens[idx] {
+            BinaryToken::Array(x) | BinaryToken::Object(x) | BinaryToken::HiddenObject(x) => x + 1,
+            _ => idx + 1,
+        }
+    }
+
+    fn token(&self, id: u16) -> Cow<'a, [u8]>
+    where
+        RES: TokenResolver,
+    {
+        match self.resolver.resolve(id) {
+            Some(x) => Cow::Owned(x.as_bytes().to_vec()),
+            None => Cow::Owned(format!("0x{:04x}", id).into_bytes()),
+        }
+    }
+}
+
+impl<'a, 'b, 'res, RES> Source<'a> for BinarySource<'a, 'b, 'res, RES>
+where
+    RES: TokenResolver,
+{
+    fn node(&self, idx: usize) -> Node<'a> {
+        match self.tokens[idx] {
+            BinaryToken::Bool(_) => Node::Bool,
+            BinaryToken::U32(x) => Node::Integer(i64::from(x)),
+            BinaryToken::I32(x) => Node::Integer(i64::from(x)),
+            BinaryToken::U64(x) => Node::Integer(x.min(i64::MAX as u64) as i64),
+            BinaryToken::F32_1(x) | BinaryToken::F32_2(x) => Node::Float(f64::from(x)),
+            BinaryToken::Text(x) | BinaryToken::Unquoted(x) => {
+                Node::Scalar(Cow::Borrowed(x.view_data()))
+            }
+            BinaryToken::Token(x) => Node::Scalar(self.token(x)),
+            BinaryToken::Rgb(_) => Node::Rgb,
+            BinaryToken::Array(end) => Node::Array(idx + 1, end),
+            BinaryToken::Object(end) | BinaryToken::HiddenObject(end) => Node::Object(idx + 1, end),
+            BinaryToken::End(_) => Node::Header,
+        }
+    }
+
+    fn entries(&self, mut idx: usize, end: usize) -> Vec<(Cow<'a, [u8]>, usize)> {
+        let mut result = Vec::new();
+        while idx + 1 < end {
+            let key = match self.tokens[idx] {
+                BinaryToken::Token(x) => self.token(x),
+                BinaryToken::Text(x) | BinaryToken::Unquoted(x) => Cow::Borrowed(x.view_data()),
+                _ => {
+                    idx = self.next_idx(idx);
+                    continue;
+                }
+            };
+
+            result.push((key, idx + 1));
+            idx = self.next_idx(idx + 1);
+        }
+        result
+    }
+
+    fn elements(&self, mut idx: usize, end: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+        while idx < end {
+            result.push(idx);
+            idx = self.next_idx(idx);
+        }
+        result
+    }
+}
+
+struct Validator {
+    path: Vec<String>,
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    fn report(&mut self, kind: ViolationKind) {
+        self.violations.push(Violation {
+            path: self.path.join("/"),
+            kind,
+        });
+    }
+
+    fn mismatch(&mut self, expected: &'static str, node: &Node) {
+        let found = node.name();
+        self.report(ViolationKind::Type { expected, found });
+    }
+
+    fn value<'a, S: Source<'a>>(&mut self, definition: &Definition, source: &S, node: Node<'a>) {
+        let matches = match (&definition.kind, &node) {
+            (DefinitionKind::Any, _) => true,
+            (DefinitionKind::Bool, Node::Bool) => true,
+            (DefinitionKind::Bool, Node::Scalar(x)) => Scalar::new(x).to_bool().is_ok(),
+            (DefinitionKind::Integer, Node::Integer(_)) => true,
+            (DefinitionKind::Integer, Node::Scalar(x)) => Scalar::new(x).to_i64().is_ok(),
+            (DefinitionKind::Float, Node::Integer(_)) | (DefinitionKind::Float, Node::Float(_)) => {
+                true
+            }
+            (DefinitionKind::Float, Node::Scalar(x)) => Scalar::new(x).to_f64().is_ok(),
+            (DefinitionKind::Date, Node::Scalar(x)) => std::str::from_utf8(x)
+                .ok()
+                .and_then(Date::parse_from_str)
+                .is_some(),
+            (DefinitionKind::Date, Node::Integer(x)) => i32::try_from(*x)
+                .ok()
+                .and_then(Date::from_binary_heuristic)
+                .is_some(),
+            (DefinitionKind::String, Node::Scalar(_)) => true,
+            (DefinitionKind::Rgb, Node::Rgb) => true,
+            (DefinitionKind::Array(element), Node::Array(start, end)) => {
+                for (i, idx) in source.elements(*start, *end).into_iter().enumerate() {
+                    self.path.push(i.to_string());
+                    self.value(element, source, source.node(idx));
+                    self.path.pop();
+                }
+                true
+            }
+            (DefinitionKind::Object(object), Node::Object(start, end)) => {
+                self.object(object, source, *start, *end);
+                true
+            }
+            (DefinitionKind::Object(object), Node::Array(start, end)) if start == end => {
+                self.object(object, source, *start, *end);
+                true
+            }
+            _ => false,
+        };
+
+        if !matches {
+            let expected = match definition.kind {
+                DefinitionKind::Any => "any",
+                DefinitionKind::Bool => "bool",
+                DefinitionKind::Integer => "integer",
+                DefinitionKind::Float => "float",
+                DefinitionKind::Date => "date",
+                DefinitionKind::String => "string",
+                DefinitionKind::Rgb => "rgb",
+                DefinitionKind::Array(_) => "array",
+                DefinitionKind::Object(_) => "object",
+            };
+            self.mismatch(expected, &node);
+            return;
+        }
+
+        if let (Some(allowed), Node::Scalar(x)) = (&definition.allowed, &node) {
+            if !allowed.iter().any(|a| a.as_slice() == x.as_ref()) {
+                let value = String::from_utf8_lossy(x).into_owned();
+                self.report(ViolationKind::NotAllowed(value));
+            }
+        }
+
+        if let Some((min, max)) = definition.range {
+            let value = match node {
+                Node::Integer(x) => Some(x as f64),
+                Node::Float(x) => Some(x),
+                Node::Scalar(ref x) => Scalar::new(x).to_f64().ok(),
+                _ => None,
+            };
+
+            if let Some(value) = value.filter(|x| *x < min || *x > max) {
+                self.report(ViolationKind::OutOfRange { value, min, max });
+            }
+        }
+    }
+
+    fn object<'a, S: Source<'a>>(
+        &mut self,
+        object: &ObjectDefinition,
+        source: &S,
+        start: usize,
+        end: usize,
+    ) {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (key, value_idx) in source.entries(start, end) {
+            let key = String::from_utf8_lossy(&key).into_owned();
+            self.path.push(key.clone());
+            match object.fields.iter().find(|x| x.key == key) {
+                Some(field) => {
+                    let count = counts.entry(field.key.as_str()).or_insert(0);
+                    *count += 1;
+                    if *count == 2 && !field.repeated {
+                        self.report(ViolationKind::Duplicated);
+                    }
+                    self.value(&field.definition, source, source.node(value_idx));
+                }
+                None if object.deny_unknown => self.report(ViolationKind::Unknown),
+                None => {}
+            }
+            self.path.pop();
+        }
+
+        for field in object.fields.iter().filter(|x| x.required) {
+            if !counts.contains_key(field.key.as_str()) {
+                self.path.push(field.key.clone());
+                self.report(ViolationKind::Missing);
+                self.path.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit() -> Definition {
+        Definition::object()
+            .required("strength", Definition::float().range(0.0, 1.0))
+            .optional("veteran", Definition::bool())
+    }
+
+    #[test]
+    fn test_validate_text() {
+        let definition = Definition::object()
+            .required("start", Definition::date())
+            .required("color", Definition::rgb())
+            .optional("flags", Definition::array(Definition::string()))
+            .optional("units", Definition::array(unit()))
+            .optional("history", Definition::object())
+            .optional("name", Definition::any());
+
+        let data = b"start=1444.11.11 color=rgb { 1 2 3 } flags={ a b } \
+            units={ { strength=0.5 veteran=yes } { strength=2 } { veteran=maybe } { a } } \
+            history={} name={ a=b }";
+        let tape = TextTape::from_slice(&data[..]).unwrap();
+        let messages: Vec<_> = definition
+            .validate_text(&tape)
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "units/1/strength: value 2 is outside of the range 0 to 1",
+                "units/2/veteran: expected bool, found scalar",
+                "units/2/strength: missing required key",
+                "units/3: expected object, found array",
+            ]
+        );
+
+        let tape = TextTape::from_slice(b"start=yes color=1 start=1444.1.1 flags=a").unwrap();
+        let messages: Vec<_> = definition
+            .validate_text(&tape)
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "start: expected date, found scalar",
+                "color: expected rgb, found scalar",
+                "start: key occurs more than once",
+                "flags: expected array, found scalar",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_binary() {
+        let data = [
+            0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01, 0x04,
+            0x00, 0x84, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x59, 0x00, 0x00, 0x00,
+        ];
+
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        map.insert(0x2d83, "b");
+
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let definition = Definition::object()
+            .required("a", Definition::object().required("b", Definition::bool()))
+            .required("c", Definition::integer())
+            .deny_unknown_keys();
+        assert_eq!(
+            definition.validate_binary(&tape, &map),
+            vec![
+                Violation {
+                    path: String::from("0x2d84"),
+                    kind: ViolationKind::Unknown,
+                },
+                Violation {
+                    path: String::from("c"),
+                    kind: ViolationKind::Missing,
+                },
+            ]
+        );
+
+        let definition = Definition::object()
+            .required(
+                "a",
+                Definition::object().required("b", Definition::integer()),
+            )
+            .optional("0x2d84", Definition::integer().range(0.0, 10.0));
+        let messages: Vec<_> = definition
+            .validate_binary(&tape, &map)
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "a/b: expected integer, found bool",
+                "0x2d84: value 89 is outside of the range 0 to 10",
+            ]
+        );
+    }
+}
@@ -75,9 +75,45 @@ fn deserialize_ck3_binary() {
     )
 }
 
+#[test]
+fn deserialize_ck3_binary_tape() {
+    let data = include_bytes!("./fixtures/ck3-header.bin");
+    let mut hash: HashMap<u16, &str> = HashMap::new();
+    hash.insert(0x3155, "meta_data");
+    hash.insert(0xee, "version");
+    hash.insert(0x58f, "save_game_version");
+    hash.insert(0x29e6, "meta_player_name");
+    hash.insert(0x29e7, "meta_title_name");
+    hash.insert(0x3391, "meta_main_portrait");
+    hash.insert(0xe1, "type");
+    hash.insert(0x6ef, "age");
+    hash.insert(0x626, "male");
+
+    let tape = jomini::BinaryTape::from_ck3(&data[..]).unwrap();
+    assert!(tape.tokens().contains(&jomini::BinaryToken::Token(0x3155)));
+
+    let header: Header = jomini::BinaryDeserializer::from_ck3_tape(&tape, &hash).unwrap();
+    assert_eq!(header.meta_data.meta_player_name, "Chieftain Botulf");
+    assert_eq!(header.meta_data.meta_title_name, "Chiefdom of Jåhkåmåhkke");
+}
+
 #[test]
 fn deserialize_ck3_plain_timeout_fix() {
     let data = include_bytes!("./fixtures/txt-timeout");
     let header: Result<Header, _> = jomini::TextDeserializer::from_utf8_slice(&data[..]);
     assert!(header.is_err());
 }
+
+#[cfg(feature = "ck3-schema")]
+#[test]
+fn validate_ck3_header_against_pack() {
+    use jomini::packs::CK3;
+
+    let data = include_bytes!("./fixtures/ck3-header.bin");
+    let tape = jomini::BinaryTape::from_ck3(&data[..]).unwrap();
+    let violations = CK3.meta_definition().validate_binary(&tape, &CK3);
+    assert!(violations.is_empty(), "{:?}", violations);
+
+    let header: Header = jomini::BinaryDeserializer::from_ck3(data, &CK3).unwrap();
+    assert_eq!(header.meta_data.meta_player_name, "Chieftain Botulf");
+}
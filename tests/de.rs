@@ -1,6 +1,9 @@
 #![cfg(feature = "derive")]
 
-use jomini::{BinaryDeserializer, TextDeserializer};
+use jomini::{
+    BinaryDeserializer, BinaryTape, BinaryToken, IntegerOverflow, JominiDeserialize, Rgb,
+    TextDeserializer, TextTape,
+};
 use serde::{
     de::{self, Visitor},
     Deserialize, Deserializer,
@@ -80,3 +83,446 @@ fn same_deserializer_for_header_token() {
         }
     }
 }
+
+#[test]
+fn same_rgb_for_text_and_binary() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct MyStruct {
+        color: Rgb,
+        channels: (u8, u8, u8),
+        map_color: [u32; 3],
+    }
+
+    let bin_data = [
+        0x3a, 0x05, 0x01, 0x00, 0x43, 0x02, 0x03, 0x00, 0x14, 0x00, 0x6e, 0x00, 0x00, 0x00, 0x14,
+        0x00, 0x1b, 0x00, 0x00, 0x00, 0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x04, 0x00, 0x3b, 0x05,
+        0x01, 0x00, 0x43, 0x02, 0x03, 0x00, 0x14, 0x00, 0x6e, 0x00, 0x00, 0x00, 0x14, 0x00, 0x1b,
+        0x00, 0x00, 0x00, 0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x04, 0x00, 0x3c, 0x05, 0x01, 0x00,
+        0x43, 0x02, 0x03, 0x00, 0x14, 0x00, 0x6e, 0x00, 0x00, 0x00, 0x14, 0x00, 0x1b, 0x00, 0x00,
+        0x00, 0x14, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x04, 0x00,
+    ];
+
+    let mut map = HashMap::new();
+    map.insert(0x053a, "color");
+    map.insert(0x053b, "channels");
+    map.insert(0x053c, "map_color");
+
+    let txt_data =
+        b"color = rgb { 110 27 27 } channels = rgb { 110 27 27 } map_color = rgb { 110 27 27 }";
+
+    let expected = Rgb {
+        r: 110,
+        g: 27,
+        b: 27,
+    };
+
+    let bin_tape = BinaryTape::from_eu4(&bin_data[..]).unwrap();
+    assert_eq!(bin_tape.tokens()[1], BinaryToken::Rgb(expected));
+
+    let txt_tape = TextTape::from_slice(&txt_data[..]).unwrap();
+    let mut reader = txt_tape.windows1252_reader();
+    let (_key, _op, value) = reader.next_field().unwrap();
+    assert_eq!(value.read_rgb().unwrap(), expected);
+
+    let bin_out: MyStruct = BinaryDeserializer::from_eu4(&bin_data[..], &map).unwrap();
+    let txt_out: MyStruct = TextDeserializer::from_windows1252_slice(&txt_data[..]).unwrap();
+    assert_eq!(bin_out, txt_out);
+    assert_eq!(
+        bin_out,
+        MyStruct {
+            color: expected,
+            channels: (110, 27, 27),
+            map_color: [110, 27, 27],
+        }
+    );
+}
+
+#[test]
+fn text_encoding_from_container_attribute() {
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    #[jomini(encoding = "utf8")]
+    struct Utf8Model {
+        name: String,
+    }
+
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    #[jomini(encoding = "windows1252")]
+    struct Windows1252Model {
+        name: String,
+    }
+
+    let data = "name=\"Jérôme\"".as_bytes();
+    let utf8: Utf8Model = TextDeserializer::from_slice(data).unwrap();
+    assert_eq!(utf8.name, "Jérôme");
+
+    let tape = TextTape::from_slice(data).unwrap();
+    let utf8: Utf8Model = TextDeserializer::from_tape(&tape).unwrap();
+    assert_eq!(utf8.name, "Jérôme");
+
+    let data = b"name=\"J\xe9r\xf4me\"";
+    let windows: Windows1252Model = TextDeserializer::from_slice(&data[..]).unwrap();
+    assert_eq!(windows.name, "Jérôme");
+}
+
+#[test]
+fn rest_field_collects_unmatched_keys() {
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    struct Country {
+        tag: String,
+        #[jomini(rest)]
+        rest: std::collections::BTreeMap<String, serde_json::Value>,
+    }
+
+    let data = b"gold=10.5 tag=ENG ideas={ a=1 b={ 1 2 } } core=ENG";
+    let country: Country = TextDeserializer::from_windows1252_slice(&data[..]).unwrap();
+    assert_eq!(country.tag, "ENG");
+    assert_eq!(
+        serde_json::to_value(&country.rest).unwrap(),
+        serde_json::json!({
+            "gold": "10.5",
+            "ideas": { "a": "1", "b": ["1", "2"] },
+            "core": "ENG",
+        })
+    );
+}
+
+#[test]
+fn positional_struct_for_text_and_binary() {
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    #[jomini(positional)]
+    struct UnitTemplate {
+        infantry: u8,
+        cavalry: u8,
+        artillery: u8,
+    }
+
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    struct Model {
+        unit_template: UnitTemplate,
+    }
+
+    let bin_data = [
+        0x82, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x05, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x03,
+        0x00, 0x00, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00,
+    ];
+
+    let mut map = HashMap::new();
+    map.insert(0x2d82, "unit_template");
+
+    let txt_data = b"unit_template = { 5 3 2 }";
+    let bin_out: Model = BinaryDeserializer::from_eu4(&bin_data[..], &map).unwrap();
+    let txt_out: Model = TextDeserializer::from_windows1252_slice(&txt_data[..]).unwrap();
+    assert_eq!(bin_out, txt_out);
+    assert_eq!(
+        txt_out.unit_template,
+        UnitTemplate {
+            infantry: 5,
+            cavalry: 3,
+            artillery: 2,
+        }
+    );
+}
+
+#[test]
+fn alias_path_lifts_nested_values() {
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    struct Save {
+        #[jomini(alias_path = "countries/ENG/treasury")]
+        england_treasury: f32,
+        #[jomini(alias_path = "countries/FRA/treasury")]
+        france_treasury: f32,
+    }
+
+    let data =
+        b"date=1444.11.11 countries={ ENG={ treasury=10.5 } FRA={ ruler={ id=1 } treasury=3 } }";
+    let save: Save = TextDeserializer::from_windows1252_slice(&data[..]).unwrap();
+    assert_eq!(
+        save,
+        Save {
+            england_treasury: 10.5,
+            france_treasury: 3.0,
+        }
+    );
+}
+
+#[test]
+fn empty_blocks_for_text_and_binary() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Inner {
+        #[serde(default)]
+        a: u8,
+    }
+
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    struct Model {
+        list: Vec<u8>,
+        empty_list: Vec<u8>,
+        #[jomini(default)]
+        absent_list: Vec<u8>,
+        opt_list: Option<Vec<u8>>,
+        empty_opt_list: Option<Vec<u8>>,
+        absent_opt_list: Option<Vec<u8>>,
+        empty_opt: Option<Inner>,
+    }
+
+    let bin_data = [
+        &[0x00, 0x30, 0x01, 0x00, 0x03, 0x00][..],
+        &[
+            0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00,
+        ],
+        &[0x04, 0x00],
+        &[0x01, 0x30, 0x01, 0x00, 0x03, 0x00, 0x04, 0x00],
+        &[0x02, 0x30, 0x01, 0x00, 0x03, 0x00],
+        &[0x0c, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00],
+        &[0x03, 0x30, 0x01, 0x00, 0x03, 0x00, 0x04, 0x00],
+        &[0x04, 0x30, 0x01, 0x00, 0x03, 0x00, 0x04, 0x00],
+    ]
+    .concat();
+
+    let mut map = HashMap::new();
+    map.insert(0x3000, "list");
+    map.insert(0x3001, "empty_list");
+    map.insert(0x3002, "opt_list");
+    map.insert(0x3003, "empty_opt_list");
+    map.insert(0x3004, "empty_opt");
+
+    let txt_data = b"list={ 1 2 } empty_list={} opt_list={ 3 } empty_opt_list={} empty_opt={}";
+
+    let mut expected = Model {
+        list: vec![1, 2],
+        empty_list: vec![],
+        absent_list: vec![],
+        opt_list: Some(vec![3]),
+        empty_opt_list: Some(vec![]),
+        absent_opt_list: None,
+        empty_opt: Some(Inner { a: 0 }),
+    };
+
+    for empty_block_as_none in [false, true] {
+        if empty_block_as_none {
+            expected.empty_opt_list = None;
+            expected.empty_opt = None;
+        }
+
+        let txt_out: Model = TextDeserializer::builder()
+            .empty_block_as_none(empty_block_as_none)
+            .from_windows1252_slice(&txt_data[..])
+            .unwrap();
+        assert_eq!(txt_out, expected);
+
+        let mut builder = BinaryDeserializer::eu4_builder();
+        builder.empty_block_as_none(empty_block_as_none);
+        let bin_out: Model = builder.from_slice(&bin_data[..], &map).unwrap();
+        assert_eq!(bin_out, expected);
+
+        let ondemand_out: Model = builder.from_slice_ondemand(&bin_data[..], &map).unwrap();
+        assert_eq!(ondemand_out, expected);
+    }
+}
+
+#[test]
+fn integer_overflow_for_text_and_binary() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Country {
+        morale: u8,
+        stability: i8,
+        list: Vec<u8>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Model {
+        country: Country,
+    }
+
+    let bin_data = [
+        &[0x00, 0x30, 0x01, 0x00, 0x03, 0x00][..],
+        &[0x01, 0x30, 0x01, 0x00, 0x14, 0x00, 0x2c, 0x01, 0x00, 0x00],
+        &[0x02, 0x30, 0x01, 0x00, 0x0c, 0x00, 0xd4, 0xfe, 0xff, 0xff],
+        &[0x03, 0x30, 0x01, 0x00, 0x03, 0x00],
+        &[0x0c, 0x00, 0x00, 0x01, 0x00, 0x00, 0x04, 0x00],
+        &[0x04, 0x00],
+    ]
+    .concat();
+
+    let mut map = HashMap::new();
+    map.insert(0x3000, "country");
+    map.insert(0x3001, "morale");
+    map.insert(0x3002, "stability");
+    map.insert(0x3003, "list");
+
+    let txt_data = b"country={ morale=300 stability=-300 list={ 256 } }";
+
+    let mut builder = TextDeserializer::builder();
+    let err = builder
+        .from_windows1252_slice::<Model>(&txt_data[..])
+        .unwrap_err();
+    let msg = "integer 300 does not fit in u8 at `country.morale`";
+    assert!(err.to_string().contains(msg), "{}", err);
+
+    let mut bin_builder = BinaryDeserializer::eu4_builder();
+    let err = bin_builder
+        .from_slice::<_, Model>(&bin_data[..], &map)
+        .unwrap_err();
+    assert!(err.to_string().contains(msg), "{}", err);
+
+    let err = bin_builder
+        .from_slice_ondemand::<_, Model>(&bin_data[..], &map)
+        .unwrap_err();
+    assert!(err.to_string().contains(msg), "{}", err);
+
+    let cases = [
+        (IntegerOverflow::Saturate, (255, -128, 255)),
+        (IntegerOverflow::Wrap, (44, -44, 0)),
+    ];
+
+    for (overflow, (morale, stability, element)) in cases {
+        let expected = Model {
+            country: Country {
+                morale,
+                stability,
+                list: vec![element],
+            },
+        };
+
+        let txt_out: Model = builder
+            .integer_overflow(overflow)
+            .from_windows1252_slice(&txt_data[..])
+            .unwrap();
+        assert_eq!(txt_out, expected);
+
+        bin_builder.integer_overflow(overflow);
+        let bin_out: Model = bin_builder.from_slice(&bin_data[..], &map).unwrap();
+        assert_eq!(bin_out, expected);
+
+        let ondemand_out: Model = bin_builder
+            .from_slice_ondemand(&bin_data[..], &map)
+            .unwrap();
+        assert_eq!(ondemand_out, expected);
+    }
+}
+
+#[test]
+fn tagged_enum_for_text_and_binary() {
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    #[jomini(tag = "type")]
+    enum Ai {
+        #[jomini(alias = "general")]
+        General { aggression: u8 },
+        #[jomini(alias = "defensive")]
+        Defensive,
+        #[jomini(alias = "navy")]
+        Navy {
+            ships: u16,
+            #[jomini(default)]
+            admiral: Option<String>,
+        },
+    }
+
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    struct Model {
+        ai: Ai,
+        units: Vec<Ai>,
+    }
+
+    let bin_data = [
+        &[0x00, 0x20, 0x01, 0x00, 0x03, 0x00][..],
+        &[0x01, 0x20, 0x01, 0x00, 0x0c, 0x00, 0x03, 0x00, 0x00, 0x00],
+        &[0x02, 0x20, 0x01, 0x00, 0x0f, 0x00, 0x07, 0x00],
+        b"general",
+        &[0x04, 0x00],
+        &[0x03, 0x20, 0x01, 0x00, 0x03, 0x00, 0x03, 0x00],
+        &[0x02, 0x20, 0x01, 0x00, 0x0f, 0x00, 0x09, 0x00],
+        b"defensive",
+        &[0x04, 0x00, 0x03, 0x00],
+        &[0x04, 0x20, 0x01, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00],
+        &[0x02, 0x20, 0x01, 0x00, 0x0f, 0x00, 0x04, 0x00],
+        b"navy",
+        &[0x04, 0x00, 0x04, 0x00],
+    ]
+    .concat();
+
+    let mut map = HashMap::new();
+    map.insert(0x2000, "ai");
+    map.insert(0x2001, "aggression");
+    map.insert(0x2002, "type");
+    map.insert(0x2003, "units");
+    map.insert(0x2004, "ships");
+
+    let txt_data =
+        b"ai={ aggression=3 type=general } units={ { type=defensive } { ships=2 type=navy } }";
+    let bin_out: Model = BinaryDeserializer::from_eu4(&bin_data[..], &map).unwrap();
+    let txt_out: Model = TextDeserializer::from_windows1252_slice(&txt_data[..]).unwrap();
+    assert_eq!(bin_out, txt_out);
+    assert_eq!(
+        txt_out,
+        Model {
+            ai: Ai::General { aggression: 3 },
+            units: vec![
+                Ai::Defensive,
+                Ai::Navy {
+                    ships: 2,
+                    admiral: None
+                }
+            ],
+        }
+    );
+
+    let err = TextDeserializer::from_windows1252_slice::<Model>(b"ai={ type=air } units={}")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("unknown variant `air`"), "{}", err);
+}
+
+#[test]
+fn generic_struct_borrows_from_text() {
+    #[derive(JominiDeserialize, Debug, PartialEq)]
+    struct Entry<'a, T> {
+        name: &'a str,
+        #[jomini(duplicated)]
+        value: Vec<T>,
+    }
+
+    let data = b"name=ENG value=1 other=2 value=3";
+    let entry: Entry<u8> = TextDeserializer::from_utf8_slice(&data[..]).unwrap();
+    assert_eq!(
+        entry,
+        Entry {
+            name: "ENG",
+            value: vec![1, 3],
+        }
+    );
+}
+
+#[allow(dead_code)]
+mod generated {
+    include!("fixtures/generated_save.rs");
+}
+
+#[test]
+fn generated_rust_structs_deserialize_sample() {
+    let samples: Vec<&[u8]> = vec![
+        b"date=1444.11.11 type=a core=ENG core=FRA Army=2 color=rgb { 1 2 3 } \
+          army={ name=x units={ { strength=1.5 veteran=yes } { strength=2 } } }",
+        b"date=1445.1.1 type=b core=ENG Army=3 color=rgb { 4 5 6 }",
+    ];
+
+    let mut schema = jomini::Schema::new();
+    for sample in &samples {
+        schema.add_text(&TextTape::from_slice(sample).unwrap());
+    }
+
+    let mut out = Vec::new();
+    schema.write_rust("Save", &mut out).unwrap();
+    let code = String::from_utf8(out).unwrap();
+    assert_eq!(code, include_str!("fixtures/generated_save.rs"));
+
+    let actual: generated::Save = TextDeserializer::from_windows1252_slice(samples[0]).unwrap();
+    assert_eq!(actual.core, vec![String::from("ENG"), String::from("FRA")]);
+    assert_eq!(actual.color, Rgb { r: 1, g: 2, b: 3 });
+    assert_eq!(actual.army_2.unwrap().units[1].veteran, None);
+
+    let actual: generated::Save = TextDeserializer::from_windows1252_slice(samples[1]).unwrap();
+    assert_eq!(actual.r#type, "b");
+    assert_eq!(actual.army, 3);
+    assert!(actual.army_2.is_none());
+}
@@ -0,0 +1,27 @@
+use jomini::JominiDeserialize;
+
+#[derive(Debug, JominiDeserialize)]
+pub struct Save {
+    pub date: jomini::common::Date,
+    #[jomini(alias = "type")]
+    pub r#type: String,
+    #[jomini(duplicated)]
+    pub core: Vec<String>,
+    #[jomini(alias = "Army")]
+    pub army: i32,
+    pub color: jomini::Rgb,
+    #[jomini(alias = "army")]
+    pub army_2: Option<Army>,
+}
+
+#[derive(Debug, JominiDeserialize)]
+pub struct Army {
+    pub name: String,
+    pub units: Vec<Units>,
+}
+
+#[derive(Debug, JominiDeserialize)]
+pub struct Units {
+    pub strength: f64,
+    pub veteran: Option<bool>,
+}
@@ -167,7 +167,7 @@ fn test_binary_meta_deserialization() {
     let data = include_bytes!("./fixtures/meta.bin");
     let data = &data["EU4bin".len()..];
     let hash = create_bin_lookup();
-    let actual: Meta = jomini::BinaryDeserializer::from_eu4(&data, &hash).unwrap();
+    let actual: Meta = jomini::BinaryDeserializer::from_eu4(data, &hash).unwrap();
     assert_eq!(actual.date.game_fmt(), String::from("1597.1.15"));
     assert_eq!(actual.savegame_version.0, String::from("1.29.4.0"));
 }
@@ -206,3 +206,53 @@ fn test_binary_timeout() {
     let hash = create_bin_lookup();
     assert!(jomini::BinaryDeserializer::from_eu4::<_, Meta>(&data[..], &hash).is_err());
 }
+
+#[test]
+fn test_binary_meta_ondemand_deserialization() {
+    let data = include_bytes!("./fixtures/meta.bin");
+    let data = &data["EU4bin".len()..];
+    let hash = create_bin_lookup();
+    let actual: Meta = jomini::BinaryDeserializer::eu4_builder()
+        .from_slice_ondemand(data, &hash)
+        .unwrap();
+    assert_eq!(actual.date.game_fmt(), String::from("1597.1.15"));
+    assert_eq!(actual.savegame_version.0, String::from("1.29.4.0"));
+}
+
+#[test]
+fn test_binary_ondemand_crashes() {
+    let fixtures: [&[u8]; 5] = [
+        include_bytes!("./fixtures/meta.bin.crash"),
+        include_bytes!("./fixtures/meta.bin.crash2"),
+        include_bytes!("./fixtures/meta.bin.crash3"),
+        include_bytes!("./fixtures/meta.bin.crash4"),
+        include_bytes!("./fixtures/bin-timeout"),
+    ];
+
+    let hash = create_bin_lookup();
+    for data in fixtures.iter() {
+        let res: Result<Meta, _> =
+            jomini::BinaryDeserializer::eu4_builder().from_slice_ondemand(data, &hash);
+        assert!(res.is_err());
+    }
+}
+
+#[cfg(feature = "eu4-schema")]
+#[test]
+fn test_meta_validates_against_pack() {
+    use jomini::packs::EU4;
+
+    let data = include_bytes!("./fixtures/meta.bin");
+    let tape = jomini::BinaryTape::from_eu4(&data["EU4bin".len()..]).unwrap();
+    let violations = EU4.meta_definition().validate_binary(&tape, &EU4);
+    assert!(violations.is_empty(), "{:?}", violations);
+
+    let data = include_bytes!("./fixtures/meta.txt");
+    let tape = jomini::TextTape::from_slice(&data["EU4txt".len()..]).unwrap();
+    let violations = EU4.meta_definition().validate_text(&tape);
+    assert!(violations.is_empty(), "{:?}", violations);
+
+    let data = include_bytes!("./fixtures/meta.bin");
+    let actual: Meta = jomini::BinaryDeserializer::from_eu4(&data["EU4bin".len()..], &EU4).unwrap();
+    assert_eq!(actual.date.game_fmt(), String::from("1597.1.15"));
+}
@@ -0,0 +1,98 @@
+#![cfg(all(feature = "metrics", feature = "derive"))]
+
+use jomini::{
+    BinaryDeserializer, BinaryTape, Eu4Flavor, FailedResolveStrategy, NormalizerBuilder,
+    ParseObserver,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug)]
+struct Counters {
+    bytes: AtomicUsize,
+    tokens: AtomicUsize,
+    unknown: AtomicUsize,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Counters {
+            bytes: AtomicUsize::new(0),
+            tokens: AtomicUsize::new(0),
+            unknown: AtomicUsize::new(0),
+        }
+    }
+
+    fn get(&self) -> (usize, usize, usize) {
+        (
+            self.bytes.load(Ordering::Relaxed),
+            self.tokens.load(Ordering::Relaxed),
+            self.unknown.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl ParseObserver for Counters {
+    fn bytes_parsed(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn tokens_produced(&self, tokens: usize) {
+        self.tokens.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    fn unknown_tokens(&self, count: usize) {
+        self.unknown.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+// a=1 0x2d83=2 b={ 0x2d84 }
+const DATA: [u8; 30] = [
+    0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x83, 0x2d, 0x01, 0x00, 0x0c, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x85, 0x2d, 0x01, 0x00, 0x03, 0x00, 0x84, 0x2d, 0x04, 0x00,
+];
+
+fn resolver() -> HashMap<u16, &'static str> {
+    let mut map = HashMap::new();
+    map.insert(0x2d82, "a");
+    map.insert(0x2d85, "b");
+    map
+}
+
+#[test]
+fn test_observe_deserialize() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Save {
+        a: i32,
+        b: Vec<String>,
+    }
+
+    static COUNTERS: Counters = Counters::new();
+    let save: Save = BinaryDeserializer::eu4_builder()
+        .on_failed_resolve(FailedResolveStrategy::Stringify)
+        .observer(&COUNTERS)
+        .from_slice(&DATA, &resolver())
+        .unwrap();
+    assert_eq!(save.b, vec![String::from("0x2d84")]);
+
+    let tokens = BinaryTape::from_eu4(&DATA).unwrap().tokens().len();
+    assert_eq!(COUNTERS.get(), (DATA.len(), tokens, 2));
+}
+
+#[test]
+fn test_observe_normalize() {
+    static COUNTERS: Counters = Counters::new();
+    let tape = BinaryTape::eu4_parser()
+        .observer(&COUNTERS)
+        .parse_slice(&DATA)
+        .unwrap();
+    assert_eq!(COUNTERS.get(), (DATA.len(), tape.tokens().len(), 0));
+
+    let normalized = NormalizerBuilder::with_flavor(Eu4Flavor::new())
+        .observer(&COUNTERS)
+        .normalize(&tape, &resolver())
+        .unwrap();
+    assert_eq!(normalized.as_bytes(), b"a=1\n0x2d83=2\nb={ 0x2d84 }\n");
+    assert_eq!(COUNTERS.get(), (DATA.len(), tape.tokens().len(), 2));
+}
@@ -1,4 +1,6 @@
-use jomini::{BinaryTape, Operator, Scalar, TextTape, TextToken};
+use jomini::{
+    BinaryTape, HiddenObjectStrategy, Operator, Scalar, TextTape, TextTapeParser, TextToken,
+};
 
 #[test]
 fn reject_bin_obj_in_hidden_obj() {
@@ -12,6 +14,19 @@ fn reject_txt_obj_in_hidden_obj() {
     assert!(TextTape::from_slice(&data[..]).is_err());
 }
 
+#[test]
+fn parse_txt_obj_in_hidden_obj() {
+    let data = include_bytes!("./fixtures/nested-hidden-obj.txt");
+    let tape = TextTapeParser::new()
+        .on_hidden_object(HiddenObjectStrategy::SynthesizeNested)
+        .parse_slice(&data[..])
+        .unwrap();
+    assert!(tape
+        .tokens()
+        .iter()
+        .any(|x| matches!(x, TextToken::HiddenObject(_))));
+}
+
 #[test]
 fn test_greater_than_operator() {
     let data = b"age > 16";
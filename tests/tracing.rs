@@ -0,0 +1,89 @@
+#![cfg(feature = "tracing")]
+
+use jomini::{BinaryTape, TextTape};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type Spans = Arc<Mutex<Vec<(&'static str, HashMap<&'static str, u64>)>>>;
+
+/// Records the name and the numeric fields of each span
+#[derive(Default)]
+struct Recorder {
+    spans: Spans,
+}
+
+struct Fields<'a>(&'a mut HashMap<&'static str, u64>);
+
+impl Visit for Fields<'_> {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name(), value);
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = HashMap::new();
+        span.record(&mut Fields(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata().name(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        let (_, fields) = &mut spans[span.into_u64() as usize - 1];
+        values.record(&mut Fields(fields));
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_parse_spans() {
+    let recorder = Recorder::default();
+    let spans = Arc::clone(&recorder.spans);
+    tracing::subscriber::with_default(recorder, || {
+        TextTape::from_slice(b"a=1 b={ c=d }").unwrap();
+
+        // a=1
+        let data = [0x82, 0x2d, 0x01, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00];
+        let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+        let mut map = HashMap::new();
+        map.insert(0x2d82, "a");
+        tape.normalize(&map, jomini::Eu4Flavor::new());
+    });
+
+    let spans = spans.lock().unwrap();
+    let found: Vec<_> = spans
+        .iter()
+        .map(|(name, fields)| {
+            let mut fields: Vec<_> = fields.iter().map(|(k, v)| (*k, *v)).collect();
+            fields.sort_unstable();
+            (*name, fields)
+        })
+        .collect();
+
+    assert_eq!(
+        found,
+        vec![
+            ("parse_text", vec![("bytes", 13), ("tokens", 7)]),
+            ("parse_binary", vec![("bytes", 10), ("tokens", 2)]),
+            ("melt", vec![("bytes", 4), ("tokens", 2)]),
+        ]
+    );
+}
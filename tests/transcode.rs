@@ -0,0 +1,46 @@
+#![cfg(feature = "derive")]
+
+use jomini::{BinaryDeserializer, BinaryTape, TextDeserializer, TextTape};
+use std::collections::HashMap;
+
+#[test]
+fn transcode_text_to_json() {
+    let data = b"player=\"ENG\" core=ENG core=FRA provinces={ 1 2 } country={ tag=ENG gold=10.5 }";
+    let tape = TextTape::from_slice(&data[..]).unwrap();
+    let builder = TextDeserializer::builder();
+    let mut deserializer = builder.deserializer(tape.windows1252_reader());
+
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::new(&mut out);
+    serde_transcode::transcode(&mut deserializer, &mut serializer).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&out).unwrap(),
+        r#"{"player":"ENG","core":"ENG","core":"FRA","provinces":["1","2"],"country":{"tag":"ENG","gold":"10.5"}}"#
+    );
+}
+
+#[test]
+fn transcode_binary_to_json() {
+    let data = [
+        0x82, 0x2d, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00, 0x45, 0x4e, 0x47, 0x83, 0x2d, 0x01, 0x00,
+        0x03, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04,
+        0x00, 0x84, 0x2d, 0x01, 0x00, 0x0e, 0x00, 0x01,
+    ];
+
+    let mut map = HashMap::new();
+    map.insert(0x2d82, "player");
+    map.insert(0x2d83, "provinces");
+    map.insert(0x2d84, "ironman");
+
+    let tape = BinaryTape::from_eu4(&data[..]).unwrap();
+    let builder = BinaryDeserializer::eu4_builder();
+    let mut deserializer = builder.deserializer(&tape, &map);
+
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::new(&mut out);
+    serde_transcode::transcode(&mut deserializer, &mut serializer).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&out).unwrap(),
+        r#"{"player":"ENG","provinces":[1,2],"ironman":true}"#
+    );
+}